@@ -2,7 +2,7 @@ use super::literal::Literal;
 
 use crate::compiler::{
     eval::{EvalErr, Evaluate},
-    token::TokenType,
+    token::{Token, TokenType},
 };
 
 impl Evaluate<Result<Literal, EvalErr>> for Expr {
@@ -36,12 +36,7 @@ impl std::ops::Add for Expr {
     type Output = Result<Literal, EvalErr>;
     fn add(self, rhs: Self) -> Self::Output {
         match self.eval() {
-            Ok(l) => {
-                l + match rhs.eval() {
-                    Ok(l2) => l2,
-                    Err(e) => return Err(e),
-                }
-            }
+            Ok(l) => l + rhs.eval()?,
             Err(e) => Err(e),
         }
     }
@@ -51,12 +46,7 @@ impl std::ops::Sub for Expr {
     type Output = Result<Literal, EvalErr>;
     fn sub(self, rhs: Self) -> Self::Output {
         match self.eval() {
-            Ok(l) => {
-                l - match rhs.eval() {
-                    Ok(l2) => l2,
-                    Err(e) => return Err(e),
-                }
-            }
+            Ok(l) => l - rhs.eval()?,
             Err(e) => Err(e),
         }
     }
@@ -66,12 +56,7 @@ impl std::ops::Mul for Expr {
     type Output = Result<Literal, EvalErr>;
     fn mul(self, rhs: Self) -> Self::Output {
         match self.eval() {
-            Ok(l) => {
-                l * match rhs.eval() {
-                    Ok(l2) => l2,
-                    Err(e) => return Err(e),
-                }
-            }
+            Ok(l) => l * rhs.eval()?,
             Err(e) => Err(e),
         }
     }
@@ -81,12 +66,7 @@ impl std::ops::Div for Expr {
     type Output = Result<Literal, EvalErr>;
     fn div(self, rhs: Self) -> Self::Output {
         match self.eval() {
-            Ok(l) => {
-                l / match rhs.eval() {
-                    Ok(l2) => l2,
-                    Err(e) => return Err(e),
-                }
-            }
+            Ok(l) => l / rhs.eval()?,
             Err(e) => Err(e),
         }
     }
@@ -96,11 +76,7 @@ impl std::ops::Not for Expr {
     type Output = Result<Literal, EvalErr>;
     fn not(self) -> Self::Output {
         match self.eval() {
-            Ok(l) => match l {
-                Literal::Bool(b) => Ok(Literal::Bool(!b)),
-                Literal::Nil => Ok(Literal::Bool(true)),
-                _ => Err(EvalErr::InvalidBang),
-            },
+            Ok(l) => !l,
             Err(e) => Err(e),
         }
     }
@@ -110,17 +86,14 @@ impl std::ops::Neg for Expr {
     type Output = Result<Literal, EvalErr>;
     fn neg(self) -> Self::Output {
         match self.eval() {
-            Ok(l) => match l {
-                Literal::Float(f) => Ok(Literal::Float(-f)),
-                Literal::Int(i) => Ok(Literal::Int(-i)),
-                _ => Err(EvalErr::InvalidNegate),
-            },
+            Ok(l) => -l,
             Err(e) => Err(e),
         }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum Expr {
     LiteralExpr(Literal),
     Unary {
@@ -132,10 +105,89 @@ pub enum Expr {
         op: BinaryOp,
         rhs: Box<Expr>,
     },
+    Logical {
+        lhs: Box<Expr>,
+        op: LogicalOp,
+        rhs: Box<Expr>,
+    },
     Grouping(Box<Expr>),
+    Variable(Identifier),
+    Assign {
+        name: Identifier,
+        value: Box<Expr>,
+    },
+    Call {
+        callee: Box<Expr>,
+        args: Vec<Expr>,
+        line: usize,
+    },
+    Get {
+        object: Box<Expr>,
+        name: Identifier,
+    },
+    Set {
+        object: Box<Expr>,
+        name: Identifier,
+        value: Box<Expr>,
+    },
+    This(Identifier),
+    /// `[a, b]`.
+    List(Vec<Expr>),
+    /// `{key: value}`, entries in source order.
+    Map(Vec<(Expr, Expr)>),
+    /// `object[index]`; `line` is that of the `]`.
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        line: usize,
+    },
+    SetIndex {
+        object: Box<Expr>,
+        index: Box<Expr>,
+        value: Box<Expr>,
+        line: usize,
+    },
 }
 
-#[derive(Debug)]
+impl Expr {
+    /// assignment is right associative and binds loosest.
+    pub const ASSIGN_PRECEDENCE: u8 = 1;
+    /// calls, indexing and property access bind tighter than any prefix or infix operator.
+    pub const CALL_PRECEDENCE: u8 = 9;
+}
+
+/// a name as written in source, e.g. a variable use, a declaration or a property.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Identifier {
+    pub name: String,
+    pub line: usize,
+    /// see `Token::column`.
+    pub column: usize,
+    /// where the variable lives at runtime, filled in by the resolver for declarations and uses.
+    /// Stays `None` for property names.
+    pub slot: Option<Slot>,
+}
+
+impl From<&Token> for Identifier {
+    fn from(token: &Token) -> Self {
+        Self {
+            name: token.lexeme.clone(),
+            line: token.line,
+            column: token.column,
+            slot: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Slot {
+    /// index into the program's global table.
+    Global(usize),
+    /// `depth` scopes out from the innermost one, at `index` in declaration order.
+    Local { depth: usize, index: usize },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum BinaryOp {
     Plus,
     Minus,
@@ -150,6 +202,34 @@ pub enum BinaryOp {
     BangEq,
 }
 
+impl BinaryOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::Plus => "+",
+            BinaryOp::Minus => "-",
+            BinaryOp::Mult => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Eq => "=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Lt => "<",
+            BinaryOp::GtEq => ">=",
+            BinaryOp::LtEq => "<=",
+            BinaryOp::EqEq => "==",
+            BinaryOp::BangEq => "!=",
+        }
+    }
+    /// binding strength matching the parser's grammar rules. Higher binds tighter.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Eq => Expr::ASSIGN_PRECEDENCE,
+            BinaryOp::EqEq | BinaryOp::BangEq => 4,
+            BinaryOp::Gt | BinaryOp::Lt | BinaryOp::GtEq | BinaryOp::LtEq => 5,
+            BinaryOp::Plus | BinaryOp::Minus => 6,
+            BinaryOp::Mult | BinaryOp::Div => 7,
+        }
+    }
+}
+
 impl TryFrom<&TokenType> for BinaryOp {
     type Error = ();
     fn try_from(value: &TokenType) -> Result<Self, Self::Error> {
@@ -170,12 +250,24 @@ impl TryFrom<&TokenType> for BinaryOp {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum UnaryOp {
     Bang,
     Negate,
 }
 
+impl UnaryOp {
+    /// binds tighter than every `BinaryOp`.
+    pub const PRECEDENCE: u8 = 8;
+
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            UnaryOp::Bang => "!",
+            UnaryOp::Negate => "-",
+        }
+    }
+}
+
 impl TryFrom<&TokenType> for UnaryOp {
     type Error = ();
     fn try_from(value: &TokenType) -> Result<Self, Self::Error> {
@@ -186,3 +278,25 @@ impl TryFrom<&TokenType> for UnaryOp {
         }
     }
 }
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogicalOp {
+    And,
+    Or,
+}
+
+impl LogicalOp {
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            LogicalOp::And => "and",
+            LogicalOp::Or => "or",
+        }
+    }
+    /// binds looser than every `BinaryOp`, with `and` tighter than `or`.
+    pub fn precedence(&self) -> u8 {
+        match self {
+            LogicalOp::Or => 2,
+            LogicalOp::And => 3,
+        }
+    }
+}
@@ -1,11 +1,35 @@
 use crate::compiler::eval::EvalErr;
 
-#[derive(Debug, Clone, Copy, PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Literal {
     Nil,
     Bool(bool),
     Int(i32),
     Float(f32),
+    Str(String),
+}
+
+impl std::fmt::Display for Literal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Literal::Nil => write!(f, "nil"),
+            Literal::Bool(b) => write!(f, "{}", b),
+            Literal::Int(i) => write!(f, "{}", i),
+            Literal::Float(fl) => write!(f, "{}", fl),
+            Literal::Str(st) => write!(f, "\"{}\"", st),
+        }
+    }
+}
+
+impl Literal {
+    /// the literal as it is written in source. Unlike `Display`, which prints values, a float
+    /// keeps its point when it has no fraction, `2.0` rather than `2`, so it reads back as a float.
+    pub fn to_source(&self) -> String {
+        match self {
+            Literal::Float(fl) if fl.is_finite() && fl.fract() == 0.0 => format!("{:.1}", fl),
+            _ => self.to_string(),
+        }
+    }
 }
 
 impl std::ops::Add for Literal {
@@ -13,7 +37,10 @@ impl std::ops::Add for Literal {
     fn add(self, rhs: Self) -> Self::Output {
         match self {
             Literal::Int(i1) => match rhs {
-                Literal::Int(i2) => Ok(Literal::Int(i1 + i2)),
+                Literal::Int(i2) => i1
+                    .checked_add(i2)
+                    .map(Literal::Int)
+                    .ok_or(EvalErr::Overflow),
                 Literal::Float(f2) => Ok(Literal::Float(i1 as f32 + f2)),
                 _ => Err(EvalErr::InvalidAdd),
             },
@@ -22,6 +49,10 @@ impl std::ops::Add for Literal {
                 Literal::Float(f2) => Ok(Literal::Float(f1 + f2)),
                 _ => Err(EvalErr::InvalidAdd),
             },
+            Literal::Str(s1) => match rhs {
+                Literal::Str(s2) => Ok(Literal::Str(s1 + &s2)),
+                _ => Err(EvalErr::InvalidAdd),
+            },
             _ => Err(EvalErr::InvalidAdd),
         }
     }
@@ -32,7 +63,10 @@ impl std::ops::Sub for Literal {
     fn sub(self, rhs: Self) -> Self::Output {
         match self {
             Literal::Int(i1) => match rhs {
-                Literal::Int(i2) => Ok(Literal::Int(i1 - i2)),
+                Literal::Int(i2) => i1
+                    .checked_sub(i2)
+                    .map(Literal::Int)
+                    .ok_or(EvalErr::Overflow),
                 Literal::Float(f2) => Ok(Literal::Float(i1 as f32 - f2)),
                 _ => Err(EvalErr::InvalidSub),
             },
@@ -51,7 +85,10 @@ impl std::ops::Mul for Literal {
     fn mul(self, rhs: Self) -> Self::Output {
         match self {
             Literal::Int(i1) => match rhs {
-                Literal::Int(i2) => Ok(Literal::Int(i1 * i2)),
+                Literal::Int(i2) => i1
+                    .checked_mul(i2)
+                    .map(Literal::Int)
+                    .ok_or(EvalErr::Overflow),
                 Literal::Float(f2) => Ok(Literal::Float(i1 as f32 * f2)),
                 _ => Err(EvalErr::InvalidMul),
             },
@@ -70,7 +107,12 @@ impl std::ops::Div for Literal {
     fn div(self, rhs: Self) -> Self::Output {
         match self {
             Literal::Int(i1) => match rhs {
-                Literal::Int(i2) => Ok(Literal::Int(i1 / i2)),
+                Literal::Int(0) => Err(EvalErr::DivisionByZero),
+                // `i32::MIN / -1` is the one quotient that does not fit
+                Literal::Int(i2) => i1
+                    .checked_div(i2)
+                    .map(Literal::Int)
+                    .ok_or(EvalErr::Overflow),
                 Literal::Float(f2) => Ok(Literal::Float(i1 as f32 / f2)),
                 _ => Err(EvalErr::InvalidDiv),
             },
@@ -84,6 +126,28 @@ impl std::ops::Div for Literal {
     }
 }
 
+impl std::ops::Not for Literal {
+    type Output = Result<Literal, EvalErr>;
+    fn not(self) -> Self::Output {
+        match self {
+            Literal::Bool(b) => Ok(Literal::Bool(!b)),
+            Literal::Nil => Ok(Literal::Bool(true)),
+            _ => Err(EvalErr::InvalidBang),
+        }
+    }
+}
+
+impl std::ops::Neg for Literal {
+    type Output = Result<Literal, EvalErr>;
+    fn neg(self) -> Self::Output {
+        match self {
+            Literal::Float(f) => Ok(Literal::Float(-f)),
+            Literal::Int(i) => i.checked_neg().map(Literal::Int).ok_or(EvalErr::Overflow),
+            _ => Err(EvalErr::InvalidNegate),
+        }
+    }
+}
+
 impl std::cmp::PartialOrd for Literal {
     fn ge(&self, other: &Self) -> bool {
         match self {
@@ -161,36 +225,19 @@ impl std::cmp::PartialOrd for Literal {
             _ => false,
         }
     }
+    /// `None` for values that do not compare, e.g. `nil` and a number.
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         if self < other {
             Some(std::cmp::Ordering::Less)
         } else if self > other {
-            Some(std::cmp::Ordering::Less)
-        } else if self == other {
-            Some(std::cmp::Ordering::Less)
+            Some(std::cmp::Ordering::Greater)
+        } else if self == other || self.ge(other) && self.le(other) {
+            // numbers of either kind are equal when neither is less
+            Some(std::cmp::Ordering::Equal)
         } else {
             None
         }
     }
 }
 
-impl std::cmp::Ord for Literal {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        if self < other {
-            std::cmp::Ordering::Less
-        } else if self > other {
-            std::cmp::Ordering::Greater
-        } else if self == other {
-            std::cmp::Ordering::Equal
-        } else {
-            panic!("Invalid comparison between {:?} and {:?}", self, other);
-        }
-    }
-    // fn cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-
-    // }
-}
-
-impl std::cmp::Eq for Literal {
-    fn assert_receiver_is_total_eq(&self) {}
-}
+impl std::cmp::Eq for Literal {}
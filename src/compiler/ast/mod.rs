@@ -1,2 +1,7 @@
 pub mod expr;
 pub mod literal;
+pub mod printer;
+#[cfg(test)]
+mod tests;
+pub mod types;
+pub mod visitor;
@@ -0,0 +1,459 @@
+use crate::compiler::{
+    ast::{
+        expr::{BinaryOp, Expr, Identifier, LogicalOp, UnaryOp},
+        literal::Literal,
+        types::{fmt_type_params, Type},
+        visitor::{walk_grouping, walk_stmt, Visitor},
+    },
+    statements::stmt::{Block, Catch, ClassDecl, FnDecl, InterfaceDecl, Param, Stmt},
+};
+
+/// Dumps an expression as an S-expression, e.g. `(* (group (- 45 75)) 6)`. Groupings are kept so
+/// the output mirrors the tree exactly, which is what debugging and golden tests want.
+#[derive(Default)]
+pub struct SExprPrinter {
+    out: String,
+}
+
+impl SExprPrinter {
+    pub fn print(expr: &Expr) -> String {
+        let mut printer = Self::default();
+        printer.visit_expr(expr);
+        printer.out
+    }
+    pub fn print_stmt(stmt: &Stmt) -> String {
+        let mut printer = Self::default();
+        printer.visit_stmt(stmt);
+        printer.out
+    }
+    fn open(&mut self, head: &str) {
+        self.out.push('(');
+        self.out.push_str(head);
+    }
+    fn arg(&mut self, expr: &Expr) {
+        self.out.push(' ');
+        self.visit_expr(expr);
+    }
+    fn params(&mut self, params: &[Param], return_type: Option<&Type>) {
+        self.out.push_str(" (");
+        let params: Vec<String> = params
+            .iter()
+            .map(|p| match &p.ty {
+                Some(ty) => format!("{}: {}", p.name.name, ty),
+                None => p.name.name.clone(),
+            })
+            .collect();
+        self.out.push_str(&params.join(" "));
+        self.out.push(')');
+        if let Some(ret) = return_type {
+            self.out.push_str(" -> ");
+            self.out.push_str(&ret.to_string());
+        }
+    }
+}
+
+impl Visitor for SExprPrinter {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        if stmt.exported {
+            self.open("export ");
+            walk_stmt(self, stmt);
+            self.out.push(')');
+        } else {
+            walk_stmt(self, stmt);
+        }
+    }
+    fn visit_print(&mut self, expr: &Expr) {
+        self.open("print");
+        self.arg(expr);
+        self.out.push(')');
+    }
+    fn visit_let(&mut self, name: &Identifier, ty: Option<&Type>, init: Option<&Expr>) {
+        self.open("let ");
+        self.out.push_str(&name.name);
+        if let Some(ty) = ty {
+            self.out.push_str(": ");
+            self.out.push_str(&ty.to_string());
+        }
+        if let Some(init) = init {
+            self.arg(init);
+        }
+        self.out.push(')');
+    }
+    fn visit_block(&mut self, block: &Block) {
+        self.open("block");
+        for stmt in &block.stmts {
+            self.out.push(' ');
+            self.visit_stmt(stmt);
+        }
+        self.out.push(')');
+    }
+    fn visit_if(&mut self, cond: &Expr, then_branch: &Block, else_branch: Option<&Stmt>) {
+        self.open("if");
+        self.arg(cond);
+        self.out.push(' ');
+        self.visit_block(then_branch);
+        if let Some(else_branch) = else_branch {
+            self.out.push(' ');
+            self.visit_stmt(else_branch);
+        }
+        self.out.push(')');
+    }
+    fn visit_while(&mut self, cond: &Expr, body: &Block) {
+        self.open("while");
+        self.arg(cond);
+        self.out.push(' ');
+        self.visit_block(body);
+        self.out.push(')');
+    }
+    fn visit_for(&mut self, name: &Identifier, iterable: &Expr, body: &Block) {
+        self.open("for ");
+        self.out.push_str(&name.name);
+        self.arg(iterable);
+        self.out.push(' ');
+        self.visit_block(body);
+        self.out.push(')');
+    }
+    fn visit_fn(&mut self, decl: &FnDecl) {
+        self.open("fn ");
+        self.out.push_str(&decl.name.name);
+        self.out.push_str(&fmt_type_params(&decl.type_params));
+        self.params(&decl.params, decl.return_type.as_ref());
+        self.out.push(' ');
+        self.visit_block(&decl.body);
+        self.out.push(')');
+    }
+    fn visit_return(&mut self, value: Option<&Expr>) {
+        self.open("return");
+        if let Some(value) = value {
+            self.arg(value);
+        }
+        self.out.push(')');
+    }
+    fn visit_throw(&mut self, value: &Expr) {
+        self.open("throw");
+        self.arg(value);
+        self.out.push(')');
+    }
+    fn visit_try(&mut self, body: &Block, catch: Option<&Catch>, finally: Option<&Block>) {
+        self.open("try ");
+        self.visit_block(body);
+        if let Some(catch) = catch {
+            self.out.push_str(" (catch ");
+            self.out.push_str(&catch.name.name);
+            self.out.push(' ');
+            self.visit_block(&catch.body);
+            self.out.push(')');
+        }
+        if let Some(finally) = finally {
+            self.out.push_str(" (finally ");
+            self.visit_block(finally);
+            self.out.push(')');
+        }
+        self.out.push(')');
+    }
+    fn visit_class(&mut self, decl: &ClassDecl) {
+        self.open("class ");
+        self.out.push_str(&decl.name.name);
+        self.out.push_str(&fmt_type_params(&decl.type_params));
+        for method in &decl.methods {
+            self.out.push(' ');
+            self.visit_fn(method);
+        }
+        self.out.push(')');
+    }
+    fn visit_interface(&mut self, decl: &InterfaceDecl) {
+        self.open("interface ");
+        self.out.push_str(&decl.name.name);
+        for method in &decl.methods {
+            self.out.push_str(" (fn ");
+            self.out.push_str(&method.name.name);
+            self.params(&method.params, method.return_type.as_ref());
+            self.out.push(')');
+        }
+        self.out.push(')');
+    }
+    fn visit_import(&mut self, path: &str, name: &Identifier) {
+        self.open("import \"");
+        self.out.push_str(path);
+        self.out.push_str("\" ");
+        self.out.push_str(&name.name);
+        self.out.push(')');
+    }
+
+    fn visit_literal(&mut self, literal: &Literal) {
+        self.out.push_str(&literal.to_source());
+    }
+    fn visit_unary(&mut self, op: &UnaryOp, rhs: &Expr) {
+        self.open(op.symbol());
+        self.arg(rhs);
+        self.out.push(')');
+    }
+    fn visit_binary(&mut self, lhs: &Expr, op: &BinaryOp, rhs: &Expr) {
+        self.open(op.symbol());
+        self.arg(lhs);
+        self.arg(rhs);
+        self.out.push(')');
+    }
+    fn visit_logical(&mut self, lhs: &Expr, op: &LogicalOp, rhs: &Expr) {
+        self.open(op.symbol());
+        self.arg(lhs);
+        self.arg(rhs);
+        self.out.push(')');
+    }
+    fn visit_grouping(&mut self, inner: &Expr) {
+        self.open("group");
+        self.arg(inner);
+        self.out.push(')');
+    }
+    fn visit_variable(&mut self, name: &Identifier) {
+        self.out.push_str(&name.name);
+    }
+    fn visit_assign(&mut self, name: &Identifier, value: &Expr) {
+        self.open("= ");
+        self.out.push_str(&name.name);
+        self.arg(value);
+        self.out.push(')');
+    }
+    fn visit_call(&mut self, callee: &Expr, args: &[Expr]) {
+        self.open("call");
+        self.arg(callee);
+        for arg in args {
+            self.arg(arg);
+        }
+        self.out.push(')');
+    }
+    fn visit_get(&mut self, object: &Expr, name: &Identifier) {
+        self.open(".");
+        self.arg(object);
+        self.out.push(' ');
+        self.out.push_str(&name.name);
+        self.out.push(')');
+    }
+    fn visit_set(&mut self, object: &Expr, name: &Identifier, value: &Expr) {
+        self.open("= (.");
+        self.arg(object);
+        self.out.push(' ');
+        self.out.push_str(&name.name);
+        self.out.push(')');
+        self.arg(value);
+        self.out.push(')');
+    }
+    fn visit_this(&mut self, _keyword: &Identifier) {
+        self.out.push_str("this");
+    }
+    fn visit_list(&mut self, elements: &[Expr]) {
+        self.open("list");
+        for element in elements {
+            self.arg(element);
+        }
+        self.out.push(')');
+    }
+    fn visit_map(&mut self, entries: &[(Expr, Expr)]) {
+        self.open("map");
+        for (key, value) in entries {
+            self.out.push_str(" (");
+            self.visit_expr(key);
+            self.arg(value);
+            self.out.push(')');
+        }
+        self.out.push(')');
+    }
+    fn visit_index(&mut self, object: &Expr, index: &Expr) {
+        self.open("[]");
+        self.arg(object);
+        self.arg(index);
+        self.out.push(')');
+    }
+    fn visit_set_index(&mut self, object: &Expr, index: &Expr, value: &Expr) {
+        self.open("= ([]");
+        self.arg(object);
+        self.arg(index);
+        self.out.push(')');
+        self.arg(value);
+        self.out.push(')');
+    }
+}
+
+/// Prints an expression back to re-parseable source. `Grouping` nodes are dropped and parentheses
+/// are only emitted where operator precedence or left associativity require them.
+#[derive(Default)]
+pub struct SourcePrinter {
+    out: String,
+    /// lowest precedence the node currently being printed may have without being parenthesised.
+    min_precedence: u8,
+    /// when set, argument lists that would run past this column are broken one argument per line.
+    width: Option<usize>,
+    /// column the first line of output starts at.
+    start_column: usize,
+    /// indentation of the line the expression starts on, used for wrapped arguments.
+    indent: String,
+}
+
+impl SourcePrinter {
+    pub const INDENT: &'static str = "    ";
+
+    pub fn print(expr: &Expr) -> String {
+        let mut printer = Self::default();
+        printer.visit_expr(expr);
+        printer.out
+    }
+    /// like `print`, but wraps argument lists that would not fit within `width` columns.
+    pub fn print_wrapped(expr: &Expr, width: usize, start_column: usize, indent: &str) -> String {
+        let mut printer = Self {
+            width: Some(width),
+            start_column,
+            indent: indent.to_owned(),
+            ..Self::default()
+        };
+        printer.visit_expr(expr);
+        printer.out
+    }
+    fn visit_with_precedence(&mut self, expr: &Expr, min_precedence: u8) {
+        let outer = std::mem::replace(&mut self.min_precedence, min_precedence);
+        self.visit_expr(expr);
+        self.min_precedence = outer;
+    }
+    fn column(&self) -> usize {
+        match self.out.rfind('\n') {
+            Some(idx) => self.out.len() - idx - 1,
+            None => self.start_column + self.out.len(),
+        }
+    }
+    fn infix(&mut self, lhs: &Expr, symbol: &str, precedence: u8, rhs: &Expr) {
+        let parens = precedence < self.min_precedence;
+        if parens {
+            self.out.push('(');
+        }
+        self.visit_with_precedence(lhs, precedence);
+        self.out.push(' ');
+        self.out.push_str(symbol);
+        self.out.push(' ');
+        // every infix operator is left associative, so an equal-precedence rhs needs parentheses.
+        self.visit_with_precedence(rhs, precedence + 1);
+        if parens {
+            self.out.push(')');
+        }
+    }
+    fn assignment(&mut self, target: impl FnOnce(&mut Self), value: &Expr) {
+        let parens = Expr::ASSIGN_PRECEDENCE < self.min_precedence;
+        if parens {
+            self.out.push('(');
+        }
+        target(self);
+        self.out.push_str(" = ");
+        self.visit_with_precedence(value, Expr::ASSIGN_PRECEDENCE);
+        if parens {
+            self.out.push(')');
+        }
+    }
+}
+
+impl Visitor for SourcePrinter {
+    fn visit_literal(&mut self, literal: &Literal) {
+        self.out.push_str(&literal.to_source());
+    }
+    fn visit_unary(&mut self, op: &UnaryOp, rhs: &Expr) {
+        let parens = UnaryOp::PRECEDENCE < self.min_precedence;
+        if parens {
+            self.out.push('(');
+        }
+        self.out.push_str(op.symbol());
+        self.visit_with_precedence(rhs, UnaryOp::PRECEDENCE);
+        if parens {
+            self.out.push(')');
+        }
+    }
+    fn visit_binary(&mut self, lhs: &Expr, op: &BinaryOp, rhs: &Expr) {
+        self.infix(lhs, op.symbol(), op.precedence(), rhs);
+    }
+    fn visit_logical(&mut self, lhs: &Expr, op: &LogicalOp, rhs: &Expr) {
+        self.infix(lhs, op.symbol(), op.precedence(), rhs);
+    }
+    fn visit_grouping(&mut self, inner: &Expr) {
+        walk_grouping(self, inner);
+    }
+    fn visit_variable(&mut self, name: &Identifier) {
+        self.out.push_str(&name.name);
+    }
+    fn visit_assign(&mut self, name: &Identifier, value: &Expr) {
+        self.assignment(|p| p.out.push_str(&name.name), value);
+    }
+    fn visit_call(&mut self, callee: &Expr, args: &[Expr]) {
+        self.visit_with_precedence(callee, Expr::CALL_PRECEDENCE);
+
+        let flat: Vec<String> = args.iter().map(Self::print).collect();
+        let flat_len = flat.iter().map(|a| a.len() + 2).sum::<usize>();
+        let wrap = match self.width {
+            Some(width) => !args.is_empty() && self.column() + flat_len > width,
+            None => false,
+        };
+
+        if !wrap {
+            self.out.push('(');
+            self.out.push_str(&flat.join(", "));
+            self.out.push(')');
+            return;
+        }
+
+        let outer_indent = self.indent.clone();
+        self.indent.push_str(Self::INDENT);
+        self.out.push_str("(\n");
+        for arg in args {
+            self.out.push_str(&self.indent.clone());
+            self.visit_with_precedence(arg, 0);
+            self.out.push_str(",\n");
+        }
+        self.indent = outer_indent;
+        self.out.push_str(&self.indent);
+        self.out.push(')');
+    }
+    fn visit_get(&mut self, object: &Expr, name: &Identifier) {
+        self.visit_with_precedence(object, Expr::CALL_PRECEDENCE);
+        self.out.push('.');
+        self.out.push_str(&name.name);
+    }
+    fn visit_set(&mut self, object: &Expr, name: &Identifier, value: &Expr) {
+        self.assignment(
+            |p| {
+                p.visit_with_precedence(object, Expr::CALL_PRECEDENCE);
+                p.out.push('.');
+                p.out.push_str(&name.name);
+            },
+            value,
+        );
+    }
+    fn visit_this(&mut self, _keyword: &Identifier) {
+        self.out.push_str("this");
+    }
+    fn visit_list(&mut self, elements: &[Expr]) {
+        self.out.push('[');
+        for (i, element) in elements.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.visit_with_precedence(element, 0);
+        }
+        self.out.push(']');
+    }
+    fn visit_map(&mut self, entries: &[(Expr, Expr)]) {
+        self.out.push('{');
+        for (i, (key, value)) in entries.iter().enumerate() {
+            if i > 0 {
+                self.out.push_str(", ");
+            }
+            self.visit_with_precedence(key, 0);
+            self.out.push_str(": ");
+            self.visit_with_precedence(value, 0);
+        }
+        self.out.push('}');
+    }
+    fn visit_index(&mut self, object: &Expr, index: &Expr) {
+        self.visit_with_precedence(object, Expr::CALL_PRECEDENCE);
+        self.out.push('[');
+        self.visit_with_precedence(index, 0);
+        self.out.push(']');
+    }
+    fn visit_set_index(&mut self, object: &Expr, index: &Expr, value: &Expr) {
+        self.assignment(|p| p.visit_index(object, index), value);
+    }
+}
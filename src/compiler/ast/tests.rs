@@ -0,0 +1,218 @@
+use super::{
+    expr::{BinaryOp, Expr, UnaryOp},
+    literal::Literal,
+    printer::{SExprPrinter, SourcePrinter},
+    visitor::{walk_expr_mut, Visitor, VisitorMut},
+};
+use crate::compiler::{lexer::Lexer, parser::Parser, token::Token};
+
+fn parse(source: &str) -> Vec<Expr> {
+    let tokens: Vec<Token> = Lexer::from_source(source).collect();
+    Parser::new(&tokens).collect()
+}
+
+/// (-(45 - 75)) * 6
+fn sample_expr() -> Expr {
+    Expr::Binary {
+        lhs: Box::new(Expr::Grouping(Box::new(Expr::Unary {
+            op: UnaryOp::Negate,
+            rhs: Box::new(Expr::Binary {
+                lhs: Box::new(Expr::LiteralExpr(Literal::Int(45))),
+                op: BinaryOp::Minus,
+                rhs: Box::new(Expr::LiteralExpr(Literal::Int(75))),
+            }),
+        }))),
+        op: BinaryOp::Mult,
+        rhs: Box::new(Expr::LiteralExpr(Literal::Int(6))),
+    }
+}
+
+#[derive(Default)]
+struct LiteralCollector {
+    literals: Vec<Literal>,
+    groupings: usize,
+}
+
+impl Visitor for LiteralCollector {
+    fn visit_literal(&mut self, literal: &Literal) {
+        self.literals.push(literal.clone());
+    }
+    fn visit_grouping(&mut self, inner: &Expr) {
+        self.groupings += 1;
+        self.visit_expr(inner);
+    }
+}
+
+#[test]
+fn test_visitor_walks_every_node() {
+    let mut collector = LiteralCollector::default();
+    collector.visit_expr(&sample_expr());
+    assert_eq!(
+        collector.literals,
+        vec![Literal::Int(45), Literal::Int(75), Literal::Int(6)]
+    );
+    assert_eq!(collector.groupings, 1);
+}
+
+struct Ungroup;
+
+impl VisitorMut for Ungroup {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+        if let Expr::Grouping(inner) = expr {
+            let inner = std::mem::replace(inner.as_mut(), Expr::LiteralExpr(Literal::Nil));
+            *expr = inner;
+        }
+    }
+    fn visit_literal_mut(&mut self, literal: &mut Literal) {
+        if let Literal::Int(i) = literal {
+            *i *= 2;
+        }
+    }
+}
+
+#[test]
+fn test_visitor_mut_rewrites_in_place() {
+    let mut expr = sample_expr();
+    Ungroup.visit_expr_mut(&mut expr);
+
+    let mut collector = LiteralCollector::default();
+    collector.visit_expr(&expr);
+    assert_eq!(collector.groupings, 0);
+    assert_eq!(
+        collector.literals,
+        vec![Literal::Int(90), Literal::Int(150), Literal::Int(12)]
+    );
+}
+
+#[test]
+fn test_sexpr_printer() {
+    let exprs = parse("(45 - 75) * 6 == false");
+    assert_eq!(
+        SExprPrinter::print(&exprs[0]),
+        "(== (* (group (- 45 75)) 6) false)"
+    );
+    assert_eq!(
+        SExprPrinter::print(&sample_expr()),
+        "(* (group (- (- 45 75))) 6)"
+    );
+}
+
+#[test]
+fn test_source_printer_minimal_parens() {
+    let cases = [
+        ("(45 - 75) * 6 == false", "(45 - 75) * 6 == false"),
+        ("((1 + 2)) + 3", "1 + 2 + 3"),
+        ("1 + (2 + 3)", "1 + (2 + 3)"),
+        ("1 - (2 * 3)", "1 - 2 * 3"),
+        ("!(1 == 2)", "!(1 == 2)"),
+        ("-(3)", "-3"),
+        ("(1 < 2) == (3 >= 4)", "1 < 2 == 3 >= 4"),
+        ("[(1 + 2), -x][(0)]", "[1 + 2, -x][0]"),
+        ("(-x)[0]", "(-x)[0]"),
+        ("f(xs)[0] = ({\"a\": [1,],})", "f(xs)[0] = {\"a\": [1]}"),
+    ];
+    for (source, expected) in cases {
+        let exprs = parse(source);
+        assert_eq!(exprs.len(), 1, "{}", source);
+        assert_eq!(SourcePrinter::print(&exprs[0]), expected);
+    }
+}
+
+/// xorshift, so the property test is reproducible without pulling in a crate.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % bound
+    }
+}
+
+fn random_expr(rng: &mut Rng, depth: u32) -> Expr {
+    let leaf = depth == 0 || rng.next(4) == 0;
+    if leaf {
+        return Expr::LiteralExpr(match rng.next(6) {
+            0 => Literal::Nil,
+            1 => Literal::Bool(rng.next(2) == 0),
+            2 => Literal::Float(rng.next(1000) as f32 / 4.0),
+            3 => Literal::Float(rng.next(100) as f32),
+            _ => Literal::Int(rng.next(1000) as i32),
+        });
+    }
+    match rng.next(6) {
+        0 => Expr::Grouping(Box::new(random_expr(rng, depth - 1))),
+        1 => Expr::Unary {
+            op: if rng.next(2) == 0 {
+                UnaryOp::Bang
+            } else {
+                UnaryOp::Negate
+            },
+            rhs: Box::new(random_expr(rng, depth - 1)),
+        },
+        _ => {
+            let ops = [
+                BinaryOp::Plus,
+                BinaryOp::Minus,
+                BinaryOp::Mult,
+                BinaryOp::Div,
+                BinaryOp::Gt,
+                BinaryOp::Lt,
+                BinaryOp::GtEq,
+                BinaryOp::LtEq,
+                BinaryOp::EqEq,
+                BinaryOp::BangEq,
+            ];
+            let idx = rng.next(ops.len() as u64) as usize;
+            Expr::Binary {
+                lhs: Box::new(random_expr(rng, depth - 1)),
+                op: ops.into_iter().nth(idx).unwrap(),
+                rhs: Box::new(random_expr(rng, depth - 1)),
+            }
+        }
+    }
+}
+
+/// Drops the groupings, which the printer adds and removes as precedence needs.
+struct Flatten;
+
+impl VisitorMut for Flatten {
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr);
+        if let Expr::Grouping(inner) = expr {
+            let inner = std::mem::replace(inner.as_mut(), Expr::LiteralExpr(Literal::Nil));
+            *expr = inner;
+        }
+    }
+}
+
+#[test]
+fn test_source_printer_roundtrip_is_fixpoint() {
+    let mut rng = Rng(0x9e3779b97f4a7c15);
+    for _ in 0..200 {
+        let mut expr = random_expr(&mut rng, 6);
+        let printed = SourcePrinter::print(&expr);
+        let mut reparsed = parse(&printed);
+        assert_eq!(reparsed.len(), 1, "{}", printed);
+        assert_eq!(SourcePrinter::print(&reparsed[0]), printed);
+        // the same tree, literals of the same kind included
+        Flatten.visit_expr_mut(&mut expr);
+        Flatten.visit_expr_mut(&mut reparsed[0]);
+        assert_eq!(reparsed[0], expr, "{}", printed);
+    }
+}
+
+#[test]
+fn test_literal_ordering() {
+    use std::cmp::Ordering::*;
+    let cmp = |a: Literal, b: Literal| a.partial_cmp(&b);
+    assert_eq!(cmp(Literal::Int(1), Literal::Int(2)), Some(Less));
+    assert_eq!(cmp(Literal::Float(2.5), Literal::Int(2)), Some(Greater));
+    assert_eq!(cmp(Literal::Int(2), Literal::Float(2.0)), Some(Equal));
+    assert_eq!(cmp(Literal::Nil, Literal::Nil), Some(Equal));
+    // values that do not compare have no order, rather than panicking
+    assert_eq!(cmp(Literal::Nil, Literal::Int(1)), None);
+    assert_eq!(cmp(Literal::Bool(true), Literal::Int(1)), None);
+}
@@ -0,0 +1,266 @@
+/// A static type, as written in an annotation or inferred by the checker. `Any` opts out of checking:
+/// it is compatible with every other type.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Any,
+    Nil,
+    Bool,
+    Int,
+    Float,
+    Str,
+    Fn {
+        params: Vec<Type>,
+        ret: Box<Type>,
+    },
+    /// `list<T>`, written like a generic class but built in.
+    List(Box<Type>),
+    /// `map<K, V>`.
+    Map(Box<Type>, Box<Type>),
+    /// an instance of the named class, with its type arguments.
+    Instance(String, Vec<Type>),
+    /// a type parameter of the enclosing generic declaration. The parser writes these as
+    /// `Instance` with no arguments, the checker tells them apart.
+    Param(String),
+    /// an inference variable. Only the checker creates these, never the parser.
+    Var(usize),
+}
+
+impl Type {
+    /// names of the built-in types, as written in annotations.
+    pub fn from_name(name: &str) -> Option<Type> {
+        match name {
+            "any" => Some(Type::Any),
+            "nil" => Some(Type::Nil),
+            "bool" => Some(Type::Bool),
+            "int" => Some(Type::Int),
+            "float" => Some(Type::Float),
+            "str" => Some(Type::Str),
+            _ => None,
+        }
+    }
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int | Type::Float)
+    }
+    /// writes the type, naming inference variables with `var_name`.
+    fn fmt_with(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        var_name: &dyn Fn(usize) -> String,
+    ) -> std::fmt::Result {
+        match self {
+            Type::Any => write!(f, "any"),
+            Type::Nil => write!(f, "nil"),
+            Type::Bool => write!(f, "bool"),
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::Str => write!(f, "str"),
+            Type::Fn { params, ret } => {
+                write!(f, "fn(")?;
+                for (i, param) in params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    param.fmt_with(f, var_name)?;
+                }
+                write!(f, ") -> ")?;
+                ret.fmt_with(f, var_name)
+            }
+            Type::List(elem) => {
+                write!(f, "list<")?;
+                elem.fmt_with(f, var_name)?;
+                write!(f, ">")
+            }
+            Type::Map(key, value) => {
+                write!(f, "map<")?;
+                key.fmt_with(f, var_name)?;
+                write!(f, ", ")?;
+                value.fmt_with(f, var_name)?;
+                write!(f, ">")
+            }
+            Type::Instance(name, args) => {
+                write!(f, "{}", name)?;
+                if !args.is_empty() {
+                    write!(f, "<")?;
+                    for (i, arg) in args.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        arg.fmt_with(f, var_name)?;
+                    }
+                    write!(f, ">")?;
+                }
+                Ok(())
+            }
+            Type::Param(name) => write!(f, "{}", name),
+            Type::Var(id) => write!(f, "{}", var_name(*id)),
+        }
+    }
+    /// inference variables in order of first appearance.
+    pub fn vars(&self, out: &mut Vec<usize>) {
+        match self {
+            Type::Var(id) if !out.contains(id) => out.push(*id),
+            Type::Fn { params, ret } => {
+                for param in params {
+                    param.vars(out);
+                }
+                ret.vars(out);
+            }
+            Type::List(elem) => elem.vars(out),
+            Type::Map(key, value) => {
+                key.vars(out);
+                value.vars(out);
+            }
+            Type::Instance(_, args) => {
+                for arg in args {
+                    arg.vars(out);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl std::fmt::Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.fmt_with(f, &|id| format!("'t{}", id))
+    }
+}
+
+/// What an operator demands of an operand whose type is still being inferred, much like a built-in
+/// interface that only some base types implement.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Constraint {
+    /// `int` or `float`: `-`, `*`, `/`, comparisons and negation.
+    Num,
+    /// `int`, `float` or `str`: `+`.
+    Add,
+}
+
+impl Constraint {
+    pub fn admits(self, ty: &Type) -> bool {
+        match self {
+            Constraint::Num => ty.is_numeric(),
+            Constraint::Add => ty.is_numeric() || *ty == Type::Str,
+        }
+    }
+    /// the constraint on a variable that has to satisfy both.
+    pub fn meet(self, other: Constraint) -> Constraint {
+        if self == Constraint::Num || other == Constraint::Num {
+            Constraint::Num
+        } else {
+            Constraint::Add
+        }
+    }
+}
+
+impl std::fmt::Display for Constraint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Constraint::Num => write!(f, "number"),
+            Constraint::Add => write!(f, "number or str"),
+        }
+    }
+}
+
+/// A type parameter of a generic `fn` or `class`, e.g. `T: Comparable`. Every bound names an
+/// interface the type argument has to implement.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeParam {
+    pub name: String,
+    pub bounds: Vec<String>,
+}
+
+impl std::fmt::Display for TypeParam {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+        if !self.bounds.is_empty() {
+            write!(f, ": {}", self.bounds.join(" + "))?;
+        }
+        Ok(())
+    }
+}
+
+/// writes `<T, U: Bound>`, or nothing for a declaration without type parameters.
+pub fn fmt_type_params(params: &[TypeParam]) -> String {
+    if params.is_empty() {
+        return String::new();
+    }
+    let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+    format!("<{}>", params.join(", "))
+}
+
+/// An inferred variable a `Scheme` quantifies over, with what it must still satisfy.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quantified {
+    pub id: usize,
+    pub constraint: Option<Constraint>,
+    /// interfaces it has to implement, picked up from the bounds of the functions it was passed to.
+    pub bounds: Vec<String>,
+    /// the two variables, by id, that this one is the promotion of, as the result of an operator on
+    /// them is: `int` if both are ints, `float` if either is a float, `str` if both are strings.
+    pub promotes: Option<(usize, usize)>,
+}
+
+/// A possibly polymorphic type. Every use instantiates `vars`, the inferred variables of `ty`, and
+/// `params`, the declared type parameters, afresh.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scheme {
+    pub vars: Vec<Quantified>,
+    pub params: Vec<TypeParam>,
+    pub ty: Type,
+}
+
+impl Scheme {
+    pub fn mono(ty: Type) -> Self {
+        Self {
+            vars: Vec::new(),
+            params: Vec::new(),
+            ty,
+        }
+    }
+}
+
+/// Prints quantified variables as `'a`, `'b`, .. in order of appearance, followed by their
+/// constraints and the bounds of type parameters, e.g. `fn('a, 'a) -> 'a where 'a: number or str`
+/// or `fn(T, T) -> T where T: Comparable`, and a promotion as `'c = promote('a, 'b)`. Variables
+/// that inference could not pin down, and that are not quantified, print as `_`.
+impl std::fmt::Display for Scheme {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut order = Vec::new();
+        self.ty.vars(&mut order);
+        let quantified: Vec<usize> = order
+            .into_iter()
+            .filter(|id| self.vars.iter().any(|q| q.id == *id))
+            .collect();
+        let name = |id: usize| match quantified.iter().position(|v| *v == id) {
+            Some(i) => format!("'{}", (b'a' + i as u8) as char),
+            None => "_".to_owned(),
+        };
+        self.ty.fmt_with(f, &name)?;
+
+        let mut clauses = Vec::new();
+        for id in &quantified {
+            let Some(q) = self.vars.iter().find(|q| q.id == *id) else {
+                continue;
+            };
+            if let Some((a, b)) = q.promotes {
+                clauses.push(format!("{} = promote({}, {})", name(*id), name(a), name(b)));
+                continue;
+            }
+            let mut requires: Vec<String> = q.constraint.iter().map(|c| c.to_string()).collect();
+            requires.extend(q.bounds.iter().cloned());
+            if !requires.is_empty() {
+                clauses.push(format!("{}: {}", name(*id), requires.join(" + ")));
+            }
+        }
+        for param in &self.params {
+            if !param.bounds.is_empty() {
+                clauses.push(param.to_string());
+            }
+        }
+        if !clauses.is_empty() {
+            write!(f, " where {}", clauses.join(", "))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,635 @@
+use std::rc::Rc;
+
+use crate::compiler::{
+    ast::{
+        expr::{BinaryOp, Expr, Identifier, LogicalOp, UnaryOp},
+        literal::Literal,
+        types::Type,
+    },
+    statements::stmt::{Block, Catch, ClassDecl, FnDecl, InterfaceDecl, Stmt, StmtKind},
+};
+
+/// Read-only traversal over the AST. Every method defaults to its matching `walk_*` function, so an
+/// implementor only overrides the nodes it cares about and calls `walk_*` itself to keep descending.
+pub trait Visitor: Sized {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        walk_stmt(self, stmt)
+    }
+    fn visit_expr_stmt(&mut self, expr: &Expr) {
+        self.visit_expr(expr)
+    }
+    fn visit_print(&mut self, expr: &Expr) {
+        self.visit_expr(expr)
+    }
+    fn visit_let(&mut self, name: &Identifier, ty: Option<&Type>, init: Option<&Expr>) {
+        walk_let(self, name, ty, init)
+    }
+    fn visit_block(&mut self, block: &Block) {
+        walk_block(self, block)
+    }
+    fn visit_if(&mut self, cond: &Expr, then_branch: &Block, else_branch: Option<&Stmt>) {
+        walk_if(self, cond, then_branch, else_branch)
+    }
+    fn visit_while(&mut self, cond: &Expr, body: &Block) {
+        walk_while(self, cond, body)
+    }
+    fn visit_for(&mut self, name: &Identifier, iterable: &Expr, body: &Block) {
+        walk_for(self, name, iterable, body)
+    }
+    fn visit_fn(&mut self, decl: &FnDecl) {
+        walk_fn(self, decl)
+    }
+    fn visit_return(&mut self, value: Option<&Expr>) {
+        walk_return(self, value)
+    }
+    fn visit_throw(&mut self, value: &Expr) {
+        self.visit_expr(value)
+    }
+    fn visit_try(&mut self, body: &Block, catch: Option<&Catch>, finally: Option<&Block>) {
+        walk_try(self, body, catch, finally)
+    }
+    /// the name is bound in the scope of the body's statements, as a `for` loop's is.
+    fn visit_catch(&mut self, catch: &Catch) {
+        walk_catch(self, catch)
+    }
+    fn visit_class(&mut self, decl: &ClassDecl) {
+        walk_class(self, decl)
+    }
+    /// interfaces hold no expressions, so there is nothing to walk.
+    fn visit_interface(&mut self, _decl: &InterfaceDecl) {}
+    /// the module is bound by the resolver, and holds no expressions.
+    fn visit_import(&mut self, _path: &str, _name: &Identifier) {}
+
+    fn visit_expr(&mut self, expr: &Expr) {
+        walk_expr(self, expr)
+    }
+    fn visit_literal(&mut self, _literal: &Literal) {}
+    fn visit_unary(&mut self, op: &UnaryOp, rhs: &Expr) {
+        walk_unary(self, op, rhs)
+    }
+    fn visit_binary(&mut self, lhs: &Expr, op: &BinaryOp, rhs: &Expr) {
+        walk_binary(self, lhs, op, rhs)
+    }
+    fn visit_logical(&mut self, lhs: &Expr, op: &LogicalOp, rhs: &Expr) {
+        walk_logical(self, lhs, op, rhs)
+    }
+    fn visit_grouping(&mut self, inner: &Expr) {
+        walk_grouping(self, inner)
+    }
+    fn visit_variable(&mut self, _name: &Identifier) {}
+    fn visit_assign(&mut self, name: &Identifier, value: &Expr) {
+        walk_assign(self, name, value)
+    }
+    fn visit_call(&mut self, callee: &Expr, args: &[Expr]) {
+        walk_call(self, callee, args)
+    }
+    fn visit_get(&mut self, object: &Expr, name: &Identifier) {
+        walk_get(self, object, name)
+    }
+    fn visit_set(&mut self, object: &Expr, name: &Identifier, value: &Expr) {
+        walk_set(self, object, name, value)
+    }
+    fn visit_this(&mut self, _keyword: &Identifier) {}
+    fn visit_list(&mut self, elements: &[Expr]) {
+        walk_list(self, elements)
+    }
+    fn visit_map(&mut self, entries: &[(Expr, Expr)]) {
+        walk_map(self, entries)
+    }
+    fn visit_index(&mut self, object: &Expr, index: &Expr) {
+        walk_index(self, object, index)
+    }
+    fn visit_set_index(&mut self, object: &Expr, index: &Expr, value: &Expr) {
+        walk_set_index(self, object, index, value)
+    }
+}
+
+pub fn walk_stmt<V: Visitor>(visitor: &mut V, stmt: &Stmt) {
+    match &stmt.kind {
+        StmtKind::Expr(e) => visitor.visit_expr_stmt(e),
+        StmtKind::Print(e) => visitor.visit_print(e),
+        StmtKind::Let { name, ty, init } => visitor.visit_let(name, ty.as_ref(), init.as_ref()),
+        StmtKind::Block(b) => visitor.visit_block(b),
+        StmtKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => visitor.visit_if(cond, then_branch, else_branch.as_deref()),
+        StmtKind::While { cond, body } => visitor.visit_while(cond, body),
+        StmtKind::For {
+            name,
+            iterable,
+            body,
+        } => visitor.visit_for(name, iterable, body),
+        StmtKind::Fn(decl) => visitor.visit_fn(decl),
+        StmtKind::Return(value) => visitor.visit_return(value.as_ref()),
+        StmtKind::Throw(value) => visitor.visit_throw(value),
+        StmtKind::Try {
+            body,
+            catch,
+            finally,
+        } => visitor.visit_try(body, catch.as_ref(), finally.as_ref()),
+        StmtKind::Class(decl) => visitor.visit_class(decl),
+        StmtKind::Interface(decl) => visitor.visit_interface(decl),
+        StmtKind::Import { path, name } => visitor.visit_import(path, name),
+    }
+}
+
+pub fn walk_let<V: Visitor>(
+    visitor: &mut V,
+    _name: &Identifier,
+    _ty: Option<&Type>,
+    init: Option<&Expr>,
+) {
+    if let Some(init) = init {
+        visitor.visit_expr(init);
+    }
+}
+
+pub fn walk_block<V: Visitor>(visitor: &mut V, block: &Block) {
+    for stmt in &block.stmts {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_if<V: Visitor>(
+    visitor: &mut V,
+    cond: &Expr,
+    then_branch: &Block,
+    else_branch: Option<&Stmt>,
+) {
+    visitor.visit_expr(cond);
+    visitor.visit_block(then_branch);
+    if let Some(else_branch) = else_branch {
+        visitor.visit_stmt(else_branch);
+    }
+}
+
+pub fn walk_while<V: Visitor>(visitor: &mut V, cond: &Expr, body: &Block) {
+    visitor.visit_expr(cond);
+    visitor.visit_block(body);
+}
+
+pub fn walk_for<V: Visitor>(visitor: &mut V, _name: &Identifier, iterable: &Expr, body: &Block) {
+    visitor.visit_expr(iterable);
+    visitor.visit_block(body);
+}
+
+pub fn walk_fn<V: Visitor>(visitor: &mut V, decl: &FnDecl) {
+    visitor.visit_block(&decl.body);
+}
+
+pub fn walk_return<V: Visitor>(visitor: &mut V, value: Option<&Expr>) {
+    if let Some(value) = value {
+        visitor.visit_expr(value);
+    }
+}
+
+pub fn walk_try<V: Visitor>(
+    visitor: &mut V,
+    body: &Block,
+    catch: Option<&Catch>,
+    finally: Option<&Block>,
+) {
+    visitor.visit_block(body);
+    if let Some(catch) = catch {
+        visitor.visit_catch(catch);
+    }
+    if let Some(finally) = finally {
+        visitor.visit_block(finally);
+    }
+}
+
+pub fn walk_catch<V: Visitor>(visitor: &mut V, catch: &Catch) {
+    for stmt in &catch.body.stmts {
+        visitor.visit_stmt(stmt);
+    }
+}
+
+pub fn walk_class<V: Visitor>(visitor: &mut V, decl: &ClassDecl) {
+    for method in &decl.methods {
+        visitor.visit_fn(method);
+    }
+}
+
+pub fn walk_expr<V: Visitor>(visitor: &mut V, expr: &Expr) {
+    match expr {
+        Expr::LiteralExpr(l) => visitor.visit_literal(l),
+        Expr::Unary { op, rhs } => visitor.visit_unary(op, rhs),
+        Expr::Binary { lhs, op, rhs } => visitor.visit_binary(lhs, op, rhs),
+        Expr::Logical { lhs, op, rhs } => visitor.visit_logical(lhs, op, rhs),
+        Expr::Grouping(inner) => visitor.visit_grouping(inner),
+        Expr::Variable(name) => visitor.visit_variable(name),
+        Expr::Assign { name, value } => visitor.visit_assign(name, value),
+        Expr::Call { callee, args, .. } => visitor.visit_call(callee, args),
+        Expr::Get { object, name } => visitor.visit_get(object, name),
+        Expr::Set {
+            object,
+            name,
+            value,
+        } => visitor.visit_set(object, name, value),
+        Expr::This(keyword) => visitor.visit_this(keyword),
+        Expr::List(elements) => visitor.visit_list(elements),
+        Expr::Map(entries) => visitor.visit_map(entries),
+        Expr::Index { object, index, .. } => visitor.visit_index(object, index),
+        Expr::SetIndex {
+            object,
+            index,
+            value,
+            ..
+        } => visitor.visit_set_index(object, index, value),
+    }
+}
+
+pub fn walk_unary<V: Visitor>(visitor: &mut V, _op: &UnaryOp, rhs: &Expr) {
+    visitor.visit_expr(rhs);
+}
+
+pub fn walk_binary<V: Visitor>(visitor: &mut V, lhs: &Expr, _op: &BinaryOp, rhs: &Expr) {
+    visitor.visit_expr(lhs);
+    visitor.visit_expr(rhs);
+}
+
+pub fn walk_logical<V: Visitor>(visitor: &mut V, lhs: &Expr, _op: &LogicalOp, rhs: &Expr) {
+    visitor.visit_expr(lhs);
+    visitor.visit_expr(rhs);
+}
+
+pub fn walk_grouping<V: Visitor>(visitor: &mut V, inner: &Expr) {
+    visitor.visit_expr(inner);
+}
+
+pub fn walk_assign<V: Visitor>(visitor: &mut V, _name: &Identifier, value: &Expr) {
+    visitor.visit_expr(value);
+}
+
+pub fn walk_call<V: Visitor>(visitor: &mut V, callee: &Expr, args: &[Expr]) {
+    visitor.visit_expr(callee);
+    for arg in args {
+        visitor.visit_expr(arg);
+    }
+}
+
+pub fn walk_get<V: Visitor>(visitor: &mut V, object: &Expr, _name: &Identifier) {
+    visitor.visit_expr(object);
+}
+
+pub fn walk_set<V: Visitor>(visitor: &mut V, object: &Expr, _name: &Identifier, value: &Expr) {
+    visitor.visit_expr(object);
+    visitor.visit_expr(value);
+}
+
+pub fn walk_list<V: Visitor>(visitor: &mut V, elements: &[Expr]) {
+    for element in elements {
+        visitor.visit_expr(element);
+    }
+}
+
+pub fn walk_map<V: Visitor>(visitor: &mut V, entries: &[(Expr, Expr)]) {
+    for (key, value) in entries {
+        visitor.visit_expr(key);
+        visitor.visit_expr(value);
+    }
+}
+
+pub fn walk_index<V: Visitor>(visitor: &mut V, object: &Expr, index: &Expr) {
+    visitor.visit_expr(object);
+    visitor.visit_expr(index);
+}
+
+pub fn walk_set_index<V: Visitor>(visitor: &mut V, object: &Expr, index: &Expr, value: &Expr) {
+    visitor.visit_expr(object);
+    visitor.visit_expr(index);
+    visitor.visit_expr(value);
+}
+
+/// Mutable counterpart of `Visitor`. Passes that rewrite the tree (folding, desugaring) override
+/// `visit_expr_mut`, walk the children first and then replace `*expr` in place.
+pub trait VisitorMut: Sized {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        walk_stmt_mut(self, stmt)
+    }
+    fn visit_expr_stmt_mut(&mut self, expr: &mut Expr) {
+        self.visit_expr_mut(expr)
+    }
+    fn visit_print_mut(&mut self, expr: &mut Expr) {
+        self.visit_expr_mut(expr)
+    }
+    fn visit_let_mut(
+        &mut self,
+        name: &mut Identifier,
+        ty: Option<&mut Type>,
+        init: Option<&mut Expr>,
+    ) {
+        walk_let_mut(self, name, ty, init)
+    }
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        walk_block_mut(self, block)
+    }
+    fn visit_if_mut(
+        &mut self,
+        cond: &mut Expr,
+        then_branch: &mut Block,
+        else_branch: Option<&mut Stmt>,
+    ) {
+        walk_if_mut(self, cond, then_branch, else_branch)
+    }
+    fn visit_while_mut(&mut self, cond: &mut Expr, body: &mut Block) {
+        walk_while_mut(self, cond, body)
+    }
+    fn visit_for_mut(&mut self, name: &mut Identifier, iterable: &mut Expr, body: &mut Block) {
+        walk_for_mut(self, name, iterable, body)
+    }
+    fn visit_fn_mut(&mut self, decl: &mut FnDecl) {
+        walk_fn_mut(self, decl)
+    }
+    fn visit_return_mut(&mut self, value: Option<&mut Expr>) {
+        walk_return_mut(self, value)
+    }
+    fn visit_throw_mut(&mut self, value: &mut Expr) {
+        self.visit_expr_mut(value)
+    }
+    fn visit_try_mut(
+        &mut self,
+        body: &mut Block,
+        catch: Option<&mut Catch>,
+        finally: Option<&mut Block>,
+    ) {
+        walk_try_mut(self, body, catch, finally)
+    }
+    fn visit_catch_mut(&mut self, catch: &mut Catch) {
+        walk_catch_mut(self, catch)
+    }
+    fn visit_class_mut(&mut self, decl: &mut ClassDecl) {
+        walk_class_mut(self, decl)
+    }
+    fn visit_interface_mut(&mut self, _decl: &mut InterfaceDecl) {}
+    fn visit_import_mut(&mut self, _path: &mut String, _name: &mut Identifier) {}
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        walk_expr_mut(self, expr)
+    }
+    fn visit_literal_mut(&mut self, _literal: &mut Literal) {}
+    fn visit_unary_mut(&mut self, op: &mut UnaryOp, rhs: &mut Expr) {
+        walk_unary_mut(self, op, rhs)
+    }
+    fn visit_binary_mut(&mut self, lhs: &mut Expr, op: &mut BinaryOp, rhs: &mut Expr) {
+        walk_binary_mut(self, lhs, op, rhs)
+    }
+    fn visit_logical_mut(&mut self, lhs: &mut Expr, op: &mut LogicalOp, rhs: &mut Expr) {
+        walk_logical_mut(self, lhs, op, rhs)
+    }
+    fn visit_grouping_mut(&mut self, inner: &mut Expr) {
+        walk_grouping_mut(self, inner)
+    }
+    fn visit_variable_mut(&mut self, _name: &mut Identifier) {}
+    fn visit_assign_mut(&mut self, name: &mut Identifier, value: &mut Expr) {
+        walk_assign_mut(self, name, value)
+    }
+    fn visit_call_mut(&mut self, callee: &mut Expr, args: &mut [Expr]) {
+        walk_call_mut(self, callee, args)
+    }
+    fn visit_get_mut(&mut self, object: &mut Expr, name: &mut Identifier) {
+        walk_get_mut(self, object, name)
+    }
+    fn visit_set_mut(&mut self, object: &mut Expr, name: &mut Identifier, value: &mut Expr) {
+        walk_set_mut(self, object, name, value)
+    }
+    fn visit_this_mut(&mut self, _keyword: &mut Identifier) {}
+    fn visit_list_mut(&mut self, elements: &mut [Expr]) {
+        walk_list_mut(self, elements)
+    }
+    fn visit_map_mut(&mut self, entries: &mut [(Expr, Expr)]) {
+        walk_map_mut(self, entries)
+    }
+    fn visit_index_mut(&mut self, object: &mut Expr, index: &mut Expr) {
+        walk_index_mut(self, object, index)
+    }
+    fn visit_set_index_mut(&mut self, object: &mut Expr, index: &mut Expr, value: &mut Expr) {
+        walk_set_index_mut(self, object, index, value)
+    }
+}
+
+pub fn walk_stmt_mut<V: VisitorMut>(visitor: &mut V, stmt: &mut Stmt) {
+    match &mut stmt.kind {
+        StmtKind::Expr(e) => visitor.visit_expr_stmt_mut(e),
+        StmtKind::Print(e) => visitor.visit_print_mut(e),
+        StmtKind::Let { name, ty, init } => visitor.visit_let_mut(name, ty.as_mut(), init.as_mut()),
+        StmtKind::Block(b) => visitor.visit_block_mut(b),
+        StmtKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        } => visitor.visit_if_mut(cond, then_branch, else_branch.as_deref_mut()),
+        StmtKind::While { cond, body } => visitor.visit_while_mut(cond, body),
+        StmtKind::For {
+            name,
+            iterable,
+            body,
+        } => visitor.visit_for_mut(name, iterable, body),
+        StmtKind::Fn(decl) => visitor.visit_fn_mut(Rc::make_mut(decl)),
+        StmtKind::Return(value) => visitor.visit_return_mut(value.as_mut()),
+        StmtKind::Throw(value) => visitor.visit_throw_mut(value),
+        StmtKind::Try {
+            body,
+            catch,
+            finally,
+        } => visitor.visit_try_mut(body, catch.as_mut(), finally.as_mut()),
+        StmtKind::Class(decl) => visitor.visit_class_mut(decl),
+        StmtKind::Interface(decl) => visitor.visit_interface_mut(decl),
+        StmtKind::Import { path, name } => visitor.visit_import_mut(path, name),
+    }
+}
+
+pub fn walk_let_mut<V: VisitorMut>(
+    visitor: &mut V,
+    _name: &mut Identifier,
+    _ty: Option<&mut Type>,
+    init: Option<&mut Expr>,
+) {
+    if let Some(init) = init {
+        visitor.visit_expr_mut(init);
+    }
+}
+
+pub fn walk_block_mut<V: VisitorMut>(visitor: &mut V, block: &mut Block) {
+    for stmt in &mut block.stmts {
+        visitor.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_if_mut<V: VisitorMut>(
+    visitor: &mut V,
+    cond: &mut Expr,
+    then_branch: &mut Block,
+    else_branch: Option<&mut Stmt>,
+) {
+    visitor.visit_expr_mut(cond);
+    visitor.visit_block_mut(then_branch);
+    if let Some(else_branch) = else_branch {
+        visitor.visit_stmt_mut(else_branch);
+    }
+}
+
+pub fn walk_while_mut<V: VisitorMut>(visitor: &mut V, cond: &mut Expr, body: &mut Block) {
+    visitor.visit_expr_mut(cond);
+    visitor.visit_block_mut(body);
+}
+
+pub fn walk_for_mut<V: VisitorMut>(
+    visitor: &mut V,
+    _name: &mut Identifier,
+    iterable: &mut Expr,
+    body: &mut Block,
+) {
+    visitor.visit_expr_mut(iterable);
+    visitor.visit_block_mut(body);
+}
+
+pub fn walk_fn_mut<V: VisitorMut>(visitor: &mut V, decl: &mut FnDecl) {
+    visitor.visit_block_mut(&mut decl.body);
+}
+
+pub fn walk_return_mut<V: VisitorMut>(visitor: &mut V, value: Option<&mut Expr>) {
+    if let Some(value) = value {
+        visitor.visit_expr_mut(value);
+    }
+}
+
+pub fn walk_try_mut<V: VisitorMut>(
+    visitor: &mut V,
+    body: &mut Block,
+    catch: Option<&mut Catch>,
+    finally: Option<&mut Block>,
+) {
+    visitor.visit_block_mut(body);
+    if let Some(catch) = catch {
+        visitor.visit_catch_mut(catch);
+    }
+    if let Some(finally) = finally {
+        visitor.visit_block_mut(finally);
+    }
+}
+
+pub fn walk_catch_mut<V: VisitorMut>(visitor: &mut V, catch: &mut Catch) {
+    for stmt in &mut catch.body.stmts {
+        visitor.visit_stmt_mut(stmt);
+    }
+}
+
+pub fn walk_class_mut<V: VisitorMut>(visitor: &mut V, decl: &mut ClassDecl) {
+    for method in &mut decl.methods {
+        visitor.visit_fn_mut(Rc::make_mut(method));
+    }
+}
+
+pub fn walk_expr_mut<V: VisitorMut>(visitor: &mut V, expr: &mut Expr) {
+    match expr {
+        Expr::LiteralExpr(l) => visitor.visit_literal_mut(l),
+        Expr::Unary { op, rhs } => visitor.visit_unary_mut(op, rhs),
+        Expr::Binary { lhs, op, rhs } => visitor.visit_binary_mut(lhs, op, rhs),
+        Expr::Logical { lhs, op, rhs } => visitor.visit_logical_mut(lhs, op, rhs),
+        Expr::Grouping(inner) => visitor.visit_grouping_mut(inner),
+        Expr::Variable(name) => visitor.visit_variable_mut(name),
+        Expr::Assign { name, value } => visitor.visit_assign_mut(name, value),
+        Expr::Call { callee, args, .. } => visitor.visit_call_mut(callee, args),
+        Expr::Get { object, name } => visitor.visit_get_mut(object, name),
+        Expr::Set {
+            object,
+            name,
+            value,
+        } => visitor.visit_set_mut(object, name, value),
+        Expr::This(keyword) => visitor.visit_this_mut(keyword),
+        Expr::List(elements) => visitor.visit_list_mut(elements),
+        Expr::Map(entries) => visitor.visit_map_mut(entries),
+        Expr::Index { object, index, .. } => visitor.visit_index_mut(object, index),
+        Expr::SetIndex {
+            object,
+            index,
+            value,
+            ..
+        } => visitor.visit_set_index_mut(object, index, value),
+    }
+}
+
+pub fn walk_unary_mut<V: VisitorMut>(visitor: &mut V, _op: &mut UnaryOp, rhs: &mut Expr) {
+    visitor.visit_expr_mut(rhs);
+}
+
+pub fn walk_binary_mut<V: VisitorMut>(
+    visitor: &mut V,
+    lhs: &mut Expr,
+    _op: &mut BinaryOp,
+    rhs: &mut Expr,
+) {
+    visitor.visit_expr_mut(lhs);
+    visitor.visit_expr_mut(rhs);
+}
+
+pub fn walk_logical_mut<V: VisitorMut>(
+    visitor: &mut V,
+    lhs: &mut Expr,
+    _op: &mut LogicalOp,
+    rhs: &mut Expr,
+) {
+    visitor.visit_expr_mut(lhs);
+    visitor.visit_expr_mut(rhs);
+}
+
+pub fn walk_grouping_mut<V: VisitorMut>(visitor: &mut V, inner: &mut Expr) {
+    visitor.visit_expr_mut(inner);
+}
+
+pub fn walk_assign_mut<V: VisitorMut>(visitor: &mut V, _name: &mut Identifier, value: &mut Expr) {
+    visitor.visit_expr_mut(value);
+}
+
+pub fn walk_call_mut<V: VisitorMut>(visitor: &mut V, callee: &mut Expr, args: &mut [Expr]) {
+    visitor.visit_expr_mut(callee);
+    for arg in args {
+        visitor.visit_expr_mut(arg);
+    }
+}
+
+pub fn walk_get_mut<V: VisitorMut>(visitor: &mut V, object: &mut Expr, _name: &mut Identifier) {
+    visitor.visit_expr_mut(object);
+}
+
+pub fn walk_set_mut<V: VisitorMut>(
+    visitor: &mut V,
+    object: &mut Expr,
+    _name: &mut Identifier,
+    value: &mut Expr,
+) {
+    visitor.visit_expr_mut(object);
+    visitor.visit_expr_mut(value);
+}
+
+pub fn walk_list_mut<V: VisitorMut>(visitor: &mut V, elements: &mut [Expr]) {
+    for element in elements {
+        visitor.visit_expr_mut(element);
+    }
+}
+
+pub fn walk_map_mut<V: VisitorMut>(visitor: &mut V, entries: &mut [(Expr, Expr)]) {
+    for (key, value) in entries {
+        visitor.visit_expr_mut(key);
+        visitor.visit_expr_mut(value);
+    }
+}
+
+pub fn walk_index_mut<V: VisitorMut>(visitor: &mut V, object: &mut Expr, index: &mut Expr) {
+    visitor.visit_expr_mut(object);
+    visitor.visit_expr_mut(index);
+}
+
+pub fn walk_set_index_mut<V: VisitorMut>(
+    visitor: &mut V,
+    object: &mut Expr,
+    index: &mut Expr,
+    value: &mut Expr,
+) {
+    visitor.visit_expr_mut(object);
+    visitor.visit_expr_mut(index);
+    visitor.visit_expr_mut(value);
+}
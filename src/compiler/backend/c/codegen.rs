@@ -0,0 +1,356 @@
+use std::{collections::HashMap, fmt::Write};
+
+use crate::compiler::{
+    ast::{
+        expr::{BinaryOp, UnaryOp},
+        literal::Literal,
+    },
+    backend::{c::RUNTIME, BackendErr},
+    ir::{BlockId, FuncId, Function, Module, Op, Terminator, ValueId},
+};
+
+/// Translates every function of the module to a C function taking its closure and an array of its
+/// arguments. Each value is a local variable and each block a label, so control flow is `goto`s.
+/// String constants and globals are static; `main` runs the top-level code.
+pub fn generate(module: &Module) -> Result<String, BackendErr> {
+    let mut strings = Strings::default();
+    let mut bodies = String::new();
+    for (id, func) in module.functions.iter().enumerate() {
+        FnGen {
+            module,
+            strings: &mut strings,
+            out: &mut bodies,
+            func,
+        }
+        .function(FuncId(id))?;
+    }
+
+    let mut out = RUNTIME.to_owned();
+    out.push_str("\n/* the program */\n\n");
+    for (i, s) in strings.list.iter().enumerate() {
+        writeln!(
+            out,
+            "static Str str{} = {{{}, {}}};",
+            i,
+            s.len(),
+            c_string(s)
+        )
+        .unwrap();
+    }
+    let undefined = vec!["{T_UNDEFINED}"; module.globals.len().max(1)];
+    writeln!(
+        out,
+        "static Value globals[] = {{{}}};",
+        undefined.join(", ")
+    )
+    .unwrap();
+    for id in 0..module.functions.len() {
+        writeln!(
+            out,
+            "static Value {}(Closure *self, Value *args);",
+            function_name(FuncId(id))
+        )
+        .unwrap();
+    }
+    out.push_str(&bodies);
+    writeln!(out, "\nint main(void) {{").unwrap();
+    writeln!(out, "    {}(NULL, NULL);", function_name(FuncId(0))).unwrap();
+    writeln!(out, "    return 0;\n}}").unwrap();
+    Ok(out)
+}
+
+const TARGET: &str = "c";
+
+fn function_name(id: FuncId) -> String {
+    format!("fn{}", id.0)
+}
+
+/// the name a function value prints with: methods are named after their class in the IR.
+fn display_name(func: &Function) -> &str {
+    let name = func.name.split('#').next().unwrap_or(&func.name);
+    name.rsplit('.').next().unwrap_or(name)
+}
+
+/// a C string literal with the bytes of `s`. Anything but printable ASCII is escaped, as is `?`,
+/// which could start a trigraph.
+fn c_string(s: &str) -> String {
+    let mut literal = String::from("\"");
+    for b in s.bytes() {
+        match b {
+            b'"' | b'\\' | b'?' => write!(literal, "\\{}", b as char).unwrap(),
+            0x20..=0x7e => literal.push(b as char),
+            _ => write!(literal, "\\{:03o}", b).unwrap(),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// string constants, each declared once.
+#[derive(Default)]
+struct Strings {
+    list: Vec<String>,
+    index: HashMap<String, usize>,
+}
+
+impl Strings {
+    /// the name of the constant holding `s`.
+    fn get(&mut self, s: &str) -> String {
+        let index = match self.index.get(s) {
+            Some(i) => *i,
+            None => {
+                self.list.push(s.to_owned());
+                self.index.insert(s.to_owned(), self.list.len() - 1);
+                self.list.len() - 1
+            }
+        };
+        format!("str{}", index)
+    }
+}
+
+struct FnGen<'a> {
+    module: &'a Module,
+    strings: &'a mut Strings,
+    out: &'a mut String,
+    func: &'a Function,
+}
+
+impl FnGen<'_> {
+    fn line(&mut self, line: impl AsRef<str>) {
+        self.out.push_str("    ");
+        self.out.push_str(line.as_ref());
+        self.out.push('\n');
+    }
+
+    fn function(&mut self, id: FuncId) -> Result<(), BackendErr> {
+        let func = self.func;
+        write!(
+            self.out,
+            "\n/* {} */\nstatic Value {}(Closure *self, Value *args) {{\n",
+            func.name,
+            function_name(id)
+        )
+        .unwrap();
+        let values: Vec<String> = func
+            .blocks
+            .iter()
+            .flat_map(|b| &b.insts)
+            .filter(|v| func.op(**v).has_value())
+            .map(|v| format!("v{}", v.0))
+            .collect();
+        if !values.is_empty() {
+            self.line(format!("Value {};", values.join(", ")));
+        }
+        self.line("(void)self;");
+        self.line("(void)args;");
+        for (b, block) in func.blocks.iter().enumerate() {
+            if !block.preds.is_empty() {
+                writeln!(self.out, "bb{}:", b).unwrap();
+            }
+            for value in &block.insts {
+                self.op(*value)?;
+            }
+            self.terminator(BlockId(b))?;
+        }
+        self.out.push_str("}\n");
+        Ok(())
+    }
+
+    fn op(&mut self, value: ValueId) -> Result<(), BackendErr> {
+        let v = |value: &ValueId| format!("v{}", value.0);
+        let expr = match self.func.op(value) {
+            Op::Const(literal) => match literal {
+                Literal::Nil => "rt_nil".to_owned(),
+                Literal::Bool(b) => format!("rt_bool({})", *b as u8),
+                Literal::Int(i) => format!("rt_int({})", i),
+                Literal::Float(f) => format!("rt_float_bits({:#x}u)", f.to_bits()),
+                Literal::Str(s) => format!("rt_str(&{})", self.strings.get(s)),
+            },
+            Op::Param(index) => format!("args[{}]", index),
+            Op::Capture(index) => format!("rt_capture(self->cells[{}])", index),
+            // assigned by the predecessors
+            Op::Phi(_) => return Ok(()),
+            Op::Copy(source) => v(source),
+            Op::Unary(op, operand) => {
+                let routine = match op {
+                    UnaryOp::Bang => "rt_not",
+                    UnaryOp::Negate => "rt_neg",
+                };
+                format!("{}({})", routine, v(operand))
+            }
+            Op::Binary(op, lhs, rhs) => {
+                format!("{}({}, {})", binary_routine(*op), v(lhs), v(rhs))
+            }
+            Op::LoadGlobal(index) if *index < self.module.natives => {
+                return Err(BackendErr::Unsupported {
+                    target: TARGET,
+                    feature: format!("the native '{}'", self.module.globals[*index]),
+                })
+            }
+            Op::LoadGlobal(index) => {
+                let message = format!("undefined variable '{}'", self.module.globals[*index]);
+                format!("rt_load_global(globals[{}], {})", index, c_string(&message))
+            }
+            Op::StoreGlobal(index, source) => {
+                self.line(format!("globals[{}] = {};", index, v(source)));
+                return Ok(());
+            }
+            Op::NewCell(initial) => format!("rt_cell({})", v(initial)),
+            Op::LoadCell(cell) => format!("*{}.as.cell", v(cell)),
+            Op::StoreCell(cell, source) => {
+                self.line(format!("*{}.as.cell = {};", v(cell), v(source)));
+                return Ok(());
+            }
+            Op::Closure(func, cells) => {
+                let callee = &self.module.functions[func.0];
+                format!(
+                    "rt_closure({}, {}, {}, {})",
+                    function_name(*func),
+                    callee.params,
+                    c_string(display_name(callee)),
+                    array(cells.iter().map(v).collect())
+                )
+            }
+            Op::Class(name, methods) => {
+                let names = methods.iter().map(|(n, _)| c_string(n)).collect();
+                let closures = methods.iter().map(|(_, m)| v(m)).collect();
+                format!(
+                    "rt_class({}, {}, {}, {})",
+                    c_string(name),
+                    methods.len(),
+                    compound("const char *", names),
+                    compound("Value", closures)
+                )
+            }
+            Op::Call(callee, args) => {
+                format!(
+                    "rt_call({}, {})",
+                    v(callee),
+                    array(args.iter().map(v).collect())
+                )
+            }
+            Op::GetProp(object, name) => format!("rt_get({}, {})", v(object), c_string(name)),
+            Op::SetProp(object, name, source) => {
+                self.line(format!(
+                    "rt_set({}, {}, {});",
+                    v(object),
+                    c_string(name),
+                    v(source)
+                ));
+                return Ok(());
+            }
+            Op::Print(operand) => {
+                self.line(format!("rt_print({});", v(operand)));
+                return Ok(());
+            }
+            Op::List(_)
+            | Op::Map(_)
+            | Op::GetIndex(..)
+            | Op::SetIndex(..)
+            | Op::Len(_)
+            | Op::Element(..) => {
+                return Err(BackendErr::Unsupported {
+                    target: TARGET,
+                    feature: "lists and maps".to_owned(),
+                })
+            }
+            Op::Caught | Op::EndTry => {
+                return Err(BackendErr::Unsupported {
+                    target: TARGET,
+                    feature: "exceptions".to_owned(),
+                })
+            }
+        };
+        self.line(format!("v{} = {};", value.0, expr));
+        Ok(())
+    }
+
+    fn terminator(&mut self, block: BlockId) -> Result<(), BackendErr> {
+        match &self.func.blocks[block.0].term {
+            Terminator::Jump(target) => {
+                self.phi_moves(block, *target);
+                self.line(format!("goto bb{};", target.0));
+            }
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.line(format!("if (rt_truthy(v{})) {{", cond.0));
+                self.phi_moves(block, *then);
+                self.line(format!("    goto bb{};", then.0));
+                self.line("}");
+                self.phi_moves(block, *otherwise);
+                self.line(format!("goto bb{};", otherwise.0));
+            }
+            Terminator::Return(value) => self.line(format!("return v{};", value.0)),
+            Terminator::Unreachable => self.line("abort();"),
+            Terminator::Throw(_) | Terminator::Try { .. } => {
+                return Err(BackendErr::Unsupported {
+                    target: TARGET,
+                    feature: "exceptions".to_owned(),
+                })
+            }
+        }
+        Ok(())
+    }
+
+    /// assigns the `phi`s of `to` the values they take coming from `from`, through temporaries
+    /// since one `phi` may be another's operand.
+    fn phi_moves(&mut self, from: BlockId, to: BlockId) {
+        let mut moves = Vec::new();
+        for value in &self.func.blocks[to.0].insts {
+            let Op::Phi(incoming) = self.func.op(*value) else {
+                break;
+            };
+            if let Some((_, source)) = incoming.iter().find(|(pred, _)| *pred == from) {
+                moves.push((*value, *source));
+            }
+        }
+        match moves.as_slice() {
+            [] => {}
+            [(phi, source)] => self.line(format!("v{} = v{};", phi.0, source.0)),
+            _ => {
+                let temps = moves
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (_, source))| format!("t{} = v{}", i, source.0))
+                    .collect::<Vec<_>>();
+                self.line(format!("{{ Value {};", temps.join(", ")));
+                for (i, (phi, _)) in moves.iter().enumerate() {
+                    self.line(format!("  v{} = t{};", phi.0, i));
+                }
+                self.line("}");
+            }
+        }
+    }
+}
+
+/// a count and an array of values, as the runtime takes arguments and cells.
+fn array(values: Vec<String>) -> String {
+    format!("{}, {}", values.len(), compound("Value", values))
+}
+
+/// a C99 compound literal array, or `NULL` since those cannot be empty.
+fn compound(ty: &str, items: Vec<String>) -> String {
+    match items.is_empty() {
+        true => "NULL".to_owned(),
+        false => format!("({}[]){{{}}}", ty, items.join(", ")),
+    }
+}
+
+fn binary_routine(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Plus => "rt_add",
+        BinaryOp::Minus => "rt_sub",
+        BinaryOp::Mult => "rt_mul",
+        BinaryOp::Div => "rt_div",
+        BinaryOp::EqEq => "rt_eq",
+        BinaryOp::BangEq => "rt_ne",
+        BinaryOp::Lt => "rt_lt",
+        BinaryOp::LtEq => "rt_le",
+        BinaryOp::Gt => "rt_gt",
+        BinaryOp::GtEq => "rt_ge",
+        BinaryOp::Eq => unreachable!("assignment is not an operator in the IR"),
+    }
+}
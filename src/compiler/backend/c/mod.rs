@@ -0,0 +1,16 @@
+//! Portable C: the IR is translated to a single C99 file that starts with the runtime in
+//! `runtime.h`, so it builds with any C compiler and no other files, e.g. `cc -std=c99 out.c`.
+
+mod codegen;
+#[cfg(test)]
+mod tests;
+
+use crate::compiler::{backend::BackendErr, ir::Module};
+
+/// the runtime every translated program starts with.
+pub const RUNTIME: &str = include_str!("runtime.h");
+
+/// the source of a C program running the module.
+pub fn compile(module: &Module) -> Result<String, BackendErr> {
+    codegen::generate(module)
+}
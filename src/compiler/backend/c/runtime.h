@@ -0,0 +1,485 @@
+/* The runtime of programs translated to C: values, the operators, printing, closures and classes,
+ * following the interpreter. Memory is allocated with malloc and never freed. */
+
+#include <math.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef enum {
+    T_NIL,
+    T_BOOL,
+    T_INT,
+    T_FLOAT,
+    T_STR,
+    T_FN,
+    T_CLASS,
+    T_INSTANCE,
+    /* a variable captured by closures; never seen by the program */
+    T_CELL,
+    /* a global that has not been defined yet */
+    T_UNDEFINED
+} Tag;
+
+struct Str;
+struct Closure;
+struct Class;
+struct Instance;
+
+typedef struct Value {
+    Tag tag;
+    union {
+        int b;
+        int32_t i;
+        float f;
+        struct Str *s;
+        struct Closure *fn;
+        struct Class *class_;
+        struct Instance *instance;
+        struct Value *cell;
+    } as;
+} Value;
+
+typedef struct Str {
+    size_t len;
+    const char *bytes;
+} Str;
+
+typedef Value (*Code)(struct Closure *self, Value *args);
+
+/* a function with the cells it captured; a bound method also carries its receiver, which the code
+ * takes as argument 0 */
+typedef struct Closure {
+    Code code;
+    int arity;
+    const char *name;
+    Value **cells;
+    int bound;
+    Value receiver;
+} Closure;
+
+typedef struct Class {
+    const char *name;
+    int methods;
+    const char **names;
+    Closure **closures;
+} Class;
+
+typedef struct Instance {
+    Class *class_;
+    int fields;
+    int capacity;
+    const char **names;
+    Value *values;
+} Instance;
+
+static const Value rt_nil = {T_NIL};
+
+static void rt_fail(const char *message) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s\n", message);
+    exit(1);
+}
+
+static void *rt_alloc(size_t size) {
+    void *p = malloc(size ? size : 1);
+    if (!p) rt_fail("out of memory");
+    return p;
+}
+
+static Value rt_bool(int b) {
+    Value v = {T_BOOL};
+    v.as.b = b != 0;
+    return v;
+}
+
+static Value rt_int(int32_t i) {
+    Value v = {T_INT};
+    v.as.i = i;
+    return v;
+}
+
+/* an int result computed on 64 bits */
+static Value rt_checked(int64_t i) {
+    if (i < INT32_MIN || i > INT32_MAX) rt_fail("integer overflow");
+    return rt_int((int32_t)i);
+}
+
+static Value rt_float(float f) {
+    Value v = {T_FLOAT};
+    v.as.f = f;
+    return v;
+}
+
+/* a float from its bits, so constants round-trip exactly */
+static Value rt_float_bits(uint32_t bits) {
+    float f;
+    memcpy(&f, &bits, sizeof f);
+    return rt_float(f);
+}
+
+static Value rt_str(Str *s) {
+    Value v = {T_STR};
+    v.as.s = s;
+    return v;
+}
+
+static Value rt_cell(Value initial) {
+    Value v = {T_CELL};
+    v.as.cell = rt_alloc(sizeof(Value));
+    *v.as.cell = initial;
+    return v;
+}
+
+static Value rt_capture(Value *cell) {
+    Value v = {T_CELL};
+    v.as.cell = cell;
+    return v;
+}
+
+static Value rt_closure(Code code, int arity, const char *name, int cells, const Value *values) {
+    Closure *c = rt_alloc(sizeof(Closure));
+    int i;
+    c->code = code;
+    c->arity = arity;
+    c->name = name;
+    c->cells = rt_alloc(sizeof(Value *) * cells);
+    for (i = 0; i < cells; i++) c->cells[i] = values[i].as.cell;
+    c->bound = 0;
+    c->receiver = rt_nil;
+    {
+        Value v = {T_FN};
+        v.as.fn = c;
+        return v;
+    }
+}
+
+static Value rt_class(const char *name, int methods, const char **names, const Value *closures) {
+    Class *c = rt_alloc(sizeof(Class));
+    int i;
+    c->name = name;
+    c->methods = methods;
+    c->names = rt_alloc(sizeof(char *) * methods);
+    c->closures = rt_alloc(sizeof(Closure *) * methods);
+    for (i = 0; i < methods; i++) {
+        c->names[i] = names[i];
+        c->closures[i] = closures[i].as.fn;
+    }
+    {
+        Value v = {T_CLASS};
+        v.as.class_ = c;
+        return v;
+    }
+}
+
+static Value rt_load_global(Value v, const char *message) {
+    if (v.tag == T_UNDEFINED) rt_fail(message);
+    return v;
+}
+
+static int rt_truthy(Value v) {
+    return !(v.tag == T_NIL || (v.tag == T_BOOL && !v.as.b));
+}
+
+static int rt_is_literal(Value v) {
+    return v.tag <= T_STR;
+}
+
+static int rt_is_number(Value v) {
+    return v.tag == T_INT || v.tag == T_FLOAT;
+}
+
+static float rt_number(Value v) {
+    return v.tag == T_INT ? (float)v.as.i : v.as.f;
+}
+
+/* like Rust's `as i32`: saturating, with NaN as 0 */
+static int32_t rt_truncate(float f) {
+    if (f != f) return 0;
+    if (f >= 2147483648.0f) return INT32_MAX;
+    if (f <= -2147483648.0f) return INT32_MIN;
+    return (int32_t)f;
+}
+
+static Value rt_concat(Value a, Value b) {
+    Str *s = rt_alloc(sizeof(Str));
+    char *bytes = rt_alloc(a.as.s->len + b.as.s->len);
+    memcpy(bytes, a.as.s->bytes, a.as.s->len);
+    memcpy(bytes + a.as.s->len, b.as.s->bytes, b.as.s->len);
+    s->len = a.as.s->len + b.as.s->len;
+    s->bytes = bytes;
+    return rt_str(s);
+}
+
+static Value rt_add(Value a, Value b) {
+    if (a.tag == T_INT && b.tag == T_INT) return rt_checked((int64_t)a.as.i + b.as.i);
+    if (rt_is_number(a) && rt_is_number(b)) return rt_float(rt_number(a) + rt_number(b));
+    if (a.tag == T_STR && b.tag == T_STR) return rt_concat(a, b);
+    rt_fail("operands of '+' must be numbers or strings");
+    return rt_nil;
+}
+
+static Value rt_sub(Value a, Value b) {
+    if (a.tag == T_INT && b.tag == T_INT) return rt_checked((int64_t)a.as.i - b.as.i);
+    if (rt_is_number(a) && rt_is_number(b)) return rt_float(rt_number(a) - rt_number(b));
+    rt_fail("operands of '-' must be numbers");
+    return rt_nil;
+}
+
+static Value rt_mul(Value a, Value b) {
+    if (a.tag == T_INT && b.tag == T_INT) return rt_checked((int64_t)a.as.i * b.as.i);
+    if (rt_is_number(a) && rt_is_number(b)) return rt_float(rt_number(a) * rt_number(b));
+    rt_fail("operands of '*' must be numbers");
+    return rt_nil;
+}
+
+static Value rt_div(Value a, Value b) {
+    if (a.tag == T_INT && b.tag == T_INT) {
+        if (b.as.i == 0) rt_fail("division by zero");
+        return rt_checked((int64_t)a.as.i / b.as.i);
+    }
+    if (rt_is_number(a) && rt_is_number(b)) return rt_float(rt_number(a) / rt_number(b));
+    rt_fail("operands of '/' must be numbers");
+    return rt_nil;
+}
+
+enum { CMP_LT, CMP_LE, CMP_GT, CMP_GE };
+
+/* An int compared with a float compares with the float truncated, a float with an int with the int
+ * converted. Other literals are never ordered. */
+static Value rt_compare(Value a, Value b, int op) {
+    if (!rt_is_literal(a) || !rt_is_literal(b)) rt_fail("only numbers can be compared");
+    if (a.tag == T_FLOAT && rt_is_number(b)) {
+        float x = a.as.f, y = rt_number(b);
+        switch (op) {
+        case CMP_LT: return rt_bool(x < y);
+        case CMP_LE: return rt_bool(x <= y);
+        case CMP_GT: return rt_bool(x > y);
+        default: return rt_bool(x >= y);
+        }
+    }
+    if (a.tag == T_INT && rt_is_number(b)) {
+        int32_t x = a.as.i, y = b.tag == T_INT ? b.as.i : rt_truncate(b.as.f);
+        switch (op) {
+        case CMP_LT: return rt_bool(x < y);
+        case CMP_LE: return rt_bool(x <= y);
+        case CMP_GT: return rt_bool(x > y);
+        default: return rt_bool(x >= y);
+        }
+    }
+    return rt_bool(0);
+}
+
+static Value rt_lt(Value a, Value b) { return rt_compare(a, b, CMP_LT); }
+static Value rt_le(Value a, Value b) { return rt_compare(a, b, CMP_LE); }
+static Value rt_gt(Value a, Value b) { return rt_compare(a, b, CMP_GT); }
+static Value rt_ge(Value a, Value b) { return rt_compare(a, b, CMP_GE); }
+
+/* literals are equal if they are the same kind and value, so `1 == 1.0` is false; everything else
+ * is equal only to itself */
+static int rt_equal(Value a, Value b) {
+    if (a.tag != b.tag) return 0;
+    switch (a.tag) {
+    case T_NIL: return 1;
+    case T_BOOL: return a.as.b == b.as.b;
+    case T_INT: return a.as.i == b.as.i;
+    case T_FLOAT: return a.as.f == b.as.f;
+    case T_STR:
+        return a.as.s->len == b.as.s->len &&
+               memcmp(a.as.s->bytes, b.as.s->bytes, a.as.s->len) == 0;
+    case T_FN: return a.as.fn == b.as.fn;
+    case T_CLASS: return a.as.class_ == b.as.class_;
+    case T_INSTANCE: return a.as.instance == b.as.instance;
+    default: return 0;
+    }
+}
+
+static Value rt_eq(Value a, Value b) { return rt_bool(rt_equal(a, b)); }
+static Value rt_ne(Value a, Value b) { return rt_bool(!rt_equal(a, b)); }
+
+static Value rt_not(Value v) {
+    if (v.tag == T_NIL) return rt_bool(1);
+    if (v.tag == T_BOOL) return rt_bool(!v.as.b);
+    rt_fail("operand of '!' must be a bool or nil");
+    return rt_nil;
+}
+
+static Value rt_neg(Value v) {
+    if (v.tag == T_INT) return rt_checked(-(int64_t)v.as.i);
+    if (v.tag == T_FLOAT) return rt_float(-v.as.f);
+    rt_fail("operand of '-' must be a number");
+    return rt_nil;
+}
+
+/* as Rust prints an f32: the shortest digits that read back as the same number, never with an
+ * exponent */
+static void rt_print_float(float x) {
+    char buf[32], digits[16];
+    int precision, n = 0, point, i;
+    char *p;
+    if (x != x) {
+        fputs("NaN", stdout);
+        return;
+    }
+    if (x == INFINITY || x == -INFINITY) {
+        fputs(x > 0 ? "inf" : "-inf", stdout);
+        return;
+    }
+    if (x == 0) {
+        fputs(signbit(x) ? "-0" : "0", stdout);
+        return;
+    }
+    for (precision = 1; precision < 9; precision++) {
+        snprintf(buf, sizeof buf, "%.*e", precision - 1, (double)x);
+        if (strtof(buf, NULL) == x) break;
+    }
+    snprintf(buf, sizeof buf, "%.*e", precision - 1, (double)x);
+    p = buf;
+    if (*p == '-') {
+        putchar('-');
+        p++;
+    }
+    for (; *p != 'e'; p++) {
+        if (*p != '.') digits[n++] = *p;
+    }
+    while (n > 1 && digits[n - 1] == '0') n--;
+    point = 1 + atoi(p + 1);
+    if (point <= 0) {
+        fputs("0.", stdout);
+        for (i = point; i < 0; i++) putchar('0');
+        fwrite(digits, 1, n, stdout);
+    } else if (point >= n) {
+        fwrite(digits, 1, n, stdout);
+        for (i = n; i < point; i++) putchar('0');
+    } else {
+        fwrite(digits, 1, point, stdout);
+        putchar('.');
+        fwrite(digits + point, 1, n - point, stdout);
+    }
+}
+
+static void rt_print(Value v) {
+    switch (v.tag) {
+    case T_NIL: fputs("nil", stdout); break;
+    case T_BOOL: fputs(v.as.b ? "true" : "false", stdout); break;
+    case T_INT: printf("%ld", (long)v.as.i); break;
+    case T_FLOAT: rt_print_float(v.as.f); break;
+    case T_STR: fwrite(v.as.s->bytes, 1, v.as.s->len, stdout); break;
+    case T_FN: printf("<fn %s>", v.as.fn->name); break;
+    case T_CLASS: fputs(v.as.class_->name, stdout); break;
+    case T_INSTANCE: printf("<%s instance>", v.as.instance->class_->name); break;
+    default: break;
+    }
+    putchar('\n');
+}
+
+static void rt_arity(int expected, int got) {
+    char message[64];
+    snprintf(message, sizeof message, "expected %d arguments but got %d", expected, got);
+    rt_fail(message);
+}
+
+static Value rt_call_closure(Closure *c, int argc, Value *args) {
+    Value *with_receiver;
+    int i;
+    if (!c->bound) {
+        if (argc != c->arity) rt_arity(c->arity, argc);
+        return c->code(c, args);
+    }
+    if (argc != c->arity - 1) rt_arity(c->arity - 1, argc);
+    with_receiver = rt_alloc(sizeof(Value) * (argc + 1));
+    with_receiver[0] = c->receiver;
+    for (i = 0; i < argc; i++) with_receiver[i + 1] = args[i];
+    return c->code(c, with_receiver);
+}
+
+static Value rt_bind(Closure *method, Value receiver) {
+    Closure *bound = rt_alloc(sizeof(Closure));
+    Value v = {T_FN};
+    *bound = *method;
+    bound->bound = 1;
+    bound->receiver = receiver;
+    v.as.fn = bound;
+    return v;
+}
+
+static Closure *rt_method(Class *c, const char *name) {
+    int i;
+    for (i = 0; i < c->methods; i++) {
+        if (strcmp(c->names[i], name) == 0) return c->closures[i];
+    }
+    return NULL;
+}
+
+static Value rt_call(Value callee, int argc, Value *args) {
+    if (callee.tag == T_FN) return rt_call_closure(callee.as.fn, argc, args);
+    if (callee.tag == T_CLASS) {
+        Instance *instance = rt_alloc(sizeof(Instance));
+        Value v = {T_INSTANCE};
+        Closure *init = rt_method(callee.as.class_, "init");
+        instance->class_ = callee.as.class_;
+        instance->fields = 0;
+        instance->capacity = 0;
+        instance->names = NULL;
+        instance->values = NULL;
+        v.as.instance = instance;
+        if (init) {
+            rt_call_closure(rt_bind(init, v).as.fn, argc, args);
+        } else if (argc != 0) {
+            rt_arity(0, argc);
+        }
+        return v;
+    }
+    rt_fail("can only call functions and classes");
+    return rt_nil;
+}
+
+static void rt_property_error(const char *name) {
+    char message[256];
+    snprintf(message, sizeof message, "undefined property '%s'", name);
+    rt_fail(message);
+}
+
+/* fields shadow methods */
+static Value rt_get(Value object, const char *name) {
+    Instance *instance;
+    Closure *method;
+    int i;
+    if (object.tag != T_INSTANCE) rt_fail("only instances have properties");
+    instance = object.as.instance;
+    for (i = 0; i < instance->fields; i++) {
+        if (strcmp(instance->names[i], name) == 0) return instance->values[i];
+    }
+    method = rt_method(instance->class_, name);
+    if (!method) rt_property_error(name);
+    return rt_bind(method, object);
+}
+
+static void rt_set(Value object, const char *name, Value value) {
+    Instance *instance;
+    int i;
+    if (object.tag != T_INSTANCE) rt_fail("only instances have fields");
+    instance = object.as.instance;
+    for (i = 0; i < instance->fields; i++) {
+        if (strcmp(instance->names[i], name) == 0) {
+            instance->values[i] = value;
+            return;
+        }
+    }
+    if (instance->fields == instance->capacity) {
+        int capacity = instance->capacity ? 2 * instance->capacity : 4;
+        const char **names = rt_alloc(sizeof(char *) * capacity);
+        Value *values = rt_alloc(sizeof(Value) * capacity);
+        if (instance->fields) {
+            memcpy(names, instance->names, sizeof(char *) * instance->fields);
+            memcpy(values, instance->values, sizeof(Value) * instance->fields);
+        }
+        instance->names = names;
+        instance->values = values;
+        instance->capacity = capacity;
+    }
+    instance->names[instance->fields] = name;
+    instance->values[instance->fields] = value;
+    instance->fields++;
+}
@@ -0,0 +1,185 @@
+use crate::compiler::{
+    backend::c::compile,
+    checker::check,
+    ir::{lower::lower, opt::optimize},
+    lexer::Lexer,
+    natives::Natives,
+    parser::Parser,
+    resolver::resolve,
+    token::Token,
+};
+
+/// the C source translated from a program at an optimisation level. There are no natives, which C
+/// cannot call, so the program's globals are numbered from 0.
+fn translate(source: &str, level: u8) -> String {
+    let tokens: Vec<Token> = Lexer::from_source(source).collect();
+    let mut program = Parser::new(&tokens).parse().unwrap();
+    let natives = Natives::default();
+    let globals = resolve(&mut program, &natives).unwrap();
+    check(&program, &natives).unwrap();
+    let mut module = lower(&program, &globals, natives.len());
+    optimize(&mut module, level);
+    compile(&module).unwrap()
+}
+
+#[test]
+fn test_unsupported_features() {
+    for (source, feature) in [
+        ("print [1][0];", "lists and maps"),
+        ("throw 1;", "exceptions"),
+        ("try { print 1; } catch (e) { print e; }", "exceptions"),
+        // compiled code cannot call into the host
+        ("print str(1);", "the native 'str'"),
+    ] {
+        let tokens: Vec<Token> = Lexer::from_source(source).collect();
+        let mut program = Parser::new(&tokens).parse().unwrap();
+        let globals = resolve(&mut program, &Natives::standard()).unwrap();
+        let module = lower(&program, &globals, Natives::standard().len());
+        assert_eq!(
+            compile(&module).unwrap_err().to_string(),
+            format!("the c target does not support {} yet", feature)
+        );
+    }
+}
+
+#[test]
+fn test_translation() {
+    let c = translate("let greeting = \"hi?\"; print greeting;", 1);
+    assert!(c.starts_with("/* The runtime"), "{}", c);
+    for line in [
+        "static Str str0 = {3, \"hi\\?\"};",
+        "static Value globals[] = {{T_UNDEFINED}};",
+        "static Value fn0(Closure *self, Value *args) {",
+        "    globals[0] = v0;",
+        "    fn0(NULL, NULL);",
+    ] {
+        assert!(c.lines().any(|l| l == line), "{}\n{}", line, c);
+    }
+}
+
+/// compiles the translation of a program at optimisation level 1 with the system C compiler and
+/// runs it, returning its exit status, stdout and stderr, or `None` if there is no `cc`.
+fn run_c(name: &str, source: &str) -> Option<(i32, String, String)> {
+    run_c_at(name, source, 1)
+}
+
+/// `run_c` at an optimisation level.
+fn run_c_at(name: &str, source: &str, level: u8) -> Option<(i32, String, String)> {
+    let dir = std::env::temp_dir();
+    let c_file = dir.join(format!("{}-{}.c", name, std::process::id()));
+    let executable = dir.join(format!("{}-{}", name, std::process::id()));
+    std::fs::write(&c_file, translate(source, level)).unwrap();
+    let cc = std::process::Command::new("cc")
+        .arg("-std=c99")
+        .arg("-o")
+        .arg(&executable)
+        .arg(&c_file)
+        .output();
+    std::fs::remove_file(&c_file).unwrap();
+    let cc = cc.ok()?;
+    assert!(
+        cc.status.success(),
+        "{}",
+        String::from_utf8_lossy(&cc.stderr)
+    );
+    let output = std::process::Command::new(&executable).output().unwrap();
+    std::fs::remove_file(&executable).unwrap();
+    Some((
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    ))
+}
+
+#[test]
+fn test_c_programs_run() {
+    let source = "
+fn fib(n) {
+    if (n < 2) { return n; }
+    return fib(n - 1) + fib(n - 2);
+}
+fn counter() {
+    let n = 0;
+    fn next() { n = n + 1; return n; }
+    return next;
+}
+class Point {
+    fn init(x, y) { this.x = x; this.y = y; }
+    fn sum() { return this.x + this.y; }
+}
+let next = counter();
+next();
+print next();
+print fib(20);
+let p = Point(1, 2.5);
+let sum = p.sum;
+print sum();
+print p;
+print Point;
+print sum;
+print \"hello\" + \", \" + \"world\";
+print -7 / 2;
+print 0.1 * 3;
+print 1 == 1.0;
+print 3 < 3.5;
+";
+    let Some((status, stdout, stderr)) = run_c("c-programs", source) else {
+        return;
+    };
+    assert_eq!((status, stderr.as_str()), (0, ""));
+    assert_eq!(
+        stdout,
+        "2\n6765\n3.5\n<Point instance>\nPoint\n<fn sum>\nhello, world\n-3\n0.3\nfalse\nfalse\n"
+    );
+}
+
+#[test]
+fn test_c_runtime_errors() {
+    let Some((status, stdout, stderr)) = run_c(
+        "c-arity",
+        "class A { fn init(a) {} } let g: any = A; print 1; print g(1, 2);",
+    ) else {
+        return;
+    };
+    assert_eq!(status, 1);
+    assert_eq!(stdout, "1\n");
+    assert_eq!(stderr, "error: expected 1 arguments but got 2\n");
+
+    let (status, _, stderr) = run_c(
+        "c-property",
+        "class A {} let a: any = A(); print a.missing;",
+    )
+    .unwrap();
+    assert_eq!(status, 1);
+    assert_eq!(stderr, "error: undefined property 'missing'\n");
+}
+
+#[test]
+fn test_c_unused_failing_operators() {
+    // dead code elimination must not drop an operator whose error is the program's only output
+    for (source, error) in [
+        (
+            "fn d(a, b) { let x = a / b; return 1; } print d(1, 0);",
+            "division by zero",
+        ),
+        (
+            "fn m(a, b) { let x = a * b; return 1; } print m(2147483647, 2);",
+            "integer overflow",
+        ),
+        (
+            "fn n(a) { let x = -a; return 1; } print n(-2147483647 - 1);",
+            "integer overflow",
+        ),
+        (
+            "fn s(a) { let x = a - 1; return 1; } let v: any = \"s\"; print s(v);",
+            "operands of '-' must be numbers",
+        ),
+    ] {
+        let Some(o0) = run_c_at("c-dce-o0", source, 0) else {
+            return;
+        };
+        let o1 = run_c_at("c-dce-o1", source, 1).unwrap();
+        assert_eq!(o0, (1, String::new(), format!("error: {}\n", error)));
+        assert_eq!(o1, o0, "{}", source);
+    }
+}
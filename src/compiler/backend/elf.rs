@@ -0,0 +1,73 @@
+//! Static ELF64 executables for x86-64 Linux. There are no sections, only the two segments the
+//! loader needs: the headers and code, readable and executable, then the data, readable and
+//! writable, with the zeroed memory after it.
+
+const ELF_HEADER_SIZE: u64 = 64;
+const PROGRAM_HEADER_SIZE: u64 = 56;
+const PAGE: u64 = 0x1000;
+
+/// where the first segment, starting with the headers, is loaded.
+pub const TEXT_VADDR: u64 = 0x40_0000;
+/// where code starts, right after the headers.
+pub const TEXT_START: u64 = TEXT_VADDR + ELF_HEADER_SIZE + 2 * PROGRAM_HEADER_SIZE;
+/// where data is loaded. Fixed so code can be assembled before the size of the file is known, and
+/// low enough that every address fits in 32 bits.
+pub const DATA_VADDR: u64 = 0x1000_0000;
+
+const PT_LOAD: u32 = 1;
+const PF_X: u32 = 1;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+
+/// an executable that runs `text` from `entry`, with `data` at `DATA_VADDR` followed by `bss`
+/// zeroed bytes.
+pub fn executable(text: &[u8], data: &[u8], bss: usize, entry: u64) -> Vec<u8> {
+    let text_end = TEXT_START - TEXT_VADDR + text.len() as u64;
+    // a segment's file offset and address must agree modulo the page size
+    let data_offset = text_end.next_multiple_of(PAGE);
+
+    let mut out = Vec::with_capacity(data_offset as usize + data.len());
+    out.extend([0x7f, b'E', b'L', b'F']);
+    // 64-bit, little endian, version 1, System V ABI, padding
+    out.extend([2, 1, 1, 0]);
+    out.extend([0; 8]);
+    out.extend(2u16.to_le_bytes()); // executable
+    out.extend(0x3eu16.to_le_bytes()); // x86-64
+    out.extend(1u32.to_le_bytes());
+    out.extend(entry.to_le_bytes());
+    out.extend(ELF_HEADER_SIZE.to_le_bytes()); // program headers
+    out.extend(0u64.to_le_bytes()); // no section headers
+    out.extend(0u32.to_le_bytes());
+    out.extend((ELF_HEADER_SIZE as u16).to_le_bytes());
+    out.extend((PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    out.extend(2u16.to_le_bytes());
+    out.extend(64u16.to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+    out.extend(0u16.to_le_bytes());
+
+    program_header(&mut out, PF_R | PF_X, 0, TEXT_VADDR, text_end, text_end);
+    program_header(
+        &mut out,
+        PF_R | PF_W,
+        data_offset,
+        DATA_VADDR,
+        data.len() as u64,
+        (data.len() + bss) as u64,
+    );
+
+    out.extend(text);
+    out.resize(data_offset as usize, 0);
+    out.extend(data);
+    out
+}
+
+fn program_header(out: &mut Vec<u8>, flags: u32, offset: u64, vaddr: u64, size: u64, mem: u64) {
+    out.extend(PT_LOAD.to_le_bytes());
+    out.extend(flags.to_le_bytes());
+    out.extend(offset.to_le_bytes());
+    out.extend(vaddr.to_le_bytes());
+    out.extend(vaddr.to_le_bytes());
+    out.extend(size.to_le_bytes());
+    out.extend(mem.to_le_bytes());
+    out.extend(PAGE.to_le_bytes());
+}
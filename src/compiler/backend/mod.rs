@@ -0,0 +1,25 @@
+//! Code generation from the IR for targets other than the interpreter.
+
+pub mod c;
+pub mod elf;
+pub mod wasm;
+pub mod x86_64;
+
+#[derive(Debug, PartialEq)]
+pub enum BackendErr {
+    /// a language feature the target cannot compile yet.
+    Unsupported {
+        target: &'static str,
+        feature: String,
+    },
+}
+
+impl std::fmt::Display for BackendErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BackendErr::Unsupported { target, feature } => {
+                write!(f, "the {} target does not support {} yet", target, feature)
+            }
+        }
+    }
+}
@@ -0,0 +1,479 @@
+use crate::compiler::{
+    ast::{
+        expr::{BinaryOp, UnaryOp},
+        literal::Literal,
+    },
+    backend::{
+        wasm::{
+            module::{BlockType, Export, Func, Global, Import, Instr, Module, NumOp, ValType},
+            runtime::{self, import, Data, Layout, Rt},
+        },
+        BackendErr,
+    },
+    ir::{self, BlockId, FuncId, Function, Op, Terminator, ValueId},
+};
+
+const TARGET: &str = "wasm32";
+
+/// Translates every function of the module to a wasm function taking its closure's address and
+/// then its arguments, all called through the table, and adds the runtime. The top-level code is
+/// exported as `main`.
+pub fn generate(program: &ir::Module) -> Result<Module, BackendErr> {
+    let mut module = Module::default();
+    let layout = Layout {
+        functions: program.functions.len() as u32,
+    };
+    let mut data = Data::default();
+
+    for (name, params, results) in [
+        ("write", &[ValType::I32, ValType::I32][..], &[][..]),
+        ("write_float", &[ValType::F64], &[]),
+        ("fail", &[ValType::I32, ValType::I32], &[]),
+    ] {
+        let ty = module.ty(params, results);
+        module.imports.push(Import {
+            module: "env".to_owned(),
+            name: name.to_owned(),
+            ty,
+        });
+    }
+    module.globals.push(Global {
+        name: "rt.heap_ptr".to_owned(),
+        ty: ValType::I32,
+        mutable: true,
+        // set once the data is known
+        init: 0,
+    });
+    module.globals.push(Global {
+        name: "rt.receiver".to_owned(),
+        ty: ValType::I64,
+        mutable: true,
+        init: runtime::NIL,
+    });
+    for name in &program.globals {
+        module.globals.push(Global {
+            name: name.clone(),
+            ty: ValType::I64,
+            mutable: true,
+            init: runtime::TAG_UNDEFINED << 32,
+        });
+    }
+
+    for (id, func) in program.functions.iter().enumerate() {
+        let ty = function_type(&mut module, func.params);
+        let mut gen = FnGen {
+            program,
+            module: &mut module,
+            data: &mut data,
+            layout,
+            func,
+            body: Vec::new(),
+        };
+        gen.function()?;
+        let body = gen.body;
+        let mut locals = vec![ValType::I32];
+        locals.extend(std::iter::repeat_n(ValType::I64, func.insts.len()));
+        locals.push(ValType::I32);
+        module.functions.push(Func {
+            name: format!("fn{}_{}", id, display_name(func)),
+            ty,
+            locals,
+            body,
+        });
+        module.table.push(import::COUNT + id as u32);
+    }
+    runtime::emit(&mut module, layout, &mut data);
+
+    let heap = data.end().next_multiple_of(8);
+    module.globals[runtime::HEAP_PTR as usize].init = heap as i64;
+    module.memory_pages = heap.div_ceil(1 << 16).max(1);
+    module.data.push(data.into_segment());
+    module.exports.push(Export::Memory("memory".to_owned()));
+    module
+        .exports
+        .push(Export::Func("main".to_owned(), import::COUNT));
+    Ok(module)
+}
+
+fn unsupported(feature: &str) -> BackendErr {
+    BackendErr::Unsupported {
+        target: TARGET,
+        feature: feature.to_owned(),
+    }
+}
+
+/// the closure's address, then the arguments.
+fn function_type(module: &mut Module, params: usize) -> u32 {
+    let mut types = vec![ValType::I32];
+    types.extend(std::iter::repeat_n(ValType::I64, params));
+    module.ty(&types, &[ValType::I64])
+}
+
+/// the name a function value prints with, without the class of a method.
+fn display_name(func: &Function) -> &str {
+    let name = func.name.split('#').next().unwrap_or(&func.name);
+    name.rsplit('.').next().unwrap_or(name)
+}
+
+struct FnGen<'a> {
+    program: &'a ir::Module,
+    module: &'a mut Module,
+    data: &'a mut Data,
+    layout: Layout,
+    func: &'a Function,
+    body: Vec<Instr>,
+}
+
+/// Blocks are laid out in order inside a loop, each nested one level shallower than the one before,
+/// so a jump either falls through to the next block or sets the `pc` local and branches back to the
+/// loop, whose `br_table` enters the block it names.
+impl FnGen<'_> {
+    fn emit(&mut self, instrs: impl IntoIterator<Item = Instr>) {
+        self.body.extend(instrs);
+    }
+    fn call(&mut self, rt: Rt) {
+        let index = self.layout.rt(rt);
+        self.emit([Instr::Call(index)]);
+    }
+    /// local 0 is the closure, the parameters follow.
+    fn param(&self, index: usize) -> u32 {
+        1 + index as u32
+    }
+    fn pc(&self) -> u32 {
+        self.param(self.func.params)
+    }
+    fn local(&self, value: ValueId) -> u32 {
+        self.pc() + 1 + value.0 as u32
+    }
+    /// an `i32` for addresses.
+    fn scratch(&self) -> u32 {
+        self.pc() + 1 + self.func.insts.len() as u32
+    }
+    fn get(&mut self, value: ValueId) {
+        let local = self.local(value);
+        self.emit([Instr::LocalGet(local)]);
+    }
+    /// pushes the address held by a value.
+    fn address(&mut self, value: ValueId) {
+        self.get(value);
+        self.emit([Instr::Num(NumOp::I32WrapI64)]);
+    }
+    fn error(&mut self, message: &str) {
+        let message = self.data.string(message);
+        self.emit([Instr::I32Const(message as i32)]);
+        self.call(Rt::Fail);
+        self.emit([Instr::Unreachable]);
+    }
+
+    fn function(&mut self) -> Result<(), BackendErr> {
+        let blocks = self.func.blocks.len() as u32;
+        self.emit([Instr::Loop(BlockType::Empty)]);
+        self.emit((0..blocks).map(|_| Instr::Block(BlockType::Empty)));
+        let pc = self.pc();
+        self.emit([
+            Instr::LocalGet(pc),
+            Instr::BrTable((0..blocks).collect(), blocks - 1),
+        ]);
+        for b in 0..self.func.blocks.len() {
+            self.emit([Instr::End]);
+            for value in &self.func.blocks[b].insts {
+                self.op(*value)?;
+            }
+            self.terminator(BlockId(b))?;
+        }
+        self.emit([Instr::End, Instr::Unreachable]);
+        Ok(())
+    }
+
+    fn op(&mut self, value: ValueId) -> Result<(), BackendErr> {
+        use Instr::*;
+        match self.func.op(value) {
+            Op::Const(literal) => {
+                let word = match literal {
+                    Literal::Nil => runtime::NIL,
+                    Literal::Bool(b) => runtime::make(runtime::TAG_BOOL, *b as u32),
+                    Literal::Int(i) => runtime::make(runtime::TAG_INT, *i as u32),
+                    Literal::Float(f) => runtime::make(runtime::TAG_FLOAT, f.to_bits()),
+                    Literal::Str(s) => runtime::make(runtime::TAG_STR, self.data.string(s)),
+                };
+                self.emit([I64Const(word)]);
+            }
+            Op::Param(index) => {
+                let local = self.param(*index);
+                self.emit([LocalGet(local)]);
+            }
+            Op::Capture(index) => {
+                let offset = 16 + 4 * *index as u32;
+                self.emit([LocalGet(0), I32Load(offset), Num(NumOp::I64ExtendI32U)]);
+            }
+            // assigned by the predecessors
+            Op::Phi(_) => return Ok(()),
+            Op::Copy(source) => self.get(*source),
+            Op::Unary(op, operand) => {
+                self.get(*operand);
+                self.call(match op {
+                    UnaryOp::Bang => Rt::Not,
+                    UnaryOp::Negate => Rt::Neg,
+                });
+            }
+            Op::Binary(op, lhs, rhs) => {
+                self.get(*lhs);
+                self.get(*rhs);
+                self.call(binary_routine(*op));
+            }
+            Op::LoadGlobal(index) if *index < self.program.natives => {
+                let name = &self.program.globals[*index];
+                return Err(unsupported(&format!("the native '{}'", name)));
+            }
+            Op::LoadGlobal(index) => {
+                let local = self.local(value);
+                self.emit([
+                    GlobalGet(runtime::GLOBALS + *index as u32),
+                    LocalTee(local),
+                    I64Const(runtime::TAG_UNDEFINED << 32),
+                    Num(NumOp::I64Eq),
+                    If(BlockType::Empty),
+                ]);
+                let name = &self.program.globals[*index];
+                self.error(&format!("undefined variable '{}'", name));
+                self.emit([End]);
+                return Ok(());
+            }
+            Op::StoreGlobal(index, source) => {
+                self.get(*source);
+                self.emit([GlobalSet(runtime::GLOBALS + *index as u32)]);
+            }
+            Op::NewCell(initial) => {
+                let scratch = self.scratch();
+                self.emit([I32Const(8)]);
+                self.call(Rt::Alloc);
+                self.emit([LocalTee(scratch)]);
+                self.get(*initial);
+                self.emit([I64Store(0), LocalGet(scratch), Num(NumOp::I64ExtendI32U)]);
+            }
+            Op::LoadCell(cell) => {
+                self.address(*cell);
+                self.emit([I64Load(0)]);
+            }
+            Op::StoreCell(cell, source) => {
+                self.address(*cell);
+                self.get(*source);
+                self.emit([I64Store(0)]);
+            }
+            Op::Closure(func, cells) => self.closure(*func, cells),
+            Op::Call(callee, args) => self.call_value(*callee, args),
+            Op::Print(operand) => {
+                self.get(*operand);
+                self.call(Rt::Print);
+            }
+            Op::Class(name, methods) => self.class(name, methods),
+            Op::GetProp(object, name) => {
+                self.get(*object);
+                let name = self.data.string(name);
+                self.emit([I32Const(name as i32)]);
+                self.call(Rt::Get);
+            }
+            Op::SetProp(object, name, source) => {
+                self.get(*object);
+                let name = self.data.string(name);
+                self.emit([I32Const(name as i32)]);
+                self.get(*source);
+                self.call(Rt::Set);
+            }
+            Op::List(_)
+            | Op::Map(_)
+            | Op::GetIndex(..)
+            | Op::SetIndex(..)
+            | Op::Len(_)
+            | Op::Element(..) => return Err(unsupported("lists and maps")),
+            Op::Caught | Op::EndTry => return Err(unsupported("exceptions")),
+        }
+        if self.func.op(value).has_value() {
+            let local = self.local(value);
+            self.emit([LocalSet(local)]);
+        }
+        Ok(())
+    }
+
+    /// closures without cells are static data; the others are allocated with their cells.
+    fn closure(&mut self, func: FuncId, cells: &[ValueId]) {
+        use Instr::*;
+        let callee = &self.program.functions[func.0];
+        if cells.is_empty() {
+            let address = self
+                .data
+                .closure(func.0 as u32, callee.params, display_name(callee));
+            self.emit([I64Const(runtime::make(runtime::TAG_FN, address))]);
+            return;
+        }
+        let scratch = self.scratch();
+        let name = self.data.string(display_name(callee));
+        self.emit([I32Const(16 + 4 * cells.len() as i32)]);
+        self.call(Rt::Alloc);
+        self.emit([LocalSet(scratch)]);
+        for (offset, word) in [
+            (0, func.0 as i32),
+            (4, callee.params as i32),
+            (8, name as i32),
+            (12, cells.len() as i32),
+        ] {
+            self.emit([LocalGet(scratch), I32Const(word), I32Store(offset)]);
+        }
+        for (i, cell) in cells.iter().enumerate() {
+            self.emit([LocalGet(scratch)]);
+            self.address(*cell);
+            self.emit([I32Store(16 + 4 * i as u32)]);
+        }
+        self.emit([I32Const(runtime::TAG_FN as i32), LocalGet(scratch)]);
+        self.call(Rt::Make);
+    }
+
+    /// calls through the closure `Callable` finds, passing the receiver it leaves, if any, first.
+    /// Calling a class without `init` just makes the instance.
+    fn call_value(&mut self, callee: ValueId, args: &[ValueId]) {
+        use Instr::*;
+        let value = BlockType::Value(ValType::I64);
+        let scratch = self.scratch();
+        self.get(callee);
+        self.emit([I32Const(args.len() as i32)]);
+        self.call(Rt::Callable);
+        self.emit([
+            LocalSet(scratch),
+            GlobalGet(runtime::RECEIVER),
+            Num(NumOp::I64Eqz),
+            If(value),
+            LocalGet(scratch),
+        ]);
+        for arg in args {
+            self.get(*arg);
+        }
+        let ty = function_type(self.module, args.len());
+        self.emit([
+            LocalGet(scratch),
+            I32Load(0),
+            CallIndirect(ty),
+            Else,
+            LocalGet(scratch),
+            Num(NumOp::I32Eqz),
+            If(value),
+            GlobalGet(runtime::RECEIVER),
+            Else,
+            LocalGet(scratch),
+            GlobalGet(runtime::RECEIVER),
+        ]);
+        for arg in args {
+            self.get(*arg);
+        }
+        let ty = function_type(self.module, args.len() + 1);
+        self.emit([LocalGet(scratch), I32Load(0), CallIndirect(ty), End, End]);
+    }
+
+    /// the class's name and methods, each method's closure by address.
+    fn class(&mut self, name: &str, methods: &[(String, ValueId)]) {
+        use Instr::*;
+        let scratch = self.scratch();
+        let name = self.data.string(name);
+        self.emit([I32Const(8 + 8 * methods.len() as i32)]);
+        self.call(Rt::Alloc);
+        self.emit([
+            LocalTee(scratch),
+            I32Const(name as i32),
+            I32Store(0),
+            LocalGet(scratch),
+            I32Const(methods.len() as i32),
+            I32Store(4),
+        ]);
+        for (i, (name, method)) in methods.iter().enumerate() {
+            let name = self.data.string(name);
+            let offset = 8 + 8 * i as u32;
+            self.emit([LocalGet(scratch), I32Const(name as i32), I32Store(offset)]);
+            self.emit([LocalGet(scratch)]);
+            self.address(*method);
+            self.emit([I32Store(offset + 4)]);
+        }
+        self.emit([I32Const(runtime::TAG_CLASS as i32), LocalGet(scratch)]);
+        self.call(Rt::Make);
+    }
+
+    fn terminator(&mut self, block: BlockId) -> Result<(), BackendErr> {
+        use Instr::*;
+        match &self.func.blocks[block.0].term {
+            Terminator::Jump(target) => {
+                self.phi_moves(block, *target);
+                self.jump(block, *target, 0);
+            }
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => {
+                self.get(*cond);
+                self.call(Rt::Truthy);
+                self.emit([If(BlockType::Empty)]);
+                self.phi_moves(block, *then);
+                self.jump(block, *then, 1);
+                self.emit([Else]);
+                self.phi_moves(block, *otherwise);
+                self.jump(block, *otherwise, 1);
+                self.emit([End]);
+            }
+            Terminator::Return(value) => {
+                self.get(*value);
+                self.emit([Return]);
+            }
+            Terminator::Unreachable => self.emit([Unreachable]),
+            Terminator::Throw(_) | Terminator::Try { .. } => return Err(unsupported("exceptions")),
+        }
+        Ok(())
+    }
+
+    /// jumps from the end of `from`, inside `nesting` more blocks than its code starts in.
+    fn jump(&mut self, from: BlockId, to: BlockId, nesting: u32) {
+        if to.0 == from.0 + 1 {
+            return;
+        }
+        // the blocks of those after `from` are still open
+        let depth = (self.func.blocks.len() - 1 - from.0) as u32 + nesting;
+        let pc = self.pc();
+        self.emit([
+            Instr::I32Const(to.0 as i32),
+            Instr::LocalSet(pc),
+            Instr::Br(depth),
+        ]);
+    }
+
+    /// assigns the `phi`s of `to` the values they take coming from `from`. All of them are read
+    /// onto the stack before any is written, since one `phi` may be another's operand.
+    fn phi_moves(&mut self, from: BlockId, to: BlockId) {
+        let mut phis = Vec::new();
+        for value in &self.func.blocks[to.0].insts {
+            let Op::Phi(incoming) = self.func.op(*value) else {
+                break;
+            };
+            if let Some((_, source)) = incoming.iter().find(|(pred, _)| *pred == from) {
+                self.get(*source);
+                phis.push(*value);
+            }
+        }
+        for phi in phis.into_iter().rev() {
+            let local = self.local(phi);
+            self.emit([Instr::LocalSet(local)]);
+        }
+    }
+}
+
+fn binary_routine(op: BinaryOp) -> Rt {
+    match op {
+        BinaryOp::Plus => Rt::Add,
+        BinaryOp::Minus => Rt::Sub,
+        BinaryOp::Mult => Rt::Mul,
+        BinaryOp::Div => Rt::Div,
+        BinaryOp::EqEq => Rt::Eq,
+        BinaryOp::BangEq => Rt::Ne,
+        BinaryOp::Lt => Rt::Lt,
+        BinaryOp::LtEq => Rt::Le,
+        BinaryOp::Gt => Rt::Gt,
+        BinaryOp::GtEq => Rt::Ge,
+        BinaryOp::Eq => unreachable!("assignment is not an operator in the IR"),
+    }
+}
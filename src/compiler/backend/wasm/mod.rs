@@ -0,0 +1,16 @@
+//! WebAssembly for browsers and other wasm hosts: the IR is translated to a module with its own
+//! runtime, which only imports output and error reporting from the host. `run.js` is such a host
+//! for node, running a module with `node run.js program.wasm`.
+
+mod codegen;
+pub mod module;
+mod runtime;
+#[cfg(test)]
+mod tests;
+
+use crate::compiler::{backend::BackendErr, ir};
+
+/// a module running the program, to be encoded or printed as text.
+pub fn compile(program: &ir::Module) -> Result<module::Module, BackendErr> {
+    codegen::generate(program)
+}
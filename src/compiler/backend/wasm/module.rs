@@ -0,0 +1,734 @@
+//! A WebAssembly module in memory, encoded to the binary format or printed in the text format.
+//! Only the parts the backend uses are modelled: function imports, one table, one memory, globals
+//! with constant initialisers and active data segments.
+
+use crate::util::leb128;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl ValType {
+    fn code(self) -> u8 {
+        match self {
+            ValType::I32 => 0x7f,
+            ValType::I64 => 0x7e,
+            ValType::F32 => 0x7d,
+            ValType::F64 => 0x7c,
+        }
+    }
+}
+
+impl std::fmt::Display for ValType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ValType::I32 => "i32",
+            ValType::I64 => "i64",
+            ValType::F32 => "f32",
+            ValType::F64 => "f64",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+/// what a `block`, `loop` or `if` leaves on the stack.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BlockType {
+    Empty,
+    Value(ValType),
+}
+
+/// instructions without immediates.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NumOp {
+    I32Eqz,
+    I32Eq,
+    I32Ne,
+    I32LtS,
+    I32GtS,
+    I32GtU,
+    I32LeS,
+    I32LeU,
+    I32GeS,
+    I32GeU,
+    I32Add,
+    I32Sub,
+    I32And,
+    I32Or,
+    I32Shl,
+    I32ShrU,
+    I64Eqz,
+    I64Eq,
+    I64Ne,
+    I64LtS,
+    I64Add,
+    I64Sub,
+    I64Mul,
+    I64DivS,
+    I64DivU,
+    I64RemU,
+    I64Or,
+    I64Xor,
+    I64Shl,
+    I64ShrU,
+    F32Eq,
+    F32Lt,
+    F32Gt,
+    F32Le,
+    F32Ge,
+    F32Neg,
+    F32Add,
+    F32Sub,
+    F32Mul,
+    F32Div,
+    I32WrapI64,
+    I64ExtendI32S,
+    I64ExtendI32U,
+    F32ConvertI32S,
+    F64PromoteF32,
+    I32ReinterpretF32,
+    F32ReinterpretI32,
+    /// `f32` to `i32`, saturating instead of trapping when out of range like Rust's `as`.
+    I32TruncSatF32S,
+}
+
+impl NumOp {
+    fn opcode(self) -> &'static [u8] {
+        use NumOp::*;
+        match self {
+            I32Eqz => &[0x45],
+            I32Eq => &[0x46],
+            I32Ne => &[0x47],
+            I32LtS => &[0x48],
+            I32GtS => &[0x4a],
+            I32GtU => &[0x4b],
+            I32LeS => &[0x4c],
+            I32LeU => &[0x4d],
+            I32GeS => &[0x4e],
+            I32GeU => &[0x4f],
+            I64Eqz => &[0x50],
+            I64Eq => &[0x51],
+            I64Ne => &[0x52],
+            I64LtS => &[0x53],
+            F32Eq => &[0x5b],
+            F32Lt => &[0x5d],
+            F32Gt => &[0x5e],
+            F32Le => &[0x5f],
+            F32Ge => &[0x60],
+            I32Add => &[0x6a],
+            I32Sub => &[0x6b],
+            I32And => &[0x71],
+            I32Or => &[0x72],
+            I32Shl => &[0x74],
+            I32ShrU => &[0x76],
+            I64Add => &[0x7c],
+            I64Sub => &[0x7d],
+            I64Mul => &[0x7e],
+            I64DivS => &[0x7f],
+            I64DivU => &[0x80],
+            I64RemU => &[0x82],
+            I64Or => &[0x84],
+            I64Xor => &[0x85],
+            I64Shl => &[0x86],
+            I64ShrU => &[0x88],
+            F32Neg => &[0x8c],
+            F32Add => &[0x92],
+            F32Sub => &[0x93],
+            F32Mul => &[0x94],
+            F32Div => &[0x95],
+            I32WrapI64 => &[0xa7],
+            I64ExtendI32S => &[0xac],
+            I64ExtendI32U => &[0xad],
+            F32ConvertI32S => &[0xb2],
+            F64PromoteF32 => &[0xbb],
+            I32ReinterpretF32 => &[0xbc],
+            F32ReinterpretI32 => &[0xbe],
+            I32TruncSatF32S => &[0xfc, 0x00],
+        }
+    }
+    fn name(self) -> &'static str {
+        use NumOp::*;
+        match self {
+            I32Eqz => "i32.eqz",
+            I32Eq => "i32.eq",
+            I32Ne => "i32.ne",
+            I32LtS => "i32.lt_s",
+            I32GtS => "i32.gt_s",
+            I32GtU => "i32.gt_u",
+            I32LeS => "i32.le_s",
+            I32LeU => "i32.le_u",
+            I32GeS => "i32.ge_s",
+            I32GeU => "i32.ge_u",
+            I32Add => "i32.add",
+            I32Sub => "i32.sub",
+            I32And => "i32.and",
+            I32Or => "i32.or",
+            I32Shl => "i32.shl",
+            I32ShrU => "i32.shr_u",
+            I64Eqz => "i64.eqz",
+            I64Eq => "i64.eq",
+            I64Ne => "i64.ne",
+            I64LtS => "i64.lt_s",
+            I64Add => "i64.add",
+            I64Sub => "i64.sub",
+            I64Mul => "i64.mul",
+            I64DivS => "i64.div_s",
+            I64DivU => "i64.div_u",
+            I64RemU => "i64.rem_u",
+            I64Or => "i64.or",
+            I64Xor => "i64.xor",
+            I64Shl => "i64.shl",
+            I64ShrU => "i64.shr_u",
+            F32Eq => "f32.eq",
+            F32Lt => "f32.lt",
+            F32Gt => "f32.gt",
+            F32Le => "f32.le",
+            F32Ge => "f32.ge",
+            F32Neg => "f32.neg",
+            F32Add => "f32.add",
+            F32Sub => "f32.sub",
+            F32Mul => "f32.mul",
+            F32Div => "f32.div",
+            I32WrapI64 => "i32.wrap_i64",
+            I64ExtendI32S => "i64.extend_i32_s",
+            I64ExtendI32U => "i64.extend_i32_u",
+            F32ConvertI32S => "f32.convert_i32_s",
+            F64PromoteF32 => "f64.promote_f32",
+            I32ReinterpretF32 => "i32.reinterpret_f32",
+            F32ReinterpretI32 => "f32.reinterpret_i32",
+            I32TruncSatF32S => "i32.trunc_sat_f32_s",
+        }
+    }
+}
+
+/// memory accesses take a constant offset added to the address on the stack.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    Unreachable,
+    Block(BlockType),
+    Loop(BlockType),
+    If(BlockType),
+    Else,
+    End,
+    Br(u32),
+    BrIf(u32),
+    BrTable(Vec<u32>, u32),
+    Return,
+    Call(u32),
+    /// a call through the table to a function of the given type.
+    CallIndirect(u32),
+    Drop,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    I32Load(u32),
+    I64Load(u32),
+    I32Load8U(u32),
+    I32Store(u32),
+    I64Store(u32),
+    I32Store8(u32),
+    MemorySize,
+    MemoryGrow,
+    /// copies `n` bytes from `src` to `dest`, taking `dest src n` from the stack.
+    MemoryCopy,
+    I32Const(i32),
+    I64Const(i64),
+    Num(NumOp),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    pub ty: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Func {
+    /// for the text format only.
+    pub name: String,
+    pub ty: u32,
+    /// beyond the parameters.
+    pub locals: Vec<ValType>,
+    /// without the final `end`.
+    pub body: Vec<Instr>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Global {
+    pub name: String,
+    pub ty: ValType,
+    pub mutable: bool,
+    pub init: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Export {
+    Func(String, u32),
+    Memory(String),
+}
+
+/// Functions are indexed imports first, then the module's own.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Module {
+    pub types: Vec<FuncType>,
+    pub imports: Vec<Import>,
+    pub functions: Vec<Func>,
+    /// the function index of every table entry.
+    pub table: Vec<u32>,
+    /// the initial size of the memory, in 64KiB pages.
+    pub memory_pages: u32,
+    pub globals: Vec<Global>,
+    pub exports: Vec<Export>,
+    /// bytes copied into memory at these addresses.
+    pub data: Vec<(u32, Vec<u8>)>,
+}
+
+impl Module {
+    /// the index of the function type, added if the module has no such type yet.
+    pub fn ty(&mut self, params: &[ValType], results: &[ValType]) -> u32 {
+        let ty = FuncType {
+            params: params.to_vec(),
+            results: results.to_vec(),
+        };
+        let index = match self.types.iter().position(|t| *t == ty) {
+            Some(i) => i,
+            None => {
+                self.types.push(ty);
+                self.types.len() - 1
+            }
+        };
+        index as u32
+    }
+
+    /// the module in the binary format.
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = b"\0asm".to_vec();
+        out.extend(1u32.to_le_bytes());
+
+        section(&mut out, 1, &self.types, |out, ty| {
+            out.push(0x60);
+            vec(out, &ty.params, |out, v| out.push(v.code()));
+            vec(out, &ty.results, |out, v| out.push(v.code()));
+        });
+        section(&mut out, 2, &self.imports, |out, import| {
+            name(out, &import.module);
+            name(out, &import.name);
+            out.push(0x00);
+            leb128::write_unsigned(out, import.ty as u64);
+        });
+        section(&mut out, 3, &self.functions, |out, func| {
+            leb128::write_unsigned(out, func.ty as u64)
+        });
+        section(&mut out, 4, &[self.table.len()], |out, size| {
+            out.extend([0x70, 0x00]);
+            leb128::write_unsigned(out, *size as u64);
+        });
+        section(&mut out, 5, &[self.memory_pages], |out, pages| {
+            out.push(0x00);
+            leb128::write_unsigned(out, *pages as u64);
+        });
+        section(&mut out, 6, &self.globals, |out, global| {
+            out.extend([global.ty.code(), global.mutable as u8]);
+            let init = match global.ty {
+                ValType::I64 => Instr::I64Const(global.init),
+                _ => Instr::I32Const(global.init as i32),
+            };
+            instr(out, &init);
+            out.push(0x0b);
+        });
+        section(&mut out, 7, &self.exports, |out, export| match export {
+            Export::Func(n, index) => {
+                name(out, n);
+                out.push(0x00);
+                leb128::write_unsigned(out, *index as u64);
+            }
+            Export::Memory(n) => {
+                name(out, n);
+                out.extend([0x02, 0x00]);
+            }
+        });
+        section(&mut out, 9, &[&self.table], |out, table| {
+            out.push(0x00);
+            instr(out, &Instr::I32Const(0));
+            out.push(0x0b);
+            vec(out, table, |out, index| {
+                leb128::write_unsigned(out, *index as u64)
+            });
+        });
+        section(&mut out, 10, &self.functions, |out, func| {
+            let mut code = Vec::new();
+            // runs of locals of the same type are declared together
+            let mut runs: Vec<(u32, ValType)> = Vec::new();
+            for local in &func.locals {
+                match runs.last_mut() {
+                    Some((count, ty)) if ty == local => *count += 1,
+                    _ => runs.push((1, *local)),
+                }
+            }
+            vec(&mut code, &runs, |out, (count, ty)| {
+                leb128::write_unsigned(out, *count as u64);
+                out.push(ty.code());
+            });
+            for i in &func.body {
+                instr(&mut code, i);
+            }
+            code.push(0x0b);
+            leb128::write_unsigned(out, code.len() as u64);
+            out.extend(code);
+        });
+        section(&mut out, 11, &self.data, |out, (address, bytes)| {
+            out.push(0x00);
+            instr(out, &Instr::I32Const(*address as i32));
+            out.push(0x0b);
+            leb128::write_unsigned(out, bytes.len() as u64);
+            out.extend(bytes);
+        });
+        out
+    }
+
+    fn func_name(&self, index: u32) -> String {
+        let index = index as usize;
+        match self.imports.get(index) {
+            Some(import) => identifier(&import.name),
+            None => identifier(&self.functions[index - self.imports.len()].name),
+        }
+    }
+
+    fn write_instr(&self, f: &mut std::fmt::Formatter<'_>, i: &Instr) -> std::fmt::Result {
+        use Instr::*;
+        let block_type = |ty: &BlockType| match ty {
+            BlockType::Empty => String::new(),
+            BlockType::Value(v) => format!(" (result {})", v),
+        };
+        let offset = |offset: &u32| match offset {
+            0 => String::new(),
+            n => format!(" offset={}", n),
+        };
+        match i {
+            Unreachable => write!(f, "unreachable"),
+            Block(ty) => write!(f, "block{}", block_type(ty)),
+            Loop(ty) => write!(f, "loop{}", block_type(ty)),
+            If(ty) => write!(f, "if{}", block_type(ty)),
+            Else => write!(f, "else"),
+            End => write!(f, "end"),
+            Br(depth) => write!(f, "br {}", depth),
+            BrIf(depth) => write!(f, "br_if {}", depth),
+            BrTable(depths, default) => {
+                write!(f, "br_table")?;
+                for depth in depths {
+                    write!(f, " {}", depth)?;
+                }
+                write!(f, " {}", default)
+            }
+            Return => write!(f, "return"),
+            Call(index) => write!(f, "call ${}", self.func_name(*index)),
+            CallIndirect(ty) => write!(f, "call_indirect (type {})", ty),
+            Drop => write!(f, "drop"),
+            LocalGet(index) => write!(f, "local.get {}", index),
+            LocalSet(index) => write!(f, "local.set {}", index),
+            LocalTee(index) => write!(f, "local.tee {}", index),
+            GlobalGet(index) => write!(
+                f,
+                "global.get ${}",
+                identifier(&self.globals[*index as usize].name)
+            ),
+            GlobalSet(index) => write!(
+                f,
+                "global.set ${}",
+                identifier(&self.globals[*index as usize].name)
+            ),
+            I32Load(o) => write!(f, "i32.load{}", offset(o)),
+            I64Load(o) => write!(f, "i64.load{}", offset(o)),
+            I32Load8U(o) => write!(f, "i32.load8_u{}", offset(o)),
+            I32Store(o) => write!(f, "i32.store{}", offset(o)),
+            I64Store(o) => write!(f, "i64.store{}", offset(o)),
+            I32Store8(o) => write!(f, "i32.store8{}", offset(o)),
+            MemorySize => write!(f, "memory.size"),
+            MemoryGrow => write!(f, "memory.grow"),
+            MemoryCopy => write!(f, "memory.copy"),
+            I32Const(n) => write!(f, "i32.const {}", n),
+            I64Const(n) => write!(f, "i64.const {}", n),
+            Num(op) => write!(f, "{}", op.name()),
+        }
+    }
+}
+
+/// the module in the text format.
+impl std::fmt::Display for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "(module")?;
+        for (i, ty) in self.types.iter().enumerate() {
+            write!(f, "  (type {} (func", i)?;
+            write_types(f, "param", &ty.params)?;
+            write_types(f, "result", &ty.results)?;
+            writeln!(f, "))")?;
+        }
+        for import in &self.imports {
+            writeln!(
+                f,
+                "  (import {:?} {:?} (func ${} (type {})))",
+                import.module,
+                import.name,
+                identifier(&import.name),
+                import.ty
+            )?;
+        }
+        writeln!(f, "  (table {} funcref)", self.table.len())?;
+        writeln!(f, "  (memory {})", self.memory_pages)?;
+        for global in &self.globals {
+            let ty = match global.mutable {
+                true => format!("(mut {})", global.ty),
+                false => global.ty.to_string(),
+            };
+            let init = match global.ty {
+                ValType::I64 => "i64",
+                _ => "i32",
+            };
+            writeln!(
+                f,
+                "  (global ${} {} ({}.const {}))",
+                identifier(&global.name),
+                ty,
+                init,
+                global.init
+            )?;
+        }
+        for export in &self.exports {
+            match export {
+                Export::Func(name, index) => writeln!(
+                    f,
+                    "  (export {:?} (func ${}))",
+                    name,
+                    self.func_name(*index)
+                )?,
+                Export::Memory(name) => writeln!(f, "  (export {:?} (memory 0))", name)?,
+            }
+        }
+        write!(f, "  (elem (i32.const 0) func")?;
+        for index in &self.table {
+            write!(f, " ${}", self.func_name(*index))?;
+        }
+        writeln!(f, ")")?;
+        for func in &self.functions {
+            write!(f, "  (func ${} (type {})", identifier(&func.name), func.ty)?;
+            let ty = &self.types[func.ty as usize];
+            write_types(f, "param", &ty.params)?;
+            write_types(f, "result", &ty.results)?;
+            writeln!(f)?;
+            if !func.locals.is_empty() {
+                write!(f, "   ")?;
+                write_types(f, "local", &func.locals)?;
+                writeln!(f)?;
+            }
+            let mut depth = 2;
+            for i in &func.body {
+                if matches!(i, Instr::Else | Instr::End) {
+                    depth -= 1;
+                }
+                write!(f, "{:1$}", "", 2 * depth)?;
+                self.write_instr(f, i)?;
+                writeln!(f)?;
+                if matches!(
+                    i,
+                    Instr::Block(_) | Instr::Loop(_) | Instr::If(_) | Instr::Else
+                ) {
+                    depth += 1;
+                }
+            }
+            writeln!(f, "  )")?;
+        }
+        for (address, bytes) in &self.data {
+            write!(f, "  (data (i32.const {}) \"", address)?;
+            for b in bytes {
+                match b {
+                    b'"' | b'\\' => write!(f, "\\{:02x}", b)?,
+                    0x20..=0x7e => write!(f, "{}", *b as char)?,
+                    _ => write!(f, "\\{:02x}", b)?,
+                }
+            }
+            writeln!(f, "\")")?;
+        }
+        write!(f, ")")
+    }
+}
+
+fn write_types(
+    f: &mut std::fmt::Formatter<'_>,
+    keyword: &str,
+    types: &[ValType],
+) -> std::fmt::Result {
+    if types.is_empty() {
+        return Ok(());
+    }
+    write!(f, " ({}", keyword)?;
+    for ty in types {
+        write!(f, " {}", ty)?;
+    }
+    write!(f, ")")
+}
+
+/// `name` with the characters the text format does not allow in identifiers replaced.
+fn identifier(name: &str) -> String {
+    name.chars()
+        .map(|c| match c {
+            c if c.is_ascii_alphanumeric() => c,
+            '!' | '#' | '$' | '%' | '&' | '\'' | '*' | '+' | '-' | '.' | '/' | ':' | '<' | '='
+            | '>' | '?' | '@' | '\\' | '^' | '_' | '`' | '|' | '~' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+fn section<T>(out: &mut Vec<u8>, id: u8, items: &[T], item: impl Fn(&mut Vec<u8>, &T)) {
+    if items.is_empty() {
+        return;
+    }
+    let mut contents = Vec::new();
+    vec(&mut contents, items, item);
+    out.push(id);
+    leb128::write_unsigned(out, contents.len() as u64);
+    out.extend(contents);
+}
+
+fn vec<T>(out: &mut Vec<u8>, items: &[T], item: impl Fn(&mut Vec<u8>, &T)) {
+    leb128::write_unsigned(out, items.len() as u64);
+    for i in items {
+        item(out, i);
+    }
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+    leb128::write_unsigned(out, name.len() as u64);
+    out.extend(name.as_bytes());
+}
+
+fn block_type(out: &mut Vec<u8>, ty: &BlockType) {
+    match ty {
+        BlockType::Empty => out.push(0x40),
+        BlockType::Value(v) => out.push(v.code()),
+    }
+}
+
+/// loads and stores declare the natural alignment of what they access.
+fn memarg(out: &mut Vec<u8>, align: u8, offset: u32) {
+    out.push(align);
+    leb128::write_unsigned(out, offset as u64);
+}
+
+fn uleb(out: &mut Vec<u8>, value: u32) {
+    leb128::write_unsigned(out, value as u64)
+}
+
+pub fn instr(out: &mut Vec<u8>, i: &Instr) {
+    use Instr::*;
+    match i {
+        Unreachable => out.push(0x00),
+        Block(ty) => {
+            out.push(0x02);
+            block_type(out, ty);
+        }
+        Loop(ty) => {
+            out.push(0x03);
+            block_type(out, ty);
+        }
+        If(ty) => {
+            out.push(0x04);
+            block_type(out, ty);
+        }
+        Else => out.push(0x05),
+        End => out.push(0x0b),
+        Br(depth) => {
+            out.push(0x0c);
+            uleb(out, *depth);
+        }
+        BrIf(depth) => {
+            out.push(0x0d);
+            uleb(out, *depth);
+        }
+        BrTable(depths, default) => {
+            out.push(0x0e);
+            vec(out, depths, |out, depth| uleb(out, *depth));
+            uleb(out, *default);
+        }
+        Return => out.push(0x0f),
+        Call(index) => {
+            out.push(0x10);
+            uleb(out, *index);
+        }
+        CallIndirect(ty) => {
+            out.push(0x11);
+            uleb(out, *ty);
+            out.push(0x00);
+        }
+        Drop => out.push(0x1a),
+        LocalGet(index) => {
+            out.push(0x20);
+            uleb(out, *index);
+        }
+        LocalSet(index) => {
+            out.push(0x21);
+            uleb(out, *index);
+        }
+        LocalTee(index) => {
+            out.push(0x22);
+            uleb(out, *index);
+        }
+        GlobalGet(index) => {
+            out.push(0x23);
+            uleb(out, *index);
+        }
+        GlobalSet(index) => {
+            out.push(0x24);
+            uleb(out, *index);
+        }
+        I32Load(offset) => {
+            out.push(0x28);
+            memarg(out, 2, *offset);
+        }
+        I64Load(offset) => {
+            out.push(0x29);
+            memarg(out, 3, *offset);
+        }
+        I32Load8U(offset) => {
+            out.push(0x2d);
+            memarg(out, 0, *offset);
+        }
+        I32Store(offset) => {
+            out.push(0x36);
+            memarg(out, 2, *offset);
+        }
+        I64Store(offset) => {
+            out.push(0x37);
+            memarg(out, 3, *offset);
+        }
+        I32Store8(offset) => {
+            out.push(0x3a);
+            memarg(out, 0, *offset);
+        }
+        MemorySize => out.extend([0x3f, 0x00]),
+        MemoryGrow => out.extend([0x40, 0x00]),
+        MemoryCopy => out.extend([0xfc, 0x0a, 0x00, 0x00]),
+        I32Const(n) => {
+            out.push(0x41);
+            leb128::write_signed(out, *n as i64);
+        }
+        I64Const(n) => {
+            out.push(0x42);
+            leb128::write_signed(out, *n);
+        }
+        Num(op) => out.extend(op.opcode()),
+    }
+}
@@ -0,0 +1,45 @@
+// Runs a module built for the wasm32 target under node: `node run.js program.wasm`.
+"use strict";
+const fs = require("fs");
+
+let memory;
+const bytes = (ptr, len) => Buffer.from(memory.buffer, ptr, len);
+
+// as Rust prints an f32: the shortest digits that read back as the same number, never with an
+// exponent
+function formatFloat(x) {
+  if (Number.isNaN(x)) return "NaN";
+  if (!Number.isFinite(x)) return x > 0 ? "inf" : "-inf";
+  if (x === 0) return Object.is(x, -0) ? "-0" : "0";
+  let precise;
+  for (let digits = 1; digits <= 9; digits++) {
+    precise = x.toPrecision(digits);
+    if (Math.fround(Number(precise)) === x) break;
+  }
+  const sign = x < 0 ? "-" : "";
+  const [mantissa, exponent] = precise.replace("-", "").split("e");
+  const dot = mantissa.indexOf(".");
+  let point = (dot < 0 ? mantissa.length : dot) + Number(exponent || 0);
+  let digits = mantissa.replace(".", "");
+  while (digits.length > 1 && digits[0] === "0") {
+    digits = digits.slice(1);
+    point--;
+  }
+  if (point <= 0) return sign + "0." + "0".repeat(-point) + digits.replace(/0+$/, "");
+  if (point >= digits.length) return sign + digits + "0".repeat(point - digits.length);
+  return sign + digits.slice(0, point) + ("." + digits.slice(point)).replace(/\.?0+$/, "");
+}
+
+const env = {
+  write: (ptr, len) => fs.writeSync(1, bytes(ptr, len)),
+  write_float: (x) => fs.writeSync(1, formatFloat(x)),
+  fail: (ptr, len) => {
+    fs.writeSync(2, "error: " + bytes(ptr, len).toString() + "\n");
+    process.exit(1);
+  },
+};
+
+WebAssembly.instantiate(fs.readFileSync(process.argv[2]), { env }).then(({ instance }) => {
+  memory = instance.exports.memory;
+  instance.exports.main(0);
+});
@@ -0,0 +1,1165 @@
+//! The runtime of compiled modules: the operators, printing, string concatenation, allocation and
+//! runtime errors, as functions added to every module. The host only provides output, through the
+//! imports of the "env" module.
+//!
+//! A value is an `i64`: a type tag in the upper half and the payload in the lower one, an `int`, the
+//! bits of a `float`, or the address in linear memory of a string object or closure. Address 0 is
+//! never used. String objects are an `i32` length followed by the bytes; closures are the table
+//! index of their function, its arity, the address of its name and the number of cells, followed by
+//! the cells' addresses. A cell is 8 bytes holding a value. Memory is allocated by bumping a pointer
+//! and never freed.
+//!
+//! A class is the address of its name and its number of methods, followed by each method's name
+//! and closure. An instance is its class, its number of fields, their capacity and the address of
+//! the fields, 16 bytes each: the name, then the value at offset 8. A bound method is its closure
+//! and, at offset 8, the instance. Property names are the program's string constants, which are
+//! stored once, so their addresses are compared rather than their bytes.
+
+use std::collections::HashMap;
+
+use crate::compiler::{
+    backend::wasm::module::{BlockType, Func, Instr, Module, NumOp, ValType},
+    eval::EvalErr,
+};
+
+pub const TAG_BOOL: i64 = 1;
+pub const TAG_INT: i64 = 2;
+pub const TAG_FLOAT: i64 = 3;
+pub const TAG_STR: i64 = 4;
+pub const TAG_FN: i64 = 5;
+pub const TAG_CLASS: i64 = 6;
+/// a global that has not been defined yet.
+pub const TAG_UNDEFINED: i64 = 7;
+pub const TAG_INSTANCE: i64 = 8;
+/// a bound method.
+pub const TAG_METHOD: i64 = 9;
+
+pub const NIL: i64 = 0;
+pub const FALSE: i64 = TAG_BOOL << 32;
+pub const TRUE: i64 = TAG_BOOL << 32 | 1;
+
+pub fn make(tag: i64, payload: u32) -> i64 {
+    tag << 32 | payload as i64
+}
+
+/// the imported functions, which come first in the function index space.
+pub mod import {
+    /// `write(ptr: i32, len: i32)` writes the bytes to standard output.
+    pub const WRITE: u32 = 0;
+    /// `write_float(x: f64)` writes the number as the interpreter prints a `float`.
+    pub const WRITE_FLOAT: u32 = 1;
+    /// `fail(ptr: i32, len: i32)` reports the message as a runtime error and stops the program.
+    pub const FAIL: u32 = 2;
+    pub const COUNT: u32 = 3;
+}
+
+/// global 0 is the allocation pointer, then the receiver, then the program's globals.
+pub const HEAP_PTR: u32 = 0;
+/// the instance `Callable` found for the call it was asked about, or `nil`.
+pub const RECEIVER: u32 = 1;
+pub const GLOBALS: u32 = 2;
+
+/// where static data starts, leaving address 0 unused.
+const DATA_START: u32 = 8;
+
+/// the runtime's functions, in the order they are added after the program's.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Rt {
+    /// `(value) -> i32`
+    Tag,
+    /// `(tag, payload: i32) -> value`
+    Make,
+    /// `(message: i32)`, the address of a string object.
+    Fail,
+    /// `(size: i32) -> i32`, 8-byte aligned.
+    Alloc,
+    /// `(value) -> i32`
+    Truthy,
+    /// `(value) -> i32`, whether it is an `int` or a `float`.
+    IsNum,
+    /// `(value) -> f32`, a number as a float.
+    Num,
+    /// `(f32) -> value`
+    Float,
+    /// `(i64) -> value`, failing if it does not fit an `int`.
+    Int,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+    Not,
+    Neg,
+    /// `(a: i32, b: i32) -> i32`, whether two string objects have the same bytes.
+    StrEq,
+    /// `(value, value) -> value`
+    Concat,
+    /// `(i32) -> value`, the decimal string of an `int`.
+    Itoa,
+    /// `(string: i32)`
+    WriteStr,
+    Print,
+    /// `(expected: i32, got: i32)`, reporting a call with the wrong number of arguments.
+    Arity,
+    /// `(callee: value, arguments: i32) -> i32`, the closure a call goes to, failing if the callee
+    /// is not callable or takes another number of arguments. A bound method's instance, or the new
+    /// one when calling a class, is left in `RECEIVER` to be passed first. A class without `init`
+    /// has no closure to call, so it is 0.
+    Callable,
+    /// `(class: i32, name: i32) -> i32`, the closure of the class's method, or 0.
+    Method,
+    /// `(value, name: i32) -> value`
+    Get,
+    /// `(value, name: i32, value)`
+    Set,
+}
+
+const ALL: [Rt; 31] = [
+    Rt::Tag,
+    Rt::Make,
+    Rt::Fail,
+    Rt::Alloc,
+    Rt::Truthy,
+    Rt::IsNum,
+    Rt::Num,
+    Rt::Float,
+    Rt::Int,
+    Rt::Add,
+    Rt::Sub,
+    Rt::Mul,
+    Rt::Div,
+    Rt::Lt,
+    Rt::Le,
+    Rt::Gt,
+    Rt::Ge,
+    Rt::Eq,
+    Rt::Ne,
+    Rt::Not,
+    Rt::Neg,
+    Rt::StrEq,
+    Rt::Concat,
+    Rt::Itoa,
+    Rt::WriteStr,
+    Rt::Print,
+    Rt::Arity,
+    Rt::Callable,
+    Rt::Method,
+    Rt::Get,
+    Rt::Set,
+];
+
+/// where the runtime's functions are, given how many the program has.
+#[derive(Debug, Clone, Copy)]
+pub struct Layout {
+    pub functions: u32,
+}
+
+impl Layout {
+    pub fn rt(&self, rt: Rt) -> u32 {
+        import::COUNT + self.functions + rt as u32
+    }
+}
+
+/// The static data of a module: strings, each stored once, and closures without cells.
+#[derive(Debug, Default)]
+pub struct Data {
+    bytes: Vec<u8>,
+    strings: HashMap<String, u32>,
+}
+
+impl Data {
+    fn reserve(&mut self, bytes: &[u8]) -> u32 {
+        while !self.bytes.len().is_multiple_of(4) {
+            self.bytes.push(0);
+        }
+        let address = DATA_START + self.bytes.len() as u32;
+        self.bytes.extend(bytes);
+        address
+    }
+    /// the address of a string object holding `s`.
+    pub fn string(&mut self, s: &str) -> u32 {
+        if let Some(address) = self.strings.get(s) {
+            return *address;
+        }
+        let mut bytes = (s.len() as u32).to_le_bytes().to_vec();
+        bytes.extend(s.as_bytes());
+        let address = self.reserve(&bytes);
+        self.strings.insert(s.to_owned(), address);
+        address
+    }
+    /// the address of a closure without cells.
+    pub fn closure(&mut self, table_index: u32, arity: usize, name: &str) -> u32 {
+        let name = self.string(name);
+        let mut bytes = Vec::new();
+        for word in [table_index, arity as u32, name, 0] {
+            bytes.extend(word.to_le_bytes());
+        }
+        self.reserve(&bytes)
+    }
+    /// the first address after the data.
+    pub fn end(&self) -> u32 {
+        DATA_START + self.bytes.len() as u32
+    }
+    pub fn into_segment(self) -> (u32, Vec<u8>) {
+        (DATA_START, self.bytes)
+    }
+}
+
+/// adds the runtime's functions to `module`, right after the program's.
+pub fn emit(module: &mut Module, layout: Layout, data: &mut Data) {
+    use ValType::*;
+    let mut gen = RtGen {
+        layout,
+        data,
+        body: Vec::new(),
+    };
+    for rt in ALL {
+        let (params, results, locals): (&[ValType], &[ValType], &[ValType]) = match rt {
+            Rt::Tag | Rt::Truthy | Rt::IsNum => (&[I64], &[I32], &[]),
+            Rt::Make => (&[I32, I32], &[I64], &[]),
+            Rt::Fail | Rt::WriteStr => (&[I32], &[], &[]),
+            Rt::Alloc => (&[I32], &[I32], &[I32]),
+            Rt::Num => (&[I64], &[F32], &[]),
+            Rt::Float => (&[F32], &[I64], &[]),
+            Rt::Int | Rt::Not | Rt::Neg => (&[I64], &[I64], &[]),
+            Rt::StrEq => (&[I32, I32], &[I32], &[I32, I32]),
+            Rt::Concat => (&[I64, I64], &[I64], &[I32, I32, I32, I32, I32]),
+            Rt::Itoa => (&[I32], &[I64], &[I32, I32, I32, I64]),
+            Rt::Print => (&[I64], &[], &[I32]),
+            Rt::Arity => (&[I32, I32], &[], &[]),
+            Rt::Callable => (&[I64, I32], &[I32], &[I32, I32, I32]),
+            Rt::Method => (&[I32, I32], &[I32], &[I32, I32]),
+            Rt::Get => (&[I64, I32], &[I64], &[I32, I32, I32, I32]),
+            Rt::Set => (&[I64, I32, I64], &[], &[I32, I32, I32, I32]),
+            _ => (&[I64, I64], &[I64], &[]),
+        };
+        match rt {
+            Rt::Tag => gen.tag(),
+            Rt::Make => gen.make(),
+            Rt::Fail => gen.fail(),
+            Rt::Alloc => gen.alloc(),
+            Rt::Truthy => gen.truthy(),
+            Rt::IsNum => gen.is_num(),
+            Rt::Num => gen.num(),
+            Rt::Float => gen.float(),
+            Rt::Int => gen.int(),
+            Rt::Add => gen.arithmetic(NumOp::I64Add, NumOp::F32Add, EvalErr::InvalidAdd),
+            Rt::Sub => gen.arithmetic(NumOp::I64Sub, NumOp::F32Sub, EvalErr::InvalidSub),
+            Rt::Mul => gen.arithmetic(NumOp::I64Mul, NumOp::F32Mul, EvalErr::InvalidMul),
+            Rt::Div => gen.arithmetic(NumOp::I64DivS, NumOp::F32Div, EvalErr::InvalidDiv),
+            Rt::Lt => gen.comparison(NumOp::I32LtS, NumOp::F32Lt),
+            Rt::Le => gen.comparison(NumOp::I32LeS, NumOp::F32Le),
+            Rt::Gt => gen.comparison(NumOp::I32GtS, NumOp::F32Gt),
+            Rt::Ge => gen.comparison(NumOp::I32GeS, NumOp::F32Ge),
+            Rt::Eq => gen.eq(),
+            Rt::Ne => gen.ne(),
+            Rt::Not => gen.not(),
+            Rt::Neg => gen.neg(),
+            Rt::StrEq => gen.str_eq(),
+            Rt::Concat => gen.concat(),
+            Rt::Itoa => gen.itoa(),
+            Rt::WriteStr => gen.write_str(),
+            Rt::Print => gen.print(),
+            Rt::Arity => gen.arity(),
+            Rt::Callable => gen.callable(),
+            Rt::Method => gen.method(),
+            Rt::Get => gen.get(),
+            Rt::Set => gen.set(),
+        }
+        let ty = module.ty(params, results);
+        module.functions.push(Func {
+            name: format!("rt_{:?}", rt).to_lowercase(),
+            ty,
+            locals: locals.to_vec(),
+            body: std::mem::take(&mut gen.body),
+        });
+    }
+}
+
+struct RtGen<'a> {
+    layout: Layout,
+    data: &'a mut Data,
+    body: Vec<Instr>,
+}
+
+impl RtGen<'_> {
+    fn emit(&mut self, instrs: impl IntoIterator<Item = Instr>) {
+        self.body.extend(instrs);
+    }
+    fn call(&mut self, rt: Rt) {
+        let index = self.layout.rt(rt);
+        self.emit([Instr::Call(index)]);
+    }
+    /// pushes whether local `local` has the tag.
+    fn has_tag(&mut self, local: u32, tag: i64) {
+        self.emit([Instr::LocalGet(local)]);
+        self.call(Rt::Tag);
+        self.emit([Instr::I32Const(tag as i32), Instr::Num(NumOp::I32Eq)]);
+    }
+    /// pushes whether locals 0 and 1 both have the tag.
+    fn both_have_tag(&mut self, tag: i64) {
+        self.has_tag(0, tag);
+        self.has_tag(1, tag);
+        self.emit([Instr::Num(NumOp::I32And)]);
+    }
+    /// pushes the `int` in local `local`, sign-extended.
+    fn int_operand(&mut self, local: u32) {
+        self.emit([
+            Instr::LocalGet(local),
+            Instr::Num(NumOp::I32WrapI64),
+            Instr::Num(NumOp::I64ExtendI32S),
+        ]);
+    }
+    fn num_operand(&mut self, local: u32) {
+        self.emit([Instr::LocalGet(local)]);
+        self.call(Rt::Num);
+    }
+    fn error(&mut self, message: &str) {
+        let message = self.data.string(message);
+        self.emit([Instr::I32Const(message as i32)]);
+        self.call(Rt::Fail);
+        self.emit([Instr::Unreachable]);
+    }
+    /// writes a string known at compile time.
+    fn write(&mut self, s: &str) {
+        let s = self.data.string(s);
+        self.emit([Instr::I32Const(s as i32)]);
+        self.call(Rt::WriteStr);
+    }
+
+    fn tag(&mut self) {
+        self.emit([
+            Instr::LocalGet(0),
+            Instr::I64Const(32),
+            Instr::Num(NumOp::I64ShrU),
+            Instr::Num(NumOp::I32WrapI64),
+        ]);
+    }
+    fn make(&mut self) {
+        self.emit([
+            Instr::LocalGet(0),
+            Instr::Num(NumOp::I64ExtendI32U),
+            Instr::I64Const(32),
+            Instr::Num(NumOp::I64Shl),
+            Instr::LocalGet(1),
+            Instr::Num(NumOp::I64ExtendI32U),
+            Instr::Num(NumOp::I64Or),
+        ]);
+    }
+    fn fail(&mut self) {
+        self.emit([
+            Instr::LocalGet(0),
+            Instr::I32Const(4),
+            Instr::Num(NumOp::I32Add),
+            Instr::LocalGet(0),
+            Instr::I32Load(0),
+            Instr::Call(import::FAIL),
+            Instr::Unreachable,
+        ]);
+    }
+    /// grows memory by whole pages when the allocation does not fit.
+    fn alloc(&mut self) {
+        use Instr::*;
+        self.emit([
+            GlobalGet(HEAP_PTR),
+            LocalTee(1),
+            LocalGet(0),
+            Num(NumOp::I32Add),
+            I32Const(7),
+            Num(NumOp::I32Add),
+            I32Const(-8),
+            Num(NumOp::I32And),
+            GlobalSet(HEAP_PTR),
+            GlobalGet(HEAP_PTR),
+            MemorySize,
+            I32Const(16),
+            Num(NumOp::I32Shl),
+            Num(NumOp::I32GtU),
+            If(BlockType::Empty),
+            GlobalGet(HEAP_PTR),
+            MemorySize,
+            I32Const(16),
+            Num(NumOp::I32Shl),
+            Num(NumOp::I32Sub),
+            I32Const(0xffff),
+            Num(NumOp::I32Add),
+            I32Const(16),
+            Num(NumOp::I32ShrU),
+            MemoryGrow,
+            I32Const(-1),
+            Num(NumOp::I32Eq),
+            If(BlockType::Empty),
+        ]);
+        self.error("out of memory");
+        self.emit([End, End, LocalGet(1)]);
+    }
+    /// everything but `nil` and `false`.
+    fn truthy(&mut self) {
+        self.emit([
+            Instr::LocalGet(0),
+            Instr::Num(NumOp::I64Eqz),
+            Instr::LocalGet(0),
+            Instr::I64Const(FALSE),
+            Instr::Num(NumOp::I64Eq),
+            Instr::Num(NumOp::I32Or),
+            Instr::Num(NumOp::I32Eqz),
+        ]);
+    }
+    fn is_num(&mut self) {
+        self.emit([Instr::LocalGet(0)]);
+        self.call(Rt::Tag);
+        self.emit([
+            Instr::I32Const(TAG_INT as i32),
+            Instr::Num(NumOp::I32Sub),
+            Instr::I32Const(1),
+            Instr::Num(NumOp::I32LeU),
+        ]);
+    }
+    fn num(&mut self) {
+        self.has_tag(0, TAG_INT);
+        self.emit([
+            Instr::If(BlockType::Value(ValType::F32)),
+            Instr::LocalGet(0),
+            Instr::Num(NumOp::I32WrapI64),
+            Instr::Num(NumOp::F32ConvertI32S),
+            Instr::Else,
+            Instr::LocalGet(0),
+            Instr::Num(NumOp::I32WrapI64),
+            Instr::Num(NumOp::F32ReinterpretI32),
+            Instr::End,
+        ]);
+    }
+    fn float(&mut self) {
+        self.emit([
+            Instr::I32Const(TAG_FLOAT as i32),
+            Instr::LocalGet(0),
+            Instr::Num(NumOp::I32ReinterpretF32),
+        ]);
+        self.call(Rt::Make);
+    }
+    fn int(&mut self) {
+        self.int_operand(0);
+        self.emit([
+            Instr::LocalGet(0),
+            Instr::Num(NumOp::I64Ne),
+            Instr::If(BlockType::Empty),
+        ]);
+        self.error("integer overflow");
+        self.emit([
+            Instr::End,
+            Instr::I32Const(TAG_INT as i32),
+            Instr::LocalGet(0),
+            Instr::Num(NumOp::I32WrapI64),
+        ]);
+        self.call(Rt::Make);
+    }
+    /// `int`s are computed on 64 bits and checked to fit; a `float` operand makes both floats.
+    /// Strings are concatenated by `+`.
+    fn arithmetic(&mut self, int_op: NumOp, float_op: NumOp, err: EvalErr) {
+        use Instr::*;
+        let value = BlockType::Value(ValType::I64);
+        self.both_have_tag(TAG_INT);
+        self.emit([If(value)]);
+        if int_op == NumOp::I64DivS {
+            self.emit([
+                LocalGet(1),
+                Num(NumOp::I32WrapI64),
+                Num(NumOp::I32Eqz),
+                If(BlockType::Empty),
+            ]);
+            self.error("division by zero");
+            self.emit([End]);
+        }
+        self.int_operand(0);
+        self.int_operand(1);
+        self.emit([Num(int_op)]);
+        self.call(Rt::Int);
+        self.emit([Else, LocalGet(0)]);
+        self.call(Rt::IsNum);
+        self.emit([LocalGet(1)]);
+        self.call(Rt::IsNum);
+        self.emit([Num(NumOp::I32And), If(value)]);
+        self.num_operand(0);
+        self.num_operand(1);
+        self.emit([Num(float_op)]);
+        self.call(Rt::Float);
+        self.emit([Else]);
+        if int_op == NumOp::I64Add {
+            self.both_have_tag(TAG_STR);
+            self.emit([If(value), LocalGet(0), LocalGet(1)]);
+            self.call(Rt::Concat);
+            self.emit([Else]);
+            self.error(&err.to_string());
+            self.emit([End]);
+        } else {
+            self.error(&err.to_string());
+        }
+        self.emit([End, End]);
+    }
+    /// numbers only. As in the interpreter, an `int` compared with a `float` compares with the
+    /// float truncated, and a `float` with an `int` with the int converted.
+    fn comparison(&mut self, int_op: NumOp, float_op: NumOp) {
+        use Instr::*;
+        let truth = BlockType::Value(ValType::I32);
+        self.emit([I32Const(TAG_BOOL as i32)]);
+        self.both_have_tag(TAG_INT);
+        self.emit([
+            If(truth),
+            LocalGet(0),
+            Num(NumOp::I32WrapI64),
+            LocalGet(1),
+            Num(NumOp::I32WrapI64),
+            Num(int_op),
+            Else,
+            LocalGet(0),
+        ]);
+        self.call(Rt::IsNum);
+        self.emit([LocalGet(1)]);
+        self.call(Rt::IsNum);
+        self.emit([Num(NumOp::I32And), If(truth)]);
+        self.has_tag(0, TAG_INT);
+        self.emit([If(truth), LocalGet(0), Num(NumOp::I32WrapI64)]);
+        self.num_operand(1);
+        self.emit([Num(NumOp::I32TruncSatF32S), Num(int_op), Else]);
+        self.num_operand(0);
+        self.num_operand(1);
+        self.emit([Num(float_op), End, Else]);
+        self.error(&EvalErr::InvalidCompare.to_string());
+        self.emit([End, End]);
+        self.call(Rt::Make);
+    }
+    /// literals are equal if they are the same kind and value, so `1 == 1.0` is false; strings
+    /// compare their bytes and functions their identity.
+    fn eq(&mut self) {
+        use Instr::*;
+        let truth = BlockType::Value(ValType::I32);
+        self.emit([I32Const(TAG_BOOL as i32)]);
+        self.both_have_tag(TAG_FLOAT);
+        self.emit([If(truth)]);
+        self.num_operand(0);
+        self.num_operand(1);
+        self.emit([Num(NumOp::F32Eq), Else]);
+        self.both_have_tag(TAG_STR);
+        self.emit([
+            If(truth),
+            LocalGet(0),
+            Num(NumOp::I32WrapI64),
+            LocalGet(1),
+            Num(NumOp::I32WrapI64),
+        ]);
+        self.call(Rt::StrEq);
+        self.emit([Else, LocalGet(0), LocalGet(1), Num(NumOp::I64Eq), End, End]);
+        self.call(Rt::Make);
+    }
+    fn ne(&mut self) {
+        self.emit([Instr::LocalGet(0), Instr::LocalGet(1)]);
+        self.call(Rt::Eq);
+        self.emit([Instr::I64Const(1), Instr::Num(NumOp::I64Xor)]);
+    }
+    fn not(&mut self) {
+        use Instr::*;
+        let value = BlockType::Value(ValType::I64);
+        self.emit([
+            LocalGet(0),
+            Num(NumOp::I64Eqz),
+            If(value),
+            I64Const(TRUE),
+            Else,
+        ]);
+        self.has_tag(0, TAG_BOOL);
+        self.emit([
+            If(value),
+            LocalGet(0),
+            I64Const(1),
+            Num(NumOp::I64Xor),
+            Else,
+        ]);
+        self.error(&EvalErr::InvalidBang.to_string());
+        self.emit([End, End]);
+    }
+    fn neg(&mut self) {
+        use Instr::*;
+        let value = BlockType::Value(ValType::I64);
+        self.has_tag(0, TAG_INT);
+        self.emit([If(value), I64Const(0)]);
+        self.int_operand(0);
+        self.emit([Num(NumOp::I64Sub)]);
+        self.call(Rt::Int);
+        self.emit([Else]);
+        self.has_tag(0, TAG_FLOAT);
+        self.emit([If(value)]);
+        self.num_operand(0);
+        self.emit([Num(NumOp::F32Neg)]);
+        self.call(Rt::Float);
+        self.emit([Else]);
+        self.error(&EvalErr::InvalidNegate.to_string());
+        self.emit([End, End]);
+    }
+    /// locals: 2 the index, 3 the length.
+    fn str_eq(&mut self) {
+        use Instr::*;
+        self.emit([
+            LocalGet(0),
+            I32Load(0),
+            LocalTee(3),
+            LocalGet(1),
+            I32Load(0),
+            Num(NumOp::I32Ne),
+            If(BlockType::Empty),
+            I32Const(0),
+            Return,
+            End,
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+            LocalGet(2),
+            LocalGet(3),
+            Num(NumOp::I32GeU),
+            BrIf(1),
+            LocalGet(0),
+            LocalGet(2),
+            Num(NumOp::I32Add),
+            I32Load8U(4),
+            LocalGet(1),
+            LocalGet(2),
+            Num(NumOp::I32Add),
+            I32Load8U(4),
+            Num(NumOp::I32Ne),
+            If(BlockType::Empty),
+            I32Const(0),
+            Return,
+            End,
+            LocalGet(2),
+            I32Const(1),
+            Num(NumOp::I32Add),
+            LocalSet(2),
+            Br(0),
+            End,
+            End,
+            I32Const(1),
+        ]);
+    }
+    /// locals: 2 and 3 the operands' addresses, 4 and 5 their lengths, 6 the result's address.
+    fn concat(&mut self) {
+        use Instr::*;
+        self.emit([
+            LocalGet(0),
+            Num(NumOp::I32WrapI64),
+            LocalTee(2),
+            I32Load(0),
+            LocalSet(4),
+            LocalGet(1),
+            Num(NumOp::I32WrapI64),
+            LocalTee(3),
+            I32Load(0),
+            LocalSet(5),
+            LocalGet(4),
+            LocalGet(5),
+            Num(NumOp::I32Add),
+            I32Const(4),
+            Num(NumOp::I32Add),
+        ]);
+        self.call(Rt::Alloc);
+        self.emit([
+            LocalTee(6),
+            LocalGet(4),
+            LocalGet(5),
+            Num(NumOp::I32Add),
+            I32Store(0),
+            LocalGet(6),
+            I32Const(4),
+            Num(NumOp::I32Add),
+            LocalGet(2),
+            I32Const(4),
+            Num(NumOp::I32Add),
+            LocalGet(4),
+            MemoryCopy,
+            LocalGet(6),
+            I32Const(4),
+            Num(NumOp::I32Add),
+            LocalGet(4),
+            Num(NumOp::I32Add),
+            LocalGet(3),
+            I32Const(4),
+            Num(NumOp::I32Add),
+            LocalGet(5),
+            MemoryCopy,
+            I32Const(TAG_STR as i32),
+            LocalGet(6),
+        ]);
+        self.call(Rt::Make);
+    }
+    /// digits are written backwards from the end of a 16-byte buffer, then the length right
+    /// before the first, which leaves room for the sign of the longest `int`.
+    /// locals: 1 whether it is negative, 2 the buffer, 3 the first byte written, 4 what is left.
+    fn itoa(&mut self) {
+        use Instr::*;
+        self.emit([I32Const(16)]);
+        self.call(Rt::Alloc);
+        self.emit([
+            LocalTee(2),
+            I32Const(16),
+            Num(NumOp::I32Add),
+            LocalSet(3),
+            LocalGet(0),
+            Num(NumOp::I64ExtendI32S),
+            LocalTee(4),
+            I64Const(0),
+            Num(NumOp::I64LtS),
+            LocalTee(1),
+            If(BlockType::Empty),
+            I64Const(0),
+            LocalGet(4),
+            Num(NumOp::I64Sub),
+            LocalSet(4),
+            End,
+            Loop(BlockType::Empty),
+            LocalGet(3),
+            I32Const(1),
+            Num(NumOp::I32Sub),
+            LocalTee(3),
+            LocalGet(4),
+            I64Const(10),
+            Num(NumOp::I64RemU),
+            Num(NumOp::I32WrapI64),
+            I32Const(b'0' as i32),
+            Num(NumOp::I32Add),
+            I32Store8(0),
+            LocalGet(4),
+            I64Const(10),
+            Num(NumOp::I64DivU),
+            LocalTee(4),
+            Num(NumOp::I64Eqz),
+            Num(NumOp::I32Eqz),
+            BrIf(0),
+            End,
+            LocalGet(1),
+            If(BlockType::Empty),
+            LocalGet(3),
+            I32Const(1),
+            Num(NumOp::I32Sub),
+            LocalTee(3),
+            I32Const(b'-' as i32),
+            I32Store8(0),
+            End,
+            LocalGet(3),
+            I32Const(4),
+            Num(NumOp::I32Sub),
+            LocalGet(2),
+            I32Const(16),
+            Num(NumOp::I32Add),
+            LocalGet(3),
+            Num(NumOp::I32Sub),
+            I32Store(0),
+            I32Const(TAG_STR as i32),
+            LocalGet(3),
+            I32Const(4),
+            Num(NumOp::I32Sub),
+        ]);
+        self.call(Rt::Make);
+    }
+    fn write_str(&mut self) {
+        self.emit([
+            Instr::LocalGet(0),
+            Instr::I32Const(4),
+            Instr::Num(NumOp::I32Add),
+            Instr::LocalGet(0),
+            Instr::I32Load(0),
+            Instr::Call(import::WRITE),
+        ]);
+    }
+    /// prints like the interpreter's `Display` for values, followed by a newline.
+    /// locals: 1 the tag.
+    fn print(&mut self) {
+        use Instr::*;
+        self.emit([Block(BlockType::Empty), LocalGet(0)]);
+        self.call(Rt::Tag);
+        self.emit([LocalTee(1), Num(NumOp::I32Eqz), If(BlockType::Empty)]);
+        self.write("nil");
+        self.emit([Br(1), End]);
+
+        self.emit([
+            LocalGet(1),
+            I32Const(TAG_BOOL as i32),
+            Num(NumOp::I32Eq),
+            If(BlockType::Empty),
+            LocalGet(0),
+            Num(NumOp::I32WrapI64),
+            If(BlockType::Empty),
+        ]);
+        self.write("true");
+        self.emit([Else]);
+        self.write("false");
+        self.emit([End, Br(1), End]);
+
+        self.emit([
+            LocalGet(1),
+            I32Const(TAG_INT as i32),
+            Num(NumOp::I32Eq),
+            If(BlockType::Empty),
+            LocalGet(0),
+            Num(NumOp::I32WrapI64),
+        ]);
+        self.call(Rt::Itoa);
+        self.emit([Num(NumOp::I32WrapI64)]);
+        self.call(Rt::WriteStr);
+        self.emit([Br(1), End]);
+
+        self.emit([
+            LocalGet(1),
+            I32Const(TAG_FLOAT as i32),
+            Num(NumOp::I32Eq),
+            If(BlockType::Empty),
+        ]);
+        self.num_operand(0);
+        self.emit([
+            Num(NumOp::F64PromoteF32),
+            Call(import::WRITE_FLOAT),
+            Br(1),
+            End,
+        ]);
+
+        self.emit([
+            LocalGet(1),
+            I32Const(TAG_STR as i32),
+            Num(NumOp::I32Eq),
+            If(BlockType::Empty),
+            LocalGet(0),
+            Num(NumOp::I32WrapI64),
+        ]);
+        self.call(Rt::WriteStr);
+        self.emit([Br(1), End]);
+
+        self.emit([
+            LocalGet(1),
+            I32Const(TAG_CLASS as i32),
+            Num(NumOp::I32Eq),
+            If(BlockType::Empty),
+            LocalGet(0),
+            Num(NumOp::I32WrapI64),
+            I32Load(0),
+        ]);
+        self.call(Rt::WriteStr);
+        self.emit([Br(1), End]);
+
+        self.emit([
+            LocalGet(1),
+            I32Const(TAG_INSTANCE as i32),
+            Num(NumOp::I32Eq),
+            If(BlockType::Empty),
+        ]);
+        self.write("<");
+        self.emit([LocalGet(0), Num(NumOp::I32WrapI64), I32Load(0), I32Load(0)]);
+        self.call(Rt::WriteStr);
+        self.write(" instance>");
+        self.emit([Br(1), End]);
+
+        // a function, or a bound method printing as its closure
+        self.write("<fn ");
+        self.emit([
+            LocalGet(1),
+            I32Const(TAG_METHOD as i32),
+            Num(NumOp::I32Eq),
+            If(BlockType::Value(ValType::I32)),
+            LocalGet(0),
+            Num(NumOp::I32WrapI64),
+            I32Load(0),
+            Else,
+            LocalGet(0),
+            Num(NumOp::I32WrapI64),
+            End,
+            I32Load(8),
+        ]);
+        self.call(Rt::WriteStr);
+        self.write(">");
+        self.emit([End]);
+        self.write("\n");
+    }
+    fn arity(&mut self) {
+        use Instr::*;
+        // "expected {} arguments but got {}", put together at runtime
+        let expected = make(TAG_STR, self.data.string("expected "));
+        let but_got = make(TAG_STR, self.data.string(" arguments but got "));
+        self.emit([I64Const(expected), LocalGet(0)]);
+        self.call(Rt::Itoa);
+        self.call(Rt::Concat);
+        self.emit([I64Const(but_got)]);
+        self.call(Rt::Concat);
+        self.emit([LocalGet(1)]);
+        self.call(Rt::Itoa);
+        self.call(Rt::Concat);
+        self.emit([Num(NumOp::I32WrapI64)]);
+        self.call(Rt::Fail);
+    }
+    /// fails unless the closure in local 2 takes the number of arguments in local 1, plus the
+    /// receiver if there is one.
+    fn check_arity(&mut self, receiver: bool) {
+        use Instr::*;
+        let expected = [
+            LocalGet(2),
+            I32Load(4),
+            I32Const(i32::from(receiver)),
+            Num(NumOp::I32Sub),
+        ];
+        self.emit(expected.clone());
+        self.emit([LocalGet(1), Num(NumOp::I32Ne), If(BlockType::Empty)]);
+        self.emit(expected);
+        self.emit([LocalGet(1)]);
+        self.call(Rt::Arity);
+        self.emit([End]);
+    }
+    /// locals: 2 the closure, 3 the tag, 4 the bound method or the instance.
+    fn callable(&mut self) {
+        use Instr::*;
+        self.emit([I64Const(NIL), GlobalSet(RECEIVER), LocalGet(0)]);
+        self.call(Rt::Tag);
+        self.emit([
+            LocalSet(3),
+            LocalGet(3),
+            I32Const(TAG_METHOD as i32),
+            Num(NumOp::I32Eq),
+            If(BlockType::Empty),
+            LocalGet(0),
+            Num(NumOp::I32WrapI64),
+            LocalTee(4),
+            I64Load(8),
+            GlobalSet(RECEIVER),
+            LocalGet(4),
+            I32Load(0),
+            LocalSet(2),
+        ]);
+        self.check_arity(true);
+        self.emit([LocalGet(2), Return, End]);
+
+        let init = self.data.string("init");
+        self.emit([
+            LocalGet(3),
+            I32Const(TAG_CLASS as i32),
+            Num(NumOp::I32Eq),
+            If(BlockType::Empty),
+            I32Const(16),
+        ]);
+        self.call(Rt::Alloc);
+        self.emit([
+            LocalTee(4),
+            LocalGet(0),
+            Num(NumOp::I32WrapI64),
+            I32Store(0),
+            I32Const(TAG_INSTANCE as i32),
+            LocalGet(4),
+        ]);
+        self.call(Rt::Make);
+        self.emit([
+            GlobalSet(RECEIVER),
+            LocalGet(0),
+            Num(NumOp::I32WrapI64),
+            I32Const(init as i32),
+        ]);
+        self.call(Rt::Method);
+        self.emit([
+            LocalTee(2),
+            Num(NumOp::I32Eqz),
+            If(BlockType::Empty),
+            LocalGet(1),
+            If(BlockType::Empty),
+            I32Const(0),
+            LocalGet(1),
+        ]);
+        self.call(Rt::Arity);
+        self.emit([End, I32Const(0), Return, End]);
+        self.check_arity(true);
+        self.emit([LocalGet(2), Return, End]);
+
+        self.emit([
+            LocalGet(3),
+            I32Const(TAG_FN as i32),
+            Num(NumOp::I32Ne),
+            If(BlockType::Empty),
+        ]);
+        self.error(&EvalErr::InvalidCall.to_string());
+        self.emit([End, LocalGet(0), Num(NumOp::I32WrapI64), LocalSet(2)]);
+        self.check_arity(false);
+        self.emit([LocalGet(2)]);
+    }
+    /// locals: 2 the index, 3 the address of the method's name.
+    fn method(&mut self) {
+        use Instr::*;
+        self.emit([
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+            LocalGet(2),
+            LocalGet(0),
+            I32Load(4),
+            Num(NumOp::I32GeU),
+            BrIf(1),
+            LocalGet(0),
+            LocalGet(2),
+            I32Const(3),
+            Num(NumOp::I32Shl),
+            Num(NumOp::I32Add),
+            LocalTee(3),
+            I32Load(8),
+            LocalGet(1),
+            Num(NumOp::I32Eq),
+            If(BlockType::Empty),
+            LocalGet(3),
+            I32Load(12),
+            Return,
+            End,
+            LocalGet(2),
+            I32Const(1),
+            Num(NumOp::I32Add),
+            LocalSet(2),
+            Br(0),
+            End,
+            End,
+            I32Const(0),
+        ]);
+    }
+    /// looks for the field named by local `name` of the instance in local `instance`, counting
+    /// with local `index`. When found, its address is in local `field` and `found` runs.
+    fn find_field(&mut self, instance: u32, name: u32, index: u32, field: u32, found: &[Instr]) {
+        use Instr::*;
+        self.emit([
+            Block(BlockType::Empty),
+            Loop(BlockType::Empty),
+            LocalGet(index),
+            LocalGet(instance),
+            I32Load(4),
+            Num(NumOp::I32GeU),
+            BrIf(1),
+            LocalGet(instance),
+            I32Load(12),
+            LocalGet(index),
+            I32Const(4),
+            Num(NumOp::I32Shl),
+            Num(NumOp::I32Add),
+            LocalTee(field),
+            I32Load(0),
+            LocalGet(name),
+            Num(NumOp::I32Eq),
+            If(BlockType::Empty),
+        ]);
+        self.emit(found.iter().cloned());
+        self.emit([
+            End,
+            LocalGet(index),
+            I32Const(1),
+            Num(NumOp::I32Add),
+            LocalSet(index),
+            Br(0),
+            End,
+            End,
+        ]);
+    }
+    /// fields shadow methods, which are bound to the instance.
+    /// locals: 2 the instance, then the bound method, 3 the index, 4 the field, 5 the method.
+    fn get(&mut self) {
+        use Instr::*;
+        self.has_tag(0, TAG_INSTANCE);
+        self.emit([Num(NumOp::I32Eqz), If(BlockType::Empty)]);
+        self.error(&EvalErr::InvalidGet.to_string());
+        self.emit([End, LocalGet(0), Num(NumOp::I32WrapI64), LocalSet(2)]);
+        self.find_field(2, 1, 3, 4, &[LocalGet(4), I64Load(8), Return]);
+        self.emit([LocalGet(2), I32Load(0), LocalGet(1)]);
+        self.call(Rt::Method);
+        self.emit([LocalTee(5), Num(NumOp::I32Eqz), If(BlockType::Empty)]);
+        // "undefined property '{}'", put together at runtime
+        let undefined = make(TAG_STR, self.data.string("undefined property '"));
+        let quote = make(TAG_STR, self.data.string("'"));
+        self.emit([I64Const(undefined), I32Const(TAG_STR as i32), LocalGet(1)]);
+        self.call(Rt::Make);
+        self.call(Rt::Concat);
+        self.emit([I64Const(quote)]);
+        self.call(Rt::Concat);
+        self.emit([Num(NumOp::I32WrapI64)]);
+        self.call(Rt::Fail);
+        self.emit([End, I32Const(16)]);
+        self.call(Rt::Alloc);
+        self.emit([
+            LocalTee(2),
+            LocalGet(5),
+            I32Store(0),
+            LocalGet(2),
+            LocalGet(0),
+            I64Store(8),
+            I32Const(TAG_METHOD as i32),
+            LocalGet(2),
+        ]);
+        self.call(Rt::Make);
+    }
+    /// a new field goes at the end, after moving the fields to twice the room, or four, if they
+    /// are out of it.
+    /// locals: 3 the instance, 4 the index, 5 the field, 6 the new capacity.
+    fn set(&mut self) {
+        use Instr::*;
+        self.has_tag(0, TAG_INSTANCE);
+        self.emit([Num(NumOp::I32Eqz), If(BlockType::Empty)]);
+        self.error(&EvalErr::InvalidSet.to_string());
+        self.emit([End, LocalGet(0), Num(NumOp::I32WrapI64), LocalSet(3)]);
+        self.find_field(3, 1, 4, 5, &[LocalGet(5), LocalGet(2), I64Store(8), Return]);
+        self.emit([
+            LocalGet(3),
+            I32Load(4),
+            LocalGet(3),
+            I32Load(8),
+            Num(NumOp::I32Eq),
+            If(BlockType::Empty),
+            LocalGet(3),
+            LocalGet(3),
+            I32Load(8),
+            I32Const(1),
+            Num(NumOp::I32Shl),
+            LocalTee(6),
+            Num(NumOp::I32Eqz),
+            If(BlockType::Value(ValType::I32)),
+            I32Const(4),
+            Else,
+            LocalGet(6),
+            End,
+            LocalTee(6),
+            I32Store(8),
+            LocalGet(6),
+            I32Const(4),
+            Num(NumOp::I32Shl),
+        ]);
+        self.call(Rt::Alloc);
+        self.emit([
+            LocalTee(5),
+            LocalGet(3),
+            I32Load(12),
+            LocalGet(3),
+            I32Load(4),
+            I32Const(4),
+            Num(NumOp::I32Shl),
+            MemoryCopy,
+            LocalGet(3),
+            LocalGet(5),
+            I32Store(12),
+            End,
+            LocalGet(3),
+            I32Load(12),
+            LocalGet(3),
+            I32Load(4),
+            I32Const(4),
+            Num(NumOp::I32Shl),
+            Num(NumOp::I32Add),
+            LocalTee(5),
+            LocalGet(1),
+            I32Store(0),
+            LocalGet(5),
+            LocalGet(2),
+            I64Store(8),
+            LocalGet(3),
+            LocalGet(3),
+            I32Load(4),
+            I32Const(1),
+            Num(NumOp::I32Add),
+            I32Store(4),
+        ]);
+    }
+}
@@ -0,0 +1,255 @@
+use crate::compiler::{
+    backend::wasm::{
+        compile,
+        module::{Export, Func, Instr, Module, NumOp, ValType},
+    },
+    checker::check,
+    ir::{lower::lower, opt::optimize},
+    lexer::Lexer,
+    natives::Natives,
+    parser::Parser,
+    resolver::resolve,
+    token::Token,
+};
+
+#[test]
+fn test_encode_module() {
+    let mut module = Module::default();
+    let ty = module.ty(&[ValType::I32], &[ValType::I64]);
+    module.functions.push(Func {
+        name: "f".to_owned(),
+        ty,
+        locals: vec![ValType::I64, ValType::I64],
+        body: vec![
+            Instr::LocalGet(0),
+            Instr::Num(NumOp::I64ExtendI32S),
+            Instr::I64Const(-200),
+            Instr::Num(NumOp::I64Add),
+        ],
+    });
+    module.exports.push(Export::Func("f".to_owned(), 0));
+    assert_eq!(
+        module.encode(),
+        [
+            &b"\0asm\x01\0\0\0"[..],
+            // type: (i32) -> i64
+            &[0x01, 0x06, 0x01, 0x60, 0x01, 0x7f, 0x01, 0x7e],
+            // function
+            &[0x03, 0x02, 0x01, 0x00],
+            // an empty table and memory
+            &[0x04, 0x04, 0x01, 0x70, 0x00, 0x00],
+            &[0x05, 0x03, 0x01, 0x00, 0x00],
+            // export "f"
+            &[0x07, 0x05, 0x01, 0x01, b'f', 0x00, 0x00],
+            // element
+            &[0x09, 0x06, 0x01, 0x00, 0x41, 0x00, 0x0b, 0x00],
+            // code: two i64 locals, then the body
+            &[0x0a, 0x0d, 0x01, 0x0b, 0x01, 0x02, 0x7e],
+            &[0x20, 0x00, 0xac, 0x42, 0xb8, 0x7e, 0x7c, 0x0b],
+        ]
+        .concat()
+    );
+}
+
+/// the module compiled from a program at optimisation level 1.
+fn build(source: &str) -> Module {
+    let tokens: Vec<Token> = Lexer::from_source(source).collect();
+    let mut program = Parser::new(&tokens).parse().unwrap();
+    let globals = resolve(&mut program, &Natives::standard()).unwrap();
+    check(&program, &Natives::standard()).unwrap();
+    let mut module = lower(&program, &globals, Natives::standard().len());
+    optimize(&mut module, 1);
+    compile(&module).map_err(|e| e.to_string()).unwrap()
+}
+
+#[test]
+fn test_wat_output() {
+    let wat = build("let x = 1; print x + 2;").to_string();
+    assert!(wat.starts_with("(module\n"), "{}", wat);
+    for line in [
+        "  (import \"env\" \"write\" (func $write (type 0)))",
+        "  (global $x (mut i64) (i64.const 30064771072))",
+        "  (export \"main\" (func $fn0_main))",
+        "  (func $fn0_main (type 2) (param i32) (result i64)",
+        "      call $rt_add",
+    ] {
+        assert!(wat.lines().any(|l| l == line), "{}\n{}", line, wat);
+    }
+}
+
+/// runs a compiled program under node and returns its exit status, stdout and stderr, or `None`
+/// if node is not installed.
+fn run_wasm(name: &str, source: &str) -> Option<(i32, String, String)> {
+    let node = std::process::Command::new("node").arg("--version").output();
+    if !node.is_ok_and(|o| o.status.success()) {
+        return None;
+    }
+    let path = std::env::temp_dir().join(format!("{}-{}.wasm", name, std::process::id()));
+    std::fs::write(&path, build(source).encode()).unwrap();
+    let host = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/src/compiler/backend/wasm/run.js"
+    );
+    let output = std::process::Command::new("node")
+        .arg(host)
+        .arg(&path)
+        .output()
+        .unwrap();
+    std::fs::remove_file(&path).unwrap();
+    Some((
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    ))
+}
+
+#[test]
+fn test_wasm_programs_run() {
+    let source = "
+fn fib(n) {
+    if (n < 2) { return n; }
+    return fib(n - 1) + fib(n - 2);
+}
+fn counter() {
+    let n = 0;
+    fn next() { n = n + 1; return n; }
+    return next;
+}
+let next = counter();
+next();
+print next();
+print fib(20);
+let greeting = \"hello\" + \", \" + \"world\";
+print greeting == \"hello, world\";
+print greeting;
+print -7 / 2;
+print 1.5 + 2;
+print 0.1 * 3;
+print 1 == 1.0;
+print !nil;
+print fib;
+";
+    let Some((status, stdout, stderr)) = run_wasm("wasm-programs", source) else {
+        return;
+    };
+    assert_eq!((status, stderr.as_str()), (0, ""));
+    assert_eq!(
+        stdout,
+        "2\n6765\ntrue\nhello, world\n-3\n3.5\n0.3\nfalse\ntrue\n<fn fib>\n"
+    );
+}
+
+#[test]
+fn test_wasm_classes() {
+    let source = "
+class Point {
+    fn init(x, y) { this.x = x; this.y = y; }
+    fn sum() { return this.x + this.y; }
+    fn scaled(k) { return Point(this.x * k, this.y * k); }
+}
+let p = Point(1, 2.5);
+let sum = p.sum;
+print sum();
+print p.scaled(10).sum();
+print p;
+print Point;
+print sum;
+class Bag {}
+let b = Bag();
+b.a = 1; b.b = 2; b.c = 3; b.d = 4; b.e = 5; b.f = 6;
+b.c = 30;
+print b.a + b.b + b.c + b.d + b.e + b.f;
+fn make(step) {
+    class Counter {
+        fn init() { this.n = 0; }
+        fn next() { this.n = this.n + step; return this.n; }
+    }
+    return Counter();
+}
+let c = make(5);
+c.next();
+print c.next();
+c.next = 7;
+print c.next;
+";
+    let Some((status, stdout, stderr)) = run_wasm("wasm-classes", source) else {
+        return;
+    };
+    assert_eq!((status, stderr.as_str()), (0, ""));
+    assert_eq!(
+        stdout,
+        "3.5\n35\n<Point instance>\nPoint\n<fn sum>\n48\n10\n7\n"
+    );
+
+    for (source, error) in [
+        (
+            "class A { fn init(a) {} } let g: any = A; print g(1, 2);",
+            "expected 1 arguments but got 2",
+        ),
+        (
+            "class A {} let g: any = A; print g(1);",
+            "expected 0 arguments but got 1",
+        ),
+        (
+            "class A { fn m(a) {} } let m: any = A().m; m();",
+            "expected 1 arguments but got 0",
+        ),
+        (
+            "class A {} let a: any = A(); print a.missing;",
+            "undefined property 'missing'",
+        ),
+        (
+            "let a: any = 1; print a.x;",
+            "only instances have properties",
+        ),
+        ("let a: any = 1; a.x = 2;", "only instances have fields"),
+    ] {
+        let (status, _, stderr) = run_wasm("wasm-class-errors", source).unwrap();
+        assert_eq!(
+            (status, stderr),
+            (1, format!("error: {}\n", error)),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+fn test_wasm_runtime_errors() {
+    let Some((status, stdout, stderr)) = run_wasm(
+        "wasm-arity",
+        "fn f(a) { return a; } let g: any = f; print 1; print g(1, 2);",
+    ) else {
+        return;
+    };
+    assert_eq!(status, 1);
+    assert_eq!(stdout, "1\n");
+    assert_eq!(stderr, "error: expected 1 arguments but got 2\n");
+
+    let (status, _, stderr) = run_wasm(
+        "wasm-overflow",
+        "fn f(x) { return x * 2147483647; } print f(3);",
+    )
+    .unwrap();
+    assert_eq!(status, 1);
+    assert_eq!(stderr, "error: integer overflow\n");
+}
+
+#[test]
+fn test_unsupported_features() {
+    for (source, feature) in [
+        ("throw 1;", "exceptions"),
+        ("try { print 1; } catch (e) { print e; }", "exceptions"),
+        // compiled code cannot call into the host
+        ("print str(1);", "the native 'str'"),
+    ] {
+        let tokens: Vec<Token> = Lexer::from_source(source).collect();
+        let mut program = Parser::new(&tokens).parse().unwrap();
+        let globals = resolve(&mut program, &Natives::standard()).unwrap();
+        let module = lower(&program, &globals, Natives::standard().len());
+        assert_eq!(
+            compile(&module).unwrap_err().to_string(),
+            format!("the wasm32 target does not support {} yet", feature)
+        );
+    }
+}
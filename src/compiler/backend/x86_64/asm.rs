@@ -0,0 +1,580 @@
+use std::collections::HashMap;
+
+/// general purpose registers, numbered as the encoding numbers them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Reg {
+    Rax,
+    Rcx,
+    Rdx,
+    Rbx,
+    Rsp,
+    Rbp,
+    Rsi,
+    Rdi,
+    R8,
+    R9,
+    R10,
+    R11,
+    R12,
+    R13,
+    R14,
+    R15,
+}
+
+impl Reg {
+    /// the registers the System V ABI passes the first six integer arguments in.
+    pub const ARGS: [Reg; 6] = [Reg::Rdi, Reg::Rsi, Reg::Rdx, Reg::Rcx, Reg::R8, Reg::R9];
+
+    fn code(self) -> u8 {
+        self as u8
+    }
+    fn name(self) -> &'static str {
+        [
+            "rax", "rcx", "rdx", "rbx", "rsp", "rbp", "rsi", "rdi", "r8", "r9", "r10", "r11",
+            "r12", "r13", "r14", "r15",
+        ][self as usize]
+    }
+    fn name32(self) -> &'static str {
+        [
+            "eax", "ecx", "edx", "ebx", "esp", "ebp", "esi", "edi", "r8d", "r9d", "r10d", "r11d",
+            "r12d", "r13d", "r14d", "r15d",
+        ][self as usize]
+    }
+    fn name8(self) -> &'static str {
+        [
+            "al", "cl", "dl", "bl", "spl", "bpl", "sil", "dil", "r8b", "r9b", "r10b", "r11b",
+            "r12b", "r13b", "r14b", "r15b",
+        ][self as usize]
+    }
+}
+
+impl std::fmt::Display for Reg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// SSE registers, which hold the floats the runtime computes with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Xmm {
+    Xmm0,
+    Xmm1,
+    Xmm2,
+}
+
+impl Xmm {
+    fn code(self) -> u8 {
+        self as u8
+    }
+}
+
+impl std::fmt::Display for Xmm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "xmm{}", self.code())
+    }
+}
+
+/// `[base + disp]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mem {
+    pub base: Reg,
+    pub disp: i32,
+}
+
+impl Mem {
+    pub fn new(base: Reg, disp: i32) -> Self {
+        Self { base, disp }
+    }
+}
+
+impl std::fmt::Display for Mem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.disp {
+            0 => write!(f, "[{}]", self.base),
+            d if d < 0 => write!(f, "[{} - {}]", self.base, -(d as i64)),
+            d => write!(f, "[{} + {}]", self.base, d),
+        }
+    }
+}
+
+/// condition codes, numbered as `jcc` and `cmovcc` encode them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Cond {
+    O = 0x0,
+    B = 0x2,
+    Ae = 0x3,
+    E = 0x4,
+    Ne = 0x5,
+    Be = 0x6,
+    A = 0x7,
+    /// parity, which a float comparison sets when either side is NaN.
+    P = 0xa,
+    L = 0xc,
+    Ge = 0xd,
+    Le = 0xe,
+    G = 0xf,
+}
+
+impl Cond {
+    fn suffix(self) -> &'static str {
+        match self {
+            Cond::O => "o",
+            Cond::B => "b",
+            Cond::Ae => "ae",
+            Cond::E => "e",
+            Cond::Ne => "ne",
+            Cond::Be => "be",
+            Cond::A => "a",
+            Cond::P => "p",
+            Cond::L => "l",
+            Cond::Ge => "ge",
+            Cond::Le => "le",
+            Cond::G => "g",
+        }
+    }
+}
+
+/// the two-operand arithmetic instructions that share an encoding scheme.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AluOp {
+    Add,
+    Or,
+    And,
+    Sub,
+    Xor,
+    Cmp,
+}
+
+impl AluOp {
+    /// the `/digit` of the immediate form, which also picks the register form's opcode.
+    fn digit(self) -> u8 {
+        match self {
+            AluOp::Add => 0,
+            AluOp::Or => 1,
+            AluOp::And => 4,
+            AluOp::Sub => 5,
+            AluOp::Xor => 6,
+            AluOp::Cmp => 7,
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            AluOp::Add => "add",
+            AluOp::Or => "or",
+            AluOp::And => "and",
+            AluOp::Sub => "sub",
+            AluOp::Xor => "xor",
+            AluOp::Cmp => "cmp",
+        }
+    }
+}
+
+/// the scalar single-precision arithmetic instructions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SseOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+impl SseOp {
+    fn opcode(self) -> u8 {
+        match self {
+            SseOp::Add => 0x58,
+            SseOp::Sub => 0x5c,
+            SseOp::Mul => 0x59,
+            SseOp::Div => 0x5e,
+        }
+    }
+    fn name(self) -> &'static str {
+        match self {
+            SseOp::Add => "addss",
+            SseOp::Sub => "subss",
+            SseOp::Mul => "mulss",
+            SseOp::Div => "divss",
+        }
+    }
+}
+
+/// The instructions the backend generates, with destination operands first as in Intel syntax.
+/// Operations are on 64-bit registers unless their name says otherwise; the 32-bit forms clear the
+/// upper half of their destination.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    Label(String),
+    /// a note for the reader of the assembly, taking no space.
+    Comment(String),
+    Mov(Reg, Reg),
+    MovImm(Reg, i64),
+    /// the absolute address of a label, plus a constant.
+    MovAddr(Reg, String, i64),
+    Load(Reg, Mem),
+    Store(Mem, Reg),
+    /// zero-extending byte load.
+    LoadByte(Reg, Mem),
+    StoreByte(Mem, Reg),
+    Mov32(Reg, Reg),
+    Alu(AluOp, Reg, Reg),
+    AluImm(AluOp, Reg, i32),
+    Alu32(AluOp, Reg, Reg),
+    Imul(Reg, Reg),
+    Imul32(Reg, Reg),
+    Neg(Reg),
+    Neg32(Reg),
+    /// sign-extends `eax` into `edx` for `idiv`.
+    Cdq,
+    Idiv32(Reg),
+    /// unsigned division of `rdx:rax`.
+    Div(Reg),
+    Shl(Reg, u8),
+    Shr(Reg, u8),
+    Sar(Reg, u8),
+    /// shift right by `cl`.
+    ShrCl(Reg),
+    Test(Reg, Reg),
+    Cmov(Cond, Reg, Reg),
+    Jmp(String),
+    JmpReg(Reg),
+    Jcc(Cond, String),
+    Call(String),
+    CallReg(Reg),
+    Ret,
+    Push(Reg),
+    Pop(Reg),
+    Syscall,
+    Ud2,
+    /// the low 32 bits of a register into the low lane of an SSE register.
+    MovdToXmm(Xmm, Reg),
+    /// the low lane of an SSE register into a register, clearing the upper half.
+    MovdFromXmm(Reg, Xmm),
+    /// a signed 32-bit integer to a float.
+    Cvtsi2ss(Xmm, Reg),
+    /// a float to a signed 32-bit integer, rounding toward zero; out of range gives `i32::MIN`.
+    Cvttss2si(Reg, Xmm),
+    Sse(SseOp, Xmm, Xmm),
+    /// compares floats into the flags as `cmp` compares unsigned integers, setting the parity
+    /// flag too when they are unordered.
+    Ucomiss(Xmm, Xmm),
+}
+
+impl std::fmt::Display for Inst {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Inst::Label(label) => write!(f, "{}:", label),
+            Inst::Comment(text) => write!(f, "    ; {}", text),
+            Inst::Mov(d, s) => write!(f, "    mov {}, {}", d, s),
+            Inst::MovImm(d, imm) => write!(f, "    mov {}, {}", d, imm),
+            Inst::MovAddr(d, label, 0) => write!(f, "    mov {}, {}", d, label),
+            Inst::MovAddr(d, label, addend) => write!(f, "    mov {}, {} + {}", d, label, addend),
+            Inst::Load(d, m) => write!(f, "    mov {}, qword ptr {}", d, m),
+            Inst::Store(m, s) => write!(f, "    mov qword ptr {}, {}", m, s),
+            Inst::LoadByte(d, m) => write!(f, "    movzx {}, byte ptr {}", d.name32(), m),
+            Inst::StoreByte(m, s) => write!(f, "    mov byte ptr {}, {}", m, s.name8()),
+            Inst::Mov32(d, s) => write!(f, "    mov {}, {}", d.name32(), s.name32()),
+            Inst::Alu(op, d, s) => write!(f, "    {} {}, {}", op.name(), d, s),
+            Inst::AluImm(op, d, imm) => write!(f, "    {} {}, {}", op.name(), d, imm),
+            Inst::Alu32(op, d, s) => write!(f, "    {} {}, {}", op.name(), d.name32(), s.name32()),
+            Inst::Imul(d, s) => write!(f, "    imul {}, {}", d, s),
+            Inst::Imul32(d, s) => write!(f, "    imul {}, {}", d.name32(), s.name32()),
+            Inst::Neg(r) => write!(f, "    neg {}", r),
+            Inst::Neg32(r) => write!(f, "    neg {}", r.name32()),
+            Inst::Cdq => write!(f, "    cdq"),
+            Inst::Idiv32(r) => write!(f, "    idiv {}", r.name32()),
+            Inst::Div(r) => write!(f, "    div {}", r),
+            Inst::Shl(r, n) => write!(f, "    shl {}, {}", r, n),
+            Inst::Shr(r, n) => write!(f, "    shr {}, {}", r, n),
+            Inst::Sar(r, n) => write!(f, "    sar {}, {}", r, n),
+            Inst::ShrCl(r) => write!(f, "    shr {}, cl", r),
+            Inst::Test(a, b) => write!(f, "    test {}, {}", a, b),
+            Inst::Cmov(c, d, s) => write!(f, "    cmov{} {}, {}", c.suffix(), d, s),
+            Inst::Jmp(label) => write!(f, "    jmp {}", label),
+            Inst::JmpReg(r) => write!(f, "    jmp {}", r),
+            Inst::Jcc(c, label) => write!(f, "    j{} {}", c.suffix(), label),
+            Inst::Call(label) => write!(f, "    call {}", label),
+            Inst::CallReg(r) => write!(f, "    call {}", r),
+            Inst::Ret => write!(f, "    ret"),
+            Inst::Push(r) => write!(f, "    push {}", r),
+            Inst::Pop(r) => write!(f, "    pop {}", r),
+            Inst::Syscall => write!(f, "    syscall"),
+            Inst::Ud2 => write!(f, "    ud2"),
+            Inst::MovdToXmm(d, s) => write!(f, "    movd {}, {}", d, s.name32()),
+            Inst::MovdFromXmm(d, s) => write!(f, "    movd {}, {}", d.name32(), s),
+            Inst::Cvtsi2ss(d, s) => write!(f, "    cvtsi2ss {}, {}", d, s.name32()),
+            Inst::Cvttss2si(d, s) => write!(f, "    cvttss2si {}, {}", d.name32(), s),
+            Inst::Sse(op, d, s) => write!(f, "    {} {}, {}", op.name(), d, s),
+            Inst::Ucomiss(a, b) => write!(f, "    ucomiss {}, {}", a, b),
+        }
+    }
+}
+
+/// initialised, writable data.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Data {
+    pub label: String,
+    pub bytes: Vec<u8>,
+    /// offsets into `bytes` that hold the absolute address of a label.
+    pub relocs: Vec<(usize, String)>,
+}
+
+/// what the assembler turns into an executable: code, data, and zeroed memory after the data.
+#[derive(Debug, Default)]
+pub struct Program {
+    pub text: Vec<Inst>,
+    pub data: Vec<Data>,
+    /// labels and sizes of zero-initialised memory.
+    pub bss: Vec<(String, usize)>,
+    strings: HashMap<String, String>,
+}
+
+impl Program {
+    /// the label of a string object holding `s`: its length as 8 bytes, then its bytes. Equal
+    /// strings share one object.
+    pub fn string(&mut self, s: &str) -> String {
+        if let Some(label) = self.strings.get(s) {
+            return label.clone();
+        }
+        let label = format!("str{}", self.strings.len());
+        let mut bytes = (s.len() as u64).to_le_bytes().to_vec();
+        bytes.extend(s.as_bytes());
+        self.data.push(Data {
+            label: label.clone(),
+            bytes,
+            relocs: Vec::new(),
+        });
+        self.strings.insert(s.to_owned(), label.clone());
+        label
+    }
+}
+
+impl std::fmt::Display for Program {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for inst in &self.text {
+            writeln!(f, "{}", inst)?;
+        }
+        Ok(())
+    }
+}
+
+/// machine code and data ready to be loaded at the addresses they were assembled for.
+#[derive(Debug)]
+pub struct Object {
+    pub text: Vec<u8>,
+    pub data: Vec<u8>,
+    /// bytes of zeroed memory after the data.
+    pub bss: usize,
+    /// address of `_start`.
+    pub entry: u64,
+}
+
+enum Fixup {
+    /// a 32-bit displacement relative to the end of the field.
+    Rel32(usize, String),
+    /// a 64-bit absolute address plus an addend.
+    Abs64(usize, String, i64),
+}
+
+/// Encodes a program for code loaded at `text_base` and data at `data_base`. Every operand that
+/// can take a 32-bit displacement or immediate gets one, so instruction sizes never depend on
+/// where labels end up and one pass plus fixups is enough.
+pub fn assemble(program: &Program, text_base: u64, data_base: u64) -> Object {
+    let mut labels = HashMap::new();
+    let mut data = Vec::new();
+    for item in &program.data {
+        while data.len() % 8 != 0 {
+            data.push(0);
+        }
+        labels.insert(item.label.clone(), data_base + data.len() as u64);
+        data.extend(&item.bytes);
+    }
+    while data.len() % 8 != 0 {
+        data.push(0);
+    }
+    let mut bss = 0;
+    for (label, size) in &program.bss {
+        labels.insert(label.clone(), data_base + (data.len() + bss) as u64);
+        bss += size.next_multiple_of(8);
+    }
+
+    let mut asm = Encoder::default();
+    for inst in &program.text {
+        match inst {
+            Inst::Label(label) => {
+                let previous = labels.insert(label.clone(), text_base + asm.code.len() as u64);
+                assert!(previous.is_none(), "label {} is defined twice", label);
+            }
+            Inst::Comment(_) => {}
+            _ => asm.encode(inst),
+        }
+    }
+
+    let address = |label: &str| -> u64 {
+        *labels
+            .get(label)
+            .unwrap_or_else(|| panic!("label {} is never defined", label))
+    };
+    for fixup in &asm.fixups {
+        match fixup {
+            Fixup::Rel32(at, label) => {
+                let next = text_base + *at as u64 + 4;
+                let rel = address(label) as i64 - next as i64;
+                let rel = i32::try_from(rel).expect("code fits in 2GiB");
+                asm.code[*at..*at + 4].copy_from_slice(&rel.to_le_bytes());
+            }
+            Fixup::Abs64(at, label, addend) => {
+                let value = (address(label) as i64).wrapping_add(*addend);
+                asm.code[*at..*at + 8].copy_from_slice(&value.to_le_bytes());
+            }
+        }
+    }
+    for item in &program.data {
+        let start = (address(&item.label) - data_base) as usize;
+        for (offset, label) in &item.relocs {
+            let at = start + offset;
+            data[at..at + 8].copy_from_slice(&address(label).to_le_bytes());
+        }
+    }
+
+    Object {
+        text: asm.code,
+        data,
+        bss,
+        entry: address("_start"),
+    }
+}
+
+#[derive(Default)]
+struct Encoder {
+    code: Vec<u8>,
+    fixups: Vec<Fixup>,
+}
+
+impl Encoder {
+    /// a REX prefix extending `reg` and `rm`, left out when it would be `0x40` unless `force`d,
+    /// which byte operations need to reach `sil` and `dil`.
+    fn rex(&mut self, wide: bool, reg: u8, rm: u8, force: bool) {
+        let rex = 0x40 | (u8::from(wide) << 3) | ((reg >> 3) << 2) | (rm >> 3);
+        if rex != 0x40 || force {
+            self.code.push(rex);
+        }
+    }
+    /// an instruction on two registers, or a register and an opcode extension.
+    fn rr(&mut self, wide: bool, opcode: &[u8], reg: u8, rm: u8) {
+        self.rex(wide, reg, rm, false);
+        self.code.extend(opcode);
+        self.code.push(0xc0 | ((reg & 7) << 3) | (rm & 7));
+    }
+    /// an instruction on a register and `[base + disp32]`.
+    fn rm(&mut self, wide: bool, opcode: &[u8], reg: u8, mem: Mem, force_rex: bool) {
+        let base = mem.base.code();
+        self.rex(wide, reg, base, force_rex);
+        self.code.extend(opcode);
+        self.code.push(0x80 | ((reg & 7) << 3) | (base & 7));
+        // `rsp` and `r12` as a base need a SIB byte
+        if base & 7 == 4 {
+            self.code.push(0x24);
+        }
+        self.code.extend(mem.disp.to_le_bytes());
+    }
+    /// a 32-bit instruction on two registers behind a mandatory prefix, which goes before REX.
+    fn prefixed(&mut self, prefix: u8, opcode: &[u8], reg: u8, rm: u8) {
+        self.code.push(prefix);
+        self.rr(false, opcode, reg, rm);
+    }
+    fn rel32(&mut self, label: &str) {
+        self.fixups
+            .push(Fixup::Rel32(self.code.len(), label.to_owned()));
+        self.code.extend([0; 4]);
+    }
+
+    fn encode(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Label(_) | Inst::Comment(_) => unreachable!("labels and comments take no space"),
+            Inst::Mov(d, s) => self.rr(true, &[0x89], s.code(), d.code()),
+            Inst::MovImm(d, imm) => {
+                self.rex(true, 0, d.code(), false);
+                self.code.push(0xb8 + (d.code() & 7));
+                self.code.extend(imm.to_le_bytes());
+            }
+            Inst::MovAddr(d, label, addend) => {
+                self.rex(true, 0, d.code(), false);
+                self.code.push(0xb8 + (d.code() & 7));
+                self.fixups
+                    .push(Fixup::Abs64(self.code.len(), label.clone(), *addend));
+                self.code.extend([0; 8]);
+            }
+            Inst::Load(d, m) => self.rm(true, &[0x8b], d.code(), *m, false),
+            Inst::Store(m, s) => self.rm(true, &[0x89], s.code(), *m, false),
+            Inst::LoadByte(d, m) => self.rm(false, &[0x0f, 0xb6], d.code(), *m, false),
+            Inst::StoreByte(m, s) => self.rm(false, &[0x88], s.code(), *m, true),
+            Inst::Mov32(d, s) => self.rr(false, &[0x89], s.code(), d.code()),
+            Inst::Alu(op, d, s) => self.rr(true, &[op.digit() << 3 | 1], s.code(), d.code()),
+            Inst::AluImm(op, d, imm) => {
+                self.rr(true, &[0x81], op.digit(), d.code());
+                self.code.extend(imm.to_le_bytes());
+            }
+            Inst::Alu32(op, d, s) => self.rr(false, &[op.digit() << 3 | 1], s.code(), d.code()),
+            Inst::Imul(d, s) => self.rr(true, &[0x0f, 0xaf], d.code(), s.code()),
+            Inst::Imul32(d, s) => self.rr(false, &[0x0f, 0xaf], d.code(), s.code()),
+            Inst::Neg(r) => self.rr(true, &[0xf7], 3, r.code()),
+            Inst::Neg32(r) => self.rr(false, &[0xf7], 3, r.code()),
+            Inst::Cdq => self.code.push(0x99),
+            Inst::Idiv32(r) => self.rr(false, &[0xf7], 7, r.code()),
+            Inst::Div(r) => self.rr(true, &[0xf7], 6, r.code()),
+            Inst::Shl(r, n) => {
+                self.rr(true, &[0xc1], 4, r.code());
+                self.code.push(*n);
+            }
+            Inst::Shr(r, n) => {
+                self.rr(true, &[0xc1], 5, r.code());
+                self.code.push(*n);
+            }
+            Inst::Sar(r, n) => {
+                self.rr(true, &[0xc1], 7, r.code());
+                self.code.push(*n);
+            }
+            Inst::ShrCl(r) => self.rr(true, &[0xd3], 5, r.code()),
+            Inst::Test(a, b) => self.rr(true, &[0x85], b.code(), a.code()),
+            Inst::Cmov(c, d, s) => self.rr(true, &[0x0f, 0x40 + *c as u8], d.code(), s.code()),
+            Inst::Jmp(label) => {
+                self.code.push(0xe9);
+                self.rel32(label);
+            }
+            Inst::JmpReg(r) => self.rr(false, &[0xff], 4, r.code()),
+            Inst::Jcc(c, label) => {
+                self.code.extend([0x0f, 0x80 + *c as u8]);
+                self.rel32(label);
+            }
+            Inst::Call(label) => {
+                self.code.push(0xe8);
+                self.rel32(label);
+            }
+            Inst::CallReg(r) => self.rr(false, &[0xff], 2, r.code()),
+            Inst::Ret => self.code.push(0xc3),
+            Inst::Push(r) => {
+                self.rex(false, 0, r.code(), false);
+                self.code.push(0x50 + (r.code() & 7));
+            }
+            Inst::Pop(r) => {
+                self.rex(false, 0, r.code(), false);
+                self.code.push(0x58 + (r.code() & 7));
+            }
+            Inst::Syscall => self.code.extend([0x0f, 0x05]),
+            Inst::Ud2 => self.code.extend([0x0f, 0x0b]),
+            Inst::MovdToXmm(d, s) => self.prefixed(0x66, &[0x0f, 0x6e], d.code(), s.code()),
+            Inst::MovdFromXmm(d, s) => self.prefixed(0x66, &[0x0f, 0x7e], s.code(), d.code()),
+            Inst::Cvtsi2ss(d, s) => self.prefixed(0xf3, &[0x0f, 0x2a], d.code(), s.code()),
+            Inst::Cvttss2si(d, s) => self.prefixed(0xf3, &[0x0f, 0x2c], d.code(), s.code()),
+            Inst::Sse(op, d, s) => self.prefixed(0xf3, &[0x0f, op.opcode()], d.code(), s.code()),
+            Inst::Ucomiss(a, b) => self.rr(false, &[0x0f, 0x2e], a.code(), b.code()),
+        }
+    }
+}
+
+/// the bytes of a single instruction, for tests.
+#[cfg(test)]
+pub fn encode(inst: &Inst) -> Vec<u8> {
+    let mut encoder = Encoder::default();
+    encoder.encode(inst);
+    encoder.code
+}
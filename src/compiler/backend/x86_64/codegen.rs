@@ -0,0 +1,476 @@
+use crate::compiler::{
+    ast::{
+        expr::{BinaryOp, UnaryOp},
+        literal::Literal,
+    },
+    backend::{
+        x86_64::{
+            asm::{AluOp, Cond, Data, Inst, Mem, Program, Reg},
+            regalloc::{self, Allocation, Location},
+            runtime::{self, routine},
+        },
+        BackendErr,
+    },
+    ir::{BlockId, FuncId, Function, Module, Op, Terminator, ValueId},
+};
+
+const TARGET: &str = "x86_64-linux";
+
+/// Generates code for every function of the module, plus an entry point that runs the top-level
+/// code and exits. Values live where the register allocator puts them; instructions compute in
+/// scratch registers and move the result to its location. Comments give the position and live
+/// interval of every value for reading the assembly.
+pub fn generate(module: &Module) -> Result<Program, BackendErr> {
+    let mut program = Program::default();
+    program.text.extend([
+        Inst::Label("_start".to_owned()),
+        Inst::Call(function_label(FuncId(0))),
+        Inst::MovImm(Reg::Rax, 60),
+        Inst::MovImm(Reg::Rdi, 0),
+        Inst::Syscall,
+    ]);
+    for (id, func) in module.functions.iter().enumerate() {
+        FnGen {
+            program: &mut program,
+            module,
+            id: FuncId(id),
+            func,
+            alloc: regalloc::allocate(func),
+        }
+        .function()?;
+
+        // a function value points at its descriptor: code, arity, name
+        let name = func.name.split('#').next().unwrap_or(&func.name);
+        let name = name.rsplit('.').next().unwrap_or(name);
+        let name = program.string(name);
+        let mut bytes = vec![0; 8];
+        bytes.extend((func.params as u64).to_le_bytes());
+        bytes.extend([0; 8]);
+        program.data.push(Data {
+            label: descriptor_label(FuncId(id)),
+            bytes,
+            relocs: vec![(0, function_label(FuncId(id))), (16, name)],
+        });
+    }
+    runtime::emit(&mut program, &module.globals);
+    Ok(program)
+}
+
+fn unsupported(feature: &str) -> BackendErr {
+    BackendErr::Unsupported {
+        target: TARGET,
+        feature: feature.to_owned(),
+    }
+}
+
+fn function_label(id: FuncId) -> String {
+    format!("fn{}", id.0)
+}
+
+fn descriptor_label(id: FuncId) -> String {
+    format!("fn{}_desc", id.0)
+}
+
+struct FnGen<'a> {
+    program: &'a mut Program,
+    module: &'a Module,
+    id: FuncId,
+    func: &'a Function,
+    alloc: Allocation,
+}
+
+impl FnGen<'_> {
+    fn emit(&mut self, insts: impl IntoIterator<Item = Inst>) {
+        self.program.text.extend(insts);
+    }
+    fn block_label(&self, block: BlockId) -> String {
+        format!("fn{}_{}", self.id.0, block)
+    }
+    /// parameters are spilled below the saved registers, spilled values below them.
+    fn param_slot(&self, index: usize) -> Mem {
+        let below = self.alloc.saved.len() + index + 1;
+        Mem::new(Reg::Rbp, -8 * below as i32)
+    }
+    /// where a function with cells keeps the address of the closure it was called through.
+    fn closure_slot(&self) -> Mem {
+        self.param_slot(self.func.params + self.alloc.spill_slots)
+    }
+    fn load(&mut self, reg: Reg, value: ValueId) {
+        match self.alloc.location(value) {
+            Location::Reg(r) if r == reg => {}
+            Location::Reg(r) => self.emit([Inst::Mov(reg, r)]),
+            Location::Stack(slot) => {
+                let slot = self.param_slot(self.func.params + slot);
+                self.emit([Inst::Load(reg, slot)]);
+            }
+        }
+    }
+    fn store(&mut self, value: ValueId, reg: Reg) {
+        match self.alloc.location(value) {
+            Location::Reg(r) if r == reg => {}
+            Location::Reg(r) => self.emit([Inst::Mov(r, reg)]),
+            Location::Stack(slot) => {
+                let slot = self.param_slot(self.func.params + slot);
+                self.emit([Inst::Store(slot, reg)]);
+            }
+        }
+    }
+    /// the register to compute a value in: its own if it has one.
+    fn target(&self, value: ValueId) -> Reg {
+        match self.alloc.location(value) {
+            Location::Reg(reg) => reg,
+            Location::Stack(_) => Reg::Rax,
+        }
+    }
+
+    fn function(&mut self) -> Result<(), BackendErr> {
+        let saved = self.alloc.saved.clone();
+        let closure = usize::from(self.func.captures > 0);
+        let slots = self.func.params + self.alloc.spill_slots + closure;
+        // keeps `rsp` 16-byte aligned at calls, as it is right after `push rbp`
+        let frame = (8 * (saved.len() + slots)).next_multiple_of(16) - 8 * saved.len();
+        let frame = i32::try_from(frame).map_err(|_| unsupported("functions this large"))?;
+        self.emit([
+            Inst::Label(function_label(self.id)),
+            Inst::Comment(self.func.name.clone()),
+        ]);
+        for interval in &self.alloc.intervals {
+            let comment = format!(
+                "{} [{}, {}] {}",
+                interval.value,
+                interval.start,
+                interval.end,
+                self.alloc.location(interval.value)
+            );
+            self.program.text.push(Inst::Comment(comment));
+        }
+        self.emit([Inst::Push(Reg::Rbp), Inst::Mov(Reg::Rbp, Reg::Rsp)]);
+        self.emit(saved.iter().map(|reg| Inst::Push(*reg)));
+        if frame > 0 {
+            self.emit([Inst::AluImm(AluOp::Sub, Reg::Rsp, frame)]);
+        }
+        for (i, reg) in Reg::ARGS.iter().take(self.func.params).enumerate() {
+            let slot = self.param_slot(i);
+            self.emit([Inst::Store(slot, *reg)]);
+        }
+        // the rest were passed on the stack, above the return address
+        for i in Reg::ARGS.len()..self.func.params {
+            let slot = self.param_slot(i);
+            let passed = Mem::new(Reg::Rbp, 16 + 8 * (i - Reg::ARGS.len()) as i32);
+            self.emit([Inst::Load(Reg::R11, passed), Inst::Store(slot, Reg::R11)]);
+        }
+        if closure > 0 {
+            let slot = self.closure_slot();
+            self.emit([Inst::Store(slot, Reg::Rax)]);
+        }
+        for (b, block) in self.func.blocks.iter().enumerate() {
+            let label = self.block_label(BlockId(b));
+            self.emit([Inst::Label(label)]);
+            for value in &block.insts {
+                let comment = format!(
+                    "{}: {}",
+                    self.alloc.positions.inst(*value),
+                    self.module.display_inst(self.func, *value)
+                );
+                self.emit([Inst::Comment(comment)]);
+                self.op(*value)?;
+            }
+            let comment = format!("{}: {}", self.alloc.positions.terms[b], block.term);
+            self.emit([Inst::Comment(comment)]);
+            self.terminator(BlockId(b))?;
+        }
+        Ok(())
+    }
+
+    fn op(&mut self, value: ValueId) -> Result<(), BackendErr> {
+        use Inst::*;
+        use Reg::*;
+        match self.func.op(value) {
+            Op::Const(literal) => {
+                let reg = self.target(value);
+                match literal {
+                    Literal::Nil => self.emit([MovImm(reg, runtime::NIL)]),
+                    Literal::Bool(b) => {
+                        let b = if *b { runtime::TRUE } else { runtime::FALSE };
+                        self.emit([MovImm(reg, b)]);
+                    }
+                    Literal::Int(i) => self.emit([MovImm(reg, runtime::int(*i))]),
+                    Literal::Str(s) => {
+                        let label = self.program.string(s);
+                        self.emit([MovAddr(reg, label, runtime::TAG_STR << 32)]);
+                    }
+                    Literal::Float(f) => self.emit([MovImm(reg, runtime::float(*f))]),
+                }
+                self.store(value, reg);
+            }
+            Op::Param(index) => {
+                let reg = self.target(value);
+                let slot = self.param_slot(*index);
+                self.emit([Load(reg, slot)]);
+                self.store(value, reg);
+            }
+            // assigned by the predecessors
+            Op::Phi(_) => {}
+            Op::Copy(source) => {
+                if self.alloc.location(*source) != self.alloc.location(value) {
+                    let reg = self.target(value);
+                    self.load(reg, *source);
+                    self.store(value, reg);
+                }
+            }
+            Op::Unary(op, operand) => {
+                self.load(Rdi, *operand);
+                let routine = match op {
+                    UnaryOp::Bang => routine::NOT,
+                    UnaryOp::Negate => routine::NEG,
+                };
+                self.emit([Call(routine.to_owned())]);
+                self.store(value, Rax);
+            }
+            Op::Binary(op, lhs, rhs) => {
+                self.load(Rdi, *lhs);
+                self.load(Rsi, *rhs);
+                self.emit([Call(binary_routine(*op).to_owned())]);
+                self.store(value, Rax);
+            }
+            Op::LoadGlobal(index) if *index < self.module.natives => {
+                let name = &self.module.globals[*index];
+                return Err(unsupported(&format!("the native '{}'", name)));
+            }
+            Op::LoadGlobal(index) => {
+                self.emit([
+                    MovAddr(Rcx, runtime::GLOBALS.to_owned(), 8 * *index as i64),
+                    Load(Rax, Mem::new(Rcx, 0)),
+                    MovImm(Rdx, runtime::TAG_UNDEFINED << 32),
+                    MovImm(Rdi, *index as i64),
+                    Alu(AluOp::Cmp, Rax, Rdx),
+                    Jcc(Cond::E, routine::UNDEFINED.to_owned()),
+                ]);
+                self.store(value, Rax);
+            }
+            Op::StoreGlobal(index, source) => {
+                self.load(Rax, *source);
+                self.emit([
+                    MovAddr(Rcx, runtime::GLOBALS.to_owned(), 8 * *index as i64),
+                    Store(Mem::new(Rcx, 0), Rax),
+                ]);
+            }
+            Op::Closure(func, cells) if cells.is_empty() => {
+                self.emit([MovAddr(Rax, descriptor_label(*func), runtime::TAG_FN << 32)]);
+                self.store(value, Rax);
+            }
+            Op::Closure(func, cells) => {
+                let params = self.module.functions[func.0].params;
+                self.emit([
+                    MovImm(Rax, 24 + 8 * cells.len() as i64),
+                    Call(routine::ALLOC.to_owned()),
+                    MovAddr(Rcx, function_label(*func), 0),
+                    Store(Mem::new(Rax, 0), Rcx),
+                    MovImm(Rcx, params as i64),
+                    Store(Mem::new(Rax, 8), Rcx),
+                    MovAddr(Rcx, descriptor_label(*func), 0),
+                    Load(Rcx, Mem::new(Rcx, 16)),
+                    Store(Mem::new(Rax, 16), Rcx),
+                ]);
+                for (i, cell) in cells.iter().enumerate() {
+                    self.load(Rcx, *cell);
+                    self.emit([Store(Mem::new(Rax, 24 + 8 * i as i32), Rcx)]);
+                }
+                self.emit([MovImm(Rcx, runtime::TAG_FN << 32), Alu(AluOp::Or, Rax, Rcx)]);
+                self.store(value, Rax);
+            }
+            Op::Capture(index) => {
+                let slot = self.closure_slot();
+                self.emit([
+                    Load(Rax, slot),
+                    Load(Rax, Mem::new(Rax, 24 + 8 * *index as i32)),
+                ]);
+                self.store(value, Rax);
+            }
+            Op::NewCell(initial) => {
+                self.load(Rcx, *initial);
+                self.emit([
+                    MovImm(Rax, 8),
+                    Call(routine::ALLOC.to_owned()),
+                    Store(Mem::new(Rax, 0), Rcx),
+                ]);
+                self.store(value, Rax);
+            }
+            Op::LoadCell(cell) => {
+                self.load(Rax, *cell);
+                self.emit([Load(Rax, Mem::new(Rax, 0))]);
+                self.store(value, Rax);
+            }
+            Op::StoreCell(cell, source) => {
+                self.load(Rax, *cell);
+                self.load(Rcx, *source);
+                self.emit([Store(Mem::new(Rax, 0), Rcx)]);
+            }
+            Op::Call(callee, args) => {
+                let bytes = 8 * runtime::stack_words(args.len());
+                let bytes = i32::try_from(bytes).map_err(|_| unsupported("calls this large"))?;
+                if bytes > 0 {
+                    self.emit([AluImm(AluOp::Sub, Rsp, bytes)]);
+                }
+                for (i, arg) in args.iter().enumerate().skip(Reg::ARGS.len()) {
+                    self.load(Rax, *arg);
+                    let passed = Mem::new(Rsp, 8 * (i - Reg::ARGS.len()) as i32);
+                    self.emit([Store(passed, Rax)]);
+                }
+                self.load(Rax, *callee);
+                for (arg, reg) in args.iter().zip(Reg::ARGS) {
+                    self.load(reg, *arg);
+                }
+                self.emit([
+                    MovImm(R11, args.len() as i64),
+                    Call(routine::CALL.to_owned()),
+                ]);
+                if bytes > 0 {
+                    self.emit([AluImm(AluOp::Add, Rsp, bytes)]);
+                }
+                self.store(value, Rax);
+            }
+            Op::Print(operand) => {
+                self.load(Rdi, *operand);
+                self.emit([Call(routine::PRINT.to_owned())]);
+            }
+            Op::Class(name, methods) => {
+                let name = self.program.string(name);
+                self.emit([
+                    MovImm(Rax, 16 + 16 * methods.len() as i64),
+                    Call(routine::ALLOC.to_owned()),
+                    MovAddr(Rcx, name, 0),
+                    Store(Mem::new(Rax, 0), Rcx),
+                    MovImm(Rcx, methods.len() as i64),
+                    Store(Mem::new(Rax, 8), Rcx),
+                ]);
+                for (i, (name, method)) in methods.iter().enumerate() {
+                    let name = self.program.string(name);
+                    let at = 16 + 16 * i as i32;
+                    self.emit([MovAddr(Rcx, name, 0), Store(Mem::new(Rax, at), Rcx)]);
+                    self.load(Rcx, *method);
+                    self.emit([Mov32(Rcx, Rcx), Store(Mem::new(Rax, at + 8), Rcx)]);
+                }
+                self.emit([
+                    MovImm(Rcx, runtime::TAG_CLASS << 32),
+                    Alu(AluOp::Or, Rax, Rcx),
+                ]);
+                self.store(value, Rax);
+            }
+            Op::GetProp(object, name) => {
+                self.load(Rdi, *object);
+                let name = self.program.string(name);
+                self.emit([MovAddr(Rsi, name, 0), Call(routine::GET.to_owned())]);
+                self.store(value, Rax);
+            }
+            Op::SetProp(object, name, source) => {
+                self.load(Rdi, *object);
+                self.load(Rdx, *source);
+                let name = self.program.string(name);
+                self.emit([MovAddr(Rsi, name, 0), Call(routine::SET.to_owned())]);
+            }
+            Op::List(_)
+            | Op::Map(_)
+            | Op::GetIndex(..)
+            | Op::SetIndex(..)
+            | Op::Len(_)
+            | Op::Element(..) => return Err(unsupported("lists and maps")),
+            Op::Caught | Op::EndTry => return Err(unsupported("exceptions")),
+        }
+        Ok(())
+    }
+
+    fn terminator(&mut self, block: BlockId) -> Result<(), BackendErr> {
+        use Inst::*;
+        use Reg::*;
+        match &self.func.blocks[block.0].term {
+            Terminator::Jump(target) => {
+                self.phi_moves(block, *target);
+                let label = self.block_label(*target);
+                self.emit([Jmp(label)]);
+            }
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => {
+                // `nil` and `false` are the falsy values
+                let falsy = format!("{}_else", self.block_label(block));
+                self.load(Rax, *cond);
+                self.emit([
+                    Test(Rax, Rax),
+                    Jcc(Cond::E, falsy.clone()),
+                    MovImm(Rcx, runtime::FALSE),
+                    Alu(AluOp::Cmp, Rax, Rcx),
+                    Jcc(Cond::E, falsy.clone()),
+                ]);
+                self.phi_moves(block, *then);
+                let then = self.block_label(*then);
+                self.emit([Jmp(then), Label(falsy)]);
+                self.phi_moves(block, *otherwise);
+                let otherwise = self.block_label(*otherwise);
+                self.emit([Jmp(otherwise)]);
+            }
+            Terminator::Return(value) => {
+                self.load(Rax, *value);
+                self.emit([Mov(Rsp, Rbp)]);
+                let saved = self.alloc.saved.clone();
+                if !saved.is_empty() {
+                    self.emit([AluImm(AluOp::Sub, Rsp, 8 * saved.len() as i32)]);
+                }
+                self.emit(saved.iter().rev().map(|reg| Pop(*reg)));
+                self.emit([Pop(Rbp), Ret]);
+            }
+            Terminator::Unreachable => self.emit([Ud2]),
+            Terminator::Throw(_) | Terminator::Try { .. } => return Err(unsupported("exceptions")),
+        }
+        Ok(())
+    }
+
+    /// assigns the `phi`s of `to` the values they take coming from `from`. All of them are read
+    /// before any is written, since one `phi` may be another's operand. Moves the allocator
+    /// coalesced are left out.
+    fn phi_moves(&mut self, from: BlockId, to: BlockId) {
+        let mut moves = Vec::new();
+        for value in &self.func.blocks[to.0].insts {
+            let Op::Phi(incoming) = self.func.op(*value) else {
+                break;
+            };
+            if let Some((_, source)) = incoming.iter().find(|(pred, _)| *pred == from) {
+                if self.alloc.location(*value) != self.alloc.location(*source) {
+                    moves.push((*value, *source));
+                }
+            }
+        }
+        if let [(phi, source)] = moves[..] {
+            let reg = self.target(phi);
+            self.load(reg, source);
+            self.store(phi, reg);
+            return;
+        }
+        for (_, source) in &moves {
+            self.load(Reg::Rax, *source);
+            self.emit([Inst::Push(Reg::Rax)]);
+        }
+        for (phi, _) in moves.iter().rev() {
+            self.emit([Inst::Pop(Reg::Rax)]);
+            self.store(*phi, Reg::Rax);
+        }
+    }
+}
+
+fn binary_routine(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Plus => routine::ADD,
+        BinaryOp::Minus => routine::SUB,
+        BinaryOp::Mult => routine::MUL,
+        BinaryOp::Div => routine::DIV,
+        BinaryOp::EqEq => routine::EQ,
+        BinaryOp::BangEq => routine::NE,
+        BinaryOp::Lt => routine::LT,
+        BinaryOp::LtEq => routine::LE,
+        BinaryOp::Gt => routine::GT,
+        BinaryOp::GtEq => routine::GE,
+        BinaryOp::Eq => unreachable!("assignment is not an operator in the IR"),
+    }
+}
@@ -0,0 +1,32 @@
+//! Native code for x86-64 Linux: the IR is translated to instructions, encoded by our own
+//! assembler together with the runtime, and written out as a static ELF executable that needs
+//! neither a linker nor libc.
+
+pub mod asm;
+mod codegen;
+mod regalloc;
+mod runtime;
+#[cfg(test)]
+mod tests;
+
+use crate::compiler::{
+    backend::{elf, BackendErr},
+    ir::Module,
+};
+
+/// the assembly of the module, with its runtime, annotated with where every value lives.
+pub fn assembly(module: &Module) -> Result<asm::Program, BackendErr> {
+    codegen::generate(module)
+}
+
+/// the bytes of an executable running the module.
+pub fn compile(module: &Module) -> Result<Vec<u8>, BackendErr> {
+    let program = codegen::generate(module)?;
+    let object = asm::assemble(&program, elf::TEXT_START, elf::DATA_VADDR);
+    Ok(elf::executable(
+        &object.text,
+        &object.data,
+        object.bss,
+        object.entry,
+    ))
+}
@@ -0,0 +1,155 @@
+use std::collections::HashMap;
+
+use crate::compiler::{
+    backend::x86_64::asm::Reg,
+    ir::{
+        liveness::{self, Interval, Positions},
+        Function, Op, ValueId,
+    },
+};
+
+/// registers the runtime routines and other functions may clobber, so only values not live across
+/// a call can have them. The rest of the caller-saved registers are scratch for the code generator.
+const CALLER_SAVED: [Reg; 2] = [Reg::R10, Reg::R11];
+/// registers a function must restore before returning if it uses them.
+const CALLEE_SAVED: [Reg; 5] = [Reg::Rbx, Reg::R12, Reg::R13, Reg::R14, Reg::R15];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Reg(Reg),
+    /// an index among the function's spill slots.
+    Stack(usize),
+}
+
+impl std::fmt::Display for Location {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Location::Reg(reg) => write!(f, "{}", reg),
+            Location::Stack(slot) => write!(f, "stack {}", slot),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Allocation {
+    pub positions: Positions,
+    pub intervals: Vec<Interval>,
+    pub locations: HashMap<ValueId, Location>,
+    pub spill_slots: usize,
+    /// the callee-saved registers the function uses, which its prologue saves.
+    pub saved: Vec<Reg>,
+}
+
+impl Allocation {
+    pub fn location(&self, value: ValueId) -> Location {
+        self.locations[&value]
+    }
+}
+
+/// Assigns every value of the function a register or a stack slot by linear scan over its live
+/// intervals. Values live across a call only get callee-saved registers. When registers run out,
+/// whichever competing interval ends last is spilled to the stack for its whole lifetime. A `copy`
+/// or `phi` prefers the register of a value it moves, so the move can be dropped.
+pub fn allocate(func: &Function) -> Allocation {
+    let positions = Positions::new(func);
+    let intervals = liveness::intervals(func, &positions);
+    let mut calls: Vec<usize> = func
+        .blocks
+        .iter()
+        .flat_map(|b| &b.insts)
+        .filter(|v| {
+            matches!(
+                func.op(**v),
+                Op::Unary(..)
+                    | Op::Binary(..)
+                    | Op::Call(..)
+                    | Op::Print(_)
+                    | Op::GetProp(..)
+                    | Op::SetProp(..)
+            )
+        })
+        .map(|v| positions.inst(*v))
+        .collect();
+    calls.sort();
+    let crosses_call = |interval: &Interval| {
+        let first = calls.partition_point(|p| *p <= interval.start);
+        calls.get(first).is_some_and(|p| *p < interval.end)
+    };
+
+    let mut hints: HashMap<ValueId, Vec<ValueId>> = HashMap::new();
+    for value in func.blocks.iter().flat_map(|b| &b.insts) {
+        match func.op(*value) {
+            Op::Copy(source) => hints.entry(*value).or_default().push(*source),
+            Op::Phi(incoming) => {
+                for (_, source) in incoming {
+                    hints.entry(*value).or_default().push(*source);
+                    hints.entry(*source).or_default().push(*value);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let mut locations = HashMap::new();
+    let mut spill_slots = 0;
+    let mut spill = |locations: &mut HashMap<ValueId, Location>, value: ValueId| {
+        locations.insert(value, Location::Stack(spill_slots));
+        spill_slots += 1;
+    };
+    // intervals holding a register, with the register
+    let mut active: Vec<(Interval, Reg)> = Vec::new();
+    for interval in &intervals {
+        // a value dying where another is defined can hand over its register: instructions read
+        // their operands before writing their result, and `phi` moves read every source first
+        active.retain(|(other, _)| other.end > interval.start);
+        let candidates: Vec<Reg> = match crosses_call(interval) {
+            true => CALLEE_SAVED.to_vec(),
+            false => CALLER_SAVED.iter().chain(&CALLEE_SAVED).copied().collect(),
+        };
+        let free: Vec<Reg> = candidates
+            .iter()
+            .copied()
+            .filter(|reg| active.iter().all(|(_, r)| r != reg))
+            .collect();
+        let hinted = hints
+            .get(&interval.value)
+            .into_iter()
+            .flatten()
+            .find_map(|v| match locations.get(v) {
+                Some(Location::Reg(reg)) if free.contains(reg) => Some(*reg),
+                _ => None,
+            });
+        if let Some(reg) = hinted.or(free.first().copied()) {
+            locations.insert(interval.value, Location::Reg(reg));
+            active.push((*interval, reg));
+            continue;
+        }
+        let victim = active
+            .iter()
+            .enumerate()
+            .filter(|(_, (_, reg))| candidates.contains(reg))
+            .max_by_key(|(_, (other, _))| other.end)
+            .map(|(i, (other, _))| (i, other.end));
+        match victim {
+            Some((i, end)) if end > interval.end => {
+                let (other, reg) = active.remove(i);
+                spill(&mut locations, other.value);
+                locations.insert(interval.value, Location::Reg(reg));
+                active.push((*interval, reg));
+            }
+            _ => spill(&mut locations, interval.value),
+        }
+    }
+
+    let saved = CALLEE_SAVED
+        .into_iter()
+        .filter(|reg| locations.values().any(|l| *l == Location::Reg(*reg)))
+        .collect();
+    Allocation {
+        positions,
+        intervals,
+        locations,
+        spill_slots,
+        saved,
+    }
+}
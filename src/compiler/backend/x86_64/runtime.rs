@@ -0,0 +1,1567 @@
+//! The runtime compiled programs are linked against: printing, the operators, string
+//! concatenation and runtime errors. It is written here as code for our own assembler rather than
+//! compiled separately, so building an executable needs nothing but this crate. Routines take
+//! their operands in `rdi` and `rsi`, return in `rax` and may clobber every caller-saved register.
+//!
+//! A value is one 64-bit word: a type tag in the upper half and the payload in the lower one, an
+//! `int` or the bits of a `float` itself, or the address of a string object or function. Addresses
+//! fit, since everything is loaded below 4GiB and the heap is mapped below 2GiB. A function is its
+//! code, arity and name, followed by the cells it captured, if any; those with no cells are static
+//! descriptors, the rest are allocated when created. A cell is the address of the word holding a
+//! captured variable.
+//!
+//! A class is its name, its number of methods and then each method's name and function. An
+//! instance is its class, its number of fields, their capacity and the address of the fields'
+//! names and values, in pairs. A method read from an instance is bound to it: the function
+//! followed by the instance. Property names are the string objects of the program's constants,
+//! which are shared, so their addresses are compared rather than their bytes.
+//!
+//! Float arithmetic is SSE2 on the operands moved into `xmm0` and `xmm1`, and no routine preserves
+//! the SSE registers; compiled code keeps nothing in them.
+
+use crate::compiler::{
+    backend::x86_64::asm::{AluOp, Cond, Data, Inst, Mem, Program, Reg, SseOp, Xmm},
+    eval::EvalErr,
+};
+
+pub const TAG_BOOL: i64 = 1;
+pub const TAG_INT: i64 = 2;
+pub const TAG_STR: i64 = 3;
+pub const TAG_FN: i64 = 4;
+pub const TAG_CLASS: i64 = 5;
+pub const TAG_INSTANCE: i64 = 6;
+/// a global that has not been defined yet.
+pub const TAG_UNDEFINED: i64 = 7;
+/// a bound method.
+pub const TAG_METHOD: i64 = 8;
+pub const TAG_FLOAT: i64 = 9;
+
+pub const NIL: i64 = 0;
+pub const FALSE: i64 = TAG_BOOL << 32;
+pub const TRUE: i64 = TAG_BOOL << 32 | 1;
+
+pub fn int(i: i32) -> i64 {
+    TAG_INT << 32 | i as u32 as i64
+}
+
+pub fn float(f: f32) -> i64 {
+    TAG_FLOAT << 32 | f.to_bits() as i64
+}
+
+/// The words of stack a call with `args` arguments passes the ones after the sixth in, from the
+/// lowest address up. There is one to spare, for when a bound method or `init` gets its instance
+/// as the first argument and the sixth has to move to the stack too, and the count is even, so
+/// `rsp` stays aligned.
+pub fn stack_words(args: usize) -> usize {
+    match args < Reg::ARGS.len() {
+        true => 0,
+        false => (args + 1 - Reg::ARGS.len()).next_multiple_of(2),
+    }
+}
+
+/// the label of the `n`th global's word.
+pub const GLOBALS: &str = "rt_globals";
+
+/// memory for strings, functions and cells built at runtime is mapped in chunks of at least this
+/// many bytes, as the program needs them. Nothing is freed.
+const HEAP_CHUNK: i64 = 16 << 20;
+
+/// the bits Ryu keeps of `2^k / 5^q` and of `5^i` for `f32`.
+const POW5_INV_BITS: u32 = 59;
+const POW5_BITS: u32 = 61;
+
+/// Ryu's tables for `f32`, as little-endian words: `2^k / 5^q` rounded up for `q` below 31, with
+/// `k` leaving `POW5_INV_BITS` bits, and the top `POW5_BITS` bits of `5^i` for `i` below 48.
+fn ryu_tables() -> (Vec<u8>, Vec<u8>) {
+    let mut inverses = Vec::new();
+    let mut powers = Vec::new();
+    let mut power: u128 = 1;
+    for i in 0..48 {
+        let bits = 128 - power.leading_zeros();
+        if i < 31 {
+            let inverse = match bits - 1 + POW5_INV_BITS {
+                128 => u128::MAX / power,
+                shift => (1 << shift) / power,
+            };
+            inverses.extend((inverse as u64 + 1).to_le_bytes());
+        }
+        let top = match bits > POW5_BITS {
+            true => power >> (bits - POW5_BITS),
+            false => power << (POW5_BITS - bits),
+        };
+        powers.extend((top as u64).to_le_bytes());
+        power *= 5;
+    }
+    (inverses, powers)
+}
+
+/// labels of the routines behind the operators.
+pub mod routine {
+    pub const PRINT: &str = "rt_print";
+    pub const NOT: &str = "rt_not";
+    pub const NEG: &str = "rt_neg";
+    pub const ADD: &str = "rt_add";
+    pub const SUB: &str = "rt_sub";
+    pub const MUL: &str = "rt_mul";
+    pub const DIV: &str = "rt_div";
+    pub const EQ: &str = "rt_eq";
+    pub const NE: &str = "rt_ne";
+    pub const LT: &str = "rt_lt";
+    pub const LE: &str = "rt_le";
+    pub const GT: &str = "rt_gt";
+    pub const GE: &str = "rt_ge";
+    /// calls the value in `rax` with the `r11` arguments in `Reg::ARGS` and, past the sixth, on
+    /// the stack as `stack_words` lays them out, passing the function itself in `rax` so it can
+    /// reach its cells.
+    pub const CALL: &str = "rt_call";
+    /// the property named by the string object in `rsi` of the instance in `rdi`.
+    pub const GET: &str = "rt_get";
+    /// sets the property named by the string object in `rsi` of the instance in `rdi` to `rdx`.
+    pub const SET: &str = "rt_set";
+    /// allocates `rax` bytes, a multiple of eight, and returns their address in `rax`. Unlike the
+    /// other routines it preserves every other register.
+    pub const ALLOC: &str = "rt_alloc";
+    /// reports calling something that is not a function.
+    pub const NOT_CALLABLE: &str = "rt_err_call";
+    /// reports a call with the wrong number of arguments, expected in `rdi` and given in `rsi`.
+    pub const ARITY: &str = "rt_arity";
+    /// reports reading the global whose index is in `rdi` before it is defined.
+    pub const UNDEFINED: &str = "rt_undefined";
+}
+
+/// adds the runtime's routines and data to `program`.
+pub fn emit(program: &mut Program, globals: &[String]) {
+    // every global starts out undefined
+    let mut words = Vec::new();
+    let mut names = Data {
+        label: "rt_global_names".to_owned(),
+        bytes: Vec::new(),
+        relocs: Vec::new(),
+    };
+    for (i, name) in globals.iter().enumerate() {
+        words.extend((TAG_UNDEFINED << 32).to_le_bytes());
+        names.bytes.extend([0; 8]);
+        names.relocs.push((8 * i, program.string(name)));
+    }
+    program.data.push(Data {
+        label: GLOBALS.to_owned(),
+        bytes: words,
+        relocs: Vec::new(),
+    });
+    program.data.push(names);
+    // the free part of the current chunk, empty until the first allocation maps one
+    program.bss.push(("rt_heap_ptr".to_owned(), 8));
+    program.bss.push(("rt_heap_end".to_owned(), 8));
+    program.bss.push(("rt_itoa_buf".to_owned(), 16));
+    // the longest float is `0.`, 44 zeros and 9 digits
+    program.bss.push(("rt_float_buf".to_owned(), 64));
+
+    let mut rt = Runtime { program };
+    rt.io();
+    rt.errors();
+    rt.print();
+    rt.floats();
+    rt.arithmetic();
+    rt.comparisons();
+    rt.concat();
+    rt.alloc();
+    rt.call();
+    rt.properties();
+
+    rt.not();
+}
+
+struct Runtime<'a> {
+    program: &'a mut Program,
+}
+
+impl Runtime<'_> {
+    fn emit(&mut self, insts: impl IntoIterator<Item = Inst>) {
+        self.program.text.extend(insts);
+    }
+    /// writes a constant string to stdout, or stderr if `err`.
+    fn write(&mut self, s: &str, err: bool) {
+        let label = self.program.string(s);
+        self.emit([
+            Inst::MovAddr(Reg::Rsi, label, 8),
+            Inst::MovImm(Reg::Rdx, s.len() as i64),
+            Inst::Call(if err { "rt_write_err" } else { "rt_write_out" }.to_owned()),
+        ]);
+    }
+    /// writes the string object whose address is in `rsi`.
+    fn write_object(&mut self, err: bool) {
+        self.emit([
+            Inst::Load(Reg::Rdx, Mem::new(Reg::Rsi, 0)),
+            Inst::AluImm(AluOp::Add, Reg::Rsi, 8),
+            Inst::Call(if err { "rt_write_err" } else { "rt_write_out" }.to_owned()),
+        ]);
+    }
+    fn exit(&mut self, code: i64) {
+        self.emit([
+            Inst::MovImm(Reg::Rax, 60),
+            Inst::MovImm(Reg::Rdi, code),
+            Inst::Syscall,
+        ]);
+    }
+    /// jumps to `error` unless the value in `reg` has tag `tag`. Clobbers `rax`.
+    fn expect_tag(&mut self, reg: Reg, tag: i64, error: &str) {
+        self.emit([
+            Inst::Mov(Reg::Rax, reg),
+            Inst::Shr(Reg::Rax, 32),
+            Inst::AluImm(AluOp::Cmp, Reg::Rax, tag as i32),
+            Inst::Jcc(Cond::Ne, error.to_owned()),
+        ]);
+    }
+    /// tags the `int` in `eax`.
+    fn tag_int(&mut self) {
+        self.emit([
+            Inst::MovImm(Reg::Rcx, TAG_INT << 32),
+            Inst::Alu(AluOp::Or, Reg::Rax, Reg::Rcx),
+            Inst::Ret,
+        ]);
+    }
+
+    fn io(&mut self) {
+        use Inst::*;
+        use Reg::*;
+        for (label, fd) in [("rt_write_out", 1), ("rt_write_err", 2)] {
+            self.emit([
+                Label(label.to_owned()),
+                MovImm(Rax, 1),
+                MovImm(Rdi, fd),
+                Syscall,
+                Ret,
+            ]);
+        }
+
+        // the decimal digits of the `int` in `edi`, at `rsi` with length `rdx`
+        self.emit([
+            Label("rt_itoa".to_owned()),
+            Mov32(Rax, Rdi),
+            Shl(Rax, 32),
+            Sar(Rax, 32),
+            MovImm(R8, 0),
+            Test(Rax, Rax),
+            Jcc(Cond::Ge, "rt_itoa.positive".to_owned()),
+            Neg(Rax),
+            MovImm(R8, 1),
+            Label("rt_itoa.positive".to_owned()),
+            MovAddr(Rsi, "rt_itoa_buf".to_owned(), 16),
+            MovImm(Rcx, 10),
+            Label("rt_itoa.digit".to_owned()),
+            Alu(AluOp::Xor, Rdx, Rdx),
+            Div(Rcx),
+            AluImm(AluOp::Add, Rdx, b'0' as i32),
+            AluImm(AluOp::Sub, Rsi, 1),
+            StoreByte(Mem::new(Rsi, 0), Rdx),
+            Test(Rax, Rax),
+            Jcc(Cond::Ne, "rt_itoa.digit".to_owned()),
+            Test(R8, R8),
+            Jcc(Cond::E, "rt_itoa.done".to_owned()),
+            AluImm(AluOp::Sub, Rsi, 1),
+            MovImm(Rdx, b'-' as i64),
+            StoreByte(Mem::new(Rsi, 0), Rdx),
+            Label("rt_itoa.done".to_owned()),
+            MovAddr(Rdx, "rt_itoa_buf".to_owned(), 16),
+            Alu(AluOp::Sub, Rdx, Rsi),
+            Ret,
+        ]);
+    }
+
+    /// Runtime errors print the interpreter's message and exit with status 1. They are jumped to,
+    /// never called.
+    fn errors(&mut self) {
+        use Inst::*;
+        use Reg::*;
+        // the message is the string object at `rsi`
+        self.emit([Label("rt_error".to_owned()), Push(Rsi)]);
+        self.write("error: ", true);
+        self.emit([Pop(Rsi)]);
+        self.write_object(true);
+        self.write("\n", true);
+        self.exit(1);
+
+        let messages = [
+            ("rt_err_bang", EvalErr::InvalidBang.to_string()),
+            ("rt_err_negate", EvalErr::InvalidNegate.to_string()),
+            ("rt_err_add", EvalErr::InvalidAdd.to_string()),
+            ("rt_err_sub", EvalErr::InvalidSub.to_string()),
+            ("rt_err_mul", EvalErr::InvalidMul.to_string()),
+            ("rt_err_div", EvalErr::InvalidDiv.to_string()),
+            ("rt_err_compare", EvalErr::InvalidCompare.to_string()),
+            (routine::NOT_CALLABLE, EvalErr::InvalidCall.to_string()),
+            ("rt_err_get", EvalErr::InvalidGet.to_string()),
+            ("rt_err_set", EvalErr::InvalidSet.to_string()),
+            ("rt_err_zero", EvalErr::DivisionByZero.to_string()),
+            ("rt_err_overflow", EvalErr::Overflow.to_string()),
+            ("rt_err_oom", "out of memory".to_owned()),
+        ];
+        for (label, message) in messages {
+            let message = self.program.string(&message);
+            self.emit([
+                Label(label.to_owned()),
+                MovAddr(Rsi, message, 0),
+                Jmp("rt_error".to_owned()),
+            ]);
+        }
+
+        self.emit([Label(routine::ARITY.to_owned()), Push(Rsi), Push(Rdi)]);
+        self.write("error: expected ", true);
+        self.emit([
+            Pop(Rdi),
+            Call("rt_itoa".to_owned()),
+            Call("rt_write_err".to_owned()),
+        ]);
+        self.write(" arguments but got ", true);
+        self.emit([
+            Pop(Rdi),
+            Call("rt_itoa".to_owned()),
+            Call("rt_write_err".to_owned()),
+        ]);
+        self.write("\n", true);
+        self.exit(1);
+
+        // the name is the string object at `rsi`
+        self.emit([Label("rt_err_property".to_owned()), Push(Rsi)]);
+        self.write("error: undefined property '", true);
+        self.emit([Pop(Rsi)]);
+        self.write_object(true);
+        self.write("'\n", true);
+        self.exit(1);
+
+        self.emit([
+            Label(routine::UNDEFINED.to_owned()),
+            Shl(Rdi, 3),
+            MovAddr(Rax, "rt_global_names".to_owned(), 0),
+            Alu(AluOp::Add, Rax, Rdi),
+            Load(Rax, Mem::new(Rax, 0)),
+            Push(Rax),
+        ]);
+        self.write("error: undefined variable '", true);
+        self.emit([Pop(Rsi)]);
+        self.write_object(true);
+        self.write("'\n", true);
+        self.exit(1);
+    }
+
+    /// prints the value in `rdi` as the interpreter's `print` does.
+    fn print(&mut self) {
+        use Inst::*;
+        use Reg::*;
+        let label = |s: &str| format!("rt_print.{}", s);
+        self.emit([
+            Label(routine::PRINT.to_owned()),
+            Mov(Rax, Rdi),
+            Shr(Rax, 32),
+            Test(Rax, Rax),
+            Jcc(Cond::E, label("nil")),
+            AluImm(AluOp::Cmp, Rax, TAG_BOOL as i32),
+            Jcc(Cond::E, label("bool")),
+            AluImm(AluOp::Cmp, Rax, TAG_INT as i32),
+            Jcc(Cond::E, label("int")),
+            AluImm(AluOp::Cmp, Rax, TAG_STR as i32),
+            Jcc(Cond::E, label("str")),
+            AluImm(AluOp::Cmp, Rax, TAG_FLOAT as i32),
+            Jcc(Cond::E, label("float")),
+            AluImm(AluOp::Cmp, Rax, TAG_CLASS as i32),
+            Jcc(Cond::E, label("class")),
+            AluImm(AluOp::Cmp, Rax, TAG_INSTANCE as i32),
+            Jcc(Cond::E, label("instance")),
+            AluImm(AluOp::Cmp, Rax, TAG_METHOD as i32),
+            Jcc(Cond::Ne, label("fn")),
+            // a bound method prints as its function
+            Mov32(Rdi, Rdi),
+            Load(Rdi, Mem::new(Rdi, 0)),
+            Label(label("fn")),
+            Push(Rdi),
+        ]);
+        self.write("<fn ", false);
+        self.emit([Pop(Rdi), Mov32(Rdi, Rdi), Load(Rsi, Mem::new(Rdi, 16))]);
+        self.write_object(false);
+        self.write(">", false);
+        self.emit([Jmp(label("newline")), Label(label("nil"))]);
+        self.write("nil", false);
+        self.emit([
+            Jmp(label("newline")),
+            Label(label("bool")),
+            Mov32(Rax, Rdi),
+            Test(Rax, Rax),
+            Jcc(Cond::E, label("false")),
+        ]);
+        self.write("true", false);
+        self.emit([Jmp(label("newline")), Label(label("false"))]);
+        self.write("false", false);
+        self.emit([
+            Jmp(label("newline")),
+            Label(label("int")),
+            Call("rt_itoa".to_owned()),
+            Call("rt_write_out".to_owned()),
+            Jmp(label("newline")),
+            Label(label("float")),
+            Call("rt_print_float".to_owned()),
+            Jmp(label("newline")),
+            Label(label("str")),
+            Mov32(Rsi, Rdi),
+        ]);
+        self.write_object(false);
+        self.emit([
+            Jmp(label("newline")),
+            Label(label("class")),
+            Mov32(Rdi, Rdi),
+            Load(Rsi, Mem::new(Rdi, 0)),
+        ]);
+        self.write_object(false);
+        self.emit([
+            Jmp(label("newline")),
+            Label(label("instance")),
+            Mov32(Rdi, Rdi),
+            Load(Rdi, Mem::new(Rdi, 0)),
+            Push(Rdi),
+        ]);
+        self.write("<", false);
+        self.emit([Pop(Rdi), Load(Rsi, Mem::new(Rdi, 0))]);
+        self.write_object(false);
+        self.write(" instance>", false);
+        self.emit([Label(label("newline"))]);
+        self.write("\n", false);
+        self.emit([Ret]);
+    }
+
+    /// Writes the float in `edi` as Rust's `Display` does, with the shortest digits that read back
+    /// as the same float and no exponent. The digits come from Ryu (Ulf Adams, 2018), in
+    /// `rt_f2d`, except that a value exactly halfway between two candidates rounds up, as Rust's
+    /// formatting rounds it, rather than to even.
+    fn floats(&mut self) {
+        use Inst::*;
+        use Reg::*;
+        let (inverses, powers) = ryu_tables();
+        for (label, bytes) in [("rt_pow5_inv", inverses), ("rt_pow5", powers)] {
+            self.program.data.push(Data {
+                label: label.to_owned(),
+                bytes,
+                relocs: Vec::new(),
+            });
+        }
+
+        // `rdi` times the 64-bit factor in `rsi`, shifted right by `rcx`, more than 32
+        self.emit([
+            Label("rt_mulshift".to_owned()),
+            Mov32(Rax, Rsi),
+            Imul(Rax, Rdi),
+            Shr(Rax, 32),
+            Mov(Rdx, Rsi),
+            Shr(Rdx, 32),
+            Imul(Rdx, Rdi),
+            Alu(AluOp::Add, Rax, Rdx),
+            AluImm(AluOp::Sub, Rcx, 32),
+            ShrCl(Rax),
+            Ret,
+        ]);
+        // the same with the factor at index `rsi` of a table
+        for (label, table) in [
+            ("rt_mulshift_inv", "rt_pow5_inv"),
+            ("rt_mulshift_pow", "rt_pow5"),
+        ] {
+            self.emit([
+                Label(label.to_owned()),
+                MovAddr(Rax, table.to_owned(), 0),
+                Shl(Rsi, 3),
+                Alu(AluOp::Add, Rsi, Rax),
+                Load(Rsi, Mem::new(Rsi, 0)),
+                Jmp("rt_mulshift".to_owned()),
+            ]);
+        }
+        // whether `rdi`, not zero, is a multiple of `5^rsi`, as 1 or 0 in `rax`
+        self.emit([
+            Label("rt_pow5_factor".to_owned()),
+            Mov(Rax, Rdi),
+            MovImm(Rcx, 5),
+            MovImm(R11, 0),
+            Label("rt_pow5_factor.divide".to_owned()),
+            Alu(AluOp::Xor, Rdx, Rdx),
+            Div(Rcx),
+            Test(Rdx, Rdx),
+            Jcc(Cond::Ne, "rt_pow5_factor.done".to_owned()),
+            AluImm(AluOp::Add, R11, 1),
+            Jmp("rt_pow5_factor.divide".to_owned()),
+            Label("rt_pow5_factor.done".to_owned()),
+            Alu(AluOp::Cmp, R11, Rsi),
+            MovImm(Rax, 0),
+            MovImm(Rdx, 1),
+            Cmov(Cond::Ge, Rax, Rdx),
+            Ret,
+        ]);
+
+        // The shortest digits of the positive, finite float in `edi`, as an integer in `rax` times
+        // ten to the power in `rdx`. `r8` holds the digits of the float's exact value and `r9` and
+        // `r10` those of the bounds of the interval that reads back as it; digits are dropped
+        // until the bounds would meet.
+        let label = |s: &str| format!("rt_f2d.{}", s);
+        // the rest of its state is in the frame
+        let slot = |n: i32| Mem::new(Rbp, -8 * n);
+        let (e10, vm_zeros, last, accept, e2, q) =
+            (slot(1), slot(2), slot(3), slot(4), slot(5), slot(6));
+        let (mv, mp, mm, mm_shift, shift, index) =
+            (slot(7), slot(8), slot(9), slot(10), slot(11), slot(12));
+        // Ryu's approximations of logarithms: `reg` times `factor`, shifted right by `bits`
+        let log =
+            |reg: Reg, factor: i64, bits: u8| [MovImm(Rdx, factor), Imul(reg, Rdx), Shr(reg, bits)];
+        // jumps to `to` if the bounds differ before their last digits
+        let apart = |to: String| {
+            [
+                Mov(Rax, R9),
+                AluImm(AluOp::Sub, Rax, 1),
+                MovImm(Rcx, 10),
+                Alu(AluOp::Xor, Rdx, Rdx),
+                Div(Rcx),
+                Mov(R11, Rax),
+                Mov(Rax, R10),
+                Alu(AluOp::Xor, Rdx, Rdx),
+                Div(Rcx),
+                Alu(AluOp::Cmp, R11, Rax),
+                Jcc(Cond::A, to),
+            ]
+        };
+        // the last digit of the float's value in `rax`
+        let last_digit = [
+            MovImm(Rcx, 10),
+            Alu(AluOp::Xor, Rdx, Rdx),
+            Div(Rcx),
+            Store(last, Rdx),
+        ];
+        self.emit([
+            Label("rt_f2d".to_owned()),
+            Push(Rbp),
+            Mov(Rbp, Rsp),
+            AluImm(AluOp::Sub, Rsp, 96),
+            Mov32(Rax, Rdi),
+            AluImm(AluOp::And, Rax, 0x7fffff),
+            Mov32(Rcx, Rdi),
+            Shr(Rcx, 23),
+            // the lower bound is closer when the mantissa is a power of two
+            MovImm(Rdx, 1),
+            Test(Rax, Rax),
+            Jcc(Cond::Ne, label("shift")),
+            AluImm(AluOp::Cmp, Rcx, 1),
+            Jcc(Cond::Be, label("shift")),
+            MovImm(Rdx, 0),
+            Label(label("shift")),
+            Store(mm_shift, Rdx),
+            Test(Rcx, Rcx),
+            Jcc(Cond::Ne, label("normal")),
+            MovImm(Rcx, 1),
+            Jmp(label("scale")),
+            Label(label("normal")),
+            AluImm(AluOp::Or, Rax, 1 << 23),
+            Label(label("scale")),
+            // the value is `m * 2^e2` with two more bits of mantissa for the bounds
+            AluImm(AluOp::Sub, Rcx, 127 + 23 + 2),
+            Store(e2, Rcx),
+            // reading a decimal rounds ties to even, so an even float owns its bounds
+            Mov(Rdx, Rax),
+            AluImm(AluOp::And, Rdx, 1),
+            AluImm(AluOp::Xor, Rdx, 1),
+            Store(accept, Rdx),
+            Shl(Rax, 2),
+            Store(mv, Rax),
+            Mov(Rdx, Rax),
+            AluImm(AluOp::Add, Rdx, 2),
+            Store(mp, Rdx),
+            Mov(Rdx, Rax),
+            AluImm(AluOp::Sub, Rdx, 1),
+            Load(R8, mm_shift),
+            Alu(AluOp::Sub, Rdx, R8),
+            Store(mm, Rdx),
+            MovImm(Rdx, 0),
+            Store(vm_zeros, Rdx),
+            Store(last, Rdx),
+            Test(Rcx, Rcx),
+            Jcc(Cond::L, label("negative")),
+            // a positive exponent: divide by `10^q`, `q = log10(2^e2)`
+            Mov(Rax, Rcx),
+        ]);
+        self.emit(log(Rax, 78913, 18));
+        self.emit([Store(q, Rax), Store(e10, Rax)]);
+        self.emit(log(Rax, 1217359, 19));
+        self.emit([
+            AluImm(AluOp::Add, Rax, POW5_INV_BITS as i32),
+            Load(Rdx, q),
+            Alu(AluOp::Add, Rax, Rdx),
+            Load(Rdx, e2),
+            Alu(AluOp::Sub, Rax, Rdx),
+            Store(shift, Rax),
+        ]);
+        for (m, v) in [(mv, R8), (mp, R9), (mm, R10)] {
+            self.emit([
+                Load(Rdi, m),
+                Load(Rsi, q),
+                Load(Rcx, shift),
+                Call("rt_mulshift_inv".to_owned()),
+                Mov(v, Rax),
+            ]);
+        }
+        self.emit([
+            Load(Rax, q),
+            Test(Rax, Rax),
+            Jcc(Cond::E, label("positive_zeros")),
+        ]);
+        self.emit(apart(label("positive_zeros")));
+        self.emit([Load(Rax, q), AluImm(AluOp::Sub, Rax, 1)]);
+        self.emit(log(Rax, 1217359, 19));
+        self.emit([
+            AluImm(AluOp::Add, Rax, POW5_INV_BITS as i32 - 1),
+            Load(Rdx, q),
+            Alu(AluOp::Add, Rax, Rdx),
+            Load(Rdx, e2),
+            Alu(AluOp::Sub, Rax, Rdx),
+            Mov(Rcx, Rax),
+            Load(Rdi, mv),
+            Load(Rsi, q),
+            AluImm(AluOp::Sub, Rsi, 1),
+            Call("rt_mulshift_inv".to_owned()),
+        ]);
+        self.emit(last_digit.clone());
+        self.emit([
+            // whether a bound ends in zeros that are not digits, only possible for a small `q`
+            Label(label("positive_zeros")),
+            Load(Rax, q),
+            AluImm(AluOp::Cmp, Rax, 9),
+            Jcc(Cond::A, label("digits")),
+            Load(Rax, mv),
+            MovImm(Rcx, 5),
+            Alu(AluOp::Xor, Rdx, Rdx),
+            Div(Rcx),
+            Test(Rdx, Rdx),
+            Jcc(Cond::E, label("digits")),
+            Load(Rax, accept),
+            Test(Rax, Rax),
+            Jcc(Cond::E, label("positive_upper")),
+            Load(Rdi, mm),
+            Load(Rsi, q),
+            Call("rt_pow5_factor".to_owned()),
+            Store(vm_zeros, Rax),
+            Jmp(label("digits")),
+            Label(label("positive_upper")),
+            Load(Rdi, mp),
+            Load(Rsi, q),
+            Call("rt_pow5_factor".to_owned()),
+            Alu(AluOp::Sub, R9, Rax),
+            Jmp(label("digits")),
+            // a negative exponent: multiply by `5^i` and divide by `10^q`, `q = log10(5^-e2)`
+            Label(label("negative")),
+            Mov(Rax, Rcx),
+            Neg(Rax),
+        ]);
+        self.emit(log(Rax, 732923, 20));
+        self.emit([
+            Store(q, Rax),
+            Load(Rdx, e2),
+            Alu(AluOp::Add, Rdx, Rax),
+            Store(e10, Rdx),
+            Load(Rdx, e2),
+            Neg(Rdx),
+            Alu(AluOp::Sub, Rdx, Rax),
+            Store(index, Rdx),
+            Mov(Rax, Rdx),
+        ]);
+        self.emit(log(Rax, 1217359, 19));
+        self.emit([
+            AluImm(AluOp::Sub, Rax, POW5_BITS as i32 - 1),
+            Load(Rdx, q),
+            Alu(AluOp::Sub, Rdx, Rax),
+            Store(shift, Rdx),
+        ]);
+        for (m, v) in [(mv, R8), (mp, R9), (mm, R10)] {
+            self.emit([
+                Load(Rdi, m),
+                Load(Rsi, index),
+                Load(Rcx, shift),
+                Call("rt_mulshift_pow".to_owned()),
+                Mov(v, Rax),
+            ]);
+        }
+        self.emit([
+            Load(Rax, q),
+            Test(Rax, Rax),
+            Jcc(Cond::E, label("negative_zeros")),
+        ]);
+        self.emit(apart(label("negative_zeros")));
+        self.emit([Load(Rax, index), AluImm(AluOp::Add, Rax, 1)]);
+        self.emit(log(Rax, 1217359, 19));
+        self.emit([
+            AluImm(AluOp::Sub, Rax, POW5_BITS as i32 - 1),
+            Load(Rcx, q),
+            AluImm(AluOp::Sub, Rcx, 1),
+            Alu(AluOp::Sub, Rcx, Rax),
+            Load(Rdi, mv),
+            Load(Rsi, index),
+            AluImm(AluOp::Add, Rsi, 1),
+            Call("rt_mulshift_pow".to_owned()),
+        ]);
+        self.emit(last_digit);
+        self.emit([
+            Label(label("negative_zeros")),
+            Load(Rax, q),
+            AluImm(AluOp::Cmp, Rax, 1),
+            Jcc(Cond::A, label("digits")),
+            Load(Rax, accept),
+            Test(Rax, Rax),
+            Jcc(Cond::E, label("negative_upper")),
+            Load(Rax, mm_shift),
+            Store(vm_zeros, Rax),
+            Jmp(label("digits")),
+            Label(label("negative_upper")),
+            AluImm(AluOp::Sub, R9, 1),
+            // drop digits while the bounds differ in more than the last one, counting them in r11
+            Label(label("digits")),
+            MovImm(R11, 0),
+            MovImm(Rcx, 10),
+            Label(label("drop")),
+            Mov(Rax, R9),
+            Alu(AluOp::Xor, Rdx, Rdx),
+            Div(Rcx),
+            Mov(Rsi, Rax),
+            Mov(Rax, R10),
+            Alu(AluOp::Xor, Rdx, Rdx),
+            Div(Rcx),
+            Mov(Rdi, Rax),
+            Alu(AluOp::Cmp, Rsi, Rdi),
+            Jcc(Cond::Be, label("zeros")),
+            Test(Rdx, Rdx),
+            Jcc(Cond::E, label("dropped")),
+            MovImm(Rax, 0),
+            Store(vm_zeros, Rax),
+            Label(label("dropped")),
+            Mov(R9, Rsi),
+            Mov(R10, Rdi),
+            Mov(Rax, R8),
+            Alu(AluOp::Xor, Rdx, Rdx),
+            Div(Rcx),
+            Mov(R8, Rax),
+            Store(last, Rdx),
+            AluImm(AluOp::Add, R11, 1),
+            Jmp(label("drop")),
+            // then the zeros the lower bound ends in, if it is in the interval
+            Label(label("zeros")),
+            Load(Rax, vm_zeros),
+            Test(Rax, Rax),
+            Jcc(Cond::E, label("round")),
+            Label(label("zero")),
+            Mov(Rax, R10),
+            Alu(AluOp::Xor, Rdx, Rdx),
+            Div(Rcx),
+            Test(Rdx, Rdx),
+            Jcc(Cond::Ne, label("round")),
+            Mov(R10, Rax),
+            Mov(Rax, R9),
+            Alu(AluOp::Xor, Rdx, Rdx),
+            Div(Rcx),
+            Mov(R9, Rax),
+            Mov(Rax, R8),
+            Alu(AluOp::Xor, Rdx, Rdx),
+            Div(Rcx),
+            Mov(R8, Rax),
+            Store(last, Rdx),
+            AluImm(AluOp::Add, R11, 1),
+            Jmp(label("zero")),
+            // round up if the dropped digits were at least half, or if the digits are the lower
+            // bound and it is not in the interval
+            Label(label("round")),
+            Mov(Rax, R8),
+            Load(Rdx, last),
+            AluImm(AluOp::Cmp, Rdx, 5),
+            Jcc(Cond::Ae, label("up")),
+            Alu(AluOp::Cmp, R8, R10),
+            Jcc(Cond::Ne, label("done")),
+            Load(Rdx, accept),
+            Test(Rdx, Rdx),
+            Jcc(Cond::E, label("up")),
+            Load(Rdx, vm_zeros),
+            Test(Rdx, Rdx),
+            Jcc(Cond::Ne, label("done")),
+            Label(label("up")),
+            AluImm(AluOp::Add, Rax, 1),
+            Label(label("done")),
+            Load(Rdx, e10),
+            Alu(AluOp::Add, Rdx, R11),
+            Mov(Rsp, Rbp),
+            Pop(Rbp),
+            Ret,
+        ]);
+
+        // the digits go in `rt_float_buf` at `r9`, with the point where the exponent puts it
+        let label = |s: &str| format!("rt_print_float.{}", s);
+        // `rcx` bytes at `r9`, copied from `rsi` or zeros, advancing both
+        let fill = |name: &str, copy: bool| {
+            let mut insts = vec![
+                Label(label(name)),
+                Test(Rcx, Rcx),
+                Jcc(Cond::E, label(&format!("{}_done", name))),
+            ];
+            if copy {
+                insts.extend([LoadByte(Rax, Mem::new(Rsi, 0)), AluImm(AluOp::Add, Rsi, 1)]);
+            } else {
+                insts.push(MovImm(Rax, b'0' as i64));
+            }
+            insts.extend([
+                StoreByte(Mem::new(R9, 0), Rax),
+                AluImm(AluOp::Add, R9, 1),
+                AluImm(AluOp::Sub, Rcx, 1),
+                Jmp(label(name)),
+                Label(label(&format!("{}_done", name))),
+            ]);
+            insts
+        };
+        self.emit([
+            Label("rt_print_float".to_owned()),
+            Mov32(Rdi, Rdi),
+            Mov(Rax, Rdi),
+            AluImm(AluOp::And, Rax, 0x7fffffff),
+            MovImm(Rcx, 0x7f800000),
+            Alu(AluOp::Cmp, Rax, Rcx),
+            Jcc(Cond::A, label("nan")),
+            Push(Rax),
+            Alu(AluOp::Cmp, Rax, Rdi),
+            Jcc(Cond::E, label("positive")),
+        ]);
+        self.write("-", false);
+        self.emit([
+            Label(label("positive")),
+            Pop(Rdi),
+            MovImm(Rcx, 0x7f800000),
+            Alu(AluOp::Cmp, Rdi, Rcx),
+            Jcc(Cond::E, label("inf")),
+            Test(Rdi, Rdi),
+            Jcc(Cond::E, label("zero")),
+            Call("rt_f2d".to_owned()),
+            Push(Rdx),
+            Mov(Rdi, Rax),
+            Call("rt_itoa".to_owned()),
+            // the digits are at `rsi`, `rdx` of them, and the point goes after `r8` of them
+            Pop(R8),
+            Alu(AluOp::Add, R8, Rdx),
+            MovAddr(R9, "rt_float_buf".to_owned(), 0),
+            Test(R8, R8),
+            Jcc(Cond::G, label("whole")),
+            MovImm(Rax, b'0' as i64),
+            StoreByte(Mem::new(R9, 0), Rax),
+            MovImm(Rax, b'.' as i64),
+            StoreByte(Mem::new(R9, 1), Rax),
+            AluImm(AluOp::Add, R9, 2),
+            Mov(Rcx, R8),
+            Neg(Rcx),
+        ]);
+        self.emit(fill("leading_zeros", false));
+        self.emit([Mov(Rcx, Rdx)]);
+        self.emit(fill("fraction", true));
+        self.emit([
+            Jmp(label("write")),
+            Label(label("whole")),
+            Alu(AluOp::Cmp, R8, Rdx),
+            Jcc(Cond::L, label("point")),
+            Mov(Rcx, Rdx),
+        ]);
+        self.emit(fill("integer", true));
+        self.emit([Mov(Rcx, R8), Alu(AluOp::Sub, Rcx, Rdx)]);
+        self.emit(fill("trailing_zeros", false));
+        self.emit([Jmp(label("write")), Label(label("point")), Mov(Rcx, R8)]);
+        self.emit(fill("before_point", true));
+        self.emit([
+            MovImm(Rax, b'.' as i64),
+            StoreByte(Mem::new(R9, 0), Rax),
+            AluImm(AluOp::Add, R9, 1),
+            Mov(Rcx, Rdx),
+            Alu(AluOp::Sub, Rcx, R8),
+        ]);
+        self.emit(fill("after_point", true));
+        self.emit([
+            Label(label("write")),
+            MovAddr(Rsi, "rt_float_buf".to_owned(), 0),
+            Mov(Rdx, R9),
+            Alu(AluOp::Sub, Rdx, Rsi),
+            Jmp("rt_write_out".to_owned()),
+            Label(label("nan")),
+        ]);
+        self.write("NaN", false);
+        self.emit([Ret, Label(label("inf"))]);
+        self.write("inf", false);
+        self.emit([Ret, Label(label("zero"))]);
+        self.write("0", false);
+        self.emit([Ret]);
+    }
+
+    /// Arithmetic on two `int`s stays in them, checking for overflow; with a `float` on either
+    /// side both are converted and the result is a `float`, as in the interpreter.
+    fn arithmetic(&mut self) {
+        use Inst::*;
+        use Reg::*;
+
+        // the operands in `rdi` and `rsi` as floats in `xmm0` and `xmm1`, and `rax` 0 if either
+        // is not a number
+        let label = |s: &str| format!("rt_floats.{}", s);
+        self.emit([Label("rt_floats".to_owned())]);
+        for (reg, xmm) in [(Rdi, Xmm::Xmm0), (Rsi, Xmm::Xmm1)] {
+            let done = label(&format!("{}_done", reg));
+            self.emit([
+                Mov(Rax, reg),
+                Shr(Rax, 32),
+                AluImm(AluOp::Cmp, Rax, TAG_INT as i32),
+                Jcc(Cond::Ne, label(&format!("{}_float", reg))),
+                Cvtsi2ss(xmm, reg),
+                Jmp(done.clone()),
+                Label(label(&format!("{}_float", reg))),
+                AluImm(AluOp::Cmp, Rax, TAG_FLOAT as i32),
+                Jcc(Cond::Ne, label("invalid")),
+                MovdToXmm(xmm, reg),
+                Label(done),
+            ]);
+        }
+        self.emit([
+            MovImm(Rax, 1),
+            Ret,
+            Label(label("invalid")),
+            MovImm(Rax, 0),
+            Ret,
+        ]);
+
+        // `+` also concatenates strings
+        self.emit([
+            Label(routine::ADD.to_owned()),
+            Mov(Rax, Rdi),
+            Shr(Rax, 32),
+            Mov(Rcx, Rsi),
+            Shr(Rcx, 32),
+            Alu(AluOp::Cmp, Rax, Rcx),
+            Jcc(Cond::Ne, "rt_add.float".to_owned()),
+            AluImm(AluOp::Cmp, Rax, TAG_STR as i32),
+            Jcc(Cond::E, "rt_concat".to_owned()),
+            AluImm(AluOp::Cmp, Rax, TAG_INT as i32),
+            Jcc(Cond::Ne, "rt_add.float".to_owned()),
+            Mov32(Rax, Rdi),
+            Alu32(AluOp::Add, Rax, Rsi),
+            Jcc(Cond::O, "rt_err_overflow".to_owned()),
+        ]);
+        self.tag_int();
+        self.float_op(routine::ADD, SseOp::Add, "rt_err_add");
+
+        for (label, error, op, sse) in [
+            (
+                routine::SUB,
+                "rt_err_sub",
+                Alu32(AluOp::Sub, Rax, Rsi),
+                SseOp::Sub,
+            ),
+            (routine::MUL, "rt_err_mul", Imul32(Rax, Rsi), SseOp::Mul),
+        ] {
+            let float = format!("{}.float", label);
+            self.emit([Label(label.to_owned())]);
+            self.expect_tag(Rdi, TAG_INT, &float);
+            self.expect_tag(Rsi, TAG_INT, &float);
+            self.emit([
+                Mov32(Rax, Rdi),
+                op,
+                Jcc(Cond::O, "rt_err_overflow".to_owned()),
+            ]);
+            self.tag_int();
+            self.float_op(label, sse, error);
+        }
+
+        // only `int` division checks for zero; a float quotient is infinite or NaN instead
+        self.emit([Label(routine::DIV.to_owned())]);
+        self.expect_tag(Rdi, TAG_INT, "rt_div.float");
+        self.expect_tag(Rsi, TAG_INT, "rt_div.float");
+        self.emit([
+            Mov32(Rax, Rdi),
+            Mov32(Rcx, Rsi),
+            Test(Rcx, Rcx),
+            Jcc(Cond::E, "rt_err_zero".to_owned()),
+            // `idiv` faults on the one quotient that does not fit
+            MovImm(Rdx, u32::MAX as i64),
+            Alu(AluOp::Cmp, Rcx, Rdx),
+            Jcc(Cond::Ne, "rt_div.divide".to_owned()),
+            MovImm(Rdx, i32::MIN as u32 as i64),
+            Alu(AluOp::Cmp, Rax, Rdx),
+            Jcc(Cond::E, "rt_err_overflow".to_owned()),
+            Label("rt_div.divide".to_owned()),
+            Cdq,
+            Idiv32(Rcx),
+        ]);
+        self.tag_int();
+        self.float_op(routine::DIV, SseOp::Div, "rt_err_div");
+
+        // negating a float flips its sign bit
+        self.emit([Label(routine::NEG.to_owned())]);
+        self.expect_tag(Rdi, TAG_INT, "rt_neg.float");
+        self.emit([
+            Mov32(Rax, Rdi),
+            Neg32(Rax),
+            Jcc(Cond::O, "rt_err_overflow".to_owned()),
+        ]);
+        self.tag_int();
+        self.emit([Label("rt_neg.float".to_owned())]);
+        self.expect_tag(Rdi, TAG_FLOAT, "rt_err_negate");
+        self.emit([
+            Mov(Rax, Rdi),
+            MovImm(Rcx, 1 << 31),
+            Alu(AluOp::Xor, Rax, Rcx),
+            Ret,
+        ]);
+    }
+
+    /// the part of the arithmetic routine at `label` for operands that are not both `int`s, at
+    /// `<label>.float`.
+    fn float_op(&mut self, label: &str, op: SseOp, error: &str) {
+        use Inst::*;
+        use Reg::*;
+        self.emit([
+            Label(format!("{}.float", label)),
+            Call("rt_floats".to_owned()),
+            Test(Rax, Rax),
+            Jcc(Cond::E, error.to_owned()),
+            Sse(op, Xmm::Xmm0, Xmm::Xmm1),
+            MovdFromXmm(Rax, Xmm::Xmm0),
+            MovImm(Rcx, TAG_FLOAT << 32),
+            Alu(AluOp::Or, Rax, Rcx),
+            Ret,
+        ]);
+    }
+
+    fn comparisons(&mut self) {
+        use Inst::*;
+        use Reg::*;
+        // As the interpreter orders literals: a `float` on the left compares with a number as
+        // floats and an `int` with a `float` truncated to an `int`. Any other literals are
+        // unordered, and other values an error. Returns in `rax` 0 if the operands are unordered,
+        // 1 for `int`s in `ecx` and `edx` and 2 for floats in `xmm0` and `xmm1`.
+        let label = |s: &str| format!("rt_order.{}", s);
+        self.emit([
+            Label("rt_order".to_owned()),
+            Mov(Rax, Rdi),
+            Shr(Rax, 32),
+            Mov(R8, Rsi),
+            Shr(R8, 32),
+        ]);
+        for tag in [Rax, R8] {
+            let literal = label(&format!("{}_literal", tag));
+            self.emit([
+                AluImm(AluOp::Cmp, tag, TAG_FLOAT as i32),
+                Jcc(Cond::E, literal.clone()),
+                AluImm(AluOp::Cmp, tag, TAG_STR as i32),
+                Jcc(Cond::A, "rt_err_compare".to_owned()),
+                Label(literal),
+            ]);
+        }
+        self.emit([
+            AluImm(AluOp::Cmp, Rax, TAG_FLOAT as i32),
+            Jcc(Cond::E, label("float")),
+            AluImm(AluOp::Cmp, Rax, TAG_INT as i32),
+            Jcc(Cond::Ne, label("unordered")),
+            Mov32(Rcx, Rdi),
+            Mov32(Rdx, Rsi),
+            AluImm(AluOp::Cmp, R8, TAG_INT as i32),
+            Jcc(Cond::E, label("ints")),
+            AluImm(AluOp::Cmp, R8, TAG_FLOAT as i32),
+            Jcc(Cond::Ne, label("unordered")),
+            // like Rust's `as i32`: saturating, with NaN as 0
+            MovdToXmm(Xmm::Xmm1, Rsi),
+            MovImm(Rdx, 0),
+            Ucomiss(Xmm::Xmm1, Xmm::Xmm1),
+            Jcc(Cond::P, label("ints")),
+            MovImm(Rdx, i32::MAX as i64),
+            MovImm(Rax, 2147483648f32.to_bits() as i64),
+            MovdToXmm(Xmm::Xmm2, Rax),
+            Ucomiss(Xmm::Xmm1, Xmm::Xmm2),
+            Jcc(Cond::Ae, label("ints")),
+            // out of range below gives `i32::MIN`
+            Cvttss2si(Rdx, Xmm::Xmm1),
+            Label(label("ints")),
+            MovImm(Rax, 1),
+            Ret,
+            Label(label("float")),
+            MovdToXmm(Xmm::Xmm0, Rdi),
+            MovdToXmm(Xmm::Xmm1, Rsi),
+            AluImm(AluOp::Cmp, R8, TAG_FLOAT as i32),
+            Jcc(Cond::E, label("floats")),
+            AluImm(AluOp::Cmp, R8, TAG_INT as i32),
+            Jcc(Cond::Ne, label("unordered")),
+            Cvtsi2ss(Xmm::Xmm1, Rsi),
+            Label(label("floats")),
+            MovImm(Rax, 2),
+            Ret,
+            Label(label("unordered")),
+            MovImm(Rax, 0),
+            Ret,
+        ]);
+
+        // `ucomiss` reports NaN as below, so `<` and `<=` compare the other way around to be
+        // false for it
+        for (label, cond, swap, float_cond) in [
+            (routine::LT, Cond::L, true, Cond::A),
+            (routine::LE, Cond::Le, true, Cond::Ae),
+            (routine::GT, Cond::G, false, Cond::A),
+            (routine::GE, Cond::Ge, false, Cond::Ae),
+        ] {
+            let (a, b) = match swap {
+                true => (Xmm::Xmm1, Xmm::Xmm0),
+                false => (Xmm::Xmm0, Xmm::Xmm1),
+            };
+            let float = format!("{}.float", label);
+            self.emit([
+                Label(label.to_owned()),
+                Call("rt_order".to_owned()),
+                Test(Rax, Rax),
+                Jcc(Cond::E, format!("{}.unordered", label)),
+                AluImm(AluOp::Cmp, Rax, 1),
+                Jcc(Cond::Ne, float.clone()),
+                Alu32(AluOp::Cmp, Rcx, Rdx),
+                MovImm(Rax, FALSE),
+                MovImm(Rdx, TRUE),
+                Cmov(cond, Rax, Rdx),
+                Ret,
+                Label(float),
+                Ucomiss(a, b),
+                MovImm(Rax, FALSE),
+                MovImm(Rdx, TRUE),
+                Cmov(float_cond, Rax, Rdx),
+                Ret,
+                Label(format!("{}.unordered", label)),
+                MovImm(Rax, FALSE),
+                Ret,
+            ]);
+        }
+
+        // values are equal if their words are, or if both are strings with the same bytes; floats
+        // compare as floats, so NaN is not equal to itself and the zeros are equal
+        let label = |s: &str| format!("rt_eq.{}", s);
+        self.emit([Label(routine::EQ.to_owned())]);
+        self.expect_tag(Rdi, TAG_FLOAT, &label("words"));
+        self.expect_tag(Rsi, TAG_FLOAT, &label("false"));
+        self.emit([
+            MovdToXmm(Xmm::Xmm0, Rdi),
+            MovdToXmm(Xmm::Xmm1, Rsi),
+            Ucomiss(Xmm::Xmm0, Xmm::Xmm1),
+            Jcc(Cond::P, label("false")),
+            Jcc(Cond::E, label("true")),
+            Jmp(label("false")),
+            Label(label("words")),
+            Alu(AluOp::Cmp, Rdi, Rsi),
+            Jcc(Cond::E, label("true")),
+        ]);
+        self.expect_tag(Rdi, TAG_STR, &label("false"));
+        self.expect_tag(Rsi, TAG_STR, &label("false"));
+        self.emit([
+            Mov32(Rdi, Rdi),
+            Mov32(Rsi, Rsi),
+            Load(Rcx, Mem::new(Rdi, 0)),
+            Load(Rdx, Mem::new(Rsi, 0)),
+            Alu(AluOp::Cmp, Rcx, Rdx),
+            Jcc(Cond::Ne, label("false")),
+            AluImm(AluOp::Add, Rdi, 8),
+            AluImm(AluOp::Add, Rsi, 8),
+            Label(label("byte")),
+            Test(Rcx, Rcx),
+            Jcc(Cond::E, label("true")),
+            LoadByte(Rax, Mem::new(Rdi, 0)),
+            LoadByte(Rdx, Mem::new(Rsi, 0)),
+            Alu(AluOp::Cmp, Rax, Rdx),
+            Jcc(Cond::Ne, label("false")),
+            AluImm(AluOp::Add, Rdi, 1),
+            AluImm(AluOp::Add, Rsi, 1),
+            AluImm(AluOp::Sub, Rcx, 1),
+            Jmp(label("byte")),
+            Label(label("true")),
+            MovImm(Rax, TRUE),
+            Ret,
+            Label(label("false")),
+            MovImm(Rax, FALSE),
+            Ret,
+            Label(routine::NE.to_owned()),
+            Call(routine::EQ.to_owned()),
+            AluImm(AluOp::Xor, Rax, 1),
+            Ret,
+        ]);
+    }
+
+    /// Bump allocation from the current chunk. One too small for the request is left behind for
+    /// a new one, mapped with `MAP_32BIT` so its addresses fit in a value.
+    fn alloc(&mut self) {
+        use Inst::*;
+        use Reg::*;
+        let label = |s: &str| format!("rt_alloc.{}", s);
+        self.emit([
+            Label(routine::ALLOC.to_owned()),
+            Push(Rcx),
+            Push(Rdx),
+            MovAddr(Rcx, "rt_heap_ptr".to_owned(), 0),
+            Load(Rdx, Mem::new(Rcx, 0)),
+            Alu(AluOp::Add, Rax, Rdx),
+            Push(Rdx),
+            MovAddr(Rdx, "rt_heap_end".to_owned(), 0),
+            Load(Rdx, Mem::new(Rdx, 0)),
+            Alu(AluOp::Cmp, Rax, Rdx),
+            Jcc(Cond::A, label("map")),
+            Store(Mem::new(Rcx, 0), Rax),
+            Pop(Rax),
+            Pop(Rdx),
+            Pop(Rcx),
+            Ret,
+            Label(label("map")),
+            Pop(Rdx),
+            Alu(AluOp::Sub, Rax, Rdx),
+        ]);
+        self.emit([Rsi, Rdi, R8, R9, R10, R11].map(Push));
+        self.emit([
+            // mmap(0, max(size, HEAP_CHUNK), PROT_READ | PROT_WRITE,
+            //      MAP_PRIVATE | MAP_ANONYMOUS | MAP_32BIT, -1, 0)
+            Push(Rax),
+            Mov(Rsi, Rax),
+            MovImm(Rdi, HEAP_CHUNK),
+            Alu(AluOp::Cmp, Rsi, Rdi),
+            Cmov(Cond::B, Rsi, Rdi),
+            Push(Rsi),
+            MovImm(Rax, 9),
+            MovImm(Rdi, 0),
+            MovImm(Rdx, 0x3),
+            MovImm(R10, 0x62),
+            MovImm(R8, -1),
+            MovImm(R9, 0),
+            Syscall,
+            Pop(Rsi),
+            Pop(Rdx),
+            // errors are returned as -4095 to -1
+            MovImm(Rcx, -4096),
+            Alu(AluOp::Cmp, Rax, Rcx),
+            Jcc(Cond::A, "rt_err_oom".to_owned()),
+            Alu(AluOp::Add, Rsi, Rax),
+            MovAddr(Rcx, "rt_heap_end".to_owned(), 0),
+            Store(Mem::new(Rcx, 0), Rsi),
+            Alu(AluOp::Add, Rdx, Rax),
+            MovAddr(Rcx, "rt_heap_ptr".to_owned(), 0),
+            Store(Mem::new(Rcx, 0), Rdx),
+        ]);
+        self.emit([R11, R10, R9, R8, Rdi, Rsi, Rdx, Rcx].map(Pop));
+        self.emit([Ret]);
+    }
+
+    /// jumps to the function's code after checking the arity, so the callee returns straight to
+    /// the caller. A bound method gets its instance as the first argument; calling a class makes
+    /// an instance and runs its `init`, if it has one.
+    fn call(&mut self) {
+        use Inst::*;
+        use Reg::*;
+        let label = |s: &str| format!("rt_call.{}", s);
+        self.emit([
+            Label(routine::CALL.to_owned()),
+            Mov(R10, Rax),
+            Shr(R10, 32),
+            AluImm(AluOp::Cmp, R10, TAG_FN as i32),
+            Jcc(Cond::Ne, label("method")),
+            Mov32(Rax, Rax),
+            Load(R10, Mem::new(Rax, 8)),
+            Alu(AluOp::Cmp, R10, R11),
+            Jcc(Cond::Ne, label("arity")),
+            Load(R10, Mem::new(Rax, 0)),
+            JmpReg(R10),
+            Label(label("arity")),
+            Mov(Rdi, R10),
+            Mov(Rsi, R11),
+            Jmp(routine::ARITY.to_owned()),
+            Label(label("method")),
+            AluImm(AluOp::Cmp, R10, TAG_METHOD as i32),
+            Jcc(Cond::Ne, label("class")),
+            Call("rt_shift_args".to_owned()),
+            Mov32(Rax, Rax),
+            Load(R10, Mem::new(Rax, 8)),
+            Load(Rax, Mem::new(Rax, 0)),
+            Mov(R9, R8),
+            Mov(R8, Rcx),
+            Mov(Rcx, Rdx),
+            Mov(Rdx, Rsi),
+            Mov(Rsi, Rdi),
+            Mov(Rdi, R10),
+            AluImm(AluOp::Add, R11, 1),
+            Load(R10, Mem::new(Rax, 8)),
+            Alu(AluOp::Cmp, R10, R11),
+            Jcc(Cond::Ne, label("method_arity")),
+            Load(R10, Mem::new(Rax, 0)),
+            JmpReg(R10),
+            Label(label("method_arity")),
+            Mov(Rdi, R10),
+            AluImm(AluOp::Sub, Rdi, 1),
+            Mov(Rsi, R11),
+            AluImm(AluOp::Sub, Rsi, 1),
+            Jmp(routine::ARITY.to_owned()),
+            Label(label("class")),
+            AluImm(AluOp::Cmp, R10, TAG_CLASS as i32),
+            Jcc(Cond::Ne, routine::NOT_CALLABLE.to_owned()),
+            Call("rt_shift_args".to_owned()),
+            Push(Rbp),
+            Mov(Rbp, Rsp),
+        ]);
+        // the arguments and their count are kept in the frame while the instance is made, then
+        // the instance below them, which leaves `rsp` aligned for calling `init`
+        self.emit([Rdi, Rsi, Rdx, Rcx, R8, R9, R11, R11].map(Push));
+        let init = self.program.string("init");
+        self.emit([
+            Mov32(Rdi, Rax),
+            MovImm(Rax, 32),
+            Call(routine::ALLOC.to_owned()),
+            Store(Mem::new(Rax, 0), Rdi),
+            MovImm(Rcx, TAG_INSTANCE << 32),
+            Alu(AluOp::Or, Rax, Rcx),
+            Store(Mem::new(Rbp, -64), Rax),
+            MovAddr(Rsi, init, 0),
+            Call("rt_method".to_owned()),
+            Load(R11, Mem::new(Rbp, -56)),
+            MovImm(R10, 0),
+            Test(Rax, Rax),
+            Jcc(Cond::E, label("no_init")),
+            Load(R10, Mem::new(Rax, 8)),
+            AluImm(AluOp::Sub, R10, 1),
+            Alu(AluOp::Cmp, R10, R11),
+            Jcc(Cond::Ne, label("init_arity")),
+            // the words the caller passed, the sixth argument now among them, are passed on
+            AluImm(AluOp::Cmp, R11, Reg::ARGS.len() as i32),
+            Jcc(Cond::L, label("init")),
+            Mov(Rcx, R11),
+            AluImm(AluOp::Sub, Rcx, Reg::ARGS.len() as i32 - 2),
+            AluImm(AluOp::And, Rcx, -2),
+            Mov(Rdx, Rcx),
+            Shl(Rdx, 3),
+            Alu(AluOp::Add, Rdx, Rbp),
+            AluImm(AluOp::Add, Rdx, 8),
+            Label(label("pass")),
+            Load(Rdi, Mem::new(Rdx, 0)),
+            Push(Rdi),
+            AluImm(AluOp::Sub, Rdx, 8),
+            AluImm(AluOp::Sub, Rcx, 1),
+            Jcc(Cond::Ne, label("pass")),
+            Label(label("init")),
+            Load(Rdi, Mem::new(Rbp, -64)),
+            Load(Rsi, Mem::new(Rbp, -8)),
+            Load(Rdx, Mem::new(Rbp, -16)),
+            Load(Rcx, Mem::new(Rbp, -24)),
+            Load(R8, Mem::new(Rbp, -32)),
+            Load(R9, Mem::new(Rbp, -40)),
+            Load(R10, Mem::new(Rax, 0)),
+            CallReg(R10),
+            Label(label("made")),
+            Load(Rax, Mem::new(Rbp, -64)),
+            Mov(Rsp, Rbp),
+            Pop(Rbp),
+            Ret,
+            Label(label("no_init")),
+            Test(R11, R11),
+            Jcc(Cond::E, label("made")),
+            Label(label("init_arity")),
+            Mov(Rdi, R10),
+            Mov(Rsi, R11),
+            Jmp(routine::ARITY.to_owned()),
+        ]);
+
+        // With six or more arguments, makes room for the instance among the registers by moving
+        // the sixth to the stack, below the rest, into the spare word `stack_words` leaves. Called
+        // from `rt_call`, so the words start above two return addresses.
+        let label = |s: &str| format!("rt_shift_args.{}", s);
+        self.emit([
+            Label("rt_shift_args".to_owned()),
+            AluImm(AluOp::Cmp, R11, Reg::ARGS.len() as i32),
+            Jcc(Cond::L, label("done")),
+            Push(Rax),
+            Push(R11),
+            Mov(Rax, R11),
+            Shl(Rax, 3),
+            Alu(AluOp::Add, Rax, Rsp),
+            AluImm(AluOp::Sub, Rax, 8 * Reg::ARGS.len() as i32 - 32),
+            AluImm(AluOp::Sub, R11, Reg::ARGS.len() as i32),
+            Label(label("move")),
+            Test(R11, R11),
+            Jcc(Cond::E, label("moved")),
+            Load(R10, Mem::new(Rax, -8)),
+            Store(Mem::new(Rax, 0), R10),
+            AluImm(AluOp::Sub, Rax, 8),
+            AluImm(AluOp::Sub, R11, 1),
+            Jmp(label("move")),
+            Label(label("moved")),
+            Store(Mem::new(Rax, 0), R9),
+            Pop(R11),
+            Pop(Rax),
+            Label(label("done")),
+            Ret,
+        ]);
+    }
+
+    /// Reading and setting the properties of instances. Fields shadow methods.
+    fn properties(&mut self) {
+        use Inst::*;
+        use Reg::*;
+        // the function of the method named `rsi` of the class at `rdi`, or 0
+        self.emit([
+            Label("rt_method".to_owned()),
+            Load(Rcx, Mem::new(Rdi, 8)),
+            Mov(Rdx, Rdi),
+            AluImm(AluOp::Add, Rdx, 16),
+            Label("rt_method.next".to_owned()),
+            MovImm(Rax, 0),
+            Test(Rcx, Rcx),
+            Jcc(Cond::E, "rt_method.done".to_owned()),
+            Load(Rax, Mem::new(Rdx, 0)),
+            AluImm(AluOp::Add, Rdx, 16),
+            AluImm(AluOp::Sub, Rcx, 1),
+            Alu(AluOp::Cmp, Rax, Rsi),
+            Jcc(Cond::Ne, "rt_method.next".to_owned()),
+            Load(Rax, Mem::new(Rdx, -8)),
+            Label("rt_method.done".to_owned()),
+            Ret,
+        ]);
+
+        let label = |s: &str| format!("rt_get.{}", s);
+        self.emit([Label(routine::GET.to_owned())]);
+        self.expect_tag(Rdi, TAG_INSTANCE, "rt_err_get");
+        self.emit([
+            Mov32(Rdi, Rdi),
+            Load(Rcx, Mem::new(Rdi, 8)),
+            Load(Rdx, Mem::new(Rdi, 24)),
+            Label(label("field")),
+            Test(Rcx, Rcx),
+            Jcc(Cond::E, label("method")),
+            Load(Rax, Mem::new(Rdx, 0)),
+            AluImm(AluOp::Add, Rdx, 16),
+            AluImm(AluOp::Sub, Rcx, 1),
+            Alu(AluOp::Cmp, Rax, Rsi),
+            Jcc(Cond::Ne, label("field")),
+            Load(Rax, Mem::new(Rdx, -8)),
+            Ret,
+            Label(label("method")),
+            Mov(R8, Rdi),
+            Load(Rdi, Mem::new(R8, 0)),
+            Call("rt_method".to_owned()),
+            Test(Rax, Rax),
+            Jcc(Cond::E, "rt_err_property".to_owned()),
+            Mov(R9, Rax),
+            MovImm(Rax, 16),
+            Call(routine::ALLOC.to_owned()),
+            Store(Mem::new(Rax, 0), R9),
+            MovImm(Rcx, TAG_INSTANCE << 32),
+            Alu(AluOp::Or, R8, Rcx),
+            Store(Mem::new(Rax, 8), R8),
+            MovImm(Rcx, TAG_METHOD << 32),
+            Alu(AluOp::Or, Rax, Rcx),
+            Ret,
+        ]);
+
+        let label = |s: &str| format!("rt_set.{}", s);
+        self.emit([Label(routine::SET.to_owned())]);
+        self.expect_tag(Rdi, TAG_INSTANCE, "rt_err_set");
+        self.emit([
+            Mov32(Rdi, Rdi),
+            Load(Rcx, Mem::new(Rdi, 8)),
+            Load(R8, Mem::new(Rdi, 24)),
+            Label(label("field")),
+            Test(Rcx, Rcx),
+            Jcc(Cond::E, label("add")),
+            Load(Rax, Mem::new(R8, 0)),
+            AluImm(AluOp::Add, R8, 16),
+            AluImm(AluOp::Sub, Rcx, 1),
+            Alu(AluOp::Cmp, Rax, Rsi),
+            Jcc(Cond::Ne, label("field")),
+            Store(Mem::new(R8, -8), Rdx),
+            Ret,
+            Label(label("add")),
+            Load(Rcx, Mem::new(Rdi, 8)),
+            Load(Rax, Mem::new(Rdi, 16)),
+            Alu(AluOp::Cmp, Rcx, Rax),
+            Jcc(Cond::Ne, label("append")),
+            // out of room: move the fields to twice the capacity, or four
+            Shl(Rax, 1),
+            MovImm(R9, 4),
+            Test(Rax, Rax),
+            Cmov(Cond::E, Rax, R9),
+            Store(Mem::new(Rdi, 16), Rax),
+            Shl(Rax, 4),
+            Call(routine::ALLOC.to_owned()),
+            Load(R8, Mem::new(Rdi, 24)),
+            Store(Mem::new(Rdi, 24), Rax),
+            Mov(R9, Rcx),
+            Shl(R9, 1),
+            Label(label("copy")),
+            Test(R9, R9),
+            Jcc(Cond::E, label("append")),
+            Load(R10, Mem::new(R8, 0)),
+            Store(Mem::new(Rax, 0), R10),
+            AluImm(AluOp::Add, R8, 8),
+            AluImm(AluOp::Add, Rax, 8),
+            AluImm(AluOp::Sub, R9, 1),
+            Jmp(label("copy")),
+            Label(label("append")),
+            Load(R8, Mem::new(Rdi, 24)),
+            Mov(Rax, Rcx),
+            Shl(Rax, 4),
+            Alu(AluOp::Add, R8, Rax),
+            Store(Mem::new(R8, 0), Rsi),
+            Store(Mem::new(R8, 8), Rdx),
+            AluImm(AluOp::Add, Rcx, 1),
+            Store(Mem::new(Rdi, 8), Rcx),
+            Ret,
+        ]);
+    }
+
+    /// `!` on `nil` or a bool.
+    fn not(&mut self) {
+        use Inst::*;
+        use Reg::*;
+        self.emit([
+            Label(routine::NOT.to_owned()),
+            MovImm(Rax, TRUE),
+            Test(Rdi, Rdi),
+            Jcc(Cond::E, "rt_not.done".to_owned()),
+        ]);
+        self.expect_tag(Rdi, TAG_BOOL, "rt_err_bang");
+        self.emit([
+            Mov(Rax, Rdi),
+            AluImm(AluOp::Xor, Rax, 1),
+            Label("rt_not.done".to_owned()),
+            Ret,
+        ]);
+    }
+
+    /// a new string object holding the strings in `rdi` and `rsi` one after the other.
+    fn concat(&mut self) {
+        use Inst::*;
+        use Reg::*;
+        let label = |s: &str| format!("rt_concat.{}", s);
+        self.emit([
+            Label("rt_concat".to_owned()),
+            Mov32(Rdi, Rdi),
+            Mov32(Rsi, Rsi),
+            Load(Rcx, Mem::new(Rdi, 0)),
+            Load(Rdx, Mem::new(Rsi, 0)),
+            Mov(R8, Rcx),
+            Alu(AluOp::Add, R8, Rdx),
+            // allocate the length word and the bytes, rounded up to a word
+            Mov(Rax, R8),
+            AluImm(AluOp::Add, Rax, 15),
+            AluImm(AluOp::And, Rax, -8),
+            Call(routine::ALLOC.to_owned()),
+            Store(Mem::new(Rax, 0), R8),
+            Mov(R11, Rax),
+            AluImm(AluOp::Add, R11, 8),
+        ]);
+        for (src, len, part) in [(Rdi, Rcx, "lhs"), (Rsi, Rdx, "rhs")] {
+            self.emit([
+                AluImm(AluOp::Add, src, 8),
+                Label(label(part)),
+                Test(len, len),
+                Jcc(Cond::E, label(&format!("{}_done", part))),
+                LoadByte(R10, Mem::new(src, 0)),
+                StoreByte(Mem::new(R11, 0), R10),
+                AluImm(AluOp::Add, src, 1),
+                AluImm(AluOp::Add, R11, 1),
+                AluImm(AluOp::Sub, len, 1),
+                Jmp(label(part)),
+                Label(label(&format!("{}_done", part))),
+            ]);
+        }
+        self.emit([MovImm(Rcx, TAG_STR << 32), Alu(AluOp::Or, Rax, Rcx), Ret]);
+    }
+}
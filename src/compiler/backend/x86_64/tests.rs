@@ -0,0 +1,523 @@
+use crate::compiler::{
+    ast::literal::Literal,
+    backend::x86_64::{
+        asm::{encode, AluOp, Cond, Inst, Mem, Reg, SseOp, Xmm},
+        assembly, compile,
+        regalloc::{allocate, Location},
+    },
+    checker::check,
+    eval::EvalErr,
+    ir::{lower::lower, opt::optimize, Module},
+    lexer::Lexer,
+    natives::Natives,
+    parser::Parser,
+    resolver::resolve,
+    token::Token,
+};
+
+#[test]
+fn test_encode_instructions() {
+    use Inst::*;
+    use Reg::*;
+    let cases = [
+        (Mov(Rax, Rbx), vec![0x48, 0x89, 0xd8]),
+        (Mov(R8, Rdi), vec![0x49, 0x89, 0xf8]),
+        (
+            Load(Rdi, Mem::new(Rbp, -16)),
+            vec![0x48, 0x8b, 0xbd, 0xf0, 0xff, 0xff, 0xff],
+        ),
+        (
+            Store(Mem::new(Rsp, 8), Rax),
+            vec![0x48, 0x89, 0x84, 0x24, 0x08, 0, 0, 0],
+        ),
+        (
+            StoreByte(Mem::new(Rsi, 0), Rdx),
+            vec![0x40, 0x88, 0x96, 0, 0, 0, 0],
+        ),
+        (
+            LoadByte(R10, Mem::new(Rdi, 0)),
+            vec![0x44, 0x0f, 0xb6, 0x97, 0, 0, 0, 0],
+        ),
+        (Alu32(AluOp::Add, Rax, Rsi), vec![0x01, 0xf0]),
+        (
+            AluImm(AluOp::Sub, Rsp, 32),
+            vec![0x48, 0x81, 0xec, 0x20, 0, 0, 0],
+        ),
+        (Alu(AluOp::Cmp, R11, R10), vec![0x4d, 0x39, 0xd3]),
+        (Imul32(Rax, Rsi), vec![0x0f, 0xaf, 0xc6]),
+        (Cmov(Cond::L, Rax, Rdx), vec![0x48, 0x0f, 0x4c, 0xc2]),
+        (Push(R12), vec![0x41, 0x54]),
+        (CallReg(Rax), vec![0xff, 0xd0]),
+        (JmpReg(R10), vec![0x41, 0xff, 0xe2]),
+        (MovImm(Rdi, 1), vec![0x48, 0xbf, 1, 0, 0, 0, 0, 0, 0, 0]),
+        (Sar(Rax, 32), vec![0x48, 0xc1, 0xf8, 0x20]),
+        (Imul(Rax, Rdx), vec![0x48, 0x0f, 0xaf, 0xc2]),
+        (ShrCl(Rax), vec![0x48, 0xd3, 0xe8]),
+        (MovdToXmm(Xmm::Xmm1, R8), vec![0x66, 0x41, 0x0f, 0x6e, 0xc8]),
+        (MovdFromXmm(Rax, Xmm::Xmm0), vec![0x66, 0x0f, 0x7e, 0xc0]),
+        (Cvtsi2ss(Xmm::Xmm1, Rsi), vec![0xf3, 0x0f, 0x2a, 0xce]),
+        (Cvttss2si(Rdx, Xmm::Xmm1), vec![0xf3, 0x0f, 0x2c, 0xd1]),
+        (
+            Sse(SseOp::Div, Xmm::Xmm0, Xmm::Xmm1),
+            vec![0xf3, 0x0f, 0x5e, 0xc1],
+        ),
+        (Ucomiss(Xmm::Xmm1, Xmm::Xmm0), vec![0x0f, 0x2e, 0xc8]),
+    ];
+    for (inst, bytes) in cases {
+        assert_eq!(encode(&inst), bytes, "{}", inst);
+    }
+}
+
+/// the IR of a program at optimisation level 1.
+fn lowered(source: &str) -> Module {
+    let tokens: Vec<Token> = Lexer::from_source(source).collect();
+    let mut program = Parser::new(&tokens).parse().unwrap();
+    let globals = resolve(&mut program, &Natives::standard()).unwrap();
+    check(&program, &Natives::standard()).unwrap();
+    let mut module = lower(&program, &globals, Natives::standard().len());
+    optimize(&mut module, 1);
+    module
+}
+
+/// the executable compiled from a program at optimisation level 1.
+fn build(source: &str) -> Vec<u8> {
+    compile(&lowered(source))
+        .map_err(|e| e.to_string())
+        .unwrap()
+}
+
+/// a loop keeping more values live than there are registers to hold them.
+const PRESSURE: &str = "
+fn sum(n) {
+    let a = 1; let b = 2; let c = 3; let d = 4; let e = 5; let f = 6; let g = 7;
+    let s = 0;
+    let i = 0;
+    while (i < n) {
+        s = s + i + a + b + c + d + e + f + g;
+        i = i + 1;
+    }
+    return s + a + b + c + d + e + f + g;
+}
+print sum(10);
+";
+
+#[test]
+fn test_register_allocation() {
+    let module = lowered(PRESSURE);
+    let func = &module.functions[1];
+    let alloc = allocate(func);
+    let callee_saved = [Reg::Rbx, Reg::R12, Reg::R13, Reg::R14, Reg::R15];
+    for (i, a) in alloc.intervals.iter().enumerate() {
+        let location = alloc.location(a.value);
+        for b in &alloc.intervals[i + 1..] {
+            // a value dying where another is defined may hand its register over
+            if a.overlaps(b) && a.end != b.start && b.end != a.start {
+                assert_ne!(location, alloc.location(b.value), "{:?} {:?}", a, b);
+            }
+        }
+    }
+    // `n` is live across the calls in the loop
+    let Location::Reg(n) = alloc.location(alloc.intervals[0].value) else {
+        panic!("{:?}", alloc);
+    };
+    assert!(callee_saved.contains(&n), "{}", n);
+    assert!(alloc.spill_slots > 0);
+    assert_eq!(alloc.saved, callee_saved);
+}
+
+#[test]
+fn test_annotated_assembly() {
+    let asm = assembly(&lowered(PRESSURE)).unwrap().to_string();
+    let sum = &asm[asm.find("fn1:\n").unwrap()..];
+    for line in [
+        "    ; sum",
+        "    ; %0 [2, 52] rbx",
+        "    ; 2: %0 = param 0",
+        "    push r15",
+        "    pop rbx",
+    ] {
+        assert!(sum.lines().any(|l| l == line), "{}\n{}", line, sum);
+    }
+    assert!(sum.contains("] stack 0\n"), "{}", sum);
+}
+
+#[test]
+fn test_elf_header() {
+    let elf = build("print 1;");
+    assert_eq!(elf[..4], *b"\x7fELF");
+    // an x86-64 executable
+    assert_eq!(elf[16..20], [2, 0, 0x3e, 0]);
+    let entry = u64::from_le_bytes(elf[24..32].try_into().unwrap());
+    assert!(entry > 0x40_0000, "{:#x}", entry);
+}
+
+/// runs a compiled program and returns its exit status, stdout and stderr.
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn run_native(name: &str, source: &str) -> (i32, String, String) {
+    use std::os::unix::fs::PermissionsExt;
+
+    let path = std::env::temp_dir().join(format!("{}-{}", name, std::process::id()));
+    std::fs::write(&path, build(source)).unwrap();
+    std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let output = std::process::Command::new(&path).output().unwrap();
+    std::fs::remove_file(&path).unwrap();
+    (
+        output.status.code().unwrap(),
+        String::from_utf8(output.stdout).unwrap(),
+        String::from_utf8(output.stderr).unwrap(),
+    )
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn test_native_programs_run() {
+    let source = "
+fn fib(n) {
+    if (n < 2) { return n; }
+    return fib(n - 1) + fib(n - 2);
+}
+let i = 0;
+while (i <= 20) {
+    if (i == 0 or i == 20) { print fib(i); }
+    i = i + 5;
+}
+let greeting = \"hello\" + \", \" + \"world\";
+print greeting;
+print greeting == \"hello, world\";
+print -7 / 2;
+print !nil;
+print fib;
+";
+    let (status, stdout, stderr) = run_native("native-programs", source);
+    assert_eq!((status, stderr.as_str()), (0, ""));
+    assert_eq!(stdout, "0\n6765\nhello, world\ntrue\n-3\ntrue\n<fn fib>\n");
+
+    let source = "
+fn counter(step) {
+    let n = 0;
+    fn next() { n = n + step; return n; }
+    return next;
+}
+let a = counter(1);
+let b = counter(10);
+a();
+b();
+print a();
+print b();
+print a;
+fn adders() {
+    let total = 0;
+    fn add(x) { total = total + x; }
+    fn get() { return total; }
+    add(3);
+    add(4);
+    return get;
+}
+print adders()();
+";
+    let (status, stdout, stderr) = run_native("native-closures", source);
+    assert_eq!((status, stderr.as_str()), (0, ""));
+    assert_eq!(stdout, "2\n20\n<fn next>\n7\n");
+
+    let (status, stdout, _) = run_native("native-spills", PRESSURE);
+    assert_eq!((status, stdout.as_str()), (0, "353\n"));
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn test_native_classes() {
+    let source = "
+class Point {
+    fn init(x, y) { this.x = x; this.y = y; }
+    fn sum() { return this.x + this.y; }
+    fn scaled(k) { return Point(this.x * k, this.y * k); }
+}
+let p = Point(1, 2);
+let sum = p.sum;
+print sum();
+print p.scaled(10).sum();
+print p;
+print Point;
+print sum;
+class Bag {}
+let b = Bag();
+b.a = 1; b.b = 2; b.c = 3; b.d = 4; b.e = 5; b.f = 6;
+b.c = 30;
+print b.a + b.b + b.c + b.d + b.e + b.f;
+fn make(step) {
+    class Counter {
+        fn init() { this.n = 0; }
+        fn next() { this.n = this.n + step; return this.n; }
+    }
+    return Counter();
+}
+let c = make(5);
+c.next();
+print c.next();
+c.next = 7;
+print c.next;
+";
+    let (status, stdout, stderr) = run_native("native-classes", source);
+    assert_eq!((status, stderr.as_str()), (0, ""));
+    assert_eq!(
+        stdout,
+        "3\n30\n<Point instance>\nPoint\n<fn sum>\n48\n10\n7\n"
+    );
+
+    for (source, error) in [
+        (
+            "class A { fn init(a) {} } let g: any = A; print g(1, 2);",
+            "expected 1 arguments but got 2",
+        ),
+        (
+            "class A {} let g: any = A; print g(1);",
+            "expected 0 arguments but got 1",
+        ),
+        (
+            "class A { fn m(a) {} } let m: any = A().m; m();",
+            "expected 1 arguments but got 0",
+        ),
+        (
+            "class A {} let a: any = A(); print a.missing;",
+            "undefined property 'missing'",
+        ),
+        (
+            "let a: any = 1; print a.x;",
+            "only instances have properties",
+        ),
+        ("let a: any = 1; a.x = 2;", "only instances have fields"),
+    ] {
+        let (status, _, stderr) = run_native("native-class-errors", source);
+        assert_eq!(
+            (status, stderr),
+            (1, format!("error: {}\n", error)),
+            "{}",
+            source
+        );
+    }
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn test_native_runtime_errors() {
+    let (status, stdout, stderr) = run_native(
+        "native-arity",
+        "fn f(a) { return a; } let g: any = f; print 1; print g(1, 2);",
+    );
+    assert_eq!(status, 1);
+    assert_eq!(stdout, "1\n");
+    assert_eq!(stderr, "error: expected 1 arguments but got 2\n");
+
+    let (status, _, stderr) = run_native(
+        "native-overflow",
+        "fn f(x) { return x * 2147483647; } print f(3);",
+    );
+    assert_eq!(status, 1);
+    assert_eq!(stderr, "error: integer overflow\n");
+
+    let (status, _, stderr) = run_native("native-call", "let f: any = 1; f();");
+    assert_eq!(status, 1);
+    assert_eq!(stderr, "error: can only call functions and classes\n");
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn test_native_stack_arguments() {
+    let source = "
+fn digits(a, b, c, d, e, f, g, h) {
+    return a + 10 * (b + 10 * (c + 10 * (d + 10 * (e + 10 * (f + 10 * (g + 10 * h))))));
+}
+print digits(1, 2, 3, 4, 5, 6, 7, 8);
+fn outer(k) {
+    fn inner(a, b, c, d, e, f, g) { return a + b + c + d + e + f + g + k; }
+    return inner;
+}
+print outer(100)(1, 2, 3, 4, 5, 6, 7);
+class Box {
+    fn init(a, b, c, d, e, f, g) { this.sum = a + b + c + d + e + f + g; }
+    fn six(a, b, c, d, e, f) { return this.sum + a * b * c * d * e * f; }
+    fn seven(a, b, c, d, e, f, g) { return a - b - c - d - e - f - g; }
+}
+let box = Box(1, 2, 3, 4, 5, 6, 7);
+print box.sum;
+print box.six(1, 2, 3, 4, 5, 6);
+let seven = box.seven;
+print seven(100, 1, 2, 3, 4, 5, 6);
+class Pair {
+    fn init(a, b, c, d, e, f) { this.last = f; }
+}
+print Pair(1, 2, 3, 4, 5, 6).last;
+";
+    let (status, stdout, stderr) = run_native("native-stack-arguments", source);
+    assert_eq!((status, stderr.as_str()), (0, ""));
+    assert_eq!(stdout, "87654321\n128\n28\n748\n79\n6\n");
+
+    let (status, _, stderr) = run_native(
+        "native-stack-arity",
+        "class A { fn m(a, b, c, d, e, f) {} } let m: any = A().m; m(1, 2, 3, 4, 5, 6, 7);",
+    );
+    assert_eq!(
+        (status, stderr.as_str()),
+        (1, "error: expected 6 arguments but got 7\n")
+    );
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn test_native_heap_grows() {
+    // more than one chunk of objects, and a string larger than a chunk
+    let source = "
+class Node {
+    fn init(next) { this.next = next; }
+}
+let head: any = nil;
+let i = 0;
+while (i < 200000) {
+    head = Node(head);
+    i = i + 1;
+}
+let s = \"ab\";
+let t = \"ab\";
+i = 0;
+while (i < 22) {
+    s = s + s;
+    t = t + t;
+    i = i + 1;
+}
+let n = 0;
+while (head != nil) {
+    head = head.next;
+    n = n + 1;
+}
+print n;
+print s == t;
+";
+    let (status, stdout, stderr) = run_native("native-heap", source);
+    assert_eq!((status, stderr.as_str()), (0, ""));
+    assert_eq!(stdout, "200000\ntrue\n");
+}
+
+#[test]
+#[cfg(all(target_arch = "x86_64", target_os = "linux"))]
+fn test_native_floats() {
+    // literals from all over the range print as Rust prints them, which the interpreter uses
+    let mut source = String::new();
+    let mut expected = String::new();
+    let mut bits: u32 = 1;
+    for _ in 0..3000 {
+        bits = bits.wrapping_mul(1664525).wrapping_add(1013904223);
+        let f = f32::from_bits(bits);
+        if f.is_finite() {
+            source += &format!("print {};\n", Literal::Float(f).to_source());
+            expected += &format!("{}\n", f);
+        }
+    }
+    for f in [
+        f32::MAX,
+        f32::MIN_POSITIVE,
+        1e-45,
+        1e-7,
+        0.3,
+        16777216.0,
+        1.0,
+        100.0,
+    ] {
+        source += &format!("print {};\n", Literal::Float(f).to_source());
+        expected += &format!("{}\n", f);
+    }
+    let (status, stdout, stderr) = run_native("native-float-literals", &source);
+    assert_eq!((status, stderr.as_str()), (0, ""));
+    assert_eq!(stdout, expected);
+
+    // operands are parameters so the optimiser leaves the operators to the runtime
+    let source = "
+fn arithmetic(a, b) {
+    print a + b;
+    print a - b;
+    print a * b;
+    print a / b;
+    print -a;
+}
+arithmetic(1.5, 2);
+arithmetic(7, 0.25);
+arithmetic(0.0, 0.0);
+arithmetic(-1.0, 0.0);
+let sum = 0.0;
+let i = 0;
+while (i < 10) {
+    sum = sum + 0.1;
+    i = i + 1;
+}
+print sum;
+fn compare(a, b) {
+    print a < b;
+    print a <= b;
+    print a > b;
+    print a >= b;
+    print a == b;
+    print a != b;
+}
+compare(2.5, 2);
+compare(2, 2.5);
+compare(0.0 / 0.0, 1);
+compare(-1, 0.0 / 0.0);
+compare(1, 10000000000.0);
+compare(0.0, -0.0);
+compare(1, 1.0);
+let text: any = \"a\";
+print text < 1.5;
+";
+    let (status, stdout, stderr) = run_native("native-floats", source);
+    assert_eq!((status, stderr.as_str()), (0, ""));
+    let arithmetic =
+        |a: f32, b: f32| format!("{}\n{}\n{}\n{}\n{}\n", a + b, a - b, a * b, a / b, -a);
+    let mut sum = 0.0f32;
+    for _ in 0..10 {
+        sum += 0.1;
+    }
+    let expected = [
+        arithmetic(1.5, 2.0),
+        arithmetic(7.0, 0.25),
+        arithmetic(0.0, 0.0),
+        arithmetic(-1.0, 0.0),
+        format!("{}\n", sum),
+        // an `int` compares with a float truncated, NaN as 0, and is never equal to one
+        "false\nfalse\ntrue\ntrue\nfalse\ntrue\n".to_owned(),
+        "false\ntrue\nfalse\ntrue\nfalse\ntrue\n".to_owned(),
+        "false\nfalse\nfalse\nfalse\nfalse\ntrue\n".to_owned(),
+        "true\ntrue\nfalse\nfalse\nfalse\ntrue\n".to_owned(),
+        "true\ntrue\nfalse\nfalse\nfalse\ntrue\n".to_owned(),
+        "false\ntrue\nfalse\ntrue\ntrue\nfalse\n".to_owned(),
+        "false\ntrue\nfalse\ntrue\nfalse\ntrue\n".to_owned(),
+        "false\n".to_owned(),
+    ];
+    assert_eq!(stdout, expected.concat());
+
+    let (status, _, stderr) =
+        run_native("native-float-error", "let s: any = \"a\"; print 1.5 - s;");
+    assert_eq!(
+        (status, stderr),
+        (1, format!("error: {}\n", EvalErr::InvalidSub))
+    );
+}
+
+#[test]
+fn test_unsupported_features() {
+    for (source, feature) in [
+        ("throw 1;", "exceptions"),
+        ("try { print 1; } catch (e) { print e; }", "exceptions"),
+        // compiled code cannot call into the host
+        ("print str(1);", "the native 'str'"),
+        // even in code that never runs
+        ("fn f() { return str(1); } let x = 1;", "the native 'str'"),
+    ] {
+        let tokens: Vec<Token> = Lexer::from_source(source).collect();
+        let mut program = Parser::new(&tokens).parse().unwrap();
+        let globals = resolve(&mut program, &Natives::standard()).unwrap();
+        let module = lower(&program, &globals, Natives::standard().len());
+        assert_eq!(
+            compile(&module).unwrap_err().to_string(),
+            format!("the x86_64-linux target does not support {} yet", feature)
+        );
+    }
+}
@@ -0,0 +1,1798 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::{
+    ast::{
+        expr::{BinaryOp, Expr, Identifier, Slot, UnaryOp},
+        literal::Literal,
+        types::{Constraint, Quantified, Scheme, Type, TypeParam},
+        visitor::{walk_stmt, Visitor},
+    },
+    modules::Module,
+    natives::Natives,
+    statements::stmt::{Block, Catch, ClassDecl, FnDecl, InterfaceDecl, Stmt, StmtKind},
+    value::Class,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum TypeErr {
+    Mismatch {
+        expected: Type,
+        found: Type,
+        line: usize,
+    },
+    Unsatisfied(Constraint, Type, usize),
+    InvalidOperands(&'static str, Type, Type, usize),
+    InvalidOperand(&'static str, Type, usize),
+    InvalidArity(usize, usize, usize),
+    NotCallable(Type, usize),
+    NoProperties(Type, usize),
+    NoMethod(Type, String, usize),
+    UnknownType(String, usize),
+    UnknownInterface(String, usize),
+    /// expected and found number of type arguments to a generic class.
+    InvalidTypeArity(String, usize, usize, usize),
+    NotIndexable(Type, usize),
+    NotIterable(Type, usize),
+    /// a type argument that lacks what a bound of its parameter asks for, and why if it is a class.
+    Unimplemented {
+        ty: Type,
+        interface: String,
+        reason: Option<String>,
+        line: usize,
+    },
+}
+
+impl std::fmt::Display for TypeErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypeErr::Mismatch {
+                expected,
+                found,
+                line,
+            } => write!(
+                f,
+                "line {}: expected {} but found {}",
+                line, expected, found
+            ),
+            TypeErr::Unsatisfied(constraint, found, line) => write!(
+                f,
+                "line {}: expected a {} but found {}",
+                line, constraint, found
+            ),
+            TypeErr::InvalidOperands(op, lhs, rhs, line) => write!(
+                f,
+                "line {}: '{}' cannot be applied to {} and {}",
+                line, op, lhs, rhs
+            ),
+            TypeErr::InvalidOperand(op, rhs, line) => {
+                write!(f, "line {}: '{}' cannot be applied to {}", line, op, rhs)
+            }
+            TypeErr::InvalidArity(expected, found, line) => write!(
+                f,
+                "line {}: expected {} arguments but got {}",
+                line, expected, found
+            ),
+            TypeErr::NotCallable(ty, line) => write!(f, "line {}: {} is not callable", line, ty),
+            TypeErr::NoProperties(ty, line) => {
+                write!(f, "line {}: {} has no properties", line, ty)
+            }
+            TypeErr::NoMethod(ty, name, line) => {
+                write!(f, "line {}: {} has no method '{}'", line, ty, name)
+            }
+            TypeErr::UnknownType(name, line) => write!(f, "line {}: unknown type '{}'", line, name),
+            TypeErr::UnknownInterface(name, line) => {
+                write!(f, "line {}: unknown interface '{}'", line, name)
+            }
+            TypeErr::InvalidTypeArity(class, expected, found, line) => write!(
+                f,
+                "line {}: expected {} type arguments for {} but got {}",
+                line, expected, class, found
+            ),
+            TypeErr::NotIndexable(ty, line) => {
+                write!(f, "line {}: {} cannot be indexed", line, ty)
+            }
+            TypeErr::NotIterable(ty, line) => {
+                write!(f, "line {}: {} cannot be iterated over", line, ty)
+            }
+            TypeErr::Unimplemented {
+                ty,
+                interface,
+                reason,
+                line,
+            } => {
+                write!(f, "line {}: {} does not implement {}", line, ty, interface)?;
+                match reason {
+                    Some(reason) => write!(f, ": {}", reason),
+                    None => Ok(()),
+                }
+            }
+        }
+    }
+}
+
+impl TypeErr {
+    pub fn line(&self) -> usize {
+        match self {
+            TypeErr::Mismatch { line, .. }
+            | TypeErr::Unsatisfied(_, _, line)
+            | TypeErr::InvalidOperands(_, _, _, line)
+            | TypeErr::InvalidOperand(_, _, line)
+            | TypeErr::InvalidArity(_, _, line)
+            | TypeErr::NotCallable(_, line)
+            | TypeErr::NoProperties(_, line)
+            | TypeErr::NoMethod(_, _, line)
+            | TypeErr::UnknownType(_, line)
+            | TypeErr::UnknownInterface(_, line)
+            | TypeErr::InvalidTypeArity(_, _, _, line)
+            | TypeErr::NotIndexable(_, line)
+            | TypeErr::NotIterable(_, line)
+            | TypeErr::Unimplemented { line, .. } => *line,
+        }
+    }
+}
+
+/// The inferred type of a declaration, as reported by `check --show-types`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Binding {
+    /// `name`, or `Class.method` for methods.
+    pub name: String,
+    pub line: usize,
+    pub scheme: Scheme,
+}
+
+/// What checking a module found the types of its top-level declarations to be: all that the
+/// modules importing it need of it, and all a cache needs to skip checking it again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interface {
+    /// the scheme of every top-level `fn`, `class` and `let`, in order. Quantified variables are
+    /// numbered from 0 in order of appearance, so equal interfaces are equal values.
+    pub globals: Vec<Scheme>,
+    /// the schemes of the methods of every top-level class, in order.
+    pub methods: Vec<Vec<Scheme>>,
+}
+
+/// asked for the types of a module, by index, given the interfaces of the modules before it.
+pub type ReuseTypes<'a> = dyn FnMut(usize, &[Option<Interface>]) -> Option<ModuleTypes> + 'a;
+
+/// The types of one module of a program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleTypes {
+    pub bindings: Vec<Binding>,
+    /// `None` if the module left a type for the modules importing it to pin down, e.g. that of an
+    /// exported `let xs = [];`, and so has to be checked along with them.
+    pub interface: Option<Interface>,
+    /// the operands of `+ - * /` proven to be `int`, by address into the module's program, which
+    /// the optimizer needs to turn `x + 0` into `x`. Empty for a module whose types were reused.
+    pub ints: HashSet<*const Expr>,
+}
+
+/// Infers and checks the types of a resolved program, Hindley–Milner style. Annotations are taken as
+/// given and everything else is inferred from use, so `fn double(x) { return x * 2; }` is
+/// `fn(int) -> int` and calling it with a string is an error before the program runs. `fn`
+/// declarations are generalised, so a helper like `fn id(x) { return x; }` can be used at several
+/// types; `let` variables are not, since they can be reassigned. `any` opts out: it unifies with
+/// every type without pinning it down.
+///
+/// Operators follow the `Literal` operator impls. On known types an `int` and a `float` mix to a
+/// `float`, and inference leaves room for that: an operand that is still being inferred is only
+/// constrained to what the operator accepts, so `fn add(a, b) { return a + b; }` takes an `int` and
+/// a `float`. Likewise a variable given an `int` can be assigned a `float` later, and is an `int`
+/// only if nothing in the function declaring it does so.
+///
+/// Generic `fn`s and `class`es are checked once against their type parameters, which are opaque
+/// inside the body except for the methods their bounds promise. At a use the parameters are
+/// instantiated like inferred variables, so type arguments are never written at call sites, and an
+/// argument has to implement every interface its parameter is bounded by. Interfaces are
+/// structural: a class implements one by having methods of the right types. Built-in types
+/// implement none.
+///
+/// Lists and maps are homogeneous: `[1, 2]` is a `list<int>`. A literal whose elements disagree is
+/// a `list<any>` rather than an error, as with `and` and `or`.
+pub fn check(program: &[Stmt], natives: &Natives) -> Result<ModuleTypes, Vec<TypeErr>> {
+    let (mut types, errors) = check_programs(&[program], &[""], natives, &mut |_, _| None);
+    match errors.is_empty() {
+        true => Ok(types.remove(0)),
+        false => Err(errors.into_iter().map(|(_, e)| e).collect()),
+    }
+}
+
+/// Checks the modules of a program, resolved together, as one program, and returns the types of
+/// each, or the errors with the index of their module. Before checking a module, `reuse` is asked
+/// for its types with the interfaces of the modules before it; a module it has an interface for
+/// is taken to have it rather than checked.
+pub fn check_modules(
+    modules: &[Module],
+    natives: &Natives,
+    reuse: &mut ReuseTypes,
+) -> Result<Vec<ModuleTypes>, Vec<(usize, TypeErr)>> {
+    let (types, errors) = check_modules_partial(modules, natives, reuse);
+    match errors.is_empty() {
+        true => Ok(types),
+        false => Err(errors),
+    }
+}
+
+/// Like `check_modules`, but returns the types inferred even when there are errors, together with
+/// them. A name whose declaration checked keeps its type; one that didn't may be left partly open.
+pub fn check_modules_partial(
+    modules: &[Module],
+    natives: &Natives,
+    reuse: &mut ReuseTypes,
+) -> (Vec<ModuleTypes>, Vec<(usize, TypeErr)>) {
+    let programs: Vec<&[Stmt]> = modules.iter().map(|m| m.program.as_slice()).collect();
+    let names: Vec<&str> = modules.iter().map(|m| m.name.as_str()).collect();
+    check_programs(&programs, &names, natives, reuse)
+}
+
+/// Checks that a value of type `found`, such as one the host gives a global, can stand where
+/// `scheme` is declared, on `line`.
+pub fn check_assignable(scheme: &Scheme, found: &Type, line: usize) -> Result<(), TypeErr> {
+    // a variable inference left open, so not quantified, could have been anything
+    let mut open = Vec::new();
+    scheme.ty.vars(&mut open);
+    open.retain(|id| !scheme.vars.iter().any(|q| q.id == *id));
+    let any = open.into_iter().map(|id| (id, Type::Any)).collect();
+    let scheme = Scheme {
+        ty: substitute(&scheme.ty, &any),
+        ..scheme.clone()
+    };
+    let mut checker = Checker {
+        line,
+        ..Checker::default()
+    };
+    let expected = checker.instantiate(&scheme);
+    checker.expect(&expected, found);
+    checker.errors.pop().map_or(Ok(()), Err)
+}
+
+/// `names` are the modules' names, which qualify the classes and interfaces they declare, see
+/// `Checker::type_name`.
+fn check_programs(
+    programs: &[&[Stmt]],
+    names: &[&str],
+    natives: &Natives,
+    reuse: &mut ReuseTypes,
+) -> (Vec<ModuleTypes>, Vec<(usize, TypeErr)>) {
+    let mut checker = Checker::default();
+    checker.hoist_globals(programs, names, natives);
+    let mut interfaces = Vec::new();
+    let mut reused = Vec::new();
+    let mut ends = Vec::new();
+    for (module, program) in programs.iter().enumerate() {
+        checker.module = names[module].to_owned();
+        match reuse(module, &interfaces) {
+            Some(ModuleTypes {
+                bindings,
+                interface: Some(interface),
+                ..
+            }) => {
+                checker.install(program, &interface);
+                interfaces.push(Some(interface));
+                reused.push(Some(bindings));
+            }
+            _ => {
+                for stmt in *program {
+                    checker.visit_stmt(stmt);
+                }
+                checker.settle_joins();
+                checker.settle_numbers(0);
+                checker.settle_joins();
+                checker.attribute_errors(module);
+                interfaces.push(checker.interface(program));
+                reused.push(None);
+            }
+        }
+        ends.push((checker.bindings.len(), checker.operands.len()));
+    }
+
+    let mut errors: Vec<(usize, TypeErr)> = std::mem::take(&mut checker.owners)
+        .into_iter()
+        .zip(std::mem::take(&mut checker.errors))
+        .collect();
+    // a bad type argument is reported both in an annotation and in the value it annotates
+    errors.dedup();
+    let mut bindings = std::mem::take(&mut checker.bindings).into_iter();
+    let mut operands = std::mem::take(&mut checker.operands).into_iter();
+    let mut start = (0, 0);
+    let types = ends
+        .into_iter()
+        .zip(interfaces)
+        .zip(reused)
+        .map(|((end, interface), reused)| {
+            let checked = bindings.by_ref().take(end.0 - start.0);
+            let ints = operands
+                .by_ref()
+                .take(end.1 - start.1)
+                .filter(|(_, ty)| checker.zonk(ty) == Type::Int)
+                .map(|(expr, _)| expr)
+                .collect();
+            start = end;
+            let bindings = reused.unwrap_or_else(|| {
+                checked
+                    .map(|mut binding| {
+                        binding.scheme.ty = checker.zonk(&binding.scheme.ty);
+                        binding
+                    })
+                    .collect()
+            });
+            ModuleTypes {
+                bindings,
+                interface,
+                ints,
+            }
+        })
+        .collect();
+    (types, errors)
+}
+
+#[derive(Debug, Clone)]
+enum VarState {
+    /// `level` is how many functions deep the variable was created, see `Checker::generalize`.
+    Unbound {
+        level: usize,
+        constraint: Option<Constraint>,
+        /// interfaces whatever the variable is bound to must implement.
+        bounds: Vec<String>,
+    },
+    Bound(Type),
+}
+
+/// why two types failed to unify.
+enum UnifyErr {
+    Mismatch,
+    Unsatisfied(Constraint, Type),
+    Unimplemented(Type, String, Option<String>),
+}
+
+struct ClassInfo {
+    params: Vec<TypeParam>,
+    /// signatures in terms of `params`, which a use of the class substitutes with its arguments.
+    methods: HashMap<String, Scheme>,
+}
+
+#[derive(Default)]
+struct Checker {
+    vars: Vec<VarState>,
+    /// number of enclosing function declarations.
+    level: usize,
+    /// indexed by `Slot::Global`, in the order the resolver hoisted them.
+    globals: Vec<Scheme>,
+    /// mirrors the resolver's scopes, so `Slot::Local` indexes straight into them.
+    scopes: Vec<Vec<Scheme>>,
+    /// by `type_name`.
+    classes: HashMap<String, ClassInfo>,
+    /// the types the host registered, whose objects have no properties.
+    host_types: Vec<&'static str>,
+    /// the methods of every interface, typed with `Self` as a type parameter, by `type_name`.
+    interfaces: HashMap<String, Vec<(String, Type)>>,
+    /// type parameters of the enclosing generic declarations, innermost last.
+    type_params: Vec<TypeParam>,
+    /// return type of the enclosing function, `None` at the top level.
+    return_type: Option<Type>,
+    bindings: Vec<Binding>,
+    /// every operand of an arithmetic operator with its type, for `ModuleTypes::ints`.
+    operands: Vec<(*const Expr, Type)>,
+    /// variables given an `int` that a `float` may still widen, see `store`.
+    numbers: Vec<Type>,
+    /// results of operators on operands still being inferred, with the operands and what the
+    /// operator accepts: once both are known the result is their promotion, see `settle_joins`.
+    joins: Vec<(Type, Type, Type, Option<Constraint>)>,
+    line: usize,
+    errors: Vec<TypeErr>,
+    /// the module each of `errors` is in, as far as `attribute_errors` has got.
+    owners: Vec<usize>,
+    /// the name of the module being checked, empty for the one a program starts from.
+    module: String,
+}
+
+impl Checker {
+    /// types the top-level declarations in the same order as `resolver::resolve`, so code can use
+    /// functions and classes declared after it. Their signatures start out monomorphic and are
+    /// generalised once their bodies have been checked. With several modules, every class and
+    /// interface is known before any module's globals are typed.
+    fn hoist_globals(&mut self, programs: &[&[Stmt]], names: &[&str], natives: &Natives) {
+        for native in natives.iter() {
+            self.globals.push(native.signature.clone());
+        }
+        for name in natives.types() {
+            let info = ClassInfo {
+                params: Vec::new(),
+                methods: HashMap::new(),
+            };
+            self.classes.insert(name.to_string(), info);
+            self.host_types.push(name);
+        }
+        for (program, name) in programs.iter().zip(names) {
+            self.module = name.to_string();
+            for stmt in *program {
+                if let StmtKind::Class(decl) = &stmt.kind {
+                    let info = ClassInfo {
+                        params: decl.type_params.clone(),
+                        methods: HashMap::new(),
+                    };
+                    self.classes.insert(self.type_name(&decl.name), info);
+                }
+            }
+        }
+        for (module, program) in programs.iter().enumerate() {
+            self.module = names[module].to_owned();
+            for stmt in *program {
+                if let StmtKind::Interface(decl) = &stmt.kind {
+                    self.line = stmt.line;
+                    self.declare_interface(decl);
+                }
+            }
+            self.attribute_errors(module);
+        }
+        for (module, program) in programs.iter().enumerate() {
+            self.module = names[module].to_owned();
+            self.hoist_program(program, natives);
+            self.attribute_errors(module);
+        }
+    }
+    fn hoist_program(&mut self, program: &[Stmt], natives: &Natives) {
+        for stmt in program {
+            self.line = stmt.line;
+            let (name, scheme) = match &stmt.kind {
+                StmtKind::Fn(decl) => (
+                    &decl.name,
+                    Scheme {
+                        vars: Vec::new(),
+                        params: decl.type_params.clone(),
+                        ty: self.signature(decl, self.level + 1),
+                    },
+                ),
+                StmtKind::Class(decl) => (
+                    &decl.name,
+                    Scheme {
+                        vars: Vec::new(),
+                        params: decl.type_params.clone(),
+                        ty: self.declare_class(decl),
+                    },
+                ),
+                StmtKind::Let { name, .. } => (name, Scheme::mono(self.fresh(self.level))),
+                _ => continue,
+            };
+            // a global named like a native may take over its slot, see `Resolver::hoist_globals`
+            let index = match name.slot {
+                Some(Slot::Global(index)) => Some(index),
+                _ => natives.iter().position(|native| native.name == name.name),
+            };
+            match index {
+                Some(index) if index < self.globals.len() => self.globals[index] = scheme,
+                _ => self.globals.push(scheme),
+            }
+        }
+    }
+    /// the final types of a checked program's top-level declarations, if it left none of them for
+    /// other modules to pin down.
+    fn interface(&self, program: &[Stmt]) -> Option<Interface> {
+        let mut globals = Vec::new();
+        let mut methods = Vec::new();
+        for stmt in program {
+            let name = match &stmt.kind {
+                StmtKind::Fn(decl) => &decl.name,
+                StmtKind::Class(decl) => {
+                    let info = &self.classes[&self.type_name(&decl.name)];
+                    let schemes = decl
+                        .methods
+                        .iter()
+                        .map(|method| self.close(&info.methods[&method.name.name]))
+                        .collect::<Option<_>>()?;
+                    methods.push(schemes);
+                    &decl.name
+                }
+                StmtKind::Let { name, .. } => name,
+                _ => continue,
+            };
+            let Some(Slot::Global(index)) = name.slot else {
+                return None;
+            };
+            globals.push(self.close(&self.globals[index])?);
+        }
+        Some(Interface { globals, methods })
+    }
+    /// `scheme` with its bound variables replaced and its quantified ones numbered from 0, or
+    /// `None` if it has a variable it does not quantify.
+    fn close(&self, scheme: &Scheme) -> Option<Scheme> {
+        let ty = self.zonk(&scheme.ty);
+        let mut order = Vec::new();
+        ty.vars(&mut order);
+        let mut renumbered = HashMap::new();
+        let mut vars = Vec::new();
+        for id in order {
+            let q = scheme.vars.iter().find(|q| q.id == id)?;
+            renumbered.insert(id, Type::Var(vars.len()));
+            vars.push(Quantified {
+                id: vars.len(),
+                ..q.clone()
+            });
+        }
+        for q in &mut vars {
+            if let Some((a, b)) = q.promotes {
+                let renumber = |id| match renumbered.get(&id) {
+                    Some(Type::Var(id)) => Some(*id),
+                    _ => None,
+                };
+                q.promotes = Some((renumber(a)?, renumber(b)?));
+            }
+        }
+        Some(Scheme {
+            vars,
+            params: scheme.params.clone(),
+            ty: substitute(&ty, &renumbered),
+        })
+    }
+    /// gives the program's top-level declarations the types in `interface` rather than checking
+    /// their bodies.
+    fn install(&mut self, program: &[Stmt], interface: &Interface) {
+        let mut globals = interface.globals.iter();
+        let mut methods = interface.methods.iter();
+        for stmt in program {
+            let name = match &stmt.kind {
+                StmtKind::Fn(decl) => &decl.name,
+                StmtKind::Class(decl) => {
+                    for (method, scheme) in
+                        decl.methods.iter().zip(methods.next().unwrap_or(&vec![]))
+                    {
+                        let scheme = self.fresh_scheme(scheme);
+                        if let Some(info) = self.classes.get_mut(&self.type_name(&decl.name)) {
+                            info.methods.insert(method.name.name.clone(), scheme);
+                        }
+                    }
+                    &decl.name
+                }
+                StmtKind::Let { name, .. } => name,
+                _ => continue,
+            };
+            if let (Some(Slot::Global(index)), Some(scheme)) = (name.slot, globals.next()) {
+                self.globals[index] = self.fresh_scheme(scheme);
+            }
+        }
+    }
+    /// `scheme` with fresh variables of this checker for its quantified ones, whose numbers are
+    /// those of another.
+    fn fresh_scheme(&mut self, scheme: &Scheme) -> Scheme {
+        let mut fresh = HashMap::new();
+        let mut vars = Vec::new();
+        for q in &scheme.vars {
+            self.vars.push(VarState::Unbound {
+                level: self.level + 1,
+                constraint: q.constraint,
+                bounds: q.bounds.clone(),
+            });
+            let id = self.vars.len() - 1;
+            fresh.insert(q.id, Type::Var(id));
+            vars.push(Quantified { id, ..q.clone() });
+        }
+        let renumber = |id: usize| match fresh[&id] {
+            Type::Var(id) => id,
+            _ => unreachable!("quantified variables are replaced by variables"),
+        };
+        for q in &mut vars {
+            q.promotes = q.promotes.map(|(a, b)| (renumber(a), renumber(b)));
+        }
+        Scheme {
+            vars,
+            params: scheme.params.clone(),
+            ty: substitute(&scheme.ty, &fresh),
+        }
+    }
+    /// what the class or interface declared as `name` is known by: a top-level one of a module
+    /// other than the one a program starts from is qualified with the module's name, e.g.
+    /// `geometry.Point`, which is what the resolver turns the names in annotations into.
+    fn type_name(&self, name: &Identifier) -> String {
+        if self.module.is_empty() || !self.scopes.is_empty() {
+            name.name.clone()
+        } else {
+            format!("{}.{}", self.module, name.name)
+        }
+    }
+    /// marks the errors since the last call as `module`'s.
+    fn attribute_errors(&mut self, module: usize) {
+        self.owners.resize(self.errors.len(), module);
+    }
+
+    fn fresh(&mut self, level: usize) -> Type {
+        self.fresh_bounded(level, Vec::new())
+    }
+    fn fresh_bounded(&mut self, level: usize, bounds: Vec<String>) -> Type {
+        self.vars.push(VarState::Unbound {
+            level,
+            constraint: None,
+            bounds,
+        });
+        Type::Var(self.vars.len() - 1)
+    }
+    /// the bounds of the type parameter `name` in scope, or `None` if there is no such parameter.
+    fn param_bounds(&self, name: &str) -> Option<&[String]> {
+        self.type_params
+            .iter()
+            .rev()
+            .find(|p| p.name == name)
+            .map(|p| p.bounds.as_slice())
+    }
+    /// reports bounds that do not name an interface.
+    fn check_bounds(&mut self, params: &[TypeParam]) {
+        for param in params {
+            for bound in &param.bounds {
+                if !self.interfaces.contains_key(bound) {
+                    self.errors
+                        .push(TypeErr::UnknownInterface(bound.clone(), self.line));
+                }
+            }
+        }
+    }
+    /// an annotation as the checker sees it: names of type parameters in scope become `Param`, and
+    /// a generic class written without arguments gets fresh variables at `level` for them. Unknown
+    /// classes and wrong type arguments are reported and taken as `any`.
+    fn annotation(&mut self, ty: &Type, level: usize) -> Type {
+        match ty {
+            Type::Instance(name, args) => {
+                if args.is_empty() && self.param_bounds(name).is_some() {
+                    return Type::Param(name.clone());
+                }
+                let Some(params) = self.classes.get(name).map(|c| c.params.clone()) else {
+                    self.errors
+                        .push(TypeErr::UnknownType(name.clone(), self.line));
+                    return Type::Any;
+                };
+                if args.is_empty() {
+                    let args = params
+                        .into_iter()
+                        .map(|p| self.fresh_bounded(level, p.bounds))
+                        .collect();
+                    return Type::Instance(name.clone(), args);
+                }
+                if args.len() != params.len() {
+                    self.errors.push(TypeErr::InvalidTypeArity(
+                        name.clone(),
+                        params.len(),
+                        args.len(),
+                        self.line,
+                    ));
+                    return Type::Any;
+                }
+                let args: Vec<Type> = args.iter().map(|a| self.annotation(a, level)).collect();
+                for (param, arg) in params.iter().zip(&args) {
+                    for bound in &param.bounds {
+                        if let Err(err) = self.implement(arg, bound) {
+                            self.report(err);
+                        }
+                    }
+                }
+                Type::Instance(name.clone(), args)
+            }
+            Type::Fn { params, ret } => Type::Fn {
+                params: params.iter().map(|p| self.annotation(p, level)).collect(),
+                ret: Box::new(self.annotation(ret, level)),
+            },
+            Type::List(elem) => Type::List(Box::new(self.annotation(elem, level))),
+            Type::Map(key, value) => Type::Map(
+                Box::new(self.annotation(key, level)),
+                Box::new(self.annotation(value, level)),
+            ),
+            ty => ty.clone(),
+        }
+    }
+    /// the declared signature of a function, with fresh variables at `level` for whatever is not
+    /// annotated.
+    fn signature(&mut self, decl: &FnDecl, level: usize) -> Type {
+        self.check_bounds(&decl.type_params);
+        let depth = self.type_params.len();
+        self.type_params.extend(decl.type_params.iter().cloned());
+        let mut params = Vec::with_capacity(decl.params.len());
+        for param in &decl.params {
+            params.push(match &param.ty {
+                Some(ty) => self.annotation(ty, level),
+                None => self.fresh(level),
+            });
+        }
+        let ret = match &decl.return_type {
+            Some(ty) => self.annotation(ty, level),
+            None => self.fresh(level),
+        };
+        self.type_params.truncate(depth);
+        Type::Fn {
+            params,
+            ret: Box::new(ret),
+        }
+    }
+    /// the type of instances of `decl` inside its own methods.
+    fn this_type(&self, decl: &ClassDecl) -> Type {
+        let args = decl
+            .type_params
+            .iter()
+            .map(|p| Type::Param(p.name.clone()))
+            .collect();
+        Type::Instance(self.type_name(&decl.name), args)
+    }
+    /// registers the method signatures of a class and returns the type of its constructor.
+    fn declare_class(&mut self, decl: &ClassDecl) -> Type {
+        self.check_bounds(&decl.type_params);
+        let instance = self.this_type(decl);
+        let depth = self.type_params.len();
+        self.type_params.extend(decl.type_params.iter().cloned());
+        let mut methods = HashMap::new();
+        let mut ctor_params = Vec::new();
+        for method in &decl.methods {
+            self.line = method.name.line;
+            let mut sig = self.signature(method, self.level + 1);
+            if method.name.name == Class::INITIALIZER {
+                // `init` hands back the instance whatever its body returns
+                if let Type::Fn { params, ret } = &mut sig {
+                    ctor_params = params.clone();
+                    **ret = instance.clone();
+                }
+            }
+            let scheme = Scheme {
+                vars: Vec::new(),
+                params: method.type_params.clone(),
+                ty: sig,
+            };
+            methods.insert(method.name.name.clone(), scheme);
+        }
+        self.type_params.truncate(depth);
+        let info = ClassInfo {
+            params: decl.type_params.clone(),
+            methods,
+        };
+        self.classes.insert(self.type_name(&decl.name), info);
+        Type::Fn {
+            params: ctor_params,
+            ret: Box::new(instance),
+        }
+    }
+    /// registers the method types of an interface. A signature has no body to infer the rest
+    /// from, so whatever it leaves unannotated is `any`.
+    fn declare_interface(&mut self, decl: &InterfaceDecl) {
+        self.type_params.push(TypeParam {
+            name: "Self".to_owned(),
+            bounds: Vec::new(),
+        });
+        let mut methods = Vec::new();
+        for method in &decl.methods {
+            self.line = method.name.line;
+            let mut params = Vec::new();
+            for param in &method.params {
+                params.push(match &param.ty {
+                    Some(ty) => self.annotation(ty, self.level),
+                    None => Type::Any,
+                });
+            }
+            let ret = match &method.return_type {
+                Some(ty) => self.annotation(ty, self.level),
+                None => Type::Any,
+            };
+            let ty = Type::Fn {
+                params,
+                ret: Box::new(ret),
+            };
+            methods.push((method.name.name.clone(), ty));
+        }
+        self.type_params.pop();
+        self.interfaces.insert(self.type_name(&decl.name), methods);
+    }
+    /// the type of method `name` on an instance of `class` with type arguments `args`.
+    fn method(&mut self, class: &str, args: &[Type], name: &str) -> Option<Type> {
+        let info = self.classes.get(class)?;
+        let scheme = info.methods.get(name)?;
+        let params: HashMap<String, Type> = info
+            .params
+            .iter()
+            .map(|p| p.name.clone())
+            .zip(args.iter().cloned())
+            .collect();
+        let scheme = Scheme {
+            ty: substitute_params(&scheme.ty, &params),
+            ..scheme.clone()
+        };
+        Some(self.instantiate(&scheme))
+    }
+
+    /// follows bound variables until reaching a type that is not one.
+    fn resolve(&self, ty: &Type) -> Type {
+        let mut ty = ty.clone();
+        while let Type::Var(id) = ty {
+            match &self.vars[id] {
+                VarState::Bound(bound) => ty = bound.clone(),
+                VarState::Unbound { .. } => break,
+            }
+        }
+        ty
+    }
+    /// replaces every bound variable inside `ty` by what it is bound to.
+    fn zonk(&self, ty: &Type) -> Type {
+        match self.resolve(ty) {
+            Type::Fn { params, ret } => Type::Fn {
+                params: params.iter().map(|p| self.zonk(p)).collect(),
+                ret: Box::new(self.zonk(&ret)),
+            },
+            Type::Instance(class, args) => {
+                Type::Instance(class, args.iter().map(|a| self.zonk(a)).collect())
+            }
+            Type::List(elem) => Type::List(Box::new(self.zonk(&elem))),
+            Type::Map(key, value) => {
+                Type::Map(Box::new(self.zonk(&key)), Box::new(self.zonk(&value)))
+            }
+            ty => ty,
+        }
+    }
+    fn unify(&mut self, expected: &Type, found: &Type) -> Result<(), UnifyErr> {
+        match (self.resolve(expected), self.resolve(found)) {
+            (Type::Any, _) | (_, Type::Any) => Ok(()),
+            (Type::Var(a), Type::Var(b)) if a == b => Ok(()),
+            (Type::Var(id), ty) | (ty, Type::Var(id)) => self.bind(id, ty),
+            (
+                Type::Fn { params, ret },
+                Type::Fn {
+                    params: found_params,
+                    ret: found_ret,
+                },
+            ) => {
+                if params.len() != found_params.len() {
+                    return Err(UnifyErr::Mismatch);
+                }
+                for (p, f) in params.iter().zip(&found_params) {
+                    self.unify(p, f)?;
+                }
+                self.unify(&ret, &found_ret)
+            }
+            (Type::Instance(class, args), Type::Instance(found_class, found_args)) => {
+                if class != found_class || args.len() != found_args.len() {
+                    return Err(UnifyErr::Mismatch);
+                }
+                for (a, f) in args.iter().zip(&found_args) {
+                    self.unify(a, f)?;
+                }
+                Ok(())
+            }
+            (Type::List(elem), Type::List(found_elem)) => self.unify(&elem, &found_elem),
+            (Type::Map(key, value), Type::Map(found_key, found_value)) => {
+                self.unify(&key, &found_key)?;
+                self.unify(&value, &found_value)
+            }
+            (a, b) if a == b => Ok(()),
+            _ => Err(UnifyErr::Mismatch),
+        }
+    }
+    fn bind(&mut self, id: usize, ty: Type) -> Result<(), UnifyErr> {
+        let VarState::Unbound {
+            level,
+            constraint,
+            bounds,
+        } = self.vars[id].clone()
+        else {
+            unreachable!("bind called on a bound variable");
+        };
+        if let Type::Var(other) = ty {
+            // the merged variable keeps the outer level and everything both must satisfy
+            if let VarState::Unbound {
+                level: other_level,
+                constraint: other_constraint,
+                bounds: mut other_bounds,
+            } = self.vars[other].clone()
+            {
+                for bound in bounds {
+                    if !other_bounds.contains(&bound) {
+                        other_bounds.push(bound);
+                    }
+                }
+                self.vars[other] = VarState::Unbound {
+                    level: level.min(other_level),
+                    constraint: match (constraint, other_constraint) {
+                        (Some(a), Some(b)) => Some(a.meet(b)),
+                        (a, b) => a.or(b),
+                    },
+                    bounds: other_bounds,
+                };
+            }
+            self.vars[id] = VarState::Bound(ty);
+            return Ok(());
+        }
+
+        if self.occurs(id, &ty) {
+            return Err(UnifyErr::Mismatch);
+        }
+        self.lower_levels(&ty, level);
+        // bound regardless, so that other uses do not report the same mistake again
+        self.vars[id] = VarState::Bound(ty.clone());
+        if let Some(constraint) = constraint.filter(|c| !c.admits(&ty)) {
+            return Err(UnifyErr::Unsatisfied(constraint, ty));
+        }
+        for bound in &bounds {
+            self.implement(&ty, bound)?;
+        }
+        Ok(())
+    }
+    fn occurs(&self, id: usize, ty: &Type) -> bool {
+        match self.resolve(ty) {
+            Type::Var(other) => other == id,
+            Type::Fn { params, ret } => {
+                params.iter().any(|p| self.occurs(id, p)) || self.occurs(id, &ret)
+            }
+            Type::Instance(_, args) => args.iter().any(|a| self.occurs(id, a)),
+            Type::List(elem) => self.occurs(id, &elem),
+            Type::Map(key, value) => self.occurs(id, &key) || self.occurs(id, &value),
+            _ => false,
+        }
+    }
+    /// variables that become part of a type at `level` must not be generalised deeper than it.
+    fn lower_levels(&mut self, ty: &Type, max: usize) {
+        match self.resolve(ty) {
+            Type::Var(id) => {
+                if let VarState::Unbound { level, .. } = &mut self.vars[id] {
+                    *level = (*level).min(max);
+                }
+            }
+            Type::Fn { params, ret } => {
+                for param in &params {
+                    self.lower_levels(param, max);
+                }
+                self.lower_levels(&ret, max);
+            }
+            Type::Instance(_, args) => {
+                for arg in &args {
+                    self.lower_levels(arg, max);
+                }
+            }
+            Type::List(elem) => self.lower_levels(&elem, max),
+            Type::Map(key, value) => {
+                self.lower_levels(&key, max);
+                self.lower_levels(&value, max);
+            }
+            _ => {}
+        }
+    }
+    /// requires `ty` to satisfy `constraint`, now if it is known or once it is inferred.
+    fn constrain(&mut self, ty: &Type, constraint: Constraint) -> Result<(), UnifyErr> {
+        match self.resolve(ty) {
+            Type::Any => Ok(()),
+            Type::Var(id) => {
+                if let VarState::Unbound { constraint: c, .. } = &mut self.vars[id] {
+                    *c = Some(c.map_or(constraint, |c| c.meet(constraint)));
+                }
+                Ok(())
+            }
+            ty if constraint.admits(&ty) => Ok(()),
+            ty => Err(UnifyErr::Unsatisfied(constraint, ty)),
+        }
+    }
+    /// requires `ty` to implement `interface`, now if it is known or once it is inferred. A class
+    /// does if it has every method of the interface, at a type that unifies with the interface's
+    /// once `Self` is replaced by `ty`.
+    fn implement(&mut self, ty: &Type, interface: &str) -> Result<(), UnifyErr> {
+        let unimplemented =
+            |ty: Type, reason| UnifyErr::Unimplemented(ty, interface.to_owned(), reason);
+        match self.resolve(ty) {
+            Type::Any => Ok(()),
+            Type::Var(id) => {
+                if let VarState::Unbound { bounds, .. } = &mut self.vars[id] {
+                    if !bounds.iter().any(|b| b == interface) {
+                        bounds.push(interface.to_owned());
+                    }
+                }
+                Ok(())
+            }
+            Type::Param(name) => match self.param_bounds(&name) {
+                Some(bounds) if bounds.iter().any(|b| b == interface) => Ok(()),
+                _ => Err(unimplemented(Type::Param(name), None)),
+            },
+            Type::Instance(class, args) => {
+                // an unknown interface has been reported where it was named
+                let Some(required) = self.interfaces.get(interface).cloned() else {
+                    return Ok(());
+                };
+                let ty = Type::Instance(class.clone(), args.clone());
+                let this = HashMap::from([("Self".to_owned(), ty.clone())]);
+                for (name, expected) in required {
+                    let Some(found) = self.method(&class, &args, &name) else {
+                        let reason = format!("missing method '{}'", name);
+                        return Err(unimplemented(ty, Some(reason)));
+                    };
+                    let expected = substitute_params(&expected, &this);
+                    if self.unify(&expected, &found).is_err() {
+                        let reason = format!(
+                            "'{}' is {} but {} needs {}",
+                            name,
+                            self.zonk(&found),
+                            interface,
+                            self.zonk(&expected)
+                        );
+                        return Err(unimplemented(ty, Some(reason)));
+                    }
+                }
+                Ok(())
+            }
+            other => Err(unimplemented(other, None)),
+        }
+    }
+    /// unifies, reporting a failure as `found` not being the `expected` type.
+    fn expect(&mut self, expected: &Type, found: &Type) {
+        match self.unify(expected, found) {
+            Ok(()) => {}
+            Err(UnifyErr::Mismatch) => self.errors.push(TypeErr::Mismatch {
+                expected: self.zonk(expected),
+                found: self.zonk(found),
+                line: self.line,
+            }),
+            Err(err) => self.report(err),
+        }
+    }
+    /// reports a failed constraint or bound on the current line.
+    fn report(&mut self, err: UnifyErr) {
+        let err = match err {
+            UnifyErr::Mismatch => unreachable!("mismatches are reported with both types"),
+            UnifyErr::Unsatisfied(constraint, ty) => {
+                TypeErr::Unsatisfied(constraint, self.zonk(&ty), self.line)
+            }
+            UnifyErr::Unimplemented(ty, interface, reason) => TypeErr::Unimplemented {
+                ty: self.zonk(&ty),
+                interface,
+                reason,
+                line: self.line,
+            },
+        };
+        self.errors.push(err);
+    }
+
+    /// quantifies the variables of `ty` that were created inside the function just checked and
+    /// did not escape into anything outside it, along with the declared type parameters `params`.
+    fn generalize(&self, ty: &Type, params: &[TypeParam]) -> Scheme {
+        let ty = self.zonk(ty);
+        let mut vars = Vec::new();
+        self.collect_generic(&ty, &mut vars);
+        Scheme {
+            vars,
+            params: params.to_vec(),
+            ty,
+        }
+    }
+    fn collect_generic(&self, ty: &Type, out: &mut Vec<Quantified>) {
+        match ty {
+            Type::Var(id) => {
+                if let VarState::Unbound {
+                    level,
+                    constraint,
+                    bounds,
+                } = &self.vars[*id]
+                {
+                    if *level > self.level && !out.iter().any(|q| q.id == *id) {
+                        let promotes = self.joins.iter().find_map(|(result, a, b, _)| {
+                            match (self.resolve(result), self.resolve(a), self.resolve(b)) {
+                                (Type::Var(r), Type::Var(a), Type::Var(b)) if r == *id => {
+                                    Some((a, b))
+                                }
+                                _ => None,
+                            }
+                        });
+                        out.push(Quantified {
+                            id: *id,
+                            constraint: *constraint,
+                            bounds: bounds.clone(),
+                            promotes,
+                        });
+                    }
+                }
+            }
+            Type::Fn { params, ret } => {
+                for param in params {
+                    self.collect_generic(param, out);
+                }
+                self.collect_generic(ret, out);
+            }
+            Type::Instance(_, args) => {
+                for arg in args {
+                    self.collect_generic(arg, out);
+                }
+            }
+            Type::List(elem) => self.collect_generic(elem, out),
+            Type::Map(key, value) => {
+                self.collect_generic(key, out);
+                self.collect_generic(value, out);
+            }
+            _ => {}
+        }
+    }
+    /// a copy of the scheme's type with fresh variables for the quantified ones and the type
+    /// parameters, which is how type arguments get inferred.
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        if scheme.vars.is_empty() && scheme.params.is_empty() {
+            return scheme.ty.clone();
+        }
+        let mut fresh = HashMap::new();
+        for q in &scheme.vars {
+            self.vars.push(VarState::Unbound {
+                level: self.level,
+                constraint: q.constraint,
+                bounds: q.bounds.clone(),
+            });
+            fresh.insert(q.id, Type::Var(self.vars.len() - 1));
+        }
+        for q in &scheme.vars {
+            if let Some((a, b)) = q.promotes {
+                let var = |id| fresh.get(&id).cloned().unwrap_or(Type::Var(id));
+                self.joins
+                    .push((fresh[&q.id].clone(), var(a), var(b), q.constraint));
+            }
+        }
+        let mut params = HashMap::new();
+        for param in &scheme.params {
+            let var = self.fresh_bounded(self.level, param.bounds.clone());
+            params.insert(param.name.clone(), var);
+        }
+        substitute_params(&substitute(&scheme.ty, &fresh), &params)
+    }
+
+    /// gives a newly declared local its type. Globals were typed while hoisting, and locals are
+    /// pushed in declaration order, which is the order the resolver numbered them in.
+    fn define(&mut self, name: &Identifier, scheme: Scheme) {
+        if let (Some(Slot::Local { .. }), Some(scope)) = (name.slot, self.scopes.last_mut()) {
+            scope.push(scheme);
+        }
+    }
+    fn scheme(&mut self, name: &Identifier) -> &mut Scheme {
+        match name.slot {
+            Some(Slot::Global(index)) => &mut self.globals[index],
+            Some(Slot::Local { depth, index }) => {
+                let scope = self.scopes.len() - 1 - depth;
+                &mut self.scopes[scope][index]
+            }
+            None => unreachable!("'{}' was not resolved before checking", name.name),
+        }
+    }
+    fn lookup(&mut self, name: &Identifier) -> Type {
+        let scheme = self.scheme(name).clone();
+        self.instantiate(&scheme)
+    }
+    /// checks a function body against its signature, one level deeper than the declaration.
+    fn check_function(&mut self, decl: &FnDecl, sig: &Type, body_returns: Type) {
+        let Type::Fn { params, .. } = sig else {
+            unreachable!("function signatures are always function types");
+        };
+        self.level += 1;
+        let depth = self.type_params.len();
+        self.type_params.extend(decl.type_params.iter().cloned());
+        let outer = self.return_type.replace(body_returns);
+        self.scopes
+            .push(params.iter().cloned().map(Scheme::mono).collect());
+        for stmt in &decl.body.stmts {
+            self.visit_stmt(stmt);
+        }
+        if !always_returns(&decl.body.stmts) {
+            // falling off the end returns nil
+            self.line = decl.body.end_line;
+            self.visit_return(None);
+        }
+        self.scopes.pop();
+        self.return_type = outer;
+        self.type_params.truncate(depth);
+        self.settle_joins();
+        self.settle_numbers(self.level);
+        self.settle_joins();
+        self.level -= 1;
+    }
+    /// stores a value of type `found` in a variable of type `declared`. An `int` may go where a
+    /// `float` is expected, and leaves a variable still being inferred open to floats.
+    fn store(&mut self, declared: &Type, found: &Type) {
+        if self.resolve(found) == Type::Int {
+            let declared = self.resolve(declared);
+            match &declared {
+                Type::Float => return,
+                // ints implement no interface, so a variable with bounds has to be something else
+                Type::Var(id)
+                    if self
+                        .unbound(*id)
+                        .is_some_and(|(_, bounds)| bounds.is_empty()) =>
+                {
+                    self.constrain(&declared, Constraint::Num).ok();
+                    self.numbers.push(declared);
+                    return;
+                }
+                _ => {}
+            }
+        }
+        self.expect(declared, found);
+    }
+    /// types the result of every join whose operands are known now as their promotion. A join whose
+    /// result was quantified by a function declared in here is the function's scheme's to keep.
+    fn settle_joins(&mut self) {
+        for (result, lhs, rhs, constraint) in std::mem::take(&mut self.joins) {
+            let (a, b) = (self.resolve(&lhs), self.resolve(&rhs));
+            if let (Type::Var(_), _) | (_, Type::Var(_)) = (&a, &b) {
+                let quantified = match self.resolve(&result) {
+                    Type::Var(id) => self
+                        .unbound(id)
+                        .is_some_and(|(level, _)| level > self.level),
+                    _ => false,
+                };
+                if !quantified {
+                    self.joins.push((result, lhs, rhs, constraint));
+                }
+                continue;
+            }
+            match known_binary(&BinaryOp::Plus, &a, &b) {
+                Some(Type::Int) if self.resolve(&result) == Type::Float => {}
+                Some(ty) => self.expect(&ty, &result),
+                // a string with a number is all `+` lets through, anything else broke a constraint
+                None if constraint == Some(Constraint::Add)
+                    && [&a, &b].iter().all(|t| t.is_numeric() || **t == Type::Str) =>
+                {
+                    let (a, b) = (self.zonk(&a), self.zonk(&b));
+                    self.errors
+                        .push(TypeErr::InvalidOperands("+", a, b, self.line));
+                }
+                None => {}
+            }
+        }
+    }
+    /// makes the variables `store` left open, and that were created `level` or more functions deep,
+    /// ints: nothing there assigned them a float.
+    fn settle_numbers(&mut self, level: usize) {
+        for var in std::mem::take(&mut self.numbers) {
+            if let Type::Var(id) = self.resolve(&var) {
+                match self.unbound(id) {
+                    Some((l, _)) if l >= level => self.expect(&Type::Int, &var),
+                    _ => self.numbers.push(var),
+                }
+            }
+        }
+    }
+    /// the level and bounds of the variable `id`, if it is unbound.
+    fn unbound(&self, id: usize) -> Option<(usize, &[String])> {
+        match &self.vars[id] {
+            VarState::Unbound { level, bounds, .. } => Some((*level, bounds)),
+            VarState::Bound(_) => None,
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::LiteralExpr(l) => match l {
+                Literal::Nil => Type::Nil,
+                Literal::Bool(_) => Type::Bool,
+                Literal::Int(_) => Type::Int,
+                Literal::Float(_) => Type::Float,
+                Literal::Str(_) => Type::Str,
+            },
+            Expr::Grouping(inner) => self.expr(inner),
+            Expr::Unary { op, rhs } => {
+                let rhs = self.expr(rhs);
+                let result = match op {
+                    UnaryOp::Negate => self.constrain(&rhs, Constraint::Num).map(|_| rhs.clone()),
+                    UnaryOp::Bang => match self.resolve(&rhs) {
+                        Type::Nil => Ok(Type::Bool),
+                        _ => self.unify(&Type::Bool, &rhs).map(|_| Type::Bool),
+                    },
+                };
+                result.unwrap_or_else(|_| {
+                    let rhs = self.zonk(&rhs);
+                    self.errors
+                        .push(TypeErr::InvalidOperand(op.symbol(), rhs, self.line));
+                    Type::Any
+                })
+            }
+            Expr::Binary { lhs: l, op, rhs: r } => {
+                let lhs = self.expr(l);
+                let rhs = self.expr(r);
+                if matches!(
+                    op,
+                    BinaryOp::Plus | BinaryOp::Minus | BinaryOp::Mult | BinaryOp::Div
+                ) {
+                    self.operands.push((&**l, lhs.clone()));
+                    self.operands.push((&**r, rhs.clone()));
+                }
+                self.binary(op, &lhs, &rhs).unwrap_or_else(|| {
+                    let (lhs, rhs) = (self.zonk(&lhs), self.zonk(&rhs));
+                    self.errors
+                        .push(TypeErr::InvalidOperands(op.symbol(), lhs, rhs, self.line));
+                    Type::Any
+                })
+            }
+            Expr::Logical { lhs, rhs, .. } => {
+                // either operand may be the result, so only agreeing operands give a known type
+                let lhs = self.expr(lhs);
+                let rhs = self.expr(rhs);
+                let (lhs, rhs) = (self.zonk(&lhs), self.zonk(&rhs));
+                if lhs == rhs {
+                    lhs
+                } else {
+                    Type::Any
+                }
+            }
+            Expr::Variable(name) | Expr::This(name) => self.lookup(name),
+            Expr::Assign { name, value } => {
+                let value = self.expr(value);
+                let expected = self.lookup(name);
+                self.store(&expected, &value);
+                value
+            }
+            Expr::Call { callee, args, line } => {
+                let callee = self.expr(callee);
+                let args: Vec<Type> = args.iter().map(|a| self.expr(a)).collect();
+                self.line = *line;
+                match self.resolve(&callee) {
+                    Type::Any => Type::Any,
+                    Type::Fn { params, ret } => {
+                        if params.len() != args.len() {
+                            self.errors.push(TypeErr::InvalidArity(
+                                params.len(),
+                                args.len(),
+                                *line,
+                            ));
+                        }
+                        for (param, arg) in params.iter().zip(&args) {
+                            self.expect(param, arg);
+                        }
+                        *ret
+                    }
+                    Type::Var(_) => {
+                        let ret = self.fresh(self.level);
+                        let called = Type::Fn {
+                            params: args,
+                            ret: Box::new(ret.clone()),
+                        };
+                        self.expect(&callee, &called);
+                        ret
+                    }
+                    other => {
+                        self.errors.push(TypeErr::NotCallable(other, *line));
+                        Type::Any
+                    }
+                }
+            }
+            Expr::Get { object, name } => {
+                let object = self.expr(object);
+                self.property(&object, name)
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                let object = self.expr(object);
+                self.property(&object, name);
+                self.expr(value)
+            }
+            Expr::List(elements) => {
+                let elem = self.fresh(self.level);
+                let mut mixed = false;
+                for element in elements {
+                    let ty = self.expr(element);
+                    mixed |= self.unify(&elem, &ty).is_err();
+                }
+                Type::List(Box::new(if mixed { Type::Any } else { elem }))
+            }
+            Expr::Map(entries) => {
+                let (key, value) = (self.fresh(self.level), self.fresh(self.level));
+                let (mut mixed_keys, mut mixed_values) = (false, false);
+                for (k, v) in entries {
+                    let k = self.expr(k);
+                    mixed_keys |= self.unify(&key, &k).is_err();
+                    let v = self.expr(v);
+                    mixed_values |= self.unify(&value, &v).is_err();
+                }
+                let key = if mixed_keys { Type::Any } else { key };
+                let value = if mixed_values { Type::Any } else { value };
+                Type::Map(Box::new(key), Box::new(value))
+            }
+            Expr::Index {
+                object,
+                index,
+                line,
+            } => {
+                let object = self.expr(object);
+                let index = self.expr(index);
+                self.line = *line;
+                self.element(&object, &index)
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                line,
+            } => {
+                let object = self.expr(object);
+                let index = self.expr(index);
+                let value = self.expr(value);
+                self.line = *line;
+                let expected = self.element(&object, &index);
+                self.expect(&expected, &value);
+                value
+            }
+        }
+    }
+    /// the type of `object[index]`. An object that is still being inferred could be either a list
+    /// or a map, so its elements are `any`.
+    fn element(&mut self, object: &Type, index: &Type) -> Type {
+        match self.resolve(object) {
+            Type::Any | Type::Var(_) => Type::Any,
+            Type::List(elem) => {
+                self.expect(&Type::Int, index);
+                *elem
+            }
+            Type::Map(key, value) => {
+                self.expect(&key, index);
+                *value
+            }
+            other => {
+                let other = self.zonk(&other);
+                self.errors.push(TypeErr::NotIndexable(other, self.line));
+                Type::Any
+            }
+        }
+    }
+    /// result type of a binary operator, or `None` if the `Literal` impls would reject the operands.
+    fn binary(&mut self, op: &BinaryOp, lhs: &Type, rhs: &Type) -> Option<Type> {
+        let (lhs, rhs) = (self.resolve(lhs), self.resolve(rhs));
+        if !matches!(lhs, Type::Var(_)) && !matches!(rhs, Type::Var(_)) {
+            return known_binary(op, &lhs, &rhs);
+        }
+
+        let constraint = match op {
+            BinaryOp::EqEq | BinaryOp::BangEq => return Some(Type::Bool),
+            BinaryOp::Plus => Constraint::Add,
+            _ => Constraint::Num,
+        };
+        let comparison = matches!(
+            op,
+            BinaryOp::Gt | BinaryOp::GtEq | BinaryOp::Lt | BinaryOp::LtEq
+        );
+        // a number of the other kind is promoted rather than unified with, so `x * 2` leaves `x`
+        // any number, and is an int for an int `x` and a float for a float one
+        if let (Type::Var(_), Type::Int | Type::Float) | (Type::Int | Type::Float, Type::Var(_)) =
+            (&lhs, &rhs)
+        {
+            let (var, known) = match lhs {
+                Type::Var(_) => (&lhs, &rhs),
+                _ => (&rhs, &lhs),
+            };
+            self.constrain(var, Constraint::Num).ok()?;
+            return Some(match known {
+                _ if comparison => Type::Bool,
+                Type::Int => var.clone(),
+                _ => Type::Float,
+            });
+        }
+        let result = if lhs == Type::Any || rhs == Type::Any {
+            self.constrain(&lhs, constraint)
+                .and_then(|_| self.constrain(&rhs, constraint))
+                .map(|_| Type::Any)
+        } else if let (Type::Var(a), Type::Var(b)) = (&lhs, &rhs) {
+            // either may turn out to be promoted, so the result is their promotion once known
+            let same = a == b;
+            self.constrain(&lhs, constraint)
+                .and_then(|_| self.constrain(&rhs, constraint))
+                .map(|_| match same {
+                    true => lhs.clone(),
+                    false => {
+                        let result = self.fresh(self.level);
+                        self.constrain(&result, constraint).ok();
+                        self.joins.push((
+                            result.clone(),
+                            lhs.clone(),
+                            rhs.clone(),
+                            Some(constraint),
+                        ));
+                        result
+                    }
+                })
+        } else {
+            self.unify(&lhs, &rhs)
+                .and_then(|_| self.constrain(&lhs, constraint))
+                .map(|_| lhs.clone())
+        };
+        match result {
+            Ok(_) if comparison => Some(Type::Bool),
+            Ok(ty) => Some(ty),
+            Err(_) => None,
+        }
+    }
+    /// the type of `object.name`. Fields are untyped, methods have their declared signature.
+    fn property(&mut self, object: &Type, name: &Identifier) -> Type {
+        match self.resolve(object) {
+            // the class of an inferred object is not known, so neither are its properties
+            Type::Any | Type::Var(_) => Type::Any,
+            Type::Instance(class, args) if !self.host_types.contains(&class.as_str()) => {
+                self.method(&class, &args, &name.name).unwrap_or(Type::Any)
+            }
+            // a type parameter only has the methods its bounds promise
+            Type::Param(param) => {
+                let bounds = self.param_bounds(&param).unwrap_or_default().to_vec();
+                let this = HashMap::from([("Self".to_owned(), Type::Param(param.clone()))]);
+                let method = bounds.iter().find_map(|bound| {
+                    let methods = self.interfaces.get(bound)?;
+                    methods
+                        .iter()
+                        .find(|(n, _)| *n == name.name)
+                        .map(|(_, ty)| ty)
+                });
+                match method {
+                    Some(ty) => substitute_params(ty, &this),
+                    None => {
+                        let err =
+                            TypeErr::NoMethod(Type::Param(param), name.name.clone(), name.line);
+                        self.errors.push(err);
+                        Type::Any
+                    }
+                }
+            }
+            other => {
+                let other = self.zonk(&other);
+                self.errors.push(TypeErr::NoProperties(other, name.line));
+                Type::Any
+            }
+        }
+    }
+}
+
+/// whether every path through `stmts` ends in a `return` or a `throw`.
+fn always_returns(stmts: &[Stmt]) -> bool {
+    let Some(last) = stmts.last() else {
+        return false;
+    };
+    match &last.kind {
+        StmtKind::Return(_) | StmtKind::Throw(_) => true,
+        StmtKind::Try {
+            body,
+            catch,
+            finally,
+        } => {
+            let handled = catch
+                .as_ref()
+                .is_none_or(|catch| always_returns(&catch.body.stmts));
+            (always_returns(&body.stmts) && handled)
+                || finally.as_ref().is_some_and(|f| always_returns(&f.stmts))
+        }
+        StmtKind::Block(block) => always_returns(&block.stmts),
+        StmtKind::If {
+            then_branch,
+            else_branch: Some(else_branch),
+            ..
+        } => {
+            always_returns(&then_branch.stmts) && always_returns(std::slice::from_ref(else_branch))
+        }
+        _ => false,
+    }
+}
+
+fn substitute(ty: &Type, vars: &HashMap<usize, Type>) -> Type {
+    match ty {
+        Type::Var(id) => vars.get(id).cloned().unwrap_or(Type::Var(*id)),
+        Type::Fn { params, ret } => Type::Fn {
+            params: params.iter().map(|p| substitute(p, vars)).collect(),
+            ret: Box::new(substitute(ret, vars)),
+        },
+        Type::Instance(class, args) => Type::Instance(
+            class.clone(),
+            args.iter().map(|a| substitute(a, vars)).collect(),
+        ),
+        Type::List(elem) => Type::List(Box::new(substitute(elem, vars))),
+        Type::Map(key, value) => Type::Map(
+            Box::new(substitute(key, vars)),
+            Box::new(substitute(value, vars)),
+        ),
+        ty => ty.clone(),
+    }
+}
+
+/// replaces type parameters by name.
+fn substitute_params(ty: &Type, params: &HashMap<String, Type>) -> Type {
+    match ty {
+        Type::Param(name) => params
+            .get(name)
+            .cloned()
+            .unwrap_or(Type::Param(name.clone())),
+        Type::Fn {
+            params: fn_params,
+            ret,
+        } => Type::Fn {
+            params: fn_params
+                .iter()
+                .map(|p| substitute_params(p, params))
+                .collect(),
+            ret: Box::new(substitute_params(ret, params)),
+        },
+        Type::Instance(class, args) => Type::Instance(
+            class.clone(),
+            args.iter().map(|a| substitute_params(a, params)).collect(),
+        ),
+        Type::List(elem) => Type::List(Box::new(substitute_params(elem, params))),
+        Type::Map(key, value) => Type::Map(
+            Box::new(substitute_params(key, params)),
+            Box::new(substitute_params(value, params)),
+        ),
+        ty => ty.clone(),
+    }
+}
+
+/// result type of a binary operator on operands that are not being inferred, or `None` if the
+/// `Literal` impls would reject them.
+fn known_binary(op: &BinaryOp, lhs: &Type, rhs: &Type) -> Option<Type> {
+    use Type::{Any, Float, Int, Str};
+
+    match op {
+        BinaryOp::EqEq | BinaryOp::BangEq => return Some(Type::Bool),
+        BinaryOp::Gt | BinaryOp::GtEq | BinaryOp::Lt | BinaryOp::LtEq => {
+            return match (lhs, rhs) {
+                (Any | Int | Float, Any | Int | Float) => Some(Type::Bool),
+                _ => None,
+            };
+        }
+        _ => {}
+    }
+
+    match (lhs, rhs) {
+        (Int, Int) => Some(Int),
+        (Int | Float, Int | Float) => Some(Float),
+        (Str, Str) if *op == BinaryOp::Plus => Some(Str),
+        (Any, Any | Int | Float) | (Int | Float, Any) => Some(Any),
+        (Any, Str) | (Str, Any) if *op == BinaryOp::Plus => Some(Any),
+        _ => None,
+    }
+}
+
+impl Visitor for Checker {
+    fn visit_stmt(&mut self, stmt: &Stmt) {
+        self.line = stmt.line;
+        walk_stmt(self, stmt);
+        self.line = stmt.line;
+        self.settle_joins();
+    }
+    fn visit_expr(&mut self, expr: &Expr) {
+        self.expr(expr);
+    }
+    fn visit_let(&mut self, name: &Identifier, ty: Option<&Type>, init: Option<&Expr>) {
+        let declared = match (ty, name.slot) {
+            (Some(ty), Some(Slot::Global(index))) => {
+                // functions that ran into the global before its declaration may have pinned it
+                let ty = self.annotation(ty, self.level);
+                let hoisted = self.globals[index].ty.clone();
+                self.expect(&ty, &hoisted);
+                // `any` unifies without binding, so uses after this must see the annotation
+                self.globals[index] = Scheme::mono(ty.clone());
+                ty
+            }
+            (Some(ty), _) => self.annotation(ty, self.level),
+            (None, Some(Slot::Global(index))) => self.globals[index].ty.clone(),
+            (None, _) => self.fresh(self.level),
+        };
+        if let Some(init) = init {
+            let found = self.expr(init);
+            self.store(&declared, &found);
+        }
+        self.bindings.push(Binding {
+            name: name.name.clone(),
+            line: name.line,
+            scheme: Scheme::mono(declared.clone()),
+        });
+        self.define(name, Scheme::mono(declared));
+    }
+    fn visit_block(&mut self, block: &Block) {
+        self.scopes.push(Vec::new());
+        for stmt in &block.stmts {
+            self.visit_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+    fn visit_for(&mut self, _name: &Identifier, iterable: &Expr, body: &Block) {
+        let iterable = self.expr(iterable);
+        let element = match self.resolve(&iterable) {
+            Type::Any | Type::Var(_) => Type::Any,
+            Type::List(elem) => *elem,
+            Type::Map(key, _) => *key,
+            other => {
+                let other = self.zonk(&other);
+                self.errors.push(TypeErr::NotIterable(other, self.line));
+                Type::Any
+            }
+        };
+        // the loop variable shares the body's scope, as the resolver numbered it
+        self.scopes.push(vec![Scheme::mono(element)]);
+        for stmt in &body.stmts {
+            self.visit_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+    /// the exception could be anything a program throws, or the message of a runtime error.
+    fn visit_catch(&mut self, catch: &Catch) {
+        self.scopes.push(vec![Scheme::mono(Type::Any)]);
+        for stmt in &catch.body.stmts {
+            self.visit_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+    fn visit_fn(&mut self, decl: &FnDecl) {
+        let sig = match decl.name.slot {
+            Some(Slot::Global(index)) => self.globals[index].ty.clone(),
+            _ => {
+                let sig = self.signature(decl, self.level + 1);
+                // defined before the body is checked, so the function can call itself
+                let scheme = Scheme {
+                    vars: Vec::new(),
+                    params: decl.type_params.clone(),
+                    ty: sig.clone(),
+                };
+                self.define(&decl.name, scheme);
+                sig
+            }
+        };
+        let Type::Fn { ret, .. } = &sig else {
+            unreachable!("function signatures are always function types");
+        };
+        self.check_function(decl, &sig, *ret.clone());
+
+        let scheme = self.generalize(&sig, &decl.type_params);
+        *self.scheme(&decl.name) = scheme.clone();
+        self.bindings.push(Binding {
+            name: decl.name.name.clone(),
+            line: decl.name.line,
+            scheme,
+        });
+    }
+    fn visit_return(&mut self, value: Option<&Expr>) {
+        let found = match value {
+            Some(value) => self.expr(value),
+            None => Type::Nil,
+        };
+        if let Some(expected) = self.return_type.clone() {
+            self.expect(&expected, &found);
+        }
+    }
+    fn visit_class(&mut self, decl: &ClassDecl) {
+        let class = self.type_name(&decl.name);
+        let ctor = match decl.name.slot {
+            Some(Slot::Global(index)) => self.globals[index].ty.clone(),
+            _ => {
+                let ctor = self.declare_class(decl);
+                let scheme = Scheme {
+                    vars: Vec::new(),
+                    params: decl.type_params.clone(),
+                    ty: ctor.clone(),
+                };
+                self.define(&decl.name, scheme);
+                ctor
+            }
+        };
+
+        // methods see `this` in an enclosing scope of its own, as the resolver numbered it
+        self.scopes.push(vec![Scheme::mono(self.this_type(decl))]);
+        let depth = self.type_params.len();
+        self.type_params.extend(decl.type_params.iter().cloned());
+        for method in &decl.methods {
+            self.line = method.name.line;
+            let sig = self.classes[&class].methods[&method.name.name].ty.clone();
+            let Type::Fn { ret, .. } = &sig else {
+                unreachable!("method signatures are always function types");
+            };
+            let body_returns = if method.name.name == Class::INITIALIZER {
+                Type::Nil
+            } else {
+                *ret.clone()
+            };
+            self.check_function(method, &sig, body_returns);
+        }
+        self.type_params.truncate(depth);
+        self.scopes.pop();
+
+        for method in &decl.methods {
+            let sig = &self.classes[&class].methods[&method.name.name].ty;
+            let scheme = self.generalize(sig, &method.type_params);
+            self.bindings.push(Binding {
+                name: format!("{}.{}", decl.name.name, method.name.name),
+                line: method.name.line,
+                scheme: scheme.clone(),
+            });
+            if let Some(info) = self.classes.get_mut(&class) {
+                info.methods.insert(method.name.name.clone(), scheme);
+            }
+        }
+        *self.scheme(&decl.name) = self.generalize(&ctor, &decl.type_params);
+    }
+    fn visit_interface(&mut self, decl: &InterfaceDecl) {
+        // top-level interfaces were declared while hoisting
+        if !self.interfaces.contains_key(&self.type_name(&decl.name)) {
+            self.declare_interface(decl);
+        }
+    }
+}
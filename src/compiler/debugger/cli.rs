@@ -0,0 +1,304 @@
+//! A debugger driven by commands typed at a prompt, one per line, in the manner of gdb. The
+//! program stops before its first statement, for breakpoints to be set.
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    ops::ControlFlow,
+    path::Path,
+};
+
+use super::{lookup, Debugger, Position, Reason, Resume, Session, Sources, StackFrame};
+use crate::compiler::{interpreter::Interpreter, modules::Module, value::Value};
+
+const HELP: &str = "\
+break [FILE:]LINE   stop at a line, of the program's own file unless FILE is given (b)
+delete [[FILE:]LINE]  remove a breakpoint, or all of them (d)
+breakpoints         list the breakpoints
+continue            run to the next breakpoint (c)
+step                run to the next statement, into calls (s)
+next                run to the next statement, over calls (n)
+finish              run until the call returns (o)
+backtrace           list the calls in progress (bt)
+frame N, up, down   look at another call
+locals              show the variables of the call
+globals             show the globals
+print NAME          show a variable, or a part of one, e.g. `p.x` or `points.0` (p)
+list                show the source around the line (l)
+quit                end the program (q)";
+
+/// Reads commands from `input` whenever the program stops, and writes what they show to `output`.
+pub struct Cli<R, W> {
+    input: R,
+    output: W,
+    session: Session,
+    sources: Sources,
+    /// the call the commands look at, counting from the innermost.
+    frame: usize,
+    /// the text of each module listed so far.
+    texts: HashMap<usize, String>,
+}
+
+impl<R: BufRead, W: Write> Cli<R, W> {
+    /// a debugger for the program made of `modules`, as the interpreter is to run them.
+    pub fn new(input: R, output: W, modules: &[Module]) -> Self {
+        Self {
+            input,
+            output,
+            session: Session::new(true),
+            sources: Sources::new(modules),
+            frame: 0,
+            texts: HashMap::new(),
+        }
+    }
+
+    /// runs a command, and says how the program is to go on if it should.
+    fn command(
+        &mut self,
+        interpreter: &Interpreter,
+        line: &str,
+    ) -> std::io::Result<Option<ControlFlow<()>>> {
+        let (command, argument) = match line.trim().split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line.trim(), ""),
+        };
+        let resume = match command {
+            "c" | "continue" => Resume::Continue,
+            "s" | "step" => Resume::StepIn,
+            "n" | "next" => Resume::StepOver,
+            "o" | "finish" => Resume::StepOut,
+            "q" | "quit" => return Ok(Some(ControlFlow::Break(()))),
+            _ => {
+                self.show(interpreter, command, argument)?;
+                return Ok(None);
+            }
+        };
+        self.session.resume(resume);
+        Ok(Some(ControlFlow::Continue(())))
+    }
+
+    /// runs a command that leaves the program where it is.
+    fn show(
+        &mut self,
+        interpreter: &Interpreter,
+        command: &str,
+        argument: &str,
+    ) -> std::io::Result<()> {
+        let stack = interpreter.stack();
+        match command {
+            "" => {}
+            "h" | "help" => writeln!(self.output, "{}", HELP)?,
+            "b" | "break" => {
+                let result = self.location(argument).and_then(|(module, line)| {
+                    let path = self.sources.path(module).display();
+                    match self.sources.breakable(module, line) {
+                        Some(line) => Ok((module, line)),
+                        None => Err(format!("no statement at or after {}:{}", path, line)),
+                    }
+                });
+                match result {
+                    Ok((module, line)) => {
+                        let lines = self.session.breakpoints(module).chain([line]);
+                        let lines: Vec<usize> = lines.collect();
+                        self.session.set_breakpoints(module, lines);
+                        let path = self.sources.path(module).display();
+                        writeln!(self.output, "breakpoint at {}:{}", path, line)?;
+                    }
+                    Err(e) => writeln!(self.output, "{}", e)?,
+                }
+            }
+            "d" | "delete" if argument.is_empty() => {
+                for module in 0..self.sources.len() {
+                    self.session.set_breakpoints(module, []);
+                }
+            }
+            "d" | "delete" => match self.location(argument) {
+                Ok((module, line)) => {
+                    let lines = self.session.breakpoints(module).filter(|l| *l != line);
+                    let lines: Vec<usize> = lines.collect();
+                    self.session.set_breakpoints(module, lines);
+                }
+                Err(e) => writeln!(self.output, "{}", e)?,
+            },
+            "breakpoints" => {
+                for module in 0..self.sources.len() {
+                    let path = self.sources.path(module).display().to_string();
+                    for line in self.session.breakpoints(module) {
+                        writeln!(self.output, "{}:{}", path, line)?;
+                    }
+                }
+            }
+            "bt" | "backtrace" => {
+                for (i, frame) in stack.iter().enumerate() {
+                    let marker = if i == self.frame { ">" } else { " " };
+                    let path = self.sources.path(frame.module).display();
+                    writeln!(
+                        self.output,
+                        "{} #{} {} at {}:{}",
+                        marker, i, frame.function, path, frame.line
+                    )?;
+                }
+            }
+            "frame" | "up" | "down" => {
+                let frame = match command {
+                    "up" => Some(self.frame + 1),
+                    "down" => self.frame.checked_sub(1),
+                    _ => argument.parse().ok(),
+                };
+                match frame.filter(|frame| *frame < stack.len()) {
+                    Some(frame) => {
+                        self.frame = frame;
+                        let StackFrame {
+                            function,
+                            module,
+                            line,
+                        } = &stack[frame];
+                        self.show_line(function, *module, *line)?;
+                    }
+                    None => writeln!(self.output, "no such frame")?,
+                }
+            }
+            "locals" => {
+                let locals = interpreter.locals(self.frame);
+                self.show_variables(interpreter, &locals, "no locals")?;
+            }
+            "globals" => {
+                let globals = interpreter.globals();
+                self.show_variables(interpreter, &globals, "no globals")?;
+            }
+            "p" | "print" => match lookup(interpreter, self.frame, argument) {
+                Ok(value) => {
+                    let value = interpreter.heap().inspect(&value);
+                    writeln!(self.output, "{} = {}", argument, value)?;
+                }
+                Err(e) => writeln!(self.output, "{}", e)?,
+            },
+            "l" | "list" => {
+                if let Some(frame) = stack.get(self.frame) {
+                    self.list(frame.module, frame.line)?;
+                }
+            }
+            _ => writeln!(self.output, "unknown command '{}', try 'help'", command)?,
+        }
+        Ok(())
+    }
+
+    /// the module and line `argument`, `[FILE:]LINE`, names. Without a file, it is the program's
+    /// own, which runs last.
+    fn location(&self, argument: &str) -> Result<(usize, usize), String> {
+        let (file, line) = match argument.rsplit_once(':') {
+            Some((file, line)) => (Some(file), line),
+            None => (None, argument),
+        };
+        let line = line
+            .parse()
+            .map_err(|_| format!("expected a line number, found '{}'", line))?;
+        let module = match file {
+            Some(file) => self
+                .sources
+                .find(Path::new(file))
+                .ok_or_else(|| format!("no file '{}' in the program", file))?,
+            None => self.sources.len() - 1,
+        };
+        Ok((module, line))
+    }
+
+    fn show_variables(
+        &mut self,
+        interpreter: &Interpreter,
+        variables: &[(String, Value)],
+        none: &str,
+    ) -> std::io::Result<()> {
+        if variables.is_empty() {
+            writeln!(self.output, "{}", none)?;
+        }
+        for (name, value) in variables {
+            writeln!(
+                self.output,
+                "{} = {}",
+                name,
+                interpreter.heap().inspect(value)
+            )?;
+        }
+        Ok(())
+    }
+
+    /// where a call is, and the source line.
+    fn show_line(&mut self, function: &str, module: usize, line: usize) -> std::io::Result<()> {
+        let path = self.sources.path(module).display().to_string();
+        writeln!(self.output, "{} at {}:{}", function, path, line)?;
+        let text = self.source_line(module, line);
+        writeln!(self.output, "{:>5} | {}", line, text)
+    }
+
+    /// the lines around `line` of `module`, with an arrow at it.
+    fn list(&mut self, module: usize, line: usize) -> std::io::Result<()> {
+        let first = line.saturating_sub(3).max(1);
+        let lines: Vec<String> = self
+            .text(module)
+            .lines()
+            .enumerate()
+            .skip(first - 1)
+            .take(line + 4 - first)
+            .map(|(i, text)| {
+                let marker = if i + 1 == line { "->" } else { "  " };
+                format!("{} {:>4} | {}", marker, i + 1, text.trim_end())
+            })
+            .collect();
+        for text in lines {
+            writeln!(self.output, "{}", text)?;
+        }
+        Ok(())
+    }
+
+    fn source_line(&mut self, module: usize, line: usize) -> String {
+        let text = self.text(module).lines().nth(line.saturating_sub(1));
+        text.unwrap_or("").trim_end().to_owned()
+    }
+
+    /// the source of `module`, read when first needed.
+    fn text(&mut self, module: usize) -> &str {
+        let path = self.sources.path(module);
+        self.texts
+            .entry(module)
+            .or_insert_with(|| std::fs::read_to_string(path).unwrap_or_default())
+    }
+}
+
+impl<R: BufRead, W: Write> Debugger for Cli<R, W> {
+    fn statement(&mut self, interpreter: &Interpreter, at: Position) -> ControlFlow<()> {
+        let Some(reason) = self.session.stop(at) else {
+            return ControlFlow::Continue(());
+        };
+        self.frame = 0;
+        let stack = interpreter.stack();
+        let function = stack.first().map_or("<script>", |frame| &frame.function);
+        let stopped = match reason {
+            Reason::Entry => writeln!(self.output, "stopped at entry, try 'help' for commands"),
+            Reason::Breakpoint => writeln!(self.output, "stopped at breakpoint"),
+            Reason::Step => Ok(()),
+        };
+        if stopped
+            .and_then(|()| self.show_line(function, at.module, at.line))
+            .is_err()
+        {
+            return ControlFlow::Break(());
+        }
+        loop {
+            let mut line = String::new();
+            let read = write!(self.output, "(debug) ")
+                .and_then(|()| self.output.flush())
+                .and_then(|()| self.input.read_line(&mut line));
+            // the end of the input quits, as does anything going wrong with the terminal
+            match read {
+                Ok(0) | Err(_) => return ControlFlow::Break(()),
+                Ok(_) => {}
+            }
+            match self.command(interpreter, &line) {
+                Ok(Some(flow)) => return flow,
+                Ok(None) => {}
+                Err(_) => return ControlFlow::Break(()),
+            }
+        }
+    }
+}
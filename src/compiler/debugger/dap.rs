@@ -0,0 +1,472 @@
+//! A debug adapter, for editors to debug programs with. It speaks the Debug Adapter Protocol over a
+//! pair of streams, normally stdin and stdout: JSON messages framed as the language server's are.
+//!
+//! The client launches a program by path, and the adapter loads it before saying it is ready for
+//! breakpoints, so that it can tell where each will stop. Once the client is done configuring,
+//! the program runs, its output sent as events. Requests are only read while the program is
+//! stopped or before and after it runs: one sent while it runs, a `pause` among them, waits until
+//! it next stops. The program is the only thread.
+
+use std::{
+    cell::RefCell,
+    io::{BufRead, Write},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use super::{children, compile, lookup, Debugger, Position, Resume, Session, Sources};
+use crate::{
+    compiler::{eval::EvalErr, interpreter::Interpreter, lsp, natives::Natives, value::Value},
+    util::json::Json,
+};
+
+/// the id of the one thread.
+const THREAD: usize = 1;
+
+/// Serves one client, reading its requests from `input` and writing responses and events to
+/// `output`, until it disconnects or closes `input`. Imports are looked for next to the importing
+/// file and then in `search_path`, as by `modules::Loader`.
+pub fn serve(
+    input: impl BufRead + 'static,
+    output: impl Write + 'static,
+    search_path: Vec<PathBuf>,
+) -> std::io::Result<()> {
+    let client = Rc::new(RefCell::new(Client {
+        input: Box::new(input),
+        output: Box::new(output),
+        seq: 0,
+        disconnected: false,
+        failed: None,
+    }));
+    let natives = Natives::standard();
+
+    let (modules, globals, stop_on_entry) = loop {
+        let mut client = client.borrow_mut();
+        let Some(request) = client.read()? else {
+            return Ok(());
+        };
+        let arguments = request.get("arguments");
+        match request.get("command").as_str().unwrap_or("") {
+            "initialize" => client.respond(&request, Ok(capabilities()))?,
+            "launch" => {
+                let program = arguments.get("program").as_str().unwrap_or("");
+                match compile(Path::new(program), &search_path, &natives) {
+                    Ok((modules, globals)) => {
+                        client.respond(&request, Ok(Json::object([])))?;
+                        client.event("initialized", Json::object([]))?;
+                        let stop_on_entry = arguments.get("stopOnEntry").as_bool();
+                        break (modules, globals, stop_on_entry.unwrap_or(false));
+                    }
+                    Err(errors) => {
+                        let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                        client.respond(&request, Err(errors.join("\n")))?;
+                    }
+                }
+            }
+            "disconnect" => return client.respond(&request, Ok(Json::object([]))),
+            _ => client.respond(&request, Err("no program has been launched".into()))?,
+        }
+    };
+
+    let mut adapter = Adapter {
+        client: client.clone(),
+        sources: Sources::new(&modules),
+        session: Session::new(stop_on_entry),
+        handles: Vec::new(),
+    };
+    loop {
+        let Some(request) = client.borrow_mut().read()? else {
+            return Ok(());
+        };
+        match adapter.handle(&request, None)? {
+            Some(Next::Configured) => break,
+            Some(Next::Disconnect) => return Ok(()),
+            Some(Next::Resume(_)) | None => {}
+        }
+    }
+
+    let output = Output {
+        client: client.clone(),
+        line: Vec::new(),
+    };
+    let mut interpreter = Interpreter::with_output(&natives, globals, Box::new(output))
+        .with_debugger(Box::new(adapter));
+    let result = interpreter.run_modules(&modules);
+    let mut client = client.borrow_mut();
+    if let Some(e) = client.failed.take() {
+        return Err(e);
+    }
+    if client.disconnected {
+        return Ok(());
+    }
+    let exit_code = match result {
+        Err(e) if e.error != EvalErr::Stopped => {
+            let mut text = format!("{}\n", e);
+            for frame in &e.trace {
+                text.push_str(&format!("  {}\n", frame));
+            }
+            client.output("stderr", &text)?;
+            1usize
+        }
+        _ => 0,
+    };
+    client.event("exited", Json::object([("exitCode", exit_code.into())]))?;
+    client.event("terminated", Json::object([]))?;
+
+    while let Some(request) = client.read()? {
+        match request.get("command").as_str().unwrap_or("") {
+            "disconnect" => return client.respond(&request, Ok(Json::object([]))),
+            "threads" => {
+                let threads = Json::object([("threads", Json::Array(Vec::new()))]);
+                client.respond(&request, Ok(threads))?;
+            }
+            _ => client.respond(&request, Err("the program has ended".into()))?,
+        }
+    }
+    Ok(())
+}
+
+fn capabilities() -> Json {
+    Json::object([
+        ("supportsConfigurationDoneRequest", true.into()),
+        ("supportsEvaluateForHovers", true.into()),
+    ])
+}
+
+/// The streams to the client, shared by the adapter and the program's output.
+struct Client {
+    input: Box<dyn BufRead>,
+    output: Box<dyn Write>,
+    /// the number of the last message sent.
+    seq: usize,
+    /// whether the client disconnected while the program ran.
+    disconnected: bool,
+    /// what went wrong with the streams while the program ran, which ended it.
+    failed: Option<std::io::Error>,
+}
+
+impl Client {
+    /// the next message that is JSON, or `None` once the client closed the stream.
+    fn read(&mut self) -> std::io::Result<Option<Json>> {
+        while let Some(body) = lsp::read_message(&mut self.input)? {
+            if let Some(message) = Json::parse(&body) {
+                return Ok(Some(message));
+            }
+        }
+        Ok(None)
+    }
+    fn send(&mut self, message: impl FnOnce(usize) -> Json) -> std::io::Result<()> {
+        self.seq += 1;
+        lsp::write_message(&mut self.output, &message(self.seq))
+    }
+    /// answers `request` with a body, or with why it failed.
+    fn respond(&mut self, request: &Json, result: Result<Json, String>) -> std::io::Result<()> {
+        let request_seq = request.get("seq").as_usize().unwrap_or(0);
+        let command = request.get("command").as_str().unwrap_or("").to_owned();
+        self.send(|seq| {
+            let (success, body) = match result {
+                Ok(body) => (true, ("body", body)),
+                Err(message) => (false, ("message", message.into())),
+            };
+            Json::object([
+                ("seq", seq.into()),
+                ("type", "response".into()),
+                ("request_seq", request_seq.into()),
+                ("success", success.into()),
+                ("command", command.into()),
+                body,
+            ])
+        })
+    }
+    fn event(&mut self, event: &str, body: Json) -> std::io::Result<()> {
+        self.send(|seq| {
+            Json::object([
+                ("seq", seq.into()),
+                ("type", "event".into()),
+                ("event", event.into()),
+                ("body", body),
+            ])
+        })
+    }
+    /// shows `text` in the client's console.
+    fn output(&mut self, category: &str, text: &str) -> std::io::Result<()> {
+        let body = Json::object([("category", category.into()), ("output", text.into())]);
+        self.event("output", body)
+    }
+}
+
+/// The program's output, sent to the client a line at a time.
+struct Output {
+    client: Rc<RefCell<Client>>,
+    /// what has been written of the line so far.
+    line: Vec<u8>,
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.line.extend_from_slice(buf);
+        while let Some(end) = self.line.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.line.drain(..=end).collect();
+            let text = String::from_utf8_lossy(&line);
+            self.client.borrow_mut().output("stdout", &text)?;
+        }
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.line.is_empty() {
+            let text = String::from_utf8_lossy(&self.line).into_owned();
+            self.line.clear();
+            self.client.borrow_mut().output("stdout", &text)?;
+        }
+        Ok(())
+    }
+}
+
+/// What a request asks of the program, if anything.
+enum Next {
+    /// the client is done configuring: the program can run.
+    Configured,
+    Resume(Resume),
+    Disconnect,
+}
+
+/// What a `variablesReference` stands for.
+enum Handle {
+    /// the locals of a call, counting from the innermost.
+    Locals(usize),
+    Globals,
+    /// the children of a value.
+    Value(Value),
+}
+
+struct Adapter {
+    client: Rc<RefCell<Client>>,
+    sources: Sources,
+    session: Session,
+    /// what each reference handed out since the program stopped stands for, counting from 1.
+    handles: Vec<Handle>,
+}
+
+impl Adapter {
+    /// answers `request`, with the program stopped in `interpreter` if it has started.
+    fn handle(
+        &mut self,
+        request: &Json,
+        interpreter: Option<&Interpreter>,
+    ) -> std::io::Result<Option<Next>> {
+        let command = request.get("command").as_str().unwrap_or("");
+        let arguments = request.get("arguments");
+        let mut next = None;
+        let result = match (command, interpreter) {
+            ("setBreakpoints", _) => Ok(self.set_breakpoints(arguments)),
+            ("setExceptionBreakpoints", _) => Ok(Json::object([])),
+            ("configurationDone", _) => {
+                next = Some(Next::Configured);
+                Ok(Json::object([]))
+            }
+            ("threads", _) => {
+                let thread = Json::object([("id", THREAD.into()), ("name", "main".into())]);
+                Ok(Json::object([("threads", vec![thread].into())]))
+            }
+            ("disconnect" | "terminate", _) => {
+                next = Some(Next::Disconnect);
+                Ok(Json::object([]))
+            }
+            // there is nothing to pause: the program is stopped whenever requests are read
+            ("pause", _) => Ok(Json::object([])),
+            (_, None) => Err("the program is not running".to_owned()),
+            ("continue" | "next" | "stepIn" | "stepOut", _) => {
+                let resume = match command {
+                    "continue" => Resume::Continue,
+                    "next" => Resume::StepOver,
+                    "stepIn" => Resume::StepIn,
+                    _ => Resume::StepOut,
+                };
+                next = Some(Next::Resume(resume));
+                Ok(Json::object([("allThreadsContinued", true.into())]))
+            }
+            ("stackTrace", Some(interpreter)) => Ok(self.stack_trace(interpreter)),
+            ("scopes", Some(_)) => self.scopes(arguments),
+            ("variables", Some(interpreter)) => self.variables(interpreter, arguments),
+            ("evaluate", Some(interpreter)) => self.evaluate(interpreter, arguments),
+            _ => Err(format!("unsupported request '{}'", command)),
+        };
+        self.client.borrow_mut().respond(request, result)?;
+        Ok(next)
+    }
+
+    /// replaces the breakpoints of a file, moving each to the first line from there on that has a
+    /// statement.
+    fn set_breakpoints(&mut self, arguments: &Json) -> Json {
+        let path = arguments.get("source").get("path").as_str().unwrap_or("");
+        let module = self.sources.find(Path::new(path));
+        let requested = arguments.get("breakpoints").as_array().unwrap_or(&[]);
+        let mut lines = Vec::new();
+        let breakpoints = requested.iter().map(|breakpoint| {
+            let line = breakpoint.get("line").as_usize().unwrap_or(0);
+            let Some(module) = module else {
+                return Json::object([
+                    ("verified", false.into()),
+                    ("line", line.into()),
+                    ("message", "not a file of the program".into()),
+                ]);
+            };
+            match self.sources.breakable(module, line) {
+                Some(line) => {
+                    lines.push(line);
+                    Json::object([("verified", true.into()), ("line", line.into())])
+                }
+                None => Json::object([
+                    ("verified", false.into()),
+                    ("line", line.into()),
+                    ("message", "no statement here or after".into()),
+                ]),
+            }
+        });
+        let breakpoints: Vec<Json> = breakpoints.collect();
+        if let Some(module) = module {
+            self.session.set_breakpoints(module, lines);
+        }
+        Json::object([("breakpoints", breakpoints.into())])
+    }
+
+    /// the calls in progress, innermost first, numbered from 1 that way.
+    fn stack_trace(&self, interpreter: &Interpreter) -> Json {
+        let stack = interpreter.stack();
+        let frames = stack.iter().enumerate().map(|(i, frame)| {
+            let path = self.sources.path(frame.module);
+            let name = path.file_name().unwrap_or_default().to_string_lossy();
+            // clients look files up by absolute path
+            let path = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+            Json::object([
+                ("id", (i + 1).into()),
+                ("name", frame.function.as_str().into()),
+                ("line", frame.line.into()),
+                ("column", 1usize.into()),
+                (
+                    "source",
+                    Json::object([
+                        ("name", name.as_ref().into()),
+                        ("path", path.to_string_lossy().as_ref().into()),
+                    ]),
+                ),
+            ])
+        });
+        Json::object([
+            ("stackFrames", frames.collect::<Vec<_>>().into()),
+            ("totalFrames", stack.len().into()),
+        ])
+    }
+
+    fn scopes(&mut self, arguments: &Json) -> Result<Json, String> {
+        let frame = frame(arguments)?;
+        let scope = |name: &str, reference: usize| {
+            Json::object([
+                ("name", name.into()),
+                ("variablesReference", reference.into()),
+                ("expensive", false.into()),
+            ])
+        };
+        let scopes = vec![
+            scope("Locals", self.reference(Handle::Locals(frame))),
+            scope("Globals", self.reference(Handle::Globals)),
+        ];
+        Ok(Json::object([("scopes", scopes.into())]))
+    }
+
+    fn variables(&mut self, interpreter: &Interpreter, arguments: &Json) -> Result<Json, String> {
+        let reference = arguments.get("variablesReference").as_usize().unwrap_or(0);
+        let variables = match reference.checked_sub(1).and_then(|i| self.handles.get(i)) {
+            Some(Handle::Locals(frame)) => interpreter.locals(*frame),
+            Some(Handle::Globals) => interpreter.globals(),
+            Some(Handle::Value(value)) => children(interpreter.heap(), value),
+            None => return Err(format!("no variables by reference {}", reference)),
+        };
+        let variables = variables.into_iter().map(|(name, value)| {
+            let shown = interpreter.heap().inspect(&value);
+            Json::object([
+                ("name", name.into()),
+                ("value", shown.into()),
+                ("variablesReference", self.value(interpreter, value).into()),
+            ])
+        });
+        let variables: Vec<Json> = variables.collect();
+        Ok(Json::object([("variables", variables.into())]))
+    }
+
+    fn evaluate(&mut self, interpreter: &Interpreter, arguments: &Json) -> Result<Json, String> {
+        let frame = frame(arguments).unwrap_or(0);
+        let expression = arguments.get("expression").as_str().unwrap_or("");
+        let value = lookup(interpreter, frame, expression)?;
+        Ok(Json::object([
+            ("result", interpreter.heap().inspect(&value).into()),
+            ("variablesReference", self.value(interpreter, value).into()),
+        ]))
+    }
+
+    /// answers requests about the program stopped in `interpreter` until one says how to go on,
+    /// or `None` if the client disconnects.
+    fn wait(&mut self, interpreter: &Interpreter) -> std::io::Result<Option<Resume>> {
+        loop {
+            let Some(request) = self.client.borrow_mut().read()? else {
+                return Ok(None);
+            };
+            match self.handle(&request, Some(interpreter))? {
+                Some(Next::Resume(resume)) => return Ok(Some(resume)),
+                Some(Next::Disconnect) => return Ok(None),
+                Some(Next::Configured) | None => {}
+            }
+        }
+    }
+
+    fn reference(&mut self, handle: Handle) -> usize {
+        self.handles.push(handle);
+        self.handles.len()
+    }
+    /// a reference to the children of `value`, or 0 if it has none.
+    fn value(&mut self, interpreter: &Interpreter, value: Value) -> usize {
+        match children(interpreter.heap(), &value).is_empty() {
+            true => 0,
+            false => self.reference(Handle::Value(value)),
+        }
+    }
+}
+
+/// the call a request is about, counting from the innermost.
+fn frame(arguments: &Json) -> Result<usize, String> {
+    let id = arguments.get("frameId").as_usize();
+    id.and_then(|id| id.checked_sub(1))
+        .ok_or_else(|| "no frame given".to_owned())
+}
+
+impl Debugger for Adapter {
+    fn statement(&mut self, interpreter: &Interpreter, at: Position) -> ControlFlow<()> {
+        let Some(reason) = self.session.stop(at) else {
+            return ControlFlow::Continue(());
+        };
+        let stopped = Json::object([
+            ("reason", reason.name().into()),
+            ("threadId", THREAD.into()),
+            ("allThreadsStopped", true.into()),
+        ]);
+        let event = self.client.borrow_mut().event("stopped", stopped);
+        let resume = event.and_then(|()| self.wait(interpreter));
+        // values handed out are only kept alive while the program is stopped
+        self.handles.clear();
+        match resume {
+            Ok(Some(resume)) => {
+                self.session.resume(resume);
+                ControlFlow::Continue(())
+            }
+            Ok(None) => {
+                self.client.borrow_mut().disconnected = true;
+                ControlFlow::Break(())
+            }
+            Err(e) => {
+                self.client.borrow_mut().failed = Some(e);
+                ControlFlow::Break(())
+            }
+        }
+    }
+}
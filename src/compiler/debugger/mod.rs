@@ -0,0 +1,286 @@
+//! Stopping a running program to look at it. An interpreter with a `Debugger` attached asks it
+//! before every statement whether to go on; the debugger may look at the calls in progress and
+//! their variables meanwhile, and answer once it is told to continue or step.
+//!
+//! A `Session` decides where to stop, from line breakpoints and the last step asked for. Two
+//! debuggers are built on it: `cli`, driven by commands typed at a prompt, and `dap`, which speaks
+//! the Debug Adapter Protocol for editors to attach.
+
+pub mod cli;
+pub mod dap;
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    ops::ControlFlow,
+    path::{Path, PathBuf},
+};
+
+use crate::compiler::{
+    ast::visitor::{walk_stmt, Visitor},
+    gc::Heap,
+    interpreter::Interpreter,
+    modules::{self, Loader, Module, ModuleErr},
+    natives::Natives,
+    statements::stmt::Stmt,
+    value::Value,
+};
+
+/// What an interpreter asks before every statement it runs.
+pub trait Debugger {
+    /// whether to go on to the statement at `at`, or end the run. The interpreter waits for the
+    /// answer, so stopping there is not answering until the user says how to go on.
+    fn statement(&mut self, interpreter: &Interpreter, at: Position) -> ControlFlow<()>;
+}
+
+/// Where a statement about to run is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    /// counting in the order the modules run, the program's own last.
+    pub module: usize,
+    pub line: usize,
+    /// the calls in progress, the top-level code's included.
+    pub depth: usize,
+    /// the blocks being executed, which grows into a statement's body and the calls it makes.
+    pub nesting: usize,
+}
+
+/// A call in progress: the function, or `<script>` for the top-level code, and the statement it
+/// is at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub function: String,
+    pub module: usize,
+    pub line: usize,
+}
+
+/// How to go on from a stop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// to the next breakpoint.
+    Continue,
+    /// to the next statement, into any call.
+    StepIn,
+    /// to the next statement of this call, or of its caller once it returns.
+    StepOver,
+    /// to the caller's next statement.
+    StepOut,
+}
+
+/// Why the program stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Reason {
+    /// before its first statement.
+    Entry,
+    Breakpoint,
+    Step,
+}
+
+impl Reason {
+    /// as the Debug Adapter Protocol has it.
+    pub fn name(self) -> &'static str {
+        match self {
+            Reason::Entry => "entry",
+            Reason::Breakpoint => "breakpoint",
+            Reason::Step => "step",
+        }
+    }
+}
+
+/// Where to stop next: at the breakpoints, and wherever the last step asked for ends.
+///
+/// A line is stopped at once, at the first of its statements to run: the statements nested in it
+/// that start on the same line, e.g. the body of `if (x) { y = 1; }`, are passed. A loop's body
+/// stops again on every iteration, though, as do recursive calls.
+#[derive(Debug)]
+pub struct Session {
+    /// lines by module.
+    breakpoints: HashMap<usize, BTreeSet<usize>>,
+    resume: Resume,
+    /// where the program last stopped, if it has.
+    stopped: Option<Position>,
+    /// whether the statement stopped at is still running.
+    inside: bool,
+}
+
+impl Session {
+    /// a session stopping at the first statement if `stop_on_entry` is set, and otherwise at the
+    /// first breakpoint.
+    pub fn new(stop_on_entry: bool) -> Self {
+        Self {
+            breakpoints: HashMap::new(),
+            resume: match stop_on_entry {
+                true => Resume::StepIn,
+                false => Resume::Continue,
+            },
+            stopped: None,
+            inside: false,
+        }
+    }
+    /// replaces the breakpoints of `module`.
+    pub fn set_breakpoints(&mut self, module: usize, lines: impl IntoIterator<Item = usize>) {
+        self.breakpoints.insert(module, lines.into_iter().collect());
+    }
+    pub fn breakpoints(&self, module: usize) -> impl Iterator<Item = usize> + '_ {
+        self.breakpoints.get(&module).into_iter().flatten().copied()
+    }
+    /// whether to stop before the statement at `at`, and why.
+    pub fn stop(&mut self, at: Position) -> Option<Reason> {
+        if let Some(stopped) = self.stopped {
+            self.inside &= at.nesting > stopped.nesting;
+            let place = |p: Position| (p.module, p.line, p.depth);
+            if self.inside && place(at) == place(stopped) {
+                return None;
+            }
+        }
+        let reason = if self.breakpoints(at.module).any(|line| line == at.line) {
+            Reason::Breakpoint
+        } else {
+            let step = match (self.resume, self.stopped) {
+                (Resume::Continue, _) => false,
+                (_, None) => true,
+                (Resume::StepIn, _) => true,
+                (Resume::StepOver, Some(stopped)) => at.depth <= stopped.depth,
+                (Resume::StepOut, Some(stopped)) => at.depth < stopped.depth,
+            };
+            match (step, self.stopped) {
+                (false, _) => return None,
+                (true, None) => Reason::Entry,
+                (true, Some(_)) => Reason::Step,
+            }
+        };
+        self.stopped = Some(at);
+        self.inside = true;
+        Some(reason)
+    }
+    /// goes on from a stop.
+    pub fn resume(&mut self, resume: Resume) {
+        self.resume = resume;
+    }
+}
+
+/// The files of a program being debugged, by module, and the lines their statements start on.
+#[derive(Debug)]
+pub struct Sources {
+    paths: Vec<PathBuf>,
+    lines: Vec<BTreeSet<usize>>,
+}
+
+impl Sources {
+    pub fn new(modules: &[Module]) -> Self {
+        Self {
+            paths: modules.iter().map(|m| m.path.clone()).collect(),
+            lines: modules
+                .iter()
+                .map(|m| statement_lines(&m.program))
+                .collect(),
+        }
+    }
+    /// how many modules there are.
+    pub fn len(&self) -> usize {
+        self.paths.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.paths.is_empty()
+    }
+    pub fn path(&self, module: usize) -> &Path {
+        &self.paths[module]
+    }
+    /// the module the file at `path` is, which may be given relative to the working directory or
+    /// by a suffix, e.g. just its name.
+    pub fn find(&self, path: &Path) -> Option<usize> {
+        let canonical = path.canonicalize().ok();
+        let exact = self
+            .paths
+            .iter()
+            .position(|p| p == path || canonical.is_some() && p.canonicalize().ok() == canonical);
+        exact.or_else(|| self.paths.iter().position(|p| p.ends_with(path)))
+    }
+    /// the line a breakpoint asked for at `line` of `module` stops at: the first with a statement
+    /// from there on, if any.
+    pub fn breakable(&self, module: usize, line: usize) -> Option<usize> {
+        self.lines.get(module)?.range(line..).next().copied()
+    }
+}
+
+/// every line a statement of `program` starts on.
+fn statement_lines(program: &[Stmt]) -> BTreeSet<usize> {
+    struct Lines(BTreeSet<usize>);
+    impl Visitor for Lines {
+        fn visit_stmt(&mut self, stmt: &Stmt) {
+            self.0.insert(stmt.line);
+            walk_stmt(self, stmt)
+        }
+    }
+    let mut lines = Lines(BTreeSet::new());
+    for stmt in program {
+        lines.visit_stmt(stmt);
+    }
+    lines.0
+}
+
+/// loads the program at `path` and compiles it as written, without folding constants, for the
+/// interpreter to run module by module. The globals are those the interpreter needs.
+pub fn compile(
+    path: &Path,
+    search_path: &[PathBuf],
+    natives: &Natives,
+) -> Result<(Vec<Module>, Vec<String>), Vec<ModuleErr>> {
+    let mut modules = Loader::new(search_path.to_vec())
+        .load(path)
+        .map_err(|e| vec![e])?;
+    let compiled = modules::compile(&mut modules, natives, false, None)?;
+    Ok((modules, compiled.globals))
+}
+
+/// what can be looked into of a value: the elements of a list, the entries of a map, and the
+/// fields of an instance, by name.
+pub fn children(heap: &Heap, value: &Value) -> Vec<(String, Value)> {
+    match value {
+        Value::List(gc) => {
+            let elements = heap.list(*gc).iter().cloned().enumerate();
+            elements.map(|(i, v)| (i.to_string(), v)).collect()
+        }
+        Value::Map(gc) => {
+            let entries = heap.map(*gc).entries();
+            entries.map(|(k, v)| (k.to_string(), v.clone())).collect()
+        }
+        Value::Instance(gc) => {
+            let mut fields: Vec<(String, Value)> = heap
+                .instance(*gc)
+                .fields
+                .iter()
+                .map(|(name, v)| (name.clone(), v.clone()))
+                .collect();
+            fields.sort_by(|a, b| a.0.cmp(&b.0));
+            fields
+        }
+        _ => Vec::new(),
+    }
+}
+
+/// the value `path` names as seen from the `frame`th call in progress: a variable, then the names
+/// of children to look into, all joined by dots, e.g. `p.x` or `points.0.x`. Globals of imported
+/// modules are named with their module's, e.g. `geometry.unit`.
+pub fn lookup(interpreter: &Interpreter, frame: usize, path: &str) -> Result<Value, String> {
+    let parts: Vec<&str> = path.trim().split('.').collect();
+    let variables = [interpreter.locals(frame), interpreter.globals()].concat();
+    // the longest variable the path starts with
+    let (mut value, rest) = (1..=parts.len())
+        .rev()
+        .find_map(|len| {
+            let name = parts[..len].join(".");
+            let found = variables.iter().find(|(n, _)| *n == name);
+            found.map(|(_, value)| (value.clone(), &parts[len..]))
+        })
+        .ok_or_else(|| format!("no variable '{}' here", parts[0]))?;
+    for part in rest {
+        let children = children(interpreter.heap(), &value);
+        value = match children.into_iter().find(|(name, _)| name == part) {
+            Some((_, child)) => child,
+            None => return Err(format!("'{}' has no '{}'", path.trim(), part)),
+        };
+    }
+    Ok(value)
+}
@@ -0,0 +1,357 @@
+use std::{cell::RefCell, collections::VecDeque, io::Cursor, ops::ControlFlow, rc::Rc};
+
+use super::{cli::Cli, compile, dap, Debugger, Position, Reason, Resume, Session};
+use crate::{
+    compiler::{
+        eval::{EvalErr, RuntimeError},
+        interpreter::Interpreter,
+        lsp::{read_message, write_message},
+        modules::Module,
+        natives::Natives,
+        testing::{dir, SharedOutput, TempDir},
+    },
+    util::json::Json,
+};
+
+/// `main.txt` in a fresh directory, loaded and compiled, with the directory, which the command
+/// line debugger reads the source back from.
+fn load(name: &str, source: &str) -> (TempDir, Vec<Module>, Vec<String>) {
+    let dir = dir(name, &[("main.txt", source)]);
+    let (modules, globals) = compile(&dir.join("main.txt"), &[], &Natives::standard()).unwrap();
+    (dir, modules, globals)
+}
+
+/// runs the program with `debugger` attached, printing to `out`.
+fn debug(
+    modules: &[Module],
+    globals: Vec<String>,
+    debugger: impl Debugger + 'static,
+    out: &SharedOutput,
+) -> Result<(), RuntimeError> {
+    let mut interpreter =
+        Interpreter::with_output(&Natives::standard(), globals, Box::new(out.clone()))
+            .with_debugger(Box::new(debugger));
+    interpreter.run_modules(modules)
+}
+
+/// the line of a stop, why it was made, and the locals there.
+type Stop = (usize, Reason, Vec<String>);
+
+/// A debugger that goes on from each stop as it is told in turn, noting where it stopped, why, and
+/// the locals there.
+struct Script {
+    session: Session,
+    resumes: VecDeque<Resume>,
+    stops: Rc<RefCell<Vec<Stop>>>,
+}
+
+impl Debugger for Script {
+    fn statement(&mut self, interpreter: &Interpreter, at: Position) -> ControlFlow<()> {
+        let Some(reason) = self.session.stop(at) else {
+            return ControlFlow::Continue(());
+        };
+        let locals = interpreter.locals(0).into_iter().map(|(name, value)| {
+            let value = interpreter.heap().inspect(&value);
+            format!("{}={}", name, value)
+        });
+        let stop = (at.line, reason, locals.collect());
+        self.stops.borrow_mut().push(stop);
+        match self.resumes.pop_front() {
+            Some(resume) => {
+                self.session.resume(resume);
+                ControlFlow::Continue(())
+            }
+            None => ControlFlow::Break(()),
+        }
+    }
+}
+
+fn script(source: &str, session: Session, resumes: &[Resume]) -> (Vec<Stop>, String) {
+    let (_dir, modules, globals) = load("script", source);
+    let stops = Rc::new(RefCell::new(Vec::new()));
+    let script = Script {
+        session,
+        resumes: resumes.iter().copied().collect(),
+        stops: stops.clone(),
+    };
+    let out = SharedOutput::default();
+    debug(&modules, globals, script, &out).unwrap();
+    let stops = stops.borrow().clone();
+    (stops, out.text())
+}
+
+const ADD: &str = "fn add(a, b) {
+    let sum = a + b;
+    return sum;
+}
+let x = add(1, 2);
+if (x > 2) { x = 0; }
+print x;
+";
+
+#[test]
+fn test_stepping() {
+    use Resume::*;
+    let (stops, printed) = script(
+        ADD,
+        Session::new(true),
+        &[StepOver, StepIn, StepOver, StepOut, StepOver, Continue],
+    );
+    let lines: Vec<(usize, Reason)> = stops.iter().map(|s| (s.0, s.1)).collect();
+    assert_eq!(
+        lines,
+        vec![
+            (1, Reason::Entry),
+            (5, Reason::Step),
+            (2, Reason::Step),
+            (3, Reason::Step),
+            // back in the caller, whose line 5 is done
+            (6, Reason::Step),
+            // past the body, on the same line as the `if`
+            (7, Reason::Step),
+        ]
+    );
+    assert_eq!(stops[3].2, vec!["a=1", "b=2", "sum=3"]);
+    assert_eq!(printed, "0\n");
+}
+
+#[test]
+fn test_breakpoints() {
+    let source = "fn count(n) {
+    if (n > 0) {
+        count(n - 1);
+    }
+}
+count(2);
+for i in [1, 2] {
+    print i;
+}
+";
+    let mut session = Session::new(false);
+    session.set_breakpoints(0, [2, 8]);
+    let (stops, printed) = script(source, session, &[Resume::Continue; 5]);
+    let stops: Vec<(usize, Vec<String>)> = stops.into_iter().map(|s| (s.0, s.2)).collect();
+    assert_eq!(
+        stops,
+        vec![
+            // every recursive call stops, and every iteration
+            (2, vec!["n=2".to_owned()]),
+            (2, vec!["n=1".to_owned()]),
+            (2, vec!["n=0".to_owned()]),
+            (8, vec!["i=1".to_owned()]),
+            (8, vec!["i=2".to_owned()]),
+        ]
+    );
+    assert_eq!(printed, "1\n2\n");
+}
+
+#[test]
+fn test_locals_are_named_innermost_first() {
+    let source = "class Counter {
+    fn init(start) {
+        this.count = start;
+    }
+    fn add(n) {
+        let total = this.count + n;
+        return total;
+    }
+}
+fn outer(x) {
+    let y = 1;
+    fn inner() {
+        let x = \"shadowing\";
+        return x;
+    }
+    return inner();
+}
+Counter(1).add(2);
+outer(5);
+";
+    let mut session = Session::new(false);
+    session.set_breakpoints(0, [7, 14]);
+    let (stops, _) = script(source, session, &[Resume::Continue; 2]);
+    let locals: Vec<Vec<String>> = stops.into_iter().map(|s| s.2).collect();
+    assert_eq!(
+        locals,
+        vec![
+            vec!["n=2", "total=3", "this=<Counter instance>"],
+            vec!["x=\"shadowing\"", "y=1", "inner=<fn inner>"],
+        ]
+    );
+}
+
+/// runs the program at `source` under the command line debugger, typing `commands`, and what
+/// the debugger and the program wrote.
+fn cli(name: &str, source: &str, commands: &str) -> (Result<(), RuntimeError>, String) {
+    let (_dir, modules, globals) = load(name, source);
+    let output = SharedOutput::default();
+    let input = Cursor::new(commands.as_bytes().to_vec());
+    let cli = Cli::new(input, output.clone(), &modules);
+    let result = debug(&modules, globals, cli, &output);
+    (result, output.text())
+}
+
+#[test]
+fn test_cli() {
+    let source = "class Point {
+    fn init(x, y) {
+        this.x = x;
+        this.y = y;
+    }
+}
+fn norm(p) {
+    return p.x * p.x + p.y * p.y;
+}
+let points = [Point(3, 4)];
+print norm(points[0]);
+";
+    let commands = "b 8\nb 20\nc\nbt\nlocals\np p.y\np points.0.x\np q\nup\nlocals\nc\n";
+    let (result, output) = cli("cli", source, commands);
+    result.unwrap();
+    let lines: Vec<&str> = output.lines().collect();
+    assert!(lines[0].starts_with("stopped at entry"), "{}", output);
+    let expected = [
+        "(debug) breakpoint at ",
+        "(debug) no statement at or after ",
+        "(debug) stopped at breakpoint",
+        "norm at ",
+        "    8 |     return p.x * p.x + p.y * p.y;",
+        "(debug) > #0 norm at ",
+        "  #1 <script> at ",
+        "(debug) p = <Point instance>",
+        "(debug) p.y = 4",
+        "(debug) points.0.x = 3",
+        "(debug) no variable 'q' here",
+        "(debug) <script> at ",
+        "   11 | print norm(points[0]);",
+        "(debug) no locals",
+        "(debug) 25",
+    ];
+    for (line, expected) in lines[3..].iter().zip(expected) {
+        assert!(line.starts_with(expected), "{:?} in\n{}", expected, output);
+    }
+    assert_eq!(lines.len(), 3 + expected.len(), "{}", output);
+}
+
+#[test]
+fn test_quitting_ends_the_run() {
+    let source = "try {
+    print 1;
+} finally {
+    print 2;
+}
+";
+    let (result, output) = cli("quit", source, "n\nq\n");
+    assert_eq!(result.unwrap_err().error, EvalErr::Stopped);
+    // nothing runs after the debugger stops the program, `finally` blocks included
+    assert_eq!(output.lines().last(), Some("(debug) "), "{}", output);
+}
+
+fn request(seq: usize, command: &str, arguments: Json) -> Json {
+    Json::object([
+        ("seq", seq.into()),
+        ("type", "request".into()),
+        ("command", command.into()),
+        ("arguments", arguments),
+    ])
+}
+
+#[test]
+fn test_serve_over_a_stream() {
+    let dir = dir("dap", &[("main.txt", ADD)]);
+    let program = dir.join("main.txt").to_string_lossy().into_owned();
+    let breakpoints = Json::object([
+        ("source", Json::object([("path", program.as_str().into())])),
+        (
+            "breakpoints",
+            vec![
+                Json::object([("line", 2usize.into())]),
+                Json::object([("line", 4usize.into())]),
+                Json::object([("line", 40usize.into())]),
+            ]
+            .into(),
+        ),
+    ]);
+    let frame = Json::object([("frameId", 1usize.into())]);
+    let mut input = Vec::new();
+    let requests = [
+        request(1, "initialize", Json::object([])),
+        request(
+            2,
+            "launch",
+            Json::object([("program", program.as_str().into())]),
+        ),
+        request(3, "setBreakpoints", breakpoints),
+        request(4, "configurationDone", Json::object([])),
+        request(5, "continue", Json::object([])),
+        request(6, "stackTrace", Json::object([])),
+        request(7, "scopes", frame),
+        request(
+            8,
+            "variables",
+            Json::object([("variablesReference", 1usize.into())]),
+        ),
+        request(9, "continue", Json::object([])),
+        request(10, "disconnect", Json::object([])),
+    ];
+    for request in &requests {
+        write_message(&mut input, request).unwrap();
+    }
+    let output = SharedOutput::default();
+    dap::serve(Cursor::new(input), output.clone(), Vec::new()).unwrap();
+
+    let output = output.bytes();
+    let mut output = output.as_slice();
+    let mut messages = Vec::new();
+    while let Some(body) = read_message(&mut output).unwrap() {
+        messages.push(Json::parse(&body).unwrap());
+    }
+    let kinds: Vec<String> = messages
+        .iter()
+        .map(|m| match m.get("type").as_str() {
+            Some("event") => m.get("event").as_str().unwrap().to_owned(),
+            _ => m.get("command").as_str().unwrap().to_owned(),
+        })
+        .collect();
+    assert_eq!(
+        kinds,
+        vec![
+            "initialize",
+            "launch",
+            "initialized",
+            "setBreakpoints",
+            "configurationDone",
+            "stopped",
+            "continue",
+            "stopped",
+            "stackTrace",
+            "scopes",
+            "variables",
+            "continue",
+            "output",
+            "exited",
+            "terminated",
+            "disconnect",
+        ]
+    );
+    let verified = messages[3].get("body").get("breakpoints").to_string();
+    assert_eq!(
+        verified,
+        r#"[{"verified":true,"line":2},{"verified":true,"line":5},"#.to_owned()
+            + r#"{"verified":false,"line":40,"message":"no statement here or after"}]"#
+    );
+    // the breakpoint moved to line 5 stops before the one in the function it calls
+    let frames = messages[8].get("body").get("stackFrames");
+    let frames = frames.as_array().unwrap();
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].get("name").as_str(), Some("add"));
+    assert_eq!(frames[0].get("line").as_usize(), Some(2));
+    let variables = messages[10].get("body").get("variables").to_string();
+    assert_eq!(
+        variables,
+        r#"[{"name":"a","value":"1","variablesReference":0},"#.to_owned()
+            + r#"{"name":"b","value":"2","variablesReference":0}]"#
+    );
+    assert_eq!(messages[12].get("body").get("output").as_str(), Some("0\n"));
+    assert_eq!(messages[13].get("body").get("exitCode").as_usize(), Some(0));
+}
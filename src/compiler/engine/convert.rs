@@ -0,0 +1,296 @@
+//! Conversions between script values and Rust ones. They are what lets a host register a plain
+//! closure as a native, and call script functions with Rust arguments.
+
+use std::{
+    cell::{Ref, RefCell, RefMut},
+    rc::Rc,
+};
+
+use crate::compiler::{
+    ast::{
+        literal::Literal,
+        types::{Scheme, Type},
+    },
+    eval::EvalErr,
+    gc::Heap,
+    interpreter::Interpreter,
+    natives::{function, invalid_argument, Natives},
+    value::{HostObject, Value},
+};
+
+/// A Rust type with a script counterpart, which is what the checker sees where it is used.
+pub trait Typed {
+    fn ty() -> Type;
+}
+
+/// A Rust type script values can be read as.
+pub trait FromValue: Sized {
+    /// `None` if the value is of another type.
+    fn from_value(value: &Value, heap: &Heap) -> Option<Self>;
+}
+
+/// A Rust type that can be handed to scripts. Converting can allocate, or fail where a native
+/// returns an error.
+pub trait IntoValue {
+    fn into_value(self, interpreter: &mut Interpreter) -> Result<Value, EvalErr>;
+}
+
+/// A Rust type scripts can hold objects of, under `NAME`. Register it with
+/// `Engine::register_type`, then pass its objects around as `Host`s.
+pub trait HostType: 'static {
+    const NAME: &'static str;
+}
+
+/// A shared handle on an object of a host type. The host and the scripts holding it see the same
+/// object.
+pub struct Host<T>(Rc<RefCell<T>>);
+
+impl<T: HostType> Host<T> {
+    pub fn new(value: T) -> Self {
+        Host(Rc::new(RefCell::new(value)))
+    }
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.0.borrow()
+    }
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.0.borrow_mut()
+    }
+}
+
+impl<T> Clone for Host<T> {
+    fn clone(&self) -> Self {
+        Host(self.0.clone())
+    }
+}
+
+impl Typed for Value {
+    fn ty() -> Type {
+        Type::Any
+    }
+}
+impl FromValue for Value {
+    fn from_value(value: &Value, _: &Heap) -> Option<Self> {
+        Some(value.clone())
+    }
+}
+impl IntoValue for Value {
+    fn into_value(self, _: &mut Interpreter) -> Result<Value, EvalErr> {
+        Ok(self)
+    }
+}
+
+/// implements the conversions of a type that is a `Literal` variant.
+macro_rules! literal {
+    ($rust:ty, $ty:expr, $value:pat => $from:expr, $into:expr) => {
+        impl Typed for $rust {
+            fn ty() -> Type {
+                $ty
+            }
+        }
+        impl FromValue for $rust {
+            fn from_value(value: &Value, _: &Heap) -> Option<Self> {
+                match value {
+                    $value => Some($from),
+                    _ => None,
+                }
+            }
+        }
+        impl IntoValue for $rust {
+            fn into_value(self, _: &mut Interpreter) -> Result<Value, EvalErr> {
+                let into: fn($rust) -> Literal = $into;
+                Ok(Value::Literal(into(self)))
+            }
+        }
+    };
+}
+
+literal!((), Type::Nil, Value::Literal(Literal::Nil) => (), |()| Literal::Nil);
+literal!(bool, Type::Bool, Value::Literal(Literal::Bool(b)) => *b, Literal::Bool);
+literal!(i32, Type::Int, Value::Literal(Literal::Int(i)) => *i, Literal::Int);
+literal!(f32, Type::Float, Value::Literal(Literal::Float(f)) => *f, Literal::Float);
+// scripts only have f32s, so an f64 loses precision on the way in
+literal!(f64, Type::Float, Value::Literal(Literal::Float(f)) => *f as f64, |f| {
+    Literal::Float(f as f32)
+});
+literal!(String, Type::Str, Value::Literal(Literal::Str(s)) => s.clone(), Literal::Str);
+
+impl Typed for &str {
+    fn ty() -> Type {
+        Type::Str
+    }
+}
+impl IntoValue for &str {
+    fn into_value(self, _: &mut Interpreter) -> Result<Value, EvalErr> {
+        Ok(Value::Literal(Literal::Str(self.to_owned())))
+    }
+}
+
+impl<T: Typed> Typed for Vec<T> {
+    fn ty() -> Type {
+        Type::List(Box::new(T::ty()))
+    }
+}
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value, heap: &Heap) -> Option<Self> {
+        match value {
+            Value::List(list) => heap
+                .list(*list)
+                .iter()
+                .map(|element| T::from_value(element, heap))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self, interpreter: &mut Interpreter) -> Result<Value, EvalErr> {
+        let values = hold_all(
+            interpreter,
+            self.into_iter().map(|e| move |i: &mut _| e.into_value(i)),
+        )?;
+        interpreter.new_list(values)
+    }
+}
+
+/// `nil` or a `T`. Scripts have no type for that, so the checker lets anything through.
+impl<T> Typed for Option<T> {
+    fn ty() -> Type {
+        Type::Any
+    }
+}
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value, heap: &Heap) -> Option<Self> {
+        match value {
+            Value::Literal(Literal::Nil) => Some(None),
+            value => T::from_value(value, heap).map(Some),
+        }
+    }
+}
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self, interpreter: &mut Interpreter) -> Result<Value, EvalErr> {
+        match self {
+            Some(value) => value.into_value(interpreter),
+            None => Ok(Value::NIL),
+        }
+    }
+}
+
+/// what a fallible native returns: its error becomes a runtime error of the script.
+impl<T: Typed, E> Typed for Result<T, E> {
+    fn ty() -> Type {
+        T::ty()
+    }
+}
+impl<T: IntoValue, E: std::fmt::Display> IntoValue for Result<T, E> {
+    fn into_value(self, interpreter: &mut Interpreter) -> Result<Value, EvalErr> {
+        match self {
+            Ok(value) => value.into_value(interpreter),
+            Err(e) => Err(EvalErr::Native(e.to_string())),
+        }
+    }
+}
+
+impl<T: HostType> Typed for Host<T> {
+    fn ty() -> Type {
+        Type::Instance(T::NAME.to_owned(), Vec::new())
+    }
+}
+impl<T: HostType> FromValue for Host<T> {
+    fn from_value(value: &Value, _: &Heap) -> Option<Self> {
+        match value {
+            Value::Host(object) => object.data.clone().downcast::<RefCell<T>>().ok().map(Host),
+            _ => None,
+        }
+    }
+}
+impl<T: HostType> IntoValue for Host<T> {
+    fn into_value(self, _: &mut Interpreter) -> Result<Value, EvalErr> {
+        Ok(Value::Host(HostObject {
+            type_name: T::NAME,
+            data: self.0,
+        }))
+    }
+}
+
+/// Arguments for a script function: a tuple of up to four values that convert.
+pub trait IntoArgs {
+    fn into_args(self, interpreter: &mut Interpreter) -> Result<Vec<Value>, EvalErr>;
+}
+
+/// A Rust closure that can be registered as a native, taking up to four arguments. `Args` is the
+/// tuple of their types, which only tells the implementations apart.
+pub trait IntoNative<Args> {
+    fn register(self, name: &str, natives: &mut Natives);
+}
+
+/// converts values in turn, keeping each alive while the next may allocate.
+fn hold_all<C>(
+    interpreter: &mut Interpreter,
+    conversions: impl IntoIterator<Item = C>,
+) -> Result<Vec<Value>, EvalErr>
+where
+    C: FnOnce(&mut Interpreter) -> Result<Value, EvalErr>,
+{
+    let mark = interpreter.held();
+    for convert in conversions {
+        match convert(interpreter) {
+            Ok(value) => interpreter.hold(value),
+            Err(e) => {
+                interpreter.release(mark);
+                return Err(e);
+            }
+        }
+    }
+    Ok(interpreter.release(mark))
+}
+
+macro_rules! arity {
+    ($($arg:ident $position:tt),*) => {
+        impl<$($arg: IntoValue),*> IntoArgs for ($($arg,)*) {
+            #[allow(unused_variables)]
+            fn into_args(self, interpreter: &mut Interpreter) -> Result<Vec<Value>, EvalErr> {
+                let mark = interpreter.held();
+                $(
+                    match self.$position.into_value(interpreter) {
+                        Ok(value) => interpreter.hold(value),
+                        Err(e) => {
+                            interpreter.release(mark);
+                            return Err(e);
+                        }
+                    }
+                )*
+                Ok(interpreter.release(mark))
+            }
+        }
+
+        impl<F, R, $($arg),*> IntoNative<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R + 'static,
+            R: IntoValue + Typed,
+            $($arg: FromValue + Typed,)*
+        {
+            // a native without parameters uses neither its arguments nor its name
+            #[allow(unused_variables)]
+            fn register(self, name: &str, natives: &mut Natives) {
+                let signature: Scheme = function(vec![$($arg::ty()),*], R::ty());
+                let owned = name.to_owned();
+                natives.register(name, signature, move |interpreter, args| {
+                    $(
+                        #[allow(non_snake_case)]
+                        let $arg = $arg::from_value(&args[$position], interpreter.heap())
+                            .ok_or_else(|| {
+                                invalid_argument(&owned, $position, $arg::ty().to_string())
+                            })?;
+                    )*
+                    self($($arg),*).into_value(interpreter)
+                });
+            }
+        }
+    };
+}
+
+arity!();
+arity!(A 0);
+arity!(A 0, B 1);
+arity!(A 0, B 1, C 2);
+arity!(A 0, B 1, C 2, D 3);
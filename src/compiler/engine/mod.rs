@@ -0,0 +1,283 @@
+//! The API for embedding the language in a Rust program. An `Engine` holds what the host provides,
+//! compiles source against it into a `Script`, and runs scripts, after which the host can read and
+//! write their globals and call their functions:
+//!
+//! ```
+//! let mut engine = compiler::Engine::new();
+//! engine.register_fn("greeting", || "hello");
+//! let script = engine.compile("let n = 2; fn shout(s: str) -> str { return greeting() + s; }")?;
+//! engine.run(&script)?;
+//! assert_eq!(engine.global::<i32>("n")?, 2);
+//! assert_eq!(engine.call::<String>("shout", ("!",))?, "hello!");
+//! # Ok::<(), compiler::Error>(())
+//! ```
+
+pub mod convert;
+#[cfg(test)]
+mod tests;
+
+use std::{
+    cell::RefCell,
+    io::Write,
+    path::{Path, PathBuf},
+    rc::Rc,
+};
+
+use crate::compiler::{
+    ast::types::Scheme,
+    checker::{check, check_assignable, Binding, TypeErr},
+    eval::{EvalErr, RuntimeError},
+    interpreter::Interpreter,
+    lexer::Lexer,
+    limits::Limits,
+    modules::{self, Loader, ModuleErr},
+    natives::Natives,
+    optimizer::{optimize, FoldErr},
+    parser::{ParseErr, Parser},
+    resolver::{resolve, ResolveErr},
+    statements::stmt::Stmt,
+    token::Token,
+    value::Value,
+};
+
+use convert::{FromValue, HostType, IntoArgs, IntoNative, IntoValue, Typed};
+
+#[derive(Debug)]
+pub enum Error {
+    Parse(ParseErr),
+    Resolve(Vec<ResolveErr>),
+    Type(Vec<TypeErr>),
+    Fold(Vec<FoldErr>),
+    /// anything that went wrong compiling a file and the modules it imports, which say where.
+    Module(Vec<ModuleErr>),
+    Runtime(RuntimeError),
+    /// there is no script running to ask.
+    NotRunning,
+    /// the running script has no global by that name, or its declaration has not run.
+    UndefinedGlobal(String),
+    /// a value is not of the type the host asked for, which it names.
+    Conversion(String),
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fn lines(
+            f: &mut std::fmt::Formatter<'_>,
+            errors: &[impl std::fmt::Display],
+        ) -> std::fmt::Result {
+            let errors: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+            write!(f, "{}", errors.join("\n"))
+        }
+        match self {
+            Error::Parse(e) => write!(f, "{}", e),
+            Error::Resolve(errors) => lines(f, errors),
+            Error::Type(errors) => lines(f, errors),
+            Error::Fold(errors) => lines(f, errors),
+            Error::Module(errors) => lines(f, errors),
+            Error::Runtime(e) => write!(f, "{}", e),
+            Error::NotRunning => write!(f, "no script has run yet"),
+            Error::UndefinedGlobal(name) => write!(f, "undefined variable '{}'", name),
+            Error::Conversion(expected) => write!(f, "expected a value of type {}", expected),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+impl From<EvalErr> for Error {
+    /// for errors converting the host's values, which happen outside of any run.
+    fn from(error: EvalErr) -> Self {
+        Error::Runtime(error.into())
+    }
+}
+
+/// A program compiled against the natives of the engine that compiled it, which it keeps.
+pub struct Script {
+    program: Vec<Stmt>,
+    globals: Vec<String>,
+    natives: Natives,
+    bindings: Vec<Binding>,
+}
+
+impl Script {
+    /// the inferred type of every declaration, leaving out those of imported modules.
+    pub fn types(&self) -> &[Binding] {
+        &self.bindings
+    }
+}
+
+#[derive(Default)]
+pub struct Engine {
+    natives: Natives,
+    /// where scripts print, stdout if `None`.
+    out: Option<SharedOutput>,
+    limits: Limits,
+    /// where `compile_file` looks for imports that are not next to the importing file.
+    module_path: Vec<PathBuf>,
+    /// the state of the last script run.
+    interpreter: Option<Interpreter>,
+    /// the types of the declarations of the last script run.
+    bindings: Vec<Binding>,
+}
+
+impl Engine {
+    /// an engine with the standard library.
+    pub fn new() -> Self {
+        Self {
+            natives: Natives::standard(),
+            ..Self::default()
+        }
+    }
+    /// an engine with no natives at all, not even the standard library.
+    pub fn empty() -> Self {
+        Self::default()
+    }
+
+    /// registers a Rust closure as a native, typed after its parameters and result, e.g.
+    /// `|s: String, n: i32| s.repeat(n as usize)` as a `fn(str, int) -> str`. Returning a
+    /// `Result` makes its errors runtime errors of the script. Scripts compiled from now on can
+    /// call it.
+    pub fn register_fn<Args>(&mut self, name: &str, f: impl IntoNative<Args>) -> &mut Self {
+        f.register(name, &mut self.natives);
+        self
+    }
+    /// registers a native working on script values directly, for what `register_fn` cannot type,
+    /// e.g. generic functions.
+    pub fn register_native(
+        &mut self,
+        name: &str,
+        signature: Scheme,
+        body: impl Fn(&mut Interpreter, &[Value]) -> Result<Value, EvalErr> + 'static,
+    ) -> &mut Self {
+        self.natives.register(name, signature, body);
+        self
+    }
+    /// lets scripts name `T` in annotations, and natives take and return its objects as
+    /// `convert::Host<T>`.
+    pub fn register_type<T: HostType>(&mut self) -> &mut Self {
+        self.natives.register_type(T::NAME);
+        self
+    }
+    /// bounds the runs that follow, and the calls into the scripts they run. A script that goes
+    /// past a limit fails with a runtime error saying which.
+    pub fn set_limits(&mut self, limits: Limits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+    /// the directories, in order, that `compile_file` looks for imported modules in when they are
+    /// not next to the importing file.
+    pub fn set_module_path(&mut self, module_path: Vec<PathBuf>) -> &mut Self {
+        self.module_path = module_path;
+        self
+    }
+    /// sends what scripts print to `out` rather than stdout.
+    pub fn set_output(&mut self, out: impl Write + 'static) -> &mut Self {
+        self.out = Some(SharedOutput(Rc::new(RefCell::new(out))));
+        self
+    }
+
+    /// parses and checks `source`, and folds its constants, as the `compiler` binary does before
+    /// running a file. The source cannot import modules, since it is in no file they could be
+    /// relative to; see `compile_file`.
+    pub fn compile(&self, source: &str) -> Result<Script, Error> {
+        let tokens: Vec<Token> = Lexer::from_source(source).collect();
+        let mut program = Parser::new(&tokens).parse().map_err(Error::Parse)?;
+        let globals = resolve(&mut program, &self.natives).map_err(Error::Resolve)?;
+        let types = check(&program, &self.natives).map_err(Error::Type)?;
+        optimize(&mut program, &types.ints).map_err(Error::Fold)?;
+        Ok(Script {
+            program,
+            globals,
+            natives: self.natives.clone(),
+            bindings: types.bindings,
+        })
+    }
+    /// compiles the program starting at the file `path` together with the modules it imports,
+    /// which run before it. Globals of the imported modules are named after their module, e.g.
+    /// `geometry.area`.
+    pub fn compile_file(&self, path: impl AsRef<Path>) -> Result<Script, Error> {
+        let mut modules = Loader::new(self.module_path.clone())
+            .load(path.as_ref())
+            .map_err(|e| Error::Module(vec![e]))?;
+        let mut compiled =
+            modules::compile(&mut modules, &self.natives, true, None).map_err(Error::Module)?;
+        Ok(Script {
+            program: modules::link(modules),
+            globals: compiled.globals,
+            natives: self.natives.clone(),
+            bindings: compiled.bindings.pop().unwrap_or_default(),
+        })
+    }
+    /// runs the script's top-level code. Its globals stay for the host to use until the next run,
+    /// even if this one fails.
+    pub fn run(&mut self, script: &Script) -> Result<(), Error> {
+        let out: Box<dyn Write> = match &self.out {
+            Some(out) => Box::new(out.clone()),
+            None => Box::new(std::io::stdout()),
+        };
+        let interpreter = Interpreter::with_output(&script.natives, script.globals.clone(), out)
+            .with_limits(self.limits);
+        self.bindings = script.bindings.clone();
+        let interpreter = self.interpreter.insert(interpreter);
+        interpreter.run(&script.program).map_err(Error::Runtime)
+    }
+
+    /// the value of a global of the running script.
+    pub fn global<T: FromValue + Typed>(&self, name: &str) -> Result<T, Error> {
+        let interpreter = self.interpreter.as_ref().ok_or(Error::NotRunning)?;
+        let value = interpreter
+            .global(name)
+            .ok_or_else(|| Error::UndefinedGlobal(name.to_owned()))?;
+        convert(&value, interpreter)
+    }
+    /// sets a global of the running script, to a value of the type it was declared with or
+    /// inferred to have.
+    pub fn set_global<T: IntoValue + Typed>(&mut self, name: &str, value: T) -> Result<(), Error> {
+        let interpreter = self.interpreter.as_mut().ok_or(Error::NotRunning)?;
+        // globals of imported modules have no binding here, and are left to the host
+        if let Some(binding) = self.bindings.iter().find(|b| b.name == name) {
+            check_assignable(&binding.scheme, &T::ty(), binding.line)
+                .map_err(|e| Error::Type(vec![e]))?;
+        }
+        let value = value.into_value(interpreter)?;
+        if interpreter.set_global(name, value) {
+            Ok(())
+        } else {
+            Err(Error::UndefinedGlobal(name.to_owned()))
+        }
+    }
+    /// calls the function a global of the running script holds with a tuple of arguments, e.g.
+    /// `engine.call::<i32>("add", (1, 2))`.
+    pub fn call<R: FromValue + Typed>(
+        &mut self,
+        name: &str,
+        args: impl IntoArgs,
+    ) -> Result<R, Error> {
+        let interpreter = self.interpreter.as_mut().ok_or(Error::NotRunning)?;
+        let callee = interpreter
+            .global(name)
+            .ok_or_else(|| Error::UndefinedGlobal(name.to_owned()))?;
+        let args = args.into_args(interpreter)?;
+        let result = interpreter
+            .call_value(callee, args)
+            .map_err(Error::Runtime)?;
+        convert(&result, interpreter)
+    }
+}
+
+fn convert<T: FromValue + Typed>(value: &Value, interpreter: &Interpreter) -> Result<T, Error> {
+    T::from_value(value, interpreter.heap()).ok_or_else(|| Error::Conversion(T::ty().to_string()))
+}
+
+/// lets every run write to the one output the host set.
+#[derive(Clone)]
+struct SharedOutput(Rc<RefCell<dyn Write>>);
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.0.borrow_mut().flush()
+    }
+}
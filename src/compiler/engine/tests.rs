@@ -0,0 +1,282 @@
+use std::{cell::RefCell, rc::Rc};
+
+use super::{
+    convert::{Host, HostType},
+    Engine, Error,
+};
+use crate::compiler::{
+    ast::types::Type,
+    eval::{EvalErr, RuntimeError},
+    limits::Limits,
+    natives::generic,
+    testing::{dir, SharedOutput},
+    value::Value,
+};
+
+fn engine() -> (Engine, SharedOutput) {
+    let out = SharedOutput::default();
+    let mut engine = Engine::new();
+    engine.set_output(out.clone());
+    (engine, out)
+}
+
+#[test]
+fn test_compile_and_run() {
+    let (mut engine, out) = engine();
+    let script = engine
+        .compile("let xs = [1, 2]; push(xs, 3); print len(xs);")
+        .unwrap();
+    engine.run(&script).unwrap();
+    assert_eq!(out.take(), "3\n");
+    // every run starts afresh
+    engine.run(&script).unwrap();
+    assert_eq!(out.take(), "3\n");
+    assert_eq!(engine.global::<Vec<i32>>("xs").unwrap(), vec![1, 2, 3]);
+}
+
+#[test]
+fn test_globals() {
+    let (mut engine, out) = engine();
+    assert!(matches!(engine.global::<i32>("n"), Err(Error::NotRunning)));
+
+    let script = engine
+        .compile("let n = 1; let name = \"a\"; fn show() { print name + str(n); }")
+        .unwrap();
+    engine.run(&script).unwrap();
+    assert_eq!(engine.global::<i32>("n").unwrap(), 1);
+    assert_eq!(engine.global::<String>("name").unwrap(), "a");
+    assert_eq!(engine.global::<Option<i32>>("n").unwrap(), Some(1));
+
+    engine.set_global("n", 5).unwrap();
+    engine.set_global("name", "b").unwrap();
+    engine.call::<()>("show", ()).unwrap();
+    assert_eq!(out.take(), "b5\n");
+    // a value of another type than the global's is refused before the script can see it
+    let Err(Error::Type(errors)) = engine.set_global("n", "five") else {
+        panic!("a str set as an int");
+    };
+    assert_eq!(errors[0].to_string(), "line 1: expected int but found str");
+    assert_eq!(engine.global::<i32>("n").unwrap(), 5);
+
+    assert!(matches!(
+        engine.global::<i32>("missing"),
+        Err(Error::UndefinedGlobal(name)) if name == "missing"
+    ));
+    assert!(matches!(
+        engine.set_global("missing", 1),
+        Err(Error::UndefinedGlobal(_))
+    ));
+    let Err(e) = engine.global::<bool>("name") else {
+        panic!("a str read as a bool");
+    };
+    assert_eq!(e.to_string(), "expected a value of type bool");
+
+    // what inference left open takes anything
+    let script = engine.compile("let xs = [];").unwrap();
+    engine.run(&script).unwrap();
+    engine.set_global("xs", vec!["a"]).unwrap();
+    assert_eq!(engine.global::<Vec<String>>("xs").unwrap(), ["a"]);
+}
+
+#[test]
+fn test_call_script_functions() {
+    let (mut engine, _) = engine();
+    let script = engine
+        .compile(
+            "fn add(a: int, b: int) -> int { return a + b; }
+             fn words(s: str) -> list<str> { return split(s, \" \"); }
+             fn fail() { [1][2]; }",
+        )
+        .unwrap();
+    engine.run(&script).unwrap();
+    assert_eq!(engine.call::<i32>("add", (1, 2)).unwrap(), 3);
+    assert_eq!(
+        engine.call::<Vec<String>>("words", ("a b",)).unwrap(),
+        vec!["a", "b"]
+    );
+    assert!(matches!(
+        engine.call::<Value>("add", (1,)),
+        Err(Error::Runtime(RuntimeError {
+            error: EvalErr::InvalidArity(2, 1),
+            ..
+        }))
+    ));
+    assert!(matches!(
+        engine.call::<Value>("fail", ()),
+        Err(Error::Runtime(_))
+    ));
+    // arithmetic faults are errors for the host too, not panics
+    assert!(matches!(
+        engine.call::<i32>("add", (i32::MAX, 1)),
+        Err(Error::Runtime(RuntimeError {
+            error: EvalErr::Overflow,
+            ..
+        }))
+    ));
+    // a failed call leaves the script usable
+    assert_eq!(engine.call::<i32>("add", (2, 2)).unwrap(), 4);
+}
+
+#[test]
+fn test_register_fn() {
+    let (mut engine, out) = engine();
+    let calls = Rc::new(RefCell::new(0));
+    let counted = calls.clone();
+    engine
+        .register_fn("repeat", |s: String, n: i32| s.repeat(n as usize))
+        .register_fn("tick", move || *counted.borrow_mut() += 1)
+        .register_fn("halve", |n: i32| {
+            if n % 2 == 0 {
+                Ok(n / 2)
+            } else {
+                Err(format!("{} is odd", n))
+            }
+        });
+    let script = engine
+        .compile("tick(); tick(); print repeat(\"ab\", 2); print halve(4); halve(3);")
+        .unwrap();
+    let Err(Error::Runtime(e)) = engine.run(&script) else {
+        panic!("halving 3 succeeded");
+    };
+    assert_eq!(e.to_string(), "3 is odd");
+    assert_eq!(out.take(), "abab\n2\n");
+    assert_eq!(*calls.borrow(), 2);
+
+    // registered natives are typed like the standard library
+    let Err(Error::Type(errors)) = engine.compile("repeat(1, 2);") else {
+        panic!("repeat took an int");
+    };
+    assert_eq!(errors.len(), 1);
+}
+
+#[test]
+fn test_register_native() {
+    let (mut engine, out) = engine();
+    engine.register_native(
+        "first",
+        generic(None, |t| (vec![Type::List(Box::new(t.clone()))], t)),
+        |interpreter, args| match &args[0] {
+            Value::List(list) => Ok(interpreter.heap().list(*list)[0].clone()),
+            _ => unreachable!("checked against the signature"),
+        },
+    );
+    let script = engine
+        .compile("let s: str = first([\"x\"]); print s + str(first([1]));")
+        .unwrap();
+    engine.run(&script).unwrap();
+    assert_eq!(out.take(), "x1\n");
+}
+
+struct Counter {
+    count: i32,
+}
+
+impl HostType for Counter {
+    const NAME: &'static str = "Counter";
+}
+
+#[test]
+fn test_host_types() {
+    let (mut engine, out) = engine();
+    let counter = Host::new(Counter { count: 0 });
+    let shared = counter.clone();
+    engine
+        .register_type::<Counter>()
+        .register_fn("counter", move || shared.clone())
+        .register_fn("bump", |c: Host<Counter>| {
+            c.borrow_mut().count += 1;
+            c.borrow().count
+        });
+    let script = engine
+        .compile("let c: Counter = counter(); bump(c); print bump(counter());")
+        .unwrap();
+    engine.run(&script).unwrap();
+    assert_eq!(out.take(), "2\n");
+    assert_eq!(counter.borrow().count, 2);
+    let c = engine.global::<Host<Counter>>("c").unwrap();
+    assert_eq!(c.borrow().count, 2);
+
+    // host objects have no properties, and are not interchangeable with other values
+    let Err(Error::Type(errors)) = engine.compile("print counter().count;") else {
+        panic!("a host object had a property");
+    };
+    assert_eq!(errors.len(), 1);
+    assert!(engine.compile("bump(1);").is_err());
+}
+
+#[test]
+fn test_compile_errors() {
+    let engine = Engine::new();
+    assert!(matches!(engine.compile("let = 1;"), Err(Error::Parse(_))));
+    assert!(matches!(
+        engine.compile("print undefined;"),
+        Err(Error::Resolve(_))
+    ));
+    assert!(matches!(
+        engine.compile("let x: int = \"a\";"),
+        Err(Error::Type(_))
+    ));
+    assert!(matches!(
+        engine.compile("print 1 / 0;"),
+        Err(Error::Fold(_))
+    ));
+    // without the standard library there is nothing to call
+    assert!(matches!(
+        Engine::empty().compile("print len(\"a\");"),
+        Err(Error::Resolve(_))
+    ));
+}
+
+#[test]
+fn test_limits() {
+    let (mut engine, _) = engine();
+    engine.set_limits(Limits {
+        fuel: Some(500),
+        ..Limits::default()
+    });
+    let script = engine
+        .compile("fn spin() { while true {} } fn quick() -> int { return 1; }")
+        .unwrap();
+    engine.run(&script).unwrap();
+    assert!(matches!(
+        engine.call::<()>("spin", ()),
+        Err(Error::Runtime(RuntimeError {
+            error: EvalErr::OutOfFuel(500),
+            ..
+        }))
+    ));
+    // every call gets the whole allowance again
+    assert_eq!(engine.call::<i32>("quick", ()).unwrap(), 1);
+}
+
+#[test]
+fn test_compile_file_with_imports() {
+    let dir = dir(
+        "engine-modules",
+        &[
+            (
+                "main.txt",
+                "import \"strings\" as strings;\nlet s = strings.shout(\"hi\");",
+            ),
+            (
+                "lib/strings.txt",
+                "export fn shout(s: str) -> str { return s + \"!\"; }",
+            ),
+        ],
+    );
+
+    let (mut engine, _) = engine();
+    assert!(matches!(
+        engine.compile_file(dir.join("main.txt")),
+        Err(Error::Module(errors)) if errors.len() == 1
+    ));
+    engine.set_module_path(vec![dir.join("lib")]);
+    let script = engine.compile_file(dir.join("main.txt")).unwrap();
+    engine.run(&script).unwrap();
+    assert_eq!(engine.global::<String>("s").unwrap(), "hi!");
+    // an imported module's globals are named after it
+    assert_eq!(
+        engine.call::<String>("strings.shout", ("hey",)).unwrap(),
+        "hey!"
+    );
+}
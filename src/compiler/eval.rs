@@ -1,8 +1,13 @@
+use std::time::Duration;
+
+use crate::compiler::value::Value;
+
 pub trait Evaluate<T> {
     fn eval(self) -> T;
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
+#[allow(clippy::enum_variant_names)]
 pub enum EvalErr {
     InvalidBang,
     InvalidNegate,
@@ -10,4 +15,155 @@ pub enum EvalErr {
     InvalidDiv,
     InvalidAdd,
     InvalidSub,
+    InvalidCompare,
+    /// an int divided by zero.
+    DivisionByZero,
+    /// an int result that does not fit in 32 bits.
+    Overflow,
+    InvalidCall,
+    InvalidArity(usize, usize),
+    InvalidProperty(String),
+    InvalidGet,
+    InvalidSet,
+    UndefinedVariable(String),
+    NotIndexable,
+    InvalidIndex,
+    /// the index and the length of the list.
+    IndexOutOfBounds(i32, usize),
+    InvalidKey,
+    /// the key as it prints inside a map.
+    MissingKey(String),
+    NotIterable,
+    /// the native, the position of the argument counting from 1, and what it should have been.
+    InvalidArgument {
+        native: String,
+        position: usize,
+        expected: String,
+    },
+    /// a failure a native describes itself.
+    Native(String),
+    /// the steps the run was allowed.
+    OutOfFuel(u64),
+    /// how long the run was allowed.
+    Timeout(Duration),
+    /// the number of nested calls allowed.
+    StackOverflow(usize),
+    /// the bytes the heap was allowed.
+    OutOfMemory(usize),
+    /// the debugger ended the run.
+    Stopped,
+    /// a value the program threw and nothing caught.
+    Thrown(Value),
+}
+
+impl EvalErr {
+    /// whether the error is going past one of the `Limits`, or being stopped by a debugger, which
+    /// a program cannot catch: it ends the run without even `finally` blocks running.
+    pub fn is_limit(&self) -> bool {
+        matches!(
+            self,
+            EvalErr::OutOfFuel(_)
+                | EvalErr::Timeout(_)
+                | EvalErr::StackOverflow(_)
+                | EvalErr::OutOfMemory(_)
+                | EvalErr::Stopped
+        )
+    }
+}
+
+impl std::fmt::Display for EvalErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EvalErr::InvalidBang => write!(f, "operand of '!' must be a bool or nil"),
+            EvalErr::InvalidNegate => write!(f, "operand of '-' must be a number"),
+            EvalErr::InvalidMul => write!(f, "operands of '*' must be numbers"),
+            EvalErr::InvalidDiv => write!(f, "operands of '/' must be numbers"),
+            EvalErr::InvalidAdd => write!(f, "operands of '+' must be numbers or strings"),
+            EvalErr::InvalidSub => write!(f, "operands of '-' must be numbers"),
+            EvalErr::InvalidCompare => write!(f, "only numbers can be compared"),
+            EvalErr::DivisionByZero => write!(f, "division by zero"),
+            EvalErr::Overflow => write!(f, "integer overflow"),
+            EvalErr::InvalidCall => write!(f, "can only call functions and classes"),
+            EvalErr::InvalidArity(expected, got) => {
+                write!(f, "expected {} arguments but got {}", expected, got)
+            }
+            EvalErr::InvalidProperty(name) => write!(f, "undefined property '{}'", name),
+            EvalErr::InvalidGet => write!(f, "only instances have properties"),
+            EvalErr::InvalidSet => write!(f, "only instances have fields"),
+            EvalErr::UndefinedVariable(name) => write!(f, "undefined variable '{}'", name),
+            EvalErr::NotIndexable => write!(f, "only lists and maps can be indexed"),
+            EvalErr::InvalidIndex => write!(f, "list indices must be ints"),
+            EvalErr::IndexOutOfBounds(index, len) => write!(
+                f,
+                "index {} is out of bounds for a list of length {}",
+                index, len
+            ),
+            EvalErr::InvalidKey => write!(f, "map keys must be nil, bools, ints or strings"),
+            EvalErr::MissingKey(key) => write!(f, "key {} is not in the map", key),
+            EvalErr::NotIterable => write!(f, "can only iterate over lists and maps"),
+            EvalErr::InvalidArgument {
+                native,
+                position,
+                expected,
+            } => write!(
+                f,
+                "argument {} of '{}' must be {}",
+                position, native, expected
+            ),
+            EvalErr::Native(message) => write!(f, "{}", message),
+            EvalErr::OutOfFuel(fuel) => write!(f, "ran out of fuel after {} steps", fuel),
+            EvalErr::Timeout(timeout) => write!(f, "timed out after {:?}", timeout),
+            EvalErr::StackOverflow(depth) => {
+                write!(f, "stack overflow: more than {} nested calls", depth)
+            }
+            EvalErr::OutOfMemory(bytes) => {
+                write!(f, "out of memory: the heap would exceed {} bytes", bytes)
+            }
+            EvalErr::Stopped => write!(f, "stopped by the debugger"),
+            // the value needs the heap to be shown, see `RuntimeError`
+            EvalErr::Thrown(_) => write!(f, "uncaught exception"),
+        }
+    }
+}
+
+/// a call in progress when an error left it: the function, or `<script>` for the top-level code,
+/// and the line of the statement it was running.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    pub line: usize,
+}
+
+impl std::fmt::Display for Frame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at {} (line {})", self.function, self.line)
+    }
+}
+
+/// an error that ended a run, or a call into the program from the host.
+#[derive(Debug, PartialEq)]
+pub struct RuntimeError {
+    pub error: EvalErr,
+    /// what went wrong, with any value thrown shown as the program would print it.
+    pub message: String,
+    /// the calls the error left, innermost first. Errors raised before any statement ran, e.g.
+    /// calling a global with the wrong number of arguments, have none.
+    pub trace: Vec<Frame>,
+}
+
+impl From<EvalErr> for RuntimeError {
+    /// for errors raised outside of a run, which have no trace.
+    fn from(error: EvalErr) -> Self {
+        Self {
+            message: error.to_string(),
+            error,
+            trace: Vec::new(),
+        }
+    }
+}
+
+impl std::fmt::Display for RuntimeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
 }
@@ -0,0 +1,498 @@
+use std::collections::VecDeque;
+
+use crate::compiler::{
+    ast::{
+        expr::Expr,
+        printer::SourcePrinter,
+        types::{fmt_type_params, Type, TypeParam},
+    },
+    lexer::Lexer,
+    parser::{ParseErr, Parser},
+    statements::stmt::{Block, FnDecl, Param, Stmt, StmtKind},
+    token::{Token, TokenType},
+};
+
+pub const DEFAULT_WIDTH: usize = 100;
+
+/// Formats a whole source file. Comments are kept: a comment on its own line stays above the
+/// statement that follows it, and a comment after code stays after the token it follows, even
+/// inside an expression. Runs of blank lines between statements collapse to one.
+pub fn format_source(source: &str, width: usize) -> Result<String, ParseErr> {
+    let (comments, tokens): (Vec<Token>, Vec<Token>) = Lexer::from_source(source)
+        .with_trivia()
+        .partition(|t| t.token_type == TokenType::Comment);
+    let stmts = Parser::new(&tokens).parse()?;
+
+    let mut formatter = Formatter {
+        out: String::new(),
+        width,
+        depth: 0,
+        comments: comments.into(),
+        held: None,
+        tokens,
+        cursor: 0,
+        last_line: 0,
+        at_block_start: true,
+    };
+    formatter.stmts(&stmts);
+    formatter.release();
+    formatter.leading(usize::MAX);
+    Ok(formatter.out)
+}
+
+struct Formatter {
+    out: String,
+    width: usize,
+    depth: usize,
+    /// comments not yet emitted, in source order.
+    comments: VecDeque<Token>,
+    /// the comment ending the source line being emitted, and where in `out` it goes: after the
+    /// last line break emitted for that source line, as that follows the line's last token.
+    held: Option<(usize, Token)>,
+    /// the code tokens, which place comments among the tokens of an expression.
+    tokens: Vec<Token>,
+    /// index of the first token of `tokens` not yet emitted, or before it.
+    cursor: usize,
+    /// last source line that has been emitted, used to keep blank lines.
+    last_line: usize,
+    /// nothing has been emitted since the enclosing `{`, so no blank line is kept.
+    at_block_start: bool,
+}
+
+impl Formatter {
+    fn indent(&self) -> String {
+        SourcePrinter::INDENT.repeat(self.depth)
+    }
+    fn column(&self) -> usize {
+        match self.out.rfind('\n') {
+            Some(idx) => self.out.len() - idx - 1,
+            None => self.out.len(),
+        }
+    }
+    fn blank_line_before(&mut self, line: usize) {
+        if !self.at_block_start && line > self.last_line + 1 {
+            self.out.push('\n');
+        }
+        self.at_block_start = false;
+    }
+    /// emits the comments that sit on their own line before `line`.
+    fn leading(&mut self, line: usize) {
+        while let Some(comment) = self.comments.front() {
+            if comment.line >= line {
+                break;
+            }
+            let comment = self.comments.pop_front().unwrap();
+            self.blank_line_before(comment.line);
+            self.out.push_str(&self.indent());
+            self.out.push_str(comment.lexeme.trim_end());
+            self.out.push('\n');
+            self.last_line = comment.line;
+        }
+    }
+    /// ends the output line, which the comment that follows code on `line`, if any, ends unless
+    /// a later output line also holds code from `line`.
+    fn trailing(&mut self, line: usize) {
+        match &mut self.held {
+            Some((at, comment)) if comment.line == line => *at = self.out.len(),
+            _ => {
+                if self.comments.front().is_some_and(|c| c.line == line) {
+                    self.release();
+                    let comment = self.comments.pop_front().unwrap();
+                    self.held = Some((self.out.len(), comment));
+                }
+            }
+        }
+        self.out.push('\n');
+        self.last_line = self.last_line.max(line);
+    }
+    /// emits the held comment where it was last placed.
+    fn release(&mut self) {
+        if let Some((at, comment)) = self.held.take() {
+            self.out
+                .insert_str(at, &format!(" {}", comment.lexeme.trim_end()));
+        }
+    }
+    /// emits `;` and moves past it in the source.
+    fn semicolon(&mut self) {
+        self.out.push(';');
+        self.cursor = self.find(TokenType::Semi) + 1;
+    }
+    /// the index of the next `end` token from the cursor that is not inside brackets.
+    fn find(&self, end: TokenType) -> usize {
+        let mut depth = 0usize;
+        for (index, token) in self.tokens.iter().enumerate().skip(self.cursor) {
+            match token.token_type {
+                TokenType::LParen | TokenType::LBracket => depth += 1,
+                TokenType::RParen | TokenType::RBracket => depth = depth.saturating_sub(1),
+                t if t == end && depth == 0 => return index,
+                _ => {}
+            }
+        }
+        self.tokens.len()
+    }
+    /// emits an expression the source ends with the next `end` token, `;` or the `{` after a
+    /// condition, keeping the comments written inside it after the tokens they follow. Moves past
+    /// the `end` token and returns its line.
+    fn expr(&mut self, expr: &Expr, end: TokenType) -> usize {
+        let indent = self.indent();
+        let printed = SourcePrinter::print_wrapped(expr, self.width, self.column(), &indent);
+        let start = self.cursor;
+        let end = self.find(end);
+        self.cursor = end + 1;
+        let Some(end_token) = self.tokens.get(end) else {
+            self.out.push_str(&printed);
+            return self.last_line;
+        };
+        let before_end = |c: &Token| (c.line, c.column) < (end_token.line, end_token.column);
+
+        let mut inside = Vec::new();
+        if let Some((_, comment)) = &self.held {
+            let first = &self.tokens[start];
+            if (first.line, first.column) < (comment.line, comment.column) && before_end(comment) {
+                inside.push(self.held.take().unwrap().1);
+            }
+        }
+        while self.comments.front().is_some_and(before_end) {
+            inside.push(self.comments.pop_front().unwrap());
+        }
+        if inside.is_empty() {
+            self.out.push_str(&printed);
+            return end_token.line;
+        }
+
+        // where each source token ends in `printed`, found by matching the tokens from the end,
+        // as the printer drops and adds only parentheses and commas
+        let lines: Vec<usize> = std::iter::once(0)
+            .chain(printed.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        let printed_tokens: Vec<Token> = Lexer::from_source(&printed).collect();
+        let mut ends = vec![None; end - start];
+        let (mut i, mut j) = (printed_tokens.len(), end - start);
+        while i > 0 && j > 0 {
+            let (p, s) = (&printed_tokens[i - 1], &self.tokens[start + j - 1]);
+            let skippable = |t: &Token| {
+                matches!(t.token_type, TokenType::LParen | TokenType::RParen)
+                    || t.token_type == TokenType::Comma
+            };
+            if p.token_type == s.token_type {
+                ends[j - 1] = Some(lines[p.line - 1] + p.column - 1 + p.lexeme.len());
+                i -= 1;
+                j -= 1;
+            } else if skippable(s) {
+                j -= 1;
+            } else if skippable(p) {
+                i -= 1;
+            } else {
+                break;
+            }
+        }
+
+        let mut at = 0;
+        for comment in inside {
+            let follows = self.tokens[start..end]
+                .iter()
+                .rposition(|t| (t.line, t.column) < (comment.line, comment.column));
+            let own_line = follows.is_none_or(|t| self.tokens[start + t].line < comment.line);
+            let offset = follows
+                .and_then(|t| ends[..=t].iter().rev().find_map(|end| *end))
+                .unwrap_or(0)
+                .max(at);
+            self.out.push_str(&printed[at..offset]);
+            self.out.truncate(self.out.trim_end_matches(' ').len());
+            let continuation = self.continuation();
+            match own_line {
+                true => {
+                    self.out.push('\n');
+                    self.out.push_str(&continuation);
+                }
+                false => self.out.push(' '),
+            }
+            self.out.push_str(comment.lexeme.trim_end());
+            at = printed.len() - printed[offset..].trim_start_matches(' ').len();
+            if !printed[at..].starts_with('\n') {
+                self.out.push('\n');
+                self.out.push_str(&continuation);
+            }
+        }
+        self.out.push_str(&printed[at..]);
+        end_token.line
+    }
+    /// the indentation of a line an expression continues on: that of the current line if it
+    /// already continues one, or else one level deeper than the statement.
+    fn continuation(&self) -> String {
+        let line = &self.out[self.out.rfind('\n').map_or(0, |i| i + 1)..];
+        let indent = &line[..line.len() - line.trim_start().len()];
+        match indent.len() > self.depth * SourcePrinter::INDENT.len() {
+            true => indent.to_owned(),
+            false => SourcePrinter::INDENT.repeat(self.depth + 1),
+        }
+    }
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            if self.held.as_ref().is_some_and(|(_, c)| c.line < stmt.line) {
+                self.release();
+            }
+            self.leading(stmt.line);
+            self.blank_line_before(stmt.line);
+            self.out.push_str(&self.indent());
+            self.stmt(stmt);
+        }
+    }
+    /// emits a statement starting at the current column, through the end of its last line.
+    fn stmt(&mut self, stmt: &Stmt) {
+        if stmt.exported {
+            self.out.push_str("export ");
+        }
+        match &stmt.kind {
+            StmtKind::Expr(e) => {
+                let end_line = self.expr(e, TokenType::Semi);
+                self.simple_end(end_line);
+            }
+            StmtKind::Print(e) => {
+                self.out.push_str("print ");
+                let end_line = self.expr(e, TokenType::Semi);
+                self.simple_end(end_line);
+            }
+            StmtKind::Let { name, ty, init } => {
+                self.out.push_str("let ");
+                self.out.push_str(&name.name);
+                if let Some(ty) = ty {
+                    self.out.push_str(": ");
+                    self.out.push_str(&ty.to_string());
+                }
+                match init {
+                    Some(init) => {
+                        self.out.push_str(" = ");
+                        let end_line = self.expr(init, TokenType::Semi);
+                        self.simple_end(end_line);
+                    }
+                    None => {
+                        self.semicolon();
+                        self.trailing(stmt.line);
+                    }
+                }
+            }
+            StmtKind::Block(block) => {
+                self.block(block, stmt.line);
+                self.trailing(block.end_line);
+            }
+            StmtKind::If { .. } => {
+                let end_line = self.if_chain(stmt);
+                self.trailing(end_line);
+            }
+            StmtKind::While { cond, body } => {
+                self.out.push_str("while ");
+                let open_line = self.expr(cond, TokenType::LBrace);
+                self.out.push(' ');
+                self.block(body, open_line);
+                self.trailing(body.end_line);
+            }
+            StmtKind::For {
+                name,
+                iterable,
+                body,
+            } => {
+                self.out.push_str("for ");
+                self.out.push_str(&name.name);
+                self.out.push_str(" in ");
+                let open_line = self.expr(iterable, TokenType::LBrace);
+                self.out.push(' ');
+                self.block(body, open_line);
+                self.trailing(body.end_line);
+            }
+            StmtKind::Fn(decl) => {
+                self.out.push_str("fn ");
+                self.function(decl, stmt.line);
+                self.trailing(decl.body.end_line);
+            }
+            StmtKind::Return(value) => {
+                self.out.push_str("return");
+                match value {
+                    Some(value) => {
+                        self.out.push(' ');
+                        let end_line = self.expr(value, TokenType::Semi);
+                        self.simple_end(end_line);
+                    }
+                    None => {
+                        self.semicolon();
+                        self.trailing(stmt.line);
+                    }
+                }
+            }
+            StmtKind::Throw(value) => {
+                self.out.push_str("throw ");
+                let end_line = self.expr(value, TokenType::Semi);
+                self.simple_end(end_line);
+            }
+            StmtKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                // `catch` and `finally` go on the line their block follows from
+                self.out.push_str("try ");
+                self.block(body, stmt.line);
+                let mut end_line = body.end_line;
+                if let Some(catch) = catch {
+                    self.out.push_str(" catch (");
+                    self.out.push_str(&catch.name.name);
+                    self.out.push_str(") ");
+                    self.block(&catch.body, end_line);
+                    end_line = catch.body.end_line;
+                }
+                if let Some(finally) = finally {
+                    self.out.push_str(" finally ");
+                    self.block(finally, end_line);
+                    end_line = finally.end_line;
+                }
+                self.trailing(end_line);
+            }
+            StmtKind::Class(decl) => {
+                self.out.push_str("class ");
+                self.out.push_str(&decl.name.name);
+                self.out.push_str(&fmt_type_params(&decl.type_params));
+                self.out.push_str(" {");
+                self.trailing(stmt.line);
+                self.depth += 1;
+                self.at_block_start = true;
+                for method in &decl.methods {
+                    self.leading(method.name.line);
+                    self.blank_line_before(method.name.line);
+                    self.out.push_str(&self.indent());
+                    self.out.push_str("fn ");
+                    self.function(method, method.name.line);
+                    self.trailing(method.body.end_line);
+                }
+                self.leading(decl.end_line);
+                self.depth -= 1;
+                self.out.push_str(&self.indent());
+                self.out.push('}');
+                self.trailing(decl.end_line);
+            }
+            StmtKind::Interface(decl) => {
+                self.out.push_str("interface ");
+                self.out.push_str(&decl.name.name);
+                self.out.push_str(" {");
+                self.trailing(stmt.line);
+                self.depth += 1;
+                self.at_block_start = true;
+                for method in &decl.methods {
+                    self.leading(method.name.line);
+                    self.blank_line_before(method.name.line);
+                    self.out.push_str(&self.indent());
+                    self.out.push_str("fn ");
+                    self.signature(
+                        &method.name.name,
+                        &[],
+                        &method.params,
+                        method.return_type.as_ref(),
+                    );
+                    self.semicolon();
+                    self.trailing(method.name.line);
+                }
+                self.leading(decl.end_line);
+                self.depth -= 1;
+                self.out.push_str(&self.indent());
+                self.out.push('}');
+                self.trailing(decl.end_line);
+            }
+            StmtKind::Import { path, name } => {
+                self.out.push_str("import \"");
+                self.out.push_str(path);
+                self.out.push_str("\" as ");
+                self.out.push_str(&name.name);
+                self.semicolon();
+                self.trailing(stmt.line);
+            }
+        }
+    }
+    /// ends a statement that is an expression followed by `;`, on `line`.
+    fn simple_end(&mut self, line: usize) {
+        self.out.push(';');
+        self.trailing(line);
+    }
+    /// emits `if cond { .. } else if .. else { .. }` and returns the line of the final `}`.
+    fn if_chain(&mut self, stmt: &Stmt) -> usize {
+        let StmtKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        } = &stmt.kind
+        else {
+            unreachable!("if_chain called on a statement that is not an if");
+        };
+        self.out.push_str("if ");
+        let open_line = self.expr(cond, TokenType::LBrace);
+        self.out.push(' ');
+        self.block(then_branch, open_line);
+        match else_branch.as_deref() {
+            None => then_branch.end_line,
+            Some(else_stmt) => {
+                self.out.push_str(" else ");
+                match &else_stmt.kind {
+                    StmtKind::Block(block) => {
+                        self.block(block, else_stmt.line);
+                        block.end_line
+                    }
+                    _ => self.if_chain(else_stmt),
+                }
+            }
+        }
+    }
+    fn function(&mut self, decl: &FnDecl, line: usize) {
+        self.signature(
+            &decl.name.name,
+            &decl.type_params,
+            &decl.params,
+            decl.return_type.as_ref(),
+        );
+        self.out.push(' ');
+        self.block(&decl.body, line);
+    }
+    /// emits `name<T>(a: int, b) -> ret`, without a trailing space.
+    fn signature(
+        &mut self,
+        name: &str,
+        type_params: &[TypeParam],
+        params: &[Param],
+        return_type: Option<&Type>,
+    ) {
+        self.out.push_str(name);
+        self.out.push_str(&fmt_type_params(type_params));
+        self.out.push('(');
+        let params: Vec<String> = params
+            .iter()
+            .map(|p| match &p.ty {
+                Some(ty) => format!("{}: {}", p.name.name, ty),
+                None => p.name.name.clone(),
+            })
+            .collect();
+        self.out.push_str(&params.join(", "));
+        self.out.push(')');
+        if let Some(ret) = return_type {
+            self.out.push_str(" -> ");
+            self.out.push_str(&ret.to_string());
+        }
+    }
+    /// emits `{ .. }` up to and including the closing brace. `open_line` is the line of the `{`.
+    fn block(&mut self, block: &Block, open_line: usize) {
+        let has_comments = match self.comments.front() {
+            Some(c) => c.line < block.end_line,
+            None => false,
+        };
+        if block.stmts.is_empty() && !has_comments {
+            self.out.push_str("{}");
+            return;
+        }
+
+        self.out.push('{');
+        self.trailing(open_line);
+        self.depth += 1;
+        self.at_block_start = true;
+        self.stmts(&block.stmts);
+        self.leading(block.end_line);
+        self.depth -= 1;
+        self.out.push_str(&self.indent());
+        self.out.push('}');
+    }
+}
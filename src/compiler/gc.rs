@@ -0,0 +1,288 @@
+//! The interpreter's heap and its mark-and-sweep collector. Environments, functions, classes,
+//! instances and collections refer to each other freely, so the cycles reference counting would
+//! leak, such as a scope holding a closure over itself, are common. Objects are reached through
+//! `Gc` handles; the interpreter decides when to collect and supplies the roots, since only it
+//! knows which values its own frames hold.
+
+use crate::compiler::{
+    ast::literal::Literal,
+    value::{Class, Env, Function, Instance, Map, Object, Value},
+};
+
+/// a reference to an object on the `Heap`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Gc(usize);
+
+/// the estimated bytes the heap may hold before its first collection, and at least before any
+/// later one.
+const MIN_THRESHOLD: usize = 1 << 20;
+/// how much the heap may grow past what survived a collection before the next one.
+const GROWTH_FACTOR: usize = 2;
+
+#[derive(Debug)]
+struct Entry {
+    object: Object,
+    marked: bool,
+    /// what the object counts for in `bytes`.
+    size: usize,
+}
+
+#[derive(Debug)]
+pub struct Heap {
+    /// freed slots are `None` and listed in `free`.
+    entries: Vec<Option<Entry>>,
+    free: Vec<usize>,
+    /// estimated bytes in use.
+    bytes: usize,
+    next_gc: usize,
+    /// collect on every allocation, so objects the roots miss are freed as early as possible.
+    stress: bool,
+    collections: usize,
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self {
+            entries: Vec::new(),
+            free: Vec::new(),
+            bytes: 0,
+            next_gc: MIN_THRESHOLD,
+            stress: false,
+            collections: 0,
+        }
+    }
+}
+
+impl Heap {
+    pub fn set_stress(&mut self, stress: bool) {
+        self.stress = stress;
+    }
+    /// whether enough has been allocated since the last collection to run another.
+    pub fn should_collect(&self) -> bool {
+        self.stress || self.bytes >= self.next_gc
+    }
+    /// estimated bytes in use, as of the last collection plus what was allocated or grew since.
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+    pub fn alloc(&mut self, object: Object) -> Gc {
+        let size = object.size();
+        self.bytes += size;
+        let entry = Some(Entry {
+            object,
+            marked: false,
+            size,
+        });
+        // under stress freed slots stay empty, so a handle that outlived its object cannot end
+        // up referring to a new one
+        let reuse = if self.stress { None } else { self.free.pop() };
+        match reuse {
+            Some(index) => {
+                self.entries[index] = entry;
+                Gc(index)
+            }
+            None => {
+                self.entries.push(entry);
+                Gc(self.entries.len() - 1)
+            }
+        }
+    }
+
+    /// Frees every object not reachable from `roots`, and sets the next threshold in proportion to
+    /// what survived. Returns how many objects were freed.
+    pub fn collect(&mut self, roots: impl IntoIterator<Item = Gc>) -> usize {
+        self.collect_holding(roots, 0)
+    }
+    /// `collect`, for roots that themselves hold `held` bytes off the heap, e.g. in strings, which
+    /// count as part of it.
+    pub fn collect_holding(&mut self, roots: impl IntoIterator<Item = Gc>, held: usize) -> usize {
+        let mut gray: Vec<Gc> = roots.into_iter().collect();
+        while let Some(gc) = gray.pop() {
+            let entry = self.entries[gc.0]
+                .as_mut()
+                .expect("a root or live object refers to a freed one");
+            if entry.marked {
+                continue;
+            }
+            entry.marked = true;
+            gray.extend(entry.object.references());
+        }
+
+        let mut freed = 0;
+        self.bytes = held;
+        for (index, slot) in self.entries.iter_mut().enumerate() {
+            match slot {
+                Some(entry) if entry.marked => {
+                    entry.marked = false;
+                    entry.size = entry.object.size();
+                    self.bytes += entry.size;
+                }
+                Some(_) => {
+                    *slot = None;
+                    self.free.push(index);
+                    freed += 1;
+                }
+                None => {}
+            }
+        }
+        self.next_gc = (self.bytes * GROWTH_FACTOR).max(MIN_THRESHOLD);
+        self.collections += 1;
+        freed
+    }
+
+    pub fn live_objects(&self) -> usize {
+        self.entries.len() - self.free.len()
+    }
+    pub fn collections(&self) -> usize {
+        self.collections
+    }
+
+    /// counts memory held outside any object, e.g. a string just made, until the next collection
+    /// counts whatever holds it instead.
+    pub fn count(&mut self, bytes: usize) {
+        self.bytes += bytes;
+    }
+
+    /// counts an object changed in place for what it has grown or shrunk by since.
+    pub fn resize(&mut self, gc: Gc) {
+        let entry = self.entries[gc.0]
+            .as_mut()
+            .expect("handle to a freed object");
+        let size = entry.object.size();
+        self.bytes = self.bytes + size - entry.size;
+        entry.size = size;
+    }
+
+    pub fn get(&self, gc: Gc) -> &Object {
+        &self.entries[gc.0]
+            .as_ref()
+            .expect("handle to a freed object")
+            .object
+    }
+    fn get_mut(&mut self, gc: Gc) -> &mut Object {
+        &mut self.entries[gc.0]
+            .as_mut()
+            .expect("handle to a freed object")
+            .object
+    }
+    pub fn env(&self, gc: Gc) -> &Env {
+        match self.get(gc) {
+            Object::Env(env) => env,
+            other => panic!("expected an environment, found {:?}", other),
+        }
+    }
+    pub fn env_mut(&mut self, gc: Gc) -> &mut Env {
+        match self.get_mut(gc) {
+            Object::Env(env) => env,
+            other => panic!("expected an environment, found {:?}", other),
+        }
+    }
+    pub fn function(&self, gc: Gc) -> &Function {
+        match self.get(gc) {
+            Object::Function(function) => function,
+            other => panic!("expected a function, found {:?}", other),
+        }
+    }
+    pub fn class(&self, gc: Gc) -> &Class {
+        match self.get(gc) {
+            Object::Class(class) => class,
+            other => panic!("expected a class, found {:?}", other),
+        }
+    }
+    pub fn instance(&self, gc: Gc) -> &Instance {
+        match self.get(gc) {
+            Object::Instance(instance) => instance,
+            other => panic!("expected an instance, found {:?}", other),
+        }
+    }
+    pub fn instance_mut(&mut self, gc: Gc) -> &mut Instance {
+        match self.get_mut(gc) {
+            Object::Instance(instance) => instance,
+            other => panic!("expected an instance, found {:?}", other),
+        }
+    }
+    pub fn list(&self, gc: Gc) -> &Vec<Value> {
+        match self.get(gc) {
+            Object::List(values) => values,
+            other => panic!("expected a list, found {:?}", other),
+        }
+    }
+    pub fn list_mut(&mut self, gc: Gc) -> &mut Vec<Value> {
+        match self.get_mut(gc) {
+            Object::List(values) => values,
+            other => panic!("expected a list, found {:?}", other),
+        }
+    }
+    pub fn map(&self, gc: Gc) -> &Map {
+        match self.get(gc) {
+            Object::Map(map) => map,
+            other => panic!("expected a map, found {:?}", other),
+        }
+    }
+    pub fn map_mut(&mut self, gc: Gc) -> &mut Map {
+        match self.get_mut(gc) {
+            Object::Map(map) => map,
+            other => panic!("expected a map, found {:?}", other),
+        }
+    }
+
+    /// the environment `depth` scopes out from `env`.
+    pub fn ancestor(&self, mut env: Gc, depth: usize) -> Gc {
+        for _ in 0..depth {
+            env = self
+                .env(env)
+                .parent
+                .expect("resolver depth exceeds the environment chain");
+        }
+        env
+    }
+
+    /// how `print` shows a value.
+    pub fn display(&self, value: &Value) -> String {
+        match value {
+            Value::Literal(Literal::Str(s)) => s.clone(),
+            value => self.repr(value, &mut Vec::new()),
+        }
+    }
+    /// how a debugger shows a value: as inside a list, so that strings are quoted.
+    pub fn inspect(&self, value: &Value) -> String {
+        self.repr(value, &mut Vec::new())
+    }
+    /// how a value shows inside a list or map, where strings are quoted. `enclosing` holds the
+    /// collections being printed, so one that contains itself shows as `[...]` or `{...}` there.
+    fn repr(&self, value: &Value, enclosing: &mut Vec<Gc>) -> String {
+        match value {
+            Value::Literal(l) => l.to_string(),
+            Value::Function(gc) => format!("<fn {}>", self.function(*gc).decl.name.name),
+            Value::Native(native) => format!("<native fn {}>", native.name),
+            Value::Host(object) => format!("{:?}", object),
+            Value::Class(gc) => self.class(*gc).name.clone(),
+            Value::Instance(gc) => {
+                let class = self.class(self.instance(*gc).class);
+                format!("<{} instance>", class.name)
+            }
+            Value::List(gc) if enclosing.contains(gc) => "[...]".to_owned(),
+            Value::List(gc) => {
+                enclosing.push(*gc);
+                let elements: Vec<String> = self
+                    .list(*gc)
+                    .iter()
+                    .map(|v| self.repr(v, enclosing))
+                    .collect();
+                enclosing.pop();
+                format!("[{}]", elements.join(", "))
+            }
+            Value::Map(gc) if enclosing.contains(gc) => "{...}".to_owned(),
+            Value::Map(gc) => {
+                enclosing.push(*gc);
+                let entries: Vec<String> = self
+                    .map(*gc)
+                    .entries()
+                    .map(|(k, v)| format!("{}: {}", k, self.repr(v, enclosing)))
+                    .collect();
+                enclosing.pop();
+                format!("{{{}}}", entries.join(", "))
+            }
+        }
+    }
+}
@@ -0,0 +1,346 @@
+//! Syntax highlighting. Every token the lexer finds, comments included, is classified by its type,
+//! and identifiers further by what the resolver found them to name: a parameter, a local, a global,
+//! a function, a class or a module. Names the resolver does not see are classified by where they
+//! are, e.g. a field after a `.`. The classes can be rendered as ANSI-coloured text for terminals,
+//! as HTML, or as the semantic tokens of the Language Server Protocol.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use crate::compiler::{
+    ast::types::Type,
+    lexer::Lexer,
+    modules::Loader,
+    natives::Natives,
+    resolver::{resolve_symbols, SymbolKind, Symbols},
+    token::{Token, TokenType},
+};
+
+/// What a token is, as far as colouring it goes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TokenClass {
+    Keyword,
+    /// a name nothing more is known of, e.g. an undefined variable.
+    Identifier,
+    Function,
+    Method,
+    Class,
+    /// a type in an annotation or the name of an interface.
+    Type,
+    Module,
+    Parameter,
+    /// a variable declared in a block, a loop variable or a caught exception.
+    Local,
+    /// a top-level `let`.
+    Global,
+    Field,
+    Number,
+    String,
+    Operator,
+    Punctuation,
+    Comment,
+    /// a lexeme that is no token.
+    Invalid,
+}
+
+impl TokenClass {
+    /// as HTML classes it.
+    pub fn name(self) -> &'static str {
+        match self {
+            TokenClass::Keyword => "keyword",
+            TokenClass::Identifier => "identifier",
+            TokenClass::Function => "function",
+            TokenClass::Method => "method",
+            TokenClass::Class => "class",
+            TokenClass::Type => "type",
+            TokenClass::Module => "module",
+            TokenClass::Parameter => "parameter",
+            TokenClass::Local => "local",
+            TokenClass::Global => "global",
+            TokenClass::Field => "field",
+            TokenClass::Number => "number",
+            TokenClass::String => "string",
+            TokenClass::Operator => "operator",
+            TokenClass::Punctuation => "punctuation",
+            TokenClass::Comment => "comment",
+            TokenClass::Invalid => "invalid",
+        }
+    }
+    /// the SGR parameters of its colour, if it has one.
+    fn ansi(self) -> Option<&'static str> {
+        match self {
+            TokenClass::Keyword => Some("35"),
+            TokenClass::Function | TokenClass::Method => Some("34"),
+            TokenClass::Class | TokenClass::Type => Some("33"),
+            TokenClass::Module => Some("36"),
+            TokenClass::Parameter => Some("3"),
+            TokenClass::Global => Some("1"),
+            TokenClass::Field => Some("96"),
+            TokenClass::Number => Some("91"),
+            TokenClass::String => Some("32"),
+            TokenClass::Comment => Some("90"),
+            TokenClass::Invalid => Some("4;31"),
+            TokenClass::Identifier
+            | TokenClass::Local
+            | TokenClass::Operator
+            | TokenClass::Punctuation => None,
+        }
+    }
+    /// its index in `TOKEN_TYPES`, if the protocol has a type for it.
+    pub fn token_type(self) -> Option<usize> {
+        let name = match self {
+            TokenClass::Keyword => "keyword",
+            TokenClass::Identifier | TokenClass::Local | TokenClass::Global => "variable",
+            TokenClass::Function => "function",
+            TokenClass::Method => "method",
+            TokenClass::Class => "class",
+            TokenClass::Type => "type",
+            TokenClass::Module => "namespace",
+            TokenClass::Parameter => "parameter",
+            TokenClass::Field => "property",
+            TokenClass::Number => "number",
+            TokenClass::String => "string",
+            TokenClass::Operator => "operator",
+            TokenClass::Comment => "comment",
+            TokenClass::Punctuation | TokenClass::Invalid => return None,
+        };
+        TOKEN_TYPES.iter().position(|t| *t == name)
+    }
+}
+
+/// the legend of the semantic tokens `semantic_tokens` encodes: the protocol's names of the token
+/// types, indexed by their number.
+pub const TOKEN_TYPES: &[&str] = &[
+    "keyword",
+    "variable",
+    "function",
+    "method",
+    "class",
+    "type",
+    "namespace",
+    "parameter",
+    "property",
+    "number",
+    "string",
+    "operator",
+    "comment",
+];
+
+/// A classified token, at a line and byte column counting from 1, as the lexer has them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+    /// in bytes.
+    pub len: usize,
+    pub class: TokenClass,
+}
+
+/// Classifies the tokens of `source`, the program at `path`, resolving it with the modules it
+/// imports as far as it parses.
+pub fn highlight(
+    source: &str,
+    path: &Path,
+    search_path: &[PathBuf],
+    natives: &Natives,
+) -> Vec<Span> {
+    let (mut modules, _) = Loader::new(search_path.to_vec()).load_partial(source, path);
+    let (_, symbols) = resolve_symbols(&mut modules, natives);
+    classify(source, &symbols, modules.len().saturating_sub(1))
+}
+
+/// Classifies the tokens of `source`, which `symbols` knows as the module `module`.
+pub fn classify(source: &str, symbols: &Symbols, module: usize) -> Vec<Span> {
+    // what each name the resolver saw names, by where it is
+    let mut names = HashMap::new();
+    for decl in &symbols.declarations {
+        if decl.at.module == module {
+            names.insert((decl.at.line, decl.at.column), decl.kind);
+        }
+    }
+    for reference in &symbols.references {
+        if reference.at.module == module {
+            // natives are declared nowhere, and are all functions
+            let kind = match reference.decl {
+                Some(at) => symbols
+                    .declarations
+                    .iter()
+                    .find(|d| d.at == at)
+                    .map_or(SymbolKind::Function, |d| d.kind),
+                None => SymbolKind::Function,
+            };
+            names.insert((reference.at.line, reference.at.column), kind);
+        }
+    }
+
+    let tokens: Vec<Token> = Lexer::from_source(source).with_trivia().collect();
+    // classes and interfaces name types wherever they are used, annotations included
+    let mut types: HashSet<&str> = ["list", "map"].into_iter().collect();
+    for pair in tokens.windows(2) {
+        if matches!(pair[0].token_type, TokenType::Class | TokenType::Interface) {
+            types.insert(&pair[1].lexeme);
+        }
+    }
+    let kind_of = |i: usize| tokens.get(i).map(|t| t.token_type);
+    tokens
+        .iter()
+        .enumerate()
+        .map(|(i, token)| {
+            let class = match token.token_type {
+                TokenType::Identifier => match names.get(&(token.line, token.column)) {
+                    Some(SymbolKind::Function) => TokenClass::Function,
+                    Some(SymbolKind::Class) => TokenClass::Class,
+                    Some(SymbolKind::Global) => TokenClass::Global,
+                    Some(SymbolKind::Local) => TokenClass::Local,
+                    Some(SymbolKind::Parameter) => TokenClass::Parameter,
+                    Some(SymbolKind::Module) => TokenClass::Module,
+                    None => {
+                        let previous = i.checked_sub(1).and_then(kind_of);
+                        let next = kind_of(i + 1);
+                        match (previous, next) {
+                            (Some(TokenType::Dot), Some(TokenType::LParen)) => TokenClass::Method,
+                            (Some(TokenType::Dot), _) => TokenClass::Field,
+                            // the resolver declares functions, but not methods
+                            (Some(TokenType::Fn), _) => TokenClass::Method,
+                            _ if Type::from_name(&token.lexeme).is_some()
+                                || types.contains(token.lexeme.as_str()) =>
+                            {
+                                TokenClass::Type
+                            }
+                            _ => TokenClass::Identifier,
+                        }
+                    }
+                },
+                TokenType::Num => TokenClass::Number,
+                TokenType::Str => TokenClass::String,
+                TokenType::Comment => TokenClass::Comment,
+                TokenType::Invalid => TokenClass::Invalid,
+                TokenType::Minus
+                | TokenType::Plus
+                | TokenType::Slash
+                | TokenType::Star
+                | TokenType::Arrow
+                | TokenType::Bang
+                | TokenType::BangEq
+                | TokenType::Eq
+                | TokenType::EqEq
+                | TokenType::Gt
+                | TokenType::GtEq
+                | TokenType::Lt
+                | TokenType::LtEq => TokenClass::Operator,
+                TokenType::LParen
+                | TokenType::RParen
+                | TokenType::LBrace
+                | TokenType::RBrace
+                | TokenType::LBracket
+                | TokenType::RBracket
+                | TokenType::Comma
+                | TokenType::Colon
+                | TokenType::Dot
+                | TokenType::Semi
+                | TokenType::Eof => TokenClass::Punctuation,
+                _ => TokenClass::Keyword,
+            };
+            Span {
+                line: token.line,
+                column: token.column,
+                len: token.lexeme.len(),
+                class,
+            }
+        })
+        .collect()
+}
+
+/// `source`, with whatever lies between its spans as it is, and each span rendered by `render`.
+fn render(
+    source: &str,
+    spans: &[Span],
+    mut render: impl FnMut(&mut String, &str, TokenClass),
+) -> String {
+    let mut out = String::new();
+    let mut spans = spans.iter().peekable();
+    for (index, line) in source.split_inclusive('\n').enumerate() {
+        let mut rest = 0;
+        while let Some(span) = spans.next_if(|span| span.line == index + 1) {
+            let start = (span.column - 1).clamp(rest, line.len());
+            let end = (start + span.len).min(line.len());
+            out.push_str(&line[rest..start]);
+            render(&mut out, &line[start..end], span.class);
+            rest = end;
+        }
+        out.push_str(&line[rest..]);
+    }
+    out
+}
+
+/// `source` coloured with ANSI escape sequences.
+pub fn ansi(source: &str, spans: &[Span]) -> String {
+    render(source, spans, |out, text, class| match class.ansi() {
+        Some(sgr) => out.push_str(&format!("\x1b[{}m{}\x1b[0m", sgr, text)),
+        None => out.push_str(text),
+    })
+}
+
+/// `source` as a `pre` element, each token in a `span` classed by `TokenClass::name`, for a style
+/// sheet to colour.
+pub fn html(source: &str, spans: &[Span]) -> String {
+    fn escape(out: &mut String, text: &str) {
+        for c in text.chars() {
+            match c {
+                '&' => out.push_str("&amp;"),
+                '<' => out.push_str("&lt;"),
+                '>' => out.push_str("&gt;"),
+                '"' => out.push_str("&quot;"),
+                c => out.push(c),
+            }
+        }
+    }
+    let mut out = String::from("<pre class=\"highlight\"><code>");
+    // the lexer makes a token of anything but whitespace, so only tokens need escaping
+    let body = render(source, spans, |out, text, class| {
+        out.push_str(&format!("<span class=\"{}\">", class.name()));
+        escape(out, text);
+        out.push_str("</span>");
+    });
+    out.push_str(&body);
+    out.push_str("</code></pre>\n");
+    out
+}
+
+/// the spans as the protocol encodes semantic tokens: five numbers each, the line and start
+/// relative to the token before, the length, the index of the type in `TOKEN_TYPES` and no
+/// modifiers. Lines count from 0 and characters in UTF-16 code units.
+pub fn semantic_tokens(source: &str, spans: &[Span]) -> Vec<usize> {
+    let lines: Vec<&str> = source.lines().collect();
+    let utf16 = |text: &str| text.encode_utf16().count();
+    let mut data = Vec::new();
+    let (mut previous_line, mut previous_start) = (0, 0);
+    for span in spans {
+        let Some(token_type) = span.class.token_type() else {
+            continue;
+        };
+        let text = lines.get(span.line - 1).copied().unwrap_or("");
+        let Some(before) = text.get(..span.column - 1) else {
+            continue;
+        };
+        let token = text
+            .get(span.column - 1..span.column - 1 + span.len)
+            .unwrap_or("");
+        let (line, start) = (span.line - 1, utf16(before));
+        let delta_start = match line == previous_line {
+            true => start - previous_start,
+            false => start,
+        };
+        data.extend([
+            line - previous_line,
+            delta_start,
+            utf16(token),
+            token_type,
+            0,
+        ]);
+        (previous_line, previous_start) = (line, start);
+    }
+    data
+}
@@ -0,0 +1,1066 @@
+use std::{collections::HashMap, io::Write, ops::ControlFlow, rc::Rc, time::Instant};
+
+use crate::compiler::{
+    ast::{
+        expr::{BinaryOp, Expr, Identifier, LogicalOp, Slot, UnaryOp},
+        literal::Literal,
+    },
+    debugger::{Debugger, Position, StackFrame},
+    eval::{EvalErr, Frame, RuntimeError},
+    gc::{Gc, Heap},
+    limits::Limits,
+    modules::Module,
+    natives::Natives,
+    profiler::Profiler,
+    statements::stmt::{Block, Catch, ClassDecl, FnDecl, Stmt, StmtKind},
+    value::{Class, Env, Function, Instance, Key, Map, Object, Value},
+};
+
+/// what executing a statement asks of the enclosing code.
+enum Flow {
+    Next,
+    Return(Value),
+}
+
+/// a call in progress, for stack traces.
+struct Call {
+    /// `None` for the top-level code.
+    decl: Option<Rc<FnDecl>>,
+    /// the statement being run.
+    line: usize,
+    /// the module the code is in.
+    module: usize,
+    /// how many environments were being executed when the call started: those after are its own.
+    envs: usize,
+}
+
+/// Tree-walking interpreter over a resolved program. Variables are looked up through the slots the
+/// resolver stored on each `Identifier`, never by name.
+///
+/// Objects live on a garbage-collected `Heap`. A collection can happen whenever an object is
+/// allocated, so every heap value the interpreter still needs must be reachable from a root: the
+/// globals, the environments of the blocks being executed, and `temps`, where expressions park
+/// operands they have evaluated while they evaluate the rest. Environments captured by closures
+/// are reached through the functions holding them.
+///
+/// Every run, and every call into the program from the host, is held to the `Limits`.
+///
+/// A `Debugger` attached to the interpreter is asked before every statement whether to go on, and
+/// may look at the calls in progress and their variables meanwhile. A `Profiler` counts every step
+/// and call.
+///
+/// Errors, thrown values among them, travel up as `Err` until a `try` catches them. The first
+/// statement one leaves records the calls in progress, which is the trace a `RuntimeError` gets if
+/// nothing does.
+pub struct Interpreter {
+    /// `None` until the global's declaration has run.
+    globals: Vec<Option<Value>>,
+    global_names: Vec<String>,
+    out: Box<dyn Write>,
+    heap: Heap,
+    /// environments of the blocks being executed, innermost last.
+    envs: Vec<Gc>,
+    temps: Vec<Value>,
+    limits: Limits,
+    /// statements and expressions evaluated since the run started.
+    steps: u64,
+    /// when the run has to stop by, if it has to.
+    deadline: Option<Instant>,
+    /// calls in progress.
+    depth: usize,
+    /// the address of the host's stack when the run started, to measure how much of it calls take.
+    stack_base: usize,
+    /// calls in progress for stack traces, outermost first. Natives are left out.
+    calls: Vec<Call>,
+    /// where the error on its way up was raised, innermost call first.
+    trace: Option<Vec<Frame>>,
+    debugger: Option<Box<dyn Debugger>>,
+    /// what the values of each environment are called, kept only with a debugger attached: the
+    /// program itself finds its variables by slot.
+    names: HashMap<Gc, Vec<String>>,
+    profiler: Option<Box<Profiler>>,
+}
+
+/// how many steps pass between looks at the clock, which is slow next to a step.
+const CLOCK_INTERVAL: u64 = 1024;
+
+/// roughly where the host's stack is at: the address of a local in the caller's frame. Only the
+/// distance between two is used, whichever way the stack grows.
+#[inline(always)]
+fn stack_address() -> usize {
+    let here = 0u8;
+    std::hint::black_box(&here) as *const u8 as usize
+}
+
+impl Interpreter {
+    /// `global_names` is what `resolver::resolve` returned for the program given `natives`, which
+    /// come first.
+    pub fn new(natives: &Natives, global_names: Vec<String>) -> Self {
+        Self::with_output(natives, global_names, Box::new(std::io::stdout()))
+    }
+    pub fn with_output(natives: &Natives, global_names: Vec<String>, out: Box<dyn Write>) -> Self {
+        let mut globals = vec![None; global_names.len()];
+        for (global, native) in globals.iter_mut().zip(natives.iter()) {
+            *global = Some(Value::Native(native.clone()));
+        }
+        Self {
+            globals,
+            global_names,
+            out,
+            heap: Heap::default(),
+            envs: Vec::new(),
+            temps: Vec::new(),
+            limits: Limits::default(),
+            steps: 0,
+            deadline: None,
+            depth: 0,
+            stack_base: 0,
+            calls: Vec::new(),
+            trace: None,
+            debugger: None,
+            names: HashMap::new(),
+            profiler: None,
+        }
+    }
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+    /// collects garbage on every allocation rather than as the heap grows: slow, but any value
+    /// missing from the roots is freed, and any use of it caught, right away.
+    pub fn gc_stress(mut self, stress: bool) -> Self {
+        self.heap.set_stress(stress);
+        self
+    }
+    pub fn with_debugger(mut self, debugger: Box<dyn Debugger>) -> Self {
+        self.debugger = Some(debugger);
+        self
+    }
+    /// counts what the runs take, see `profiler`.
+    pub fn with_profiler(mut self) -> Self {
+        self.profiler = Some(Box::default());
+        self
+    }
+    /// what the runs so far took, if they were profiled.
+    pub fn profiler(&self) -> Option<&Profiler> {
+        self.profiler.as_deref()
+    }
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+    /// for natives to change the objects they are passed. Allocating is left to `new_list`, which
+    /// keeps everything the interpreter still needs alive, and a native that grows an object
+    /// reports it to `resized`.
+    pub fn heap_mut(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+    pub fn new_list(&mut self, values: Vec<Value>) -> Result<Value, EvalErr> {
+        Ok(Value::List(self.alloc(Object::List(values))?))
+    }
+    /// counts what `object` has grown by in place towards the heap's limit, failing if it is over
+    /// even once the garbage is collected.
+    pub fn resized(&mut self, object: Gc) -> Result<(), EvalErr> {
+        self.heap.resize(object);
+        if self.over_memory(0) {
+            self.collect([object]);
+            if let Some(max) = self.limits.max_heap_bytes.filter(|_| self.over_memory(0)) {
+                return Err(EvalErr::OutOfMemory(max));
+            }
+        }
+        Ok(())
+    }
+    /// the value of a global, or `None` if there is none by that name or its declaration has not
+    /// run.
+    pub fn global(&self, name: &str) -> Option<Value> {
+        let index = self.global_names.iter().position(|g| g == name)?;
+        self.globals[index].clone()
+    }
+    /// whether there is a global by that name to set.
+    pub fn set_global(&mut self, name: &str, value: Value) -> bool {
+        match self.global_names.iter().position(|g| g == name) {
+            Some(index) => {
+                self.globals[index] = Some(value);
+                true
+            }
+            None => false,
+        }
+    }
+    /// calls a function, native or class from outside the program, e.g. one a global holds.
+    pub fn call_value(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, RuntimeError> {
+        self.start();
+        let mark = self.temps.len();
+        self.temps.push(callee.clone());
+        self.temps.extend(args.iter().cloned());
+        let result = self.call(callee, args);
+        self.temps.truncate(mark);
+        result.map_err(|error| {
+            self.envs.clear();
+            self.calls.clear();
+            self.runtime_error(error)
+        })
+    }
+    /// keeps `value` alive until `release`, for hosts building values out of several allocations.
+    pub(crate) fn hold(&mut self, value: Value) {
+        self.temps.push(value);
+    }
+    /// how many values are held, to `release` back to.
+    pub(crate) fn held(&self) -> usize {
+        self.temps.len()
+    }
+    /// the values held since `mark`, which are no longer kept alive.
+    pub(crate) fn release(&mut self, mark: usize) -> Vec<Value> {
+        self.temps.split_off(mark)
+    }
+    pub fn run(&mut self, program: &[Stmt]) -> Result<(), RuntimeError> {
+        self.run_programs([program])
+    }
+    /// runs the modules in the order they were loaded, as `run` would them linked, but knowing
+    /// which module each statement is in.
+    pub fn run_modules(&mut self, modules: &[Module]) -> Result<(), RuntimeError> {
+        self.run_programs(modules.iter().map(|module| module.program.as_slice()))
+    }
+    fn run_programs<'p>(
+        &mut self,
+        programs: impl IntoIterator<Item = &'p [Stmt]>,
+    ) -> Result<(), RuntimeError> {
+        self.start();
+        self.calls.push(Call {
+            decl: None,
+            line: 0,
+            module: 0,
+            envs: 0,
+        });
+        let result = programs
+            .into_iter()
+            .enumerate()
+            .try_for_each(|(module, program)| {
+                self.calls[0].module = module;
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.enter_script(module);
+                }
+                let result = program
+                    .iter()
+                    .try_for_each(|stmt| self.exec(stmt, None).map(|_| ()));
+                if let Some(profiler) = &mut self.profiler {
+                    profiler.finish(self.calls[0].line);
+                }
+                result
+            });
+        // an error leaves whatever was being evaluated behind
+        self.envs.clear();
+        self.calls.clear();
+        let result = result.map_err(|error| self.runtime_error(error));
+        self.temps.clear();
+        result
+    }
+    /// the error with its message and the trace it left.
+    fn runtime_error(&mut self, error: EvalErr) -> RuntimeError {
+        let message = match &error {
+            EvalErr::Thrown(value) => format!("uncaught exception: {}", self.heap.display(value)),
+            error => error.to_string(),
+        };
+        RuntimeError {
+            error,
+            message,
+            trace: self.trace.take().unwrap_or_default(),
+        }
+    }
+    /// the calls in progress, innermost first.
+    fn backtrace(&self) -> Vec<Frame> {
+        let frame = |call: &Call| Frame {
+            function: match &call.decl {
+                Some(decl) => decl.name.name.clone(),
+                None => "<script>".to_owned(),
+            },
+            line: call.line,
+        };
+        self.calls.iter().rev().map(frame).collect()
+    }
+
+    /// the calls in progress, innermost first, for a debugger to show.
+    pub fn stack(&self) -> Vec<StackFrame> {
+        let frame = |call: &Call| StackFrame {
+            function: match &call.decl {
+                Some(decl) => decl.name.name.clone(),
+                None => "<script>".to_owned(),
+            },
+            module: call.module,
+            line: call.line,
+        };
+        self.calls.iter().rev().map(frame).collect()
+    }
+    /// the variables the `frame`th call in progress, counting from the innermost, sees besides the
+    /// globals: those of the innermost block first, and of the closure it was made in last. Only
+    /// known with a debugger attached.
+    pub fn locals(&self, frame: usize) -> Vec<(String, Value)> {
+        let Some(index) = self.calls.len().checked_sub(frame + 1) else {
+            return Vec::new();
+        };
+        let start = self.calls[index].envs;
+        let end = self
+            .calls
+            .get(index + 1)
+            .map_or(self.envs.len(), |call| call.envs);
+        let mut env = self.envs[start..end].last().copied();
+        let mut locals: Vec<(String, Value)> = Vec::new();
+        while let Some(gc) = env {
+            let Env { values, parent } = self.heap.env(gc);
+            let names = self.names.get(&gc).map_or(&[][..], Vec::as_slice);
+            let seen = locals.len();
+            for (name, value) in names.iter().zip(values) {
+                // shadowed by an inner block's
+                if !locals[..seen].iter().any(|(n, _)| n == name) {
+                    locals.push((name.clone(), value.clone()));
+                }
+            }
+            env = *parent;
+        }
+        locals
+    }
+    /// the globals whose declarations have run, natives aside, in the order they were declared.
+    pub fn globals(&self) -> Vec<(String, Value)> {
+        self.global_names
+            .iter()
+            .zip(&self.globals)
+            .filter_map(|(name, value)| match value {
+                Some(Value::Native(_)) | None => None,
+                Some(value) => Some((name.clone(), value.clone())),
+            })
+            .collect()
+    }
+    /// asks the debugger, if there is one, whether to go on to `stmt`. It may look at the
+    /// interpreter meanwhile, but not change it.
+    fn debug(&mut self, stmt: &Stmt) -> Result<(), EvalErr> {
+        let Some(mut debugger) = self.debugger.take() else {
+            return Ok(());
+        };
+        let at = Position {
+            module: self.module(),
+            line: stmt.line,
+            depth: self.calls.len(),
+            nesting: self.envs.len(),
+        };
+        let flow = debugger.statement(self, at);
+        self.debugger = Some(debugger);
+        match flow {
+            ControlFlow::Continue(()) => Ok(()),
+            ControlFlow::Break(()) => Err(EvalErr::Stopped),
+        }
+    }
+    /// records what the next values of `env` are called, if a debugger may ask.
+    fn name<'n>(&mut self, env: Gc, names: impl IntoIterator<Item = &'n str>) {
+        if self.debugger.is_some() {
+            let known = self.names.entry(env).or_default();
+            known.extend(names.into_iter().map(str::to_owned));
+        }
+    }
+
+    /// restarts the limits' count for a run or a call from the host.
+    fn start(&mut self) {
+        self.steps = 0;
+        self.depth = 0;
+        self.stack_base = stack_address();
+        self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        self.trace = None;
+    }
+    /// counts one step against the fuel, and every so often checks the clock.
+    fn step(&mut self) -> Result<(), EvalErr> {
+        self.steps += 1;
+        if let Some(profiler) = &mut self.profiler {
+            profiler.step(self.calls.last().map_or(0, |call| call.line));
+        }
+        if let Some(fuel) = self.limits.fuel.filter(|fuel| self.steps > *fuel) {
+            return Err(EvalErr::OutOfFuel(fuel));
+        }
+        if let (0, Some(deadline), Some(timeout)) = (
+            self.steps % CLOCK_INTERVAL,
+            self.deadline,
+            self.limits.timeout,
+        ) {
+            if Instant::now() >= deadline {
+                return Err(EvalErr::Timeout(timeout));
+            }
+        }
+        Ok(())
+    }
+
+    /// puts `object` on the heap, collecting first if it is time to, or if the object would not
+    /// fit under the limit otherwise.
+    fn alloc(&mut self, object: Object) -> Result<Gc, EvalErr> {
+        let size = object.size();
+        if self.heap.should_collect() || self.over_memory(size) {
+            self.collect(object.references());
+            if let Some(max) = self
+                .limits
+                .max_heap_bytes
+                .filter(|_| self.over_memory(size))
+            {
+                return Err(EvalErr::OutOfMemory(max));
+            }
+        }
+        Ok(self.heap.alloc(object))
+    }
+    /// whether `extra` more bytes would take the heap over its limit.
+    fn over_memory(&self, extra: usize) -> bool {
+        let max = self.limits.max_heap_bytes;
+        max.is_some_and(|max| self.heap.bytes() + extra > max)
+    }
+    /// frees whatever neither the roots nor `extra` reach, e.g. the references of an object about
+    /// to be allocated, which nothing else may hold yet.
+    fn collect(&mut self, extra: impl IntoIterator<Item = Gc>) {
+        let values = || self.globals.iter().flatten().chain(&self.temps);
+        let roots: Vec<Gc> = values()
+            .filter_map(Value::as_gc)
+            .chain(self.envs.iter().copied())
+            .chain(extra)
+            .collect();
+        let held = values().map(Value::string_bytes).sum();
+        self.heap.collect_holding(roots, held);
+    }
+    /// counts a string just made towards the heap's limit, as `alloc` does an object.
+    fn counted(&mut self, value: Value) -> Result<Value, EvalErr> {
+        let size = value.string_bytes();
+        if size == 0 {
+            return Ok(value);
+        }
+        if self.heap.should_collect() || self.over_memory(size) {
+            self.collect([]);
+            if let Some(max) = self
+                .limits
+                .max_heap_bytes
+                .filter(|_| self.over_memory(size))
+            {
+                return Err(EvalErr::OutOfMemory(max));
+            }
+        }
+        self.heap.count(size);
+        Ok(value)
+    }
+    fn new_env(&mut self, parent: Option<Gc>, values: Vec<Value>) -> Result<Gc, EvalErr> {
+        let env = self.alloc(Object::Env(Env { values, parent }))?;
+        // the collector may have freed an environment here before
+        if self.debugger.is_some() {
+            self.names.insert(env, Vec::new());
+        }
+        Ok(env)
+    }
+    /// the module of the innermost call.
+    fn module(&self) -> usize {
+        self.calls.last().map_or(0, |call| call.module)
+    }
+
+    /// runs `stmt` as the current line of the innermost call, which it stays if an error leaves.
+    fn exec(&mut self, stmt: &Stmt, env: Option<Gc>) -> Result<Flow, EvalErr> {
+        let outer = self
+            .calls
+            .last_mut()
+            .map(|call| std::mem::replace(&mut call.line, stmt.line));
+        let flow = self
+            .step()
+            .and_then(|_| self.debug(stmt))
+            .and_then(|_| self.exec_kind(stmt, env));
+        match (&flow, outer) {
+            (Ok(_), Some(line)) => {
+                if let Some(call) = self.calls.last_mut() {
+                    call.line = line;
+                }
+            }
+            (Err(_), _) if self.trace.is_none() => self.trace = Some(self.backtrace()),
+            _ => {}
+        }
+        flow
+    }
+    fn exec_kind(&mut self, stmt: &Stmt, env: Option<Gc>) -> Result<Flow, EvalErr> {
+        match &stmt.kind {
+            StmtKind::Expr(e) => {
+                self.eval(e, env)?;
+            }
+            StmtKind::Print(e) => {
+                let value = self.eval(e, env)?;
+                let text = self.heap.display(&value);
+                writeln!(self.out, "{}", text).expect("failed to write program output");
+            }
+            StmtKind::Let { name, init, .. } => {
+                let value = match init {
+                    Some(init) => self.eval(init, env)?,
+                    None => Value::NIL,
+                };
+                self.define(name, value, env)?;
+            }
+            StmtKind::Block(block) => {
+                let inner = self.new_env(env, Vec::new())?;
+                return self.exec_block(&block.stmts, inner);
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                if self.eval(cond, env)?.is_truthy() {
+                    let inner = self.new_env(env, Vec::new())?;
+                    return self.exec_block(&then_branch.stmts, inner);
+                } else if let Some(else_branch) = else_branch {
+                    return self.exec(else_branch, env);
+                }
+            }
+            StmtKind::While { cond, body } => {
+                while self.eval(cond, env)?.is_truthy() {
+                    let inner = self.new_env(env, Vec::new())?;
+                    if let Flow::Return(v) = self.exec_block(&body.stmts, inner)? {
+                        return Ok(Flow::Return(v));
+                    }
+                }
+            }
+            StmtKind::For {
+                name,
+                iterable,
+                body,
+            } => {
+                let iterable = self.eval(iterable, env)?;
+                self.temps.push(iterable.clone());
+                let flow = self.exec_for(&iterable, &name.name, &body.stmts, env);
+                self.temps.pop();
+                return flow;
+            }
+            StmtKind::Fn(decl) => {
+                let function = self.alloc(Object::Function(Function {
+                    decl: decl.clone(),
+                    closure: env,
+                    is_initializer: false,
+                    module: self.module(),
+                }))?;
+                self.define(&decl.name, Value::Function(function), env)?;
+            }
+            StmtKind::Return(value) => {
+                let value = match value {
+                    Some(v) => self.eval(v, env)?,
+                    None => Value::NIL,
+                };
+                return Ok(Flow::Return(value));
+            }
+            StmtKind::Throw(value) => return Err(EvalErr::Thrown(self.eval(value, env)?)),
+            StmtKind::Try {
+                body,
+                catch,
+                finally,
+            } => return self.exec_try(body, catch.as_ref(), finally.as_ref(), env),
+            StmtKind::Class(decl) => {
+                let class = self.class(decl, env)?;
+                self.define(&decl.name, Value::Class(class), env)?;
+            }
+            // type parameters and interfaces are erased: only the checker sees them
+            StmtKind::Interface(_) => {}
+            // the resolver points uses of the module at its globals, which ran before this module
+            StmtKind::Import { .. } => {}
+        }
+        Ok(Flow::Next)
+    }
+    fn exec_block(&mut self, stmts: &[Stmt], env: Gc) -> Result<Flow, EvalErr> {
+        self.envs.push(env);
+        let mut flow = Ok(Flow::Next);
+        for stmt in stmts {
+            flow = self.exec(stmt, Some(env));
+            if !matches!(flow, Ok(Flow::Next)) {
+                break;
+            }
+        }
+        self.envs.pop();
+        flow
+    }
+    /// runs `body`, then `catch` with what it raised if it raised anything the program may catch,
+    /// then `finally`, unless a limit was hit. A `return` or an error from `finally` replaces the
+    /// outcome of the rest, as does one from `catch`.
+    fn exec_try(
+        &mut self,
+        body: &Block,
+        catch: Option<&Catch>,
+        finally: Option<&Block>,
+        env: Option<Gc>,
+    ) -> Result<Flow, EvalErr> {
+        let (temps, envs, calls) = (self.temps.len(), self.envs.len(), self.calls.len());
+        let inner = self.new_env(env, Vec::new())?;
+        let mut flow = self.exec_block(&body.stmts, inner);
+        if let Some(catch) = catch {
+            flow = match flow {
+                Err(error) if !error.is_limit() => {
+                    self.unwind(temps, envs, calls);
+                    self.trace = None;
+                    let exception = match error {
+                        EvalErr::Thrown(value) => value,
+                        error => Value::Literal(Literal::Str(error.to_string())),
+                    };
+                    self.new_env(env, vec![exception]).and_then(|inner| {
+                        self.name(inner, [catch.name.name.as_str()]);
+                        self.exec_block(&catch.body.stmts, inner)
+                    })
+                }
+                flow => flow,
+            };
+        }
+        let Some(finally) = finally else {
+            return flow;
+        };
+        if matches!(&flow, Err(error) if error.is_limit()) {
+            return flow;
+        }
+        self.unwind(temps, envs, calls);
+        // the value on its way out has to outlive whatever `finally` allocates
+        if let Ok(Flow::Return(value)) | Err(EvalErr::Thrown(value)) = &flow {
+            self.temps.push(value.clone());
+        }
+        let trace = self.trace.take();
+        let outcome = self
+            .new_env(env, Vec::new())
+            .and_then(|inner| self.exec_block(&finally.stmts, inner));
+        self.temps.truncate(temps);
+        match outcome {
+            Ok(Flow::Next) => {
+                self.trace = trace;
+                flow
+            }
+            outcome => outcome,
+        }
+    }
+    /// drops what the evaluation an error cut short left behind.
+    fn unwind(&mut self, temps: usize, envs: usize, calls: usize) {
+        self.temps.truncate(temps);
+        self.envs.truncate(envs);
+        self.calls.truncate(calls);
+    }
+    /// runs `stmts` once per element of a list or key of a map, each time in a new environment
+    /// holding it. The length is looked at before every iteration, so the body may change it.
+    fn exec_for(
+        &mut self,
+        iterable: &Value,
+        name: &str,
+        stmts: &[Stmt],
+        env: Option<Gc>,
+    ) -> Result<Flow, EvalErr> {
+        for position in 0.. {
+            let element = match iterable {
+                Value::List(gc) => self.heap.list(*gc).get(position).cloned(),
+                Value::Map(gc) => self.heap.map(*gc).key(position).cloned().map(Value::from),
+                _ => return Err(EvalErr::NotIterable),
+            };
+            let Some(element) = element else {
+                break;
+            };
+            let inner = self.new_env(env, vec![element])?;
+            self.name(inner, [name]);
+            if let Flow::Return(v) = self.exec_block(stmts, inner)? {
+                return Ok(Flow::Return(v));
+            }
+        }
+        Ok(Flow::Next)
+    }
+    fn class(&mut self, decl: &ClassDecl, env: Option<Gc>) -> Result<Gc, EvalErr> {
+        // methods made so far are only held here until the class is
+        let mark = self.temps.len();
+        let mut methods = HashMap::new();
+        for method in &decl.methods {
+            let function = self.alloc(Object::Function(Function {
+                decl: method.clone(),
+                closure: env,
+                is_initializer: method.name.name == Class::INITIALIZER,
+                module: self.module(),
+            }));
+            let Ok(function) = function else {
+                self.temps.truncate(mark);
+                return function;
+            };
+            self.temps.push(Value::Function(function));
+            methods.insert(method.name.name.clone(), function);
+        }
+        let class = self.alloc(Object::Class(Class {
+            name: decl.name.name.clone(),
+            methods,
+        }));
+        self.temps.truncate(mark);
+        class
+    }
+
+    fn define(&mut self, name: &Identifier, value: Value, env: Option<Gc>) -> Result<(), EvalErr> {
+        match (name.slot, env) {
+            (Some(Slot::Global(index)), _) => self.globals[index] = Some(value),
+            (Some(Slot::Local { .. }), Some(env)) => {
+                self.heap.env_mut(env).values.push(value);
+                self.name(env, [name.name.as_str()]);
+                self.resized(env)?;
+            }
+            _ => panic!("'{}' was not resolved before running", name.name),
+        }
+        Ok(())
+    }
+    fn lookup(&self, name: &Identifier, env: Option<Gc>) -> Result<Value, EvalErr> {
+        match (name.slot, env) {
+            (Some(Slot::Global(index)), _) => match &self.globals[index] {
+                Some(v) => Ok(v.clone()),
+                None => Err(EvalErr::UndefinedVariable(self.global_names[index].clone())),
+            },
+            (Some(Slot::Local { depth, index }), Some(env)) => {
+                let env = self.heap.ancestor(env, depth);
+                Ok(self.heap.env(env).values[index].clone())
+            }
+            _ => Err(EvalErr::UndefinedVariable(name.name.clone())),
+        }
+    }
+    fn assign(&mut self, name: &Identifier, value: Value, env: Option<Gc>) -> Result<(), EvalErr> {
+        match (name.slot, env) {
+            (Some(Slot::Global(index)), _) => match &mut self.globals[index] {
+                Some(v) => *v = value,
+                None => return Err(EvalErr::UndefinedVariable(name.name.clone())),
+            },
+            (Some(Slot::Local { depth, index }), Some(env)) => {
+                let env = self.heap.ancestor(env, depth);
+                self.heap.env_mut(env).values[index] = value
+            }
+            _ => return Err(EvalErr::UndefinedVariable(name.name.clone())),
+        }
+        Ok(())
+    }
+
+    /// evaluates `expr` with `held` kept alive meanwhile.
+    fn eval_holding(
+        &mut self,
+        held: Value,
+        expr: &Expr,
+        env: Option<Gc>,
+    ) -> Result<Value, EvalErr> {
+        self.temps.push(held);
+        let result = self.eval(expr, env);
+        self.temps.pop();
+        result
+    }
+
+    /// evaluates `exprs` in order onto `temps`, which keeps them alive until the caller truncates
+    /// it again. Stops at the first error.
+    fn eval_onto_temps<'e>(
+        &mut self,
+        exprs: impl IntoIterator<Item = &'e Expr>,
+        env: Option<Gc>,
+    ) -> Result<(), EvalErr> {
+        for expr in exprs {
+            let value = self.eval(expr, env)?;
+            self.temps.push(value);
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self, expr: &Expr, env: Option<Gc>) -> Result<Value, EvalErr> {
+        self.step()?;
+        match expr {
+            Expr::LiteralExpr(l) => Ok(Value::Literal(l.clone())),
+            Expr::Grouping(inner) => self.eval(inner, env),
+            Expr::Unary { op, rhs } => {
+                let rhs = match self.eval(rhs, env)? {
+                    Value::Literal(l) => l,
+                    _ => {
+                        return Err(match op {
+                            UnaryOp::Bang => EvalErr::InvalidBang,
+                            UnaryOp::Negate => EvalErr::InvalidNegate,
+                        })
+                    }
+                };
+                let result = match op {
+                    UnaryOp::Bang => !rhs,
+                    UnaryOp::Negate => -rhs,
+                };
+                result.map(Value::Literal)
+            }
+            Expr::Binary { lhs, op, rhs } => {
+                let lhs = self.eval(lhs, env)?;
+                let rhs = self.eval_holding(lhs.clone(), rhs, env)?;
+                let result = binary(lhs, op, rhs)?;
+                self.counted(result)
+            }
+            Expr::Logical { lhs, op, rhs } => {
+                let lhs = self.eval(lhs, env)?;
+                let short_circuit = match op {
+                    LogicalOp::And => !lhs.is_truthy(),
+                    LogicalOp::Or => lhs.is_truthy(),
+                };
+                if short_circuit {
+                    Ok(lhs)
+                } else {
+                    self.eval(rhs, env)
+                }
+            }
+            Expr::Variable(name) => self.lookup(name, env),
+            Expr::This(keyword) => self.lookup(keyword, env),
+            Expr::Assign { name, value } => {
+                let value = self.eval(value, env)?;
+                self.assign(name, value.clone(), env)?;
+                Ok(value)
+            }
+            Expr::Call { callee, args, .. } => {
+                let callee = self.eval(callee, env)?;
+                let mark = self.temps.len();
+                self.temps.push(callee.clone());
+                let result = self.eval_onto_temps(args, env).and_then(|()| {
+                    let values = self.temps[mark + 1..].to_vec();
+                    self.call(callee, values)
+                });
+                self.temps.truncate(mark);
+                result
+            }
+            Expr::Get { object, name } => match self.eval(object, env)? {
+                Value::Instance(instance) => self.get_property(instance, &name.name),
+                _ => Err(EvalErr::InvalidGet),
+            },
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                let object = self.eval(object, env)?;
+                let Value::Instance(instance) = object else {
+                    return Err(EvalErr::InvalidSet);
+                };
+                let value = self.eval_holding(object, value, env)?;
+                self.heap
+                    .instance_mut(instance)
+                    .fields
+                    .insert(name.name.clone(), value.clone());
+                self.resized(instance)?;
+                Ok(value)
+            }
+            Expr::List(elements) => {
+                let mark = self.temps.len();
+                let result = self.eval_onto_temps(elements, env).and_then(|()| {
+                    let values = self.temps[mark..].to_vec();
+                    self.new_list(values)
+                });
+                self.temps.truncate(mark);
+                result
+            }
+            Expr::Map(entries) => {
+                let mark = self.temps.len();
+                let pairs = entries.iter().flat_map(|(key, value)| [key, value]);
+                let result = self.eval_onto_temps(pairs, env).and_then(|()| {
+                    let mut map = Map::default();
+                    for pair in self.temps[mark..].chunks(2) {
+                        let key = Key::try_from(&pair[0]).map_err(|()| EvalErr::InvalidKey)?;
+                        map.insert(key, pair[1].clone());
+                    }
+                    Ok(Value::Map(self.alloc(Object::Map(map))?))
+                });
+                self.temps.truncate(mark);
+                result
+            }
+            Expr::Index { object, index, .. } => {
+                let object = self.eval(object, env)?;
+                let index = self.eval_holding(object.clone(), index, env)?;
+                self.index(&object, &index)
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                let mark = self.temps.len();
+                let result = self.eval_onto_temps([&**object, &**index, &**value], env);
+                let result = result.and_then(|()| {
+                    let [object, index, value] = &self.temps[mark..] else {
+                        unreachable!("three values were evaluated");
+                    };
+                    let (object, index, value) = (object.clone(), index.clone(), value.clone());
+                    self.set_index(&object, &index, value.clone())?;
+                    Ok(value)
+                });
+                self.temps.truncate(mark);
+                result
+            }
+        }
+    }
+
+    /// `object[index]`.
+    fn index(&self, object: &Value, index: &Value) -> Result<Value, EvalErr> {
+        match object {
+            Value::List(list) => {
+                let list = self.heap.list(*list);
+                Ok(list[list_position(index, list.len())?].clone())
+            }
+            Value::Map(map) => {
+                let key = Key::try_from(index).map_err(|()| EvalErr::InvalidKey)?;
+                match self.heap.map(*map).get(&key) {
+                    Some(value) => Ok(value.clone()),
+                    None => Err(EvalErr::MissingKey(key.to_string())),
+                }
+            }
+            _ => Err(EvalErr::NotIndexable),
+        }
+    }
+    /// `object[index] = value`. Lists only have the positions they have, maps get a new entry.
+    fn set_index(&mut self, object: &Value, index: &Value, value: Value) -> Result<(), EvalErr> {
+        match object {
+            Value::List(list) => {
+                let position = list_position(index, self.heap.list(*list).len())?;
+                self.heap.list_mut(*list)[position] = value;
+            }
+            Value::Map(map) => {
+                let key = Key::try_from(index).map_err(|()| EvalErr::InvalidKey)?;
+                self.heap.map_mut(*map).insert(key, value);
+                self.resized(*map)?;
+            }
+            _ => return Err(EvalErr::NotIndexable),
+        }
+        Ok(())
+    }
+
+    fn call(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, EvalErr> {
+        match callee {
+            Value::Function(function) => self.call_function(function, args),
+            Value::Native(native) => {
+                let result = native.call(self, &args)?;
+                self.counted(result)
+            }
+            Value::Class(class) => {
+                let instance = self.alloc(Object::Instance(Instance {
+                    class,
+                    fields: HashMap::new(),
+                }))?;
+                match self.heap.class(class).methods.get(Class::INITIALIZER) {
+                    Some(init) => {
+                        let init = self.bind(*init, instance)?;
+                        self.temps.push(Value::Function(init));
+                        let result = self.call_function(init, args);
+                        self.temps.pop();
+                        result?;
+                    }
+                    None if !args.is_empty() => {
+                        return Err(EvalErr::InvalidArity(0, args.len()));
+                    }
+                    None => {}
+                }
+                Ok(Value::Instance(instance))
+            }
+            _ => Err(EvalErr::InvalidCall),
+        }
+    }
+    fn call_function(&mut self, function: Gc, args: Vec<Value>) -> Result<Value, EvalErr> {
+        let Function {
+            decl,
+            closure,
+            is_initializer,
+            module,
+        } = self.heap.function(function);
+        let (decl, closure, is_initializer, module) =
+            (decl.clone(), *closure, *is_initializer, *module);
+        if decl.params.len() != args.len() {
+            return Err(EvalErr::InvalidArity(decl.params.len(), args.len()));
+        }
+
+        let stack = self.stack_base.abs_diff(stack_address());
+        if self.depth == self.limits.max_call_depth
+            || self.limits.stack_bytes.is_some_and(|max| stack > max)
+        {
+            return Err(EvalErr::StackOverflow(self.depth));
+        }
+        self.depth += 1;
+        self.calls.push(Call {
+            decl: Some(decl.clone()),
+            line: decl.name.line,
+            module,
+            envs: self.envs.len(),
+        });
+        if let Some(profiler) = &mut self.profiler {
+            profiler.enter(&decl, module);
+        }
+        let flow = self.new_env(closure, args).and_then(|env| {
+            let params = decl.params.iter().map(|param| param.name.name.as_str());
+            self.name(env, params);
+            self.exec_block(&decl.body.stmts, env)
+        });
+        self.calls.pop();
+        if let Some(profiler) = &mut self.profiler {
+            profiler.exit();
+        }
+        self.depth -= 1;
+        let flow = flow?;
+
+        if is_initializer {
+            // `init` always hands back the instance, which bind() put in the closure's slot 0.
+            let this = closure.map(|c| self.heap.env(c).values[0].clone());
+            return Ok(this.unwrap_or(Value::NIL));
+        }
+        match flow {
+            Flow::Return(v) => Ok(v),
+            Flow::Next => Ok(Value::NIL),
+        }
+    }
+
+    /// a copy of `method` whose closure has `this` in slot 0.
+    fn bind(&mut self, method: Gc, instance: Gc) -> Result<Gc, EvalErr> {
+        let method = self.heap.function(method);
+        let (decl, closure, is_initializer, module) = (
+            method.decl.clone(),
+            method.closure,
+            method.is_initializer,
+            method.module,
+        );
+        let env = self.new_env(closure, vec![Value::Instance(instance)])?;
+        self.name(env, ["this"]);
+        self.alloc(Object::Function(Function {
+            decl,
+            closure: Some(env),
+            is_initializer,
+            module,
+        }))
+    }
+
+    fn get_property(&mut self, instance: Gc, name: &str) -> Result<Value, EvalErr> {
+        let object = self.heap.instance(instance);
+        if let Some(v) = object.fields.get(name) {
+            return Ok(v.clone());
+        }
+        match self.heap.class(object.class).methods.get(name) {
+            Some(method) => Ok(Value::Function(self.bind(*method, instance)?)),
+            None => Err(EvalErr::InvalidProperty(name.to_owned())),
+        }
+    }
+}
+
+/// the position an index refers to in a list of `len` elements.
+fn list_position(index: &Value, len: usize) -> Result<usize, EvalErr> {
+    let Value::Literal(Literal::Int(index)) = index else {
+        return Err(EvalErr::InvalidIndex);
+    };
+    match usize::try_from(*index) {
+        Ok(position) if position < len => Ok(position),
+        _ => Err(EvalErr::IndexOutOfBounds(*index, len)),
+    }
+}
+
+/// applies a binary operator using the `Literal` operator impls.
+fn binary(lhs: Value, op: &BinaryOp, rhs: Value) -> Result<Value, EvalErr> {
+    match op {
+        BinaryOp::EqEq => return Ok(Value::Literal(Literal::Bool(lhs == rhs))),
+        BinaryOp::BangEq => return Ok(Value::Literal(Literal::Bool(lhs != rhs))),
+        _ => {}
+    }
+
+    let (Value::Literal(l), Value::Literal(r)) = (lhs, rhs) else {
+        return Err(match op {
+            BinaryOp::Plus => EvalErr::InvalidAdd,
+            BinaryOp::Minus => EvalErr::InvalidSub,
+            BinaryOp::Mult => EvalErr::InvalidMul,
+            BinaryOp::Div => EvalErr::InvalidDiv,
+            _ => EvalErr::InvalidCompare,
+        });
+    };
+    let result = match op {
+        BinaryOp::Plus => l + r,
+        BinaryOp::Minus => l - r,
+        BinaryOp::Mult => l * r,
+        BinaryOp::Div => l / r,
+        BinaryOp::Gt => Ok(Literal::Bool(l > r)),
+        BinaryOp::Lt => Ok(Literal::Bool(l < r)),
+        BinaryOp::GtEq => Ok(Literal::Bool(l >= r)),
+        BinaryOp::LtEq => Ok(Literal::Bool(l <= r)),
+        BinaryOp::Eq | BinaryOp::EqEq | BinaryOp::BangEq => {
+            unreachable!("assignment and equality are handled above")
+        }
+    };
+    result.map(Value::Literal)
+}
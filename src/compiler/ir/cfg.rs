@@ -0,0 +1,259 @@
+use std::collections::HashMap;
+
+use crate::compiler::{
+    ast::literal::Literal,
+    ir::{BlockId, Function, Op, Terminator, ValueId},
+};
+
+/// the blocks reachable from the entry, each after all of its predecessors except along back edges.
+pub fn reverse_postorder(func: &Function) -> Vec<BlockId> {
+    let mut visited = vec![false; func.blocks.len()];
+    let mut postorder = Vec::new();
+    // (block, whether its successors have been pushed)
+    let mut stack = vec![(BlockId(0), false)];
+    while let Some((block, expanded)) = stack.pop() {
+        if expanded {
+            postorder.push(block);
+            continue;
+        }
+        if visited[block.0] {
+            continue;
+        }
+        visited[block.0] = true;
+        stack.push((block, true));
+        // pushed in order so the first successor finishes last and comes first, `then` before
+        // `else`
+        for succ in func.blocks[block.0].term.successors() {
+            if !visited[succ.0] {
+                stack.push((succ, false));
+            }
+        }
+    }
+    postorder.reverse();
+    postorder
+}
+
+/// the immediate dominator of every reachable block, `None` for the entry and unreachable blocks.
+/// This is the iterative algorithm of Cooper, Harvey and Kennedy.
+pub fn dominators(func: &Function) -> Vec<Option<BlockId>> {
+    let order = reverse_postorder(func);
+    let mut position = vec![usize::MAX; func.blocks.len()];
+    for (i, block) in order.iter().enumerate() {
+        position[block.0] = i;
+    }
+    let mut idom: Vec<Option<BlockId>> = vec![None; func.blocks.len()];
+    idom[0] = Some(BlockId(0));
+
+    let intersect = |idom: &[Option<BlockId>], mut a: BlockId, mut b: BlockId| {
+        while a != b {
+            while position[a.0] > position[b.0] {
+                a = idom[a.0].expect("processed blocks have a dominator");
+            }
+            while position[b.0] > position[a.0] {
+                b = idom[b.0].expect("processed blocks have a dominator");
+            }
+        }
+        a
+    };
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for block in order.iter().skip(1) {
+            let mut new_idom = None;
+            for pred in &func.blocks[block.0].preds {
+                if idom[pred.0].is_none() {
+                    continue;
+                }
+                new_idom = Some(match new_idom {
+                    None => *pred,
+                    Some(current) => intersect(&idom, *pred, current),
+                });
+            }
+            if new_idom.is_some() && idom[block.0] != new_idom {
+                idom[block.0] = new_idom;
+                changed = true;
+            }
+        }
+    }
+    idom[0] = None;
+    idom
+}
+
+/// drops the blocks control can never reach and renumbers the rest in reverse postorder.
+pub fn remove_unreachable(func: &mut Function) {
+    let order = reverse_postorder(func);
+    let mut renumbered = HashMap::new();
+    for (i, block) in order.iter().enumerate() {
+        renumbered.insert(*block, BlockId(i));
+    }
+
+    let mut old_blocks = std::mem::take(&mut func.blocks);
+    for old in &order {
+        let mut block = std::mem::replace(
+            &mut old_blocks[old.0],
+            super::Block {
+                insts: Vec::new(),
+                term: Terminator::Unreachable,
+                preds: Vec::new(),
+            },
+        );
+        block.preds = block
+            .preds
+            .iter()
+            .filter_map(|p| renumbered.get(p).copied())
+            .collect();
+        match &mut block.term {
+            Terminator::Jump(target) => *target = renumbered[target],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => {
+                *then = renumbered[then];
+                *otherwise = renumbered[otherwise];
+            }
+            Terminator::Try { body, handler } => {
+                *body = renumbered[body];
+                *handler = renumbered[handler];
+            }
+            Terminator::Return(_) | Terminator::Throw(_) | Terminator::Unreachable => {}
+        }
+        for value in &block.insts {
+            if let Op::Phi(incoming) = &mut func.insts[value.0] {
+                incoming.retain(|(pred, _)| renumbered.contains_key(pred));
+                for (pred, _) in incoming.iter_mut() {
+                    *pred = renumbered[pred];
+                }
+            }
+        }
+        func.blocks.push(block);
+    }
+    simplify_phis(func);
+}
+
+/// merges every block into its predecessor where that is the only one and only jumps to it, as
+/// folding branches leaves chains of such blocks behind. Unreachable blocks must be gone already,
+/// since they still count as predecessors.
+pub fn merge_blocks(func: &mut Function) {
+    for b in 1..func.blocks.len() {
+        let block = BlockId(b);
+        let [pred] = func.blocks[b].preds[..] else {
+            continue;
+        };
+        if pred == block || func.blocks[pred.0].term != Terminator::Jump(block) {
+            continue;
+        }
+        let merged = std::mem::replace(
+            &mut func.blocks[b],
+            super::Block {
+                insts: Vec::new(),
+                term: Terminator::Unreachable,
+                preds: Vec::new(),
+            },
+        );
+        for value in &merged.insts {
+            // a `phi` of a block with one predecessor has one operand
+            if let Op::Phi(incoming) = func.op(*value) {
+                let (_, operand) = incoming[0];
+                func.replace_uses(*value, operand);
+            } else {
+                func.blocks[pred.0].insts.push(*value);
+            }
+        }
+        for succ in merged.term.successors() {
+            for p in &mut func.blocks[succ.0].preds {
+                if *p == block {
+                    *p = pred;
+                }
+            }
+            for value in func.blocks[succ.0].insts.clone() {
+                if let Op::Phi(incoming) = &mut func.insts[value.0] {
+                    for (p, _) in incoming.iter_mut() {
+                        if *p == block {
+                            *p = pred;
+                        }
+                    }
+                }
+            }
+        }
+        func.blocks[pred.0].term = merged.term;
+    }
+    remove_unreachable(func);
+}
+
+/// forgets one edge from `from` to `to` in `to`'s predecessors and `phi`s, once the terminator of
+/// `from` no longer takes it.
+pub fn remove_edge(func: &mut Function, from: BlockId, to: BlockId) {
+    let block = &mut func.blocks[to.0];
+    if let Some(i) = block.preds.iter().position(|p| *p == from) {
+        block.preds.remove(i);
+    }
+    for value in &block.insts {
+        if let Op::Phi(incoming) = &mut func.insts[value.0] {
+            if let Some(i) = incoming.iter().position(|(p, _)| *p == from) {
+                incoming.remove(i);
+            }
+        }
+    }
+}
+
+/// removes `phi`s that merge only one value besides themselves, which SSA construction leaves
+/// behind wherever a variable is read in a loop that does not change it.
+pub fn simplify_phis(func: &mut Function) {
+    loop {
+        let mut trivial = None;
+        'search: for (b, block) in func.blocks.iter().enumerate() {
+            for value in &block.insts {
+                let Op::Phi(incoming) = func.op(*value) else {
+                    continue;
+                };
+                let mut same = None;
+                let mut unique = true;
+                for (_, operand) in incoming {
+                    if Some(*operand) == same || operand == value {
+                        continue;
+                    }
+                    if same.is_some() {
+                        unique = false;
+                        break;
+                    }
+                    same = Some(*operand);
+                }
+                if unique {
+                    trivial = Some((BlockId(b), *value, same));
+                    break 'search;
+                }
+            }
+        }
+        let Some((block, phi, same)) = trivial else {
+            return;
+        };
+        let same = match same {
+            Some(same) => same,
+            // no incoming value at all: the block is never entered, or the variable never set
+            None => {
+                func.insts.push(Op::Const(Literal::Nil));
+                let nil = ValueId(func.insts.len() - 1);
+                func.blocks[0].insts.insert(0, nil);
+                nil
+            }
+        };
+        func.blocks[block.0].insts.retain(|v| *v != phi);
+        func.replace_uses(phi, same);
+    }
+}
+
+/// how many times each value is used by a live instruction or a terminator.
+pub fn use_counts(func: &Function) -> HashMap<ValueId, usize> {
+    let mut counts = HashMap::new();
+    for block in &func.blocks {
+        for value in &block.insts {
+            for operand in func.op(*value).operands() {
+                *counts.entry(operand).or_insert(0) += 1;
+            }
+        }
+        let mut term = block.term.clone();
+        for operand in term.operands_mut() {
+            *counts.entry(*operand).or_insert(0) += 1;
+        }
+    }
+    counts
+}
@@ -0,0 +1,182 @@
+//! Which values are live where, for backends that keep values in registers. Blocks are laid out in
+//! index order and every instruction gets a position in that order, so a value's lifetime can be
+//! summarised as one interval of positions.
+
+use std::collections::HashSet;
+
+use crate::compiler::ir::{Function, Op, ValueId};
+
+/// the values live on entry to and exit from every block. A `phi` is defined at the start of its
+/// block, and its operands are live out of the predecessor they come from rather than into the
+/// `phi`'s block.
+#[derive(Debug)]
+pub struct Liveness {
+    pub live_in: Vec<HashSet<ValueId>>,
+    pub live_out: Vec<HashSet<ValueId>>,
+}
+
+pub fn liveness(func: &Function) -> Liveness {
+    let n = func.blocks.len();
+    // upward-exposed uses, and definitions, of each block
+    let mut uses = vec![HashSet::new(); n];
+    let mut defs = vec![HashSet::new(); n];
+    for (b, block) in func.blocks.iter().enumerate() {
+        for value in &block.insts {
+            let op = func.op(*value);
+            if !matches!(op, Op::Phi(_)) {
+                for operand in op.operands() {
+                    if !defs[b].contains(&operand) {
+                        uses[b].insert(operand);
+                    }
+                }
+            }
+            defs[b].insert(*value);
+        }
+        for operand in block.term.operands() {
+            if !defs[b].contains(&operand) {
+                uses[b].insert(operand);
+            }
+        }
+    }
+
+    let mut live_in: Vec<HashSet<ValueId>> = vec![HashSet::new(); n];
+    let mut live_out: Vec<HashSet<ValueId>> = vec![HashSet::new(); n];
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for b in (0..n).rev() {
+            let mut out = HashSet::new();
+            for succ in func.blocks[b].term.successors() {
+                out.extend(&live_in[succ.0]);
+                for value in &func.blocks[succ.0].insts {
+                    let Op::Phi(incoming) = func.op(*value) else {
+                        break;
+                    };
+                    out.extend(
+                        incoming
+                            .iter()
+                            .filter(|(pred, _)| pred.0 == b)
+                            .map(|(_, v)| *v),
+                    );
+                }
+            }
+            let mut inn = uses[b].clone();
+            inn.extend(out.difference(&defs[b]));
+            if out != live_out[b] || inn != live_in[b] {
+                live_out[b] = out;
+                live_in[b] = inn;
+                changed = true;
+            }
+        }
+    }
+    Liveness { live_in, live_out }
+}
+
+/// Positions of the blocks, instructions and terminators of a function, two apart so there is room
+/// between any two. A block's position is where its `phi`s are defined.
+#[derive(Debug)]
+pub struct Positions {
+    pub blocks: Vec<usize>,
+    /// indexed by `ValueId`; instructions no block lists have none.
+    pub insts: Vec<Option<usize>>,
+    pub terms: Vec<usize>,
+}
+
+impl Positions {
+    pub fn new(func: &Function) -> Self {
+        let mut positions = Positions {
+            blocks: Vec::new(),
+            insts: vec![None; func.insts.len()],
+            terms: Vec::new(),
+        };
+        let mut next = 0;
+        for block in &func.blocks {
+            positions.blocks.push(next);
+            next += 2;
+            for value in &block.insts {
+                if matches!(func.op(*value), Op::Phi(_)) {
+                    positions.insts[value.0] = Some(positions.blocks.last().copied().unwrap());
+                } else {
+                    positions.insts[value.0] = Some(next);
+                    next += 2;
+                }
+            }
+            positions.terms.push(next);
+            next += 2;
+        }
+        positions
+    }
+    pub fn inst(&self, value: ValueId) -> usize {
+        self.insts[value.0].expect("the instruction is listed in a block")
+    }
+}
+
+/// the positions from a value's definition to its last use, both included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Interval {
+    pub value: ValueId,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Interval {
+    pub fn overlaps(&self, other: &Interval) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+}
+
+/// The live interval of every value, ordered by start. An interval spans every block the value is
+/// live through, which overestimates lifetimes with holes in them but is always safe. A `phi` is
+/// assigned at the end of each predecessor, so its interval covers those too.
+pub fn intervals(func: &Function, positions: &Positions) -> Vec<Interval> {
+    let liveness = liveness(func);
+    let mut ranges: Vec<Option<(usize, usize)>> = vec![None; func.insts.len()];
+    let mut cover = |value: ValueId, at: usize| {
+        let range = ranges[value.0].get_or_insert((at, at));
+        range.0 = range.0.min(at);
+        range.1 = range.1.max(at);
+    };
+    for (b, block) in func.blocks.iter().enumerate() {
+        for value in &block.insts {
+            let op = func.op(*value);
+            if op.has_value() {
+                cover(*value, positions.inst(*value));
+            }
+            match op {
+                Op::Phi(incoming) => {
+                    for (pred, operand) in incoming {
+                        cover(*value, positions.terms[pred.0]);
+                        cover(*operand, positions.terms[pred.0]);
+                    }
+                }
+                _ => {
+                    for operand in op.operands() {
+                        cover(operand, positions.inst(*value));
+                    }
+                }
+            }
+        }
+        for operand in block.term.operands() {
+            cover(operand, positions.terms[b]);
+        }
+        for value in &liveness.live_in[b] {
+            cover(*value, positions.blocks[b]);
+        }
+        for value in &liveness.live_out[b] {
+            cover(*value, positions.terms[b]);
+        }
+    }
+    let mut intervals: Vec<Interval> = ranges
+        .into_iter()
+        .enumerate()
+        .filter_map(|(v, range)| {
+            range.map(|(start, end)| Interval {
+                value: ValueId(v),
+                start,
+                end,
+            })
+        })
+        .collect();
+    intervals.sort_by_key(|i| (i.start, i.value));
+    intervals
+}
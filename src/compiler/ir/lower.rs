@@ -0,0 +1,884 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::{
+    ast::{
+        expr::{BinaryOp, Expr, Identifier, LogicalOp, Slot},
+        literal::Literal,
+        visitor::{walk_assign, Visitor},
+    },
+    ir::{cfg, BlockId, FuncId, Function, Module, Op, Terminator, ValueId},
+    statements::stmt::{Block, Catch, ClassDecl, FnDecl, Stmt, StmtKind},
+    value::Class,
+};
+
+/// Lowers a resolved and checked program to SSA form. SSA values are built while lowering, after
+/// Braun et al., "Simple and Efficient Construction of Static Single Assignment Form": a variable
+/// read looks backwards through the blocks for its definition and places a `phi` where
+/// predecessors disagree, with loop headers waiting until every edge into them is known. The first
+/// `natives` of the globals are the natives the program was resolved with.
+pub fn lower(program: &[Stmt], globals: &[String], natives: usize) -> Module {
+    let mut captures = Captures::default();
+    for stmt in program {
+        captures.visit_stmt(stmt);
+    }
+
+    let mut lowerer = Lowerer {
+        functions: Vec::new(),
+        captured: captures.captured,
+        scopes: Vec::new(),
+        next_scope: 0,
+        fns: Vec::new(),
+    };
+    lowerer.begin_function("main".to_owned(), 0, false);
+    for stmt in program {
+        lowerer.stmt(stmt);
+    }
+    let nil = lowerer.emit(Op::Const(Literal::Nil));
+    lowerer.terminate(Terminator::Return(nil));
+    lowerer.end_function();
+
+    Module {
+        functions: lowerer
+            .functions
+            .into_iter()
+            .map(|f| f.expect("every function is finished before lowering returns"))
+            .collect(),
+        globals: globals.to_vec(),
+        natives,
+    }
+}
+
+/// Finds the locals that a function other than the one declaring them uses. Those live in cells
+/// rather than SSA values, since the closure may read or change them at any time. So do the locals
+/// a `try` body or `catch` assigns, which its handlers have to see as they were when something
+/// was raised. A local is keyed by the scope it is declared in, numbered in the order scopes are
+/// entered, and its slot index.
+#[derive(Default)]
+struct Captures {
+    /// id and owning function depth of every enclosing scope.
+    scopes: Vec<(usize, usize)>,
+    next_scope: usize,
+    depth: usize,
+    /// how many `try` bodies and `catch`es of the current function enclose the code.
+    protected: usize,
+    captured: HashSet<(usize, usize)>,
+}
+
+impl Captures {
+    fn push_scope(&mut self) {
+        self.scopes.push((self.next_scope, self.depth));
+        self.next_scope += 1;
+    }
+    fn use_slot(&mut self, slot: Option<Slot>) {
+        if let Some(Slot::Local { depth, index }) = slot {
+            let (id, owner) = self.scopes[self.scopes.len() - 1 - depth];
+            if owner != self.depth {
+                self.captured.insert((id, index));
+            }
+        }
+    }
+    /// scopes are entered exactly as `Lowerer::function` enters them.
+    fn function(&mut self, decl: &FnDecl, method: bool) {
+        let protected = std::mem::take(&mut self.protected);
+        self.depth += 1;
+        if method {
+            self.push_scope();
+        }
+        self.push_scope();
+        for stmt in &decl.body.stmts {
+            self.visit_stmt(stmt);
+        }
+        self.scopes.pop();
+        if method {
+            self.scopes.pop();
+        }
+        self.depth -= 1;
+        self.protected = protected;
+    }
+}
+
+impl Visitor for Captures {
+    fn visit_block(&mut self, block: &Block) {
+        self.push_scope();
+        for stmt in &block.stmts {
+            self.visit_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+    fn visit_for(&mut self, _name: &Identifier, iterable: &Expr, body: &Block) {
+        self.visit_expr(iterable);
+        self.push_scope();
+        for stmt in &body.stmts {
+            self.visit_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+    fn visit_try(&mut self, body: &Block, catch: Option<&Catch>, finally: Option<&Block>) {
+        self.protected += 1;
+        self.visit_block(body);
+        if let Some(catch) = catch {
+            self.visit_catch(catch);
+        }
+        self.protected -= 1;
+        if let Some(finally) = finally {
+            self.visit_block(finally);
+        }
+    }
+    fn visit_catch(&mut self, catch: &Catch) {
+        self.push_scope();
+        for stmt in &catch.body.stmts {
+            self.visit_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+    fn visit_fn(&mut self, decl: &FnDecl) {
+        self.function(decl, false);
+    }
+    fn visit_class(&mut self, decl: &ClassDecl) {
+        for method in &decl.methods {
+            self.function(method, true);
+        }
+    }
+    fn visit_variable(&mut self, name: &Identifier) {
+        self.use_slot(name.slot);
+    }
+    fn visit_assign(&mut self, name: &Identifier, value: &Expr) {
+        self.use_slot(name.slot);
+        if let (Some(Slot::Local { depth, index }), true) = (name.slot, self.protected > 0) {
+            let (id, _) = self.scopes[self.scopes.len() - 1 - depth];
+            self.captured.insert((id, index));
+        }
+        walk_assign(self, name, value);
+    }
+    fn visit_this(&mut self, keyword: &Identifier) {
+        self.use_slot(keyword.slot);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Var {
+    /// an SSA variable of the function owning the scope.
+    Ssa(usize),
+    /// a cell created by the function owning the scope.
+    Cell(ValueId),
+}
+
+struct Scope {
+    id: usize,
+    /// index into `Lowerer::fns` of the function the scope belongs to.
+    func: usize,
+    vars: Vec<Var>,
+}
+
+/// why control entered a `finally` block, which decides where it goes after it.
+const NORMAL: i32 = 0;
+const THROWN: i32 = 1;
+const RETURNED: i32 = 2;
+
+/// the `finally` of a `try` being lowered. Every way out of the `try` goes through its block,
+/// setting two SSA variables first: `reason`, and the value `pending` thrown or returned.
+#[derive(Clone, Copy)]
+struct Finally {
+    block: BlockId,
+    reason: usize,
+    pending: usize,
+}
+
+/// a function being lowered.
+struct FnState {
+    id: FuncId,
+    func: Function,
+    /// where instructions are emitted.
+    block: BlockId,
+    /// whether every predecessor of the block is known.
+    sealed: Vec<bool>,
+    /// the value each SSA variable has at the end of each block, as far as lowered.
+    defs: HashMap<(usize, BlockId), ValueId>,
+    /// `phi`s in unsealed blocks, to be given operands once the block is sealed.
+    incomplete: HashMap<BlockId, Vec<(usize, ValueId)>>,
+    next_var: usize,
+    /// the scope position and slot index of every captured variable, in capture order.
+    captures: Vec<(usize, usize)>,
+    /// the receiver, which an initializer returns.
+    this: Option<ValueId>,
+    is_initializer: bool,
+    /// the `try`s the code is in, innermost last, with their `finally` if they have one.
+    tries: Vec<Option<Finally>>,
+}
+
+impl FnState {
+    fn new_var(&mut self) -> usize {
+        self.next_var += 1;
+        self.next_var - 1
+    }
+    fn write(&mut self, var: usize, block: BlockId, value: ValueId) {
+        self.defs.insert((var, block), value);
+    }
+    fn read(&mut self, var: usize, block: BlockId) -> ValueId {
+        if let Some(value) = self.defs.get(&(var, block)) {
+            return *value;
+        }
+        let value = if !self.sealed[block.0] {
+            let phi = self.func.push(block, Op::Phi(Vec::new()));
+            self.incomplete.entry(block).or_default().push((var, phi));
+            phi
+        } else {
+            let preds = self.func.blocks[block.0].preds.clone();
+            match preds.as_slice() {
+                // only dead code reads a variable no path has defined
+                [] => self.func.push(block, Op::Const(Literal::Nil)),
+                [pred] => self.read(var, *pred),
+                _ => {
+                    let phi = self.func.push(block, Op::Phi(Vec::new()));
+                    // recorded first, so a loop back to this block finds the phi
+                    self.write(var, block, phi);
+                    self.fill_phi(var, phi, block);
+                    phi
+                }
+            }
+        };
+        self.write(var, block, value);
+        value
+    }
+    fn fill_phi(&mut self, var: usize, phi: ValueId, block: BlockId) {
+        let preds = self.func.blocks[block.0].preds.clone();
+        let incoming: Vec<_> = preds.iter().map(|p| (*p, self.read(var, *p))).collect();
+        self.func.insts[phi.0] = Op::Phi(incoming);
+    }
+    fn seal(&mut self, block: BlockId) {
+        for (var, phi) in self.incomplete.remove(&block).unwrap_or_default() {
+            self.fill_phi(var, phi, block);
+        }
+        self.sealed[block.0] = true;
+    }
+}
+
+struct Lowerer {
+    /// indexed by `FuncId`; `None` while the function is still being lowered.
+    functions: Vec<Option<Function>>,
+    captured: HashSet<(usize, usize)>,
+    scopes: Vec<Scope>,
+    next_scope: usize,
+    /// the function being lowered last, enclosing ones before it.
+    fns: Vec<FnState>,
+}
+
+impl Lowerer {
+    fn state(&mut self) -> &mut FnState {
+        self.fns
+            .last_mut()
+            .expect("code is only lowered inside a function")
+    }
+    fn emit(&mut self, op: Op) -> ValueId {
+        let state = self.state();
+        state.func.push(state.block, op)
+    }
+    fn new_block(&mut self) -> BlockId {
+        let state = self.state();
+        state.sealed.push(false);
+        state.func.new_block()
+    }
+    fn seal(&mut self, block: BlockId) {
+        self.state().seal(block);
+    }
+    fn switch_to(&mut self, block: BlockId) {
+        self.state().block = block;
+    }
+    fn terminate(&mut self, term: Terminator) {
+        let state = self.state();
+        state.func.terminate(state.block, term);
+    }
+    fn jump(&mut self, target: BlockId) {
+        self.terminate(Terminator::Jump(target));
+    }
+    /// continues in a block nothing jumps to, for the dead code after a `return`.
+    fn unreachable(&mut self) {
+        let block = self.new_block();
+        self.seal(block);
+        self.switch_to(block);
+    }
+
+    fn push_scope(&mut self) {
+        self.scopes.push(Scope {
+            id: self.next_scope,
+            func: self.fns.len() - 1,
+            vars: Vec::new(),
+        });
+        self.next_scope += 1;
+    }
+    /// gives the next local of the innermost scope its first value. Captured locals get a cell,
+    /// others an SSA variable; `copy` marks the write as a move the optimiser may fold away.
+    fn bind_local(&mut self, value: ValueId, copy: bool) {
+        let scope = self
+            .scopes
+            .last()
+            .expect("locals are declared inside a scope");
+        let key = (scope.id, scope.vars.len());
+        let var = if self.captured.contains(&key) {
+            Var::Cell(self.emit(Op::NewCell(value)))
+        } else {
+            let value = if copy {
+                self.emit(Op::Copy(value))
+            } else {
+                value
+            };
+            let state = self.state();
+            let var = state.new_var();
+            let block = state.block;
+            state.write(var, block, value);
+            Var::Ssa(var)
+        };
+        self.scopes
+            .last_mut()
+            .expect("locals are declared inside a scope")
+            .vars
+            .push(var);
+    }
+    fn declare(&mut self, name: &Identifier, value: ValueId) {
+        match name.slot {
+            Some(Slot::Global(index)) => {
+                self.emit(Op::StoreGlobal(index, value));
+            }
+            Some(Slot::Local { .. }) => self.bind_local(value, true),
+            None => unreachable!("'{}' was not resolved before lowering", name.name),
+        }
+    }
+    /// whether `name` is a local that closures capture, so it needs its cell before its value.
+    fn is_captured(&self, name: &Identifier) -> bool {
+        match (name.slot, self.scopes.last()) {
+            (Some(Slot::Local { index, .. }), Some(scope)) => {
+                self.captured.contains(&(scope.id, index))
+            }
+            _ => false,
+        }
+    }
+    /// where the local at `slot` lives, as seen from the current function.
+    fn local(&mut self, depth: usize, index: usize) -> Var {
+        let position = self.scopes.len() - 1 - depth;
+        let current = self.fns.len() - 1;
+        if self.scopes[position].func == current {
+            self.scopes[position].vars[index]
+        } else {
+            Var::Cell(self.capture(current, position, index))
+        }
+    }
+    /// the cell of a variable declared in an enclosing function, captured by `fns[func]`.
+    fn capture(&mut self, func: usize, position: usize, index: usize) -> ValueId {
+        let state = &mut self.fns[func];
+        let key = (position, index);
+        let slot = match state.captures.iter().position(|c| *c == key) {
+            Some(slot) => slot,
+            None => {
+                state.captures.push(key);
+                state.captures.len() - 1
+            }
+        };
+        state.func.push(state.block, Op::Capture(slot))
+    }
+    fn load(&mut self, name: &Identifier) -> ValueId {
+        match name.slot {
+            Some(Slot::Global(index)) => self.emit(Op::LoadGlobal(index)),
+            Some(Slot::Local { depth, index }) => match self.local(depth, index) {
+                Var::Ssa(var) => {
+                    let state = self.state();
+                    let block = state.block;
+                    state.read(var, block)
+                }
+                Var::Cell(cell) => self.emit(Op::LoadCell(cell)),
+            },
+            None => unreachable!("'{}' was not resolved before lowering", name.name),
+        }
+    }
+    /// assigns an already declared variable and returns the value it now has.
+    fn store(&mut self, name: &Identifier, value: ValueId) -> ValueId {
+        match name.slot {
+            Some(Slot::Global(index)) => {
+                self.emit(Op::StoreGlobal(index, value));
+                value
+            }
+            Some(Slot::Local { depth, index }) => match self.local(depth, index) {
+                Var::Ssa(var) => {
+                    let copy = self.emit(Op::Copy(value));
+                    let state = self.state();
+                    let block = state.block;
+                    state.write(var, block, copy);
+                    copy
+                }
+                Var::Cell(cell) => {
+                    self.emit(Op::StoreCell(cell, value));
+                    value
+                }
+            },
+            None => unreachable!("'{}' was not resolved before lowering", name.name),
+        }
+    }
+
+    fn begin_function(&mut self, mut name: String, params: usize, is_initializer: bool) {
+        let taken = |name: &str, functions: &[Option<Function>], fns: &[FnState]| {
+            functions.iter().flatten().any(|f| f.name == name)
+                || fns.iter().any(|s| s.func.name == name)
+        };
+        if taken(&name, &self.functions, &self.fns) {
+            name = format!("{}#{}", name, self.functions.len());
+        }
+        let id = FuncId(self.functions.len());
+        self.functions.push(None);
+        self.fns.push(FnState {
+            id,
+            func: Function::new(name, params),
+            block: BlockId(0),
+            sealed: vec![true],
+            defs: HashMap::new(),
+            incomplete: HashMap::new(),
+            next_var: 0,
+            captures: Vec::new(),
+            this: None,
+            is_initializer,
+            tries: Vec::new(),
+        });
+    }
+    /// finishes the current function and returns it with the variables its closures capture.
+    fn end_function(&mut self) -> (FuncId, Vec<(usize, usize)>) {
+        let mut state = self.fns.pop().expect("a function is being lowered");
+        state.func.captures = state.captures.len();
+        cfg::remove_unreachable(&mut state.func);
+        self.functions[state.id.0] = Some(state.func);
+        (state.id, state.captures)
+    }
+    /// lowers a function or method into its own `Function` and returns a closure over it.
+    fn function(&mut self, decl: &FnDecl, name: String, method: bool) -> ValueId {
+        let is_initializer = method && decl.name.name == Class::INITIALIZER;
+        let receiver = usize::from(method);
+        self.begin_function(name, decl.params.len() + receiver, is_initializer);
+        if method {
+            // the scope holding `this`, see `Resolver::visit_class_mut`
+            self.push_scope();
+            let this = self.emit(Op::Param(0));
+            self.state().this = Some(this);
+            self.bind_local(this, false);
+        }
+        self.push_scope();
+        for i in 0..decl.params.len() {
+            let param = self.emit(Op::Param(i + receiver));
+            self.bind_local(param, false);
+        }
+        for stmt in &decl.body.stmts {
+            self.stmt(stmt);
+        }
+        self.ret(None);
+        self.scopes.pop();
+        if method {
+            self.scopes.pop();
+        }
+        let (id, captures) = self.end_function();
+
+        let parent = self.fns.len() - 1;
+        let mut cells = Vec::with_capacity(captures.len());
+        for (position, index) in captures {
+            let cell = if self.scopes[position].func == parent {
+                match self.scopes[position].vars[index] {
+                    Var::Cell(cell) => cell,
+                    Var::Ssa(_) => unreachable!("captured variables always live in cells"),
+                }
+            } else {
+                self.capture(parent, position, index)
+            };
+            cells.push(cell);
+        }
+        self.emit(Op::Closure(id, cells))
+    }
+    fn ret(&mut self, value: Option<ValueId>) {
+        let value = match (self.state().this, self.state().is_initializer) {
+            // an initializer hands back the instance whatever it returns
+            (Some(this), true) => this,
+            _ => match value {
+                Some(value) => value,
+                None => self.emit(Op::Const(Literal::Nil)),
+            },
+        };
+        // leaving the `try`s on the way out, until one has a `finally` to run first
+        let tries = self.state().tries.clone();
+        for finally in tries.iter().rev() {
+            self.emit(Op::EndTry);
+            if let Some(finally) = finally {
+                self.enter_finally(*finally, RETURNED, Some(value));
+                self.unreachable();
+                return;
+            }
+        }
+        self.terminate(Terminator::Return(value));
+        self.unreachable();
+    }
+    /// jumps to a `finally` block for `reason`, with the value thrown or returned.
+    fn enter_finally(&mut self, finally: Finally, reason: i32, pending: Option<ValueId>) {
+        let reason_value = self.emit(Op::Const(Literal::Int(reason)));
+        let state = self.state();
+        let block = state.block;
+        state.write(finally.reason, block, reason_value);
+        if let Some(pending) = pending {
+            state.write(finally.pending, block, pending);
+        }
+        self.jump(finally.block);
+    }
+    /// enters a `try` region and returns its handler, which the caller fills in once the region is
+    /// left with `end_try`.
+    fn protect(&mut self, finally: Option<Finally>) -> BlockId {
+        let body = self.new_block();
+        let handler = self.new_block();
+        self.terminate(Terminator::Try { body, handler });
+        self.seal(body);
+        self.seal(handler);
+        self.switch_to(body);
+        self.state().tries.push(finally);
+        handler
+    }
+    fn end_try(&mut self) {
+        self.emit(Op::EndTry);
+        self.state().tries.pop();
+    }
+    /// a `try` with a `finally` is two regions: the outer one runs `finally` and throws again for
+    /// whatever the inner one, `body` with `catch` handling what it raises, lets through.
+    fn try_stmt(&mut self, body: &Block, catch: Option<&Catch>, finally: Option<&Block>) {
+        let outer = finally.map(|finally| {
+            let block = self.new_block();
+            let state = self.state();
+            let target = Finally {
+                block,
+                reason: state.new_var(),
+                pending: state.new_var(),
+            };
+            (finally, target, self.protect(Some(target)))
+        });
+        let inner = catch.map(|catch| (catch, self.protect(None)));
+        self.block(body);
+        if let Some((catch, handler)) = inner {
+            let after = self.new_block();
+            self.end_try();
+            self.jump(after);
+            self.switch_to(handler);
+            let exception = self.emit(Op::Caught);
+            // the exception shares the body's scope, as a `for` loop's variable does
+            self.push_scope();
+            self.bind_local(exception, false);
+            for stmt in &catch.body.stmts {
+                self.stmt(stmt);
+            }
+            self.scopes.pop();
+            self.jump(after);
+            self.seal(after);
+            self.switch_to(after);
+        }
+        let Some((finally, target, handler)) = outer else {
+            return;
+        };
+        self.end_try();
+        self.enter_finally(target, NORMAL, None);
+        self.switch_to(handler);
+        let exception = self.emit(Op::Caught);
+        self.enter_finally(target, THROWN, Some(exception));
+        // every `return` into the block has been lowered along with the body
+        self.seal(target.block);
+        self.switch_to(target.block);
+        self.block(finally);
+
+        let reason = self.read_var(target.reason);
+        let normal = self.emit(Op::Const(Literal::Int(NORMAL)));
+        let is_normal = self.emit(Op::Binary(BinaryOp::EqEq, reason, normal));
+        let after = self.new_block();
+        let abrupt = self.new_block();
+        self.terminate(Terminator::Branch {
+            cond: is_normal,
+            then: after,
+            otherwise: abrupt,
+        });
+        self.seal(abrupt);
+        self.switch_to(abrupt);
+        let thrown = self.emit(Op::Const(Literal::Int(THROWN)));
+        let is_thrown = self.emit(Op::Binary(BinaryOp::EqEq, reason, thrown));
+        let rethrow = self.new_block();
+        let returning = self.new_block();
+        self.terminate(Terminator::Branch {
+            cond: is_thrown,
+            then: rethrow,
+            otherwise: returning,
+        });
+        self.seal(rethrow);
+        self.seal(returning);
+        self.switch_to(rethrow);
+        let pending = self.read_var(target.pending);
+        self.terminate(Terminator::Throw(pending));
+        self.switch_to(returning);
+        let pending = self.read_var(target.pending);
+        self.ret(Some(pending));
+        self.seal(after);
+        self.switch_to(after);
+    }
+    /// the value an SSA variable of the current function has in the current block.
+    fn read_var(&mut self, var: usize) -> ValueId {
+        let state = self.state();
+        let block = state.block;
+        state.read(var, block)
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        match &stmt.kind {
+            StmtKind::Expr(e) => {
+                self.expr(e);
+            }
+            StmtKind::Print(e) => {
+                let value = self.expr(e);
+                self.emit(Op::Print(value));
+            }
+            StmtKind::Let { name, init, .. } => {
+                let value = match init {
+                    Some(init) => self.expr(init),
+                    None => self.emit(Op::Const(Literal::Nil)),
+                };
+                self.declare(name, value);
+            }
+            StmtKind::Block(block) => self.block(block),
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                let cond = self.expr(cond);
+                let then = self.new_block();
+                let join = self.new_block();
+                let otherwise = match else_branch {
+                    Some(_) => self.new_block(),
+                    None => join,
+                };
+                self.terminate(Terminator::Branch {
+                    cond,
+                    then,
+                    otherwise,
+                });
+                self.seal(then);
+                self.switch_to(then);
+                self.block(then_branch);
+                self.jump(join);
+                if let Some(else_branch) = else_branch {
+                    self.seal(otherwise);
+                    self.switch_to(otherwise);
+                    self.stmt(else_branch);
+                    self.jump(join);
+                }
+                self.seal(join);
+                self.switch_to(join);
+            }
+            StmtKind::While { cond, body } => {
+                let header = self.new_block();
+                self.jump(header);
+                self.switch_to(header);
+                let cond = self.expr(cond);
+                let body_block = self.new_block();
+                let exit = self.new_block();
+                self.terminate(Terminator::Branch {
+                    cond,
+                    then: body_block,
+                    otherwise: exit,
+                });
+                self.seal(body_block);
+                self.switch_to(body_block);
+                self.block(body);
+                self.jump(header);
+                // every edge into the header is known only now that the back edge is
+                self.seal(header);
+                self.seal(exit);
+                self.switch_to(exit);
+            }
+            StmtKind::For { iterable, body, .. } => {
+                // a loop over positions, with the length checked before every iteration
+                let iterable = self.expr(iterable);
+                let zero = self.emit(Op::Const(Literal::Int(0)));
+                let state = self.state();
+                let position = state.new_var();
+                let block = state.block;
+                state.write(position, block, zero);
+
+                let header = self.new_block();
+                self.jump(header);
+                self.switch_to(header);
+                let current = self.state().read(position, header);
+                let len = self.emit(Op::Len(iterable));
+                let cond = self.emit(Op::Binary(BinaryOp::Lt, current, len));
+                let body_block = self.new_block();
+                let exit = self.new_block();
+                self.terminate(Terminator::Branch {
+                    cond,
+                    then: body_block,
+                    otherwise: exit,
+                });
+                self.seal(body_block);
+                self.switch_to(body_block);
+                let element = self.emit(Op::Element(iterable, current));
+                // the loop variable shares the body's scope, see `Resolver::visit_for_mut`
+                self.push_scope();
+                self.bind_local(element, false);
+                for stmt in &body.stmts {
+                    self.stmt(stmt);
+                }
+                self.scopes.pop();
+                let one = self.emit(Op::Const(Literal::Int(1)));
+                let next = self.emit(Op::Binary(BinaryOp::Plus, current, one));
+                let state = self.state();
+                let block = state.block;
+                state.write(position, block, next);
+                self.jump(header);
+                self.seal(header);
+                self.seal(exit);
+                self.switch_to(exit);
+            }
+            StmtKind::Fn(decl) => {
+                let captured = self.is_captured(&decl.name);
+                if captured {
+                    let nil = self.emit(Op::Const(Literal::Nil));
+                    self.bind_local(nil, false);
+                }
+                let closure = self.function(decl, decl.name.name.clone(), false);
+                if captured {
+                    self.store(&decl.name, closure);
+                } else {
+                    self.declare(&decl.name, closure);
+                }
+            }
+            StmtKind::Return(value) => {
+                let value = value.as_ref().map(|v| self.expr(v));
+                self.ret(value);
+            }
+            StmtKind::Throw(value) => {
+                let value = self.expr(value);
+                self.terminate(Terminator::Throw(value));
+                self.unreachable();
+            }
+            StmtKind::Try {
+                body,
+                catch,
+                finally,
+            } => self.try_stmt(body, catch.as_ref(), finally.as_ref()),
+            StmtKind::Class(decl) => {
+                let captured = self.is_captured(&decl.name);
+                if captured {
+                    let nil = self.emit(Op::Const(Literal::Nil));
+                    self.bind_local(nil, false);
+                }
+                let mut methods = Vec::with_capacity(decl.methods.len());
+                for method in &decl.methods {
+                    let name = format!("{}.{}", decl.name.name, method.name.name);
+                    let closure = self.function(method, name, true);
+                    methods.push((method.name.name.clone(), closure));
+                }
+                let class = self.emit(Op::Class(decl.name.name.clone(), methods));
+                if captured {
+                    self.store(&decl.name, class);
+                } else {
+                    self.declare(&decl.name, class);
+                }
+            }
+            // interfaces only exist for the checker
+            StmtKind::Interface(_) => {}
+            // uses of an imported module are globals like any other by now
+            StmtKind::Import { .. } => {}
+        }
+    }
+    fn block(&mut self, block: &Block) {
+        self.push_scope();
+        for stmt in &block.stmts {
+            self.stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn expr(&mut self, expr: &Expr) -> ValueId {
+        match expr {
+            Expr::LiteralExpr(l) => self.emit(Op::Const(l.clone())),
+            Expr::Grouping(inner) => self.expr(inner),
+            Expr::Unary { op, rhs } => {
+                let rhs = self.expr(rhs);
+                self.emit(Op::Unary(*op, rhs))
+            }
+            Expr::Binary { lhs, op, rhs } => {
+                let lhs = self.expr(lhs);
+                let rhs = self.expr(rhs);
+                self.emit(Op::Binary(*op, lhs, rhs))
+            }
+            Expr::Logical { lhs, op, rhs } => {
+                // the result is whichever operand decided it
+                let lhs = self.expr(lhs);
+                let decided = self.state().block;
+                let rhs_block = self.new_block();
+                let join = self.new_block();
+                let (then, otherwise) = match op {
+                    LogicalOp::And => (rhs_block, join),
+                    LogicalOp::Or => (join, rhs_block),
+                };
+                self.terminate(Terminator::Branch {
+                    cond: lhs,
+                    then,
+                    otherwise,
+                });
+                self.seal(rhs_block);
+                self.switch_to(rhs_block);
+                let rhs = self.expr(rhs);
+                let rhs_end = self.state().block;
+                self.jump(join);
+                self.seal(join);
+                self.switch_to(join);
+                self.emit(Op::Phi(vec![(decided, lhs), (rhs_end, rhs)]))
+            }
+            Expr::Variable(name) | Expr::This(name) => self.load(name),
+            Expr::Assign { name, value } => {
+                let value = self.expr(value);
+                self.store(name, value)
+            }
+            Expr::Call { callee, args, .. } => {
+                let callee = self.expr(callee);
+                let args = args.iter().map(|a| self.expr(a)).collect();
+                self.emit(Op::Call(callee, args))
+            }
+            Expr::Get { object, name } => {
+                let object = self.expr(object);
+                self.emit(Op::GetProp(object, name.name.clone()))
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                let object = self.expr(object);
+                let value = self.expr(value);
+                self.emit(Op::SetProp(object, name.name.clone(), value));
+                value
+            }
+            Expr::List(elements) => {
+                let elements = elements.iter().map(|e| self.expr(e)).collect();
+                self.emit(Op::List(elements))
+            }
+            Expr::Map(entries) => {
+                let entries = entries
+                    .iter()
+                    .map(|(key, value)| (self.expr(key), self.expr(value)))
+                    .collect();
+                self.emit(Op::Map(entries))
+            }
+            Expr::Index { object, index, .. } => {
+                let object = self.expr(object);
+                let index = self.expr(index);
+                self.emit(Op::GetIndex(object, index))
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                ..
+            } => {
+                let object = self.expr(object);
+                let index = self.expr(index);
+                let value = self.expr(value);
+                self.emit(Op::SetIndex(object, index, value));
+                value
+            }
+        }
+    }
+}
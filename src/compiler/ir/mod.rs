@@ -0,0 +1,461 @@
+//! A mid-level IR in SSA form. Every function is a control-flow graph of basic blocks; every
+//! instruction defines at most one value, and variables that are only ever read by the function
+//! declaring them are turned into SSA values with `phi`s where control flow joins. Variables that
+//! closures capture live in cells instead, and globals in the global table, since code elsewhere can
+//! change them.
+//!
+//! The IR is as dynamically typed as the language: operators and calls do what the interpreter does
+//! with whatever values reach them.
+
+pub mod cfg;
+pub mod liveness;
+pub mod lower;
+pub mod opt;
+mod sccp;
+#[cfg(test)]
+mod tests;
+
+use crate::compiler::ast::{
+    expr::{BinaryOp, UnaryOp},
+    literal::Literal,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ValueId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FuncId(pub usize);
+
+impl std::fmt::Display for ValueId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "%{}", self.0)
+    }
+}
+
+impl std::fmt::Display for BlockId {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bb{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Module {
+    /// `functions[0]` is the top-level code.
+    pub functions: Vec<Function>,
+    /// names of the globals, indexed like `Slot::Global`.
+    pub globals: Vec<String>,
+    /// how many of the globals, the first, are natives. Only the interpreter has them: compiled
+    /// code cannot call into the host's Rust.
+    pub natives: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    /// number of `param`s, counting the receiver of a method as parameter 0.
+    pub params: usize,
+    /// number of cells the function's closures are created with.
+    pub captures: usize,
+    /// every instruction ever created, indexed by `ValueId`. Only those listed in a block are live.
+    pub insts: Vec<Op>,
+    /// `blocks[0]` is the entry.
+    pub blocks: Vec<Block>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    /// `phi`s first, then the rest in execution order.
+    pub insts: Vec<ValueId>,
+    pub term: Terminator,
+    pub preds: Vec<BlockId>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Op {
+    Const(Literal),
+    Param(usize),
+    /// the cell a closure captured at this index.
+    Capture(usize),
+    Phi(Vec<(BlockId, ValueId)>),
+    Copy(ValueId),
+    Unary(UnaryOp, ValueId),
+    Binary(BinaryOp, ValueId, ValueId),
+    LoadGlobal(usize),
+    StoreGlobal(usize, ValueId),
+    NewCell(ValueId),
+    LoadCell(ValueId),
+    StoreCell(ValueId, ValueId),
+    /// a closure over the function with these cells.
+    Closure(FuncId, Vec<ValueId>),
+    /// a class with its methods, each a closure taking the instance as parameter 0.
+    Class(String, Vec<(String, ValueId)>),
+    Call(ValueId, Vec<ValueId>),
+    GetProp(ValueId, String),
+    SetProp(ValueId, String, ValueId),
+    /// a list of these elements.
+    List(Vec<ValueId>),
+    /// a map of these keys and values, in insertion order.
+    Map(Vec<(ValueId, ValueId)>),
+    /// `object[index]`.
+    GetIndex(ValueId, ValueId),
+    /// `object[index] = value`.
+    SetIndex(ValueId, ValueId, ValueId),
+    /// how many elements a `for` loop over a list, or keys over a map, has left to visit in all.
+    Len(ValueId),
+    /// the element of a list, or key of a map, at a position below its `len`.
+    Element(ValueId, ValueId),
+    Print(ValueId),
+    /// the exception a `try` caught, which starts its handler.
+    Caught,
+    /// leaves the innermost `try` without an exception, so its handler no longer applies.
+    EndTry,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(BlockId),
+    /// jumps to `then` if `cond` is truthy, to `otherwise` if not.
+    Branch {
+        cond: ValueId,
+        then: BlockId,
+        otherwise: BlockId,
+    },
+    Return(ValueId),
+    /// raises the value as an exception, to the handler of the innermost `try` it is in, or out of
+    /// the function.
+    Throw(ValueId),
+    /// enters `body` with `handler` catching what anything up to the matching `end_try` raises.
+    /// The handler sees the variables as they were on entry, so those the body assigns live in
+    /// cells.
+    Try {
+        body: BlockId,
+        handler: BlockId,
+    },
+    /// a block that is still being built, or that control never leaves.
+    Unreachable,
+}
+
+impl Op {
+    /// whether running the instruction can be observed other than through its value. Operators
+    /// count as pure here, so constant propagation can fold them; dead code elimination still keeps
+    /// those that may fail. Indexing and map keys the checker cannot vouch for, since a list's
+    /// length and a map's keys are only known at runtime.
+    pub fn has_side_effects(&self) -> bool {
+        matches!(
+            self,
+            Op::StoreGlobal(..)
+                | Op::StoreCell(..)
+                | Op::Call(..)
+                | Op::GetProp(..)
+                | Op::SetProp(..)
+                | Op::Map(_)
+                | Op::GetIndex(..)
+                | Op::SetIndex(..)
+                | Op::Len(_)
+                | Op::Print(_)
+                | Op::Caught
+                | Op::EndTry
+        )
+    }
+    /// whether the instruction defines a value, as opposed to only having an effect.
+    pub fn has_value(&self) -> bool {
+        !matches!(
+            self,
+            Op::StoreGlobal(..)
+                | Op::StoreCell(..)
+                | Op::SetProp(..)
+                | Op::SetIndex(..)
+                | Op::Print(_)
+                | Op::EndTry
+        )
+    }
+    pub fn operands(&self) -> Vec<ValueId> {
+        let mut op = self.clone();
+        op.operands_mut().into_iter().map(|v| *v).collect()
+    }
+    pub fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            Op::Const(_)
+            | Op::Param(_)
+            | Op::Capture(_)
+            | Op::LoadGlobal(_)
+            | Op::Caught
+            | Op::EndTry => Vec::new(),
+            Op::Phi(incoming) => incoming.iter_mut().map(|(_, v)| v).collect(),
+            Op::Copy(v)
+            | Op::Unary(_, v)
+            | Op::StoreGlobal(_, v)
+            | Op::NewCell(v)
+            | Op::LoadCell(v)
+            | Op::GetProp(v, _)
+            | Op::Len(v)
+            | Op::Print(v) => vec![v],
+            Op::Binary(_, a, b)
+            | Op::StoreCell(a, b)
+            | Op::SetProp(a, _, b)
+            | Op::GetIndex(a, b)
+            | Op::Element(a, b) => vec![a, b],
+            Op::SetIndex(a, b, c) => vec![a, b, c],
+            Op::List(elements) => elements.iter_mut().collect(),
+            Op::Map(entries) => entries.iter_mut().flat_map(|(k, v)| [k, v]).collect(),
+            Op::Closure(_, cells) => cells.iter_mut().collect(),
+            Op::Class(_, methods) => methods.iter_mut().map(|(_, v)| v).collect(),
+            Op::Call(callee, args) => std::iter::once(callee).chain(args.iter_mut()).collect(),
+        }
+    }
+}
+
+impl Terminator {
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Terminator::Jump(target) => vec![*target],
+            Terminator::Branch {
+                then, otherwise, ..
+            } => vec![*then, *otherwise],
+            Terminator::Try { body, handler } => vec![*body, *handler],
+            Terminator::Return(_) | Terminator::Throw(_) | Terminator::Unreachable => Vec::new(),
+        }
+    }
+    pub fn operands(&self) -> Vec<ValueId> {
+        let mut term = self.clone();
+        term.operands_mut().into_iter().map(|v| *v).collect()
+    }
+    pub fn operands_mut(&mut self) -> Vec<&mut ValueId> {
+        match self {
+            Terminator::Branch { cond, .. } => vec![cond],
+            Terminator::Return(value) | Terminator::Throw(value) => vec![value],
+            Terminator::Jump(_) | Terminator::Try { .. } | Terminator::Unreachable => Vec::new(),
+        }
+    }
+}
+
+impl Function {
+    pub fn new(name: String, params: usize) -> Self {
+        Self {
+            name,
+            params,
+            captures: 0,
+            insts: Vec::new(),
+            blocks: vec![Block {
+                insts: Vec::new(),
+                term: Terminator::Unreachable,
+                preds: Vec::new(),
+            }],
+        }
+    }
+    pub fn op(&self, value: ValueId) -> &Op {
+        &self.insts[value.0]
+    }
+    pub fn new_block(&mut self) -> BlockId {
+        self.blocks.push(Block {
+            insts: Vec::new(),
+            term: Terminator::Unreachable,
+            preds: Vec::new(),
+        });
+        BlockId(self.blocks.len() - 1)
+    }
+    /// appends an instruction to `block`, or puts it after the block's other `phi`s if it is one.
+    pub fn push(&mut self, block: BlockId, op: Op) -> ValueId {
+        let is_phi = matches!(op, Op::Phi(_));
+        self.insts.push(op);
+        let value = ValueId(self.insts.len() - 1);
+        let insts = &mut self.blocks[block.0].insts;
+        if is_phi {
+            let at = insts
+                .iter()
+                .take_while(|v| matches!(self.insts[v.0], Op::Phi(_)))
+                .count();
+            insts.insert(at, value);
+        } else {
+            insts.push(value);
+        }
+        value
+    }
+    /// ends `block` with `term`, recording it as a predecessor of its successors.
+    pub fn terminate(&mut self, block: BlockId, term: Terminator) {
+        for succ in term.successors() {
+            self.blocks[succ.0].preds.push(block);
+        }
+        self.blocks[block.0].term = term;
+    }
+    /// points every use of `old` at `new`.
+    pub fn replace_uses(&mut self, old: ValueId, new: ValueId) {
+        for block in &mut self.blocks {
+            for value in &block.insts {
+                for operand in self.insts[value.0].operands_mut() {
+                    if *operand == old {
+                        *operand = new;
+                    }
+                }
+            }
+            for operand in block.term.operands_mut() {
+                if *operand == old {
+                    *operand = new;
+                }
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Module {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, function) in self.functions.iter().enumerate() {
+            if i > 0 {
+                writeln!(f)?;
+            }
+            self.fmt_function(f, function)?;
+        }
+        Ok(())
+    }
+}
+
+/// an instruction as it appears in the listing of its function.
+pub struct DisplayInst<'a> {
+    module: &'a Module,
+    func: &'a Function,
+    value: ValueId,
+}
+
+impl std::fmt::Display for DisplayInst<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let op = self.func.op(self.value);
+        if op.has_value() {
+            write!(f, "{} = ", self.value)?;
+        }
+        self.module.fmt_op(f, op)
+    }
+}
+
+impl std::fmt::Display for Terminator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Terminator::Jump(target) => write!(f, "jump {}", target),
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => write!(f, "branch {}, {}, {}", cond, then, otherwise),
+            Terminator::Return(value) => write!(f, "return {}", value),
+            Terminator::Throw(value) => write!(f, "throw {}", value),
+            Terminator::Try { body, handler } => write!(f, "try {}, {}", body, handler),
+            Terminator::Unreachable => write!(f, "unreachable"),
+        }
+    }
+}
+
+impl Module {
+    pub fn display_inst<'a>(&'a self, func: &'a Function, value: ValueId) -> DisplayInst<'a> {
+        DisplayInst {
+            module: self,
+            func,
+            value,
+        }
+    }
+    fn fmt_function(&self, f: &mut std::fmt::Formatter<'_>, func: &Function) -> std::fmt::Result {
+        write!(f, "fn @{}(params {}", func.name, func.params)?;
+        if func.captures > 0 {
+            write!(f, ", captures {}", func.captures)?;
+        }
+        writeln!(f, ") {{")?;
+        for (i, block) in func.blocks.iter().enumerate() {
+            write!(f, "{}:", BlockId(i))?;
+            if !block.preds.is_empty() {
+                let preds: Vec<String> = block.preds.iter().map(|p| p.to_string()).collect();
+                write!(f, " ; preds {}", preds.join(", "))?;
+            }
+            writeln!(f)?;
+            for value in &block.insts {
+                writeln!(f, "    {}", self.display_inst(func, *value))?;
+            }
+            writeln!(f, "    {}", block.term)?;
+        }
+        writeln!(f, "}}")
+    }
+    fn fmt_op(&self, f: &mut std::fmt::Formatter<'_>, op: &Op) -> std::fmt::Result {
+        let list = |values: &[ValueId]| -> String {
+            let values: Vec<String> = values.iter().map(|v| v.to_string()).collect();
+            values.join(", ")
+        };
+        match op {
+            Op::Const(literal) => write!(f, "const {}", literal),
+            Op::Param(index) => write!(f, "param {}", index),
+            Op::Capture(index) => write!(f, "capture {}", index),
+            Op::Phi(incoming) => {
+                let incoming: Vec<String> = incoming
+                    .iter()
+                    .map(|(block, value)| format!("{}: {}", block, value))
+                    .collect();
+                write!(f, "phi [{}]", incoming.join(", "))
+            }
+            Op::Copy(value) => write!(f, "copy {}", value),
+            Op::Unary(op, value) => write!(f, "{} {}", unary_name(*op), value),
+            Op::Binary(op, lhs, rhs) => write!(f, "{} {}, {}", binary_name(*op), lhs, rhs),
+            Op::LoadGlobal(index) => write!(f, "load_global ${}", self.globals[*index]),
+            Op::StoreGlobal(index, value) => {
+                write!(f, "store_global ${}, {}", self.globals[*index], value)
+            }
+            Op::NewCell(value) => write!(f, "new_cell {}", value),
+            Op::LoadCell(cell) => write!(f, "load_cell {}", cell),
+            Op::StoreCell(cell, value) => write!(f, "store_cell {}, {}", cell, value),
+            Op::Closure(func, cells) => write!(
+                f,
+                "closure @{}({})",
+                self.functions[func.0].name,
+                list(cells)
+            ),
+            Op::Class(name, methods) => {
+                let methods: Vec<String> = methods
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect();
+                write!(f, "class {} [{}]", name, methods.join(", "))
+            }
+            Op::Call(callee, args) => write!(f, "call {}({})", callee, list(args)),
+            Op::GetProp(object, name) => write!(f, "get {}.{}", object, name),
+            Op::SetProp(object, name, value) => write!(f, "set {}.{}, {}", object, name, value),
+            Op::List(elements) => write!(f, "list [{}]", list(elements)),
+            Op::Map(entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(key, value)| format!("{}: {}", key, value))
+                    .collect();
+                write!(f, "map [{}]", entries.join(", "))
+            }
+            Op::GetIndex(object, index) => write!(f, "get_index {}[{}]", object, index),
+            Op::SetIndex(object, index, value) => {
+                write!(f, "set_index {}[{}], {}", object, index, value)
+            }
+            Op::Len(value) => write!(f, "len {}", value),
+            Op::Element(object, position) => write!(f, "element {}, {}", object, position),
+            Op::Print(value) => write!(f, "print {}", value),
+            Op::Caught => write!(f, "caught"),
+            Op::EndTry => write!(f, "end_try"),
+        }
+    }
+}
+
+fn unary_name(op: UnaryOp) -> &'static str {
+    match op {
+        UnaryOp::Bang => "not",
+        UnaryOp::Negate => "neg",
+    }
+}
+
+fn binary_name(op: BinaryOp) -> &'static str {
+    match op {
+        BinaryOp::Plus => "add",
+        BinaryOp::Minus => "sub",
+        BinaryOp::Mult => "mul",
+        BinaryOp::Div => "div",
+        BinaryOp::EqEq => "eq",
+        BinaryOp::BangEq => "ne",
+        BinaryOp::Gt => "gt",
+        BinaryOp::GtEq => "ge",
+        BinaryOp::Lt => "lt",
+        BinaryOp::LtEq => "le",
+        BinaryOp::Eq => unreachable!("assignment is not an operator in the IR"),
+    }
+}
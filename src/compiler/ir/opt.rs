@@ -0,0 +1,179 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::compiler::{
+    ast::expr::BinaryOp,
+    ir::{cfg, sccp, BlockId, Function, Module, Op, ValueId},
+    optimizer::{fold_binary, fold_unary},
+};
+
+/// Runs the passes of an optimisation level over every function of the module:
+/// `0` none, `1` copy propagation and dead code elimination, and `2` constant propagation and
+/// common subexpression elimination on top.
+pub fn optimize(module: &mut Module, level: u8) {
+    for func in &mut module.functions {
+        if level >= 2 {
+            sccp::propagate(func);
+        }
+        if level >= 1 {
+            propagate_copies(func);
+        }
+        if level >= 2 {
+            eliminate_common_subexpressions(func);
+        }
+        if level >= 1 {
+            eliminate_dead_code(func);
+        }
+    }
+}
+
+/// points the users of every `copy` at the value copied, and drops the copies.
+pub fn propagate_copies(func: &mut Function) {
+    let mut copies = HashMap::new();
+    for block in &func.blocks {
+        for value in &block.insts {
+            if let Op::Copy(source) = func.op(*value) {
+                copies.insert(*value, *source);
+            }
+        }
+    }
+    if copies.is_empty() {
+        return;
+    }
+    let original = |mut value: ValueId| {
+        while let Some(source) = copies.get(&value) {
+            value = *source;
+        }
+        value
+    };
+    rewrite_uses(func, original);
+    for block in &mut func.blocks {
+        block.insts.retain(|v| !copies.contains_key(v));
+    }
+    // a loop `phi` over a variable and its copy merges only one value now
+    cfg::simplify_phis(func);
+}
+
+/// removes the instructions whose value is never used and that have no effect of their own. An
+/// operator that may fail at runtime is such an effect, since its error must still be raised.
+pub fn eliminate_dead_code(func: &mut Function) {
+    let mut live = HashSet::new();
+    let mut worklist = Vec::new();
+    for block in &func.blocks {
+        for value in &block.insts {
+            if func.op(*value).has_side_effects() || may_fail(func, *value) {
+                worklist.push(*value);
+            }
+        }
+        let mut term = block.term.clone();
+        worklist.extend(term.operands_mut().into_iter().map(|v| *v));
+    }
+    while let Some(value) = worklist.pop() {
+        if live.insert(value) {
+            worklist.extend(func.op(value).operands());
+        }
+    }
+    for block in &mut func.blocks {
+        block.insts.retain(|v| live.contains(v));
+    }
+}
+
+/// whether an operator may raise an error: overflow, a division by zero, or operands of the wrong
+/// type. Only equality never fails, and an operator on constants that folds cleanly; any other
+/// operands are only known at runtime, where even two ints can overflow.
+fn may_fail(func: &Function, value: ValueId) -> bool {
+    let constant = |v: &ValueId| match func.op(*v) {
+        Op::Const(l) => Some(l),
+        _ => None,
+    };
+    match func.op(value) {
+        Op::Unary(op, v) => match constant(v) {
+            Some(l) => !matches!(fold_unary(*op, l, 0), Ok(Some(_))),
+            None => true,
+        },
+        Op::Binary(BinaryOp::EqEq | BinaryOp::BangEq, ..) => false,
+        Op::Binary(op, a, b) => match (constant(a), constant(b)) {
+            (Some(l), Some(r)) => !matches!(fold_binary(l, *op, r, 0), Ok(Some(_))),
+            _ => true,
+        },
+        _ => false,
+    }
+}
+
+/// Replaces an operator or constant with an identical one that dominates it. The dominator tree is
+/// walked from the entry with the expressions available on the way down, so an expression is only
+/// reused where every path to it has computed it already.
+pub fn eliminate_common_subexpressions(func: &mut Function) {
+    let idom = cfg::dominators(func);
+    let mut children = vec![Vec::new(); func.blocks.len()];
+    for (block, parent) in idom.iter().enumerate() {
+        if let Some(parent) = parent {
+            children[parent.0].push(BlockId(block));
+        }
+    }
+
+    let mut available: HashMap<String, ValueId> = HashMap::new();
+    let mut replaced: HashMap<ValueId, ValueId> = HashMap::new();
+    // (block, whether its subtree is done), plus the expressions the block made available
+    let mut stack = vec![(BlockId(0), false)];
+    let mut added: Vec<Vec<String>> = vec![Vec::new(); func.blocks.len()];
+    while let Some((block, done)) = stack.pop() {
+        if done {
+            for key in &added[block.0] {
+                available.remove(key);
+            }
+            continue;
+        }
+        let insts = func.blocks[block.0].insts.clone();
+        for value in insts {
+            let op = &mut func.insts[value.0];
+            if matches!(op, Op::Phi(_)) {
+                continue;
+            }
+            // operands are defined in dominating blocks, which have been visited
+            for operand in op.operands_mut() {
+                if let Some(existing) = replaced.get(operand) {
+                    *operand = *existing;
+                }
+            }
+            if !matches!(
+                op,
+                Op::Const(_) | Op::Param(_) | Op::Capture(_) | Op::Unary(..) | Op::Binary(..)
+            ) {
+                continue;
+            }
+            let key = format!("{:?}", op);
+            match available.get(&key) {
+                Some(existing) => {
+                    replaced.insert(value, *existing);
+                }
+                None => {
+                    available.insert(key.clone(), value);
+                    added[block.0].push(key);
+                }
+            }
+        }
+        stack.push((block, true));
+        for child in children[block.0].iter().rev() {
+            stack.push((*child, false));
+        }
+    }
+
+    rewrite_uses(func, |v| replaced.get(&v).copied().unwrap_or(v));
+    for block in &mut func.blocks {
+        block.insts.retain(|v| !replaced.contains_key(v));
+    }
+}
+
+/// maps every operand of the function's instructions and terminators through `f`.
+fn rewrite_uses(func: &mut Function, f: impl Fn(ValueId) -> ValueId) {
+    for block in &mut func.blocks {
+        for value in &block.insts {
+            for operand in func.insts[value.0].operands_mut() {
+                *operand = f(*operand);
+            }
+        }
+        for operand in block.term.operands_mut() {
+            *operand = f(*operand);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! Sparse conditional constant propagation, after Wegman and Zadeck, "Constant Propagation with
+//! Conditional Branches". Values start out unknown and only move down the lattice, and a block is
+//! only looked at once an edge into it can be taken, so a `phi` ignores what flows in along
+//! branches that constants decide the other way.
+
+use std::collections::HashSet;
+
+use crate::compiler::{
+    ast::literal::Literal,
+    ir::{cfg, BlockId, Function, Op, Terminator, ValueId},
+    optimizer::{fold_binary, fold_unary},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Lattice {
+    /// no executable definition seen yet.
+    Top,
+    Const(Literal),
+    /// may have more than one value.
+    Bottom,
+}
+
+impl Lattice {
+    fn meet(&self, other: &Lattice) -> Lattice {
+        match (self, other) {
+            (Lattice::Top, x) | (x, Lattice::Top) => x.clone(),
+            (Lattice::Const(a), Lattice::Const(b)) if same(a, b) => Lattice::Const(a.clone()),
+            _ => Lattice::Bottom,
+        }
+    }
+}
+
+/// equal and of the same type: `1` and `1.0` compare equal but are different constants.
+fn same(a: &Literal, b: &Literal) -> bool {
+    std::mem::discriminant(a) == std::mem::discriminant(b) && a == b
+}
+
+enum User {
+    Inst(BlockId, ValueId),
+    Term(BlockId),
+}
+
+/// replaces the values that are constant on every executable path with constants, and branches on
+/// them with jumps, then drops the blocks that can no longer be reached.
+pub fn propagate(func: &mut Function) {
+    let mut users: Vec<Vec<User>> = (0..func.insts.len()).map(|_| Vec::new()).collect();
+    for (b, block) in func.blocks.iter().enumerate() {
+        for value in &block.insts {
+            for operand in func.op(*value).operands() {
+                users[operand.0].push(User::Inst(BlockId(b), *value));
+            }
+        }
+        let mut term = block.term.clone();
+        for operand in term.operands_mut() {
+            users[operand.0].push(User::Term(BlockId(b)));
+        }
+    }
+
+    let mut solver = Solver {
+        func,
+        values: vec![Lattice::Top; users.len()],
+        executable: Vec::new(),
+        edges: HashSet::new(),
+        flow: vec![(None, BlockId(0))],
+        ssa: Vec::new(),
+    };
+    solver.executable = vec![false; solver.func.blocks.len()];
+    loop {
+        if let Some((from, to)) = solver.flow.pop() {
+            if !solver.edges.insert((from, to)) {
+                continue;
+            }
+            let first_visit = !solver.executable[to.0];
+            solver.executable[to.0] = true;
+            let insts = solver.func.blocks[to.0].insts.clone();
+            for value in insts {
+                // a new edge only tells the `phi`s something new
+                if first_visit || matches!(solver.func.op(value), Op::Phi(_)) {
+                    solver.visit(to, value);
+                }
+            }
+            if first_visit {
+                solver.visit_term(to);
+            }
+        } else if let Some(value) = solver.ssa.pop() {
+            for user in &users[value.0] {
+                match user {
+                    User::Inst(block, user) if solver.executable[block.0] => {
+                        solver.visit(*block, *user)
+                    }
+                    User::Term(block) if solver.executable[block.0] => solver.visit_term(*block),
+                    _ => {}
+                }
+            }
+        } else {
+            break;
+        }
+    }
+
+    let Solver {
+        values, executable, ..
+    } = solver;
+    rewrite(func, &values, &executable);
+}
+
+struct Solver<'a> {
+    func: &'a Function,
+    values: Vec<Lattice>,
+    executable: Vec<bool>,
+    /// edges known to be taken, from `None` for the entry.
+    edges: HashSet<(Option<BlockId>, BlockId)>,
+    flow: Vec<(Option<BlockId>, BlockId)>,
+    ssa: Vec<ValueId>,
+}
+
+impl Solver<'_> {
+    fn visit(&mut self, block: BlockId, value: ValueId) {
+        let new = match self.func.op(value) {
+            Op::Const(l) => Lattice::Const(l.clone()),
+            Op::Phi(incoming) => incoming
+                .iter()
+                .filter(|(pred, _)| self.edges.contains(&(Some(*pred), block)))
+                .fold(Lattice::Top, |acc, (_, v)| acc.meet(&self.values[v.0])),
+            Op::Copy(v) => self.values[v.0].clone(),
+            Op::Unary(op, v) => match &self.values[v.0] {
+                Lattice::Const(l) => constant(fold_unary(*op, l, 0).ok().flatten()),
+                other => other.clone(),
+            },
+            Op::Binary(op, a, b) => match (&self.values[a.0], &self.values[b.0]) {
+                (Lattice::Const(l), Lattice::Const(r)) => {
+                    constant(fold_binary(l, *op, r, 0).ok().flatten())
+                }
+                (Lattice::Bottom, _) | (_, Lattice::Bottom) => Lattice::Bottom,
+                _ => Lattice::Top,
+            },
+            _ => Lattice::Bottom,
+        };
+        if new != self.values[value.0] {
+            self.values[value.0] = new;
+            self.ssa.push(value);
+        }
+    }
+    fn visit_term(&mut self, block: BlockId) {
+        match &self.func.blocks[block.0].term {
+            Terminator::Jump(target) => self.flow.push((Some(block), *target)),
+            Terminator::Branch {
+                cond,
+                then,
+                otherwise,
+            } => match &self.values[cond.0] {
+                Lattice::Const(l) => {
+                    let target = if truthy(l) { then } else { otherwise };
+                    self.flow.push((Some(block), *target));
+                }
+                Lattice::Bottom => {
+                    self.flow.push((Some(block), *then));
+                    self.flow.push((Some(block), *otherwise));
+                }
+                Lattice::Top => {}
+            },
+            Terminator::Try { body, handler } => {
+                self.flow.push((Some(block), *body));
+                self.flow.push((Some(block), *handler));
+            }
+            Terminator::Return(_) | Terminator::Throw(_) | Terminator::Unreachable => {}
+        }
+    }
+}
+
+/// a folded value, or `Bottom` if folding was left to the runtime.
+fn constant(folded: Option<Literal>) -> Lattice {
+    match folded {
+        Some(l) => Lattice::Const(l),
+        None => Lattice::Bottom,
+    }
+}
+
+fn truthy(l: &Literal) -> bool {
+    !matches!(l, Literal::Nil | Literal::Bool(false))
+}
+
+fn rewrite(func: &mut Function, values: &[Lattice], executable: &[bool]) {
+    for (b, _) in executable.iter().enumerate().filter(|(_, e)| **e) {
+        let mut constant_phis = Vec::new();
+        for value in func.blocks[b].insts.clone() {
+            let Lattice::Const(l) = &values[value.0] else {
+                continue;
+            };
+            let op = &mut func.insts[value.0];
+            if matches!(op, Op::Const(_)) || op.has_side_effects() || !op.has_value() {
+                continue;
+            }
+            if matches!(op, Op::Phi(_)) {
+                constant_phis.push(value);
+            }
+            *op = Op::Const(l.clone());
+        }
+        // constants go after the block's remaining `phi`s
+        let insts = &mut func.blocks[b].insts;
+        insts.retain(|v| !constant_phis.contains(v));
+        let at = insts
+            .iter()
+            .take_while(|v| matches!(func.insts[v.0], Op::Phi(_)))
+            .count();
+        insts.splice(at..at, constant_phis);
+
+        let block = BlockId(b);
+        if let Terminator::Branch {
+            cond,
+            then,
+            otherwise,
+        } = func.blocks[b].term
+        {
+            if let Lattice::Const(l) = &values[cond.0] {
+                let (taken, dropped) = if truthy(l) {
+                    (then, otherwise)
+                } else {
+                    (otherwise, then)
+                };
+                func.blocks[b].term = Terminator::Jump(taken);
+                cfg::remove_edge(func, block, dropped);
+            }
+        }
+    }
+    cfg::remove_unreachable(func);
+    cfg::merge_blocks(func);
+}
@@ -0,0 +1,332 @@
+use std::collections::HashSet;
+
+use crate::compiler::{
+    checker::check,
+    ir::{
+        liveness::{intervals, liveness, Positions},
+        lower::lower,
+        opt::optimize,
+        Module, ValueId,
+    },
+    lexer::Lexer,
+    natives::Natives,
+    parser::Parser,
+    resolver::resolve,
+    token::Token,
+};
+
+/// the IR of a program after the passes of `level`.
+fn module(source: &str, level: u8) -> Module {
+    let tokens: Vec<Token> = Lexer::from_source(source).collect();
+    let mut program = Parser::new(&tokens).parse().unwrap();
+    let globals = resolve(&mut program, &Natives::standard()).unwrap();
+    check(&program, &Natives::standard()).unwrap();
+    let mut module = lower(&program, &globals, Natives::standard().len());
+    optimize(&mut module, level);
+    module
+}
+
+/// the IR dump of a program after the passes of `level`.
+fn emit(source: &str, level: u8) -> String {
+    module(source, level).to_string()
+}
+
+/// the dump of one function.
+fn function<'a>(dump: &'a str, name: &str) -> &'a str {
+    let start = dump
+        .find(&format!("fn @{}(", name))
+        .unwrap_or_else(|| panic!("no function {} in\n{}", name, dump));
+    let end = dump[start..].find("\n}\n").unwrap() + start + 3;
+    &dump[start..end]
+}
+
+const COUNT: &str = "
+fn count(n) {
+    let i = 0;
+    let total = 0;
+    while (i < n) {
+        total = total + i;
+        i = i + 1;
+    }
+    return total;
+}
+";
+
+#[test]
+fn test_lower_loop_to_ssa() {
+    assert_eq!(
+        function(&emit(COUNT, 0), "count"),
+        "fn @count(params 1) {
+bb0:
+    %0 = param 0
+    %1 = const 0
+    %2 = copy %1
+    %3 = const 0
+    %4 = copy %3
+    jump bb1
+bb1: ; preds bb0, bb2
+    %5 = phi [bb0: %2, bb2: %13]
+    %8 = phi [bb0: %4, bb2: %10]
+    %7 = lt %5, %0
+    branch %7, bb2, bb3
+bb2: ; preds bb1
+    %9 = add %8, %5
+    %10 = copy %9
+    %11 = const 1
+    %12 = add %5, %11
+    %13 = copy %12
+    jump bb1
+bb3: ; preds bb1
+    return %8
+}
+"
+    );
+    // copies are propagated into the `phi`s and dropped
+    let count = emit(COUNT, 1);
+    let count = function(&count, "count");
+    assert!(!count.contains("copy"), "{}", count);
+    assert!(count.contains("%5 = phi [bb0: %1, bb2: %12]"), "{}", count);
+}
+
+#[test]
+fn test_lower_for_loop_over_positions() {
+    let source = "
+fn sum(xs: list<int>) -> int {
+    let total = 0;
+    for x in xs {
+        total = total + x;
+    }
+    return total;
+}
+";
+    assert_eq!(
+        function(&emit(source, 1), "sum"),
+        "fn @sum(params 1) {
+bb0:
+    %0 = param 0
+    %1 = const 0
+    %3 = const 0
+    jump bb1
+bb1: ; preds bb0, bb2
+    %4 = phi [bb0: %3, bb2: %12]
+    %8 = phi [bb0: %1, bb2: %9]
+    %5 = len %0
+    %6 = lt %4, %5
+    branch %6, bb2, bb3
+bb2: ; preds bb1
+    %7 = element %0, %4
+    %9 = add %8, %7
+    %11 = const 1
+    %12 = add %4, %11
+    jump bb1
+bb3: ; preds bb1
+    return %8
+}
+"
+    );
+    // an index may be out of bounds, so an unused one still runs
+    let o2 = emit("fn g(xs) { xs[1]; [1, 2]; }", 2);
+    let o2 = function(&o2, "g");
+    assert!(o2.contains("get_index %0[%1]"), "{}", o2);
+    assert!(!o2.contains("list"), "{}", o2);
+}
+
+#[test]
+fn test_lower_captured_variables_to_cells() {
+    let dump = emit(
+        "
+fn counter() {
+    let c = 0;
+    let unused = 1;
+    fn inc() {
+        c = c + 1;
+        return c;
+    }
+    return inc;
+}
+",
+        1,
+    );
+    assert_eq!(
+        function(&dump, "counter"),
+        "fn @counter(params 0) {
+bb0:
+    %0 = const 0
+    %1 = new_cell %0
+    %4 = closure @inc(%1)
+    return %4
+}
+"
+    );
+    let inc = function(&dump, "inc");
+    assert!(inc.starts_with("fn @inc(params 0, captures 1)"), "{}", inc);
+    assert!(inc.contains("store_cell"), "{}", inc);
+}
+
+#[test]
+fn test_lower_try_regions() {
+    let dump = emit(
+        "
+fn f(g) {
+    let tries = 0;
+    try {
+        tries = 1;
+        return g();
+    } catch (e) {
+        print e;
+    } finally {
+        print tries;
+    }
+    return nil;
+}
+",
+        1,
+    );
+    // `tries` is assigned in the body, so the handlers read it from a cell. Every way out goes
+    // through the one copy of `finally`, which then continues, throws again or returns.
+    assert_eq!(
+        function(&dump, "f"),
+        "fn @f(params 1) {
+bb0:
+    %0 = param 0
+    %1 = const 0
+    %2 = new_cell %1
+    %26 = const nil
+    try bb1, bb5
+bb1: ; preds bb0
+    try bb2, bb3
+bb2: ; preds bb1
+    %3 = const 1
+    store_cell %2, %3
+    %5 = call %0()
+    end_try
+    end_try
+    %8 = const 2
+    jump bb6
+bb3: ; preds bb1
+    %10 = caught
+    print %10
+    jump bb4
+bb4: ; preds bb3
+    end_try
+    %13 = const 0
+    jump bb6
+bb5: ; preds bb0
+    %14 = caught
+    %15 = const 1
+    jump bb6
+bb6: ; preds bb2, bb4, bb5
+    %18 = phi [bb2: %8, bb4: %13, bb5: %15]
+    %23 = phi [bb2: %5, bb4: %26, bb5: %14]
+    %16 = load_cell %2
+    print %16
+    %19 = const 0
+    %20 = eq %18, %19
+    branch %20, bb7, bb8
+bb7: ; preds bb6
+    %27 = const nil
+    return %27
+bb8: ; preds bb6
+    %21 = const 1
+    %22 = eq %18, %21
+    branch %22, bb9, bb10
+bb9: ; preds bb8
+    throw %23
+bb10: ; preds bb8
+    return %23
+}
+"
+    );
+}
+
+#[test]
+fn test_sccp_folds_branches() {
+    let source = "
+fn f(x) {
+    let debug = false;
+    let n = 2 * 3;
+    if (debug) {
+        print \"debugging\";
+    }
+    if (n > 5 or x) {
+        return n;
+    }
+    return x;
+}
+";
+    // without constant propagation the branch on `debug` stays
+    let o1 = emit(source, 1);
+    assert!(function(&o1, "f").contains("\"debugging\""));
+    assert_eq!(
+        function(&emit(source, 2), "f"),
+        "fn @f(params 1) {
+bb0:
+    %5 = const 6
+    return %5
+}
+"
+    );
+}
+
+#[test]
+fn test_cse_and_dce() {
+    let source = "
+fn f(a, b) {
+    let dead = a == b;
+    let kept = a - b;
+    let x = a * b + 1;
+    if (a > b) {
+        return a * b;
+    }
+    return x + a * b;
+}
+";
+    let o1 = emit(source, 1);
+    let o1 = function(&o1, "f");
+    assert!(!o1.contains("eq "), "{}", o1);
+    // the subtraction may overflow, so it stays for its error
+    assert!(o1.contains("sub "), "{}", o1);
+    assert_eq!(o1.matches("mul").count(), 3, "{}", o1);
+    // the product dominates the other two and is reused
+    let o2 = emit(source, 2);
+    let o2 = function(&o2, "f");
+    assert_eq!(o2.matches("mul").count(), 1, "{}", o2);
+    // unused values with effects stay
+    let o2 = emit("fn g(o) { o.field; print 1 + 2; }", 2);
+    assert!(o2.contains("get %0.field"), "{}", o2);
+    assert!(o2.contains("print"), "{}", o2);
+}
+
+#[test]
+fn test_liveness() {
+    let module = module(COUNT, 1);
+    let count = &module.functions[1];
+    // bb1 is the loop header, with `i` as %5 and `total` as %8; bb3 returns `total`
+    let live = liveness(count);
+    let set = |values: &[usize]| values.iter().map(|v| ValueId(*v)).collect::<HashSet<_>>();
+    assert_eq!(live.live_in[1], set(&[0]), "{}", module);
+    assert_eq!(live.live_out[1], set(&[0, 5, 8]));
+    assert_eq!(live.live_out[2], set(&[0, 9, 12]));
+    assert_eq!(live.live_in[3], set(&[8]));
+
+    let positions = Positions::new(count);
+    let ranges: Vec<(usize, usize, usize)> = intervals(count, &positions)
+        .iter()
+        .map(|i| (i.value.0, i.start, i.end))
+        .collect();
+    assert_eq!(
+        ranges,
+        [
+            (0, 2, 24),
+            (1, 4, 8),
+            (3, 6, 8),
+            // the `phi`s are assigned at the end of bb0 and bb2
+            (5, 8, 24),
+            (8, 8, 28),
+            (7, 12, 14),
+            (9, 18, 24),
+            (11, 20, 22),
+            (12, 22, 24),
+        ]
+    );
+}
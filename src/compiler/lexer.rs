@@ -2,30 +2,50 @@ use crate::compiler::token::Token;
 use crate::compiler::{patterns::Patterns, token::TokenType};
 use std::collections::VecDeque;
 use std::fs::File;
-use std::io::{BufRead, BufReader};
+use std::io::{BufRead, BufReader, Cursor};
 
 pub struct Lexer {
-    buf: BufReader<File>,
-    unprocessed_lexeme: VecDeque<String>,
+    buf: Box<dyn BufRead>,
+    /// lexemes of the current line not yet returned, with the column each starts at.
+    unprocessed_lexeme: VecDeque<(String, usize)>,
     line_index: usize,
     patterns: Patterns,
+    keep_trivia: bool,
 }
 
 impl Lexer {
     pub fn new(file_path: &String) -> Self {
         Self {
-            buf: BufReader::new(File::open(file_path).expect("Could not open file")),
+            buf: Box::new(BufReader::new(
+                File::open(file_path).expect("Could not open file"),
+            )),
             unprocessed_lexeme: VecDeque::new(),
             line_index: 0,
             patterns: Patterns::new(),
+            keep_trivia: false,
         }
     }
-    fn split_line_into_lexeme(&mut self, line: &String) {
-        let mut lexeme_iter = self.patterns.any.find_iter(&line);
+    /// lexes in-memory source instead of a file, e.g. for tests and the printers.
+    pub fn from_source(source: &str) -> Self {
+        Self {
+            buf: Box::new(Cursor::new(source.to_owned())),
+            unprocessed_lexeme: VecDeque::new(),
+            line_index: 0,
+            patterns: Patterns::new(),
+            keep_trivia: false,
+        }
+    }
+    /// also yields `TokenType::Comment` tokens, which are skipped by default. Used by the formatter.
+    pub fn with_trivia(mut self) -> Self {
+        self.keep_trivia = true;
+        self
+    }
+    fn split_line_into_lexeme(&mut self, line: &str) {
+        let mut lexeme_iter = self.patterns.any.find_iter(line);
         let mut m = lexeme_iter.next();
-        while m.is_some() {
+        while let Some(lexeme) = m {
             self.unprocessed_lexeme
-                .push_back(m.unwrap().as_str().to_owned());
+                .push_back((lexeme.as_str().to_owned(), lexeme.start() + 1));
             m = lexeme_iter.next();
         }
     }
@@ -37,102 +57,140 @@ impl Lexer {
         self.line_index += 1;
         Some(s)
     }
-    fn next_lexeme(&mut self) -> Option<String> {
+    fn next_lexeme(&mut self) -> Option<(String, usize)> {
         self.unprocessed_lexeme.pop_front()
     }
-    fn lexeme_type(&self, lexeme: &String) -> Option<TokenType> {
-        if self.patterns.l_paren.is_match(&lexeme) {
+    fn lexeme_type(&self, lexeme: &str) -> Option<TokenType> {
+        if self.patterns.comment.is_match(lexeme) {
+            Some(TokenType::Comment)
+        } else if self.patterns.l_paren.is_match(lexeme) {
             Some(TokenType::LParen)
-        } else if self.patterns.r_paren.is_match(&lexeme) {
+        } else if self.patterns.r_paren.is_match(lexeme) {
             Some(TokenType::RParen)
-        } else if self.patterns.l_brace.is_match(&lexeme) {
+        } else if self.patterns.l_brace.is_match(lexeme) {
             Some(TokenType::LBrace)
-        } else if self.patterns.r_brace.is_match(&lexeme) {
+        } else if self.patterns.r_brace.is_match(lexeme) {
             Some(TokenType::RBrace)
-        } else if self.patterns.comma.is_match(&lexeme) {
+        } else if self.patterns.l_bracket.is_match(lexeme) {
+            Some(TokenType::LBracket)
+        } else if self.patterns.r_bracket.is_match(lexeme) {
+            Some(TokenType::RBracket)
+        } else if self.patterns.comma.is_match(lexeme) {
             Some(TokenType::Comma)
-        } else if self.patterns.dot.is_match(&lexeme) {
+        } else if self.patterns.colon.is_match(lexeme) {
+            Some(TokenType::Colon)
+        } else if self.patterns.dot.is_match(lexeme) {
             Some(TokenType::Dot)
-        } else if self.patterns.minus.is_match(&lexeme) {
+        } else if self.patterns.arrow.is_match(lexeme) {
+            Some(TokenType::Arrow)
+        } else if self.patterns.minus.is_match(lexeme) {
             Some(TokenType::Minus)
-        } else if self.patterns.plus.is_match(&lexeme) {
+        } else if self.patterns.plus.is_match(lexeme) {
             Some(TokenType::Plus)
-        } else if self.patterns.semi.is_match(&lexeme) {
+        } else if self.patterns.semi.is_match(lexeme) {
             Some(TokenType::Semi)
-        } else if self.patterns.slash.is_match(&lexeme) {
+        } else if self.patterns.slash.is_match(lexeme) {
             Some(TokenType::Slash)
-        } else if self.patterns.star.is_match(&lexeme) {
+        } else if self.patterns.star.is_match(lexeme) {
             Some(TokenType::Star)
-        } else if self.patterns.bang_eq.is_match(&lexeme) {
+        } else if self.patterns.bang_eq.is_match(lexeme) {
             Some(TokenType::BangEq)
-        } else if self.patterns.bang.is_match(&lexeme) {
+        } else if self.patterns.bang.is_match(lexeme) {
             Some(TokenType::Bang)
-        } else if self.patterns.eq_eq.is_match(&lexeme) {
+        } else if self.patterns.eq_eq.is_match(lexeme) {
             Some(TokenType::EqEq)
-        } else if self.patterns.eq.is_match(&lexeme) {
+        } else if self.patterns.eq.is_match(lexeme) {
             Some(TokenType::Eq)
-        } else if self.patterns.gt_eq.is_match(&lexeme) {
+        } else if self.patterns.gt_eq.is_match(lexeme) {
             Some(TokenType::GtEq)
-        } else if self.patterns.gt.is_match(&lexeme) {
+        } else if self.patterns.gt.is_match(lexeme) {
             Some(TokenType::Gt)
-        } else if self.patterns.lt_eq.is_match(&lexeme) {
+        } else if self.patterns.lt_eq.is_match(lexeme) {
             Some(TokenType::LtEq)
-        } else if self.patterns.lt.is_match(&lexeme) {
+        } else if self.patterns.lt.is_match(lexeme) {
             Some(TokenType::Lt)
-        } else if self.patterns.str.is_match(&lexeme) {
+        } else if self.patterns.str.is_match(lexeme) {
             Some(TokenType::Str)
-        } else if self.patterns.num.is_match(&lexeme) {
+        } else if self.patterns.num.is_match(lexeme) {
             Some(TokenType::Num)
-        } else if self.patterns.and.is_match(&lexeme) {
+        } else if self.patterns.and.is_match(lexeme) {
             Some(TokenType::And)
-        } else if self.patterns.or.is_match(&lexeme) {
+        } else if self.patterns.or.is_match(lexeme) {
             Some(TokenType::Or)
-        } else if self.patterns.class.is_match(&lexeme) {
+        } else if self.patterns.class.is_match(lexeme) {
             Some(TokenType::Class)
-        } else if self.patterns.else_.is_match(&lexeme) {
+        } else if self.patterns.else_.is_match(lexeme) {
             Some(TokenType::Else)
-        } else if self.patterns.if_.is_match(&lexeme) {
+        } else if self.patterns.if_.is_match(lexeme) {
             Some(TokenType::If)
-        } else if self.patterns.true_.is_match(&lexeme) {
+        } else if self.patterns.fn_.is_match(lexeme) {
+            Some(TokenType::Fn)
+        } else if self.patterns.for_.is_match(lexeme) {
+            Some(TokenType::For)
+        } else if self.patterns.in_.is_match(lexeme) {
+            Some(TokenType::In)
+        } else if self.patterns.while_.is_match(lexeme) {
+            Some(TokenType::While)
+        } else if self.patterns.print.is_match(lexeme) {
+            Some(TokenType::Print)
+        } else if self.patterns.return_.is_match(lexeme) {
+            Some(TokenType::Return)
+        } else if self.patterns.this.is_match(lexeme) {
+            Some(TokenType::This)
+        } else if self.patterns.interface.is_match(lexeme) {
+            Some(TokenType::Interface)
+        } else if self.patterns.let_.is_match(lexeme) {
+            Some(TokenType::Let)
+        } else if self.patterns.try_.is_match(lexeme) {
+            Some(TokenType::Try)
+        } else if self.patterns.catch.is_match(lexeme) {
+            Some(TokenType::Catch)
+        } else if self.patterns.finally.is_match(lexeme) {
+            Some(TokenType::Finally)
+        } else if self.patterns.throw.is_match(lexeme) {
+            Some(TokenType::Throw)
+        } else if self.patterns.import.is_match(lexeme) {
+            Some(TokenType::Import)
+        } else if self.patterns.export.is_match(lexeme) {
+            Some(TokenType::Export)
+        } else if self.patterns.as_.is_match(lexeme) {
+            Some(TokenType::As)
+        } else if self.patterns.true_.is_match(lexeme) {
             Some(TokenType::True)
-        } else if self.patterns.false_.is_match(&lexeme) {
+        } else if self.patterns.false_.is_match(lexeme) {
             Some(TokenType::False)
-        } else if self.patterns.identifier.is_match(&lexeme) {
+        } else if self.patterns.nil.is_match(lexeme) {
+            Some(TokenType::Nil)
+        } else if self.patterns.identifier.is_match(lexeme) {
             Some(TokenType::Identifier)
         } else {
             None
         }
     }
-    fn next_token(&mut self) -> Option<Token> {
-        let lexeme = self.next_lexeme().unwrap().as_str().to_owned();
-        let token_type = self.lexeme_type(&lexeme);
-        None
-    }
 }
 
 impl Iterator for Lexer {
     type Item = Token;
     fn next(&mut self) -> Option<Self::Item> {
-        while self.unprocessed_lexeme.is_empty() {
-            let line = match self.next_line() {
-                Some(l) => l,
-                None => return None,
-            };
-            self.split_line_into_lexeme(&line);
-        }
+        loop {
+            while self.unprocessed_lexeme.is_empty() {
+                let line = self.next_line()?;
+                self.split_line_into_lexeme(&line);
+            }
 
-        let lexeme = match self.next_lexeme() {
-            Some(l) => l,
-            None => return None,
-        };
+            let (lexeme, column) = self.next_lexeme()?;
+            // left for the parser to report, which can then go on to find more errors
+            let token_type = self.lexeme_type(&lexeme).unwrap_or(TokenType::Invalid);
+            if token_type == TokenType::Comment && !self.keep_trivia {
+                continue;
+            }
 
-        Some(Token {
-            lexeme: lexeme.to_owned(),
-            token_type: match self.lexeme_type(&lexeme) {
-                Some(t) => t,
-                None => panic!("Invalid token: {} on line: {}", lexeme, self.line_index),
-            },
-            line: self.line_index,
-        })
+            return Some(Token {
+                lexeme: lexeme.to_owned(),
+                token_type,
+                line: self.line_index,
+                column,
+            });
+        }
     }
 }
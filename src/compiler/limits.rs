@@ -0,0 +1,70 @@
+//! Bounds on what running a program may take, so that a host can run scripts it does not trust.
+//! Going past one is a runtime error like any other, which the host gets back rather than its
+//! process hanging, overflowing its stack or running out of memory.
+
+use std::time::Duration;
+
+/// how many nested calls a run allows unless told otherwise. A call takes a few KiB of the host's
+/// stack in a release build and ten times that in a debug one, so this fits on an 8 MiB main
+/// thread either way. Hosts running scripts on threads with smaller stacks should lower it, or
+/// bound `stack_bytes`.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 256;
+
+/// the host's stack a call may take, with room for the deepest expressions in it: it is more in a
+/// debug build, whose frames are not optimised.
+const CALL_STACK_BYTES: usize = if cfg!(debug_assertions) {
+    64 << 10
+} else {
+    8 << 10
+};
+/// the stack the host takes before the run starts and after it is stopped, e.g. to report it.
+const HOST_STACK_BYTES: usize = 1 << 20;
+/// the most stack `stack_size` asks for, past which `stack_bytes` stops a run instead.
+const MAX_STACK_BYTES: usize = 1 << 30;
+
+/// Limits on one run of a program, or one call into it from the host: each starts afresh.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limits {
+    /// how many statements and expressions may be evaluated.
+    pub fuel: Option<u64>,
+    /// how long it may take. The clock is only looked at every so many steps, so a run can go a
+    /// little over, or further if a native takes long.
+    pub timeout: Option<Duration>,
+    /// how many calls may be in progress at once.
+    pub max_call_depth: usize,
+    /// the estimated bytes the heap may hold, strings included.
+    pub max_heap_bytes: Option<usize>,
+    /// how much of the host's stack calls may take, from where the run starts. Going past it fails
+    /// as going past `max_call_depth` does, and is what keeps a run within a stack too small for
+    /// that many calls. `None` trusts the stack to fit them.
+    pub stack_bytes: Option<usize>,
+}
+
+impl Limits {
+    /// the stack for a thread to run a program on within these limits: big enough for
+    /// `max_call_depth` calls up to a ceiling, past which `stack_bytes` has to be set to it.
+    pub fn stack_size(&self) -> usize {
+        let calls = self.max_call_depth.saturating_mul(CALL_STACK_BYTES);
+        calls.saturating_add(HOST_STACK_BYTES).min(MAX_STACK_BYTES)
+    }
+    /// these limits on a thread of `stack_size()`, with calls bounded by what the stack holds.
+    pub fn on_own_stack(self) -> Self {
+        Self {
+            stack_bytes: Some(self.stack_size() - HOST_STACK_BYTES),
+            ..self
+        }
+    }
+}
+
+impl Default for Limits {
+    /// no limits but the call depth, which keeps deep recursion from overflowing the stack.
+    fn default() -> Self {
+        Self {
+            fuel: None,
+            timeout: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            max_heap_bytes: None,
+            stack_bytes: None,
+        }
+    }
+}
@@ -0,0 +1,555 @@
+//! What the language server knows of a document: its program as far as it parses, the modules it
+//! imports, what is wrong with them, where each name is declared and used and, once the program
+//! resolves, the types checking it inferred. Positions here are the compiler's, lines and byte
+//! columns counting from 1; the server converts them to the protocol's.
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use crate::compiler::{
+    ast::{
+        expr::{Expr, Identifier},
+        types::{Scheme, Type},
+        visitor::{walk_class, walk_fn, walk_set, Visitor},
+    },
+    checker::{check_modules_partial, ModuleTypes},
+    modules::{Loader, Module, ModuleErr},
+    natives::Natives,
+    optimizer::optimize,
+    resolver::{
+        resolve_symbols, Declaration, Location, Reference, ResolveErr, SymbolKind, Symbols,
+    },
+    statements::stmt::{ClassDecl, FnDecl, StmtKind},
+    value::Class,
+};
+
+/// the words completion offers wherever a name may go.
+pub const KEYWORDS: &[&str] = &[
+    "and",
+    "as",
+    "catch",
+    "class",
+    "else",
+    "export",
+    "false",
+    "finally",
+    "fn",
+    "for",
+    "if",
+    "import",
+    "in",
+    "interface",
+    "let",
+    "nil",
+    "or",
+    "print",
+    "return",
+    "this",
+    "throw",
+    "true",
+    "try",
+    "while",
+];
+
+/// What an entry of an outline or a completion is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ItemKind {
+    Keyword,
+    Function,
+    Method,
+    Field,
+    Class,
+    Interface,
+    Variable,
+    Module,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Completion {
+    pub label: String,
+    pub kind: ItemKind,
+    /// the type, if it is known.
+    pub detail: Option<String>,
+}
+
+/// A declaration in the outline of a document.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outline {
+    pub name: String,
+    pub kind: ItemKind,
+    /// where the name is.
+    pub at: Location,
+    /// the first and last line of the whole declaration.
+    pub lines: (usize, usize),
+    pub children: Vec<Outline>,
+}
+
+/// what completing after `instance.` offers.
+struct ClassMembers {
+    name: String,
+    module: usize,
+    /// the first and last line of the declaration.
+    lines: (usize, usize),
+    methods: Vec<String>,
+    /// whatever its methods assign to `this.field`.
+    fields: Vec<String>,
+}
+
+pub struct Analysis {
+    /// as the loader ordered them, the document's own last.
+    pub modules: Vec<Module>,
+    /// the source of each module.
+    pub sources: Vec<String>,
+    pub errors: Vec<ModuleErr>,
+    pub symbols: Symbols,
+    /// the types of each module, if the program resolved without errors and so could be checked.
+    /// Kept despite type errors, so names their declarations don't touch still have types.
+    types: Option<Vec<ModuleTypes>>,
+    classes: Vec<ClassMembers>,
+    /// the natives' names and signatures.
+    natives: Vec<(String, Scheme)>,
+}
+
+impl Analysis {
+    /// analyses `text`, the document at `path`, with the modules it imports as they are on disk.
+    pub fn new(text: &str, path: &Path, search_path: &[PathBuf], natives: &Natives) -> Self {
+        let (mut modules, mut errors) = Loader::new(search_path.to_vec()).load_partial(text, path);
+        let sources = (0..modules.len())
+            .map(|index| match index + 1 == modules.len() {
+                true => text.to_owned(),
+                false => std::fs::read_to_string(&modules[index].path).unwrap_or_default(),
+            })
+            .collect();
+
+        let (resolve_errors, symbols) = resolve_symbols(&mut modules, natives);
+        // an import the loader could not load has been reported already
+        let resolve_errors: Vec<(usize, ResolveErr)> = resolve_errors
+            .into_iter()
+            .filter(|(_, e)| !matches!(e, ResolveErr::UnknownModule(..)))
+            .collect();
+        // the checker relies on every name having been resolved
+        let mut checked = false;
+        let types =
+            match resolve_errors.is_empty() {
+                true => {
+                    let (types, type_errors) =
+                        check_modules_partial(&modules, natives, &mut |_, _| None);
+                    checked = type_errors.is_empty();
+                    errors.extend(type_errors.into_iter().map(|(index, e)| {
+                        ModuleErr::Type(modules[index].path.clone(), Box::new(e))
+                    }));
+                    Some(types)
+                }
+                false => None,
+            };
+        errors.extend(
+            resolve_errors
+                .into_iter()
+                .map(|(index, e)| ModuleErr::Resolve(modules[index].path.clone(), e)),
+        );
+        if checked {
+            // only for the errors, which the identities on ints do not affect
+            let ints = HashSet::new();
+            for module in &modules {
+                if let Err(fold_errors) = optimize(&mut module.program.clone(), &ints) {
+                    errors.extend(
+                        fold_errors
+                            .into_iter()
+                            .map(|e| ModuleErr::Fold(module.path.clone(), e)),
+                    );
+                }
+            }
+        }
+
+        let mut classes = ClassCollector::default();
+        for (index, module) in modules.iter().enumerate() {
+            classes.module = index;
+            for stmt in &module.program {
+                classes.visit_stmt(stmt);
+            }
+        }
+        Self {
+            modules,
+            sources,
+            errors,
+            symbols,
+            types,
+            classes: classes.classes,
+            natives: natives
+                .iter()
+                .map(|native| (native.name.clone(), native.signature.clone()))
+                .collect(),
+        }
+    }
+
+    /// the index of the document's own module.
+    pub fn root(&self) -> usize {
+        self.modules.len().saturating_sub(1)
+    }
+    /// whether `name`, at `at`, covers `column` of `line` of the document, its end included so
+    /// that a cursor just past a name is still on it.
+    fn covers(&self, at: &Location, name: &str, line: usize, column: usize) -> bool {
+        at.module == self.root()
+            && at.line == line
+            && (at.column..=at.column + name.len()).contains(&column)
+    }
+    pub fn reference_at(&self, line: usize, column: usize) -> Option<&Reference> {
+        let references = &self.symbols.references;
+        references
+            .iter()
+            .find(|r| self.covers(&r.at, &r.name, line, column))
+    }
+    /// the declaration of the name at `line` and `column` of the document, which may be where it
+    /// is declared or a use of it.
+    pub fn declaration_at(&self, line: usize, column: usize) -> Option<&Declaration> {
+        match self.reference_at(line, column) {
+            Some(reference) => self.declaration(reference.decl?),
+            None => {
+                let declarations = &self.symbols.declarations;
+                declarations
+                    .iter()
+                    .find(|d| self.covers(&d.at, &d.name, line, column))
+            }
+        }
+    }
+    pub fn declaration(&self, at: Location) -> Option<&Declaration> {
+        self.symbols.declarations.iter().find(|d| d.at == at)
+    }
+    /// every use of `decl`, and `decl` itself if `include_declaration` is set, in source order.
+    pub fn references(&self, decl: &Declaration, include_declaration: bool) -> Vec<Location> {
+        let uses = self
+            .symbols
+            .references
+            .iter()
+            .filter(|r| r.decl == Some(decl.at));
+        let mut locations: Vec<Location> = uses.map(|r| r.at).collect();
+        if include_declaration {
+            locations.push(decl.at);
+        }
+        locations.sort();
+        locations
+    }
+
+    /// what the name at `line` and `column` of the document is, e.g. `area: fn(float) -> float`.
+    pub fn hover(&self, line: usize, column: usize) -> Option<String> {
+        if let Some(Reference {
+            name, decl: None, ..
+        }) = self.reference_at(line, column)
+        {
+            let (_, signature) = self.natives.iter().find(|(native, _)| native == name)?;
+            return Some(format!("{}: {}", name, signature));
+        }
+        let decl = self.declaration_at(line, column)?;
+        Some(match (decl.kind, self.type_of(decl)) {
+            (SymbolKind::Module, _) => format!("module {}", decl.name),
+            (SymbolKind::Class, _) => format!("class {}", decl.name),
+            (_, Some(scheme)) => format!("{}: {}", decl.name, scheme),
+            (_, None) => decl.name.clone(),
+        })
+    }
+    /// the type checking found for `decl`. Loop variables and caught exceptions have none.
+    fn type_of(&self, decl: &Declaration) -> Option<Scheme> {
+        let bindings = &self.types.as_ref()?[decl.at.module].bindings;
+        let binding = |name: &str, line: usize| {
+            bindings
+                .iter()
+                .find(|b| b.name == name && b.line == line)
+                .map(|b| b.scheme.clone())
+        };
+        if decl.kind != SymbolKind::Parameter {
+            return binding(&decl.name, decl.at.line);
+        }
+        // a parameter's type is part of that of its function
+        let mut owner = ParamOwner {
+            at: decl.at,
+            class: None,
+            found: None,
+        };
+        for stmt in &self.modules[decl.at.module].program {
+            owner.visit_stmt(stmt);
+        }
+        let (function, line, index) = owner.found?;
+        let scheme = binding(&function, line)?;
+        let Type::Fn { params, .. } = scheme.ty else {
+            return None;
+        };
+        Some(Scheme {
+            ty: params.into_iter().nth(index)?,
+            ..scheme
+        })
+    }
+
+    /// the declarations at the top level of the document, with the methods of its classes and
+    /// interfaces.
+    pub fn outline(&self) -> Vec<Outline> {
+        let root = self.root();
+        let entry = |name: &Identifier, kind, lines, children| Outline {
+            name: name.name.clone(),
+            kind,
+            at: Location {
+                module: root,
+                line: name.line,
+                column: name.column,
+            },
+            lines,
+            children,
+        };
+        let Some(module) = self.modules.get(root) else {
+            return Vec::new();
+        };
+        module
+            .program
+            .iter()
+            .filter_map(|stmt| match &stmt.kind {
+                StmtKind::Fn(decl) => Some(entry(
+                    &decl.name,
+                    ItemKind::Function,
+                    (stmt.line, decl.body.end_line),
+                    Vec::new(),
+                )),
+                StmtKind::Class(decl) => {
+                    let methods = decl.methods.iter().map(|method| {
+                        let lines = (method.name.line, method.body.end_line);
+                        entry(&method.name, ItemKind::Method, lines, Vec::new())
+                    });
+                    let lines = (stmt.line, decl.end_line);
+                    Some(entry(&decl.name, ItemKind::Class, lines, methods.collect()))
+                }
+                StmtKind::Interface(decl) => {
+                    let methods = decl.methods.iter().map(|method| {
+                        let lines = (method.name.line, method.name.line);
+                        entry(&method.name, ItemKind::Method, lines, Vec::new())
+                    });
+                    let lines = (stmt.line, decl.end_line);
+                    Some(entry(
+                        &decl.name,
+                        ItemKind::Interface,
+                        lines,
+                        methods.collect(),
+                    ))
+                }
+                StmtKind::Let { name, .. } => Some(entry(
+                    name,
+                    ItemKind::Variable,
+                    (stmt.line, stmt.line),
+                    Vec::new(),
+                )),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// what may be written at `line` and `column` of the document: the members of what comes
+    /// before a `.`, or else keywords and the names in scope.
+    pub fn completions(&self, line: usize, column: usize) -> Vec<Completion> {
+        let is_name = |c: char| c.is_alphanumeric() || c == '_';
+        let text = self.sources[self.root()].lines().nth(line.wrapping_sub(1));
+        let text = text.unwrap_or("");
+        let before = text.get(..column.saturating_sub(1)).unwrap_or(text);
+        match before.trim_end_matches(is_name).strip_suffix('.') {
+            Some(object) => {
+                let object = &object[object.trim_end_matches(is_name).len()..];
+                self.members(object, line).unwrap_or_default()
+            }
+            None => self.names(line),
+        }
+    }
+    /// the declarations of the document in scope at `line`, innermost first.
+    fn visible(&self, line: usize) -> Vec<&Declaration> {
+        let mut visible: Vec<&Declaration> = self
+            .symbols
+            .declarations
+            .iter()
+            .filter(|d| d.at.module == self.root())
+            .filter(|d| d.scope_end == usize::MAX || (d.at.line <= line && line <= d.scope_end))
+            .collect();
+        visible.sort_by_key(|d| (d.scope_end, std::cmp::Reverse(d.at.line)));
+        visible
+    }
+    fn names(&self, line: usize) -> Vec<Completion> {
+        let mut completions: Vec<Completion> = KEYWORDS
+            .iter()
+            .map(|keyword| Completion {
+                label: keyword.to_string(),
+                kind: ItemKind::Keyword,
+                detail: None,
+            })
+            .collect();
+        let mut seen = HashSet::new();
+        for decl in self.visible(line) {
+            if seen.insert(decl.name.as_str()) {
+                let kind = match decl.kind {
+                    SymbolKind::Function => ItemKind::Function,
+                    SymbolKind::Class => ItemKind::Class,
+                    SymbolKind::Module => ItemKind::Module,
+                    _ => ItemKind::Variable,
+                };
+                completions.push(Completion {
+                    label: decl.name.clone(),
+                    kind,
+                    detail: self.type_of(decl).map(|scheme| scheme.to_string()),
+                });
+            }
+        }
+        for (name, signature) in &self.natives {
+            if seen.insert(name) {
+                completions.push(Completion {
+                    label: name.clone(),
+                    kind: ItemKind::Function,
+                    detail: Some(signature.to_string()),
+                });
+            }
+        }
+        completions
+    }
+    /// the members of `object`, as written at `line`: the exports of an imported module, or the
+    /// methods and fields of `this` or of a variable holding an instance.
+    fn members(&self, object: &str, line: usize) -> Option<Vec<Completion>> {
+        let root = self.root();
+        if object == "this" {
+            let class = self.classes.iter().rev().find(|class| {
+                class.module == root && class.lines.0 <= line && line <= class.lines.1
+            })?;
+            return Some(self.class_members(class));
+        }
+        let decl = self.visible(line).into_iter().find(|d| d.name == object)?;
+        if decl.kind == SymbolKind::Module {
+            return self.exports(decl);
+        }
+        let Type::Instance(name, _) = self.type_of(decl)?.ty else {
+            return None;
+        };
+        let class = self.classes.iter().find(|class| class.name == name)?;
+        Some(self.class_members(class))
+    }
+    fn class_members(&self, class: &ClassMembers) -> Vec<Completion> {
+        let methods = class.methods.iter().map(|method| Completion {
+            label: method.clone(),
+            kind: ItemKind::Method,
+            detail: self.types.as_ref().and_then(|types| {
+                let name = format!("{}.{}", class.name, method);
+                let bindings = &types[class.module].bindings;
+                let binding = bindings.iter().find(|b| b.name == name)?;
+                Some(binding.scheme.to_string())
+            }),
+        });
+        let fields = class.fields.iter().map(|field| Completion {
+            label: field.clone(),
+            kind: ItemKind::Field,
+            detail: None,
+        });
+        methods.chain(fields).collect()
+    }
+    /// what the module that `alias`, an import of the document, names exports.
+    fn exports(&self, alias: &Declaration) -> Option<Vec<Completion>> {
+        let root = &self.modules[self.root()];
+        let path = root.program.iter().find_map(|stmt| match &stmt.kind {
+            StmtKind::Import { path, name } if name.line == alias.at.line => Some(path),
+            _ => None,
+        })?;
+        let index = *root.imports.get(path)?;
+        let module = &self.modules[index];
+        let exported = module.program.iter().filter(|stmt| stmt.exported);
+        let completions = exported.filter_map(|stmt| {
+            let (name, kind) = match &stmt.kind {
+                StmtKind::Fn(decl) => (&decl.name, ItemKind::Function),
+                StmtKind::Class(decl) => (&decl.name, ItemKind::Class),
+                StmtKind::Let { name, .. } => (name, ItemKind::Variable),
+                _ => return None,
+            };
+            let decl = self.declaration(Location {
+                module: index,
+                line: name.line,
+                column: name.column,
+            });
+            Some(Completion {
+                label: name.name.clone(),
+                kind,
+                detail: decl
+                    .and_then(|decl| self.type_of(decl))
+                    .map(|scheme| scheme.to_string()),
+            })
+        });
+        Some(completions.collect())
+    }
+}
+
+#[derive(Default)]
+struct ClassCollector {
+    module: usize,
+    classes: Vec<ClassMembers>,
+}
+
+impl Visitor for ClassCollector {
+    fn visit_class(&mut self, decl: &ClassDecl) {
+        let mut fields = FieldCollector::default();
+        for method in &decl.methods {
+            fields.visit_fn(method);
+        }
+        self.classes.push(ClassMembers {
+            name: decl.name.name.clone(),
+            module: self.module,
+            lines: (decl.name.line, decl.end_line),
+            methods: decl
+                .methods
+                .iter()
+                .map(|method| method.name.name.clone())
+                .filter(|name| name != Class::INITIALIZER)
+                .collect(),
+            fields: fields.fields,
+        });
+        walk_class(self, decl);
+    }
+}
+
+/// the fields a class's methods assign to.
+#[derive(Default)]
+struct FieldCollector {
+    fields: Vec<String>,
+}
+
+impl Visitor for FieldCollector {
+    fn visit_set(&mut self, object: &Expr, name: &Identifier, value: &Expr) {
+        if matches!(object, Expr::This(_)) && !self.fields.contains(&name.name) {
+            self.fields.push(name.name.clone());
+        }
+        walk_set(self, object, name, value);
+    }
+}
+
+/// finds the function declaring the parameter at `at`: its binding's name and line, and which
+/// parameter it is.
+struct ParamOwner {
+    at: Location,
+    /// the class whose methods are being visited.
+    class: Option<String>,
+    found: Option<(String, usize, usize)>,
+}
+
+impl Visitor for ParamOwner {
+    fn visit_fn(&mut self, decl: &FnDecl) {
+        // functions nested in a method are not methods
+        let class = self.class.take();
+        let name = match &class {
+            Some(class) => format!("{}.{}", class, decl.name.name),
+            None => decl.name.name.clone(),
+        };
+        let at = (self.at.line, self.at.column);
+        let params = &decl.params;
+        if let Some(index) = params
+            .iter()
+            .position(|p| (p.name.line, p.name.column) == at)
+        {
+            self.found = Some((name, decl.name.line, index));
+        }
+        walk_fn(self, decl);
+        self.class = class;
+    }
+    fn visit_class(&mut self, decl: &ClassDecl) {
+        let outer = self.class.replace(decl.name.name.clone());
+        walk_class(self, decl);
+        self.class = outer;
+    }
+}
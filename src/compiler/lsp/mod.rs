@@ -0,0 +1,515 @@
+//! A language server, for editors to show what the compiler makes of a program as it is being
+//! written. It speaks the Language Server Protocol over a pair of streams, normally stdin and
+//! stdout: JSON-RPC messages, each after a `Content-Length` header. Every change to a document
+//! analyses it afresh, see `analysis`, and publishes its diagnostics and those of the modules it
+//! imports; hovers, definitions, references, outlines, completions and semantic tokens are
+//! answered from the last analysis. Documents are synced whole, and the modules they import are
+//! read from disk.
+
+pub mod analysis;
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::HashMap,
+    io::{BufRead, Write},
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    compiler::{highlight, natives::Natives, resolver::Location},
+    util::json::Json,
+};
+use analysis::{Analysis, ItemKind, Outline};
+
+/// the JSON-RPC error codes the server answers with.
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Serves one client, reading its messages from `input` and writing replies and notifications to
+/// `output`, until it sends `exit` or closes `input`. Imports are looked for next to the importing
+/// document and then in `search_path`, as by `modules::Loader`.
+pub fn serve(
+    mut input: impl BufRead,
+    mut output: impl Write,
+    search_path: Vec<PathBuf>,
+) -> std::io::Result<()> {
+    let mut server = Server::new(search_path);
+    while let Some(body) = read_message(&mut input)? {
+        let replies = match Json::parse(&body) {
+            Some(message) => server.handle(&message),
+            None => vec![error(Json::Null, PARSE_ERROR, "invalid JSON")],
+        };
+        for reply in replies {
+            write_message(&mut output, &reply)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// the body of the next message, or `None` once the client closed the stream.
+pub fn read_message(input: &mut impl BufRead) -> std::io::Result<Option<String>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse::<usize>().ok();
+            }
+        }
+    }
+    let Some(length) = length else {
+        let e = std::io::Error::new(std::io::ErrorKind::InvalidData, "missing Content-Length");
+        return Err(e);
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    String::from_utf8(body)
+        .map(Some)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+pub fn write_message(output: &mut impl Write, message: &Json) -> std::io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    output.flush()
+}
+
+struct Server {
+    search_path: Vec<PathBuf>,
+    natives: Natives,
+    /// the open documents by URI.
+    documents: HashMap<String, Analysis>,
+    /// the URIs each open document last published diagnostics for, so that they can be cleared.
+    published: HashMap<String, Vec<String>>,
+    shut_down: bool,
+    exited: bool,
+}
+
+impl Server {
+    fn new(search_path: Vec<PathBuf>) -> Self {
+        Self {
+            search_path,
+            natives: Natives::standard(),
+            documents: HashMap::new(),
+            published: HashMap::new(),
+            shut_down: false,
+            exited: false,
+        }
+    }
+
+    /// the messages to send in reply to `message`.
+    fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").as_str().unwrap_or("");
+        let params = message.get("params");
+        let id = message.get("id");
+        if id.is_null() {
+            return self.notification(method, params);
+        }
+        let result = match method {
+            "initialize" => Ok(capabilities()),
+            "shutdown" => {
+                self.shut_down = true;
+                Ok(Json::Null)
+            }
+            _ if self.shut_down => Err((INVALID_REQUEST, "the server is shutting down".into())),
+            "textDocument/hover" => self.at_position(params, Self::hover),
+            "textDocument/definition" => self.at_position(params, Self::definition),
+            "textDocument/references" => self.at_position(params, Self::references),
+            "textDocument/documentSymbol" => self.document(params).map(Self::document_symbols),
+            "textDocument/completion" => self.at_position(params, Self::completion),
+            "textDocument/semanticTokens/full" => self.document(params).map(Self::semantic_tokens),
+            _ => Err((METHOD_NOT_FOUND, format!("unknown method '{}'", method))),
+        };
+        let reply = match result {
+            Ok(result) => Json::object([
+                ("jsonrpc", "2.0".into()),
+                ("id", id.clone()),
+                ("result", result),
+            ]),
+            Err((code, message)) => error(id.clone(), code, &message),
+        };
+        vec![reply]
+    }
+    fn notification(&mut self, method: &str, params: &Json) -> Vec<Json> {
+        let uri = params.get("textDocument").get("uri").as_str();
+        match (method, uri) {
+            ("exit", _) => {
+                self.exited = true;
+                Vec::new()
+            }
+            ("textDocument/didOpen", Some(uri)) => {
+                let text = params.get("textDocument").get("text").as_str();
+                self.update(uri, text.unwrap_or(""))
+            }
+            // the server asks for whole documents, so the last change has all of the text
+            ("textDocument/didChange", Some(uri)) => {
+                let changes = params.get("contentChanges").as_array().unwrap_or(&[]);
+                match changes
+                    .last()
+                    .and_then(|change| change.get("text").as_str())
+                {
+                    Some(text) => self.update(uri, text),
+                    None => Vec::new(),
+                }
+            }
+            ("textDocument/didClose", Some(uri)) => {
+                self.documents.remove(uri);
+                let published = self.published.remove(uri).unwrap_or_default();
+                published
+                    .into_iter()
+                    .map(|uri| diagnostics(&uri, Vec::new()))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// analyses the document anew and publishes its diagnostics, grouped by the file they are in.
+    fn update(&mut self, uri: &str, text: &str) -> Vec<Json> {
+        let path = uri_to_path(uri);
+        let analysis = Analysis::new(text, &path, &self.search_path, &self.natives);
+        let mut by_uri: Vec<(String, Vec<Json>)> = vec![(uri.to_owned(), Vec::new())];
+        for e in &analysis.errors {
+            let (file, line) = e.location();
+            let file_uri = match file == path {
+                true => uri.to_owned(),
+                false => path_to_uri(file),
+            };
+            let source = analysis
+                .modules
+                .iter()
+                .position(|m| m.path == file)
+                .map_or("", |index| analysis.sources[index].as_str());
+            let diagnostic = Json::object([
+                ("range", line_range(source, line.unwrap_or(1))),
+                ("severity", 1usize.into()),
+                ("source", "compiler".into()),
+                ("message", e.message().into()),
+            ]);
+            match by_uri.iter_mut().find(|(u, _)| *u == file_uri) {
+                Some((_, group)) => group.push(diagnostic),
+                None => by_uri.push((file_uri, vec![diagnostic])),
+            }
+        }
+        let uris: Vec<String> = by_uri.iter().map(|(u, _)| u.clone()).collect();
+        let previous = self.published.insert(uri.to_owned(), uris.clone());
+        // files that had diagnostics and now have none
+        let cleared = previous
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|u| !uris.contains(u))
+            .map(|u| (u, Vec::new()));
+        let messages = by_uri
+            .into_iter()
+            .chain(cleared)
+            .map(|(u, group)| diagnostics(&u, group))
+            .collect();
+        self.documents.insert(uri.to_owned(), analysis);
+        messages
+    }
+
+    fn document(&self, params: &Json) -> Result<&Analysis, (i64, String)> {
+        let uri = params.get("textDocument").get("uri").as_str();
+        let uri = uri.ok_or((INVALID_PARAMS, "missing textDocument.uri".to_owned()))?;
+        self.documents
+            .get(uri)
+            .ok_or((INVALID_PARAMS, format!("'{}' is not open", uri)))
+    }
+    /// answers a request about a position in a document with `answer`, given the position as the
+    /// compiler counts lines and columns.
+    fn at_position(
+        &self,
+        params: &Json,
+        answer: fn(&Analysis, &Json, usize, usize) -> Json,
+    ) -> Result<Json, (i64, String)> {
+        let analysis = self.document(params)?;
+        let position = params.get("position");
+        let (Some(line), Some(character)) = (
+            position.get("line").as_usize(),
+            position.get("character").as_usize(),
+        ) else {
+            return Err((INVALID_PARAMS, "missing position".to_owned()));
+        };
+        let column = byte_column(&analysis.sources[analysis.root()], line + 1, character);
+        Ok(answer(analysis, params, line + 1, column))
+    }
+
+    fn hover(analysis: &Analysis, _: &Json, line: usize, column: usize) -> Json {
+        match analysis.hover(line, column) {
+            Some(text) => Json::object([(
+                "contents",
+                Json::object([
+                    ("kind", "markdown".into()),
+                    ("value", format!("```\n{}\n```", text).into()),
+                ]),
+            )]),
+            None => Json::Null,
+        }
+    }
+    fn definition(analysis: &Analysis, _: &Json, line: usize, column: usize) -> Json {
+        match analysis.declaration_at(line, column) {
+            Some(decl) => location(analysis, decl.at, decl.name.len()),
+            None => Json::Null,
+        }
+    }
+    fn references(analysis: &Analysis, params: &Json, line: usize, column: usize) -> Json {
+        let Some(decl) = analysis.declaration_at(line, column) else {
+            return Json::Array(Vec::new());
+        };
+        let include = params.get("context").get("includeDeclaration");
+        let references = analysis.references(decl, include.as_bool().unwrap_or(true));
+        references
+            .into_iter()
+            .map(|at| location(analysis, at, decl.name.len()))
+            .collect::<Vec<Json>>()
+            .into()
+    }
+    fn document_symbols(analysis: &Analysis) -> Json {
+        fn symbol(source: &str, outline: Outline) -> Json {
+            let (first, last) = outline.lines;
+            let whole = Json::object([
+                ("start", position(source, first, 1)),
+                ("end", line_end(source, last)),
+            ]);
+            let name = range(source, outline.at, outline.name.len());
+            let children: Vec<Json> = outline
+                .children
+                .into_iter()
+                .map(|child| symbol(source, child))
+                .collect();
+            Json::object([
+                ("name", outline.name.into()),
+                ("kind", symbol_kind(outline.kind).into()),
+                ("range", whole),
+                ("selectionRange", name),
+                ("children", children.into()),
+            ])
+        }
+        let source = &analysis.sources[analysis.root()];
+        let outline = analysis.outline().into_iter();
+        outline
+            .map(|outline| symbol(source, outline))
+            .collect::<Vec<Json>>()
+            .into()
+    }
+    fn semantic_tokens(analysis: &Analysis) -> Json {
+        let root = analysis.root();
+        let source = &analysis.sources[root];
+        let spans = highlight::classify(source, &analysis.symbols, root);
+        let data = highlight::semantic_tokens(source, &spans);
+        let data: Vec<Json> = data.into_iter().map(Json::from).collect();
+        Json::object([("data", data.into())])
+    }
+    fn completion(analysis: &Analysis, _: &Json, line: usize, column: usize) -> Json {
+        let completions = analysis.completions(line, column);
+        completions
+            .into_iter()
+            .map(|completion| {
+                Json::object([
+                    ("label", completion.label.into()),
+                    ("kind", completion_kind(completion.kind).into()),
+                    ("detail", completion.detail.into()),
+                ])
+            })
+            .collect::<Vec<Json>>()
+            .into()
+    }
+}
+
+fn capabilities() -> Json {
+    let token_types: Vec<Json> = highlight::TOKEN_TYPES.iter().map(|&t| t.into()).collect();
+    Json::object([
+        (
+            "capabilities",
+            Json::object([
+                // whole documents on every change
+                ("textDocumentSync", 1usize.into()),
+                ("hoverProvider", true.into()),
+                ("definitionProvider", true.into()),
+                ("referencesProvider", true.into()),
+                ("documentSymbolProvider", true.into()),
+                (
+                    "completionProvider",
+                    Json::object([("triggerCharacters", vec![".".into()].into())]),
+                ),
+                (
+                    "semanticTokensProvider",
+                    Json::object([
+                        (
+                            "legend",
+                            Json::object([
+                                ("tokenTypes", token_types.into()),
+                                ("tokenModifiers", Json::Array(Vec::new())),
+                            ]),
+                        ),
+                        ("full", true.into()),
+                    ]),
+                ),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object([
+                ("name", env!("CARGO_PKG_NAME").into()),
+                ("version", env!("CARGO_PKG_VERSION").into()),
+            ]),
+        ),
+    ])
+}
+
+fn error(id: Json, code: i64, message: &str) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id),
+        (
+            "error",
+            Json::object([("code", code.into()), ("message", message.into())]),
+        ),
+    ])
+}
+
+fn diagnostics(uri: &str, diagnostics: Vec<Json>) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", "textDocument/publishDiagnostics".into()),
+        (
+            "params",
+            Json::object([("uri", uri.into()), ("diagnostics", diagnostics.into())]),
+        ),
+    ])
+}
+
+fn symbol_kind(kind: ItemKind) -> usize {
+    match kind {
+        ItemKind::Module => 2,
+        ItemKind::Class => 5,
+        ItemKind::Method => 6,
+        ItemKind::Field => 8,
+        ItemKind::Interface => 11,
+        ItemKind::Function => 12,
+        ItemKind::Variable | ItemKind::Keyword => 13,
+    }
+}
+
+fn completion_kind(kind: ItemKind) -> usize {
+    match kind {
+        ItemKind::Method => 2,
+        ItemKind::Function => 3,
+        ItemKind::Field => 5,
+        ItemKind::Variable => 6,
+        ItemKind::Class => 7,
+        ItemKind::Interface => 8,
+        ItemKind::Module => 9,
+        ItemKind::Keyword => 14,
+    }
+}
+
+/// Positions in the protocol count lines and characters from 0, and characters in UTF-16 code
+/// units; the compiler counts lines and columns from 1, and columns in bytes.
+fn position(source: &str, line: usize, column: usize) -> Json {
+    let text = source.lines().nth(line.wrapping_sub(1)).unwrap_or("");
+    let before = text.get(..column.saturating_sub(1)).unwrap_or(text);
+    Json::object([
+        ("line", line.saturating_sub(1).into()),
+        ("character", before.encode_utf16().count().into()),
+    ])
+}
+
+fn line_end(source: &str, line: usize) -> Json {
+    let text = source.lines().nth(line.wrapping_sub(1)).unwrap_or("");
+    position(source, line, text.len() + 1)
+}
+
+/// the range of a name `len` bytes long at `at`.
+fn range(source: &str, at: Location, len: usize) -> Json {
+    Json::object([
+        ("start", position(source, at.line, at.column)),
+        ("end", position(source, at.line, at.column + len)),
+    ])
+}
+
+/// the range of a line, but for its indentation, for errors that only know their line.
+fn line_range(source: &str, line: usize) -> Json {
+    let text = source.lines().nth(line.wrapping_sub(1)).unwrap_or("");
+    let indent = text.len() - text.trim_start().len();
+    Json::object([
+        ("start", position(source, line, indent + 1)),
+        ("end", line_end(source, line)),
+    ])
+}
+
+/// the byte column, counting from 1, of the UTF-16 `character` of `line`.
+fn byte_column(source: &str, line: usize, character: usize) -> usize {
+    let text = source.lines().nth(line - 1).unwrap_or("");
+    let mut units = 0;
+    for (index, c) in text.char_indices() {
+        if units >= character {
+            return index + 1;
+        }
+        units += c.len_utf16();
+    }
+    text.len() + 1
+}
+
+fn location(analysis: &Analysis, at: Location, len: usize) -> Json {
+    let module = &analysis.modules[at.module];
+    Json::object([
+        ("uri", path_to_uri(&module.path).into()),
+        ("range", range(&analysis.sources[at.module], at, len)),
+    ])
+}
+
+/// the path of a `file:` URI, decoding its escapes. Other URIs are taken as paths.
+pub fn uri_to_path(uri: &str) -> PathBuf {
+    let path = uri.strip_prefix("file://").unwrap_or(uri);
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = tail
+            .get(..2)
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match (byte, escaped) {
+            (b'%', Some(decoded)) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            _ => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    PathBuf::from(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// the `file:` URI of a path, escaping all but the characters URIs allow unescaped.
+pub fn path_to_uri(path: &Path) -> String {
+    let path = match path.is_absolute() {
+        true => path.to_owned(),
+        false => std::env::current_dir().unwrap_or_default().join(path),
+    };
+    let mut uri = String::from("file://");
+    for &byte in path.to_string_lossy().as_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                uri.push(byte as char)
+            }
+            _ => uri.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    uri
+}
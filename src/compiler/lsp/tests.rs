@@ -0,0 +1,269 @@
+use std::path::PathBuf;
+
+use super::{
+    analysis::{Analysis, ItemKind},
+    path_to_uri, read_message, serve, uri_to_path, write_message,
+};
+use crate::{
+    compiler::{highlight::TOKEN_TYPES, modules::ModuleErr, natives::Natives, testing::dir},
+    util::json::Json,
+};
+
+const GEOMETRY: &str = "export fn area(r: float) -> float {
+    return r * r * 3.14;
+}
+";
+
+const MAIN: &str = "import \"geometry\" as geo;
+class Point {
+    fn init(x, y) {
+        this.x = x;
+        this.y = y;
+    }
+    fn norm() {
+        return this.x * this.x + this.y * this.y;
+    }
+}
+fn double(n) {
+    return n * 2;
+}
+let p = Point(1, 2);
+print double(3) + len(\"ab\");
+print geo.area(2.0);
+";
+
+fn analyse(name: &str, text: &str) -> Analysis {
+    let dir = dir(name, &[("geometry.txt", GEOMETRY)]);
+    Analysis::new(text, &dir.join("main.txt"), &[], &Natives::standard())
+}
+
+fn labels(analysis: &Analysis, line: usize, column: usize) -> Vec<String> {
+    let completions = analysis.completions(line, column);
+    completions.into_iter().map(|c| c.label).collect()
+}
+
+#[test]
+fn test_hover_shows_inferred_types() {
+    let analysis = analyse("hover", MAIN);
+    assert!(analysis.errors.is_empty(), "{:?}", analysis.errors);
+    let hover = |line, column| analysis.hover(line, column);
+    assert_eq!(
+        hover(11, 4).as_deref(),
+        Some("double: fn('a) -> 'a where 'a: number")
+    );
+    assert_eq!(hover(11, 11).as_deref(), Some("n: 'a where 'a: number"));
+    assert_eq!(hover(14, 5).as_deref(), Some("p: Point"));
+    assert_eq!(hover(14, 9).as_deref(), Some("class Point"));
+    assert_eq!(hover(15, 21).as_deref(), Some("len: fn(any) -> int"));
+    assert_eq!(hover(16, 7).as_deref(), Some("module geo"));
+    assert_eq!(hover(16, 11).as_deref(), Some("area: fn(float) -> float"));
+    assert_eq!(hover(15, 1), None);
+}
+
+#[test]
+fn test_hover_keeps_types_despite_type_errors() {
+    let text = format!("{MAIN}let bad = \"s\" - 1;\nlet q = double(3);\n");
+    let analysis = analyse("hover_errors", &text);
+    assert!(
+        matches!(analysis.errors.as_slice(), [ModuleErr::Type(..)]),
+        "{:?}",
+        analysis.errors
+    );
+    let hover = |line, column| analysis.hover(line, column);
+    assert_eq!(
+        hover(11, 4).as_deref(),
+        Some("double: fn('a) -> 'a where 'a: number")
+    );
+    assert_eq!(hover(14, 5).as_deref(), Some("p: Point"));
+    assert_eq!(hover(18, 5).as_deref(), Some("q: int"));
+}
+
+#[test]
+fn test_definition_and_references() {
+    let analysis = analyse("references", MAIN);
+    let decl = analysis.declaration_at(15, 8).unwrap();
+    assert_eq!(
+        (decl.name.as_str(), decl.at.line, decl.at.column),
+        ("double", 11, 4)
+    );
+    let lines: Vec<usize> = analysis
+        .references(decl, true)
+        .into_iter()
+        .map(|at| at.line)
+        .collect();
+    assert_eq!(lines, vec![11, 15]);
+    assert_eq!(analysis.references(decl, false).len(), 1);
+
+    // into the imported module
+    let area = analysis.declaration_at(16, 12).unwrap();
+    assert!(analysis.modules[area.at.module]
+        .path
+        .ends_with("geometry.txt"));
+    assert_eq!((area.at.line, area.at.column), (1, 11));
+}
+
+#[test]
+fn test_outline() {
+    let analysis = analyse("outline", MAIN);
+    let outline: Vec<(String, ItemKind, Vec<String>)> = analysis
+        .outline()
+        .into_iter()
+        .map(|o| {
+            (
+                o.name,
+                o.kind,
+                o.children.into_iter().map(|c| c.name).collect(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        outline,
+        vec![
+            (
+                "Point".into(),
+                ItemKind::Class,
+                vec!["init".into(), "norm".into()]
+            ),
+            ("double".into(), ItemKind::Function, vec![]),
+            ("p".into(), ItemKind::Variable, vec![]),
+        ]
+    );
+}
+
+#[test]
+fn test_completion_of_names_and_members() {
+    let text = MAIN.replace("print geo.area(2.0);\n", "print geo.\np.\nthis.\n");
+    let analysis = analyse("completion", &text);
+    // the incomplete lines run together into one error, but the rest of the program is still
+    // checked
+    let errors: Vec<Option<usize>> = analysis.errors.iter().map(|e| e.location().1).collect();
+    assert_eq!(errors, vec![Some(18)]);
+
+    assert_eq!(labels(&analysis, 16, 11), vec!["area"]);
+    assert_eq!(labels(&analysis, 17, 3), vec!["norm", "x", "y"]);
+    assert_eq!(labels(&analysis, 8, 21), vec!["norm", "x", "y"]);
+    assert!(labels(&analysis, 18, 6).is_empty());
+
+    let names = labels(&analysis, 12, 5);
+    for name in ["while", "n", "double", "Point", "p", "geo", "len"] {
+        assert!(names.iter().any(|n| n == name), "{} in {:?}", name, names);
+    }
+    // `x` is a parameter of `init`, out of scope in `double`
+    assert!(!names.iter().any(|n| n == "x"));
+}
+
+#[test]
+fn test_uris() {
+    let path = PathBuf::from("/tmp/a dir/é.txt");
+    let uri = path_to_uri(&path);
+    assert_eq!(uri, "file:///tmp/a%20dir/%C3%A9.txt");
+    assert_eq!(uri_to_path(&uri), path);
+}
+
+fn frame(messages: &[Json]) -> Vec<u8> {
+    let mut framed = Vec::new();
+    for message in messages {
+        write_message(&mut framed, message).unwrap();
+    }
+    framed
+}
+
+fn request(id: usize, method: &str, params: Json) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("id", id.into()),
+        ("method", method.into()),
+        ("params", params),
+    ])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object([
+        ("jsonrpc", "2.0".into()),
+        ("method", method.into()),
+        ("params", params),
+    ])
+}
+
+#[test]
+fn test_serve_over_a_stream() {
+    let dir = dir("serve", &[("geometry.txt", GEOMETRY)]);
+    let uri = path_to_uri(&dir.join("main.txt"));
+    let document = |text: &str| {
+        Json::object([(
+            "textDocument",
+            Json::object([("uri", uri.as_str().into()), ("text", text.into())]),
+        )])
+    };
+    let change = Json::object([
+        ("textDocument", Json::object([("uri", uri.as_str().into())])),
+        (
+            "contentChanges",
+            vec![Json::object([("text", MAIN.into())])].into(),
+        ),
+    ]);
+    let hover = Json::object([
+        ("textDocument", Json::object([("uri", uri.as_str().into())])),
+        (
+            "position",
+            Json::object([("line", 10usize.into()), ("character", 3usize.into())]),
+        ),
+    ]);
+    let input = frame(&[
+        request(1, "initialize", Json::object([])),
+        notification("initialized", Json::object([])),
+        notification("textDocument/didOpen", document("let x = ;\nprint y;\n")),
+        notification("textDocument/didChange", change),
+        request(2, "textDocument/hover", hover),
+        request(3, "textDocument/rename", Json::object([])),
+        request(4, "shutdown", Json::Null),
+        notification("exit", Json::Null),
+        request(5, "shutdown", Json::Null),
+    ]);
+    let mut output = Vec::new();
+    serve(input.as_slice(), &mut output, Vec::new()).unwrap();
+
+    let mut output = output.as_slice();
+    let mut messages = Vec::new();
+    while let Some(body) = read_message(&mut output).unwrap() {
+        messages.push(Json::parse(&body).unwrap());
+    }
+    // nothing is read after `exit`
+    assert_eq!(messages.len(), 6);
+    let capabilities = messages[0].get("result").get("capabilities");
+    assert_eq!(capabilities.get("hoverProvider").as_bool(), Some(true));
+    let legend = capabilities.get("semanticTokensProvider").get("legend");
+    assert_eq!(
+        legend.get("tokenTypes").as_array().map(<[Json]>::len),
+        Some(TOKEN_TYPES.len())
+    );
+
+    let opened = messages[1].get("params");
+    assert_eq!(opened.get("uri").as_str(), Some(uri.as_str()));
+    let diagnostics = opened.get("diagnostics").as_array().unwrap();
+    let messages_of = |d: &Json| d.get("message").as_str().map(str::to_owned);
+    assert_eq!(
+        diagnostics.iter().map(messages_of).collect::<Vec<_>>(),
+        vec![
+            Some("expected an expression".to_owned()),
+            Some("undefined variable 'y'".to_owned())
+        ]
+    );
+    assert_eq!(
+        diagnostics[0].get("range").to_string(),
+        r#"{"start":{"line":0,"character":0},"end":{"line":0,"character":9}}"#
+    );
+    let changed = messages[2].get("params").get("diagnostics");
+    assert_eq!(changed.as_array().map(<[Json]>::len), Some(0));
+
+    let hover = messages[3].get("result").get("contents").get("value");
+    assert_eq!(
+        hover.as_str(),
+        Some("```\ndouble: fn('a) -> 'a where 'a: number\n```")
+    );
+    assert_eq!(
+        messages[4].get("error").get("code").as_f64(),
+        Some(-32601.0)
+    );
+    assert!(messages[5].get("result").is_null());
+}
@@ -1,8 +1,29 @@
 pub mod ast;
+pub mod backend;
+pub mod checker;
+pub mod debugger;
+pub mod engine;
 // mod expr;
+pub mod eval;
+pub mod formatter;
+pub mod gc;
+pub mod highlight;
+pub mod interpreter;
+pub mod ir;
 pub mod lexer;
+pub mod limits;
+pub mod lsp;
+pub mod modules;
+pub mod natives;
+pub mod optimizer;
 pub mod parser;
 pub mod patterns;
-// mod statements;
-pub mod eval;
+pub mod profiler;
+pub mod resolver;
+pub mod statements;
+#[cfg(test)]
+mod testing;
+#[cfg(test)]
+mod tests;
 pub mod token;
+pub mod value;
@@ -0,0 +1,225 @@
+//! Artefacts that spare a build the work of modules it has seen before. Each module's artefact is
+//! named after a hash of its source and holds its parsed program and, once it has been checked,
+//! its types along with a key: a hash of what else checking it depended on, namely the natives
+//! and the public interfaces of the modules it imports. A module whose source is unchanged is not
+//! parsed again, and one whose key is unchanged as well is not checked again. A module that is
+//! checked again but whose public interface comes out the same leaves its importers' keys as they
+//! were, so a change to the body of a function does not spread past its own module.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use super::{
+    codec::{Decoder, Encoder},
+    Module,
+};
+use crate::compiler::{
+    checker::{Binding, Interface},
+    natives::Natives,
+    statements::stmt::StmtKind,
+};
+
+/// changes whenever the encoding of artefacts does, so that older ones are ignored.
+pub const FORMAT_VERSION: usize = 3;
+const MAGIC: &[u8] = b"MODC";
+
+/// FNV-1a, which unlike the standard library's hashers is the same from one run to the next.
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// A directory of artefacts.
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+    fn path(&self, source: u64) -> PathBuf {
+        self.dir.join(format!("{:016x}.bin", source))
+    }
+
+    /// the artefact of a module whose source hashes to `source`. One that cannot be read, or was
+    /// written by another version of the compiler, is as good as none.
+    pub fn read(&self, source: u64) -> Option<Artefact> {
+        Artefact::decode(&std::fs::read(self.path(source)).ok()?)
+    }
+    pub fn write(&self, source: u64, artefact: &Artefact) -> std::io::Result<()> {
+        std::fs::create_dir_all(&self.dir)?;
+        // renamed into place so that a build running alongside never reads half an artefact
+        let path = self.path(source);
+        let partial = path.with_extension(format!("{}.tmp", std::process::id()));
+        std::fs::write(&partial, artefact.encode())?;
+        std::fs::rename(partial, path)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Artefact {
+    /// the program as parsed, before resolving gives its names slots, encoded by `codec`.
+    pub program: Vec<u8>,
+    pub checked: Option<Checked>,
+}
+
+/// What checking a module found, and what it found it against.
+#[derive(Debug, Clone)]
+pub struct Checked {
+    /// see `key`.
+    pub key: u64,
+    pub interface: Interface,
+    pub bindings: Vec<Binding>,
+}
+
+impl Artefact {
+    fn encode(&self) -> Vec<u8> {
+        let mut encoder = Encoder::default();
+        encoder.out.extend_from_slice(MAGIC);
+        encoder.uint(FORMAT_VERSION);
+        encoder.bytes(&self.program);
+        encoder.bool(self.checked.is_some());
+        if let Some(checked) = &self.checked {
+            encoder.u64(checked.key);
+            encoder.interface(&checked.interface);
+            encoder.bindings(&checked.bindings);
+        }
+        encoder.out
+    }
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        let mut decoder = Decoder::new(bytes.strip_prefix(MAGIC)?);
+        if decoder.uint()? != FORMAT_VERSION {
+            return None;
+        }
+        let program = decoder.bytes()?.to_vec();
+        let checked = match decoder.bool()? {
+            true => Some(Checked {
+                key: decoder.u64()?,
+                interface: decoder.interface()?,
+                bindings: decoder.bindings()?,
+            }),
+            false => None,
+        };
+        decoder.is_empty().then_some(Self { program, checked })
+    }
+}
+
+/// What a `Loader` with a cache keeps of a module besides its program.
+#[derive(Debug)]
+pub struct Entry {
+    /// the hash of the module's source, which names its artefact.
+    pub source: u64,
+    /// whether the program was decoded from the artefact rather than parsed.
+    pub hit: bool,
+    /// the program as the loader found it, for the artefact the build will write.
+    pub(super) program: Vec<u8>,
+    pub(super) checked: Option<Checked>,
+}
+
+/// How much of a module's compilation a build was spared.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reuse {
+    /// parsed and checked.
+    Compiled,
+    /// decoded from its artefact but checked, its source being unchanged but not what it imports.
+    Rechecked,
+    /// decoded and given the types in its artefact.
+    Reused,
+}
+
+impl std::fmt::Display for Reuse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Reuse::Compiled => "compiled",
+            Reuse::Rechecked => "rechecked",
+            Reuse::Reused => "reused",
+        };
+        f.pad(name)
+    }
+}
+
+/// What compiling a module took, as `build --timings` reports it.
+#[derive(Debug, Clone)]
+pub struct Timing {
+    pub path: PathBuf,
+    pub reuse: Reuse,
+    /// parsing the module, or decoding its artefact.
+    pub load: Duration,
+    /// checking the module, or giving it the types in its artefact.
+    pub check: Duration,
+}
+
+/// the hash of what checking `module` depends on besides its own source: the natives, and the
+/// public interface of every module it imports, or `None` if one of those has no interface.
+pub fn key(module: &Module, natives: u64, public: &[Option<u64>]) -> Option<u64> {
+    let mut encoder = Encoder::default();
+    encoder.uint(FORMAT_VERSION);
+    encoder.u64(natives);
+    for (path, index) in sorted_imports(module) {
+        encoder.str(path);
+        encoder.u64(public[index]?);
+    }
+    Some(hash(&encoder.out))
+}
+
+/// the hash of what the modules importing `module` can see of it: the types of its exports and of
+/// the methods of all of its classes, whose instances may reach modules that do not import it
+/// through those that do, the methods its interfaces ask for, which bound type parameters in any
+/// module, and so what they can see of the modules it imports as well.
+pub fn public(module: &Module, interface: &Interface, public: &[Option<u64>]) -> Option<u64> {
+    let mut encoder = Encoder::default();
+    let declarations = module.program.iter().filter(|stmt| {
+        matches!(
+            stmt.kind,
+            StmtKind::Fn(_) | StmtKind::Class(_) | StmtKind::Let { .. }
+        )
+    });
+    for (stmt, scheme) in declarations.zip(&interface.globals) {
+        if stmt.exported {
+            encoder.scheme(scheme);
+        }
+    }
+    for scheme in interface.methods.iter().flatten() {
+        encoder.scheme(scheme);
+    }
+    for stmt in &module.program {
+        if let StmtKind::Interface(decl) = &stmt.kind {
+            encoder.signatures(decl);
+        }
+    }
+    for (_, index) in sorted_imports(module) {
+        encoder.u64(public[index]?);
+    }
+    Some(hash(&encoder.out))
+}
+
+/// the hash of the natives a program is checked against.
+pub fn natives(natives: &Natives) -> u64 {
+    let mut encoder = Encoder::default();
+    for native in natives.iter() {
+        encoder.str(&native.name);
+        encoder.scheme(&native.signature);
+    }
+    for ty in natives.types() {
+        encoder.str(ty);
+    }
+    hash(&encoder.out)
+}
+
+fn sorted_imports(module: &Module) -> Vec<(&str, usize)> {
+    let mut imports: Vec<(&str, usize)> = module
+        .imports
+        .iter()
+        .map(|(path, &index)| (path.as_str(), index))
+        .collect();
+    imports.sort();
+    imports
+}
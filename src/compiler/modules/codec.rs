@@ -0,0 +1,701 @@
+//! The binary form of parsed programs and their types that a `Cache` keeps. Integers are LEB128,
+//! strings their length and UTF-8 bytes, and every enum a tag byte, in declaration order,
+//! followed by its fields. Identifiers are written without slots: a cached program is resolved
+//! afresh with the modules it is loaded with.
+
+use std::rc::Rc;
+
+use crate::{
+    compiler::{
+        ast::{
+            expr::{BinaryOp, Expr, Identifier, LogicalOp, UnaryOp},
+            literal::Literal,
+            types::{Constraint, Quantified, Scheme, Type, TypeParam},
+        },
+        checker::{Binding, Interface},
+        statements::stmt::{
+            Block, Catch, ClassDecl, FnDecl, InterfaceDecl, MethodSig, Param, Stmt, StmtKind,
+        },
+    },
+    util::leb128,
+};
+
+#[derive(Default)]
+pub struct Encoder {
+    pub out: Vec<u8>,
+}
+
+impl Encoder {
+    pub fn uint(&mut self, value: usize) {
+        leb128::write_unsigned(&mut self.out, value as u64);
+    }
+    pub fn u64(&mut self, value: u64) {
+        leb128::write_unsigned(&mut self.out, value);
+    }
+    fn tag(&mut self, tag: u8) {
+        self.out.push(tag);
+    }
+    pub fn bool(&mut self, value: bool) {
+        self.out.push(value as u8);
+    }
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.uint(bytes.len());
+        self.out.extend_from_slice(bytes);
+    }
+    pub fn str(&mut self, s: &str) {
+        self.uint(s.len());
+        self.out.extend_from_slice(s.as_bytes());
+    }
+    fn list<T>(&mut self, items: &[T], mut item: impl FnMut(&mut Self, &T)) {
+        self.uint(items.len());
+        for i in items {
+            item(self, i);
+        }
+    }
+    fn option<T>(&mut self, value: Option<&T>, some: impl FnOnce(&mut Self, &T)) {
+        match value {
+            Some(value) => {
+                self.tag(1);
+                some(self, value);
+            }
+            None => self.tag(0),
+        }
+    }
+
+    pub fn program(&mut self, program: &[Stmt]) {
+        self.list(program, Self::stmt);
+    }
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.uint(stmt.line);
+        self.bool(stmt.exported);
+        match &stmt.kind {
+            StmtKind::Expr(e) => {
+                self.tag(0);
+                self.expr(e);
+            }
+            StmtKind::Print(e) => {
+                self.tag(1);
+                self.expr(e);
+            }
+            StmtKind::Let { name, ty, init } => {
+                self.tag(2);
+                self.ident(name);
+                self.option(ty.as_ref(), Self::ty);
+                self.option(init.as_ref(), Self::expr);
+            }
+            StmtKind::Block(block) => {
+                self.tag(3);
+                self.block(block);
+            }
+            StmtKind::If {
+                cond,
+                then_branch,
+                else_branch,
+            } => {
+                self.tag(4);
+                self.expr(cond);
+                self.block(then_branch);
+                self.option(else_branch.as_deref(), Self::stmt);
+            }
+            StmtKind::While { cond, body } => {
+                self.tag(5);
+                self.expr(cond);
+                self.block(body);
+            }
+            StmtKind::For {
+                name,
+                iterable,
+                body,
+            } => {
+                self.tag(6);
+                self.ident(name);
+                self.expr(iterable);
+                self.block(body);
+            }
+            StmtKind::Fn(decl) => {
+                self.tag(7);
+                self.function(decl);
+            }
+            StmtKind::Return(value) => {
+                self.tag(8);
+                self.option(value.as_ref(), Self::expr);
+            }
+            StmtKind::Throw(value) => {
+                self.tag(9);
+                self.expr(value);
+            }
+            StmtKind::Try {
+                body,
+                catch,
+                finally,
+            } => {
+                self.tag(10);
+                self.block(body);
+                self.option(catch.as_ref(), |e, catch| {
+                    e.ident(&catch.name);
+                    e.block(&catch.body);
+                });
+                self.option(finally.as_ref(), Self::block);
+            }
+            StmtKind::Class(decl) => {
+                self.tag(11);
+                self.ident(&decl.name);
+                self.list(&decl.type_params, Self::type_param);
+                self.list(&decl.methods, |e, method| e.function(method));
+                self.uint(decl.end_line);
+            }
+            StmtKind::Interface(decl) => {
+                self.tag(12);
+                self.ident(&decl.name);
+                self.list(&decl.methods, |e, method| {
+                    e.ident(&method.name);
+                    e.list(&method.params, Self::param);
+                    e.option(method.return_type.as_ref(), Self::ty);
+                });
+                self.uint(decl.end_line);
+            }
+            StmtKind::Import { path, name } => {
+                self.tag(13);
+                self.str(path);
+                self.ident(name);
+            }
+        }
+    }
+    fn block(&mut self, block: &Block) {
+        self.list(&block.stmts, Self::stmt);
+        self.uint(block.end_line);
+    }
+    fn function(&mut self, decl: &FnDecl) {
+        self.ident(&decl.name);
+        self.list(&decl.type_params, Self::type_param);
+        self.list(&decl.params, Self::param);
+        self.option(decl.return_type.as_ref(), Self::ty);
+        self.block(&decl.body);
+    }
+    fn param(&mut self, param: &Param) {
+        self.ident(&param.name);
+        self.option(param.ty.as_ref(), Self::ty);
+    }
+    fn ident(&mut self, ident: &Identifier) {
+        self.str(&ident.name);
+        self.uint(ident.line);
+        self.uint(ident.column);
+    }
+    fn expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::LiteralExpr(literal) => {
+                self.tag(0);
+                self.literal(literal);
+            }
+            Expr::Unary { op, rhs } => {
+                self.tag(1);
+                self.tag(*op as u8);
+                self.expr(rhs);
+            }
+            Expr::Binary { lhs, op, rhs } => {
+                self.tag(2);
+                self.expr(lhs);
+                self.tag(*op as u8);
+                self.expr(rhs);
+            }
+            Expr::Logical { lhs, op, rhs } => {
+                self.tag(3);
+                self.expr(lhs);
+                self.tag(*op as u8);
+                self.expr(rhs);
+            }
+            Expr::Grouping(inner) => {
+                self.tag(4);
+                self.expr(inner);
+            }
+            Expr::Variable(name) => {
+                self.tag(5);
+                self.ident(name);
+            }
+            Expr::Assign { name, value } => {
+                self.tag(6);
+                self.ident(name);
+                self.expr(value);
+            }
+            Expr::Call { callee, args, line } => {
+                self.tag(7);
+                self.expr(callee);
+                self.list(args, Self::expr);
+                self.uint(*line);
+            }
+            Expr::Get { object, name } => {
+                self.tag(8);
+                self.expr(object);
+                self.ident(name);
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                self.tag(9);
+                self.expr(object);
+                self.ident(name);
+                self.expr(value);
+            }
+            Expr::This(keyword) => {
+                self.tag(10);
+                self.ident(keyword);
+            }
+            Expr::List(elements) => {
+                self.tag(11);
+                self.list(elements, Self::expr);
+            }
+            Expr::Map(entries) => {
+                self.tag(12);
+                self.list(entries, |e, (key, value)| {
+                    e.expr(key);
+                    e.expr(value);
+                });
+            }
+            Expr::Index {
+                object,
+                index,
+                line,
+            } => {
+                self.tag(13);
+                self.expr(object);
+                self.expr(index);
+                self.uint(*line);
+            }
+            Expr::SetIndex {
+                object,
+                index,
+                value,
+                line,
+            } => {
+                self.tag(14);
+                self.expr(object);
+                self.expr(index);
+                self.expr(value);
+                self.uint(*line);
+            }
+        }
+    }
+    fn literal(&mut self, literal: &Literal) {
+        match literal {
+            Literal::Nil => self.tag(0),
+            Literal::Bool(b) => {
+                self.tag(1);
+                self.bool(*b);
+            }
+            Literal::Int(i) => {
+                self.tag(2);
+                leb128::write_signed(&mut self.out, *i as i64);
+            }
+            Literal::Float(f) => {
+                self.tag(3);
+                self.out.extend_from_slice(&f.to_le_bytes());
+            }
+            Literal::Str(s) => {
+                self.tag(4);
+                self.str(s);
+            }
+        }
+    }
+
+    pub fn ty(&mut self, ty: &Type) {
+        match ty {
+            Type::Any => self.tag(0),
+            Type::Nil => self.tag(1),
+            Type::Bool => self.tag(2),
+            Type::Int => self.tag(3),
+            Type::Float => self.tag(4),
+            Type::Str => self.tag(5),
+            Type::Fn { params, ret } => {
+                self.tag(6);
+                self.list(params, Self::ty);
+                self.ty(ret);
+            }
+            Type::List(elem) => {
+                self.tag(7);
+                self.ty(elem);
+            }
+            Type::Map(key, value) => {
+                self.tag(8);
+                self.ty(key);
+                self.ty(value);
+            }
+            Type::Instance(name, args) => {
+                self.tag(9);
+                self.str(name);
+                self.list(args, Self::ty);
+            }
+            Type::Param(name) => {
+                self.tag(10);
+                self.str(name);
+            }
+            Type::Var(id) => {
+                self.tag(11);
+                self.uint(*id);
+            }
+        }
+    }
+    fn type_param(&mut self, param: &TypeParam) {
+        self.str(&param.name);
+        self.list(&param.bounds, |e, bound| e.str(bound));
+    }
+    pub fn scheme(&mut self, scheme: &Scheme) {
+        self.list(&scheme.vars, |e, q| {
+            e.uint(q.id);
+            e.option(q.constraint.as_ref(), |e, c| e.tag(*c as u8));
+            e.list(&q.bounds, |e, bound| e.str(bound));
+            e.option(q.promotes.as_ref(), |e, (a, b)| {
+                e.uint(*a);
+                e.uint(*b);
+            });
+        });
+        self.list(&scheme.params, Self::type_param);
+        self.ty(&scheme.ty);
+    }
+    pub fn interface(&mut self, interface: &Interface) {
+        self.list(&interface.globals, Self::scheme);
+        self.list(&interface.methods, |e, methods| {
+            e.list(methods, Self::scheme)
+        });
+    }
+    /// the methods an interface asks for, but not where it is declared.
+    pub fn signatures(&mut self, decl: &InterfaceDecl) {
+        self.str(&decl.name.name);
+        self.list(&decl.methods, |e, method| {
+            e.str(&method.name.name);
+            e.list(&method.params, |e, param| {
+                e.option(param.ty.as_ref(), Self::ty)
+            });
+            e.option(method.return_type.as_ref(), Self::ty);
+        });
+    }
+    pub fn bindings(&mut self, bindings: &[Binding]) {
+        self.list(bindings, |e, binding| {
+            e.str(&binding.name);
+            e.uint(binding.line);
+            e.scheme(&binding.scheme);
+        });
+    }
+}
+
+/// Reads what an `Encoder` wrote, or `None` if the bytes are not that.
+pub struct Decoder<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes }
+    }
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+    pub fn u64(&mut self) -> Option<u64> {
+        let (value, len) = leb128::read_unsigned(self.bytes)?;
+        self.bytes = &self.bytes[len..];
+        Some(value)
+    }
+    pub fn uint(&mut self) -> Option<usize> {
+        self.u64().map(|value| value as usize)
+    }
+    fn tag(&mut self) -> Option<u8> {
+        let (&tag, rest) = self.bytes.split_first()?;
+        self.bytes = rest;
+        Some(tag)
+    }
+    pub fn bool(&mut self) -> Option<bool> {
+        match self.tag()? {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None,
+        }
+    }
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        if len > self.bytes.len() {
+            return None;
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Some(taken)
+    }
+    pub fn bytes(&mut self) -> Option<&'a [u8]> {
+        let len = self.uint()?;
+        self.take(len)
+    }
+    pub fn str(&mut self) -> Option<String> {
+        String::from_utf8(self.bytes()?.to_vec()).ok()
+    }
+    fn list<T>(&mut self, mut item: impl FnMut(&mut Self) -> Option<T>) -> Option<Vec<T>> {
+        let len = self.uint()?;
+        // every item takes at least a byte, which bounds what a corrupt length can allocate
+        let mut items = Vec::with_capacity(len.min(self.bytes.len()));
+        for _ in 0..len {
+            items.push(item(self)?);
+        }
+        Some(items)
+    }
+    fn option<T>(&mut self, some: impl FnOnce(&mut Self) -> Option<T>) -> Option<Option<T>> {
+        match self.tag()? {
+            0 => Some(None),
+            1 => some(self).map(Some),
+            _ => None,
+        }
+    }
+
+    pub fn program(&mut self) -> Option<Vec<Stmt>> {
+        self.list(Self::stmt)
+    }
+    fn stmt(&mut self) -> Option<Stmt> {
+        let line = self.uint()?;
+        let exported = self.bool()?;
+        let kind = match self.tag()? {
+            0 => StmtKind::Expr(self.expr()?),
+            1 => StmtKind::Print(self.expr()?),
+            2 => StmtKind::Let {
+                name: self.ident()?,
+                ty: self.option(Self::ty)?,
+                init: self.option(Self::expr)?,
+            },
+            3 => StmtKind::Block(self.block()?),
+            4 => StmtKind::If {
+                cond: self.expr()?,
+                then_branch: self.block()?,
+                else_branch: self.option(Self::stmt)?.map(Box::new),
+            },
+            5 => StmtKind::While {
+                cond: self.expr()?,
+                body: self.block()?,
+            },
+            6 => StmtKind::For {
+                name: self.ident()?,
+                iterable: self.expr()?,
+                body: self.block()?,
+            },
+            7 => StmtKind::Fn(Rc::new(self.function()?)),
+            8 => StmtKind::Return(self.option(Self::expr)?),
+            9 => StmtKind::Throw(self.expr()?),
+            10 => StmtKind::Try {
+                body: self.block()?,
+                catch: self.option(|d| {
+                    Some(Catch {
+                        name: d.ident()?,
+                        body: d.block()?,
+                    })
+                })?,
+                finally: self.option(Self::block)?,
+            },
+            11 => StmtKind::Class(ClassDecl {
+                name: self.ident()?,
+                type_params: self.list(Self::type_param)?,
+                methods: self.list(|d| d.function().map(Rc::new))?,
+                end_line: self.uint()?,
+            }),
+            12 => StmtKind::Interface(InterfaceDecl {
+                name: self.ident()?,
+                methods: self.list(|d| {
+                    Some(MethodSig {
+                        name: d.ident()?,
+                        params: d.list(Self::param)?,
+                        return_type: d.option(Self::ty)?,
+                    })
+                })?,
+                end_line: self.uint()?,
+            }),
+            13 => StmtKind::Import {
+                path: self.str()?,
+                name: self.ident()?,
+            },
+            _ => return None,
+        };
+        Some(Stmt {
+            kind,
+            line,
+            exported,
+        })
+    }
+    fn block(&mut self) -> Option<Block> {
+        Some(Block {
+            stmts: self.list(Self::stmt)?,
+            end_line: self.uint()?,
+        })
+    }
+    fn function(&mut self) -> Option<FnDecl> {
+        Some(FnDecl {
+            name: self.ident()?,
+            type_params: self.list(Self::type_param)?,
+            params: self.list(Self::param)?,
+            return_type: self.option(Self::ty)?,
+            body: self.block()?,
+        })
+    }
+    fn param(&mut self) -> Option<Param> {
+        Some(Param {
+            name: self.ident()?,
+            ty: self.option(Self::ty)?,
+        })
+    }
+    fn ident(&mut self) -> Option<Identifier> {
+        Some(Identifier {
+            name: self.str()?,
+            line: self.uint()?,
+            column: self.uint()?,
+            slot: None,
+        })
+    }
+    fn expr(&mut self) -> Option<Expr> {
+        let expr = match self.tag()? {
+            0 => Expr::LiteralExpr(self.literal()?),
+            1 => Expr::Unary {
+                op: self.unary_op()?,
+                rhs: Box::new(self.expr()?),
+            },
+            2 => Expr::Binary {
+                lhs: Box::new(self.expr()?),
+                op: self.binary_op()?,
+                rhs: Box::new(self.expr()?),
+            },
+            3 => Expr::Logical {
+                lhs: Box::new(self.expr()?),
+                op: self.logical_op()?,
+                rhs: Box::new(self.expr()?),
+            },
+            4 => Expr::Grouping(Box::new(self.expr()?)),
+            5 => Expr::Variable(self.ident()?),
+            6 => Expr::Assign {
+                name: self.ident()?,
+                value: Box::new(self.expr()?),
+            },
+            7 => Expr::Call {
+                callee: Box::new(self.expr()?),
+                args: self.list(Self::expr)?,
+                line: self.uint()?,
+            },
+            8 => Expr::Get {
+                object: Box::new(self.expr()?),
+                name: self.ident()?,
+            },
+            9 => Expr::Set {
+                object: Box::new(self.expr()?),
+                name: self.ident()?,
+                value: Box::new(self.expr()?),
+            },
+            10 => Expr::This(self.ident()?),
+            11 => Expr::List(self.list(Self::expr)?),
+            12 => Expr::Map(self.list(|d| Some((d.expr()?, d.expr()?)))?),
+            13 => Expr::Index {
+                object: Box::new(self.expr()?),
+                index: Box::new(self.expr()?),
+                line: self.uint()?,
+            },
+            14 => Expr::SetIndex {
+                object: Box::new(self.expr()?),
+                index: Box::new(self.expr()?),
+                value: Box::new(self.expr()?),
+                line: self.uint()?,
+            },
+            _ => return None,
+        };
+        Some(expr)
+    }
+    fn unary_op(&mut self) -> Option<UnaryOp> {
+        const OPS: [UnaryOp; 2] = [UnaryOp::Bang, UnaryOp::Negate];
+        OPS.get(self.tag()? as usize).copied()
+    }
+    fn binary_op(&mut self) -> Option<BinaryOp> {
+        const OPS: [BinaryOp; 11] = [
+            BinaryOp::Plus,
+            BinaryOp::Minus,
+            BinaryOp::Mult,
+            BinaryOp::Div,
+            BinaryOp::Eq,
+            BinaryOp::Gt,
+            BinaryOp::Lt,
+            BinaryOp::GtEq,
+            BinaryOp::LtEq,
+            BinaryOp::EqEq,
+            BinaryOp::BangEq,
+        ];
+        OPS.get(self.tag()? as usize).copied()
+    }
+    fn logical_op(&mut self) -> Option<LogicalOp> {
+        const OPS: [LogicalOp; 2] = [LogicalOp::And, LogicalOp::Or];
+        OPS.get(self.tag()? as usize).copied()
+    }
+    fn literal(&mut self) -> Option<Literal> {
+        let literal = match self.tag()? {
+            0 => Literal::Nil,
+            1 => Literal::Bool(self.bool()?),
+            2 => {
+                let (value, len) = leb128::read_signed(self.bytes)?;
+                self.bytes = &self.bytes[len..];
+                Literal::Int(i32::try_from(value).ok()?)
+            }
+            3 => Literal::Float(f32::from_le_bytes(self.take(4)?.try_into().ok()?)),
+            4 => Literal::Str(self.str()?),
+            _ => return None,
+        };
+        Some(literal)
+    }
+
+    fn ty(&mut self) -> Option<Type> {
+        let ty = match self.tag()? {
+            0 => Type::Any,
+            1 => Type::Nil,
+            2 => Type::Bool,
+            3 => Type::Int,
+            4 => Type::Float,
+            5 => Type::Str,
+            6 => Type::Fn {
+                params: self.list(Self::ty)?,
+                ret: Box::new(self.ty()?),
+            },
+            7 => Type::List(Box::new(self.ty()?)),
+            8 => Type::Map(Box::new(self.ty()?), Box::new(self.ty()?)),
+            9 => Type::Instance(self.str()?, self.list(Self::ty)?),
+            10 => Type::Param(self.str()?),
+            11 => Type::Var(self.uint()?),
+            _ => return None,
+        };
+        Some(ty)
+    }
+    fn type_param(&mut self) -> Option<TypeParam> {
+        Some(TypeParam {
+            name: self.str()?,
+            bounds: self.list(Self::str)?,
+        })
+    }
+    fn scheme(&mut self) -> Option<Scheme> {
+        Some(Scheme {
+            vars: self.list(|d| {
+                Some(Quantified {
+                    id: d.uint()?,
+                    constraint: d.option(|d| match d.tag()? {
+                        0 => Some(Constraint::Num),
+                        1 => Some(Constraint::Add),
+                        _ => None,
+                    })?,
+                    bounds: d.list(Self::str)?,
+                    promotes: d.option(|d| Some((d.uint()?, d.uint()?)))?,
+                })
+            })?,
+            params: self.list(Self::type_param)?,
+            ty: self.ty()?,
+        })
+    }
+    pub fn interface(&mut self) -> Option<Interface> {
+        Some(Interface {
+            globals: self.list(Self::scheme)?,
+            methods: self.list(|d| d.list(Self::scheme))?,
+        })
+    }
+    pub fn bindings(&mut self) -> Option<Vec<Binding>> {
+        self.list(|d| {
+            Some(Binding {
+                name: d.str()?,
+                line: d.uint()?,
+                scheme: d.scheme()?,
+            })
+        })
+    }
+}
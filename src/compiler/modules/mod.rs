@@ -0,0 +1,444 @@
+//! Programs spread over several files. `import "path" as name;` loads the module at `path` and
+//! lets the importing one use what it declares with `export` as `name.member`. A `Loader` reads the
+//! module a program starts from and everything it imports, each file once however many modules
+//! import it, and orders them so every module comes after the modules it imports. The modules are
+//! then resolved and checked together, and `link` makes one program of them to run or lower. A
+//! loader given a `Cache` keeps what it learns of each module there, for later builds to reuse.
+
+pub mod cache;
+pub mod codec;
+#[cfg(test)]
+mod tests;
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use crate::compiler::{
+    checker::{check_modules, Binding, Interface, ModuleTypes, TypeErr},
+    lexer::Lexer,
+    natives::Natives,
+    optimizer::{optimize, FoldErr},
+    parser::{ParseErr, Parser},
+    resolver::{resolve_modules, ResolveErr},
+    statements::stmt::{Stmt, StmtKind},
+    token::Token,
+};
+use cache::{Artefact, Cache, Checked, Entry, Reuse, Timing};
+use codec::{Decoder, Encoder};
+
+/// the extension an import may leave out.
+pub const EXTENSION: &str = "txt";
+
+#[derive(Debug)]
+pub struct Module {
+    /// the file, as the loader found it.
+    pub path: PathBuf,
+    /// what the names of its globals are qualified with, e.g. the `geometry` of `geometry.area`:
+    /// the file's stem, or nothing for the module the program starts from.
+    pub name: String,
+    pub program: Vec<Stmt>,
+    /// the module each of the program's imports loaded, by the path as written.
+    pub imports: HashMap<String, usize>,
+    /// how long parsing the module, or decoding its artefact, took.
+    pub load_time: Duration,
+    /// what the loader's cache had of the module, if it has a cache.
+    pub cached: Option<Entry>,
+}
+
+#[derive(Debug)]
+pub enum ModuleErr {
+    Io(PathBuf, std::io::Error),
+    /// the importing module, the path as written, and the line of the import.
+    NotFound(PathBuf, String, usize),
+    /// modules each importing the next, the last importing the first, which it ends with again.
+    Cycle(Vec<PathBuf>),
+    Parse(PathBuf, ParseErr),
+    Resolve(PathBuf, ResolveErr),
+    /// boxed, being much the largest.
+    Type(PathBuf, Box<TypeErr>),
+    Fold(PathBuf, FoldErr),
+}
+
+impl std::fmt::Display for ModuleErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ModuleErr::Io(path, e) => write!(f, "{}: {}", path.display(), e),
+            ModuleErr::NotFound(path, import, line) => write!(
+                f,
+                "{}: line {}: cannot find module '{}'",
+                path.display(),
+                line,
+                import
+            ),
+            ModuleErr::Cycle(paths) => {
+                let paths: Vec<String> = paths.iter().map(|p| p.display().to_string()).collect();
+                write!(f, "{}: import cycle: {}", paths[0], paths.join(" -> "))
+            }
+            ModuleErr::Parse(path, e) => write!(f, "{}: {}", path.display(), e),
+            ModuleErr::Resolve(path, e) => write!(f, "{}: {}", path.display(), e),
+            ModuleErr::Type(path, e) => write!(f, "{}: {}", path.display(), e),
+            ModuleErr::Fold(path, e) => write!(f, "{}: {}", path.display(), e),
+        }
+    }
+}
+
+impl std::error::Error for ModuleErr {}
+
+impl ModuleErr {
+    /// the file the error is in, and the line if it is about one.
+    pub fn location(&self) -> (&Path, Option<usize>) {
+        match self {
+            ModuleErr::Io(path, _) => (path, None),
+            ModuleErr::NotFound(path, _, line) => (path, Some(*line)),
+            ModuleErr::Cycle(paths) => (&paths[0], None),
+            ModuleErr::Parse(path, e) => (path, Some(e.line())),
+            ModuleErr::Resolve(path, e) => (path, Some(e.line())),
+            ModuleErr::Type(path, e) => (path, Some(e.line())),
+            ModuleErr::Fold(path, e) => (path, Some(e.line())),
+        }
+    }
+    /// what went wrong, without where.
+    pub fn message(&self) -> String {
+        let message = self.to_string();
+        let (path, line) = self.location();
+        let location = match line {
+            Some(line) => format!("{}: line {}: ", path.display(), line),
+            None => format!("{}: ", path.display()),
+        };
+        match message.strip_prefix(&location) {
+            Some(message) => message.to_owned(),
+            None => message,
+        }
+    }
+}
+
+/// Finds, reads and parses the modules of a program.
+#[derive(Default)]
+pub struct Loader {
+    /// where imports are looked for when they are not next to the importing module.
+    search_path: Vec<PathBuf>,
+    modules: Vec<Module>,
+    /// every module loaded so far by its canonical path, so importing it again shares it.
+    loaded: HashMap<PathBuf, usize>,
+    /// the modules being loaded, canonical path and path as found, each importing the next.
+    loading: Vec<(PathBuf, PathBuf)>,
+    cache: Option<Cache>,
+    /// what went wrong so far if loading goes on regardless, see `load_partial`.
+    errors: Option<Vec<ModuleErr>>,
+}
+
+impl Loader {
+    pub fn new(search_path: Vec<PathBuf>) -> Self {
+        Self {
+            search_path,
+            ..Self::default()
+        }
+    }
+    /// reuses the modules `cache` has artefacts for rather than parsing them again.
+    pub fn with_cache(mut self, cache: Cache) -> Self {
+        self.cache = Some(cache);
+        self
+    }
+
+    /// loads the program starting at `entry` and every module it imports, `entry` last.
+    pub fn load(self, entry: &Path) -> Result<Vec<Module>, ModuleErr> {
+        let source =
+            std::fs::read_to_string(entry).map_err(|e| ModuleErr::Io(entry.to_owned(), e))?;
+        self.load_source(&source, entry)
+    }
+    /// as `load`, for a program whose source the host already has. `path` need not exist, but
+    /// imports are looked for next to it.
+    pub fn load_source(mut self, source: &str, path: &Path) -> Result<Vec<Module>, ModuleErr> {
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+        self.loading.push((key, path.to_owned()));
+        self.module(source, path, String::new())?;
+        Ok(self.modules)
+    }
+    /// as `load_source`, but loads what it can of a program with errors, for tools that want to
+    /// make something of code being edited. A module is parsed as `Parser::parse_partial` does, and
+    /// an import that cannot be loaded is left out.
+    pub fn load_partial(mut self, source: &str, path: &Path) -> (Vec<Module>, Vec<ModuleErr>) {
+        self.errors = Some(Vec::new());
+        let key = path.canonicalize().unwrap_or_else(|_| path.to_owned());
+        self.loading.push((key, path.to_owned()));
+        if let Err(e) = self.module(source, path, String::new()) {
+            self.errors.get_or_insert_with(Vec::new).push(e);
+        }
+        (self.modules, self.errors.unwrap_or_default())
+    }
+
+    fn module(&mut self, source: &str, path: &Path, name: String) -> Result<usize, ModuleErr> {
+        let start = Instant::now();
+        let (program, cached) = match self.cache.clone() {
+            Some(cache) => {
+                let (program, entry) = self.cached(&cache, source, path)?;
+                (program, Some(entry))
+            }
+            None => (self.parse(source, path)?, None),
+        };
+        let load_time = start.elapsed();
+        let mut imports = HashMap::new();
+        for stmt in &program {
+            if let StmtKind::Import { path: import, .. } = &stmt.kind {
+                if !imports.contains_key(import) {
+                    match (self.import(path, import, stmt.line), &mut self.errors) {
+                        (Ok(index), _) => {
+                            imports.insert(import.clone(), index);
+                        }
+                        (Err(e), Some(errors)) => errors.push(e),
+                        (Err(e), None) => return Err(e),
+                    }
+                }
+            }
+        }
+        self.modules.push(Module {
+            path: path.to_owned(),
+            name,
+            program,
+            imports,
+            load_time,
+            cached,
+        });
+        Ok(self.modules.len() - 1)
+    }
+    fn parse(&mut self, source: &str, path: &Path) -> Result<Vec<Stmt>, ModuleErr> {
+        let tokens: Vec<Token> = Lexer::from_source(source).collect();
+        let mut parser = Parser::new(&tokens);
+        match &mut self.errors {
+            Some(errors) => {
+                let (program, parse_errors) = parser.parse_partial();
+                errors.extend(
+                    parse_errors
+                        .into_iter()
+                        .map(|e| ModuleErr::Parse(path.to_owned(), e)),
+                );
+                Ok(program)
+            }
+            None => parser
+                .parse()
+                .map_err(|e| ModuleErr::Parse(path.to_owned(), e)),
+        }
+    }
+    /// the program in the artefact for `source`, or else the program parsed.
+    fn cached(
+        &mut self,
+        cache: &Cache,
+        source: &str,
+        path: &Path,
+    ) -> Result<(Vec<Stmt>, Entry), ModuleErr> {
+        let hash = cache::hash(source.as_bytes());
+        if let Some(Artefact { program, checked }) = cache.read(hash) {
+            let mut decoder = Decoder::new(&program);
+            if let Some(decoded) = decoder.program().filter(|_| decoder.is_empty()) {
+                let entry = Entry {
+                    source: hash,
+                    hit: true,
+                    program,
+                    checked,
+                };
+                return Ok((decoded, entry));
+            }
+        }
+        let program = self.parse(source, path)?;
+        let mut encoder = Encoder::default();
+        encoder.program(&program);
+        let entry = Entry {
+            source: hash,
+            hit: false,
+            program: encoder.out,
+            checked: None,
+        };
+        Ok((program, entry))
+    }
+    fn import(&mut self, from: &Path, import: &str, line: usize) -> Result<usize, ModuleErr> {
+        let path = self
+            .find(from, import)
+            .ok_or_else(|| ModuleErr::NotFound(from.to_owned(), import.to_owned(), line))?;
+        let key = path
+            .canonicalize()
+            .map_err(|e| ModuleErr::Io(path.clone(), e))?;
+        if let Some(&index) = self.loaded.get(&key) {
+            return Ok(index);
+        }
+        if let Some(start) = self.loading.iter().position(|(k, _)| *k == key) {
+            let mut cycle: Vec<PathBuf> = self.loading[start..]
+                .iter()
+                .map(|(_, p)| p.clone())
+                .collect();
+            cycle.push(path);
+            return Err(ModuleErr::Cycle(cycle));
+        }
+
+        let source = std::fs::read_to_string(&path).map_err(|e| ModuleErr::Io(path.clone(), e))?;
+        let name = self.name(&path);
+        self.loading.push((key.clone(), path.clone()));
+        let index = self.module(&source, &path, name)?;
+        self.loading.pop();
+        self.loaded.insert(key, index);
+        Ok(index)
+    }
+    /// the file `import` names: next to the importing module `from`, or else in the first
+    /// directory of the search path that has it.
+    fn find(&self, from: &Path, import: &str) -> Option<PathBuf> {
+        let mut file = PathBuf::from(import);
+        if file.extension().is_none() {
+            file.set_extension(EXTENSION);
+        }
+        let next_to = from.parent().unwrap_or(Path::new(""));
+        std::iter::once(next_to)
+            .chain(self.search_path.iter().map(PathBuf::as_path))
+            .map(|dir| dir.join(&file))
+            .find(|path| path.is_file())
+    }
+    /// the file's stem, numbered if another module already has it so that globals stay unique.
+    fn name(&self, path: &Path) -> String {
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let taken = |name: &str| self.modules.iter().any(|m| m.name == name);
+        if !taken(&stem) {
+            return stem;
+        }
+        (2..)
+            .map(|n| format!("{}#{}", stem, n))
+            .find(|name| !taken(name))
+            .unwrap()
+    }
+}
+
+/// What compiling a program's modules found.
+#[derive(Debug)]
+pub struct Compiled {
+    pub globals: Vec<String>,
+    /// the types of each module's declarations.
+    pub bindings: Vec<Vec<Binding>>,
+    pub timings: Vec<Timing>,
+}
+
+/// resolves and type checks the modules, as loaded, and folds their constants if `fold` is set,
+/// though constant errors are reported either way. A module the loader found in its cache is not
+/// checked again if what it imports is unchanged, and once all is well `cache` is given the
+/// artefacts of the modules it did not have.
+pub fn compile(
+    modules: &mut [Module],
+    natives: &Natives,
+    fold: bool,
+    cache: Option<&Cache>,
+) -> Result<Compiled, Vec<ModuleErr>> {
+    fn tag<E>(
+        modules: &[Module],
+        errors: Vec<(usize, E)>,
+        to_err: fn(PathBuf, E) -> ModuleErr,
+    ) -> Vec<ModuleErr> {
+        errors
+            .into_iter()
+            .map(|(index, e)| to_err(modules[index].path.clone(), e))
+            .collect()
+    }
+    let globals =
+        resolve_modules(modules, natives).map_err(|e| tag(modules, e, ModuleErr::Resolve))?;
+
+    let natives_key = cache::natives(natives);
+    let mut public = Vec::new();
+    let mut keys = Vec::new();
+    let mut check_times = Vec::new();
+    let mut start = Instant::now();
+    // asked for each module in turn, just before it is checked, which times the one before
+    let mut reuse = |index: usize, interfaces: &[Option<Interface>]| {
+        if index > 0 {
+            check_times.push(start.elapsed());
+        }
+        start = Instant::now();
+        let modules: &[Module] = modules;
+        for (module, interface) in modules.iter().zip(interfaces).skip(public.len()) {
+            let hash = interface
+                .as_ref()
+                .and_then(|interface| cache::public(module, interface, &public));
+            public.push(hash);
+        }
+        let key = cache::key(&modules[index], natives_key, &public);
+        keys.push(key);
+        let checked = modules[index].cached.as_ref()?.checked.as_ref()?;
+        (key == Some(checked.key)).then(|| ModuleTypes {
+            bindings: checked.bindings.clone(),
+            interface: Some(checked.interface.clone()),
+            ints: HashSet::new(),
+        })
+    };
+    let types = check_modules(modules, natives, &mut reuse).map_err(|e| {
+        let e = e
+            .into_iter()
+            .map(|(index, e)| (index, Box::new(e)))
+            .collect();
+        tag(modules, e, ModuleErr::Type)
+    })?;
+    check_times.push(start.elapsed());
+
+    let mut errors = Vec::new();
+    for (module, types) in modules.iter_mut().zip(&types) {
+        let folded = if fold {
+            optimize(&mut module.program, &types.ints)
+        } else {
+            optimize(&mut module.program.clone(), &types.ints)
+        };
+        if let Err(e) = folded {
+            errors.extend(
+                e.into_iter()
+                    .map(|e| ModuleErr::Fold(module.path.clone(), e)),
+            );
+        }
+    }
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let mut timings = Vec::new();
+    let mut bindings = Vec::new();
+    for (((module, types), key), check) in modules.iter_mut().zip(types).zip(keys).zip(check_times)
+    {
+        let reuse = match &module.cached {
+            Some(entry) if entry.checked.as_ref().map(|c| c.key) == key && key.is_some() => {
+                Reuse::Reused
+            }
+            Some(entry) if entry.hit => Reuse::Rechecked,
+            _ => Reuse::Compiled,
+        };
+        if let (Some(cache), Some(entry)) = (cache, module.cached.take()) {
+            if reuse != Reuse::Reused {
+                let checked = key.zip(types.interface).map(|(key, interface)| Checked {
+                    key,
+                    interface,
+                    bindings: types.bindings.clone(),
+                });
+                let artefact = Artefact {
+                    program: entry.program,
+                    checked,
+                };
+                // a cache that cannot be written only makes the next build slower
+                let _ = cache.write(entry.source, &artefact);
+            }
+        }
+        timings.push(Timing {
+            path: module.path.clone(),
+            reuse,
+            load: module.load_time,
+            check,
+        });
+        bindings.push(types.bindings);
+    }
+    Ok(Compiled {
+        globals,
+        bindings,
+        timings,
+    })
+}
+
+/// the programs of the modules as one, in the order they were loaded: each module's top-level
+/// code runs after that of the modules it imports.
+pub fn link(modules: Vec<Module>) -> Vec<Stmt> {
+    modules.into_iter().flat_map(|m| m.program).collect()
+}
@@ -0,0 +1,387 @@
+use std::path::{Path, PathBuf};
+
+use super::{
+    cache::{Cache, Reuse},
+    codec::{Decoder, Encoder},
+    compile, link, Loader, Module, ModuleErr,
+};
+use crate::compiler::{
+    interpreter::Interpreter,
+    lexer::Lexer,
+    natives::Natives,
+    parser::Parser,
+    testing::{dir, SharedOutput},
+    token::Token,
+};
+
+fn load(entry: &Path, search_path: &[PathBuf]) -> Result<Vec<Module>, ModuleErr> {
+    Loader::new(search_path.to_vec()).load(entry)
+}
+
+/// compiles and runs the loaded program, returning what it printed and the names of its globals,
+/// or the compile errors.
+fn run(modules: Result<Vec<Module>, ModuleErr>) -> Result<(String, Vec<String>), Vec<String>> {
+    fn strings(errors: Vec<ModuleErr>) -> Vec<String> {
+        errors.iter().map(|e| e.to_string()).collect()
+    }
+    let mut modules = modules.map_err(|e| strings(vec![e]))?;
+    let natives = Natives::standard();
+    let globals = compile(&mut modules, &natives, true, None)
+        .map_err(strings)?
+        .globals;
+    let out = SharedOutput::default();
+    let mut interpreter =
+        Interpreter::with_output(&natives, globals.clone(), Box::new(out.clone()));
+    interpreter.run(&link(modules)).unwrap();
+    let printed = out.text();
+    Ok((printed, globals))
+}
+
+#[test]
+fn test_modules_have_their_own_namespace() {
+    let dir = dir(
+        "namespace",
+        &[
+            (
+                "main.txt",
+                "import \"geometry\" as geo;
+                let p: geo.Point = geo.Point(3, 4);
+                print geo.area(p);
+                fn len(x) { return -1; }
+                print len([1]);",
+            ),
+            (
+                "geometry.txt",
+                "export class Point { fn init(x: int, y: int) { this.x = x; this.y = y; } }
+                export fn area(p: Point) -> int { return p.x * p.y; }
+                // a module's own names do not clash with those of other modules or natives
+                fn len(x) { return 0; }
+                print len([1]);",
+            ),
+        ],
+    );
+    let (printed, globals) = run(load(&dir.join("main.txt"), &[])).unwrap();
+    assert_eq!(printed, "0\n12\n-1\n");
+    let natives = Natives::standard().len();
+    // only the module the program starts from takes over a native's slot
+    assert_eq!(
+        globals[natives..],
+        ["geometry.Point", "geometry.area", "geometry.len", "p"]
+    );
+}
+
+#[test]
+fn test_only_exports_are_visible() {
+    let dir = dir(
+        "exports",
+        &[
+            ("lib.txt", "export let shown = 1; let hidden = 2;"),
+            (
+                "main.txt",
+                "import \"lib\" as lib;\nprint lib.hidden;\nlib.shown = 3;\nprint lib;",
+            ),
+        ],
+    );
+    let main = dir.join("main.txt").display().to_string();
+    assert_eq!(
+        run(load(&dir.join("main.txt"), &[])).unwrap_err(),
+        [
+            format!("{}: line 2: module 'lib' does not export 'hidden'", main),
+            format!(
+                "{}: line 3: cannot assign to a member of module 'lib'",
+                main
+            ),
+            format!("{}: line 4: module 'lib' is not a value", main),
+        ]
+    );
+}
+
+#[test]
+fn test_each_module_is_loaded_once() {
+    let dir = dir(
+        "once",
+        &[
+            ("shared.txt", "export let count = 0;\nprint \"shared\";"),
+            (
+                "a.txt",
+                "import \"shared\" as s;\nexport let a = s.count + 1;",
+            ),
+            (
+                "b.txt",
+                "import \"shared.txt\" as s;\nexport let b = s.count + 2;",
+            ),
+            (
+                "main.txt",
+                "import \"a\" as a;\nimport \"b\" as b;\nimport \"./a\" as again;\n\
+                 print a.a + b.b + again.a;",
+            ),
+        ],
+    );
+    let modules = load(&dir.join("main.txt"), &[]).unwrap();
+    let names: Vec<&str> = modules.iter().map(|m| m.name.as_str()).collect();
+    // every module comes after those it imports, the one the program starts from last
+    assert_eq!(names, ["shared", "a", "b", ""]);
+    assert_eq!(modules[3].imports["a"], modules[3].imports["./a"]);
+    assert_eq!(run(Ok(modules)).unwrap().0, "shared\n4\n");
+}
+
+#[test]
+fn test_import_cycles_are_errors() {
+    let dir = dir(
+        "cycle",
+        &[
+            ("main.txt", "import \"a\" as a;"),
+            ("a.txt", "import \"b\" as b;"),
+            ("b.txt", "import \"a\" as a;"),
+        ],
+    );
+    let err = load(&dir.join("main.txt"), &[]).unwrap_err();
+    let (a, b) = (dir.join("a.txt"), dir.join("b.txt"));
+    assert!(matches!(&err, ModuleErr::Cycle(paths) if *paths == [a.clone(), b.clone(), a.clone()]));
+    assert_eq!(
+        err.to_string(),
+        format!(
+            "{}: import cycle: {} -> {} -> {}",
+            a.display(),
+            a.display(),
+            b.display(),
+            a.display()
+        )
+    );
+}
+
+#[test]
+fn test_search_path() {
+    let dir = dir(
+        "search",
+        &[
+            (
+                "app/main.txt",
+                "import \"util\" as util;\nimport \"extra\" as extra;\n\
+                 print util.name + extra.name;",
+            ),
+            ("app/util.txt", "export let name = \"local \";"),
+            ("lib/util.txt", "export let name = \"library \";"),
+            ("vendor/extra.txt", "export let name = \"vendor\";"),
+        ],
+    );
+    let entry = dir.join("app/main.txt");
+    let search_path = [dir.join("lib"), dir.join("vendor")];
+    // a module next to the importing one comes before any on the search path
+    assert_eq!(run(load(&entry, &search_path)).unwrap().0, "local vendor\n");
+    assert_eq!(
+        run(load(&entry, &search_path[..1])).unwrap_err(),
+        [format!(
+            "{}: line 2: cannot find module 'extra'",
+            entry.display()
+        )]
+    );
+}
+
+#[test]
+fn test_modules_have_their_own_type_names() {
+    let dir = dir(
+        "types",
+        &[
+            (
+                "grid.txt",
+                "export class Point { fn init(x: int, y: int) { this.x = x; this.y = y; } }
+                export interface Named { fn name() -> str; }
+                export fn origin() -> Point { return Point(0, 0); }",
+            ),
+            (
+                "chart.txt",
+                "export class Point { fn init(label: str) { this.label = label; } }
+                interface Named { fn name() -> int; }
+                export fn show(p: Point) -> str { return p.label; }",
+            ),
+            (
+                "main.txt",
+                "import \"grid\" as grid;
+                import \"chart\" as chart;
+                class Point { fn name() -> str { return \"mine\"; } }
+                fn named<T: grid.Named>(x: T) -> str { return x.name(); }
+                let g: grid.Point = grid.origin();
+                let c: chart.Point = chart.Point(\"c\");
+                print g.x + g.y;
+                print chart.show(c) + named(Point());",
+            ),
+        ],
+    );
+    let (printed, _) = run(load(&dir.join("main.txt"), &[])).unwrap();
+    assert_eq!(printed, "0\ncmine\n");
+
+    // the classes are told apart, and only exported ones can be named
+    let main = dir.join("main.txt").display().to_string();
+    let errors = |source: &str| {
+        std::fs::write(dir.join("main.txt"), source).unwrap();
+        run(load(&dir.join("main.txt"), &[])).unwrap_err()
+    };
+    assert_eq!(
+        errors(
+            "import \"grid\" as grid;\nimport \"chart\" as chart;
+            let p: grid.Point = chart.Point(\"c\");"
+        ),
+        [format!(
+            "{}: line 3: expected grid.Point but found chart.Point",
+            main
+        )]
+    );
+    assert_eq!(
+        errors("import \"chart\" as chart;\nfn f(n: chart.Named) {}"),
+        [format!(
+            "{}: line 2: module 'chart' does not export 'Named'",
+            main
+        )]
+    );
+}
+
+#[test]
+fn test_codec_round_trip() {
+    let source = "import \"lib\" as lib;
+        export interface Named { fn name() -> str; }
+        export class Box<T: Named> {
+            fn init(item: T) { this.item = item; }
+            fn label() -> str { return this.item.name() + \"!\"; }
+        }
+        export let scale: float = 2.5;
+        fn f<T>(x: T, xs: list<int>, m: map<str, T>) -> T {
+            for k in m { xs[0] = -xs[0] * 2 / 1 - 3; }
+            while (!(1 >= 2 or 1 <= 2 and 1 != 2)) { return x; }
+            if (1 == 2) { print nil; } else if (true) { print false; } else { print {\"a\": [x]}; }
+            try { throw \"oops\"; } catch (e) { print e; } finally { print lib.value; }
+            let y;
+            y = x;
+            return y;
+        }";
+    let tokens: Vec<Token> = Lexer::from_source(source).collect();
+    let program = Parser::new(&tokens).parse().unwrap();
+    let mut encoder = Encoder::default();
+    encoder.program(&program);
+    let mut decoder = Decoder::new(&encoder.out);
+    assert_eq!(decoder.program().as_ref(), Some(&program));
+    assert!(decoder.is_empty());
+    // anything cut short is no program at all
+    let cut = &encoder.out[..encoder.out.len() - 1];
+    assert_eq!(Decoder::new(cut).program(), None);
+}
+
+/// builds and runs `main.txt` in `dir` with `cache`, returning the program's output and how much of
+/// each module was reused, or the files with errors.
+fn build_cached(dir: &Path, cache: &Cache) -> Result<(String, Vec<Reuse>), Vec<String>> {
+    let mut modules = Loader::new(Vec::new())
+        .with_cache(cache.clone())
+        .load(&dir.join("main.txt"))
+        .unwrap();
+    let natives = Natives::standard();
+    let compiled = compile(&mut modules, &natives, true, Some(cache)).map_err(|errors| {
+        let mut files: Vec<String> = errors
+            .iter()
+            .map(|e| e.to_string().replace(&format!("{}/", dir.display()), ""))
+            .map(|e| e.split(':').next().unwrap().to_owned())
+            .collect();
+        files.dedup();
+        files
+    })?;
+    let out = SharedOutput::default();
+    let mut interpreter =
+        Interpreter::with_output(&natives, compiled.globals, Box::new(out.clone()));
+    interpreter.run(&link(modules)).unwrap();
+    let printed = out.text();
+    let reuse: Vec<Reuse> = compiled.timings.iter().map(|t| t.reuse).collect();
+    Ok((printed, reuse))
+}
+
+#[test]
+fn test_cache_reuses_what_has_not_changed() {
+    let dir = dir(
+        "cache",
+        &[
+            (
+                "geometry.txt",
+                "export fn area(w: int, h: int) -> int { return w * h; }
+                fn unused(x) { return x; }",
+            ),
+            (
+                "shapes.txt",
+                "import \"geometry\" as geo;
+                export fn square(n: int) -> int { return geo.area(n, n); }",
+            ),
+            (
+                "main.txt",
+                "import \"shapes\" as shapes;\nprint shapes.square(3);",
+            ),
+        ],
+    );
+    let cache = Cache::new(dir.join(".cache"));
+    let build = || build_cached(&dir, &cache);
+    let edit = |from: &str, to: &str| {
+        let path = dir.join("geometry.txt");
+        let source = std::fs::read_to_string(&path).unwrap();
+        std::fs::write(path, source.replace(from, to)).unwrap();
+    };
+    use Reuse::*;
+    let nine = || Ok("9\n".to_owned());
+    assert_eq!(build(), nine().map(|out| (out, vec![Compiled; 3])));
+    assert_eq!(build(), nine().map(|out| (out, vec![Reused; 3])));
+    // what other modules cannot see of a module does not concern them
+    edit("return x;", "return [x];");
+    assert_eq!(
+        build(),
+        nine().map(|out| (out, vec![Compiled, Reused, Reused]))
+    );
+    edit("return w * h;", "return w * h + 1;");
+    assert_eq!(
+        build(),
+        Ok(("10\n".to_owned(), vec![Compiled, Reused, Reused]))
+    );
+    // but a change to the type of an export has its importers checked again, and theirs
+    edit("h: int) -> int", "h: int) -> any");
+    assert_eq!(build().unwrap().1, [Compiled, Rechecked, Rechecked]);
+    // an importer checked against an artefact's types is as wrong as one checked from scratch
+    edit(
+        "(w: int, h: int) -> any { return w * h + 1; }",
+        "(w: str, h: str) -> str { return w + h; }",
+    );
+    assert_eq!(build(), Err(vec!["shapes.txt".to_owned()]));
+}
+
+#[test]
+fn test_cache_rechecks_importers_of_a_changed_interface() {
+    let dir = dir(
+        "cache-interface",
+        &[
+            ("sized.txt", "export interface Sized { fn size() -> int; }"),
+            (
+                "main.txt",
+                "import \"sized\" as sized;
+                class Box { fn size() -> int { return 2; } }
+                fn twice<T: sized.Sized>(x: T) -> int { return x.size() * 2; }
+                print twice(Box());",
+            ),
+        ],
+    );
+    let cache = Cache::new(dir.join(".cache"));
+    use Reuse::*;
+    assert_eq!(
+        build_cached(&dir, &cache),
+        Ok(("4\n".to_owned(), vec![Compiled, Compiled]))
+    );
+    // where the interface is does not concern its importers
+    std::fs::write(
+        dir.join("sized.txt"),
+        "\nexport interface Sized {\n    fn size() -> int;\n}",
+    )
+    .unwrap();
+    assert_eq!(
+        build_cached(&dir, &cache),
+        Ok(("4\n".to_owned(), vec![Compiled, Reused]))
+    );
+    // but what it asks for does: `Box` no longer implements it
+    std::fs::write(
+        dir.join("sized.txt"),
+        "export interface Sized { fn size() -> str; }",
+    )
+    .unwrap();
+    assert_eq!(build_cached(&dir, &cache), Err(vec!["main.txt".to_owned()]));
+}
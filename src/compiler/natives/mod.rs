@@ -0,0 +1,210 @@
+//! Functions the host implements in Rust and scripts call like any other. The same `Natives`
+//! registry goes to the resolver, the checker and the interpreter: every native is a global,
+//! numbered before the program's own, typed by its signature, and called with arguments already
+//! checked against it. The registry also names the host's own types, which signatures and
+//! annotations can then refer to.
+
+pub mod stdlib;
+
+use std::rc::Rc;
+
+use crate::compiler::{
+    ast::{
+        literal::Literal,
+        types::{Constraint, Quantified, Scheme, Type},
+    },
+    eval::EvalErr,
+    interpreter::Interpreter,
+    value::Value,
+};
+
+/// what a native runs. It gets the interpreter, to reach the heap, and its arguments, which stay
+/// alive for the whole call.
+pub type NativeBody = dyn Fn(&mut Interpreter, &[Value]) -> Result<Value, EvalErr>;
+
+pub struct NativeFn {
+    pub name: String,
+    /// a function type, generic over its quantified variables.
+    pub signature: Scheme,
+    body: Box<NativeBody>,
+}
+
+impl NativeFn {
+    pub fn params(&self) -> &[Type] {
+        match &self.signature.ty {
+            Type::Fn { params, .. } => params,
+            _ => unreachable!("natives are registered with function types"),
+        }
+    }
+    /// runs the native once its arguments match the signature, as far as their values tell: a
+    /// `list<int>` parameter takes any list, and a variable any value its constraint admits.
+    pub fn call(&self, interpreter: &mut Interpreter, args: &[Value]) -> Result<Value, EvalErr> {
+        let params = self.params();
+        if params.len() != args.len() {
+            return Err(EvalErr::InvalidArity(params.len(), args.len()));
+        }
+        for (position, (param, arg)) in params.iter().zip(args).enumerate() {
+            if !self.admits(param, arg) {
+                return Err(invalid_argument(&self.name, position, self.expected(param)));
+            }
+        }
+        (self.body)(interpreter, args)
+    }
+    fn constraint(&self, var: usize) -> Option<Constraint> {
+        let quantified = self.signature.vars.iter().find(|q| q.id == var);
+        quantified.and_then(|q| q.constraint)
+    }
+    fn admits(&self, ty: &Type, value: &Value) -> bool {
+        match (ty, value) {
+            (Type::Any | Type::Param(_), _) => true,
+            (Type::Var(var), value) => match (self.constraint(*var), value) {
+                (None, _) => true,
+                (Some(constraint), Value::Literal(literal)) => {
+                    constraint.admits(&literal_type(literal))
+                }
+                (Some(_), _) => false,
+            },
+            (Type::Nil, Value::Literal(Literal::Nil))
+            | (Type::Bool, Value::Literal(Literal::Bool(_)))
+            | (Type::Int, Value::Literal(Literal::Int(_)))
+            | (Type::Float, Value::Literal(Literal::Float(_)))
+            | (Type::Str, Value::Literal(Literal::Str(_)))
+            | (Type::List(_), Value::List(_))
+            | (Type::Map(..), Value::Map(_))
+            | (Type::Instance(..), Value::Instance(_)) => true,
+            (Type::Instance(name, _), Value::Host(object)) => object.type_name == name,
+            (Type::Fn { .. }, value) => matches!(
+                value,
+                Value::Function(_) | Value::Native(_) | Value::Class(_)
+            ),
+            _ => false,
+        }
+    }
+    fn expected(&self, ty: &Type) -> String {
+        match ty {
+            Type::Var(var) => match self.constraint(*var) {
+                Some(constraint) => format!("a {}", constraint),
+                None => "any value".to_owned(),
+            },
+            Type::List(_) => "a list".to_owned(),
+            Type::Map(..) => "a map".to_owned(),
+            Type::Fn { .. } => "a function".to_owned(),
+            ty => format!("{}", ty),
+        }
+    }
+}
+
+/// natives are only ever equal to themselves, like heap values.
+impl PartialEq for NativeFn {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+impl std::fmt::Debug for NativeFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<native fn {}>", self.name)
+    }
+}
+
+/// The natives a program can call, in the order of their globals, and the host types.
+#[derive(Default, Clone)]
+pub struct Natives {
+    fns: Vec<Rc<NativeFn>>,
+    types: Vec<&'static str>,
+}
+
+impl Natives {
+    /// the standard library, to which hosts add their own.
+    pub fn standard() -> Self {
+        let mut natives = Self::default();
+        stdlib::register(&mut natives);
+        natives
+    }
+    /// adds a native, or replaces the one with the same name in place. The signature must be a
+    /// function type; `function` and `generic` build the common ones.
+    pub fn register(
+        &mut self,
+        name: &str,
+        signature: Scheme,
+        body: impl Fn(&mut Interpreter, &[Value]) -> Result<Value, EvalErr> + 'static,
+    ) -> &mut Self {
+        assert!(
+            matches!(signature.ty, Type::Fn { .. }),
+            "native '{}' must have a function type",
+            name
+        );
+        let native = Rc::new(NativeFn {
+            name: name.to_owned(),
+            signature,
+            body: Box::new(body),
+        });
+        match self.fns.iter_mut().find(|f| f.name == name) {
+            Some(existing) => *existing = native,
+            None => self.fns.push(native),
+        }
+        self
+    }
+    /// makes `name` a type the checker knows, of `HostObject`s with that `type_name`. Such objects
+    /// have no properties; natives are how scripts do anything with them.
+    pub fn register_type(&mut self, name: &'static str) -> &mut Self {
+        if !self.types.contains(&name) {
+            self.types.push(name);
+        }
+        self
+    }
+    pub fn types(&self) -> &[&'static str] {
+        &self.types
+    }
+    pub fn iter(&self) -> impl Iterator<Item = &Rc<NativeFn>> {
+        self.fns.iter()
+    }
+    pub fn len(&self) -> usize {
+        self.fns.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.fns.is_empty()
+    }
+}
+
+/// the signature of a native taking `params` and returning `ret`.
+pub fn function(params: Vec<Type>, ret: Type) -> Scheme {
+    Scheme::mono(Type::Fn {
+        params,
+        ret: Box::new(ret),
+    })
+}
+
+/// the signature of a native generic over one type, `T` in `make(T)`, which every call infers
+/// afresh and which has to satisfy `constraint` if there is one.
+pub fn generic(constraint: Option<Constraint>, make: impl Fn(Type) -> (Vec<Type>, Type)) -> Scheme {
+    let (params, ret) = make(Type::Var(0));
+    Scheme {
+        vars: vec![Quantified {
+            id: 0,
+            constraint,
+            bounds: Vec::new(),
+            promotes: None,
+        }],
+        ..function(params, ret)
+    }
+}
+
+/// the error for the argument at `position`, counting from 0, not being what `native` expects.
+pub fn invalid_argument(native: &str, position: usize, expected: impl Into<String>) -> EvalErr {
+    EvalErr::InvalidArgument {
+        native: native.to_owned(),
+        position: position + 1,
+        expected: expected.into(),
+    }
+}
+
+fn literal_type(literal: &Literal) -> Type {
+    match literal {
+        Literal::Nil => Type::Nil,
+        Literal::Bool(_) => Type::Bool,
+        Literal::Int(_) => Type::Int,
+        Literal::Float(_) => Type::Float,
+        Literal::Str(_) => Type::Str,
+    }
+}
@@ -0,0 +1,246 @@
+//! The natives every program gets. Arguments arrive checked against the signatures, so the bodies
+//! only look further where a signature cannot say enough, e.g. at the elements of a list.
+
+use std::{cmp::Ordering, time::SystemTime};
+
+use crate::compiler::{
+    ast::{
+        literal::Literal,
+        types::{Constraint, Quantified, Scheme, Type},
+    },
+    eval::EvalErr,
+    gc::Gc,
+    natives::{function, generic, invalid_argument as invalid, Natives},
+    value::Value,
+};
+
+pub fn register(natives: &mut Natives) {
+    natives
+        .register("clock", function(vec![], Type::Float), |_, _| {
+            let elapsed = SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+            Ok(float(elapsed.as_secs_f64()))
+        })
+        .register(
+            "len",
+            function(vec![Type::Any], Type::Int),
+            |interp, args| {
+                let len = match &args[0] {
+                    Value::Literal(Literal::Str(s)) => s.chars().count(),
+                    Value::List(list) => interp.heap().list(*list).len(),
+                    Value::Map(map) => interp.heap().map(*map).len(),
+                    _ => return Err(invalid("len", 0, "a list, map or str")),
+                };
+                Ok(int(len as i32))
+            },
+        )
+        .register(
+            "str",
+            function(vec![Type::Any], Type::Str),
+            |interp, args| Ok(string(interp.heap().display(&args[0]))),
+        )
+        .register(
+            "num",
+            function(vec![Type::Any], Type::Float),
+            |_, args| match &args[0] {
+                Value::Literal(Literal::Int(i)) => Ok(float(*i as f64)),
+                Value::Literal(Literal::Float(f)) => Ok(float(*f as f64)),
+                Value::Literal(Literal::Str(s)) => match s.trim().parse() {
+                    Ok(f) => Ok(float(f)),
+                    Err(_) => Err(EvalErr::Native(format!("'num' cannot parse \"{}\"", s))),
+                },
+                _ => Err(invalid("num", 0, "a number or str")),
+            },
+        )
+        .register("abs", numeric(1, |t| t), |_, args| match &args[0] {
+            Value::Literal(Literal::Int(i)) => i
+                .checked_abs()
+                .map(int)
+                .ok_or_else(|| EvalErr::Native("'abs' overflows an int".to_owned())),
+            arg => Ok(float(as_float(arg).abs())),
+        })
+        .register("min", either_number(), |_, args| {
+            Ok(pick(args, Ordering::Less))
+        })
+        .register("max", either_number(), |_, args| {
+            Ok(pick(args, Ordering::Greater))
+        })
+        .register("floor", numeric(1, |_| Type::Int), |_, args| {
+            match &args[0] {
+                Value::Literal(Literal::Int(i)) => Ok(int(*i)),
+                arg => {
+                    let floor = as_float(arg).floor();
+                    // `as` would saturate, and turn NaN into 0
+                    if floor >= i32::MIN as f64 && floor <= i32::MAX as f64 {
+                        Ok(int(floor as i32))
+                    } else {
+                        Err(EvalErr::Native(format!(
+                            "'floor' of {} is not an int",
+                            floor
+                        )))
+                    }
+                }
+            }
+        })
+        .register("sqrt", numeric(1, |_| Type::Float), |_, args| {
+            Ok(float(as_float(&args[0]).sqrt()))
+        })
+        .register(
+            "split",
+            function(vec![Type::Str, Type::Str], list(Type::Str)),
+            |interp, args| {
+                let (s, separator) = (as_str(&args[0]), as_str(&args[1]));
+                // an empty separator splits between every character, not at both ends too
+                let parts: Vec<Value> = if separator.is_empty() {
+                    s.chars().map(|c| string(c.to_string())).collect()
+                } else {
+                    s.split(separator).map(|p| string(p.to_owned())).collect()
+                };
+                interp.new_list(parts)
+            },
+        )
+        .register(
+            "join",
+            function(vec![list(Type::Str), Type::Str], Type::Str),
+            |interp, args| {
+                let Value::List(list) = &args[0] else {
+                    unreachable!("checked against the signature");
+                };
+                let parts = interp
+                    .heap()
+                    .list(*list)
+                    .iter()
+                    .map(|element| match element {
+                        Value::Literal(Literal::Str(s)) => Ok(s.as_str()),
+                        _ => Err(invalid("join", 0, "a list of strs")),
+                    });
+                let parts = parts.collect::<Result<Vec<&str>, EvalErr>>()?;
+                Ok(string(parts.join(as_str(&args[1]))))
+            },
+        )
+        .register("trim", function(vec![Type::Str], Type::Str), |_, args| {
+            Ok(string(as_str(&args[0]).trim().to_owned()))
+        })
+        .register(
+            "push",
+            generic(None, |t| (vec![list(t.clone()), t], Type::Nil)),
+            |interp, args| {
+                let list = as_list(&args[0]);
+                interp.heap_mut().list_mut(list).push(args[1].clone());
+                interp.resized(list)?;
+                Ok(Value::NIL)
+            },
+        )
+        .register(
+            "pop",
+            generic(None, |t| (vec![list(t.clone())], t)),
+            |interp, args| {
+                let list = as_list(&args[0]);
+                interp
+                    .heap_mut()
+                    .list_mut(list)
+                    .pop()
+                    .ok_or_else(|| EvalErr::Native("'pop' from an empty list".to_owned()))
+            },
+        )
+        .register(
+            "sort",
+            // `Add` admits exactly the types that can be ordered: numbers and strings
+            generic(Some(Constraint::Add), |t| (vec![list(t)], Type::Nil)),
+            |interp, args| {
+                let list = as_list(&args[0]);
+                let elements = interp.heap_mut().list_mut(list);
+                let mut incomparable = false;
+                elements.sort_by(|a, b| {
+                    compare(a, b).unwrap_or_else(|| {
+                        incomparable = true;
+                        Ordering::Equal
+                    })
+                });
+                if incomparable {
+                    return Err(invalid("sort", 0, "a list of numbers or of strs"));
+                }
+                Ok(Value::NIL)
+            },
+        );
+}
+
+/// `fn(T, ...) -> ret(T)` with `arity` parameters of some number type `T`.
+fn numeric(arity: usize, ret: impl Fn(Type) -> Type) -> Scheme {
+    generic(Some(Constraint::Num), |t| (vec![t.clone(); arity], ret(t)))
+}
+
+/// `fn('a, 'b) -> 'c` over numbers, of either kind, where the result is typed as their promotion.
+fn either_number() -> Scheme {
+    let number = |id, promotes| Quantified {
+        id,
+        constraint: Some(Constraint::Num),
+        bounds: Vec::new(),
+        promotes,
+    };
+    Scheme {
+        vars: vec![number(0, None), number(1, None), number(2, Some((0, 1)))],
+        ..function(vec![Type::Var(0), Type::Var(1)], Type::Var(2))
+    }
+}
+
+fn list(element: Type) -> Type {
+    Type::List(Box::new(element))
+}
+
+fn int(i: i32) -> Value {
+    Value::Literal(Literal::Int(i))
+}
+fn float(f: f64) -> Value {
+    Value::Literal(Literal::Float(f as f32))
+}
+fn string(s: String) -> Value {
+    Value::Literal(Literal::Str(s))
+}
+
+/// the arguments below have been checked to be of these types.
+fn as_float(value: &Value) -> f64 {
+    match value {
+        Value::Literal(Literal::Int(i)) => *i as f64,
+        Value::Literal(Literal::Float(f)) => *f as f64,
+        _ => unreachable!("checked against the signature"),
+    }
+}
+fn as_str(value: &Value) -> &str {
+    match value {
+        Value::Literal(Literal::Str(s)) => s,
+        _ => unreachable!("checked against the signature"),
+    }
+}
+fn as_list(value: &Value) -> Gc {
+    match value {
+        Value::List(list) => *list,
+        _ => unreachable!("checked against the signature"),
+    }
+}
+
+/// the first of two numbers unless the second compares to it as `wanted`, so ties keep the first.
+/// An int and a float only meet here when an `any` got them past the checker.
+fn pick(args: &[Value], wanted: Ordering) -> Value {
+    let (a, b) = (as_float(&args[0]), as_float(&args[1]));
+    // NaN compares as neither, so only the first can win if it is one
+    match b.partial_cmp(&a) {
+        Some(ordering) if ordering == wanted => args[1].clone(),
+        None if b.is_nan() => args[1].clone(),
+        _ => args[0].clone(),
+    }
+}
+
+/// how `sort` orders two elements, `None` for a mix of strings and numbers or anything else.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Literal(Literal::Int(a)), Value::Literal(Literal::Int(b))) => Some(a.cmp(b)),
+        (Value::Literal(Literal::Str(a)), Value::Literal(Literal::Str(b))) => Some(a.cmp(b)),
+        (
+            Value::Literal(Literal::Int(_) | Literal::Float(_)),
+            Value::Literal(Literal::Int(_) | Literal::Float(_)),
+        ) => Some(as_float(a).total_cmp(&as_float(b))),
+        _ => None,
+    }
+}
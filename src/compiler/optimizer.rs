@@ -0,0 +1,262 @@
+use std::collections::HashSet;
+
+use crate::compiler::{
+    ast::{
+        expr::{BinaryOp, Expr, LogicalOp, UnaryOp},
+        literal::Literal,
+        visitor::{walk_expr_mut, walk_stmt_mut, VisitorMut},
+    },
+    statements::stmt::Stmt,
+};
+
+#[derive(Debug, PartialEq)]
+pub enum FoldErr {
+    DivisionByZero(usize),
+    /// an integer operation on constants whose result does not fit in 32 bits.
+    Overflow(&'static str, usize),
+}
+
+impl std::fmt::Display for FoldErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FoldErr::DivisionByZero(line) => write!(f, "line {}: division by zero", line),
+            FoldErr::Overflow(op, line) => {
+                write!(f, "line {}: '{}' overflows an int", line, op)
+            }
+        }
+    }
+}
+
+impl FoldErr {
+    pub fn line(&self) -> usize {
+        match self {
+            FoldErr::DivisionByZero(line) | FoldErr::Overflow(_, line) => *line,
+        }
+    }
+}
+
+/// Simplifies every expression of a checked program in place: constant operators are folded with
+/// the `Literal` operator impls, so a folded expression has the value the interpreter would have
+/// computed, `x + 0`, `x * 1` and friends become `x`, and groupings go, since the tree already
+/// encodes precedence. Constant operations that could only fail at runtime, such as an integer
+/// division by zero, are reported instead of folded.
+///
+/// The identities only apply to operands in `ints`, those the checker proved to be `int`: on
+/// anything else, `any` included, the operator may fail or convert its operand, as `s * 1` fails
+/// for a string `s`.
+pub fn optimize(program: &mut [Stmt], ints: &HashSet<*const Expr>) -> Result<(), Vec<FoldErr>> {
+    let mut optimizer = Optimizer {
+        line: 0,
+        errors: Vec::new(),
+        ints,
+    };
+    for stmt in program {
+        optimizer.visit_stmt_mut(stmt);
+    }
+    if optimizer.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(optimizer.errors)
+    }
+}
+
+struct Optimizer<'a> {
+    line: usize,
+    errors: Vec<FoldErr>,
+    ints: &'a HashSet<*const Expr>,
+}
+
+impl Optimizer<'_> {
+    fn simplify(&mut self, expr: Expr) -> Expr {
+        match expr {
+            Expr::Grouping(inner) => *inner,
+            Expr::Unary { op, rhs } => self.unary(op, *rhs),
+            Expr::Binary { lhs, op, rhs } => {
+                // simplifying keeps an operand's type, so its address still says if it is an int
+                let ints = (
+                    self.ints.contains(&(&*lhs as *const Expr)),
+                    self.ints.contains(&(&*rhs as *const Expr)),
+                );
+                self.binary(*lhs, op, *rhs, ints)
+            }
+            Expr::Logical { lhs, op, rhs } => match *lhs {
+                // `and` and `or` evaluate to whichever operand decided them
+                Expr::LiteralExpr(l) => {
+                    let truthy = !matches!(l, Literal::Nil | Literal::Bool(false));
+                    let short_circuit = match op {
+                        LogicalOp::And => !truthy,
+                        LogicalOp::Or => truthy,
+                    };
+                    if short_circuit {
+                        Expr::LiteralExpr(l)
+                    } else {
+                        *rhs
+                    }
+                }
+                lhs => Expr::Logical {
+                    lhs: Box::new(lhs),
+                    op,
+                    rhs,
+                },
+            },
+            expr => expr,
+        }
+    }
+    fn unary(&mut self, op: UnaryOp, rhs: Expr) -> Expr {
+        match (op, rhs) {
+            (op, Expr::LiteralExpr(l)) => match fold_unary(op, &l, self.line) {
+                Ok(Some(folded)) => Expr::LiteralExpr(folded),
+                // left for the runtime to report
+                result => {
+                    if let Err(e) = result {
+                        self.errors.push(e);
+                    }
+                    Expr::Unary {
+                        op,
+                        rhs: Box::new(Expr::LiteralExpr(l)),
+                    }
+                }
+            },
+            // `!!b` is `b` only if `b` is a bool already: `!!nil` is false
+            (
+                UnaryOp::Bang,
+                Expr::Unary {
+                    op: UnaryOp::Bang,
+                    rhs,
+                },
+            ) if is_bool(&rhs) => *rhs,
+            (op, rhs) => Expr::Unary {
+                op,
+                rhs: Box::new(rhs),
+            },
+        }
+    }
+    /// `ints` says whether the operands are proven ints.
+    fn binary(&mut self, lhs: Expr, op: BinaryOp, rhs: Expr, ints: (bool, bool)) -> Expr {
+        use Literal::Int;
+
+        match (lhs, op, rhs) {
+            (Expr::LiteralExpr(l), op, Expr::LiteralExpr(r)) => {
+                match fold_binary(&l, op, &r, self.line) {
+                    Ok(Some(folded)) => Expr::LiteralExpr(folded),
+                    result => {
+                        if let Err(e) = result {
+                            self.errors.push(e);
+                        }
+                        Expr::Binary {
+                            lhs: Box::new(Expr::LiteralExpr(l)),
+                            op,
+                            rhs: Box::new(Expr::LiteralExpr(r)),
+                        }
+                    }
+                }
+            }
+            // only `int` identities: `x + 0.0` turns an int `x` into a float
+            (x, BinaryOp::Plus | BinaryOp::Minus, Expr::LiteralExpr(Int(0))) if ints.0 => x,
+            (x, BinaryOp::Mult | BinaryOp::Div, Expr::LiteralExpr(Int(1))) if ints.0 => x,
+            (Expr::LiteralExpr(Int(0)), BinaryOp::Plus, x) if ints.1 => x,
+            (Expr::LiteralExpr(Int(1)), BinaryOp::Mult, x) if ints.1 => x,
+            (lhs, op, rhs) => Expr::Binary {
+                lhs: Box::new(lhs),
+                op,
+                rhs: Box::new(rhs),
+            },
+        }
+    }
+}
+
+/// the value of a unary operator on a constant, or `None` to leave it to the runtime, which fails
+/// on it. Shared with the IR's constant propagation.
+pub fn fold_unary(op: UnaryOp, l: &Literal, line: usize) -> Result<Option<Literal>, FoldErr> {
+    if let (UnaryOp::Negate, Literal::Int(i)) = (op, l) {
+        return match i.checked_neg() {
+            Some(i) => Ok(Some(Literal::Int(i))),
+            None => Err(FoldErr::Overflow(op.symbol(), line)),
+        };
+    }
+    let result = match op {
+        UnaryOp::Bang => !l.clone(),
+        UnaryOp::Negate => -l.clone(),
+    };
+    Ok(result.ok())
+}
+
+/// the value of a binary operator on constants, or `None` to leave it to the runtime.
+pub fn fold_binary(
+    l: &Literal,
+    op: BinaryOp,
+    r: &Literal,
+    line: usize,
+) -> Result<Option<Literal>, FoldErr> {
+    if let (Literal::Int(a), Literal::Int(b)) = (l, r) {
+        let checked = match op {
+            BinaryOp::Plus => Some(a.checked_add(*b)),
+            BinaryOp::Minus => Some(a.checked_sub(*b)),
+            BinaryOp::Mult => Some(a.checked_mul(*b)),
+            BinaryOp::Div => Some(a.checked_div(*b)),
+            _ => None,
+        };
+        match checked {
+            Some(Some(i)) => return Ok(Some(Literal::Int(i))),
+            Some(None) if op == BinaryOp::Div && *b == 0 => {
+                return Err(FoldErr::DivisionByZero(line))
+            }
+            Some(None) => return Err(FoldErr::Overflow(op.symbol(), line)),
+            None => {}
+        }
+    }
+
+    let (l, r) = (l.clone(), r.clone());
+    let result = match op {
+        BinaryOp::EqEq => Ok(Literal::Bool(l == r)),
+        BinaryOp::BangEq => Ok(Literal::Bool(l != r)),
+        BinaryOp::Plus => l + r,
+        BinaryOp::Minus => l - r,
+        BinaryOp::Mult => l * r,
+        BinaryOp::Div => l / r,
+        BinaryOp::Gt => Ok(Literal::Bool(l > r)),
+        BinaryOp::Lt => Ok(Literal::Bool(l < r)),
+        BinaryOp::GtEq => Ok(Literal::Bool(l >= r)),
+        BinaryOp::LtEq => Ok(Literal::Bool(l <= r)),
+        BinaryOp::Eq => return Ok(None),
+    };
+    Ok(match result {
+        // `inf` and `NaN` have no literal syntax
+        Ok(Literal::Float(f)) if !f.is_finite() => None,
+        Ok(folded) => Some(folded),
+        Err(_) => None,
+    })
+}
+
+/// whether `expr` always evaluates to a bool.
+fn is_bool(expr: &Expr) -> bool {
+    match expr {
+        Expr::LiteralExpr(l) => matches!(l, Literal::Bool(_)),
+        Expr::Unary { op, .. } => *op == UnaryOp::Bang,
+        Expr::Binary { op, .. } => matches!(
+            op,
+            BinaryOp::EqEq
+                | BinaryOp::BangEq
+                | BinaryOp::Gt
+                | BinaryOp::GtEq
+                | BinaryOp::Lt
+                | BinaryOp::LtEq
+        ),
+        Expr::Logical { lhs, rhs, .. } => is_bool(lhs) && is_bool(rhs),
+        Expr::Grouping(inner) => is_bool(inner),
+        _ => false,
+    }
+}
+
+impl VisitorMut for Optimizer<'_> {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        self.line = stmt.line;
+        walk_stmt_mut(self, stmt);
+    }
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        // children first, so a parent sees folded operands
+        walk_expr_mut(self, expr);
+        let taken = std::mem::replace(expr, Expr::LiteralExpr(Literal::Nil));
+        *expr = self.simplify(taken);
+    }
+}
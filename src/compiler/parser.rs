@@ -1,9 +1,13 @@
-use core::panic;
+use std::rc::Rc;
 
 use crate::compiler::{
     ast::{
-        expr::{BinaryOp, Expr, UnaryOp},
+        expr::{BinaryOp, Expr, Identifier, LogicalOp, UnaryOp},
         literal::Literal,
+        types::{Type, TypeParam},
+    },
+    statements::stmt::{
+        Block, Catch, ClassDecl, FnDecl, InterfaceDecl, MethodSig, Param, Stmt, StmtKind,
     },
     token::{Token, TokenType},
 };
@@ -11,29 +15,195 @@ use crate::compiler::{
 pub struct Parser<'a> {
     tokens: &'a Vec<Token>,
     cur_idx: usize,
+    /// how deeply the source nests at the current token.
+    depth: usize,
+    /// the links of the chains, like `a + b + c`, the current token is in.
+    links: usize,
+    /// the errors of the statements left out so far, see `parse_partial`.
+    errors: Vec<ParseErr>,
 }
 
+/// how deeply expressions, statements and types may nest. Every pass after this one walks the tree
+/// recursively, so a bound here is what keeps them all from overflowing the stack.
+pub const MAX_NESTING: usize = 128;
+
+/// how many links chains of operators and calls, like `a + b + c` or `f()()`, may have at once.
+/// Each link nests the tree a level deeper on the left without the source nesting, so chains are
+/// bounded apart from `MAX_NESTING`, and more loosely.
+pub const MAX_CHAIN: usize = 1024;
+
+#[derive(Debug)]
 pub enum ParseErr {
-    InvalidExpr,
-    MissingRParen,
+    InvalidExpr(usize),
+    MissingRParen(usize),
+    Expected(&'static str, usize),
+    InvalidAssignTarget(usize),
+    TooDeep(usize),
+    TooLong(usize),
+    /// a lexeme the lexer could not make a token of.
+    InvalidToken(String, usize),
+}
+
+impl std::fmt::Display for ParseErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParseErr::InvalidExpr(line) => write!(f, "line {}: expected an expression", line),
+            ParseErr::MissingRParen(line) => write!(f, "line {}: expected ')'", line),
+            ParseErr::Expected(what, line) => write!(f, "line {}: expected {}", line, what),
+            ParseErr::InvalidAssignTarget(line) => {
+                write!(f, "line {}: invalid assignment target", line)
+            }
+            ParseErr::TooDeep(line) => write!(
+                f,
+                "line {}: nested more than {} levels deep",
+                line, MAX_NESTING
+            ),
+            ParseErr::TooLong(line) => write!(
+                f,
+                "line {}: more than {} operations chained together",
+                line, MAX_CHAIN
+            ),
+            ParseErr::InvalidToken(lexeme, line) => {
+                write!(f, "line {}: unexpected '{}'", line, lexeme)
+            }
+        }
+    }
 }
 
-/// expression     → equality ;
+impl ParseErr {
+    pub fn line(&self) -> usize {
+        match self {
+            ParseErr::InvalidExpr(line)
+            | ParseErr::MissingRParen(line)
+            | ParseErr::Expected(_, line)
+            | ParseErr::InvalidAssignTarget(line)
+            | ParseErr::TooDeep(line)
+            | ParseErr::TooLong(line)
+            | ParseErr::InvalidToken(_, line) => *line,
+        }
+    }
+}
+
+/// program        → declaration* ;
+/// declaration    → "export"? ( classDecl | interfaceDecl | fnDecl | letDecl ) | importDecl
+///                | statement ;
+/// importDecl     → "import" STRING "as" IDENTIFIER ";" ;
+/// classDecl      → "class" IDENTIFIER typeParams? "{" ( "fn" function )* "}" ;
+/// interfaceDecl  → "interface" IDENTIFIER "{" ( "fn" signature ";" )* "}" ;
+/// fnDecl         → "fn" function ;
+/// function       → signature block ;
+/// signature      → IDENTIFIER typeParams? "(" ( param ( "," param )* )? ")" ( "->" type )? ;
+/// typeParams     → "<" typeParam ( "," typeParam )* ">" ;
+/// typeParam      → IDENTIFIER ( ":" IDENTIFIER ( "+" IDENTIFIER )* )? ;
+/// param          → IDENTIFIER ( ":" type )? ;
+/// letDecl        → "let" IDENTIFIER ( ":" type )? ( "=" expression )? ";" ;
+/// type           → "nil" | IDENTIFIER ( "<" type ( "," type )* ">" )?
+///                | "fn" "(" ( type ( "," type )* )? ")" "->" type ;
+/// statement      → exprStmt | printStmt | ifStmt | whileStmt | forStmt | returnStmt | throwStmt
+///                | tryStmt | block ;
+/// exprStmt       → expression ";" ;
+/// printStmt      → "print" expression ";" ;
+/// ifStmt         → "if" expression block ( "else" ( ifStmt | block ) )? ;
+/// whileStmt      → "while" expression block ;
+/// forStmt        → "for" IDENTIFIER "in" expression block ;
+/// returnStmt     → "return" expression? ";" ;
+/// throwStmt      → "throw" expression ";" ;
+/// tryStmt        → "try" block ( "catch" "(" IDENTIFIER ")" block )? ( "finally" block )? ;
+/// block          → "{" declaration* "}" ;
+///
+/// expression     → assignment ;
+/// assignment     → ( ( call "." )? IDENTIFIER | call "[" expression "]" ) "=" assignment
+///                | logic_or ;
+/// logic_or       → logic_and ( "or" logic_and )* ;
+/// logic_and      → equality ( "and" equality )* ;
 /// equality       → comparison ( ( "!=" | "==" ) comparison )* ;
 /// comparison     → term ( ( ">" | ">=" | "<" | "<=" ) term )* ;
 /// term           → factor ( ( "-" | "+" ) factor )* ;
 /// factor         → unary ( ( "/" | "*" ) unary )* ;
-/// unary          → ( "!" | "-" ) unary | primary ;
-/// primary        → NUMBER | STRING | "true" | "false" | "nil" | "(" expression ")" ;
+/// unary          → ( "!" | "-" ) unary | call ;
+/// call           → primary ( "(" arguments? ")" | "." IDENTIFIER | "[" expression "]" )* ;
+/// arguments      → expression ( "," expression )* ","? ;
+/// entries        → expression ":" expression ( "," expression ":" expression )* ","? ;
+/// primary        → NUMBER | STRING | "true" | "false" | "nil" | "this" | IDENTIFIER
+///                | "(" expression ")" | "[" arguments? "]" | "{" entries? "}" ;
 impl<'a> Parser<'a> {
     pub fn new(tokens: &'a Vec<Token>) -> Self {
-        Self { tokens, cur_idx: 0 }
+        Self {
+            tokens,
+            cur_idx: 0,
+            depth: 0,
+            links: 0,
+            errors: Vec::new(),
+        }
+    }
+    /// goes a level deeper into the source, unless that is too deep.
+    fn descend(&mut self) -> Result<(), ParseErr> {
+        if self.depth == MAX_NESTING {
+            return Err(ParseErr::TooDeep(self.line()));
+        }
+        self.depth += 1;
+        Ok(())
+    }
+    /// adds a link to a chain, unless there are too many. Loops building left-nested trees, like
+    /// `a + b + c`, add one every time round and restore the count after.
+    fn link(&mut self) -> Result<(), ParseErr> {
+        if self.links == MAX_CHAIN {
+            return Err(ParseErr::TooLong(self.line()));
+        }
+        self.links += 1;
+        Ok(())
+    }
+    /// parses with `parse` a level deeper.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, ParseErr>,
+    ) -> Result<T, ParseErr> {
+        self.descend()?;
+        let result = parse(self);
+        self.depth -= 1;
+        result
     }
     fn peek(&self) -> Result<&Token, ParseErr> {
         if self.cur_idx < self.tokens.len() {
             Ok(&self.tokens[self.cur_idx])
         } else {
-            Err(ParseErr::InvalidExpr)
+            Err(ParseErr::InvalidExpr(self.line()))
+        }
+    }
+    /// line of the current token, or of the last one once every token is consumed.
+    fn line(&self) -> usize {
+        match self.tokens.get(self.cur_idx).or(self.tokens.last()) {
+            Some(t) => t.line,
+            None => 0,
+        }
+    }
+    fn is_at_end(&self) -> bool {
+        match self.peek() {
+            Ok(t) => t.token_type == TokenType::Eof,
+            Err(_) => true,
+        }
+    }
+    fn check(&self, token_type: TokenType) -> bool {
+        match self.peek() {
+            Ok(t) => t.token_type == token_type,
+            Err(_) => false,
+        }
+    }
+    /// consumes the current token if it matches token_type, otherwise errors with what was expected.
+    fn expect(&mut self, token_type: TokenType, what: &'static str) -> Result<&Token, ParseErr> {
+        if self.consume_match(token_type) {
+            Ok(self.previous())
+        } else {
+            Err(self.unexpected(ParseErr::Expected(what, self.line())))
+        }
+    }
+    /// `err`, unless the current token is not a token at all, which is the better thing to report.
+    fn unexpected(&self, err: ParseErr) -> ParseErr {
+        match self.peek() {
+            Ok(t) if t.token_type == TokenType::Invalid => {
+                ParseErr::InvalidToken(t.lexeme.clone(), t.line)
+            }
+            _ => err,
         }
     }
     fn previous(&self) -> &Token {
@@ -69,25 +239,481 @@ impl<'a> Parser<'a> {
         }
         false
     }
+    /// parses a whole program, or reports the first error in it.
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, ParseErr> {
+        let (stmts, mut errors) = self.parse_partial();
+        if errors.is_empty() {
+            Ok(stmts)
+        } else {
+            Err(errors.swap_remove(0))
+        }
+    }
+    /// parses as much of a program as it can, for tools that want to make something of code being
+    /// edited. A statement with an error is left out, and parsing goes on with the next; a block
+    /// keeps the statements around it. Returns the errors in the order they were found.
+    pub fn parse_partial(&mut self) -> (Vec<Stmt>, Vec<ParseErr>) {
+        let mut stmts = Vec::new();
+        while !self.is_at_end() {
+            let (start, depth) = (self.cur_idx, self.depth);
+            match self.declaration() {
+                Ok(stmt) => stmts.push(stmt),
+                Err(e) => self.recover(e, start, depth),
+            }
+        }
+        (stmts, std::mem::take(&mut self.errors))
+    }
+    /// notes the error of the statement that started at token `start` and nesting `depth`, and
+    /// skips to where the next one probably starts: past a `;`, or before a `}` or a keyword that
+    /// begins a statement. Moves on at least a token, so one no statement starts with is not tried
+    /// again.
+    fn recover(&mut self, err: ParseErr, start: usize, depth: usize) {
+        self.errors.push(err);
+        // statements are never inside expressions, so no chain is either
+        self.depth = depth;
+        self.links = 0;
+        if self.cur_idx == start {
+            self.advance();
+        }
+        while !self.is_at_end() && self.previous().token_type != TokenType::Semi {
+            match self.peek().map(|t| t.token_type) {
+                Ok(
+                    TokenType::RBrace
+                    | TokenType::Class
+                    | TokenType::Interface
+                    | TokenType::Fn
+                    | TokenType::Let
+                    | TokenType::Import
+                    | TokenType::Export
+                    | TokenType::If
+                    | TokenType::While
+                    | TokenType::For
+                    | TokenType::Print
+                    | TokenType::Return
+                    | TokenType::Throw
+                    | TokenType::Try,
+                ) => return,
+                _ => self.advance(),
+            }
+        }
+    }
+    fn declaration(&mut self) -> Result<Stmt, ParseErr> {
+        let line = self.line();
+        let exported = self.consume_match(TokenType::Export);
+        let kind = if self.consume_match(TokenType::Class) {
+            self.class_decl()?
+        } else if self.consume_match(TokenType::Interface) {
+            self.interface_decl()?
+        } else if self.consume_match(TokenType::Fn) {
+            StmtKind::Fn(Rc::new(self.function()?))
+        } else if self.consume_match(TokenType::Let) {
+            self.let_decl()?
+        } else if exported {
+            return Err(ParseErr::Expected(
+                "a declaration after 'export'",
+                self.line(),
+            ));
+        } else if self.consume_match(TokenType::Import) {
+            self.import_decl()?
+        } else {
+            return self.statement();
+        };
+        Ok(Stmt {
+            kind,
+            line,
+            exported,
+        })
+    }
+    fn import_decl(&mut self) -> Result<StmtKind, ParseErr> {
+        let lexeme = &self
+            .expect(TokenType::Str, "module path after 'import'")?
+            .lexeme;
+        let path = lexeme[1..lexeme.len() - 1].to_owned();
+        self.expect(TokenType::As, "'as' after module path")?;
+        let name = Identifier::from(self.expect(TokenType::Identifier, "module name")?);
+        self.expect(TokenType::Semi, "';' after import")?;
+        Ok(StmtKind::Import { path, name })
+    }
+    fn class_decl(&mut self) -> Result<StmtKind, ParseErr> {
+        let name = Identifier::from(self.expect(TokenType::Identifier, "class name")?);
+        let type_params = self.type_params()?;
+        self.expect(TokenType::LBrace, "'{' before class body")?;
+        let mut methods = Vec::new();
+        while !self.check(TokenType::RBrace) && !self.is_at_end() {
+            self.expect(TokenType::Fn, "'fn' before method")?;
+            methods.push(Rc::new(self.function()?));
+        }
+        let end_line = self.expect(TokenType::RBrace, "'}' after class body")?.line;
+        Ok(StmtKind::Class(ClassDecl {
+            name,
+            type_params,
+            methods,
+            end_line,
+        }))
+    }
+    fn interface_decl(&mut self) -> Result<StmtKind, ParseErr> {
+        let name = Identifier::from(self.expect(TokenType::Identifier, "interface name")?);
+        self.expect(TokenType::LBrace, "'{' before interface body")?;
+        let mut methods = Vec::new();
+        while !self.check(TokenType::RBrace) && !self.is_at_end() {
+            self.expect(TokenType::Fn, "'fn' before method")?;
+            let (name, type_params, params, return_type) = self.signature()?;
+            if !type_params.is_empty() {
+                return Err(ParseErr::Expected("'(' after method name", self.line()));
+            }
+            self.expect(TokenType::Semi, "';' after method signature")?;
+            methods.push(MethodSig {
+                name,
+                params,
+                return_type,
+            });
+        }
+        let end_line = self
+            .expect(TokenType::RBrace, "'}' after interface body")?
+            .line;
+        Ok(StmtKind::Interface(InterfaceDecl {
+            name,
+            methods,
+            end_line,
+        }))
+    }
+    fn function(&mut self) -> Result<FnDecl, ParseErr> {
+        let (name, type_params, params, return_type) = self.signature()?;
+        self.expect(TokenType::LBrace, "'{' before function body")?;
+        let body = self.block()?;
+        Ok(FnDecl {
+            name,
+            type_params,
+            params,
+            return_type,
+            body,
+        })
+    }
+    /// everything of a function up to its body.
+    #[allow(clippy::type_complexity)]
+    fn signature(
+        &mut self,
+    ) -> Result<(Identifier, Vec<TypeParam>, Vec<Param>, Option<Type>), ParseErr> {
+        let name = Identifier::from(self.expect(TokenType::Identifier, "function name")?);
+        let type_params = self.type_params()?;
+        self.expect(TokenType::LParen, "'(' after function name")?;
+        let mut params = Vec::new();
+        if !self.check(TokenType::RParen) {
+            loop {
+                let name = Identifier::from(self.expect(TokenType::Identifier, "parameter name")?);
+                let ty = self.annotation(TokenType::Colon)?;
+                params.push(Param { name, ty });
+                if !self.consume_match(TokenType::Comma) {
+                    break;
+                }
+            }
+        }
+        self.expect(TokenType::RParen, "')' after parameters")?;
+        let return_type = self.annotation(TokenType::Arrow)?;
+        Ok((name, type_params, params, return_type))
+    }
+    /// parses `<T: Bound, U>` if the current token is `<`.
+    fn type_params(&mut self) -> Result<Vec<TypeParam>, ParseErr> {
+        let mut type_params = Vec::new();
+        if !self.consume_match(TokenType::Lt) {
+            return Ok(type_params);
+        }
+        loop {
+            let name = self
+                .expect(TokenType::Identifier, "type parameter name")?
+                .lexeme
+                .clone();
+            let mut bounds = Vec::new();
+            if self.consume_match(TokenType::Colon) {
+                loop {
+                    bounds.push(self.type_name("interface name")?);
+                    if !self.consume_match(TokenType::Plus) {
+                        break;
+                    }
+                }
+            }
+            type_params.push(TypeParam { name, bounds });
+            if !self.consume_match(TokenType::Comma) {
+                break;
+            }
+        }
+        self.expect(TokenType::Gt, "'>' after type parameters")?;
+        Ok(type_params)
+    }
+    /// the name of a class or interface, which may be another module's: `alias.Name`.
+    fn type_name(&mut self, what: &'static str) -> Result<String, ParseErr> {
+        let mut name = self.expect(TokenType::Identifier, what)?.lexeme.clone();
+        if self.consume_match(TokenType::Dot) {
+            name.push('.');
+            name.push_str(&self.expect(TokenType::Identifier, what)?.lexeme);
+        }
+        Ok(name)
+    }
+    /// parses a type if the current token is `introducer` (the `:` or `->` in front of it).
+    fn annotation(&mut self, introducer: TokenType) -> Result<Option<Type>, ParseErr> {
+        if self.consume_match(introducer) {
+            Ok(Some(self.type_ann()?))
+        } else {
+            Ok(None)
+        }
+    }
+    fn type_ann(&mut self) -> Result<Type, ParseErr> {
+        if self.consume_match(TokenType::Nil) {
+            return Ok(Type::Nil);
+        }
+        if self.consume_match(TokenType::Fn) {
+            self.expect(TokenType::LParen, "'(' after 'fn' in type")?;
+            let mut params = Vec::new();
+            if !self.check(TokenType::RParen) {
+                loop {
+                    params.push(self.nested(Self::type_ann)?);
+                    if !self.consume_match(TokenType::Comma) {
+                        break;
+                    }
+                }
+            }
+            self.expect(TokenType::RParen, "')' after parameter types")?;
+            self.expect(TokenType::Arrow, "'->' before return type")?;
+            let ret = Box::new(self.nested(Self::type_ann)?);
+            return Ok(Type::Fn { params, ret });
+        }
+        let name = self.type_name("a type")?;
+        if let Some(ty) = Type::from_name(&name) {
+            return Ok(ty);
+        }
+        let mut args = Vec::new();
+        if self.consume_match(TokenType::Lt) {
+            loop {
+                args.push(self.nested(Self::type_ann)?);
+                if !self.consume_match(TokenType::Comma) {
+                    break;
+                }
+            }
+            self.expect(TokenType::Gt, "'>' after type arguments")?;
+        }
+        match (name.as_str(), args.len()) {
+            ("list", 1) => Ok(Type::List(Box::new(args.remove(0)))),
+            ("list", _) => Err(ParseErr::Expected("one type argument to list", self.line())),
+            ("map", 2) => {
+                let value = args.pop().unwrap();
+                let key = args.pop().unwrap();
+                Ok(Type::Map(Box::new(key), Box::new(value)))
+            }
+            ("map", _) => Err(ParseErr::Expected("two type arguments to map", self.line())),
+            _ => Ok(Type::Instance(name, args)),
+        }
+    }
+    fn let_decl(&mut self) -> Result<StmtKind, ParseErr> {
+        let name = Identifier::from(self.expect(TokenType::Identifier, "variable name")?);
+        let ty = self.annotation(TokenType::Colon)?;
+        let init = if self.consume_match(TokenType::Eq) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
+        self.expect(TokenType::Semi, "';' after variable declaration")?;
+        Ok(StmtKind::Let { name, ty, init })
+    }
+    fn statement(&mut self) -> Result<Stmt, ParseErr> {
+        let line = self.line();
+        let kind = if self.consume_match(TokenType::Print) {
+            let expr = self.expression()?;
+            self.expect(TokenType::Semi, "';' after value")?;
+            StmtKind::Print(expr)
+        } else if self.consume_match(TokenType::If) {
+            self.if_stmt()?
+        } else if self.consume_match(TokenType::While) {
+            let cond = self.expression()?;
+            self.expect(TokenType::LBrace, "'{' after while condition")?;
+            let body = self.block()?;
+            StmtKind::While { cond, body }
+        } else if self.consume_match(TokenType::For) {
+            let name = Identifier::from(self.expect(TokenType::Identifier, "loop variable name")?);
+            self.expect(TokenType::In, "'in' after loop variable")?;
+            let iterable = self.expression()?;
+            self.expect(TokenType::LBrace, "'{' after for iterable")?;
+            let body = self.block()?;
+            StmtKind::For {
+                name,
+                iterable,
+                body,
+            }
+        } else if self.consume_match(TokenType::Return) {
+            let value = if self.check(TokenType::Semi) {
+                None
+            } else {
+                Some(self.expression()?)
+            };
+            self.expect(TokenType::Semi, "';' after return value")?;
+            StmtKind::Return(value)
+        } else if self.consume_match(TokenType::Throw) {
+            let value = self.expression()?;
+            self.expect(TokenType::Semi, "';' after thrown value")?;
+            StmtKind::Throw(value)
+        } else if self.consume_match(TokenType::Try) {
+            self.try_stmt()?
+        } else if self.consume_match(TokenType::LBrace) {
+            StmtKind::Block(self.block()?)
+        } else {
+            let expr = self.expression()?;
+            self.expect(TokenType::Semi, "';' after expression")?;
+            StmtKind::Expr(expr)
+        };
+        Ok(Stmt {
+            kind,
+            line,
+            exported: false,
+        })
+    }
+    fn if_stmt(&mut self) -> Result<StmtKind, ParseErr> {
+        let cond = self.expression()?;
+        self.expect(TokenType::LBrace, "'{' after if condition")?;
+        let then_branch = self.block()?;
+        let links = self.links;
+        let else_branch = if self.consume_match(TokenType::Else) {
+            if self.check(TokenType::If) {
+                // `else if` chains nest the tree like `a + b + c` does, not the source
+                self.link()?;
+                Some(Box::new(self.statement()?))
+            } else {
+                let line = self.line();
+                self.expect(TokenType::LBrace, "'{' after else")?;
+                Some(Box::new(Stmt {
+                    kind: StmtKind::Block(self.block()?),
+                    line,
+                    exported: false,
+                }))
+            }
+        } else {
+            None
+        };
+        self.links = links;
+        Ok(StmtKind::If {
+            cond,
+            then_branch,
+            else_branch,
+        })
+    }
+    fn try_stmt(&mut self) -> Result<StmtKind, ParseErr> {
+        self.expect(TokenType::LBrace, "'{' after try")?;
+        let body = self.block()?;
+        let catch = if self.consume_match(TokenType::Catch) {
+            self.expect(TokenType::LParen, "'(' after catch")?;
+            let name = Identifier::from(self.expect(TokenType::Identifier, "exception name")?);
+            self.expect(TokenType::RParen, "')' after exception name")?;
+            self.expect(TokenType::LBrace, "'{' after catch")?;
+            let body = self.block()?;
+            Some(Catch { name, body })
+        } else {
+            None
+        };
+        let finally = if self.consume_match(TokenType::Finally) {
+            self.expect(TokenType::LBrace, "'{' after finally")?;
+            Some(self.block()?)
+        } else {
+            None
+        };
+        if catch.is_none() && finally.is_none() {
+            return Err(ParseErr::Expected(
+                "'catch' or 'finally' after try block",
+                self.line(),
+            ));
+        }
+        Ok(StmtKind::Try {
+            body,
+            catch,
+            finally,
+        })
+    }
+    /// parses the rest of a block whose opening '{' was already consumed.
+    fn block(&mut self) -> Result<Block, ParseErr> {
+        let mut stmts = Vec::new();
+        while !self.check(TokenType::RBrace) && !self.is_at_end() {
+            let (start, depth) = (self.cur_idx, self.depth);
+            match self.nested(Self::declaration) {
+                Ok(stmt) => stmts.push(stmt),
+                Err(e) => self.recover(e, start, depth),
+            }
+        }
+        let end_line = self.expect(TokenType::RBrace, "'}' after block")?.line;
+        Ok(Block { stmts, end_line })
+    }
     fn expression(&mut self) -> Result<Expr, ParseErr> {
-        match self.equality() {
-            Ok(e) => Ok(e),
-            Err(e) => Err(e),
+        self.nested(Self::assignment)
+    }
+    fn assignment(&mut self) -> Result<Expr, ParseErr> {
+        let expr = self.or()?;
+
+        if self.consume_match(TokenType::Eq) {
+            let line = self.previous().line;
+            let value = Box::new(self.nested(Self::assignment)?);
+            return match expr {
+                Expr::Variable(name) => Ok(Expr::Assign { name, value }),
+                Expr::Get { object, name } => Ok(Expr::Set {
+                    object,
+                    name,
+                    value,
+                }),
+                Expr::Index {
+                    object,
+                    index,
+                    line,
+                } => Ok(Expr::SetIndex {
+                    object,
+                    index,
+                    value,
+                    line,
+                }),
+                _ => Err(ParseErr::InvalidAssignTarget(line)),
+            };
         }
+
+        Ok(expr)
+    }
+    fn or(&mut self) -> Result<Expr, ParseErr> {
+        let mut expr = self.and()?;
+
+        let links = self.links;
+        while self.consume_match(TokenType::Or) {
+            self.link()?;
+            let rhs = Box::new(self.and()?);
+            expr = Expr::Logical {
+                lhs: Box::new(expr),
+                op: LogicalOp::Or,
+                rhs,
+            };
+        }
+
+        self.links = links;
+        Ok(expr)
+    }
+    fn and(&mut self) -> Result<Expr, ParseErr> {
+        let mut expr = self.equality()?;
+
+        let links = self.links;
+        while self.consume_match(TokenType::And) {
+            self.link()?;
+            let rhs = Box::new(self.equality()?);
+            expr = Expr::Logical {
+                lhs: Box::new(expr),
+                op: LogicalOp::And,
+                rhs,
+            };
+        }
+
+        self.links = links;
+        Ok(expr)
     }
     fn equality(&mut self) -> Result<Expr, ParseErr> {
-        let mut expr = match self.comparison() {
-            Ok(u) => u,
-            Err(e) => return Err(e),
-        };
+        let mut expr = self.comparison()?;
+        let links = self.links;
 
-        let mut op = BinaryOp::EqEq; // just made multiply default op. If no valid op is found, then returns Err
         let types = [TokenType::BangEq, TokenType::EqEq];
 
-        while (self.consume_first_match(&types)) {
-            op = match BinaryOp::try_from(&self.previous().token_type) {
+        while self.consume_first_match(&types) {
+            self.link()?;
+            let op = match BinaryOp::try_from(&self.previous().token_type) {
                 Ok(o) => o,
-                Err(_) => return Err(ParseErr::InvalidExpr),
+                Err(_) => return Err(ParseErr::InvalidExpr(self.line())),
             };
 
             let rhs = match self.comparison() {
@@ -102,15 +728,13 @@ impl<'a> Parser<'a> {
             };
         }
 
+        self.links = links;
         Ok(expr)
     }
     fn comparison(&mut self) -> Result<Expr, ParseErr> {
-        let mut expr = match self.term() {
-            Ok(u) => u,
-            Err(e) => return Err(e),
-        };
+        let mut expr = self.term()?;
+        let links = self.links;
 
-        let mut op = BinaryOp::Gt; // just made multiply default op. If no valid op is found, then returns Err
         let types = [
             TokenType::Gt,
             TokenType::GtEq,
@@ -118,10 +742,11 @@ impl<'a> Parser<'a> {
             TokenType::LtEq,
         ];
 
-        while (self.consume_first_match(&types)) {
-            op = match BinaryOp::try_from(&self.previous().token_type) {
+        while self.consume_first_match(&types) {
+            self.link()?;
+            let op = match BinaryOp::try_from(&self.previous().token_type) {
                 Ok(o) => o,
-                Err(_) => return Err(ParseErr::InvalidExpr),
+                Err(_) => return Err(ParseErr::InvalidExpr(self.line())),
             };
 
             let rhs = match self.term() {
@@ -136,21 +761,20 @@ impl<'a> Parser<'a> {
             };
         }
 
+        self.links = links;
         Ok(expr)
     }
     fn term(&mut self) -> Result<Expr, ParseErr> {
-        let mut expr = match self.factor() {
-            Ok(u) => u,
-            Err(e) => return Err(e),
-        };
+        let mut expr = self.factor()?;
+        let links = self.links;
 
-        let mut op = BinaryOp::Plus; // just made multiply default op. If no valid op is found, then returns Err
-        let types = [TokenType::Minus, TokenType::Minus];
+        let types = [TokenType::Minus, TokenType::Plus];
 
-        while (self.consume_first_match(&types)) {
-            op = match BinaryOp::try_from(&self.previous().token_type) {
+        while self.consume_first_match(&types) {
+            self.link()?;
+            let op = match BinaryOp::try_from(&self.previous().token_type) {
                 Ok(o) => o,
-                Err(_) => return Err(ParseErr::InvalidExpr),
+                Err(_) => return Err(ParseErr::InvalidExpr(self.line())),
             };
 
             let rhs = match self.factor() {
@@ -165,21 +789,20 @@ impl<'a> Parser<'a> {
             };
         }
 
+        self.links = links;
         Ok(expr)
     }
     fn factor(&mut self) -> Result<Expr, ParseErr> {
-        let mut expr = match self.unary() {
-            Ok(u) => u,
-            Err(e) => return Err(e),
-        };
+        let mut expr = self.unary()?;
+        let links = self.links;
 
-        let mut op = BinaryOp::Mult; // just made multiply default op. If no valid op is found, then returns Err
         let types = [TokenType::Slash, TokenType::Star];
 
-        while (self.consume_first_match(&types)) {
-            op = match BinaryOp::try_from(&self.previous().token_type) {
+        while self.consume_first_match(&types) {
+            self.link()?;
+            let op = match BinaryOp::try_from(&self.previous().token_type) {
                 Ok(o) => o,
-                Err(_) => return Err(ParseErr::InvalidExpr),
+                Err(_) => return Err(ParseErr::InvalidExpr(self.line())),
             };
 
             let rhs = match self.unary() {
@@ -194,13 +817,14 @@ impl<'a> Parser<'a> {
             };
         }
 
+        self.links = links;
         Ok(expr)
     }
     fn unary(&mut self) -> Result<Expr, ParseErr> {
         let types = [TokenType::Minus, TokenType::Bang];
         if self.consume_first_match(&types) {
             if let Ok(op) = UnaryOp::try_from(&self.previous().token_type) {
-                let rhs = match self.unary() {
+                let rhs = match self.nested(Self::unary) {
                     Ok(u) => Box::new(u),
                     Err(e) => return Err(e),
                 };
@@ -208,35 +832,121 @@ impl<'a> Parser<'a> {
             }
         }
 
-        match self.primary() {
-            Ok(p) => Ok(p),
-            Err(e) => Err(e),
+        self.call()
+    }
+    fn call(&mut self) -> Result<Expr, ParseErr> {
+        let mut expr = self.primary()?;
+
+        let links = self.links;
+        loop {
+            if self.consume_match(TokenType::LParen) {
+                self.link()?;
+                let args = self.arguments(TokenType::RParen)?;
+                let line = self.expect(TokenType::RParen, "')' after arguments")?.line;
+                expr = Expr::Call {
+                    callee: Box::new(expr),
+                    args,
+                    line,
+                };
+            } else if self.consume_match(TokenType::Dot) {
+                self.link()?;
+                let name = Identifier::from(self.expect(TokenType::Identifier, "property name")?);
+                expr = Expr::Get {
+                    object: Box::new(expr),
+                    name,
+                };
+            } else if self.consume_match(TokenType::LBracket) {
+                self.link()?;
+                let index = self.expression()?;
+                let line = self.expect(TokenType::RBracket, "']' after index")?.line;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    index: Box::new(index),
+                    line,
+                };
+            } else {
+                break;
+            }
+        }
+
+        self.links = links;
+        Ok(expr)
+    }
+    /// parses comma-separated expressions up to, but not including, `close`.
+    fn arguments(&mut self, close: TokenType) -> Result<Vec<Expr>, ParseErr> {
+        let mut args = Vec::new();
+        if !self.check(close) {
+            loop {
+                args.push(self.expression()?);
+                // a trailing comma is allowed, which the formatter emits for wrapped lists
+                if !self.consume_match(TokenType::Comma) || self.check(close) {
+                    break;
+                }
+            }
+        }
+        Ok(args)
+    }
+    /// parses the rest of a map literal whose opening '{' was already consumed.
+    fn map(&mut self) -> Result<Expr, ParseErr> {
+        let mut entries = Vec::new();
+        if !self.check(TokenType::RBrace) {
+            loop {
+                let key = self.expression()?;
+                self.expect(TokenType::Colon, "':' after map key")?;
+                entries.push((key, self.expression()?));
+                if !self.consume_match(TokenType::Comma) || self.check(TokenType::RBrace) {
+                    break;
+                }
+            }
         }
+        self.expect(TokenType::RBrace, "'}' after map entries")?;
+        Ok(Expr::Map(entries))
     }
     fn primary(&mut self) -> Result<Expr, ParseErr> {
         if self.consume_match(TokenType::Num) {
-            let t = self.previous();
-            Ok(Expr::LiteralExpr(Literal::Float(
-                t.lexeme.parse::<f32>().unwrap(),
-            )))
+            let lexeme = &self.previous().lexeme;
+            if lexeme.contains('.') {
+                Ok(Expr::LiteralExpr(Literal::Float(lexeme.parse().unwrap())))
+            } else {
+                match lexeme.parse() {
+                    Ok(i) => Ok(Expr::LiteralExpr(Literal::Int(i))),
+                    Err(_) => Err(ParseErr::Expected(
+                        "an integer that fits in 32 bits",
+                        self.line(),
+                    )),
+                }
+            }
         } else if self.consume_match(TokenType::Nil) {
             Ok(Expr::LiteralExpr(Literal::Nil))
         } else if self.consume_match(TokenType::True) {
             Ok(Expr::LiteralExpr(Literal::Bool(true)))
         } else if self.consume_match(TokenType::False) {
             Ok(Expr::LiteralExpr(Literal::Bool(false)))
+        } else if self.consume_match(TokenType::Str) {
+            let lexeme = &self.previous().lexeme;
+            Ok(Expr::LiteralExpr(Literal::Str(
+                lexeme[1..lexeme.len() - 1].to_owned(),
+            )))
+        } else if self.consume_match(TokenType::This) {
+            Ok(Expr::This(Identifier::from(self.previous())))
+        } else if self.consume_match(TokenType::Identifier) {
+            Ok(Expr::Variable(Identifier::from(self.previous())))
         } else if self.consume_match(TokenType::LParen) {
-            let expr = match self.expression() {
-                Ok(e) => e,
-                Err(e) => return Err(e),
-            };
+            let expr = self.expression()?;
             if !self.consume_match(TokenType::RParen) {
-                Err(ParseErr::MissingRParen)
+                Err(ParseErr::MissingRParen(self.line()))
             } else {
                 Ok(Expr::Grouping(Box::new(expr)))
             }
+        } else if self.consume_match(TokenType::LBracket) {
+            let elements = self.arguments(TokenType::RBracket)?;
+            self.expect(TokenType::RBracket, "']' after list elements")?;
+            Ok(Expr::List(elements))
+        } else if self.consume_match(TokenType::LBrace) {
+            // statements starting with '{' are blocks, so here it can only open a map
+            self.map()
         } else {
-            Err(ParseErr::InvalidExpr)
+            Err(self.unexpected(ParseErr::InvalidExpr(self.line())))
         }
     }
 }
@@ -244,11 +954,10 @@ impl<'a> Parser<'a> {
 impl Iterator for Parser<'_> {
     type Item = Expr;
     fn next(&mut self) -> Option<Self::Item> {
-        while self.cur_idx < self.tokens.len() {
-            match self.expression() {
-                Ok(e) => return Some(e),
-                Err(_) => return None,
-            }
+        if self.cur_idx < self.tokens.len() {
+            self.depth = 0;
+            self.links = 0;
+            return self.expression().ok();
         }
         None
     }
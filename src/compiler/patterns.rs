@@ -6,7 +6,10 @@ pub struct Patterns {
     pub r_paren: Regex,
     pub l_brace: Regex,
     pub r_brace: Regex,
+    pub l_bracket: Regex,
+    pub r_bracket: Regex,
     pub comma: Regex,
+    pub colon: Regex,
     pub dot: Regex,
     pub minus: Regex,
     pub plus: Regex,
@@ -14,6 +17,7 @@ pub struct Patterns {
     pub slash: Regex,
     pub star: Regex,
     // one or two chars
+    pub arrow: Regex,
     pub bang: Regex,
     pub bang_eq: Regex,
     pub eq: Regex,
@@ -34,6 +38,7 @@ pub struct Patterns {
     pub if_: Regex,
     pub fn_: Regex,
     pub for_: Regex,
+    pub in_: Regex,
     pub while_: Regex,
     pub nil: Regex,
     pub print: Regex,
@@ -42,10 +47,26 @@ pub struct Patterns {
     pub true_: Regex,
     pub false_: Regex,
     pub interface: Regex,
+    pub let_: Regex,
+    pub try_: Regex,
+    pub catch: Regex,
+    pub finally: Regex,
+    pub throw: Regex,
+    pub import: Regex,
+    pub export: Regex,
+    pub as_: Regex,
+    // trivia
+    pub comment: Regex,
     pub word_pattern: Regex,
     pub any: Regex,
 }
 
+impl Default for Patterns {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Patterns {
     pub fn new() -> Self {
         Self {
@@ -53,14 +74,18 @@ impl Patterns {
             r_paren: Regex::new(r"^\)").unwrap(),
             l_brace: Regex::new(r"^\{").unwrap(),
             r_brace: Regex::new(r"^\}").unwrap(),
+            l_bracket: Regex::new(r"^\[").unwrap(),
+            r_bracket: Regex::new(r"^\]").unwrap(),
             comma: Regex::new(r"^,").unwrap(),
-            num: Regex::new(r"^-?([0-9]+|[0-9]+\.[0-9]+)").unwrap(),
+            colon: Regex::new(r"^:").unwrap(),
+            num: Regex::new(r"^([0-9]+|[0-9]+\.[0-9]+)").unwrap(),
             dot: Regex::new(r"^\.").unwrap(),
             minus: Regex::new(r"^-").unwrap(),
             plus: Regex::new(r"^\+").unwrap(),
             semi: Regex::new(r"^;").unwrap(),
             slash: Regex::new(r"^\/").unwrap(),
             star: Regex::new(r"^\*").unwrap(),
+            arrow: Regex::new(r"^->").unwrap(),
             bang: Regex::new(r"^!").unwrap(),
             bang_eq: Regex::new(r"^!=").unwrap(),
             eq: Regex::new(r"^=").unwrap(),
@@ -69,25 +94,35 @@ impl Patterns {
             gt_eq: Regex::new(r"^>=").unwrap(),
             lt: Regex::new(r"^<").unwrap(),
             lt_eq: Regex::new(r"^<=").unwrap(),
-            str: Regex::new(r#"".*""#).unwrap(),
-            and: Regex::new(r"^and").unwrap(),
-            or: Regex::new(r"^or").unwrap(),
-            class: Regex::new(r"^class").unwrap(),
-            else_: Regex::new(r"^else").unwrap(),
-            if_: Regex::new(r"^if").unwrap(),
-            fn_: Regex::new(r"^fn").unwrap(),
-            for_: Regex::new(r"^for").unwrap(),
-            while_: Regex::new(r"^while").unwrap(),
-            nil: Regex::new(r"^nil").unwrap(),
-            print: Regex::new(r"^print").unwrap(),
-            return_: Regex::new(r"^return").unwrap(),
-            this: Regex::new(r"^this").unwrap(),
-            true_: Regex::new(r"^true").unwrap(),
-            false_: Regex::new(r"^false").unwrap(),
-            interface: Regex::new(r"^interface").unwrap(),
-            identifier: Regex::new(r"^[a-zA-z_][a-zA-z_0-9]*").unwrap(),
+            str: Regex::new(r#"^"[^"]*""#).unwrap(),
+            and: Regex::new(r"^and$").unwrap(),
+            or: Regex::new(r"^or$").unwrap(),
+            class: Regex::new(r"^class$").unwrap(),
+            else_: Regex::new(r"^else$").unwrap(),
+            if_: Regex::new(r"^if$").unwrap(),
+            fn_: Regex::new(r"^fn$").unwrap(),
+            for_: Regex::new(r"^for$").unwrap(),
+            in_: Regex::new(r"^in$").unwrap(),
+            while_: Regex::new(r"^while$").unwrap(),
+            nil: Regex::new(r"^nil$").unwrap(),
+            print: Regex::new(r"^print$").unwrap(),
+            return_: Regex::new(r"^return$").unwrap(),
+            this: Regex::new(r"^this$").unwrap(),
+            true_: Regex::new(r"^true$").unwrap(),
+            false_: Regex::new(r"^false$").unwrap(),
+            interface: Regex::new(r"^interface$").unwrap(),
+            let_: Regex::new(r"^let$").unwrap(),
+            try_: Regex::new(r"^try$").unwrap(),
+            catch: Regex::new(r"^catch$").unwrap(),
+            finally: Regex::new(r"^finally$").unwrap(),
+            throw: Regex::new(r"^throw$").unwrap(),
+            import: Regex::new(r"^import$").unwrap(),
+            export: Regex::new(r"^export$").unwrap(),
+            as_: Regex::new(r"^as$").unwrap(),
+            comment: Regex::new(r"^//").unwrap(),
+            identifier: Regex::new(r"^[a-zA-Z_][a-zA-Z_0-9]*").unwrap(),
             word_pattern: Regex::new(r"\w").unwrap(),
-            any: Regex::new(r#"(\(|\)|\{|\}|,|-?([0-9]+\.[0-9]+|[0-9]+)|\.|-|\+|;|\/|\*|!=|!|==|=|>=|>|<=|<|".*"|[a-zA-z_][a-zA-z_0-9]*|\S+)"#).unwrap(),
+            any: Regex::new(r#"(//.*|\(|\)|\{|\}|\[|\]|,|:|->|([0-9]+\.[0-9]+|[0-9]+)|\.|-|\+|;|\/|\*|!=|!|==|=|>=|>|<=|<|"[^"]*"|[a-zA-Z_][a-zA-Z_0-9]*|\S+)"#).unwrap(),
         }
     }
 }
@@ -0,0 +1,281 @@
+//! Where running a program spends its effort. An interpreter with a `Profiler` counts every step,
+//! a statement or expression evaluated, against the function and the line running it. Time is
+//! sampled: every so many steps the clock is read, and the time since it last was goes to what is
+//! running then, both to its own time and to the totals of every function with a call in progress,
+//! and to the calls that led there, which make up the stacks a flame graph is drawn from. Sampled
+//! the same way, a function's self time never exceeds its total.
+//!
+//! Nothing is counted without a profiler, which the interpreter only checks for.
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use crate::compiler::statements::stmt::FnDecl;
+
+/// how many steps pass between samples. A step is far quicker than reading the clock.
+const SAMPLE_INTERVAL: u64 = 64;
+
+/// What a function cost. Recursive calls count towards the totals once, from the outermost.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionProfile {
+    /// `<script>` for the top-level code of a module.
+    pub name: String,
+    pub module: usize,
+    /// where it is declared, 0 for top-level code.
+    pub line: usize,
+    pub calls: u64,
+    /// the steps it took, not counting those of the functions it called.
+    pub self_steps: u64,
+    pub total_steps: u64,
+    /// sampled, as is the total.
+    pub self_time: Duration,
+    pub total_time: Duration,
+}
+
+/// What a line cost, counting only its own statements and expressions.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct LineProfile {
+    pub module: usize,
+    pub line: usize,
+    pub steps: u64,
+    /// sampled.
+    pub time: Duration,
+}
+
+/// what a function is known by: the declaration, or the module whose top-level code it is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Key {
+    Function(*const FnDecl),
+    Script(usize),
+}
+
+/// Counts for a run as it goes, and reports them once it is over.
+#[derive(Debug)]
+pub struct Profiler {
+    functions: Vec<FunctionProfile>,
+    /// the index of each function in `functions`.
+    keys: HashMap<Key, usize>,
+    /// per function, how many calls to it are in progress, and the step the outermost started at.
+    active: Vec<(usize, u64)>,
+    lines: HashMap<(usize, usize), LineProfile>,
+    /// the functions in progress, outermost first.
+    stack: Vec<usize>,
+    /// the sampled time of each stack that has been running.
+    stacks: HashMap<Vec<usize>, Duration>,
+    steps: u64,
+    /// when the last sample was taken.
+    sampled: Instant,
+}
+
+impl Default for Profiler {
+    fn default() -> Self {
+        Self {
+            functions: Vec::new(),
+            keys: HashMap::new(),
+            active: Vec::new(),
+            lines: HashMap::new(),
+            stack: Vec::new(),
+            stacks: HashMap::new(),
+            steps: 0,
+            sampled: Instant::now(),
+        }
+    }
+}
+
+impl Profiler {
+    /// the start of a call to `decl`, declared in `module`.
+    pub(crate) fn enter(&mut self, decl: &Rc<FnDecl>, module: usize) {
+        let key = Key::Function(Rc::as_ptr(decl));
+        self.push(key, &decl.name.name, module, decl.name.line);
+    }
+    /// the start of the top-level code of `module`.
+    pub(crate) fn enter_script(&mut self, module: usize) {
+        self.push(Key::Script(module), "<script>", module, 0);
+    }
+    fn push(&mut self, key: Key, name: &str, module: usize, line: usize) {
+        if self.stack.is_empty() {
+            self.sampled = Instant::now();
+        }
+        let index = *self.keys.entry(key).or_insert_with(|| {
+            self.functions.push(FunctionProfile {
+                name: name.to_owned(),
+                module,
+                line,
+                calls: 0,
+                self_steps: 0,
+                total_steps: 0,
+                self_time: Duration::ZERO,
+                total_time: Duration::ZERO,
+            });
+            self.active.push((0, 0));
+            self.functions.len() - 1
+        });
+        self.functions[index].calls += 1;
+        let active = &mut self.active[index];
+        if active.0 == 0 {
+            *active = (0, self.steps);
+        }
+        active.0 += 1;
+        self.stack.push(index);
+    }
+    /// the end of the innermost call, however it ended.
+    pub(crate) fn exit(&mut self) {
+        let Some(index) = self.stack.pop() else {
+            return;
+        };
+        let (calls, steps) = &mut self.active[index];
+        *calls -= 1;
+        if *calls == 0 {
+            self.functions[index].total_steps += self.steps - *steps;
+        }
+    }
+    /// a step taken at `line` of the innermost call.
+    pub(crate) fn step(&mut self, line: usize) {
+        let Some(&index) = self.stack.last() else {
+            return;
+        };
+        self.steps += 1;
+        let function = &mut self.functions[index];
+        function.self_steps += 1;
+        let module = function.module;
+        let profile = self.lines.entry((module, line)).or_insert(LineProfile {
+            module,
+            line,
+            ..LineProfile::default()
+        });
+        profile.steps += 1;
+        if self.steps.is_multiple_of(SAMPLE_INTERVAL) {
+            self.sample(line);
+        }
+    }
+    /// gives the time since the last sample to `line` of the innermost call, and the calls in
+    /// progress, each function once however many of its calls are.
+    fn sample(&mut self, line: usize) {
+        let Some(&index) = self.stack.last() else {
+            return;
+        };
+        let now = Instant::now();
+        let elapsed = now - self.sampled;
+        self.sampled = now;
+        for (function, (calls, _)) in self.functions.iter_mut().zip(&self.active) {
+            if *calls > 0 {
+                function.total_time += elapsed;
+            }
+        }
+        let function = &mut self.functions[index];
+        function.self_time += elapsed;
+        if let Some(profile) = self.lines.get_mut(&(function.module, line)) {
+            profile.time += elapsed;
+        }
+        match self.stacks.get_mut(self.stack.as_slice()) {
+            Some(time) => *time += elapsed,
+            None => {
+                self.stacks.insert(self.stack.clone(), elapsed);
+            }
+        }
+    }
+    /// ends the calls still in progress when a run stops, having been at `line`.
+    pub(crate) fn finish(&mut self, line: usize) {
+        self.sample(line);
+        while !self.stack.is_empty() {
+            self.exit();
+        }
+    }
+
+    /// every function that ran, those that took the longest by themselves first.
+    pub fn functions(&self) -> Vec<FunctionProfile> {
+        let mut functions = self.functions.clone();
+        functions.sort_by(|a, b| {
+            (b.self_time, b.self_steps, &a.name).cmp(&(a.self_time, a.self_steps, &b.name))
+        });
+        functions
+    }
+    /// every line that ran, those that took the longest first.
+    pub fn lines(&self) -> Vec<LineProfile> {
+        let mut lines: Vec<LineProfile> = self.lines.values().cloned().collect();
+        lines.sort_by(|a, b| {
+            let key = |l: &LineProfile| (l.time, l.steps);
+            key(b)
+                .cmp(&key(a))
+                .then((a.module, a.line).cmp(&(b.module, b.line)))
+        });
+        lines
+    }
+
+    /// a table of the functions, then one of the lines, those that took the longest first, under
+    /// a note on how they were measured. The modules are named by their files, the paths given in
+    /// the order they ran.
+    pub fn report(&self, paths: &[PathBuf]) -> String {
+        let ms = |d: Duration| format!("{:.2}ms", d.as_secs_f64() * 1000.0);
+        let mut out = format!(
+            "times are sampled every {} steps, steps are exact\n\n",
+            SAMPLE_INTERVAL
+        );
+        out.push_str(&format!(
+            "{:>10}  {:>10}  {:>8}  {:>10}  {:>11}  function\n",
+            "self time", "total time", "calls", "self steps", "total steps"
+        ));
+        for f in self.functions() {
+            out.push_str(&format!(
+                "{:>10}  {:>10}  {:>8}  {:>10}  {:>11}  {}\n",
+                ms(f.self_time),
+                ms(f.total_time),
+                f.calls,
+                f.self_steps,
+                f.total_steps,
+                self.label(&f, paths)
+            ));
+        }
+        out.push_str(&format!("\n{:>10}  {:>10}  line\n", "time", "steps"));
+        for l in self.lines() {
+            let file = file_name(paths, l.module);
+            out.push_str(&format!(
+                "{:>10}  {:>10}  {}:{}\n",
+                ms(l.time),
+                l.steps,
+                file,
+                l.line
+            ));
+        }
+        out
+    }
+
+    /// the sampled stacks as flame graph tools read them: a line per stack, its functions from the
+    /// outermost joined by `;`, then the microseconds it ran for.
+    pub fn folded_stacks(&self, paths: &[PathBuf]) -> String {
+        let mut lines: Vec<String> = self
+            .stacks
+            .iter()
+            .filter(|(_, time)| time.as_micros() > 0)
+            .map(|(stack, time)| {
+                let labels: Vec<String> = stack
+                    .iter()
+                    .map(|&index| self.label(&self.functions[index], paths))
+                    .collect();
+                format!("{} {}", labels.join(";"), time.as_micros())
+            })
+            .collect();
+        lines.sort();
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+
+    /// the function's name and where it is, e.g. `area (geometry.txt:3)`.
+    fn label(&self, function: &FunctionProfile, paths: &[PathBuf]) -> String {
+        let file = file_name(paths, function.module);
+        match function.line {
+            0 => format!("{} ({})", function.name, file),
+            line => format!("{} ({}:{})", function.name, file, line),
+        }
+    }
+}
+
+fn file_name(paths: &[PathBuf], module: usize) -> String {
+    match paths.get(module).and_then(|path| path.file_name()) {
+        Some(name) => name.to_string_lossy().into_owned(),
+        None => format!("module {}", module),
+    }
+}
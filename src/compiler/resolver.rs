@@ -0,0 +1,748 @@
+use std::collections::HashMap;
+
+use crate::compiler::{
+    ast::{
+        expr::{Expr, Identifier, Slot},
+        types::{Type, TypeParam},
+        visitor::{walk_assign_mut, walk_expr_mut, walk_return_mut, walk_stmt_mut, VisitorMut},
+    },
+    modules::Module,
+    natives::Natives,
+    statements::stmt::{Block, Catch, ClassDecl, FnDecl, InterfaceDecl, Stmt, StmtKind},
+};
+
+#[derive(Debug, PartialEq)]
+pub enum ResolveErr {
+    Undefined(String, usize),
+    UseBeforeDefinition(String, usize),
+    Duplicate(String, usize),
+    ReturnOutsideFn(usize),
+    ThisOutsideClass(usize),
+    /// `import` or `export`, which only make sense for a module's own top-level declarations.
+    NotTopLevel(&'static str, usize),
+    /// an import of a module the program was not loaded with, see `modules::Loader`.
+    UnknownModule(String, usize),
+    /// the module, as the importing one names it, and the member it does not export.
+    NotExported(String, String, usize),
+    /// a module used other than to get one of its members.
+    ModuleAsValue(String, usize),
+    AssignToModule(String, usize),
+}
+
+impl std::fmt::Display for ResolveErr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResolveErr::Undefined(name, line) => {
+                write!(f, "line {}: undefined variable '{}'", line, name)
+            }
+            ResolveErr::UseBeforeDefinition(name, line) => {
+                write!(f, "line {}: '{}' is used before it is defined", line, name)
+            }
+            ResolveErr::Duplicate(name, line) => {
+                write!(
+                    f,
+                    "line {}: '{}' is already declared in this scope",
+                    line, name
+                )
+            }
+            ResolveErr::ReturnOutsideFn(line) => {
+                write!(f, "line {}: 'return' outside of a function", line)
+            }
+            ResolveErr::ThisOutsideClass(line) => {
+                write!(f, "line {}: 'this' outside of a class", line)
+            }
+            ResolveErr::NotTopLevel(keyword, line) => {
+                write!(f, "line {}: '{}' outside of the top level", line, keyword)
+            }
+            ResolveErr::UnknownModule(path, line) => {
+                write!(f, "line {}: module '{}' was not loaded", line, path)
+            }
+            ResolveErr::NotExported(module, name, line) => {
+                write!(
+                    f,
+                    "line {}: module '{}' does not export '{}'",
+                    line, module, name
+                )
+            }
+            ResolveErr::ModuleAsValue(module, line) => {
+                write!(f, "line {}: module '{}' is not a value", line, module)
+            }
+            ResolveErr::AssignToModule(module, line) => {
+                write!(
+                    f,
+                    "line {}: cannot assign to a member of module '{}'",
+                    line, module
+                )
+            }
+        }
+    }
+}
+
+impl ResolveErr {
+    pub fn line(&self) -> usize {
+        match self {
+            ResolveErr::Undefined(_, line)
+            | ResolveErr::UseBeforeDefinition(_, line)
+            | ResolveErr::Duplicate(_, line)
+            | ResolveErr::ReturnOutsideFn(line)
+            | ResolveErr::ThisOutsideClass(line)
+            | ResolveErr::NotTopLevel(_, line)
+            | ResolveErr::UnknownModule(_, line)
+            | ResolveErr::NotExported(_, _, line)
+            | ResolveErr::ModuleAsValue(_, line)
+            | ResolveErr::AssignToModule(_, line) => *line,
+        }
+    }
+}
+
+/// Where something is in a program's source: in which module, as `resolve_modules` indexes them,
+/// and at what line and column.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Location {
+    pub module: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Class,
+    /// a top-level `let`.
+    Global,
+    /// a `let` in a block, a loop variable or the exception a `catch` binds.
+    Local,
+    Parameter,
+    /// the name an `import` gives a module.
+    Module,
+}
+
+/// A name a program declares, as `resolve_symbols` finds it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Declaration {
+    pub name: String,
+    pub at: Location,
+    pub kind: SymbolKind,
+    /// the last line of the scope the name is declared in, or `usize::MAX` at the top level.
+    pub scope_end: usize,
+}
+
+/// A use of a declared name, e.g. a variable in an expression or assigned to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reference {
+    /// as written, which for `module.member` is just the member.
+    pub name: String,
+    pub at: Location,
+    /// where the name is declared, or `None` for a native.
+    pub decl: Option<Location>,
+}
+
+#[derive(Debug, Default)]
+pub struct Symbols {
+    pub declarations: Vec<Declaration>,
+    pub references: Vec<Reference>,
+}
+
+/// Resolves every variable in the program to a `Slot` and returns the names of the globals, indexed
+/// by `Slot::Global`. The natives come first, then the program's own, except that a program global
+/// named like a native replaces it. Top-level functions and classes are visible everywhere,
+/// top-level `let`s only after their declaration (functions may still refer to them, since they run
+/// later).
+pub fn resolve(program: &mut [Stmt], natives: &Natives) -> Result<Vec<String>, Vec<ResolveErr>> {
+    let mut resolver = Resolver::new(natives);
+    resolver.resolve_module(program, "", &HashMap::new());
+
+    if resolver.errors.is_empty() {
+        Ok(resolver.globals)
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+/// Resolves the modules of a program, in the order the loader put them, into one global table as
+/// `resolve` does a single program, and returns the errors with the index of their module. The
+/// globals of the module the program starts from keep their names; those of the others are
+/// qualified with their module's, e.g. `geometry.area`, and may reuse a native's name without
+/// taking over its slot. `alias.member` becomes the exported global it names. Classes and
+/// interfaces are qualified the same way, in the annotations and bounds naming them, and another
+/// module's exported ones are written `alias.Name`.
+pub fn resolve_modules(
+    modules: &mut [Module],
+    natives: &Natives,
+) -> Result<Vec<String>, Vec<(usize, ResolveErr)>> {
+    let mut resolver = Resolver::new(natives);
+    let errors = resolver.resolve_each(modules);
+    if errors.is_empty() {
+        Ok(resolver.globals)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Resolves the modules as `resolve_modules` does, however many errors there are, and returns the
+/// errors with every name the program declares and every use of one, for tools such as the
+/// language server.
+pub fn resolve_symbols(
+    modules: &mut [Module],
+    natives: &Natives,
+) -> (Vec<(usize, ResolveErr)>, Symbols) {
+    let mut resolver = Resolver::new(natives);
+    resolver.symbols = Some(Symbols::default());
+    let errors = resolver.resolve_each(modules);
+    (errors, resolver.symbols.unwrap_or_default())
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+enum FnKind {
+    #[default]
+    None,
+    Function,
+    Method,
+}
+
+/// a name declared in a scope.
+struct Local {
+    name: String,
+    /// whether its initializer has run.
+    defined: bool,
+    /// `None` for `this`, which is not declared anywhere.
+    at: Option<Location>,
+}
+
+#[derive(Default)]
+struct Resolver {
+    globals: Vec<String>,
+    /// whether top-level code has reached the global's declaration yet.
+    globals_defined: Vec<bool>,
+    /// where each global is declared, `None` for the natives.
+    global_decls: Vec<Option<Location>>,
+    /// innermost scope last, each holding its names in declaration order.
+    scopes: Vec<Vec<Local>>,
+    /// the last line of each scope.
+    scope_ends: Vec<usize>,
+    fn_kind: FnKind,
+    in_class: bool,
+    line: usize,
+    errors: Vec<ResolveErr>,
+    /// how many of the globals are natives, which come first.
+    natives: usize,
+    /// index of the module being resolved.
+    module: usize,
+    /// the current module's own globals by unqualified name.
+    namespace: HashMap<String, usize>,
+    /// the current module's imports by the name it gives them, as indices into `exports`, and
+    /// where that name is given.
+    aliases: HashMap<String, (usize, Location)>,
+    /// the globals each module resolved so far exports, by unqualified name.
+    exports: Vec<HashMap<String, usize>>,
+    /// the current module's own top-level classes and interfaces, from the names they are declared
+    /// with to the ones the checker knows them by.
+    types: HashMap<String, String>,
+    /// the classes and interfaces each module resolved so far exports, likewise.
+    type_exports: Vec<HashMap<String, String>>,
+    /// the type parameters in scope, which annotations may name instead of a type.
+    type_params: Vec<String>,
+    /// what `resolve_symbols` returns, if that is what is resolving.
+    symbols: Option<Symbols>,
+}
+
+impl Resolver {
+    fn new(natives: &Natives) -> Self {
+        Self {
+            globals: natives.iter().map(|native| native.name.clone()).collect(),
+            globals_defined: vec![true; natives.len()],
+            global_decls: vec![None; natives.len()],
+            natives: natives.len(),
+            ..Self::default()
+        }
+    }
+    fn resolve_each(&mut self, modules: &mut [Module]) -> Vec<(usize, ResolveErr)> {
+        let mut errors = Vec::new();
+        for (index, module) in modules.iter_mut().enumerate() {
+            self.module = index;
+            self.resolve_module(&mut module.program, &module.name, &module.imports);
+            errors.extend(self.errors.drain(..).map(|e| (index, e)));
+        }
+        errors
+    }
+    /// `qualifier` is the module's name, empty for the module a program starts from. `imports`
+    /// maps each path it imports, as written, to the index of the module loaded for it.
+    fn resolve_module(
+        &mut self,
+        program: &mut [Stmt],
+        qualifier: &str,
+        imports: &HashMap<String, usize>,
+    ) {
+        self.hoist_globals(program, qualifier, imports);
+        for stmt in program.iter_mut() {
+            self.visit_stmt_mut(stmt);
+        }
+    }
+    fn hoist_globals(
+        &mut self,
+        program: &[Stmt],
+        qualifier: &str,
+        imports: &HashMap<String, usize>,
+    ) {
+        self.namespace.clear();
+        self.aliases.clear();
+        self.types.clear();
+        let mut exports = HashMap::new();
+        let mut type_exports = HashMap::new();
+        for stmt in program {
+            let (name, defined, kind) = match &stmt.kind {
+                StmtKind::Fn(decl) => (&decl.name, true, SymbolKind::Function),
+                StmtKind::Class(decl) => {
+                    self.declare_type(&decl.name, qualifier, stmt.exported, &mut type_exports);
+                    (&decl.name, true, SymbolKind::Class)
+                }
+                StmtKind::Let { name, .. } => (name, false, SymbolKind::Global),
+                StmtKind::Interface(decl) => {
+                    self.declare_type(&decl.name, qualifier, stmt.exported, &mut type_exports);
+                    continue;
+                }
+                StmtKind::Import { path, name } => {
+                    match imports.get(path) {
+                        Some(_) if self.is_top_level_name(&name.name) => self
+                            .errors
+                            .push(ResolveErr::Duplicate(name.name.clone(), name.line)),
+                        Some(&module) => {
+                            let at = self.location(name);
+                            self.aliases.insert(name.name.clone(), (module, at));
+                            self.note_declaration(name, SymbolKind::Module, usize::MAX);
+                        }
+                        None => self
+                            .errors
+                            .push(ResolveErr::UnknownModule(path.clone(), name.line)),
+                    }
+                    continue;
+                }
+                _ => continue,
+            };
+            if self.is_top_level_name(&name.name) {
+                self.errors
+                    .push(ResolveErr::Duplicate(name.name.clone(), name.line));
+                continue;
+            }
+            let native = self.globals[..self.natives]
+                .iter()
+                .position(|g| *g == name.name);
+            let index = match native {
+                // the module a program starts from may reuse the name of a native, and takes over
+                // its slot
+                Some(index) if qualifier.is_empty() => {
+                    self.globals_defined[index] = defined;
+                    index
+                }
+                _ if qualifier.is_empty() => self.push_global(name.name.clone(), defined),
+                _ => self.push_global(format!("{}.{}", qualifier, name.name), defined),
+            };
+            self.global_decls[index] = Some(self.location(name));
+            self.note_declaration(name, kind, usize::MAX);
+            self.namespace.insert(name.name.clone(), index);
+            if stmt.exported {
+                exports.insert(name.name.clone(), index);
+            }
+        }
+        self.exports.push(exports);
+        self.type_exports.push(type_exports);
+    }
+    fn push_global(&mut self, name: String, defined: bool) -> usize {
+        self.globals.push(name);
+        self.globals_defined.push(defined);
+        self.global_decls.push(None);
+        self.globals.len() - 1
+    }
+    fn location(&self, name: &Identifier) -> Location {
+        Location {
+            module: self.module,
+            line: name.line,
+            column: name.column,
+        }
+    }
+    fn note_declaration(&mut self, name: &Identifier, kind: SymbolKind, scope_end: usize) {
+        let at = self.location(name);
+        if let Some(symbols) = &mut self.symbols {
+            symbols.declarations.push(Declaration {
+                name: name.name.clone(),
+                at,
+                kind,
+                scope_end,
+            });
+        }
+    }
+    fn note_reference(&mut self, name: &Identifier, decl: Option<Location>) {
+        let at = self.location(name);
+        if let Some(symbols) = &mut self.symbols {
+            symbols.references.push(Reference {
+                name: name.name.clone(),
+                at,
+                decl,
+            });
+        }
+    }
+    fn begin_scope(&mut self, end_line: usize) {
+        self.scopes.push(Vec::new());
+        self.scope_ends.push(end_line);
+    }
+    fn end_scope(&mut self) {
+        self.scopes.pop();
+        self.scope_ends.pop();
+    }
+    fn is_top_level_name(&self, name: &str) -> bool {
+        self.namespace.contains_key(name) || self.aliases.contains_key(name)
+    }
+    fn declare_type(
+        &mut self,
+        name: &Identifier,
+        qualifier: &str,
+        exported: bool,
+        exports: &mut HashMap<String, String>,
+    ) {
+        let qualified = if qualifier.is_empty() {
+            name.name.clone()
+        } else {
+            format!("{}.{}", qualifier, name.name)
+        };
+        if exported {
+            exports.insert(name.name.clone(), qualified.clone());
+        }
+        self.types.insert(name.name.clone(), qualified);
+    }
+    /// what the checker knows the class or interface written `name` by, or `None` if it is not one
+    /// the program declares at the top level, which leaves it for the checker to make sense of.
+    fn type_name(&mut self, name: &str) -> Option<String> {
+        let Some((alias, member)) = name.split_once('.') else {
+            return self.types.get(name).cloned();
+        };
+        let &(module, _) = self.aliases.get(alias)?;
+        let qualified = self.type_exports[module].get(member).cloned();
+        if qualified.is_none() {
+            self.errors.push(ResolveErr::NotExported(
+                alias.to_owned(),
+                member.to_owned(),
+                self.line,
+            ));
+        }
+        qualified
+    }
+    /// rewrites the names of classes and interfaces in an annotation to those `type_name` gives.
+    fn resolve_type(&mut self, ty: &mut Type) {
+        match ty {
+            Type::Instance(name, args) => {
+                for arg in args.iter_mut() {
+                    self.resolve_type(arg);
+                }
+                if args.is_empty() && self.type_params.contains(name) {
+                    return;
+                }
+                if let Some(qualified) = self.type_name(name) {
+                    *name = qualified;
+                }
+            }
+            Type::Fn { params, ret } => {
+                for param in params {
+                    self.resolve_type(param);
+                }
+                self.resolve_type(ret);
+            }
+            Type::List(elem) => self.resolve_type(elem),
+            Type::Map(key, value) => {
+                self.resolve_type(key);
+                self.resolve_type(value);
+            }
+            _ => {}
+        }
+    }
+    /// brings type parameters into scope, and resolves the interfaces bounding them.
+    fn begin_type_params(&mut self, params: &mut [TypeParam]) {
+        for param in params {
+            for bound in &mut param.bounds {
+                if let Some(qualified) = self.type_name(bound) {
+                    *bound = qualified;
+                }
+            }
+            self.type_params.push(param.name.clone());
+        }
+    }
+    /// the module's own global named `name`, or else the native.
+    fn global_index(&self, name: &str) -> Option<usize> {
+        match self.namespace.get(name) {
+            Some(&index) => Some(index),
+            None => self.globals[..self.natives].iter().position(|g| g == name),
+        }
+    }
+    /// the name and index of the module `expr` names, if it is an imported module's name that no
+    /// local shadows.
+    fn module_of(&self, expr: &Expr) -> Option<(String, usize)> {
+        let Expr::Variable(name) = expr else {
+            return None;
+        };
+        if self
+            .scopes
+            .iter()
+            .any(|scope| scope.iter().any(|local| local.name == name.name))
+        {
+            return None;
+        }
+        let (module, _) = *self.aliases.get(&name.name)?;
+        Some((name.name.clone(), module))
+    }
+    /// declares `name` in the innermost scope, or points it at its hoisted global at the top level.
+    fn declare(&mut self, name: &mut Identifier, kind: SymbolKind) {
+        let at = self.location(name);
+        let Some(scope) = self.scopes.last_mut() else {
+            name.slot = self.global_index(&name.name).map(Slot::Global);
+            return;
+        };
+        if scope.iter().any(|local| local.name == name.name) {
+            self.errors
+                .push(ResolveErr::Duplicate(name.name.clone(), name.line));
+        }
+        name.slot = Some(Slot::Local {
+            depth: 0,
+            index: scope.len(),
+        });
+        scope.push(Local {
+            name: name.name.clone(),
+            defined: false,
+            at: Some(at),
+        });
+        let scope_end = self.scope_ends.last().copied().unwrap_or(usize::MAX);
+        self.note_declaration(name, kind, scope_end);
+    }
+    fn define(&mut self, name: &Identifier) {
+        match (self.scopes.last_mut(), name.slot) {
+            (Some(scope), Some(Slot::Local { index, .. })) => scope[index].defined = true,
+            (None, Some(Slot::Global(index))) => self.globals_defined[index] = true,
+            _ => {}
+        }
+    }
+    fn resolve_use(&mut self, name: &mut Identifier) {
+        for (depth, scope) in self.scopes.iter().rev().enumerate() {
+            if let Some(index) = scope.iter().position(|local| local.name == name.name) {
+                if !scope[index].defined {
+                    self.errors.push(ResolveErr::UseBeforeDefinition(
+                        name.name.clone(),
+                        name.line,
+                    ));
+                }
+                if let Some(at) = scope[index].at {
+                    self.note_reference(name, Some(at));
+                }
+                name.slot = Some(Slot::Local { depth, index });
+                return;
+            }
+        }
+        if self.aliases.contains_key(&name.name) {
+            self.errors
+                .push(ResolveErr::ModuleAsValue(name.name.clone(), name.line));
+            return;
+        }
+
+        match self.global_index(&name.name) {
+            Some(index) => {
+                self.note_reference(name, self.global_decls[index]);
+                if self.fn_kind == FnKind::None && !self.globals_defined[index] {
+                    self.errors.push(ResolveErr::UseBeforeDefinition(
+                        name.name.clone(),
+                        name.line,
+                    ));
+                }
+                name.slot = Some(Slot::Global(index));
+            }
+            None => self
+                .errors
+                .push(ResolveErr::Undefined(name.name.clone(), name.line)),
+        }
+    }
+    /// parameters and the body's top-level statements share one scope, matching the call frame.
+    fn resolve_function(&mut self, decl: &mut FnDecl, kind: FnKind) {
+        let outer = std::mem::replace(&mut self.fn_kind, kind);
+        let type_params = self.type_params.len();
+        self.begin_type_params(&mut decl.type_params);
+        if let Some(ty) = &mut decl.return_type {
+            self.resolve_type(ty);
+        }
+        self.begin_scope(decl.body.end_line);
+        for param in &mut decl.params {
+            if let Some(ty) = &mut param.ty {
+                self.resolve_type(ty);
+            }
+            self.declare(&mut param.name, SymbolKind::Parameter);
+            self.define(&param.name);
+        }
+        for stmt in &mut decl.body.stmts {
+            self.visit_stmt_mut(stmt);
+        }
+        self.end_scope();
+        self.type_params.truncate(type_params);
+        self.fn_kind = outer;
+    }
+}
+
+impl VisitorMut for Resolver {
+    fn visit_stmt_mut(&mut self, stmt: &mut Stmt) {
+        self.line = stmt.line;
+        if stmt.exported && !self.scopes.is_empty() {
+            self.errors
+                .push(ResolveErr::NotTopLevel("export", stmt.line));
+        }
+        walk_stmt_mut(self, stmt);
+    }
+    fn visit_let_mut(
+        &mut self,
+        name: &mut Identifier,
+        ty: Option<&mut Type>,
+        init: Option<&mut Expr>,
+    ) {
+        if let Some(ty) = ty {
+            self.resolve_type(ty);
+        }
+        self.declare(name, SymbolKind::Local);
+        if let Some(init) = init {
+            self.visit_expr_mut(init);
+        }
+        self.define(name);
+    }
+    fn visit_block_mut(&mut self, block: &mut Block) {
+        self.begin_scope(block.end_line);
+        for stmt in &mut block.stmts {
+            self.visit_stmt_mut(stmt);
+        }
+        self.end_scope();
+    }
+    /// the loop variable and the body's top-level statements share one scope, matching the
+    /// environment each iteration runs in.
+    fn visit_for_mut(&mut self, name: &mut Identifier, iterable: &mut Expr, body: &mut Block) {
+        self.visit_expr_mut(iterable);
+        self.begin_scope(body.end_line);
+        self.declare(name, SymbolKind::Local);
+        self.define(name);
+        for stmt in &mut body.stmts {
+            self.visit_stmt_mut(stmt);
+        }
+        self.end_scope();
+    }
+    fn visit_catch_mut(&mut self, catch: &mut Catch) {
+        self.begin_scope(catch.body.end_line);
+        self.declare(&mut catch.name, SymbolKind::Local);
+        self.define(&catch.name);
+        for stmt in &mut catch.body.stmts {
+            self.visit_stmt_mut(stmt);
+        }
+        self.end_scope();
+    }
+    fn visit_fn_mut(&mut self, decl: &mut FnDecl) {
+        self.declare(&mut decl.name, SymbolKind::Function);
+        self.define(&decl.name);
+        self.resolve_function(decl, FnKind::Function);
+    }
+    fn visit_return_mut(&mut self, value: Option<&mut Expr>) {
+        if self.fn_kind == FnKind::None {
+            self.errors.push(ResolveErr::ReturnOutsideFn(self.line));
+        }
+        walk_return_mut(self, value);
+    }
+    /// the module was bound when hoisting, if the import is at the top level.
+    fn visit_import_mut(&mut self, _path: &mut String, _name: &mut Identifier) {
+        if !self.scopes.is_empty() {
+            self.errors
+                .push(ResolveErr::NotTopLevel("import", self.line));
+        }
+    }
+    fn visit_class_mut(&mut self, decl: &mut ClassDecl) {
+        self.declare(&mut decl.name, SymbolKind::Class);
+        self.define(&decl.name);
+
+        let outer = std::mem::replace(&mut self.in_class, true);
+        let type_params = self.type_params.len();
+        self.begin_type_params(&mut decl.type_params);
+        // methods close over a scope holding `this`, which binding a method fills in at runtime.
+        self.begin_scope(decl.end_line);
+        self.scopes.last_mut().unwrap().push(Local {
+            name: "this".to_owned(),
+            defined: true,
+            at: None,
+        });
+        for method in &mut decl.methods {
+            self.resolve_function(std::rc::Rc::make_mut(method), FnKind::Method);
+        }
+        self.end_scope();
+        self.type_params.truncate(type_params);
+        self.in_class = outer;
+    }
+    /// `Self` in the signatures stands for the implementing type.
+    fn visit_interface_mut(&mut self, decl: &mut InterfaceDecl) {
+        self.type_params.push("Self".to_owned());
+        for method in &mut decl.methods {
+            for param in &mut method.params {
+                if let Some(ty) = &mut param.ty {
+                    self.resolve_type(ty);
+                }
+            }
+            if let Some(ty) = &mut method.return_type {
+                self.resolve_type(ty);
+            }
+        }
+        self.type_params.pop();
+    }
+
+    fn visit_expr_mut(&mut self, expr: &mut Expr) {
+        match expr {
+            Expr::Get { object, name } => {
+                if let Some((alias, module)) = self.module_of(object) {
+                    if let Expr::Variable(object) = object.as_ref() {
+                        let (_, decl) = self.aliases[&alias];
+                        self.note_reference(object, Some(decl));
+                    }
+                    match self.exports[module].get(&name.name) {
+                        Some(&index) => {
+                            self.note_reference(name, self.global_decls[index]);
+                            *expr = Expr::Variable(Identifier {
+                                name: self.globals[index].clone(),
+                                line: name.line,
+                                column: name.column,
+                                slot: Some(Slot::Global(index)),
+                            })
+                        }
+                        None => self.errors.push(ResolveErr::NotExported(
+                            alias,
+                            name.name.clone(),
+                            name.line,
+                        )),
+                    }
+                    return;
+                }
+            }
+            Expr::Set {
+                object,
+                name,
+                value,
+            } => {
+                if let Some((alias, _)) = self.module_of(object) {
+                    self.errors
+                        .push(ResolveErr::AssignToModule(alias, name.line));
+                    self.visit_expr_mut(value);
+                    return;
+                }
+            }
+            _ => {}
+        }
+        walk_expr_mut(self, expr);
+    }
+    fn visit_variable_mut(&mut self, name: &mut Identifier) {
+        self.resolve_use(name);
+    }
+    fn visit_assign_mut(&mut self, name: &mut Identifier, value: &mut Expr) {
+        walk_assign_mut(self, name, value);
+        self.resolve_use(name);
+    }
+    fn visit_this_mut(&mut self, keyword: &mut Identifier) {
+        if !self.in_class {
+            self.errors.push(ResolveErr::ThisOutsideClass(keyword.line));
+            return;
+        }
+        self.resolve_use(keyword);
+    }
+}
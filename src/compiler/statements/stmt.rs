@@ -1 +1,118 @@
-pub enum Stmt {}
+use std::rc::Rc;
+
+use crate::compiler::ast::{
+    expr::{Expr, Identifier},
+    types::{Type, TypeParam},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stmt {
+    pub kind: StmtKind,
+    /// line of the statement's first token.
+    pub line: usize,
+    /// whether the declaration is marked `export`, which lets modules importing this one use it.
+    pub exported: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StmtKind {
+    Expr(Expr),
+    Print(Expr),
+    Let {
+        name: Identifier,
+        ty: Option<Type>,
+        init: Option<Expr>,
+    },
+    Block(Block),
+    If {
+        cond: Expr,
+        then_branch: Block,
+        /// either a `Block` or, for `else if`, another `If`.
+        else_branch: Option<Box<Stmt>>,
+    },
+    While {
+        cond: Expr,
+        body: Block,
+    },
+    /// runs `body` with `name` bound to each element of a list, or each key of a map.
+    For {
+        name: Identifier,
+        iterable: Expr,
+        body: Block,
+    },
+    /// shared so that runtime closures can hold on to their declaration without copying it.
+    Fn(Rc<FnDecl>),
+    Return(Option<Expr>),
+    /// raises any value as an exception.
+    Throw(Expr),
+    /// runs `body`, then `catch` if it raised an exception and there is a handler, then `finally`
+    /// whatever happened. At least one of the two is there.
+    Try {
+        body: Block,
+        catch: Option<Catch>,
+        finally: Option<Block>,
+    },
+    Class(ClassDecl),
+    Interface(InterfaceDecl),
+    /// `import "path" as name;`, which makes the exports of the module at `path` available as
+    /// `name.member`. Only allowed at the top level.
+    Import {
+        path: String,
+        name: Identifier,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub stmts: Vec<Stmt>,
+    /// line of the closing `}`.
+    pub end_line: usize,
+}
+
+/// `catch (name) { body }`, which runs with `name` bound to the exception: the value thrown, or
+/// the message of a runtime error.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Catch {
+    pub name: Identifier,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FnDecl {
+    pub name: Identifier,
+    pub type_params: Vec<TypeParam>,
+    pub params: Vec<Param>,
+    pub return_type: Option<Type>,
+    pub body: Block,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Param {
+    pub name: Identifier,
+    pub ty: Option<Type>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClassDecl {
+    pub name: Identifier,
+    pub type_params: Vec<TypeParam>,
+    pub methods: Vec<Rc<FnDecl>>,
+    pub end_line: usize,
+}
+
+/// Names the methods a type argument must have to satisfy a bound. Only the type checker looks at
+/// interfaces; at runtime they do not exist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InterfaceDecl {
+    pub name: Identifier,
+    pub methods: Vec<MethodSig>,
+    pub end_line: usize,
+}
+
+/// a method without a body. `Self` in its types stands for the implementing type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MethodSig {
+    pub name: Identifier,
+    pub params: Vec<Param>,
+    pub return_type: Option<Type>,
+}
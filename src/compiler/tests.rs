@@ -0,0 +1,1799 @@
+use std::path::Path;
+
+use super::{
+    ast::{
+        expr::{Expr, Slot},
+        literal::Literal,
+        printer::SExprPrinter,
+        types::Type,
+    },
+    checker::check,
+    eval::EvalErr,
+    formatter::format_source,
+    gc::Heap,
+    highlight::{ansi, highlight, html, semantic_tokens, TokenClass, TOKEN_TYPES},
+    interpreter::Interpreter,
+    lexer::Lexer,
+    limits::Limits,
+    natives::{function, Natives},
+    optimizer::optimize,
+    parser::{Parser, MAX_CHAIN, MAX_NESTING},
+    resolver::{resolve, ResolveErr},
+    statements::stmt::{Stmt, StmtKind},
+    testing::SharedOutput,
+    token::Token,
+    value::{Env, Object, Value},
+};
+
+fn parse_resolved(source: &str) -> Result<(Vec<Stmt>, Vec<String>), Vec<ResolveErr>> {
+    parse_resolved_with(source, &Natives::standard())
+}
+
+fn parse_resolved_with(
+    source: &str,
+    natives: &Natives,
+) -> Result<(Vec<Stmt>, Vec<String>), Vec<ResolveErr>> {
+    let tokens: Vec<Token> = Lexer::from_source(source).collect();
+    let mut program = Parser::new(&tokens).parse().unwrap();
+    let globals = resolve(&mut program, natives)?;
+    Ok((program, globals))
+}
+
+/// runs a program and returns what it printed, or the runtime error.
+fn run(source: &str) -> Result<String, String> {
+    run_with(source, false).0
+}
+
+/// runs a program, collecting garbage on every allocation if `gc_stress`, and also returns the
+/// interpreter to inspect its heap.
+fn run_with(source: &str, gc_stress: bool) -> (Result<String, String>, Interpreter) {
+    run_natives(source, &Natives::standard(), gc_stress)
+}
+
+/// like `run_with`, against `natives` rather than the standard library.
+fn run_natives(
+    source: &str,
+    natives: &Natives,
+    gc_stress: bool,
+) -> (Result<String, String>, Interpreter) {
+    let (program, globals) = parse_resolved_with(source, natives).unwrap();
+    let out = SharedOutput::default();
+    let mut interpreter =
+        Interpreter::with_output(natives, globals, Box::new(out.clone())).gc_stress(gc_stress);
+    let result = interpreter.run(&program);
+    let printed = out.text();
+    let result = match result {
+        Ok(()) => Ok(printed),
+        Err(e) => Err(e.to_string()),
+    };
+    (result, interpreter)
+}
+
+fn run_limited(source: &str, limits: Limits) -> Result<String, EvalErr> {
+    let natives = Natives::standard();
+    let (program, globals) = parse_resolved_with(source, &natives).unwrap();
+    let out = SharedOutput::default();
+    let mut interpreter =
+        Interpreter::with_output(&natives, globals, Box::new(out.clone())).with_limits(limits);
+    interpreter.run(&program).map_err(|e| e.error)?;
+    let printed = out.text();
+    Ok(printed)
+}
+
+fn parse_program(source: &str) -> Vec<String> {
+    let tokens: Vec<Token> = Lexer::from_source(source).collect();
+    match Parser::new(&tokens).parse() {
+        Ok(stmts) => stmts.iter().map(SExprPrinter::print_stmt).collect(),
+        Err(e) => panic!("{}", e),
+    }
+}
+
+#[test]
+fn test_parse_statements() {
+    let stmts = parse_program(
+        "let x = 1;
+        fn add(a, b) { return a + b; }
+        class Point { fn init(x) { this.x = x; } }
+        if x > 1 and !false { print add(x, 2); } else if x { x = 3; } else {}
+        while x < 10 { x = x + 1; }",
+    );
+    assert_eq!(
+        stmts,
+        vec![
+            "(let x 1)",
+            "(fn add (a b) (block (return (+ a b))))",
+            "(class Point (fn init (x) (block (= (. this x) x))))",
+            "(if (and (> x 1) (! false)) (block (print (call add x 2))) (if x (block (= x 3)) (block)))",
+            "(while (< x 10) (block (= x (+ x 1))))",
+        ]
+    );
+}
+
+#[test]
+fn test_parse_errors_report_line() {
+    let tokens: Vec<Token> = Lexer::from_source("let x = 1;\nprint x\n").collect();
+    let err = Parser::new(&tokens).parse().unwrap_err();
+    assert_eq!(err.to_string(), "line 2: expected ';' after value");
+
+    let tokens: Vec<Token> = Lexer::from_source("1 + 2 = 3;").collect();
+    let err = Parser::new(&tokens).parse().unwrap_err();
+    assert_eq!(err.to_string(), "line 1: invalid assignment target");
+}
+
+#[test]
+fn test_parse_partial_recovers_after_errors() {
+    let source =
+        "let x = 1\nfn f(a) {\n    let y = ;\n    return a;\n}\nprint @;\nprint \"open\nprint x;\n";
+    let tokens: Vec<Token> = Lexer::from_source(source).collect();
+    let (program, errors) = Parser::new(&tokens).parse_partial();
+    let errors: Vec<String> = errors.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        errors,
+        vec![
+            "line 2: expected ';' after variable declaration",
+            "line 3: expected an expression",
+            "line 6: unexpected '@;'",
+            "line 7: unexpected '\"open'",
+        ]
+    );
+    let stmts: Vec<String> = program.iter().map(SExprPrinter::print_stmt).collect();
+    assert_eq!(stmts, vec!["(fn f (a) (block (return a)))", "(print x)"]);
+}
+
+#[test]
+fn test_parse_nesting_limit() {
+    let parse = |source: String| {
+        let tokens: Vec<Token> = Lexer::from_source(&source).collect();
+        Parser::new(&tokens).parse().map(|_| ())
+    };
+    let under = MAX_NESTING - 2;
+    let over = MAX_NESTING + 1;
+    assert!(parse(format!("print {}1;", "-".repeat(under))).is_ok());
+    // the rest of the pipeline copes with anything the parser lets through
+    let deepest = format!("print {}1{};", "(".repeat(under), ")".repeat(under));
+    assert_eq!(run(&deepest), Ok("1\n".to_owned()));
+
+    let too_deep = [
+        format!("print {}1;", "!".repeat(over)),
+        format!("print {}1{};", "(".repeat(over), ")".repeat(over)),
+        format!("{}1;{}", "{".repeat(over), "}".repeat(over)),
+        format!("let x: {}int{};", "list<".repeat(over), ">".repeat(over)),
+    ];
+    for source in too_deep {
+        let err = parse(source).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("line 1: nested more than {} levels deep", MAX_NESTING)
+        );
+    }
+
+    // chains nest the tree but not the source, so they are bounded apart and more loosely
+    assert!(parse(format!("f{};", "()".repeat(over))).is_ok());
+    let longest = format!("let x = 1; print x{};", " + x".repeat(MAX_CHAIN));
+    // deeper than a test thread's stack takes in a debug build, but not than the binary's
+    let run_longest = std::thread::Builder::new()
+        .stack_size(16 << 20)
+        .spawn(move || run(&longest))
+        .unwrap();
+    assert_eq!(
+        run_longest.join().unwrap(),
+        Ok(format!("{}\n", MAX_CHAIN + 1))
+    );
+    // so do `else if`s, which the tree nests rightwards
+    let branches = |n: usize| {
+        let branch = |i| format!("if x == {} {{ print {}; }}", i, i);
+        let chain: Vec<String> = (0..n).map(branch).collect();
+        format!(
+            "let x = {}; {} else {{ print -1; }}",
+            n - 1,
+            chain.join(" else ")
+        )
+    };
+    // the comparisons are links too
+    let (longest, too_long) = (branches(MAX_CHAIN), branches(MAX_CHAIN + 1));
+    let run_longest = std::thread::Builder::new()
+        .stack_size(16 << 20)
+        .spawn(move || (run(&longest), parse(too_long).unwrap_err().to_string()))
+        .unwrap();
+    let (longest, too_long) = run_longest.join().unwrap();
+    assert_eq!(longest, Ok(format!("{}\n", MAX_CHAIN - 1)));
+    assert_eq!(
+        too_long,
+        format!(
+            "line 1: more than {} operations chained together",
+            MAX_CHAIN
+        )
+    );
+    let too_long = [
+        format!("print 1{};", " + 1".repeat(MAX_CHAIN + 1)),
+        format!("f{};", "()".repeat(MAX_CHAIN + 1)),
+    ];
+    for source in too_long {
+        let err = parse(source).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "line 1: more than {} operations chained together",
+                MAX_CHAIN
+            )
+        );
+    }
+}
+
+#[test]
+fn test_format_normalises_layout_and_keeps_comments() {
+    let source = "// leading comment
+let   x=(1+2) ;   // trailing
+fn  add(a,b){
+return a+b;}
+
+
+
+class Point {
+fn init(x) { this.x = x; }
+    // dangling in class
+}
+if x{print x;}else  if x>2 {
+  // inside else-if
+}else{ }
+";
+    let expected = "// leading comment
+let x = 1 + 2; // trailing
+fn add(a, b) {
+    return a + b;
+}
+
+class Point {
+    fn init(x) {
+        this.x = x;
+    }
+    // dangling in class
+}
+if x {
+    print x;
+} else if x > 2 {
+    // inside else-if
+} else {}
+";
+    let formatted = format_source(source, 100).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format_source(&formatted, 100).unwrap(), formatted);
+}
+
+#[test]
+fn test_format_keeps_comments_after_their_token() {
+    let source = "if true { print 1; } else { print 2; } // after else
+fn fib(n) {
+    if n < 2 { return n; } // trailing
+    return fib(n - 1) + fib(n - 2);
+}
+let a = 1; let b = 2; // both
+print a + // plus
+  b;
+print max(a, // first
+  [b,
+  // own line
+  3]);
+";
+    let expected = "if true {
+    print 1;
+} else {
+    print 2;
+} // after else
+fn fib(n) {
+    if n < 2 {
+        return n;
+    } // trailing
+    return fib(n - 1) + fib(n - 2);
+}
+let a = 1;
+let b = 2; // both
+print a + // plus
+    b;
+print max(a, // first
+    [b,
+    // own line
+    3]);
+";
+    let formatted = format_source(source, 100).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format_source(&formatted, 100).unwrap(), formatted);
+}
+
+#[test]
+fn test_format_wraps_long_argument_lists() {
+    let source = "print combine(first_argument, second_argument, nested(third_argument, fourth));";
+    let expected = "print combine(
+    first_argument,
+    second_argument,
+    nested(third_argument, fourth),
+);
+";
+    let formatted = format_source(source, 40).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format_source(&formatted, 40).unwrap(), formatted);
+    assert_eq!(format_source(source, 100).unwrap(), format!("{}\n", source));
+}
+
+#[test]
+fn test_format_keeps_floats_floats() {
+    // `5 / 2` would divide as ints, and `max(2.5, 1)` not check
+    let source = "print 5.0 / 2.0;\nprint max(2.5, 1.0);\nlet x = 2.0 + 0.25;\n";
+    assert_eq!(format_source(source, 100).unwrap(), source);
+    assert_eq!(run(source), run(&format_source(source, 100).unwrap()));
+}
+
+fn classes(source: &str) -> Vec<(String, TokenClass)> {
+    let spans = highlight(
+        source,
+        Path::new("highlighted.txt"),
+        &[],
+        &Natives::standard(),
+    );
+    let lines: Vec<&str> = source.lines().collect();
+    spans
+        .into_iter()
+        .map(|span| {
+            let start = span.column - 1;
+            let token = &lines[span.line - 1][start..start + span.len];
+            (token.to_owned(), span.class)
+        })
+        .collect()
+}
+
+#[test]
+fn test_highlight_classifies_tokens() {
+    use TokenClass::*;
+    let source = "// area
+class Box {
+    fn init(w: float) { this.w = w; }
+    fn area() -> float { return this.w * this.w; }
+}
+fn scale(b, k) { let s = b.area(); return len(\"x\") * s * k; }
+let total = scale(Box(2.0), 3);
+@
+";
+    let classified = classes(source);
+    let class_of = |line: usize, token: &str| {
+        let tokens = classified.iter().filter(|(t, _)| t == token);
+        tokens.map(|(_, class)| *class).nth(line).unwrap()
+    };
+    assert_eq!(classified[0], ("// area".into(), Comment));
+    assert_eq!(class_of(0, "class"), Keyword);
+    assert_eq!(class_of(0, "Box"), Class);
+    assert_eq!(class_of(0, "init"), Method);
+    assert_eq!(class_of(0, "float"), Type);
+    assert_eq!(class_of(0, "w"), Parameter);
+    assert_eq!(class_of(1, "w"), Field);
+    assert_eq!(class_of(2, "w"), Parameter);
+    assert_eq!(class_of(0, "scale"), Function);
+    assert_eq!(class_of(0, "b"), Parameter);
+    assert_eq!(class_of(0, "s"), Local);
+    assert_eq!(class_of(1, "area"), Method);
+    assert_eq!(class_of(0, "len"), Function);
+    assert_eq!(class_of(0, "\"x\""), String);
+    assert_eq!(class_of(0, "total"), Global);
+    assert_eq!(class_of(0, "2.0"), Number);
+    assert_eq!(class_of(0, "*"), Operator);
+    assert_eq!(class_of(0, "@"), Invalid);
+}
+
+#[test]
+fn test_highlight_renderers() {
+    let source = "let t = \"x\"; let s = \"é<a>\" + t;\nprint s;\n";
+    let spans = highlight(source, Path::new("rendered.txt"), &[], &Natives::standard());
+    assert_eq!(
+        ansi(source, &spans),
+        "\x1b[35mlet\x1b[0m \x1b[1mt\x1b[0m = \x1b[32m\"x\"\x1b[0m; \
+         \x1b[35mlet\x1b[0m \x1b[1ms\x1b[0m = \x1b[32m\"é<a>\"\x1b[0m + \x1b[1mt\x1b[0m;\n\
+         \x1b[35mprint\x1b[0m \x1b[1ms\x1b[0m;\n"
+    );
+    let html = html(source, &spans);
+    assert!(html.contains("<span class=\"string\">&quot;é&lt;a&gt;&quot;</span>"));
+    assert!(html.starts_with("<pre class=\"highlight\"><code><span class=\"keyword\">let</span> "));
+
+    // `é` is two bytes but one UTF-16 unit
+    let index = |name: &str| TOKEN_TYPES.iter().position(|t| *t == name).unwrap();
+    let (keyword, variable) = (index("keyword"), index("variable"));
+    let (string, operator) = (index("string"), index("operator"));
+    assert_eq!(
+        semantic_tokens(source, &spans),
+        [
+            [0, 0, 3, keyword, 0],
+            [0, 4, 1, variable, 0],
+            [0, 2, 1, operator, 0],
+            [0, 2, 3, string, 0],
+            [0, 5, 3, keyword, 0],
+            [0, 4, 1, variable, 0],
+            [0, 2, 1, operator, 0],
+            [0, 2, 6, string, 0],
+            [0, 7, 1, operator, 0],
+            [0, 2, 1, variable, 0],
+            [1, 0, 5, keyword, 0],
+            [0, 6, 1, variable, 0],
+        ]
+        .concat()
+    );
+}
+
+#[test]
+fn test_resolver_annotates_slots() {
+    let (program, globals) = parse_resolved(
+        "let g = 1;
+        fn f(a) {
+            let b = a;
+            { print b + g; }
+        }",
+    )
+    .unwrap();
+    // the natives come first
+    let natives = Natives::standard().len();
+    assert_eq!(globals[natives..], ["g", "f"]);
+
+    let StmtKind::Fn(decl) = &program[1].kind else {
+        panic!("expected a function");
+    };
+    let StmtKind::Let {
+        init: Some(Expr::Variable(a)),
+        ..
+    } = &decl.body.stmts[0].kind
+    else {
+        panic!("expected let b = a");
+    };
+    assert_eq!(a.slot, Some(Slot::Local { depth: 0, index: 0 }));
+
+    let StmtKind::Block(block) = &decl.body.stmts[1].kind else {
+        panic!("expected a block");
+    };
+    let StmtKind::Print(Expr::Binary { lhs, rhs, .. }) = &block.stmts[0].kind else {
+        panic!("expected print b + g");
+    };
+    let (Expr::Variable(b), Expr::Variable(g)) = (lhs.as_ref(), rhs.as_ref()) else {
+        panic!("expected variables");
+    };
+    assert_eq!(b.slot, Some(Slot::Local { depth: 1, index: 1 }));
+    assert_eq!(g.slot, Some(Slot::Global(natives)));
+}
+
+#[test]
+fn test_resolver_errors() {
+    let errors = |source: &str| match parse_resolved(source) {
+        Ok(_) => vec![],
+        Err(e) => e.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+    };
+    assert_eq!(
+        errors("print x;\nlet x = 1;"),
+        vec!["line 1: 'x' is used before it is defined"]
+    );
+    assert_eq!(
+        errors("{ let a = 1;\nlet a = 2; }"),
+        vec!["line 2: 'a' is already declared in this scope"]
+    );
+    assert_eq!(
+        errors("{ let a = a; }"),
+        vec!["line 1: 'a' is used before it is defined"]
+    );
+    assert_eq!(errors("print y;"), vec!["line 1: undefined variable 'y'"]);
+    assert_eq!(
+        errors("return 1;"),
+        vec!["line 1: 'return' outside of a function"]
+    );
+    assert_eq!(
+        errors("fn f() { return this; }"),
+        vec!["line 1: 'this' outside of a class"]
+    );
+    // functions run later, so they may refer to globals declared after them
+    assert!(errors("fn f() { return later; }\nlet later = 1;").is_empty());
+}
+
+#[test]
+fn test_interpreter_runs_programs() {
+    let output = run("fn fib(n) {
+            if n < 2 { return n; }
+            return fib(n - 1) + fib(n - 2);
+        }
+        print fib(10);
+
+        fn counter() {
+            let count = 0;
+            fn next() {
+                count = count + 1;
+                return count;
+            }
+            return next;
+        }
+        let next = counter();
+        next();
+        print next();
+
+        class Point {
+            fn init(x, y) {
+                this.x = x;
+                this.y = y;
+            }
+            fn sum() { return this.x + this.y; }
+        }
+        let p = Point(1, 2);
+        p.x = 10;
+        print p.sum();
+        print \"a\" + \"b\";
+        print nil or false and true;");
+    assert_eq!(output, Ok("55\n2\n12\nab\nfalse\n".to_owned()));
+}
+
+#[test]
+fn test_heap_frees_unreachable_objects() {
+    let mut heap = Heap::default();
+    let outer = heap.alloc(Object::Env(Env::default()));
+    let inner = heap.alloc(Object::Env(Env {
+        values: Vec::new(),
+        parent: Some(outer),
+    }));
+    // two environments that are each other's parent, and unreachable
+    let cycle = heap.alloc(Object::Env(Env::default()));
+    let looped = heap.alloc(Object::Env(Env {
+        values: Vec::new(),
+        parent: Some(cycle),
+    }));
+    heap.env_mut(cycle).parent = Some(looped);
+    assert_eq!(heap.live_objects(), 4);
+
+    assert_eq!(heap.collect([inner]), 2);
+    assert_eq!(heap.live_objects(), 2);
+    assert_eq!(heap.env(inner).parent, Some(outer));
+    // freed slots are reused
+    let reused = heap.alloc(Object::Env(Env::default()));
+    assert!(reused == cycle || reused == looped);
+    assert_eq!(heap.collect([]), 3);
+    assert_eq!(heap.collections(), 2);
+}
+
+#[test]
+fn test_gc_collects_cycles() {
+    let source = "
+        class Node {
+            fn init(value) {
+                this.value = value;
+                // the instance refers to itself, and its method closure to the instance
+                this.me = this;
+                this.get = this.value_of;
+            }
+            fn value_of() { return this.value; }
+        }
+        fn make(n) {
+            let node = Node(n);
+            fn peek() { return node.get(); }
+            return peek;
+        }
+        let total = 0;
+        let i = 0;
+        while (i < 200) {
+            total = total + make(i)();
+            i = i + 1;
+        }
+        print total;
+        let kept = Node(7);
+        print kept.me.me.get();";
+    let (normal, interpreter) = run_with(source, false);
+    assert_eq!(normal, Ok("19900\n7\n".to_owned()));
+    assert_eq!(interpreter.heap().collections(), 0);
+
+    let (stressed, interpreter) = run_with(source, true);
+    assert_eq!(stressed, normal);
+    assert!(interpreter.heap().collections() > 1000);
+    // the loop's nodes and closures are gone; what remains is the class, `kept` and their methods
+    assert!(
+        interpreter.heap().live_objects() < 20,
+        "{}",
+        interpreter.heap().live_objects()
+    );
+}
+
+#[test]
+fn test_gc_stress_keeps_live_values() {
+    // operands, arguments and receivers evaluated earlier must survive allocation by later ones
+    let source = "
+        class Pair {
+            fn init(a, b) { this.a = a; this.b = b; }
+            fn swap() { return Pair(this.b, this.a); }
+        }
+        fn id(x) { return x; }
+        fn pair(a, b) { return Pair(a, b); }
+        let p = pair(id(Pair(1, 2)), id(Pair(3, 4)).swap());
+        print p.a.b + p.b.a;
+        print id(p) == p;
+        print (Pair(0, 0).a = id(Pair(5, 6))).swap().a;
+        print Pair(1, 2).swap;";
+    assert_eq!(
+        run_with(source, true).0,
+        Ok("6\ntrue\n6\n<fn swap>\n".to_owned())
+    );
+    assert_eq!(
+        run_with("class A { fn init(x) {} } print A(A(1)) == nil; A();", true).0,
+        Err("expected 1 arguments but got 0".to_owned())
+    );
+}
+
+#[test]
+fn test_interpreter_runtime_errors() {
+    assert_eq!(
+        run("print 1 + true;"),
+        Err("operands of '+' must be numbers or strings".to_owned())
+    );
+    assert_eq!(
+        run("fn f(a) {} f();"),
+        Err("expected 1 arguments but got 0".to_owned())
+    );
+    assert_eq!(
+        run("let x = 1; x();"),
+        Err("can only call functions and classes".to_owned())
+    );
+}
+
+#[test]
+fn test_lists_and_maps() {
+    let source = "
+        let xs = [1, 2, 3];
+        xs[1] = 20;
+        print xs;
+        print xs[1] + len(xs);
+        let m = {\"a\": 1, \"b\": 2};
+        m[\"c\"] = 3;
+        m[\"a\"] = 10;
+        print m;
+        for k in m { print k; print m[k]; }
+        let total = 0;
+        for x in xs { total = total + x; }
+        print total;
+        print [[1], {nil: [true, \"s\"], 2: {}}];
+        print len(\"h\u{e9}llo\");";
+    let expected = "[1, 20, 3]\n23\n{\"a\": 10, \"b\": 2, \"c\": 3}\n";
+    assert_eq!(
+        run(source),
+        Ok(expected.to_owned() + "a\n10\nb\n2\nc\n3\n24\n[[1], {nil: [true, \"s\"], 2: {}}]\n5\n")
+    );
+    // each iteration has its own variable, so closures see the element of theirs
+    assert_eq!(
+        run("let fs = [];
+            for i in [1, 2] { fn f() -> int { return i; } fs = [f]; }
+            print fs[0]();
+            let cycle: list<any> = [1];
+            cycle[0] = cycle;
+            print cycle;"),
+        Ok("2\n[[...]]\n".to_owned())
+    );
+}
+
+#[test]
+fn test_gc_stress_keeps_collection_elements() {
+    let source = "
+        class Box { fn init(v) { this.v = v; } }
+        let boxes = [Box(1), Box(2)];
+        let index = {\"first\": boxes[0], \"pair\": [Box(3), Box(4)]};
+        for b in index[\"pair\"] { boxes[0] = Box(boxes[0].v + b.v); }
+        print boxes[0].v + index[\"first\"].v;
+        print len([Box(5), [Box(6)], {1: Box(7)}]);";
+    assert_eq!(run_with(source, true).0, Ok("9\n3\n".to_owned()));
+}
+
+#[test]
+fn test_collection_runtime_errors() {
+    let cases = [
+        (
+            "let g: any = [1]; print g[5];",
+            "index 5 is out of bounds for a list of length 1",
+        ),
+        (
+            "let g: any = [1]; g[-1] = 2;",
+            "index -1 is out of bounds for a list of length 1",
+        ),
+        (
+            "let g: any = [1]; print g[\"x\"];",
+            "list indices must be ints",
+        ),
+        (
+            "let g: any = {\"a\": 1}; print g[\"b\"];",
+            "key \"b\" is not in the map",
+        ),
+        (
+            "let g: any = {}; g[1.5] = 2;",
+            "map keys must be nil, bools, ints or strings",
+        ),
+        (
+            "let g: any = 3; print g[0];",
+            "only lists and maps can be indexed",
+        ),
+        (
+            "let g: any = 3; for x in g {}",
+            "can only iterate over lists and maps",
+        ),
+        (
+            "print len(3);",
+            "argument 1 of 'len' must be a list, map or str",
+        ),
+    ];
+    for (source, error) in cases {
+        assert_eq!(run(source), Err(error.to_owned()), "{}", source);
+    }
+}
+
+#[test]
+fn test_parse_collections() {
+    let stmts = parse_program(
+        "let m = {\"a\": [1, 2,], 3: []};
+        m[\"a\"][0] = m[3];
+        for x in m[\"a\"] { print x; }",
+    );
+    assert_eq!(
+        stmts,
+        vec![
+            "(let m (map (\"a\" (list 1 2)) (3 (list))))",
+            "(= ([] ([] m \"a\") 0) ([] m 3))",
+            "(for x ([] m \"a\") (block (print x)))",
+        ]
+    );
+    let source =
+        "let m: map<str, list<int>> = {\"a\": [1, 2]};\nfor x in m[\"a\"] {\n    print x;\n}\n";
+    assert_eq!(format_source(source, 100).unwrap(), source);
+}
+
+#[test]
+fn test_check_collections() {
+    assert_eq!(
+        inferred(
+            "let xs = [1, 2];
+            let mixed = [1, \"a\"];
+            let m = {\"a\": xs};
+            fn first(ys: list<str>) { return ys[0]; }
+            let n = len(m);"
+        ),
+        Ok(vec![
+            "xs: list<int>",
+            "mixed: list<any>",
+            "m: map<str, list<int>>",
+            "first: fn(list<str>) -> str",
+            "n: int",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect())
+    );
+    assert_eq!(
+        inferred(
+            "let xs = [1];
+            xs[0] = \"s\";
+            let m = {1: true};
+            print m[\"k\"];
+            print 3[0];
+            for c in \"abc\" {}
+            for x in xs { print x + true; }"
+        ),
+        Err(vec![
+            "line 2: expected int but found str",
+            "line 4: expected int but found str",
+            "line 5: int cannot be indexed",
+            "line 6: str cannot be iterated over",
+            "line 7: '+' cannot be applied to int and bool",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect())
+    );
+}
+
+#[test]
+fn test_standard_library() {
+    let source = "
+        let xs = [3, 1, 2];
+        push(xs, 0);
+        sort(xs);
+        print xs;
+        print pop(xs) + len(xs);
+        let words = split(\" a,b,,c \", \",\");
+        print words;
+        print join(words, \"-\") + trim(\"  !  \");
+        print split(\"ab\", \"\");
+        let names = [\"b\", \"a\"];
+        sort(names);
+        print names;
+        print str(1.5) + str([nil]);
+        print num(\" 2.5 \") + num(1);
+        print abs(-3);
+        print abs(-2.5);
+        print min(2, 3);
+        print max(2.5, 1.0);
+        print floor(2.7);
+        print floor(-2.5);
+        print sqrt(16);
+        print clock() > 0.0;
+        print len;";
+    let expected = "[0, 1, 2, 3]\n6\n[\" a\", \"b\", \"\", \"c \"]\n a-b--c !\n[\"a\", \"b\"]\n";
+    assert_eq!(
+        run_with(source, true).0,
+        Ok(expected.to_owned()
+            + "[\"a\", \"b\"]\n1.5[nil]\n3.5\n3\n2.5\n2\n2.5\n2\n-3\n4\ntrue\n<native fn len>\n")
+    );
+    // a program's own global takes over the slot of a native of the same name
+    assert_eq!(
+        run("fn max(a, b) { return a; } print max(1, 2); let str = 3; print str;"),
+        Ok("1\n3\n".to_owned())
+    );
+}
+
+#[test]
+fn test_standard_library_errors() {
+    let cases = [
+        ("print pop([]);", "'pop' from an empty list"),
+        ("print num(\"x\");", "'num' cannot parse \"x\""),
+        ("print abs(-2147483647 - 1);", "'abs' overflows an int"),
+        (
+            "let g: any = \"s\"; print abs(g);",
+            "argument 1 of 'abs' must be a number",
+        ),
+        (
+            "let g: any = [1]; print join(g, \"\");",
+            "argument 1 of 'join' must be a list of strs",
+        ),
+        (
+            "let g: any = [1, \"a\"]; sort(g);",
+            "argument 1 of 'sort' must be a list of numbers or of strs",
+        ),
+        (
+            "let g: any = 1; push(g, 2);",
+            "argument 1 of 'push' must be a list",
+        ),
+        ("let g: any = trim; g();", "expected 1 arguments but got 0"),
+    ];
+    for (source, error) in cases {
+        assert_eq!(run(source), Err(error.to_owned()), "{}", source);
+    }
+}
+
+#[test]
+fn test_check_standard_library() {
+    assert_eq!(
+        inferred(
+            "let p = pop([1.5]); let m = min(1, 2); let f = max(1, 2.5);
+            let s = split(\"a b\", \" \");"
+        ),
+        Ok(vec!["p: float", "m: int", "f: float", "s: list<str>"]
+            .into_iter()
+            .map(String::from)
+            .collect())
+    );
+    assert_eq!(
+        inferred(
+            "print sqrt(\"s\");
+            push([1], \"a\");
+            sort([true]);
+            print min(1, \"a\");
+            join([1], \",\");"
+        ),
+        Err(vec![
+            "line 1: expected a number but found str",
+            "line 2: expected int but found str",
+            "line 3: expected a number or str but found bool",
+            "line 4: expected a number but found str",
+            "line 5: expected list<str> but found list<int>",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect())
+    );
+}
+
+#[test]
+fn test_host_natives() {
+    let mut natives = Natives::default();
+    natives
+        .register("twice", function(vec![Type::Int], Type::Int), |_, args| {
+            let Value::Literal(Literal::Int(i)) = args[0] else {
+                unreachable!("checked against the signature");
+            };
+            Ok(Value::Literal(Literal::Int(2 * i)))
+        })
+        .register("fail", function(vec![], Type::Nil), |_, _| {
+            Err(EvalErr::Native("failed on purpose".to_owned()))
+        });
+    assert_eq!(
+        run_natives("print twice(twice(3)); print twice;", &natives, false).0,
+        Ok("12\n<native fn twice>\n".to_owned())
+    );
+    // the signature is checked statically, and again at runtime for values typed `any`
+    let (program, _) = parse_resolved_with("print twice(\"a\");", &natives).unwrap();
+    assert_eq!(
+        check(&program, &natives).unwrap_err()[0].to_string(),
+        "line 1: expected int but found str"
+    );
+    assert_eq!(
+        run_natives("let g: any = \"a\"; twice(g);", &natives, false).0,
+        Err("argument 1 of 'twice' must be int".to_owned())
+    );
+    assert_eq!(
+        run_natives("fail();", &natives, false).0,
+        Err("failed on purpose".to_owned())
+    );
+    // `len` is not there unless it is registered
+    assert!(parse_resolved_with("print len;", &natives).is_err());
+}
+
+#[test]
+fn test_parse_type_annotations() {
+    let stmts = parse_program(
+        "let x: int = 1;
+        fn apply(f: fn(int) -> int, v) -> int { return f(v); }",
+    );
+    assert_eq!(
+        stmts,
+        vec![
+            "(let x: int 1)",
+            "(fn apply (f: fn(int) -> int v) -> int (block (return (call f v))))",
+        ]
+    );
+    let source = "fn apply(f: fn(int) -> int, v) -> int {\n    return f(v);\n}\nlet s: str;\n";
+    assert_eq!(format_source(source, 100).unwrap(), source);
+}
+
+#[test]
+fn test_checker_reports_type_errors() {
+    let errors = |source: &str| {
+        let (program, _) = parse_resolved(source).unwrap();
+        match check(&program, &Natives::standard()) {
+            Ok(_) => vec![],
+            Err(e) => e.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+        }
+    };
+    assert_eq!(
+        errors(
+            "fn add(a: int, b: int) -> int { return a + b; }
+            print add(1, \"x\");
+            let s: str = 1 + 2.5;
+            print 1 + true;
+            print -\"a\";
+            fn f() -> bool { return; }
+            let p: Point = nil;
+            let n: int = 1;
+            n.x = 2;
+            add(1);"
+        ),
+        vec![
+            "line 2: expected int but found str",
+            "line 3: expected str but found float",
+            "line 4: '+' cannot be applied to int and bool",
+            "line 5: '-' cannot be applied to str",
+            "line 6: expected bool but found nil",
+            "line 7: unknown type 'Point'",
+            "line 9: int has no properties",
+            "line 10: expected 2 arguments but got 1",
+        ]
+    );
+    // `any` opts out of checking, so only provably wrong operations are rejected
+    assert!(errors("fn f(a: any, b) { return a + b; } print f(1, 2) - f(\"a\", 1.5);").is_empty());
+    assert!(errors(
+        "class P { fn init(x: int) { this.x = x; } fn get() -> int { return this.x; } }
+        let total: int = P(1).get() + 2;"
+    )
+    .is_empty());
+    assert_eq!(
+        errors("class P { fn init(x: int) {} } P(1.5);"),
+        vec!["line 1: expected int but found float"]
+    );
+}
+
+#[test]
+fn test_integer_literals_stay_integers() {
+    assert_eq!(
+        run("print 7 / 2; print 7.0 / 2;"),
+        Ok("3\n3.5\n".to_owned())
+    );
+}
+
+#[test]
+fn test_integer_faults_are_errors() {
+    // rather than panicking, or wrapping around in a release build
+    for (source, error) in [
+        ("print 1 / 0;", "division by zero"),
+        ("let zero = 0; print 7 / zero;", "division by zero"),
+        ("print 2147483647 + 1;", "integer overflow"),
+        ("print -2147483647 - 2;", "integer overflow"),
+        ("print 65536 * 65536;", "integer overflow"),
+        ("print (-2147483647 - 1) / -1;", "integer overflow"),
+        ("let min = -2147483647 - 1; print -min;", "integer overflow"),
+    ] {
+        let result = run(source).unwrap_err();
+        assert!(result.starts_with(error), "{}: {}", source, result);
+    }
+    assert_eq!(run("print 1.0 / 0;"), Ok("inf\n".to_owned()));
+    assert_eq!(
+        run("try { print 2147483647 + 1; } catch (e) { print \"caught: \" + e; }"),
+        Ok("caught: integer overflow\n".to_owned())
+    );
+}
+
+fn inferred(source: &str) -> Result<Vec<String>, Vec<String>> {
+    let (program, _) = parse_resolved(source).unwrap();
+    match check(&program, &Natives::standard()) {
+        Ok(types) => Ok(types
+            .bindings
+            .iter()
+            .map(|b| format!("{}: {}", b.name, b.scheme))
+            .collect()),
+        Err(e) => Err(e.iter().map(|e| e.to_string()).collect()),
+    }
+}
+
+#[test]
+fn test_infers_types_of_unannotated_code() {
+    assert_eq!(
+        inferred(
+            "fn id(x) { return x; }
+            fn add(a, b) { return a + b; }
+            fn twice(f, x) { return f(f(x)); }
+            fn fib(n) {
+                if n < 2 { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            fn log(s) { print s; }
+            let a = id(1);
+            let b = add(id(\"x\"), \"y\");
+            let c = twice(fib, 3.5 < 4);"
+        ),
+        Err(vec!["line 11: expected a number but found bool".to_owned()])
+    );
+    assert_eq!(
+        inferred(
+            "fn id(x) { return x; }
+            fn add(a, b) { return a + b; }
+            fn twice(f, x) { return f(f(x)); }
+            fn fib(n) {
+                if n < 2 { return n; }
+                return fib(n - 1) + fib(n - 2);
+            }
+            fn log(s) { print s; }
+            let a = id(1);
+            let b = add(id(\"x\"), \"y\");
+            let c = twice(fib, 3);
+            class Counter {
+                fn init() { this.n = 0; }
+                fn step(by) { return by - 1.0; }
+            }"
+        ),
+        Ok(vec![
+            "id: fn('a) -> 'a",
+            concat!(
+                "add: fn('a, 'b) -> 'c ",
+                "where 'a: number or str, 'b: number or str, 'c = promote('a, 'b)"
+            ),
+            "twice: fn(fn('a) -> 'a, 'a) -> 'a",
+            "fib: fn('a) -> 'a where 'a: number",
+            "log: fn('a) -> nil",
+            "a: int",
+            "b: str",
+            "c: int",
+            "Counter.init: fn() -> Counter",
+            "Counter.step: fn('a) -> float where 'a: number",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect())
+    );
+}
+
+#[test]
+fn test_numbers_stay_polymorphic() {
+    // a literal does not fix the kind of number an unannotated parameter is
+    let source = "fn f(a) { return a * 2; }\nprint f(1.5);\nprint f(3);";
+    assert_eq!(
+        inferred(source),
+        Ok(vec!["f: fn('a) -> 'a where 'a: number".to_owned()])
+    );
+    assert_eq!(run(source), Ok("3\n6\n".to_owned()));
+    assert_eq!(
+        inferred("fn half(x) { return x / 2.0; }\nlet h: float = half(3);"),
+        Ok(vec![
+            "half: fn('a) -> float where 'a: number".to_owned(),
+            "h: float".to_owned()
+        ])
+    );
+}
+
+#[test]
+fn test_inference_promotes_mixed_numbers() {
+    assert_eq!(
+        inferred(
+            "fn add(a, b) { return a + b; }
+            fn m(a, b) { return min(a, b); }
+            let f = add(1, 2.5);
+            let i = m(1, 2);
+            let s = add(\"a\", \"b\");
+            let x = 1;
+            x = 2.5;
+            let n = 1;
+            n = n * 2;"
+        ),
+        Ok(vec![
+            concat!(
+                "add: fn('a, 'b) -> 'c ",
+                "where 'a: number or str, 'b: number or str, 'c = promote('a, 'b)"
+            ),
+            "m: fn('a, 'b) -> 'c where 'a: number, 'b: number, 'c = promote('a, 'b)",
+            "f: float",
+            "i: int",
+            "s: str",
+            "x: float",
+            "n: int",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect())
+    );
+    assert_eq!(
+        run("fn add(a, b) { return a + b; } print add(1, 2.5);
+            fn m(a, b) { return min(a, b); } print m(1, 2.5);
+            let x = 1; x = 2.5; print x;"),
+        Ok("3.5\n1\n2.5\n".to_owned())
+    );
+    // a string and a number still do not mix, once the call says which is which
+    assert_eq!(
+        inferred("fn add(a, b) { return a + b; }\nprint add(\"a\", 1);").unwrap_err(),
+        vec!["line 2: '+' cannot be applied to str and int"]
+    );
+}
+
+#[test]
+fn test_inference_catches_runtime_errors_early() {
+    let errors = |source: &str| inferred(source).unwrap_err();
+    assert_eq!(
+        errors("fn double(x) { return x * 2; }\nprint double(\"a\");"),
+        vec!["line 2: expected a number but found str"]
+    );
+    assert_eq!(
+        errors("fn mul(a, b) { return a * b; }\nprint mul(true, false);"),
+        vec!["line 2: expected a number but found bool"]
+    );
+    assert_eq!(
+        errors("let x = 1;\nx = \"s\";"),
+        vec!["line 2: expected a number but found str"]
+    );
+    assert_eq!(
+        errors("fn f(g) { return g(1) + g(true); }"),
+        vec!["line 1: expected int but found bool"]
+    );
+    assert_eq!(
+        errors("fn f() -> int {\n    print 1;\n}"),
+        vec!["line 3: expected int but found nil"]
+    );
+}
+
+const COMPARABLE: &str = "interface Comparable {
+    fn compare(other: Self) -> int;
+}
+class Money {
+    fn init(cents: int) { this.cents = cents; }
+    fn compare(other: Money) -> int { return this.cents - other.cents; }
+}
+fn max<T: Comparable>(a: T, b: T) -> T {
+    if a.compare(b) > 0 { return a; }
+    return b;
+}
+";
+
+#[test]
+fn test_parse_generics_and_interfaces() {
+    let stmts = parse_program(
+        "interface Sized { fn size() -> int; }
+        class Box<T, U: Sized + Comparable> {}
+        fn first<T>(b: Box<T, str>) -> T { return b.a; }",
+    );
+    assert_eq!(
+        stmts,
+        vec![
+            "(interface Sized (fn size () -> int))",
+            "(class Box<T, U: Sized + Comparable>)",
+            "(fn first<T> (b: Box<T, str>) -> T (block (return (. b a))))",
+        ]
+    );
+    let source = "interface Sized {\n    fn size() -> int;\n}\n\nclass Box<T> {\n    fn get<U>(u: U) -> T {}\n}\n";
+    assert_eq!(format_source(source, 100).unwrap(), source);
+}
+
+#[test]
+fn test_generics_infer_type_arguments() {
+    let source = format!(
+        "{}class Box<T> {{
+            fn init(value: T) {{ this.value = value; }}
+            fn get() -> T {{ return this.value; }}
+        }}
+        fn larger(a, b) {{ return max(a, b); }}
+        let m = max(Money(5), Money(7));
+        let b: Box<int> = Box(3);
+        let s = Box(\"hi\").get();",
+        COMPARABLE
+    );
+    let types = inferred(&source).unwrap();
+    assert_eq!(
+        types[2..],
+        [
+            "max: fn(T, T) -> T where T: Comparable",
+            "Box.init: fn(T) -> Box<T>",
+            "Box.get: fn() -> T",
+            "larger: fn('a, 'a) -> 'a where 'a: Comparable",
+            "m: Money",
+            "b: Box<int>",
+            "s: str",
+        ]
+    );
+    // the runtime erases type parameters, so generic code runs as is
+    assert_eq!(
+        run(&format!(
+            "{}print max(Money(5), Money(7)).cents;",
+            COMPARABLE
+        )),
+        Ok("7\n".to_owned())
+    );
+}
+
+#[test]
+fn test_unsatisfied_bounds() {
+    let errors = |source: &str| inferred(&format!("{}{}", COMPARABLE, source)).unwrap_err();
+    assert_eq!(
+        errors("class Point {}\nmax(Point(), Point());"),
+        vec!["line 13: Point does not implement Comparable: missing method 'compare'"]
+    );
+    assert_eq!(
+        errors(
+            "class Named { fn compare(other: str) -> int { return 0; } }\nmax(Named(), Named());"
+        ),
+        vec![
+            "line 13: Named does not implement Comparable: 'compare' is fn(str) -> int but \
+             Comparable needs fn(Named) -> int"
+        ]
+    );
+    assert_eq!(
+        errors("max(1, 2);"),
+        vec!["line 12: int does not implement Comparable"]
+    );
+    assert_eq!(
+        errors("class Sorted<T: Comparable> {}\nlet s: Sorted<str>;\nlet t: Sorted<int, int>;"),
+        vec![
+            "line 13: str does not implement Comparable",
+            "line 14: expected 1 type arguments for Sorted but got 2",
+        ]
+    );
+    assert_eq!(
+        errors("fn f<T: Sized>(a: T) {}\nfn g<T>(a: T) { a.compare(a); }"),
+        vec![
+            "line 12: unknown interface 'Sized'",
+            "line 13: T has no method 'compare'",
+        ]
+    );
+}
+
+/// the program after optimisation, one S-expression per statement, or the diagnostics. A program
+/// that fails to check has no operands proven to be ints.
+fn optimized(source: &str) -> Result<Vec<String>, Vec<String>> {
+    let (mut program, _) = parse_resolved(source).unwrap();
+    let ints = check(&program, &Natives::standard())
+        .map(|types| types.ints)
+        .unwrap_or_default();
+    match optimize(&mut program, &ints) {
+        Ok(()) => Ok(program.iter().map(SExprPrinter::print_stmt).collect()),
+        Err(e) => Err(e.iter().map(|e| e.to_string()).collect()),
+    }
+}
+
+#[test]
+fn test_optimizer_folds_constants() {
+    assert_eq!(
+        optimized(
+            "print (45 - 75) * 6 == false;
+            print 7 / 2 + 0.5;
+            print -(2 * 3) < 4 and \"a\" + \"b\";
+            print nil or 1 > 2;
+            print 1 + true;"
+        ),
+        Ok(vec![
+            "(print false)".to_owned(),
+            "(print 3.5)".to_owned(),
+            "(print \"ab\")".to_owned(),
+            "(print false)".to_owned(),
+            "(print (+ 1 true))".to_owned(),
+        ])
+    );
+}
+
+#[test]
+fn test_optimizer_simplifies_identities() {
+    assert_eq!(
+        optimized(
+            "let x = 2;
+            let y: any = \"s\";
+            print (x * 1) + (0 + x) - 0;
+            print x / 1 * (1 * x);
+            print x + 0.0;
+            print !!(x < 3);
+            print !!y;
+            print y * 1;
+            let n: int = y;
+            fn f(a) { return 0 + a - 0; }"
+        ),
+        Ok(vec![
+            "(let x 2)",
+            "(let y: any \"s\")",
+            "(print (+ x x))",
+            "(print (* x x))",
+            "(print (+ x 0.0))",
+            "(print (< x 3))",
+            "(print (! (! y)))",
+            // neither is proven an int, not even `y` once it has been used as one
+            "(print (* y 1))",
+            "(let n: int y)",
+            "(fn f (a) (block (return (- (+ 0 a) 0))))",
+        ]
+        .into_iter()
+        .map(String::from)
+        .collect())
+    );
+}
+
+#[test]
+fn test_optimizer_reports_constant_errors() {
+    assert_eq!(
+        optimized("print 1 / (2 - 2);\nprint 2147483647 + 1;\nprint 1.0 / 0;"),
+        Err(vec![
+            "line 1: division by zero".to_owned(),
+            "line 2: '+' overflows an int".to_owned(),
+        ])
+    );
+    // a float divided by zero is infinite, and the dividend's type is not known here
+    assert_eq!(
+        optimized("let x = 1.5;\nprint x / 0;"),
+        Ok(vec!["(let x 1.5)".to_owned(), "(print (/ x 0))".to_owned()])
+    );
+    assert_eq!(run("let x = 1.5;\nprint x / 0;"), Ok("inf\n".to_owned()));
+}
+
+#[test]
+fn test_optimizer_keeps_identities_off_non_ints() {
+    // `x * 1` must still fail on what `any` hides, and a generic `a + 0` on what it is given
+    assert_eq!(
+        run("let x: any = \"s\"; print x * 1;"),
+        Err("operands of '*' must be numbers".to_owned())
+    );
+    assert_eq!(
+        run("fn f(a: any) { return a + 0; } print f(true);"),
+        Err("operands of '+' must be numbers or strings".to_owned())
+    );
+    assert_eq!(
+        run("fn f(a) { return a * 1; } print f(1.5);"),
+        Ok("1.5\n".to_owned())
+    );
+}
+
+#[test]
+fn test_execution_limits() {
+    let spin = "let n = 0; while true { n = n + 1; }";
+    let fuel = Limits {
+        fuel: Some(1000),
+        ..Limits::default()
+    };
+    assert_eq!(run_limited(spin, fuel), Err(EvalErr::OutOfFuel(1000)));
+    assert_eq!(run_limited("print 1 + 2;", fuel), Ok("3\n".to_owned()));
+
+    let timeout = Limits {
+        timeout: Some(std::time::Duration::from_millis(20)),
+        ..Limits::default()
+    };
+    assert_eq!(
+        run_limited(spin, timeout),
+        Err(EvalErr::Timeout(std::time::Duration::from_millis(20)))
+    );
+
+    let depth = Limits {
+        max_call_depth: 20,
+        ..Limits::default()
+    };
+    let down = "fn down(n) { if n == 0 { return 0; } return 1 + down(n - 1); }";
+    assert_eq!(
+        run_limited(&format!("{} print down(19);", down), depth),
+        Ok("19\n".to_owned())
+    );
+    assert_eq!(
+        run_limited(&format!("{} print down(20);", down), depth),
+        Err(EvalErr::StackOverflow(20))
+    );
+    // the default limit turns runaway recursion into an error on a stack the size of the main
+    // thread's, which is all it allows for
+    let runaway = std::thread::Builder::new()
+        .stack_size(8 << 20)
+        .spawn(|| {
+            let overflow = EvalErr::StackOverflow(Limits::default().max_call_depth);
+            run_limited("fn f() { f(); } f();", Limits::default()) == Err(overflow)
+        })
+        .unwrap();
+    assert!(runaway.join().unwrap());
+    // however many calls are allowed, a stack too small for them is bounded by its size instead,
+    // here a default thread's, which fits only a few dozen calls in a debug build
+    let small = Limits {
+        max_call_depth: usize::MAX,
+        stack_bytes: Some(1 << 20),
+        ..Limits::default()
+    };
+    let runaway = std::thread::Builder::new()
+        .stack_size(2 << 20)
+        .spawn(move || {
+            let result = run_limited("fn f() { f(); } f();", small);
+            matches!(result, Err(EvalErr::StackOverflow(_)))
+        })
+        .unwrap();
+    assert!(runaway.join().unwrap());
+    // and a thread sized for the limits fits all the calls they allow
+    let deep = Limits {
+        max_call_depth: 2000,
+        ..Limits::default()
+    }
+    .on_own_stack();
+    let runaway = std::thread::Builder::new()
+        .stack_size(deep.stack_size())
+        .spawn(move || {
+            run_limited("fn f() { f(); } f();", deep) == Err(EvalErr::StackOverflow(2000))
+        })
+        .unwrap();
+    assert!(runaway.join().unwrap());
+
+    let memory = Limits {
+        max_heap_bytes: Some(64 * 1024),
+        ..Limits::default()
+    };
+    // garbage is collected to stay under the limit, and only what is live counts
+    let churn = "let i = 0; while i < 10000 { let xs = [i, i, i]; i = i + 1; } print \"done\";";
+    assert_eq!(run_limited(churn, memory), Ok("done\n".to_owned()));
+    let hoard = "let all = []; while true { push(all, [1, 2, 3]); }";
+    assert_eq!(
+        run_limited(hoard, memory),
+        Err(EvalErr::OutOfMemory(64 * 1024))
+    );
+    // growing an object in place counts too
+    let grow = "let xs = []; while true { push(xs, 1); }";
+    assert_eq!(
+        run_limited(grow, memory),
+        Err(EvalErr::OutOfMemory(64 * 1024))
+    );
+    // and so do strings, which live outside the heap, whether held in a variable or an object
+    let double = "let s = \"x\"; while true { s = s + s; }";
+    assert_eq!(
+        run_limited(double, memory),
+        Err(EvalErr::OutOfMemory(64 * 1024))
+    );
+    let copies = "let s = \"x\"; let i = 0; while i < 12 { s = s + s; i = i + 1; }
+        let all = []; while len(all) < 20 { push(all, s + \"\"); } print \"kept\";";
+    assert_eq!(
+        run_limited(copies, memory),
+        Err(EvalErr::OutOfMemory(64 * 1024))
+    );
+    let text =
+        "let i = 0; while i < 10000 { let s = \"item \" + str(i); i = i + 1; } print \"done\";";
+    assert_eq!(run_limited(text, memory), Ok("done\n".to_owned()));
+}
+
+#[test]
+fn test_parse_exceptions() {
+    let stmts = parse_program(
+        "try { risky(); } catch (e) { print e; } finally { done(); }
+        try { throw \"oops\"; } finally {}",
+    );
+    assert_eq!(
+        stmts,
+        vec![
+            "(try (block (call risky)) (catch e (block (print e))) (finally (block (call done))))",
+            "(try (block (throw \"oops\")) (finally (block)))",
+        ]
+    );
+    let tokens: Vec<Token> = Lexer::from_source("try { }\nprint 1;").collect();
+    let err = Parser::new(&tokens).parse().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "line 2: expected 'catch' or 'finally' after try block"
+    );
+
+    let source = "try {
+    throw 1; // raised
+} catch (e) {
+    print e;
+} finally {}
+";
+    assert_eq!(format_source(source, 100).unwrap(), source);
+}
+
+#[test]
+fn test_exceptions() {
+    // any value can be thrown, and unwinds through calls to the nearest catch
+    assert_eq!(
+        run(
+            "fn check(n) { if n > 2 { throw [n, \"too big\"]; } return n; }
+            fn twice(n) { return check(n) * 2; }
+            try { print twice(1); print twice(3); print \"unreached\"; }
+            catch (e) { print e; }"
+        ),
+        Ok("2\n[3, \"too big\"]\n".to_owned())
+    );
+    // runtime errors are caught as their message
+    assert_eq!(
+        run("try { print [1][5]; } catch (e) { print \"caught: \" + e; }"),
+        Ok("caught: index 5 is out of bounds for a list of length 1\n".to_owned())
+    );
+    assert_eq!(
+        run("fn ratio(a, b) { return a / b; }
+            try { print ratio(6, 3); print ratio(1, 0); }
+            catch (e) { print \"caught: \" + e; }
+            finally { print \"done\"; }
+            print ratio(9, 3);"),
+        Ok("2\ncaught: division by zero\ndone\n3\n".to_owned())
+    );
+    // finally runs on every way out, and a catch can throw again
+    assert_eq!(
+        run("fn f(how) {
+                try {
+                    if how == 0 { return \"returned\"; }
+                    if how == 1 { throw \"thrown\"; }
+                } finally {
+                    print \"finally \" + str(how);
+                }
+                return \"fell through\";
+            }
+            print f(0);
+            try { f(1); } catch (e) { print e; }
+            print f(2);
+            try {
+                try { throw 1; } catch (e) { throw e + 1; } finally { print \"inner\"; }
+            } catch (e) {
+                print e;
+            }"),
+        Ok(
+            "finally 0\nreturned\nfinally 1\nthrown\nfinally 2\nfell through\ninner\n2\n"
+                .to_owned()
+        )
+    );
+    // a return from finally replaces the exception on its way out
+    assert_eq!(
+        run("fn f() { try { throw 1; } finally { return 2; } } print f();"),
+        Ok("2\n".to_owned())
+    );
+    // the catch variable is scoped to the catch block
+    assert_eq!(
+        run("let e = \"outer\"; try { throw 1; } catch (e) { print e; } print e;"),
+        Ok("1\nouter\n".to_owned())
+    );
+    assert_eq!(
+        run("throw \"nobody catches this\";"),
+        Err("uncaught exception: nobody catches this".to_owned())
+    );
+    // thrown values stay alive while they travel up and while finally blocks run
+    let (result, _) = run_with(
+        "fn f() { try { throw [1, 2]; } finally { let junk = [[3], [4]]; } }
+        try { f(); } catch (e) { print e; }",
+        true,
+    );
+    assert_eq!(result, Ok("[1, 2]\n".to_owned()));
+
+    // going past a limit is not an exception: neither catch nor finally get to run
+    let fuel = Limits {
+        fuel: Some(1000),
+        ..Limits::default()
+    };
+    assert_eq!(
+        run_limited(
+            "try { while true {} } catch (e) { print e; } finally { print \"finally\"; }",
+            fuel
+        ),
+        Err(EvalErr::OutOfFuel(1000))
+    );
+}
+
+#[test]
+fn test_runtime_stack_traces() {
+    let trace = |source: &str| {
+        let (program, globals) = parse_resolved(source).unwrap();
+        let mut interpreter =
+            Interpreter::with_output(&Natives::standard(), globals, Box::new(std::io::sink()));
+        let error = interpreter.run(&program).unwrap_err();
+        let frames: Vec<String> = error.trace.iter().map(|f| f.to_string()).collect();
+        (error.message, frames)
+    };
+    assert_eq!(
+        trace(
+            "fn inner(xs) {
+                print \"start\";
+                return xs[3];
+            }
+            fn outer() {
+                let xs = [1];
+                let value = inner(xs);
+                return value;
+            }
+            print \"go\";
+            outer();"
+        ),
+        (
+            "index 3 is out of bounds for a list of length 1".to_owned(),
+            vec![
+                "at inner (line 3)".to_owned(),
+                "at outer (line 7)".to_owned(),
+                "at <script> (line 11)".to_owned(),
+            ]
+        )
+    );
+    // a rethrown exception's trace is where it was thrown again
+    assert_eq!(
+        trace(
+            "fn fail() { throw \"first\"; }
+            try { fail(); } catch (e) {
+                throw e + \" again\";
+            }"
+        ),
+        (
+            "uncaught exception: first again".to_owned(),
+            vec!["at <script> (line 3)".to_owned()]
+        )
+    );
+    // the line of a loop's condition is the loop's, not that of the body that ran last
+    assert_eq!(
+        trace("let i = 0;\nwhile [1][i] > 0 {\n    i = i + 1;\n}").1,
+        vec!["at <script> (line 2)".to_owned()]
+    );
+}
+
+#[test]
+fn test_check_exceptions() {
+    let errors = |source: &str| {
+        let (program, _) = parse_resolved(source).unwrap();
+        match check(&program, &Natives::standard()) {
+            Ok(_) => vec![],
+            Err(e) => e.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+        }
+    };
+    // a throw ends a path as a return does, and the exception could be anything
+    assert!(errors(
+        "fn f(x: int) -> int { if x > 0 { return x; } throw \"negative\"; }
+        try { f(1); } catch (e) { print e + 1; print e.field; }
+        fn g() -> int { try { return 1; } catch (e) { throw e; } }
+        fn h() -> int { try { print 1; } finally { return 2; } }"
+    )
+    .is_empty());
+    assert_eq!(
+        errors("fn f() -> int { try { return 1; } catch (e) { print e; } }"),
+        vec!["line 1: expected int but found nil"]
+    );
+}
+
+#[test]
+fn test_parse_modules() {
+    let stmts = parse_program(
+        "import \"lib/geometry\" as geo;
+        export fn area(r) { return geo.pi * r * r; }
+        export let unit = 1;",
+    );
+    assert_eq!(
+        stmts,
+        vec![
+            "(import \"lib/geometry\" geo)",
+            "(export (fn area (r) (block (return (* (* (. geo pi) r) r)))))",
+            "(export (let unit 1))",
+        ]
+    );
+    let tokens: Vec<Token> = Lexer::from_source("export print 1;").collect();
+    let err = Parser::new(&tokens).parse().unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "line 1: expected a declaration after 'export'"
+    );
+
+    let source = "import \"util\" as util; // helpers
+
+export class Point {
+    fn init(x) {
+        this.x = x;
+    }
+}
+";
+    assert_eq!(format_source(source, 100).unwrap(), source);
+
+    // a program resolved on its own has nothing loaded for its imports
+    let errors = |source: &str| match parse_resolved(source) {
+        Ok(_) => vec![],
+        Err(e) => e.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+    };
+    assert_eq!(
+        errors("import \"util\" as util;"),
+        vec!["line 1: module 'util' was not loaded"]
+    );
+    assert_eq!(
+        errors("fn f() {\nexport let x = 1; }"),
+        vec!["line 2: 'export' outside of the top level"]
+    );
+}
+
+/// runs a program with a profiler, and returns what it printed and the interpreter.
+fn profiled(source: &str) -> (String, Interpreter) {
+    let (program, globals) = parse_resolved(source).unwrap();
+    let out = SharedOutput::default();
+    let mut interpreter =
+        Interpreter::with_output(&Natives::standard(), globals, Box::new(out.clone()))
+            .with_profiler();
+    interpreter.run(&program).unwrap();
+    let printed = out.text();
+    (printed, interpreter)
+}
+
+#[test]
+fn test_profiler_counts_calls_and_steps() {
+    let source = "fn fib(n) {
+    if (n < 2) { return n; }
+    return fib(n - 1) + fib(n - 2);
+}
+fn twice(x) { return x + x; }
+print twice(fib(10));
+";
+    let (printed, interpreter) = profiled(source);
+    let profiler = interpreter.profiler().unwrap();
+    assert_eq!(printed, "110\n");
+    let functions = profiler.functions();
+    let calls: Vec<(&str, usize, u64)> = functions
+        .iter()
+        .map(|f| (f.name.as_str(), f.line, f.calls))
+        .collect();
+    for expected in [("<script>", 0, 1), ("fib", 1, 177), ("twice", 5, 1)] {
+        assert!(calls.contains(&expected), "{:?} in {:?}", expected, calls);
+    }
+    let steps: u64 = functions.iter().map(|f| f.self_steps).sum();
+    let script = functions.iter().find(|f| f.name == "<script>").unwrap();
+    // the top-level code's total is every step, and each is counted against one line
+    assert_eq!(script.total_steps, steps);
+    assert_eq!(profiler.lines().iter().map(|l| l.steps).sum::<u64>(), steps);
+    // recursive calls count once towards the total
+    let fib = functions.iter().find(|f| f.name == "fib").unwrap();
+    assert_eq!(fib.total_steps, fib.self_steps);
+    let twice = functions.iter().find(|f| f.name == "twice").unwrap();
+    assert_eq!(twice.self_steps, twice.total_steps);
+    assert!(twice.self_steps > 0);
+
+    // both times are sampled, so what a function took by itself is part of its total
+    for f in &functions {
+        assert!(f.self_time <= f.total_time, "{:?}", f);
+    }
+    assert_eq!(
+        script.total_time,
+        functions.iter().map(|f| f.self_time).sum()
+    );
+
+    let report = profiler.report(&[Path::new("fib.txt").to_path_buf()]);
+    assert!(
+        report.starts_with("times are sampled every 64 steps"),
+        "{}",
+        report
+    );
+    assert!(report.contains("fib (fib.txt:1)"), "{}", report);
+    assert!(report.contains("<script> (fib.txt)"), "{}", report);
+    assert!(report.contains("fib.txt:3"), "{}", report);
+}
+
+#[test]
+fn test_profiler_folds_stacks() {
+    let source = "fn fib(n) {
+    if (n < 2) { return n; }
+    return fib(n - 1) + fib(n - 2);
+}
+print fib(16);
+";
+    let (_, interpreter) = profiled(source);
+    let profiler = interpreter.profiler().unwrap();
+    let folded = profiler.folded_stacks(&[Path::new("fib.txt").to_path_buf()]);
+    assert!(!folded.is_empty());
+    for line in folded.lines() {
+        let (stack, micros) = line.rsplit_once(' ').unwrap();
+        assert!(micros.parse::<u64>().unwrap() > 0, "{}", line);
+        let mut frames = stack.split(';');
+        assert_eq!(frames.next(), Some("<script> (fib.txt)"), "{}", line);
+        assert!(frames.all(|frame| frame == "fib (fib.txt:1)"), "{}", line);
+    }
+}
@@ -1,10 +1,13 @@
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenType {
     LParen,
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
     Comma,
+    Colon,
     Dot,
     Minus,
     Plus,
@@ -12,6 +15,7 @@ pub enum TokenType {
     Slash,
     Star,
 
+    Arrow,
     Bang,
     BangEq,
     Eq,
@@ -33,6 +37,7 @@ pub enum TokenType {
     If,
     Fn,
     For,
+    In,
     While,
     Nil,
     Print,
@@ -41,23 +46,26 @@ pub enum TokenType {
     True,
     False,
     Interface,
+    Let,
+    Try,
+    Catch,
+    Finally,
+    Throw,
+    Import,
+    Export,
+    As,
 
+    Comment,
+    /// a lexeme that is no token, such as an unterminated string.
+    Invalid,
     Eof,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub line: usize,
-}
-
-impl Token {
-    fn new(token_type: TokenType, lexeme: String, line: usize) -> Self {
-        Self {
-            token_type,
-            lexeme,
-            line,
-        }
-    }
+    /// of the token's first byte in its line, counting from 1 like `line`.
+    pub column: usize,
 }
@@ -0,0 +1,265 @@
+use std::{any::Any, collections::HashMap, rc::Rc};
+
+use crate::compiler::{ast::literal::Literal, gc::Gc, natives::NativeFn, statements::stmt::FnDecl};
+
+/// A runtime value. Scalars and strings reuse `Literal` so arithmetic follows its operator impls;
+/// functions, classes, instances, lists and maps live on the interpreter's `Heap`, and objects of
+/// host types with the host. Two handles are equal only if they refer to the same object.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Literal(Literal),
+    Function(Gc),
+    Native(Rc<NativeFn>),
+    Class(Gc),
+    Instance(Gc),
+    List(Gc),
+    Map(Gc),
+    Host(HostObject),
+}
+
+impl Value {
+    pub const NIL: Value = Value::Literal(Literal::Nil);
+
+    /// `nil` and `false` are falsey, everything else is truthy.
+    pub fn is_truthy(&self) -> bool {
+        !matches!(
+            self,
+            Value::Literal(Literal::Nil) | Value::Literal(Literal::Bool(false))
+        )
+    }
+    /// the object the value refers to, if it is on the heap.
+    pub fn as_gc(&self) -> Option<Gc> {
+        match self {
+            Value::Literal(_) | Value::Native(_) | Value::Host(_) => None,
+            Value::Function(gc)
+            | Value::Class(gc)
+            | Value::Instance(gc)
+            | Value::List(gc)
+            | Value::Map(gc) => Some(*gc),
+        }
+    }
+    /// the bytes of the string the value holds, which it owns itself rather than the heap.
+    pub fn string_bytes(&self) -> usize {
+        match self {
+            Value::Literal(Literal::Str(s)) => s.len(),
+            _ => 0,
+        }
+    }
+}
+
+impl From<Literal> for Value {
+    fn from(literal: Literal) -> Self {
+        Value::Literal(literal)
+    }
+}
+
+/// An object of a type the host registered, which scripts can only pass around. It never refers to
+/// script values, so the collector can leave it to reference counting.
+#[derive(Clone)]
+pub struct HostObject {
+    pub type_name: &'static str,
+    /// a `RefCell` of the host's type.
+    pub data: Rc<dyn Any>,
+}
+
+impl PartialEq for HostObject {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::addr_eq(Rc::as_ptr(&self.data), Rc::as_ptr(&other.data))
+    }
+}
+
+impl std::fmt::Debug for HostObject {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<{} instance>", self.type_name)
+    }
+}
+
+/// What a map can be keyed by: the literals that are only ever equal to themselves. Floats are
+/// not, since `NaN` is not equal to itself.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Key {
+    Nil,
+    Bool(bool),
+    Int(i32),
+    Str(String),
+}
+
+impl TryFrom<&Value> for Key {
+    type Error = ();
+    fn try_from(value: &Value) -> Result<Self, Self::Error> {
+        match value {
+            Value::Literal(Literal::Nil) => Ok(Key::Nil),
+            Value::Literal(Literal::Bool(b)) => Ok(Key::Bool(*b)),
+            Value::Literal(Literal::Int(i)) => Ok(Key::Int(*i)),
+            Value::Literal(Literal::Str(s)) => Ok(Key::Str(s.clone())),
+            _ => Err(()),
+        }
+    }
+}
+
+/// a key as it prints inside a map.
+impl std::fmt::Display for Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Key::Nil => write!(f, "nil"),
+            Key::Bool(b) => write!(f, "{}", b),
+            Key::Int(i) => write!(f, "{}", i),
+            Key::Str(s) => write!(f, "\"{}\"", s),
+        }
+    }
+}
+
+impl From<Key> for Value {
+    fn from(key: Key) -> Self {
+        Value::Literal(match key {
+            Key::Nil => Literal::Nil,
+            Key::Bool(b) => Literal::Bool(b),
+            Key::Int(i) => Literal::Int(i),
+            Key::Str(s) => Literal::Str(s),
+        })
+    }
+}
+
+/// Everything that lives on the heap.
+#[derive(Debug)]
+pub enum Object {
+    Env(Env),
+    Function(Function),
+    Class(Class),
+    Instance(Instance),
+    List(Vec<Value>),
+    Map(Map),
+}
+
+impl Object {
+    /// the objects this one keeps alive.
+    pub fn references(&self) -> Vec<Gc> {
+        match self {
+            Object::Env(env) => env
+                .values
+                .iter()
+                .filter_map(Value::as_gc)
+                .chain(env.parent)
+                .collect(),
+            Object::Function(function) => function.closure.into_iter().collect(),
+            Object::Class(class) => class.methods.values().copied().collect(),
+            Object::Instance(instance) => instance
+                .fields
+                .values()
+                .filter_map(Value::as_gc)
+                .chain([instance.class])
+                .collect(),
+            Object::List(values) => values.iter().filter_map(Value::as_gc).collect(),
+            Object::Map(map) => map.values().filter_map(Value::as_gc).collect(),
+        }
+    }
+    /// an estimate of the memory the object takes, for deciding when to collect.
+    /// Strings count in full, since copies of them are what the object holds.
+    pub fn size(&self) -> usize {
+        let entry = |key: &String| key.len() + std::mem::size_of::<(String, Value)>();
+        let values = |values: &[Value]| {
+            let strings: usize = values.iter().map(Value::string_bytes).sum();
+            std::mem::size_of_val(values) + strings
+        };
+        std::mem::size_of::<Object>()
+            + match self {
+                Object::Env(env) => values(&env.values),
+                Object::Function(_) => 0,
+                Object::Class(class) => {
+                    class.name.len() + class.methods.keys().map(entry).sum::<usize>()
+                }
+                Object::Instance(instance) => instance
+                    .fields
+                    .iter()
+                    .map(|(name, value)| entry(name) + value.string_bytes())
+                    .sum(),
+                Object::List(list) => values(list),
+                Object::Map(map) => map
+                    .entries()
+                    .map(|(key, value)| {
+                        let key = match key {
+                            Key::Str(s) => s.len(),
+                            _ => 0,
+                        };
+                        std::mem::size_of::<(Key, Value)>() + key + value.string_bytes()
+                    })
+                    .sum(),
+            }
+    }
+}
+
+/// Local variables of one scope, indexed by the resolver's `Slot::Local` index. Closures keep the
+/// scopes they were declared in alive through `parent`.
+#[derive(Debug, Default)]
+pub struct Env {
+    pub values: Vec<Value>,
+    pub parent: Option<Gc>,
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub decl: Rc<FnDecl>,
+    /// `None` for top-level functions, which only see globals.
+    pub closure: Option<Gc>,
+    pub is_initializer: bool,
+    /// the module it was declared in, counting in the order the modules run.
+    pub module: usize,
+}
+
+#[derive(Debug)]
+pub struct Class {
+    pub name: String,
+    /// each a `Function`.
+    pub methods: HashMap<String, Gc>,
+}
+
+impl Class {
+    pub const INITIALIZER: &'static str = "init";
+}
+
+#[derive(Debug)]
+pub struct Instance {
+    /// a `Class`.
+    pub class: Gc,
+    pub fields: HashMap<String, Value>,
+}
+
+/// Entries keep the order they were first inserted in, which is the order `for` visits the keys.
+#[derive(Debug, Default)]
+pub struct Map {
+    entries: Vec<(Key, Value)>,
+    /// the position of every key in `entries`.
+    index: HashMap<Key, usize>,
+}
+
+impl Map {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+    pub fn get(&self, key: &Key) -> Option<&Value> {
+        self.index.get(key).map(|i| &self.entries[*i].1)
+    }
+    /// sets the value of `key`, keeping its position if it is already there.
+    pub fn insert(&mut self, key: Key, value: Value) {
+        match self.index.get(&key) {
+            Some(i) => self.entries[*i].1 = value,
+            None => {
+                self.index.insert(key.clone(), self.entries.len());
+                self.entries.push((key, value));
+            }
+        }
+    }
+    pub fn entries(&self) -> impl Iterator<Item = &(Key, Value)> {
+        self.entries.iter()
+    }
+    pub fn values(&self) -> impl Iterator<Item = &Value> {
+        self.entries.iter().map(|(_, v)| v)
+    }
+    /// the key at `position` in insertion order.
+    pub fn key(&self, position: usize) -> Option<&Key> {
+        self.entries.get(position).map(|(k, _)| k)
+    }
+}
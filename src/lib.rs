@@ -0,0 +1,8 @@
+//! The language as a library: `Engine` compiles and runs scripts for a Rust host, and the passes it
+//! is built from are here too for tools that need them, such as the `compiler` binary.
+
+mod compiler;
+pub mod util;
+
+pub use compiler::*;
+pub use engine::{Engine, Error, Script};
@@ -1,43 +1,577 @@
-use clap::Parser;
-use util::file_util::file_ext;
-use util::file_util::file_name;
-use util::file_util::FileExt;
-
-#[allow(unused)]
-mod compiler;
-#[allow(unused)]
-mod util;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser};
+use compiler::ast::printer::SExprPrinter;
+use compiler::backend::{c, wasm, x86_64};
+use compiler::debugger::{self, cli::Cli, dap};
+use compiler::eval::{EvalErr, RuntimeError};
+use compiler::formatter::{self, format_source};
+use compiler::highlight;
+use compiler::interpreter::Interpreter;
+use compiler::ir::{self, lower::lower};
+use compiler::limits::{self, Limits};
+use compiler::lsp;
+use compiler::modules::cache::{Cache, Timing};
+use compiler::modules::{self, Compiled, Loader, Module, ModuleErr};
+use compiler::natives::Natives;
+use compiler::statements::stmt::Stmt;
+use compiler::util::file_util::file_ext;
+use compiler::util::file_util::FileExt;
+use compiler::util::json::Json;
+use std::path::{Path, PathBuf};
 
 #[derive(clap::Parser, Debug)]
 struct Args {
     #[arg(short, long)]
-    file_path: String,
+    file_path: Option<String>,
+    /// Print the parsed program as S-expressions instead of running it
+    #[arg(long)]
+    dump_ast: bool,
+    /// Print an intermediate form of the program instead of running it
+    #[arg(long, value_enum)]
+    emit: Option<Emit>,
+    /// Optimisation level: 0 runs the program as written, 1 folds constants and drops copies and
+    /// dead code, 2 adds constant propagation and common subexpression elimination to the IR
+    #[arg(short = 'O', default_value_t = 1, value_parser = clap::value_parser!(u8).range(0..=2))]
+    opt_level: u8,
+    /// Collect garbage on every allocation, to flush out values the collector fails to see
+    #[arg(long)]
+    gc_stress: bool,
+    /// Stop the program after it evaluates this many statements and expressions
+    #[arg(long)]
+    fuel: Option<u64>,
+    /// Stop the program after it runs for this many milliseconds
+    #[arg(long)]
+    timeout_ms: Option<u64>,
+    /// Stop the program when it has more calls than this in progress
+    #[arg(long, default_value_t = limits::DEFAULT_MAX_CALL_DEPTH)]
+    max_call_depth: usize,
+    /// Stop the program when its heap grows past this many bytes, as the collector estimates them
+    #[arg(long)]
+    max_heap_bytes: Option<usize>,
+    /// Count the steps and time each function and line of the program takes, and print them to
+    /// stderr once it ends, along with a file of the sampled call stacks for flame graph tools
+    #[arg(long)]
+    profile: bool,
+    /// Where `--profile` writes the call stacks, folded one to a line; by default next to the
+    /// program, with the extension `folded`
+    #[arg(long, requires = "profile")]
+    folded_stacks: Option<PathBuf>,
+    /// Directory to look for imported modules in when they are not next to the importing file.
+    /// May be given several times; directories are tried in order
+    #[arg(long, global = true)]
+    module_path: Vec<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum Emit {
+    /// the SSA intermediate representation, after the passes of the optimisation level
+    Ir,
+    /// the WebAssembly text of the module the wasm32 target builds
+    Wat,
+    /// a self-contained C99 program
+    C,
+    /// the x86-64 assembly the native target builds, annotated with live intervals and the
+    /// register or stack slot of every value
+    Asm,
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum Target {
+    /// a static ELF executable for x86-64 Linux
+    #[value(name = "x86_64-linux")]
+    X86_64Linux,
+    /// a WebAssembly module importing its output functions from the host
+    Wasm32,
+}
+
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Format source files in place
+    Fmt {
+        files: Vec<String>,
+        /// Report unformatted files and exit non-zero instead of rewriting them
+        #[arg(long)]
+        check: bool,
+        /// Column at which long argument lists are wrapped
+        #[arg(long, default_value_t = formatter::DEFAULT_WIDTH)]
+        width: usize,
+    },
+    /// Type-check source files without running them
+    Check {
+        files: Vec<String>,
+        /// Print the inferred type of every declaration
+        #[arg(long)]
+        show_types: bool,
+    },
+    /// Compile a source file to an executable or WebAssembly module
+    Build {
+        file: String,
+        #[arg(long, value_enum)]
+        target: Target,
+        /// Path of the output, by default the source file's with the target's extension
+        #[arg(short, long)]
+        output: Option<String>,
+        /// Optimisation level, as for running
+        #[arg(short = 'O', default_value_t = 1, value_parser = clap::value_parser!(u8).range(0..=2))]
+        opt_level: u8,
+        /// Print how long each module took and whether an earlier build spared the work
+        #[arg(long)]
+        timings: bool,
+        /// Directory to keep compiled modules in for later builds, by default `.cache` next to
+        /// the source file
+        #[arg(long)]
+        cache_dir: Option<PathBuf>,
+        /// Compile every module afresh, neither reading nor writing the cache
+        #[arg(long, conflicts_with = "cache_dir")]
+        no_cache: bool,
+    },
+    /// Run a language server, speaking the Language Server Protocol over stdin and stdout
+    Lsp,
+    /// Run a source file under a debugger, stopping before its first statement for commands
+    Debug { file: String },
+    /// Run a debug adapter, speaking the Debug Adapter Protocol over stdin and stdout
+    Dap,
+    /// Print a source file with its tokens classified for colouring
+    Highlight {
+        file: String,
+        #[arg(long, value_enum, default_value_t = HighlightFormat::Ansi)]
+        format: HighlightFormat,
+    },
+}
+
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum HighlightFormat {
+    /// coloured with ANSI escape sequences, for terminals
+    Ansi,
+    /// a `pre` element with a classed `span` per token, for a style sheet to colour
+    Html,
+    /// the semantic tokens of the Language Server Protocol, with their legend, as JSON
+    Lsp,
 }
 
 fn main() {
     let args = Args::parse();
+    // the program runs on a thread with room for as many calls as it may make, so that running
+    // out of stack is an error it reports rather than the process aborting
+    let runner = std::thread::Builder::new()
+        .stack_size(limits(&args).stack_size())
+        .spawn(move || run(args))
+        .expect("failed to start the thread to run on");
+    if let Err(panic) = runner.join() {
+        std::panic::resume_unwind(panic);
+    }
+}
+
+fn limits(args: &Args) -> Limits {
+    Limits {
+        fuel: args.fuel,
+        timeout: args.timeout_ms.map(std::time::Duration::from_millis),
+        max_call_depth: args.max_call_depth,
+        max_heap_bytes: args.max_heap_bytes,
+        stack_bytes: None,
+    }
+    .on_own_stack()
+}
 
-    let ext = match file_ext(&args.file_path) {
+fn run(args: Args) {
+    if let Some(command) = args.command {
+        match command {
+            Command::Fmt {
+                files,
+                check,
+                width,
+            } => fmt(&files, check, width),
+            Command::Check { files, show_types } => {
+                check_files(&files, &args.module_path, show_types)
+            }
+            Command::Build {
+                file,
+                target,
+                output,
+                opt_level,
+                timings,
+                cache_dir,
+                no_cache,
+            } => {
+                let cache = (!no_cache).then(|| {
+                    let dir = cache_dir.unwrap_or_else(|| {
+                        let dir = Path::new(&file).parent().unwrap_or(Path::new(""));
+                        dir.join(".cache")
+                    });
+                    Cache::new(dir)
+                });
+                let options = BuildOptions {
+                    target,
+                    output,
+                    opt_level,
+                    timings,
+                    cache,
+                };
+                build(&file, &args.module_path, options)
+            }
+            Command::Highlight { file, format } => highlight_file(&file, &args.module_path, format),
+            Command::Lsp => {
+                let stdin = std::io::stdin().lock();
+                if let Err(e) = lsp::serve(stdin, std::io::stdout(), args.module_path) {
+                    eprintln!("lsp: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            Command::Debug { file } => debug_file(&file, &args.module_path),
+            Command::Dap => {
+                let stdin = std::io::stdin().lock();
+                if let Err(e) = dap::serve(stdin, std::io::stdout(), args.module_path) {
+                    eprintln!("dap: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        return;
+    }
+
+    let file_path = match args.file_path.clone() {
+        Some(f) => f,
+        None => usage_error(
+            ErrorKind::MissingRequiredArgument,
+            "either --file-path or a subcommand is required",
+        ),
+    };
+    let ext = match file_ext(&file_path) {
         Some(e) => e,
-        None => panic!("Invalid file: {}", args.file_path),
+        None => usage_error(
+            ErrorKind::InvalidValue,
+            format!("cannot run '{}', only .txt programs", file_path),
+        ),
     };
 
     match ext {
         // COMPILER
         FileExt::Txt => {
-            let lexer = crate::compiler::lexer::Lexer::new(&args.file_path);
-            let tokens: Vec<compiler::token::Token> = lexer.into_iter().collect();
+            if args.dump_ast {
+                let lexer = compiler::lexer::Lexer::new(&file_path);
+                let tokens: Vec<compiler::token::Token> = lexer.into_iter().collect();
+                let program = match compiler::parser::Parser::new(&tokens).parse() {
+                    Ok(p) => p,
+                    Err(e) => exit_with_error(&file_path, e),
+                };
+                for stmt in &program {
+                    println!("{}", SExprPrinter::print_stmt(stmt));
+                }
+                return;
+            }
 
-            for t in &tokens {
-                println!("{}", t.lexeme);
+            let natives = Natives::standard();
+            // constant errors are reported whatever the level, but only folded from -O1
+            let fold = args.opt_level > 0;
+            let (modules, Compiled { globals, .. }) =
+                match compile_modules(&file_path, &args.module_path, &natives, fold, None) {
+                    Ok(compiled) => compiled,
+                    Err(errors) => exit_with_errors(&errors),
+                };
+            if let Some(emit) = args.emit {
+                let mut module = lower(&modules::link(modules), &globals, natives.len());
+                ir::opt::optimize(&mut module, args.opt_level);
+                match emit {
+                    Emit::Ir => print!("{}", module),
+                    Emit::Wat => match wasm::compile(&module) {
+                        Ok(wasm) => println!("{}", wasm),
+                        Err(e) => exit_with_error(&file_path, e),
+                    },
+                    Emit::C => match c::compile(&module) {
+                        Ok(c) => print!("{}", c),
+                        Err(e) => exit_with_error(&file_path, e),
+                    },
+                    Emit::Asm => match x86_64::assembly(&module) {
+                        Ok(program) => print!("{}", program),
+                        Err(e) => exit_with_error(&file_path, e),
+                    },
+                }
+                return;
+            }
+            let mut interpreter = Interpreter::new(&natives, globals)
+                .gc_stress(args.gc_stress)
+                .with_limits(limits(&args));
+            if args.profile {
+                interpreter = interpreter.with_profiler();
             }
+            let result = interpreter.run_modules(&modules);
+            if let Some(profiler) = interpreter.profiler() {
+                let paths: Vec<PathBuf> = modules.iter().map(|m| m.path.clone()).collect();
+                eprint!("{}", profiler.report(&paths));
+                let folded = args
+                    .folded_stacks
+                    .unwrap_or_else(|| Path::new(&file_path).with_extension("folded"));
+                if let Err(e) = std::fs::write(&folded, profiler.folded_stacks(&paths)) {
+                    exit_with_error(&folded.display().to_string(), e);
+                }
+            }
+            if let Err(e) = result {
+                exit_with_runtime_error(&file_path, e);
+            }
+        }
+        _ => usage_error(
+            ErrorKind::InvalidValue,
+            format!("cannot run '{}', only .txt programs", file_path),
+        ),
+    };
+}
+
+/// prints `message` and the usage, as clap does for arguments it rejects itself.
+fn usage_error(kind: ErrorKind, message: impl std::fmt::Display) -> ! {
+    Args::command().error(kind, message).exit()
+}
+
+fn exit_with_error(file_path: &str, e: impl std::fmt::Display) -> ! {
+    eprintln!("{}: {}", file_path, e);
+    std::process::exit(1);
+}
 
-            let parser = crate::compiler::parser::Parser::new(&tokens);
+/// the error and the calls it left.
+fn exit_with_runtime_error(file_path: &str, e: RuntimeError) -> ! {
+    eprintln!("{}: {}", file_path, e);
+    // runaway recursion repeats one frame for as deep as the stack goes
+    for run in e.trace.chunk_by(|a, b| a == b) {
+        eprintln!("  {}", run[0]);
+        if run.len() > 1 {
+            eprintln!("  ... {} more of the same", run.len() - 1);
+        }
+    }
+    std::process::exit(1);
+}
 
-            for expr in parser {
-                println!("{:?}", expr);
+/// for errors that say which file they are in themselves.
+fn exit_with_errors(errors: &[impl std::fmt::Display]) -> ! {
+    for e in errors {
+        eprintln!("{}", e);
+    }
+    std::process::exit(1);
+}
+
+/// the program starting at `file`, linked with the modules it imports after resolving, checking
+/// and folding them, and what compiling them found.
+fn compile_file(
+    file: &str,
+    module_path: &[PathBuf],
+    natives: &Natives,
+    fold: bool,
+    cache: Option<&Cache>,
+) -> Result<(Vec<Stmt>, Compiled), Vec<ModuleErr>> {
+    let (modules, compiled) = compile_modules(file, module_path, natives, fold, cache)?;
+    Ok((modules::link(modules), compiled))
+}
+
+/// as `compile_file`, but the modules as they are to run, each on its own.
+fn compile_modules(
+    file: &str,
+    module_path: &[PathBuf],
+    natives: &Natives,
+    fold: bool,
+    cache: Option<&Cache>,
+) -> Result<(Vec<Module>, Compiled), Vec<ModuleErr>> {
+    let mut loader = Loader::new(module_path.to_vec());
+    if let Some(cache) = cache {
+        loader = loader.with_cache(cache.clone());
+    }
+    let mut modules = loader.load(Path::new(file)).map_err(|e| vec![e])?;
+    let compiled = modules::compile(&mut modules, natives, fold, cache)?;
+    Ok((modules, compiled))
+}
+
+fn check_files(files: &[String], module_path: &[PathBuf], show_types: bool) {
+    let natives = Natives::standard();
+    let mut failed = false;
+    for file in files {
+        let result = compile_file(file, module_path, &natives, true, None);
+        match result {
+            Ok((_, mut compiled)) if show_types => {
+                let mut bindings = compiled.bindings.pop().unwrap_or_default();
+                bindings.sort_by_key(|b| b.line);
+                for binding in bindings {
+                    println!(
+                        "{}:{}: {}: {}",
+                        file, binding.line, binding.name, binding.scheme
+                    );
+                }
+            }
+            Ok(_) => {}
+            Err(errors) => {
+                for e in errors {
+                    eprintln!("{}", e);
+                }
+                failed = true;
             }
         }
-        _ => panic!("Invalid file type {:?}", ext),
+    }
+    if failed {
+        std::process::exit(1);
+    }
+}
+
+/// the optimised IR of a source file and the modules it imports, and how long each module took,
+/// or their diagnostics.
+fn lower_file(
+    file: &str,
+    module_path: &[PathBuf],
+    opt_level: u8,
+    cache: Option<&Cache>,
+) -> Result<(ir::Module, Vec<Timing>), Vec<ModuleErr>> {
+    let natives = Natives::standard();
+    let (program, compiled) = compile_file(file, module_path, &natives, opt_level > 0, cache)?;
+    let mut module = lower(&program, &compiled.globals, natives.len());
+    ir::opt::optimize(&mut module, opt_level);
+    Ok((module, compiled.timings))
+}
+
+struct BuildOptions {
+    target: Target,
+    output: Option<String>,
+    opt_level: u8,
+    timings: bool,
+    cache: Option<Cache>,
+}
+
+fn build(file: &str, module_path: &[PathBuf], options: BuildOptions) {
+    let BuildOptions {
+        target,
+        output,
+        opt_level,
+        timings,
+        cache,
+    } = options;
+    let (module, module_timings) = match lower_file(file, module_path, opt_level, cache.as_ref()) {
+        Ok(lowered) => lowered,
+        Err(errors) => exit_with_errors(&errors),
+    };
+    if timings {
+        print_timings(&module_timings);
+    }
+    let (bytes, extension) = match target {
+        Target::X86_64Linux => (x86_64::compile(&module), ""),
+        Target::Wasm32 => (wasm::compile(&module).map(|m| m.encode()), "wasm"),
+    };
+    let bytes = match bytes {
+        Ok(b) => b,
+        Err(e) => exit_with_error(file, e),
+    };
+    let output = output.unwrap_or_else(|| {
+        std::path::Path::new(file)
+            .with_extension(extension)
+            .to_string_lossy()
+            .into_owned()
+    });
+    let written = match target {
+        Target::X86_64Linux => write_executable(&output, &bytes),
+        Target::Wasm32 => std::fs::write(&output, &bytes),
+    };
+    if let Err(e) = written {
+        exit_with_error(&output, e);
+    }
+}
+
+/// one line per module, in the order they were compiled, then the totals.
+fn print_timings(timings: &[Timing]) {
+    let ms = |d: std::time::Duration| d.as_secs_f64() * 1000.0;
+    eprintln!("{:<9}  {:>9}  {:>9}  module", "", "load", "check");
+    for t in timings {
+        eprintln!(
+            "{:<9}  {:>7.2}ms  {:>7.2}ms  {}",
+            t.reuse,
+            ms(t.load),
+            ms(t.check),
+            t.path.display()
+        );
+    }
+    let load: std::time::Duration = timings.iter().map(|t| t.load).sum();
+    let check: std::time::Duration = timings.iter().map(|t| t.check).sum();
+    eprintln!("{:<9}  {:>7.2}ms  {:>7.2}ms", "total", ms(load), ms(check));
+}
+
+/// runs `file` with commands read from stdin whenever it stops. Quitting the debugger ends the
+/// program quietly.
+fn debug_file(file: &str, module_path: &[PathBuf]) {
+    let natives = Natives::standard();
+    let (modules, globals) = match debugger::compile(Path::new(file), module_path, &natives) {
+        Ok(compiled) => compiled,
+        Err(errors) => exit_with_errors(&errors),
     };
+    let cli = Cli::new(std::io::stdin().lock(), std::io::stdout(), &modules);
+    let mut interpreter = Interpreter::new(&natives, globals).with_debugger(Box::new(cli));
+    match interpreter.run_modules(&modules) {
+        Err(e) if e.error != EvalErr::Stopped => exit_with_runtime_error(file, e),
+        _ => {}
+    }
+}
+
+fn highlight_file(file: &str, module_path: &[PathBuf], format: HighlightFormat) {
+    let source = match std::fs::read_to_string(file) {
+        Ok(source) => source,
+        Err(e) => exit_with_error(file, e),
+    };
+    let natives = Natives::standard();
+    let spans = highlight::highlight(&source, Path::new(file), module_path, &natives);
+    match format {
+        HighlightFormat::Ansi => print!("{}", highlight::ansi(&source, &spans)),
+        HighlightFormat::Html => print!("{}", highlight::html(&source, &spans)),
+        HighlightFormat::Lsp => {
+            let types = highlight::TOKEN_TYPES.iter().map(|&t| t.into()).collect();
+            let data = highlight::semantic_tokens(&source, &spans);
+            let tokens = Json::object([
+                (
+                    "legend",
+                    Json::object([
+                        ("tokenTypes", Json::Array(types)),
+                        ("tokenModifiers", Json::Array(Vec::new())),
+                    ]),
+                ),
+                (
+                    "data",
+                    data.into_iter().map(Json::from).collect::<Vec<_>>().into(),
+                ),
+            ]);
+            println!("{}", tokens);
+        }
+    }
+}
+
+fn write_executable(path: &str, bytes: &[u8]) -> std::io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::write(path, bytes)?;
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o755))
+}
+
+fn fmt(files: &[String], check: bool, width: usize) {
+    let mut failed = false;
+    for file in files {
+        let source = match std::fs::read_to_string(file) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                failed = true;
+                continue;
+            }
+        };
+        let formatted = match format_source(&source, width) {
+            Ok(f) => f,
+            Err(e) => {
+                eprintln!("{}: {}", file, e);
+                failed = true;
+                continue;
+            }
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("{} is not formatted", file);
+            failed = true;
+        } else if let Err(e) = std::fs::write(file, formatted) {
+            eprintln!("{}: {}", file, e);
+            failed = true;
+        }
+    }
+    if failed {
+        std::process::exit(1);
+    }
 }
@@ -2,11 +2,11 @@ pub fn as_u32_be(array: &[u8; 4]) -> u32 {
     ((array[0] as u32) << 24)
         + ((array[1] as u32) << 16)
         + ((array[2] as u32) << 8)
-        + ((array[3] as u32) << 0)
+        + (array[3] as u32)
 }
 
 pub fn as_u32_le(array: &[u8; 4]) -> u32 {
-    ((array[0] as u32) << 0)
+    (array[0] as u32)
         + ((array[1] as u32) << 8)
         + ((array[2] as u32) << 16)
         + ((array[3] as u32) << 24)
@@ -16,20 +16,20 @@ pub fn as_i32_be(array: &[u8; 4]) -> i32 {
     ((array[0] as i32) << 24)
         + ((array[1] as i32) << 16)
         + ((array[2] as i32) << 8)
-        + ((array[3] as i32) << 0)
+        + (array[3] as i32)
 }
 
 pub fn as_i32_le(array: &[u8; 4]) -> i32 {
-    ((array[0] as i32) << 0)
+    (array[0] as i32)
         + ((array[1] as i32) << 8)
         + ((array[2] as i32) << 16)
         + ((array[3] as i32) << 24)
 }
 
 pub fn i32_bytes_be(int: i32) -> [u8; 4] {
-    unsafe { std::mem::transmute(int.to_be()) }
+    int.to_be_bytes()
 }
 
 pub fn i32_bytes_le(int: i32) -> [u8; 4] {
-    unsafe { std::mem::transmute(int.to_le()) }
+    int.to_le_bytes()
 }
@@ -15,15 +15,10 @@ pub fn file_ext(path: &String) -> Option<FileExt> {
     // } else {
     //     None
     // }
-    if path.len() <= 0 {
+    if path.is_empty() {
         return None;
     }
-    match std::path::Path::new(path)
-        .extension()
-        .unwrap()
-        .to_str()
-        .unwrap()
-    {
+    match std::path::Path::new(path).extension()?.to_str()? {
         "bin" => Some(FileExt::Bin),
         "asm" => Some(FileExt::Asm),
         "txt" => Some(FileExt::Txt),
@@ -32,7 +27,7 @@ pub fn file_ext(path: &String) -> Option<FileExt> {
 }
 
 pub fn file_name(path: &String) -> Option<&str> {
-    if path.len() <= 0 {
+    if path.is_empty() {
         return None;
     }
     std::path::Path::new(path).file_stem().unwrap().to_str()
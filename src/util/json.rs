@@ -0,0 +1,312 @@
+//! JSON, as much as the protocols editors speak to tools over need: values, a parser for the
+//! messages that come in and `Display` for those that go out.
+
+use std::fmt::Write;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    /// members in the order they were written or built.
+    Object(Vec<(String, Json)>),
+}
+
+static NULL: Json = Json::Null;
+
+impl Json {
+    /// the value `text` holds, or `None` if it is not JSON.
+    pub fn parse(text: &str) -> Option<Json> {
+        let mut parser = JsonParser { text, pos: 0 };
+        let value = parser.value()?;
+        parser.whitespace();
+        (parser.pos == text.len()).then_some(value)
+    }
+    pub fn object<const N: usize>(members: [(&str, Json); N]) -> Json {
+        Json::Object(
+            members
+                .into_iter()
+                .map(|(key, value)| (key.to_owned(), value))
+                .collect(),
+        )
+    }
+
+    /// the member `key` of an object, or null if there is none, so that lookups can be chained.
+    pub fn get(&self, key: &str) -> &Json {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(k, _)| k == key)
+                .map_or(&NULL, |(_, value)| value),
+            _ => &NULL,
+        }
+    }
+    pub fn is_null(&self) -> bool {
+        *self == Json::Null
+    }
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Json::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+    pub fn as_usize(&self) -> Option<usize> {
+        self.as_f64()
+            .filter(|n| *n >= 0.0 && n.fract() == 0.0)
+            .map(|n| n as usize)
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+    pub fn as_array(&self) -> Option<&[Json]> {
+        match self {
+            Json::Array(elements) => Some(elements),
+            _ => None,
+        }
+    }
+}
+
+impl From<bool> for Json {
+    fn from(b: bool) -> Self {
+        Json::Bool(b)
+    }
+}
+
+impl From<usize> for Json {
+    fn from(n: usize) -> Self {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<i64> for Json {
+    fn from(n: i64) -> Self {
+        Json::Number(n as f64)
+    }
+}
+
+impl From<f64> for Json {
+    fn from(n: f64) -> Self {
+        Json::Number(n)
+    }
+}
+
+impl From<&str> for Json {
+    fn from(s: &str) -> Self {
+        Json::String(s.to_owned())
+    }
+}
+
+impl From<String> for Json {
+    fn from(s: String) -> Self {
+        Json::String(s)
+    }
+}
+
+impl From<Vec<Json>> for Json {
+    fn from(elements: Vec<Json>) -> Self {
+        Json::Array(elements)
+    }
+}
+
+impl<T: Into<Json>> From<Option<T>> for Json {
+    fn from(value: Option<T>) -> Self {
+        value.map_or(Json::Null, Into::into)
+    }
+}
+
+impl std::fmt::Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            // integers, which are most numbers in protocol messages, without a fraction
+            Json::Number(n) if n.fract() == 0.0 && n.abs() < 1e15 => write!(f, "{}", *n as i64),
+            Json::Number(n) if n.is_finite() => write!(f, "{}", n),
+            Json::Number(_) => write!(f, "null"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(elements) => {
+                f.write_char('[')?;
+                for (i, element) in elements.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{}", element)?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(members) => {
+                f.write_char('{')?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+/// how deeply arrays and objects may nest, which bounds the parser's recursion.
+const MAX_DEPTH: usize = 128;
+
+struct JsonParser<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.pos..]
+    }
+    fn whitespace(&mut self) {
+        let rest = self.rest();
+        self.pos += rest.len() - rest.trim_start_matches([' ', '\t', '\n', '\r']).len();
+    }
+    fn eat(&mut self, token: &str) -> bool {
+        self.whitespace();
+        let matched = self.rest().starts_with(token);
+        if matched {
+            self.pos += token.len();
+        }
+        matched
+    }
+    fn value(&mut self) -> Option<Json> {
+        self.nested(0)
+    }
+    fn nested(&mut self, depth: usize) -> Option<Json> {
+        if depth > MAX_DEPTH {
+            return None;
+        }
+        self.whitespace();
+        if self.eat("null") {
+            Some(Json::Null)
+        } else if self.eat("true") {
+            Some(Json::Bool(true))
+        } else if self.eat("false") {
+            Some(Json::Bool(false))
+        } else if self.eat("\"") {
+            self.string().map(Json::String)
+        } else if self.eat("[") {
+            let mut elements = Vec::new();
+            if self.eat("]") {
+                return Some(Json::Array(elements));
+            }
+            loop {
+                elements.push(self.nested(depth + 1)?);
+                if self.eat("]") {
+                    return Some(Json::Array(elements));
+                }
+                if !self.eat(",") {
+                    return None;
+                }
+            }
+        } else if self.eat("{") {
+            let mut members = Vec::new();
+            if self.eat("}") {
+                return Some(Json::Object(members));
+            }
+            loop {
+                if !self.eat("\"") {
+                    return None;
+                }
+                let key = self.string()?;
+                if !self.eat(":") {
+                    return None;
+                }
+                members.push((key, self.nested(depth + 1)?));
+                if self.eat("}") {
+                    return Some(Json::Object(members));
+                }
+                if !self.eat(",") {
+                    return None;
+                }
+            }
+        } else {
+            self.number()
+        }
+    }
+    /// the rest of a string whose opening quote has been read.
+    fn string(&mut self) -> Option<String> {
+        let mut s = String::new();
+        let mut chars = self.rest().char_indices();
+        loop {
+            let (i, c) = chars.next()?;
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    return Some(s);
+                }
+                '\\' => {
+                    let (_, escape) = chars.next()?;
+                    match escape {
+                        '"' | '\\' | '/' => s.push(escape),
+                        'b' => s.push('\u{8}'),
+                        'f' => s.push('\u{c}'),
+                        'n' => s.push('\n'),
+                        'r' => s.push('\r'),
+                        't' => s.push('\t'),
+                        'u' => {
+                            fn unit(chars: &mut std::str::CharIndices) -> Option<u32> {
+                                let hex: String = chars.take(4).map(|(_, c)| c).collect();
+                                u32::from_str_radix(&hex, 16).ok()
+                            }
+                            let high = unit(&mut chars)?;
+                            let code = if (0xd800..0xdc00).contains(&high) {
+                                // the first half of a surrogate pair, whose second half follows
+                                if chars.next()?.1 != '\\' || chars.next()?.1 != 'u' {
+                                    return None;
+                                }
+                                let low = unit(&mut chars)?;
+                                0x10000 + ((high - 0xd800) << 10) + (low.checked_sub(0xdc00)?)
+                            } else {
+                                high
+                            };
+                            s.push(char::from_u32(code)?);
+                        }
+                        _ => return None,
+                    }
+                }
+                c => s.push(c),
+            }
+        }
+    }
+    fn number(&mut self) -> Option<Json> {
+        let rest = self.rest();
+        let len = rest
+            .find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c)))
+            .unwrap_or(rest.len());
+        let n = rest[..len].parse().ok()?;
+        self.pos += len;
+        Some(Json::Number(n))
+    }
+}
@@ -0,0 +1,54 @@
+//! LEB128, the variable-length integer encoding of WebAssembly and DWARF: seven bits per byte,
+//! least significant first, with the high bit set on every byte but the last.
+
+pub fn write_unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+pub fn write_signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        // done once the remaining bits are all copies of the sign bit just written
+        if (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0) {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// the value at the start of `bytes` and the number of bytes it took, if they hold a whole one.
+pub fn read_unsigned(bytes: &[u8]) -> Option<(u64, usize)> {
+    let mut value = 0;
+    for (i, byte) in bytes.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as u64) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+pub fn read_signed(bytes: &[u8]) -> Option<(i64, usize)> {
+    let mut value = 0;
+    for (i, byte) in bytes.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as i64) << (7 * i);
+        if byte & 0x80 == 0 {
+            let shift = 7 * (i + 1);
+            if shift < 64 && byte & 0x40 != 0 {
+                value |= -1 << shift;
+            }
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
@@ -1,4 +1,6 @@
 pub mod endianness;
 pub mod file_util;
+pub mod json;
+pub mod leb128;
 #[cfg(test)]
 mod tests;
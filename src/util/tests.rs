@@ -1,4 +1,6 @@
 use super::endianness::{as_i32_be, as_i32_le, i32_bytes_be, i32_bytes_le};
+use super::json::Json;
+use super::leb128;
 
 #[test]
 fn test_as_i32_be() {
@@ -47,3 +49,88 @@ fn test_i32_bytes_le() {
     bytes = [0, 1, 1, 0];
     assert_eq!(i32_bytes_le(65792), bytes);
 }
+
+#[test]
+fn test_leb128_unsigned() {
+    let cases: [(u64, &[u8]); 4] = [
+        (0, &[0]),
+        (127, &[0x7f]),
+        (624485, &[0xe5, 0x8e, 0x26]),
+        (
+            u64::MAX,
+            &[0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+        ),
+    ];
+    for (value, bytes) in cases {
+        let mut out = Vec::new();
+        leb128::write_unsigned(&mut out, value);
+        assert_eq!(out, bytes);
+        assert_eq!(leb128::read_unsigned(bytes), Some((value, bytes.len())));
+    }
+    assert_eq!(leb128::read_unsigned(&[0x80]), None);
+}
+
+#[test]
+fn test_leb128_signed() {
+    let cases: [(i64, &[u8]); 6] = [
+        (0, &[0]),
+        (63, &[0x3f]),
+        (64, &[0xc0, 0x00]),
+        (-1, &[0x7f]),
+        (-123456, &[0xc0, 0xbb, 0x78]),
+        (
+            i64::MIN,
+            &[0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x80, 0x7f],
+        ),
+    ];
+    for (value, bytes) in cases {
+        let mut out = Vec::new();
+        leb128::write_signed(&mut out, value);
+        assert_eq!(out, bytes);
+        assert_eq!(leb128::read_signed(bytes), Some((value, bytes.len())));
+    }
+}
+
+#[test]
+fn test_json_round_trip() {
+    let text =
+        r#"{"id":1,"params":{"text":"a \"b\"\n\u00e9\ud83d\ude00","items":[true,null,-2.5e1]}}"#;
+    let json = Json::parse(text).unwrap();
+    assert_eq!(json.get("id").as_usize(), Some(1));
+    let params = json.get("params");
+    assert_eq!(
+        params.get("text").as_str(),
+        Some("a \"b\"\n\u{e9}\u{1f600}")
+    );
+    assert_eq!(
+        params.get("items").as_array().unwrap(),
+        &[Json::Bool(true), Json::Null, Json::Number(-25.0)]
+    );
+    assert!(json.get("missing").get("deeper").is_null());
+    assert_eq!(Json::parse(&json.to_string()), Some(json));
+
+    let built = Json::object([
+        ("ok", true.into()),
+        ("n", 3usize.into()),
+        ("s", "\t".into()),
+    ]);
+    assert_eq!(built.to_string(), r#"{"ok":true,"n":3,"s":"\t"}"#);
+}
+
+#[test]
+fn test_json_rejects_malformed_text() {
+    for text in [
+        "",
+        "{",
+        "[1,]",
+        "{\"a\" 1}",
+        "tru",
+        "\"\\x\"",
+        "1 2",
+        "\"\\ud83d\"",
+    ] {
+        assert_eq!(Json::parse(text), None, "{}", text);
+    }
+    let deep = "[".repeat(1000) + &"]".repeat(1000);
+    assert_eq!(Json::parse(&deep), None);
+}